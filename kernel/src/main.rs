@@ -110,6 +110,7 @@ pub fn rust_main(hart_id: usize, dtb_addr: usize) -> ! {
 
             arch::trap::init();
             arch::time::init_timer();
+            arch::fpu::init();
         }
 
         println!(
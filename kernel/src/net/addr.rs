@@ -12,6 +12,7 @@ use alloc::{
     string::{String, ToString},
     sync::Arc,
 };
+use net::packet::LinkAddr;
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 use systype::error::{SysError, SysResult};
 
@@ -53,6 +54,66 @@ pub struct SockAddrUn {
     pub path: [u8; 108],
 }
 
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+/// Netlink socket address
+pub struct SockAddrNl {
+    /// always set to `AF_NETLINK`
+    pub family: u16,
+    pub pad: u16,
+    /// port ID of the socket, which is 0 for the kernel
+    pub pid: u32,
+    /// bitmask of multicast groups
+    pub groups: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+/// Link-layer address of a packet socket
+pub struct SockAddrLl {
+    /// always set to `AF_PACKET`
+    pub family: u16,
+    /// ethernet protocol in network byte order
+    pub protocol: [u8; 2],
+    /// index of the interface
+    pub ifindex: i32,
+    /// `ARPHRD_*` type of the interface
+    pub hatype: u16,
+    /// `PACKET_*` type of the frame
+    pub pkttype: u8,
+    /// length of the address
+    pub halen: u8,
+    pub addr: [u8; 8],
+}
+
+impl From<SockAddrLl> for LinkAddr {
+    fn from(addr: SockAddrLl) -> Self {
+        Self {
+            protocol: u16::from_be_bytes(addr.protocol),
+            ifindex: addr.ifindex as u32,
+            hatype: addr.hatype,
+            pkttype: addr.pkttype,
+            addr: addr.addr[..6].try_into().unwrap(),
+        }
+    }
+}
+
+impl From<LinkAddr> for SockAddrLl {
+    fn from(addr: LinkAddr) -> Self {
+        let mut ll_addr = [0; 8];
+        ll_addr[..6].copy_from_slice(&addr.addr);
+        Self {
+            family: SaFamily::AF_PACKET.into(),
+            protocol: addr.protocol.to_be_bytes(),
+            ifindex: addr.ifindex as i32,
+            hatype: addr.hatype,
+            pkttype: addr.pkttype,
+            halen: 6,
+            addr: ll_addr,
+        }
+    }
+}
+
 /// socket address family
 #[allow(non_camel_case_types)]
 #[derive(Debug)]
@@ -62,6 +123,10 @@ pub enum SaFamily {
     AF_INET = 2,
     /// ipv6
     AF_INET6 = 10,
+    /// netlink
+    AF_NETLINK = 16,
+    /// link-layer packets
+    AF_PACKET = 17,
 }
 
 impl TryFrom<u16> for SaFamily {
@@ -72,6 +137,8 @@ impl TryFrom<u16> for SaFamily {
             1 => Ok(Self::AF_UNIX),
             2 => Ok(Self::AF_INET),
             10 => Ok(Self::AF_INET6),
+            16 => Ok(Self::AF_NETLINK),
+            17 => Ok(Self::AF_PACKET),
             _ => Err(Self::Error::EINVAL),
         }
     }
@@ -83,6 +150,8 @@ impl From<SaFamily> for u16 {
             SaFamily::AF_UNIX => 1,
             SaFamily::AF_INET => 2,
             SaFamily::AF_INET6 => 10,
+            SaFamily::AF_NETLINK => 16,
+            SaFamily::AF_PACKET => 17,
         }
     }
 }
@@ -98,6 +167,8 @@ pub union SockAddr {
     pub ipv4: SockAddrIn,
     pub ipv6: SockAddrIn6,
     pub unix: SockAddrUn,
+    pub netlink: SockAddrNl,
+    pub packet: SockAddrLl,
 }
 
 impl SockAddr {
@@ -135,11 +206,10 @@ impl SockAddr {
                     IpAddress::Ipv4(Ipv4Addr::from(self.ipv4.addr)),
                     u16::from_be_bytes(self.ipv4.port),
                 ),
-                SaFamily::AF_INET6 => IpEndpoint::new(
-                    IpAddress::Ipv6(Ipv6Addr::from(self.ipv6.addr)),
-                    u16::from_be_bytes(self.ipv6.port),
-                ),
-                SaFamily::AF_UNIX => panic!("Shouldn't get there"),
+                SaFamily::AF_INET6 => self.ipv6.into(),
+                SaFamily::AF_UNIX | SaFamily::AF_NETLINK | SaFamily::AF_PACKET => {
+                    panic!("Shouldn't get there")
+                }
             }
         }
     }
@@ -149,11 +219,22 @@ impl SockAddr {
             match SaFamily::try_from(self.family).unwrap() {
                 SaFamily::AF_INET => Some(self.ipv4.into()),
                 SaFamily::AF_INET6 => Some(self.ipv6.into()),
-                SaFamily::AF_UNIX => None,
+                SaFamily::AF_UNIX | SaFamily::AF_NETLINK | SaFamily::AF_PACKET => None,
             }
         }
     }
 
+    pub fn from_netlink(pid: u32, groups: u32) -> Self {
+        Self {
+            netlink: SockAddrNl {
+                family: SaFamily::AF_NETLINK.into(),
+                pad: 0,
+                pid,
+                groups,
+            },
+        }
+    }
+
     pub fn from_endpoint(endpoint: IpEndpoint) -> Self {
         match endpoint.addr {
             IpAddress::Ipv4(_) => Self {
@@ -164,6 +245,14 @@ impl SockAddr {
             },
         }
     }
+
+    /// Converts `endpoint` to the address of an `AF_INET6` socket, where an ipv4
+    /// address is represented as an ipv4-mapped ipv6 address `::ffff:a.b.c.d`.
+    pub fn from_endpoint_v6(endpoint: IpEndpoint) -> Self {
+        Self {
+            ipv6: endpoint.into(),
+        }
+    }
 }
 
 impl From<SockAddrIn> for IpEndpoint {
@@ -175,10 +264,20 @@ impl From<SockAddrIn> for IpEndpoint {
     }
 }
 
+/// Converts an ipv6 address to `IpAddress`, where an ipv4-mapped ipv6 address
+/// `::ffff:a.b.c.d` is converted to the ipv4 address `a.b.c.d` it represents, so that
+/// an `AF_INET6` socket can communicate with ipv4 peers.
+fn ipv6_to_ip_address(addr: Ipv6Addr) -> IpAddress {
+    match addr.to_ipv4_mapped() {
+        Some(v4) => IpAddress::Ipv4(v4),
+        None => IpAddress::Ipv6(addr),
+    }
+}
+
 impl From<SockAddrIn6> for IpEndpoint {
     fn from(v6: SockAddrIn6) -> Self {
         IpEndpoint::new(
-            IpAddress::Ipv6(Ipv6Addr::from(v6.addr)),
+            ipv6_to_ip_address(Ipv6Addr::from(v6.addr)),
             u16::from_be_bytes(v6.port),
         )
     }
@@ -202,16 +301,16 @@ impl From<IpEndpoint> for SockAddrIn {
 
 impl From<IpEndpoint> for SockAddrIn6 {
     fn from(v6: IpEndpoint) -> Self {
-        if let IpAddress::Ipv6(v6_addr) = v6.addr {
-            Self {
-                family: SaFamily::AF_INET6.into(),
-                port: v6.port.to_be_bytes(),
-                flowinfo: 0,
-                addr: unsafe { core::mem::transmute::<Ipv6Addr, [u8; 16]>(v6_addr) },
-                scope: 0,
-            }
-        } else {
-            panic!();
+        let v6_addr = match v6.addr {
+            IpAddress::Ipv4(v4_addr) => v4_addr.to_ipv6_mapped(),
+            IpAddress::Ipv6(v6_addr) => v6_addr,
+        };
+        Self {
+            family: SaFamily::AF_INET6.into(),
+            port: v6.port.to_be_bytes(),
+            flowinfo: 0,
+            addr: v6_addr.octets(),
+            scope: 0,
         }
     }
 }
@@ -233,11 +332,11 @@ impl From<SockAddrIn> for IpListenEndpoint {
 
 impl From<SockAddrIn6> for IpListenEndpoint {
     fn from(v6: SockAddrIn6) -> Self {
-        let addr = Ipv6Addr::from(v6.addr);
+        let addr = ipv6_to_ip_address(Ipv6Addr::from(v6.addr));
         let addr = if addr.is_unspecified() {
             None
         } else {
-            Some(IpAddress::Ipv6(addr))
+            Some(addr)
         };
         Self {
             addr,
@@ -305,6 +404,22 @@ pub fn read_sockaddr(
                 ipv6: unsafe { *(addr as *const _) },
             })
         }
+        SaFamily::AF_NETLINK => {
+            if addrlen < mem::size_of::<SockAddrNl>() {
+                log::error!("[read_sockaddr] AF_NETLINK addrlen error");
+                return Err(SysError::EINVAL);
+            }
+            let sockaddr = unsafe { UserReadPtr::<SockAddrNl>::new(addr, &addrspace).read()? };
+            Ok(SockAddr { netlink: sockaddr })
+        }
+        SaFamily::AF_PACKET => {
+            if addrlen < mem::size_of::<SockAddrLl>() {
+                log::error!("[read_sockaddr] AF_PACKET addrlen error");
+                return Err(SysError::EINVAL);
+            }
+            let sockaddr = unsafe { UserReadPtr::<SockAddrLl>::new(addr, &addrspace).read()? };
+            Ok(SockAddr { packet: sockaddr })
+        }
     }
 }
 
@@ -328,16 +443,63 @@ pub fn write_sockaddr(
                 log::debug!("write af_inet success");
             }
             SaFamily::AF_INET6 => {
-                UserWritePtr::<SockAddrIn6>::new(addr, &addrspace).write(sockaddr.ipv6)?;
-                UserWritePtr::<u32>::new(addrlen, &addrspace)
-                    .write(mem::size_of::<SockAddrIn6>() as u32)?;
+                if addr != 0 {
+                    UserWritePtr::<SockAddrIn6>::new(addr, &addrspace).write(sockaddr.ipv6)?;
+                }
+                if addrlen != 0 {
+                    UserWritePtr::<u32>::new(addrlen, &addrspace)
+                        .write(mem::size_of::<SockAddrIn6>() as u32)?;
+                }
             }
             SaFamily::AF_UNIX => {
                 UserWritePtr::<SockAddrUn>::new(addr, &addrspace).write(sockaddr.unix)?;
                 UserWritePtr::<u32>::new(addrlen, &addrspace)
                     .write(mem::size_of::<SockAddrUn>() as u32)?;
             }
+            SaFamily::AF_NETLINK => {
+                if addr != 0 {
+                    UserWritePtr::<SockAddrNl>::new(addr, &addrspace).write(sockaddr.netlink)?;
+                }
+                if addrlen != 0 {
+                    UserWritePtr::<u32>::new(addrlen, &addrspace)
+                        .write(mem::size_of::<SockAddrNl>() as u32)?;
+                }
+            }
+            SaFamily::AF_PACKET => {
+                if addr != 0 {
+                    UserWritePtr::<SockAddrLl>::new(addr, &addrspace).write(sockaddr.packet)?;
+                }
+                if addrlen != 0 {
+                    UserWritePtr::<u32>::new(addrlen, &addrspace)
+                        .write(mem::size_of::<SockAddrLl>() as u32)?;
+                }
+            }
         }
     }
     Ok(())
 }
+
+/// Writes `sockaddr` to the buffer `addr` of `addrlen` bytes, truncating it if the
+/// buffer is too small, and returns the length of the address, as `recvmsg()` does for
+/// `msg_name`.
+pub fn write_sockaddr_buf(
+    addrspace: &AddrSpace,
+    addr: usize,
+    addrlen: usize,
+    sockaddr: SockAddr,
+) -> SysResult<usize> {
+    let len = match SaFamily::try_from(unsafe { sockaddr.family })? {
+        SaFamily::AF_INET => mem::size_of::<SockAddrIn>(),
+        SaFamily::AF_INET6 => mem::size_of::<SockAddrIn6>(),
+        SaFamily::AF_UNIX => mem::size_of::<SockAddrUn>(),
+        SaFamily::AF_NETLINK => mem::size_of::<SockAddrNl>(),
+        SaFamily::AF_PACKET => mem::size_of::<SockAddrLl>(),
+    };
+    // SAFETY: the address of the family is a C structure of `len` bytes.
+    let bytes =
+        unsafe { core::slice::from_raw_parts(&sockaddr as *const SockAddr as *const u8, len) };
+    unsafe {
+        UserWritePtr::<u8>::new(addr, addrspace).write_array(&bytes[..len.min(addrlen)])?;
+    }
+    Ok(len)
+}
@@ -0,0 +1,212 @@
+//! `ioctl` requests on sockets, which query and configure the network interfaces.
+//!
+//! An interface is named by `struct ifreq`, whose data depends on the request. Only
+//! ipv4 addresses are reported, as Linux does for these requests.
+
+use alloc::{string::String, vec::Vec};
+
+use net::{
+    InterfaceInfo,
+    interface::{IFF_LOOPBACK, IFF_UP},
+};
+use smoltcp::wire::{IpCidr, Ipv4Cidr};
+use strum::FromRepr;
+use systype::error::{SysError, SyscallResult};
+
+use crate::{
+    processor::current_task,
+    task::cap::CapabilitiesFlags,
+    vm::user_ptr::{UserReadPtr, UserWritePtr},
+};
+
+/// Maximum length of an interface name, including the terminating NUL.
+pub const IFNAMSIZ: usize = 16;
+
+const AF_INET: u16 = 2;
+const ARPHRD_ETHER: u16 = 1;
+const ARPHRD_LOOPBACK: u16 = 772;
+
+/// Defined in <linux/sockios.h>
+#[derive(FromRepr, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum SockIoctlCmd {
+    /// Gets the name of an interface by its index.
+    SIOCGIFNAME = 0x8910,
+    /// Gets the list of interface addresses.
+    SIOCGIFCONF = 0x8912,
+    /// Gets the flags of an interface.
+    SIOCGIFFLAGS = 0x8913,
+    /// Sets the flags of an interface.
+    SIOCSIFFLAGS = 0x8914,
+    /// Gets the address of an interface.
+    SIOCGIFADDR = 0x8915,
+    /// Gets the network mask of an interface.
+    SIOCGIFNETMASK = 0x891b,
+    /// Gets the MTU of an interface.
+    SIOCGIFMTU = 0x8921,
+    /// Gets the hardware address of an interface.
+    SIOCGIFHWADDR = 0x8927,
+    /// Gets the index of an interface by its name.
+    SIOCGIFINDEX = 0x8933,
+}
+
+/// `struct ifreq`, an interface name followed by the data of a request, which is an
+/// address, the flags, the index or the MTU.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IfReq {
+    pub name: [u8; IFNAMSIZ],
+    pub data: [u8; 24],
+}
+
+/// `struct ifconf`, a buffer of `len` bytes at `buf` for `struct ifreq`s.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct IfConf {
+    pub len: i32,
+    pub buf: usize,
+}
+
+impl IfReq {
+    fn new(name: &str) -> Self {
+        let mut req = Self {
+            name: [0; IFNAMSIZ],
+            data: [0; 24],
+        };
+        req.set_name(name);
+        req
+    }
+
+    fn set_name(&mut self, name: &str) {
+        self.name = [0; IFNAMSIZ];
+        let len = name.len().min(IFNAMSIZ - 1);
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+    }
+
+    fn name(&self) -> String {
+        let len = self.name.iter().position(|&c| c == 0).unwrap_or(IFNAMSIZ);
+        String::from_utf8_lossy(&self.name[..len]).into_owned()
+    }
+
+    /// Sets the data to a `struct sockaddr` of `family` with `bytes`.
+    fn set_sockaddr(&mut self, family: u16, bytes: &[u8]) {
+        self.data = [0; 24];
+        self.data[0..2].copy_from_slice(&family.to_ne_bytes());
+        self.data[2..2 + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Sets the data to a `struct sockaddr_in` of the ipv4 address `octets`.
+    fn set_addr(&mut self, octets: [u8; 4]) {
+        // The port is zero.
+        self.set_sockaddr(AF_INET, &[0, 0]);
+        self.data[4..8].copy_from_slice(&octets);
+    }
+
+    fn set_i32(&mut self, value: i32) {
+        self.data = [0; 24];
+        self.data[0..4].copy_from_slice(&value.to_ne_bytes());
+    }
+
+    fn flags(&self) -> u16 {
+        u16::from_ne_bytes([self.data[0], self.data[1]])
+    }
+
+    fn index(&self) -> i32 {
+        i32::from_ne_bytes(self.data[0..4].try_into().unwrap())
+    }
+}
+
+/// Returns the first ipv4 address of `info`.
+fn ipv4_addr(info: &InterfaceInfo) -> Option<Ipv4Cidr> {
+    info.ip_addrs.iter().find_map(|cidr| match cidr {
+        IpCidr::Ipv4(v4) => Some(*v4),
+        IpCidr::Ipv6(_) => None,
+    })
+}
+
+/// Returns the ipv4 network mask of `prefix_len`.
+fn netmask(prefix_len: u8) -> [u8; 4] {
+    u32::MAX
+        .checked_shl(32 - prefix_len as u32)
+        .unwrap_or(0)
+        .to_be_bytes()
+}
+
+/// Handles the request `cmd` on an interface with the argument at `arg`.
+///
+/// # Errors
+/// Returns `ENODEV` if there is no such interface, `EADDRNOTAVAIL` if it has no ipv4
+/// address, or `EPERM` if the caller can't configure it.
+pub fn interface_ioctl(cmd: SockIoctlCmd, arg: usize) -> SyscallResult {
+    let task = current_task();
+    let addrspace = task.addr_space();
+    if cmd == SockIoctlCmd::SIOCGIFCONF {
+        let mut conf = unsafe { UserReadPtr::<IfConf>::new(arg, &addrspace).read()? };
+        let mut reqs = Vec::new();
+        for info in net::interface_infos() {
+            for cidr in info.ip_addrs.iter() {
+                if let IpCidr::Ipv4(v4) = cidr {
+                    let mut req = IfReq::new(&info.name);
+                    req.set_addr(v4.address().octets());
+                    reqs.push(req);
+                }
+            }
+        }
+        // Without a buffer, only the length needed for all addresses is returned.
+        let len = if conf.buf == 0 {
+            reqs.len()
+        } else {
+            let count = reqs
+                .len()
+                .min(conf.len.max(0) as usize / size_of::<IfReq>());
+            let mut buf = UserWritePtr::<IfReq>::new(conf.buf, &addrspace);
+            unsafe { buf.write_array(&reqs[..count])? };
+            count
+        };
+        conf.len = (len * size_of::<IfReq>()) as i32;
+        unsafe { UserWritePtr::<IfConf>::new(arg, &addrspace).write(conf)? };
+        return Ok(0);
+    }
+
+    let mut req = unsafe { UserReadPtr::<IfReq>::new(arg, &addrspace).read()? };
+    let info = if cmd == SockIoctlCmd::SIOCGIFNAME {
+        u32::try_from(req.index())
+            .ok()
+            .and_then(net::interface_info)
+    } else {
+        net::interface_index(&req.name()).and_then(net::interface_info)
+    };
+    let info = info.ok_or(SysError::ENODEV)?;
+    match cmd {
+        SockIoctlCmd::SIOCGIFNAME => req.set_name(&info.name),
+        SockIoctlCmd::SIOCGIFFLAGS => req.set_i32(info.flags as i32),
+        SockIoctlCmd::SIOCSIFFLAGS => {
+            if !task.has_capability(CapabilitiesFlags::CAP_NET_ADMIN) {
+                return Err(SysError::EPERM);
+            }
+            net::set_interface_up(info.index, req.flags() as u32 & IFF_UP != 0)?;
+            return Ok(0);
+        }
+        SockIoctlCmd::SIOCGIFADDR => {
+            let cidr = ipv4_addr(&info).ok_or(SysError::EADDRNOTAVAIL)?;
+            req.set_addr(cidr.address().octets());
+        }
+        SockIoctlCmd::SIOCGIFNETMASK => {
+            let cidr = ipv4_addr(&info).ok_or(SysError::EADDRNOTAVAIL)?;
+            req.set_addr(netmask(cidr.prefix_len()));
+        }
+        SockIoctlCmd::SIOCGIFMTU => req.set_i32(info.mtu as i32),
+        SockIoctlCmd::SIOCGIFHWADDR => {
+            let hatype = if info.flags & IFF_LOOPBACK != 0 {
+                ARPHRD_LOOPBACK
+            } else {
+                ARPHRD_ETHER
+            };
+            req.set_sockaddr(hatype, &info.hw_addr);
+        }
+        SockIoctlCmd::SIOCGIFINDEX => req.set_i32(info.index as i32),
+        SockIoctlCmd::SIOCGIFCONF => unreachable!(),
+    }
+    unsafe { UserWritePtr::<IfReq>::new(arg, &addrspace).write(req)? };
+    Ok(0)
+}
@@ -1,6 +1,6 @@
 use alloc::string::ToString;
 use config::inode::InodeMode;
-use smoltcp::wire::{IpAddress, IpListenEndpoint};
+use smoltcp::wire::IpListenEndpoint;
 use strum::FromRepr;
 use systype::error::{SysError, SysResult};
 
@@ -8,6 +8,7 @@ use crate::processor::current_task;
 
 pub mod addr;
 pub mod interface;
+pub mod ioctl;
 pub mod msg;
 pub mod sock;
 pub mod socket;
@@ -50,12 +51,5 @@ fn check_unix_path(path: &str) -> SysResult<()> {
 }
 
 fn is_local_ip(listen_ep: &IpListenEndpoint) -> bool {
-    if let Some(addr) = &listen_ep.addr {
-        match addr {
-            IpAddress::Ipv4(ipv4) => ipv4.is_loopback() || ipv4.is_unspecified(),
-            IpAddress::Ipv6(ipv6) => ipv6.is_loopback() || ipv6.is_unspecified(),
-        }
-    } else {
-        true // 0.0.0.0
-    }
+    listen_ep.addr.is_none_or(net::is_local_addr) // None is 0.0.0.0
 }
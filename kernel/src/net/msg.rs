@@ -1,3 +1,14 @@
+use core::{
+    net::{Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use systype::error::{SysError, SysResult};
+
+use super::sockopt::{IpSocketOpt, Ipv6SocketOpt, SocketLevel, SocketOpt};
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct MsgHdr {
@@ -23,3 +34,170 @@ pub struct IoVec {
     pub iov_base: usize, // 数据缓冲区指针
     pub iov_len: usize,  // 缓冲区长度
 }
+
+bitflags! {
+    /// `MSG_*` flags of the `send()` and `recv()` families, and of `msg_flags` of a
+    /// received message.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct MsgFlags: u32 {
+        /// Out-of-band data, which is not supported.
+        const OOB = 0x1;
+        /// Receive data without removing it from the receive queue.
+        const PEEK = 0x2;
+        const DONTROUTE = 0x4;
+        /// Set on a received message whose control messages are truncated.
+        const CTRUNC = 0x8;
+        /// Return the real length of a datagram even if it is longer than the buffer, or
+        /// discard the received data of a stream socket. Set on a received datagram
+        /// which is truncated.
+        const TRUNC = 0x20;
+        /// Fail with `EAGAIN` instead of blocking, as if the socket was nonblocking.
+        const DONTWAIT = 0x40;
+        const EOR = 0x80;
+        /// Block until the whole buffer of a stream socket is filled.
+        const WAITALL = 0x100;
+        /// Don't raise `SIGPIPE` when a stream socket can't send any more.
+        const NOSIGNAL = 0x4000;
+        /// More data is to be sent, so a datagram socket holds the data back and sends
+        /// it in one datagram with the data of the next send without this flag.
+        const MORE = 0x8000;
+        const WAITFORONE = 0x10000;
+        const CMSG_CLOEXEC = 0x4000_0000;
+    }
+}
+
+/// Header of a control message in the ancillary data of `sendmsg()` and `recvmsg()`,
+/// which is followed by the data of the message.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct CmsgHdr {
+    /// Length of the header and the data, excluding the padding after the data.
+    pub cmsg_len: usize,
+    pub cmsg_level: i32,
+    pub cmsg_type: i32,
+}
+
+/// Rounds `len` up to the alignment of control messages, as `CMSG_ALIGN` does.
+pub const fn cmsg_align(len: usize) -> usize {
+    len.next_multiple_of(size_of::<usize>())
+}
+
+/// A control message in the ancillary data of `sendmsg()` and `recvmsg()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlMessage {
+    /// `IP_PKTINFO`, the interface and the local and destination addresses of a
+    /// received datagram, or the source address `spec_dst` of a datagram to send.
+    PktInfo {
+        ifindex: u32,
+        spec_dst: Ipv4Addr,
+        addr: Ipv4Addr,
+    },
+    /// `IPV6_PKTINFO`, the interface and the destination address of a received
+    /// datagram, or the source address of a datagram to send.
+    Ipv6PktInfo { ifindex: u32, addr: Ipv6Addr },
+    /// `IP_TTL`, the TTL of a received datagram or of a datagram to send.
+    Ttl(u8),
+    /// `SCM_TIMESTAMP`, the time a datagram is received.
+    Timestamp(Duration),
+}
+
+impl ControlMessage {
+    /// Parses the control messages in `buf`, the ancillary data of `sendmsg()`.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if a control message is malformed or is not supported.
+    pub fn parse_all(mut buf: &[u8]) -> SysResult<Vec<Self>> {
+        const HDR_LEN: usize = size_of::<CmsgHdr>();
+        let mut cmsgs = Vec::new();
+        while buf.len() >= HDR_LEN {
+            // SAFETY: `buf` holds a header, which may be unaligned.
+            let hdr = unsafe { (buf.as_ptr() as *const CmsgHdr).read_unaligned() };
+            let len = hdr.cmsg_len;
+            if len < HDR_LEN || len > buf.len() {
+                return Err(SysError::EINVAL);
+            }
+            cmsgs.push(Self::parse(
+                hdr.cmsg_level,
+                hdr.cmsg_type,
+                &buf[HDR_LEN..len],
+            )?);
+            buf = &buf[cmsg_align(len).min(buf.len())..];
+        }
+        Ok(cmsgs)
+    }
+
+    fn parse(level: i32, ty: i32, data: &[u8]) -> SysResult<Self> {
+        let level = SocketLevel::try_from(level as u32 as usize)?;
+        let ty = ty as usize;
+        let cmsg = match level {
+            SocketLevel::IPPROTO_IP if ty == IpSocketOpt::PKTINFO as usize && data.len() >= 12 => {
+                Self::PktInfo {
+                    ifindex: u32::from_ne_bytes(data[..4].try_into().unwrap()),
+                    spec_dst: Ipv4Addr::from(<[u8; 4]>::try_from(&data[4..8]).unwrap()),
+                    addr: Ipv4Addr::from(<[u8; 4]>::try_from(&data[8..12]).unwrap()),
+                }
+            }
+            SocketLevel::IPPROTO_IP if ty == IpSocketOpt::TTL as usize && data.len() >= 4 => {
+                match i32::from_ne_bytes(data[..4].try_into().unwrap()) {
+                    ttl @ 1..=255 => Self::Ttl(ttl as u8),
+                    _ => return Err(SysError::EINVAL),
+                }
+            }
+            SocketLevel::IPPROTO_IPV6
+                if ty == Ipv6SocketOpt::PKTINFO as usize && data.len() >= 20 =>
+            {
+                Self::Ipv6PktInfo {
+                    addr: Ipv6Addr::from(<[u8; 16]>::try_from(&data[..16]).unwrap()),
+                    ifindex: u32::from_ne_bytes(data[16..20].try_into().unwrap()),
+                }
+            }
+            _ => {
+                log::warn!("[ControlMessage::parse] unsupported control message {level:?} {ty}");
+                return Err(SysError::EINVAL);
+            }
+        };
+        Ok(cmsg)
+    }
+
+    /// Appends the control message to `buf`, the ancillary data of `recvmsg()`,
+    /// padded to the alignment of the next control message.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        let mut data = Vec::new();
+        let (level, ty) = match *self {
+            Self::PktInfo {
+                ifindex,
+                spec_dst,
+                addr,
+            } => {
+                // `struct in_pktinfo`
+                data.extend_from_slice(&ifindex.to_ne_bytes());
+                data.extend_from_slice(&spec_dst.octets());
+                data.extend_from_slice(&addr.octets());
+                (SocketLevel::IPPROTO_IP, IpSocketOpt::PKTINFO as i32)
+            }
+            Self::Ipv6PktInfo { ifindex, addr } => {
+                // `struct in6_pktinfo`
+                data.extend_from_slice(&addr.octets());
+                data.extend_from_slice(&ifindex.to_ne_bytes());
+                (SocketLevel::IPPROTO_IPV6, Ipv6SocketOpt::PKTINFO as i32)
+            }
+            Self::Ttl(ttl) => {
+                data.extend_from_slice(&(ttl as i32).to_ne_bytes());
+                (SocketLevel::IPPROTO_IP, IpSocketOpt::TTL as i32)
+            }
+            Self::Timestamp(time) => {
+                // `struct timeval`
+                data.extend_from_slice(&(time.as_secs() as usize).to_ne_bytes());
+                data.extend_from_slice(&(time.subsec_micros() as usize).to_ne_bytes());
+                (SocketLevel::SOL_SOCKET, SocketOpt::TIMESTAMP_OLD as i32)
+            }
+        };
+        let start = buf.len();
+        let len = size_of::<CmsgHdr>() + data.len();
+        buf.extend_from_slice(&len.to_ne_bytes());
+        buf.extend_from_slice(&(level as i32).to_ne_bytes());
+        buf.extend_from_slice(&ty.to_ne_bytes());
+        buf.extend_from_slice(&data);
+        buf.resize(start + cmsg_align(len), 0);
+    }
+}
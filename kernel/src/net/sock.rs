@@ -3,27 +3,45 @@ use core::{sync::atomic::Ordering, task::Waker};
 use alloc::sync::Arc;
 use net::{
     NetPollState,
-    addr::UNSPECIFIED_IPV4,
+    addr::{UNSPECIFIED_IPV4, UNSPECIFIED_IPV6},
+    netlink::NetlinkSocket,
+    packet::PacketSocket,
     raw::RawSocket,
     tcp::core::TcpSocket,
-    udp::UdpSocket,
+    udp::{UdpSendOpts, UdpSocket},
     unix::{UnixSocket, extract_path_from_sockaddr_un},
 };
-use smoltcp::wire::IpEndpoint;
+use smoltcp::wire::{IpAddress, IpEndpoint};
 use systype::error::{SysError, SysResult};
 
-use crate::processor::current_task;
+use crate::{processor::current_task, task::cap::CapabilitiesFlags};
 
 use super::{
     addr::{SaFamily, SockAddr, SockAddrUn},
     check_unix_path, is_local_ip,
+    msg::{ControlMessage, MsgFlags},
 };
 
+/// Metadata of a message received by [`Sock::recv_msg`].
+pub struct RecvMeta {
+    /// Length of the message, which is larger than the number of bytes received if a
+    /// datagram is truncated.
+    pub len: usize,
+    /// Source address of the message.
+    pub src: SockAddr,
+    /// Destination address of a datagram.
+    pub dst: Option<IpAddress>,
+    /// TTL or hop limit of a datagram, if it is known.
+    pub hop_limit: Option<u8>,
+}
+
 pub enum Sock {
     Tcp(TcpSocket),
     Udp(UdpSocket),
     Unix(Arc<UnixSocket>),
     Raw(RawSocket),
+    Netlink(Arc<NetlinkSocket>),
+    Packet(Arc<PacketSocket>),
 }
 
 impl Sock {
@@ -33,6 +51,8 @@ impl Sock {
             Sock::Udp(udp) => udp.set_nonblocking(true),
             Sock::Unix(_unix) => (),
             Sock::Raw(raw) => raw.set_nonblocking(true),
+            Sock::Netlink(netlink) => netlink.set_nonblocking(true),
+            Sock::Packet(packet) => packet.set_nonblocking(true),
         }
     }
 
@@ -44,7 +64,12 @@ impl Sock {
                 if !is_local_ip(&listen_ep) {
                     return Err(SysError::EADDRNOTAVAIL);
                 }
-                let addr = listen_ep.addr.unwrap_or(UNSPECIFIED_IPV4);
+                let unspecified = if tcp.is_ipv6() {
+                    UNSPECIFIED_IPV6
+                } else {
+                    UNSPECIFIED_IPV4
+                };
+                let addr = listen_ep.addr.unwrap_or(unspecified);
                 tcp.bind(IpEndpoint::new(addr, listen_ep.port))
             }
             (Sock::Udp(udp), SaFamily::AF_INET) | (Sock::Udp(udp), SaFamily::AF_INET6) => {
@@ -71,6 +96,14 @@ impl Sock {
                     Err(e) => Err(e),
                 }
             }
+            (Sock::Netlink(netlink), SaFamily::AF_NETLINK) => {
+                let addr = unsafe { local_addr.netlink };
+                netlink.bind(addr.pid, addr.groups, current_task().pid() as u32)
+            }
+            (Sock::Packet(packet), SaFamily::AF_PACKET) => {
+                let addr = unsafe { local_addr.packet };
+                packet.bind(u16::from_be_bytes(addr.protocol), addr.ifindex as u32)
+            }
             _ => Err(SysError::EAFNOSUPPORT),
         }
     }
@@ -79,10 +112,9 @@ impl Sock {
         match self {
             Sock::Tcp(tcp) => tcp.listen(current_task().waker_mut().as_ref().unwrap()),
             Sock::Udp(_udp) => Err(SysError::EOPNOTSUPP),
-            Sock::Raw(_raw) => {
-                todo!()
+            Sock::Raw(_) | Sock::Unix(_) | Sock::Netlink(_) | Sock::Packet(_) => {
+                Err(SysError::EOPNOTSUPP)
             }
-            Sock::Unix(_unix) => Err(SysError::EOPNOTSUPP),
         }
     }
 
@@ -96,29 +128,31 @@ impl Sock {
                 let remote_addr = remote_addr.as_endpoint();
                 udp.connect(remote_addr)
             }
-            Sock::Raw(_raw) => {
-                todo!()
-            }
+            Sock::Raw(raw) => raw.connect(remote_addr.as_endpoint().addr),
             Sock::Unix(unix) => unsafe {
                 let path = extract_path_from_sockaddr_un(&remote_addr.unix.path);
                 unix.connect(&path)
             },
+            // Netlink sockets only talk to the kernel.
+            Sock::Netlink(_) => Ok(()),
+            Sock::Packet(_) => Err(SysError::EOPNOTSUPP),
         }
     }
 
     pub fn peer_addr(&self) -> SysResult<SockAddr> {
         match self {
             Sock::Tcp(tcp) => {
-                let peer_addr = SockAddr::from_endpoint(tcp.peer_addr()?);
+                let peer_addr = self.sockaddr(tcp.peer_addr()?);
                 Ok(peer_addr)
             }
             Sock::Udp(udp) => {
-                let peer_addr = SockAddr::from_endpoint(udp.peer_addr()?);
+                let peer_addr = self.sockaddr(udp.peer_addr()?);
                 Ok(peer_addr)
             }
-            Sock::Raw(_raw) => {
-                todo!()
-            }
+            Sock::Raw(raw) => Ok(SockAddr::from_endpoint(IpEndpoint::new(
+                raw.peer_addr()?,
+                0,
+            ))),
             Sock::Unix(unix) => {
                 let peer = unix.peer.lock();
                 if let Some(peer_sock) = &*peer {
@@ -139,21 +173,28 @@ impl Sock {
                     Err(SysError::ENOTCONN)
                 }
             }
+            Sock::Netlink(_) => Ok(SockAddr::from_netlink(0, 0)),
+            Sock::Packet(_) => Err(SysError::EOPNOTSUPP),
         }
     }
 
     pub fn local_addr(&self) -> SysResult<SockAddr> {
         match self {
             Sock::Tcp(tcp) => {
-                let local_addr = SockAddr::from_endpoint(tcp.local_addr()?);
+                let local_addr = self.sockaddr(tcp.local_addr()?);
                 Ok(local_addr)
             }
             Sock::Udp(udp) => {
-                let local_addr = SockAddr::from_endpoint(udp.local_addr()?);
+                let local_addr = self.sockaddr(udp.local_addr()?);
                 Ok(local_addr)
             }
-            Sock::Raw(_raw) => {
-                todo!()
+            Sock::Raw(raw) => {
+                let unspecified = if raw.is_ipv6() {
+                    UNSPECIFIED_IPV6
+                } else {
+                    UNSPECIFIED_IPV4
+                };
+                Ok(SockAddr::from_endpoint(IpEndpoint::new(unspecified, 0)))
             }
             Sock::Unix(unix) => {
                 let path_opt = unix.path.lock();
@@ -170,6 +211,12 @@ impl Sock {
                     Err(SysError::ENOTCONN)
                 }
             }
+            Sock::Netlink(netlink) => {
+                Ok(SockAddr::from_netlink(netlink.portid(), netlink.groups()))
+            }
+            Sock::Packet(packet) => Ok(SockAddr {
+                packet: packet.local_addr().into(),
+            }),
         }
     }
 
@@ -185,6 +232,15 @@ impl Sock {
                 raw.send_raw(buf, dst_addr).await
             }
             Sock::Unix(unix) => unix.send(buf),
+            Sock::Netlink(netlink) => {
+                let task = current_task();
+                let net_admin = task.has_capability(CapabilitiesFlags::CAP_NET_ADMIN);
+                netlink.send(buf, net_admin, task.pid() as u32)
+            }
+            Sock::Packet(packet) => {
+                let dst = remote_addr.map(|addr| unsafe { addr.packet }.into());
+                packet.send(buf, dst)
+            }
         }
     }
 
@@ -192,11 +248,11 @@ impl Sock {
         match self {
             Sock::Tcp(tcp) => {
                 let bytes = tcp.recv(buf).await?;
-                Ok((bytes, SockAddr::from_endpoint(tcp.peer_addr()?)))
+                Ok((bytes, self.sockaddr(tcp.peer_addr()?)))
             }
             Sock::Udp(udp) => {
                 let (len, endpoint) = udp.recv_from(buf).await?;
-                Ok((len, SockAddr::from_endpoint(endpoint)))
+                Ok((len, self.sockaddr(endpoint)))
             }
             Sock::Raw(raw) => {
                 let (len, src_addr_opt) = raw.recv_raw_with_addr(buf).await?;
@@ -219,6 +275,135 @@ impl Sock {
                 }
                 Ok((n, SockAddr { unix: addr }))
             }
+            Sock::Netlink(netlink) => {
+                let len = netlink.recv(buf).await?;
+                Ok((len, SockAddr::from_netlink(0, 0)))
+            }
+            Sock::Packet(packet) => {
+                let (len, addr) = packet.recv(buf).await?;
+                Ok((len, SockAddr {
+                    packet: addr.into(),
+                }))
+            }
+        }
+    }
+
+    /// Sends `buf` to `remote_addr`, or to the peer of the socket if it is `None`, with
+    /// the control messages `cmsgs`.
+    ///
+    /// Only UDP sockets take the source address and the TTL in control messages. The
+    /// other sockets ignore them, where a raw socket sends the packets as they are given.
+    pub async fn send_msg(
+        &self,
+        buf: &[u8],
+        remote_addr: Option<SockAddr>,
+        cmsgs: &[ControlMessage],
+    ) -> SysResult<usize> {
+        let Sock::Udp(udp) = self else {
+            if !cmsgs.is_empty() {
+                log::warn!("[Sock::send_msg] ignored control messages {cmsgs:?}");
+            }
+            return self.sendto(buf, remote_addr).await;
+        };
+        let mut opts = UdpSendOpts::default();
+        for cmsg in cmsgs {
+            match *cmsg {
+                ControlMessage::PktInfo { spec_dst, .. } => {
+                    opts.src_addr = Some(IpAddress::Ipv4(spec_dst));
+                }
+                ControlMessage::Ipv6PktInfo { addr, .. } => {
+                    // An ipv4-mapped address is the ipv4 address it represents.
+                    opts.src_addr = Some(match addr.to_ipv4_mapped() {
+                        Some(v4) => IpAddress::Ipv4(v4),
+                        None => IpAddress::Ipv6(addr),
+                    });
+                }
+                ControlMessage::Ttl(ttl) => opts.hop_limit = Some(ttl),
+                ControlMessage::Timestamp(_) => return Err(SysError::EINVAL),
+            }
+        }
+        // An unspecified source address leaves the selection to the route.
+        opts.src_addr = opts.src_addr.filter(|addr| !addr.is_unspecified());
+        let remote_addr = match remote_addr {
+            Some(addr) => addr.as_endpoint(),
+            None => udp.peer_addr()?,
+        };
+        udp.send_msg(buf, remote_addr, opts).await
+    }
+
+    /// Receives a message into `buf` and returns the number of bytes received and the
+    /// metadata of the message.
+    ///
+    /// With `MSG_PEEK`, the message is left in the receive queue, which is only
+    /// supported by TCP, UDP and raw sockets. With `MSG_WAITALL`, a stream socket keeps
+    /// receiving until `buf` is full or the peer closes the connection, and returns the
+    /// data received so far if an error or a signal interrupts it.
+    pub async fn recv_msg(&self, buf: &mut [u8], flags: MsgFlags) -> SysResult<(usize, RecvMeta)> {
+        let peek = flags.contains(MsgFlags::PEEK);
+        match self {
+            Sock::Tcp(tcp) => {
+                let mut received = 0;
+                loop {
+                    let ret = if peek {
+                        tcp.peek(&mut buf[received..]).await
+                    } else {
+                        tcp.recv(&mut buf[received..]).await
+                    };
+                    match ret {
+                        Ok(0) => break,
+                        Ok(len) => received += len,
+                        Err(_) if received > 0 => break,
+                        Err(e) => return Err(e),
+                    }
+                    if peek || !flags.contains(MsgFlags::WAITALL) || received == buf.len() {
+                        break;
+                    }
+                }
+                Ok((received, RecvMeta {
+                    len: received,
+                    src: self.sockaddr(tcp.peer_addr()?),
+                    dst: None,
+                    hop_limit: None,
+                }))
+            }
+            Sock::Udp(udp) => {
+                let meta = udp.recv_msg(buf, peek).await?;
+                Ok((meta.len.min(buf.len()), RecvMeta {
+                    len: meta.len,
+                    src: self.sockaddr(meta.remote),
+                    dst: meta.local_addr,
+                    hop_limit: None,
+                }))
+            }
+            Sock::Raw(raw) => {
+                let meta = raw.recv_msg(buf, peek).await?;
+                let src_addr = meta.src_addr.unwrap_or(IpAddress::v4(0, 0, 0, 0));
+                Ok((meta.len.min(buf.len()), RecvMeta {
+                    len: meta.len,
+                    src: SockAddr::from_endpoint(IpEndpoint::new(src_addr, 0)),
+                    dst: meta.dst_addr,
+                    hop_limit: meta.hop_limit,
+                }))
+            }
+            Sock::Netlink(netlink) => {
+                let len = netlink.recv_msg(buf, peek).await?;
+                Ok((len.min(buf.len()), RecvMeta {
+                    len,
+                    src: SockAddr::from_netlink(0, 0),
+                    dst: None,
+                    hop_limit: None,
+                }))
+            }
+            _ if peek => Err(SysError::EOPNOTSUPP),
+            _ => {
+                let (len, src) = self.recvfrom(buf).await?;
+                Ok((len, RecvMeta {
+                    len,
+                    src,
+                    dst: None,
+                    hop_limit: None,
+                }))
+            }
         }
     }
 
@@ -227,6 +412,8 @@ impl Sock {
             Sock::Tcp(tcp) => tcp.poll().await,
             Sock::Udp(udp) => udp.poll().await,
             Sock::Raw(raw) => raw.poll().await,
+            Sock::Netlink(netlink) => netlink.poll().await,
+            Sock::Packet(packet) => packet.poll().await,
             Sock::Unix(_unix) => unimplemented!(),
         }
     }
@@ -236,6 +423,7 @@ impl Sock {
             Sock::Tcp(tcp) => tcp.shutdown(how),
             Sock::Udp(udp) => udp.shutdown(),
             Sock::Raw(raw) => raw.shutdown(),
+            Sock::Netlink(_) | Sock::Packet(_) => Ok(()),
             Sock::Unix(_unix) => unimplemented!(),
         }
     }
@@ -247,7 +435,7 @@ impl Sock {
                 Ok(new_tcp)
             }
             Sock::Udp(_udp) => Err(SysError::EOPNOTSUPP),
-            Sock::Raw(_raw) => Err(SysError::EOPNOTSUPP),
+            Sock::Raw(_) | Sock::Netlink(_) | Sock::Packet(_) => Err(SysError::EOPNOTSUPP),
             Sock::Unix(_unix) => unimplemented!(),
         }
     }
@@ -256,10 +444,9 @@ impl Sock {
         match self {
             Sock::Tcp(tcp) => tcp.register_recv_waker(&waker),
             Sock::Udp(udp) => udp.register_recv_waker(&waker),
-            Sock::Raw(_raw) => {
-                todo!()
-            }
-            Sock::Unix(_unix) => unimplemented!(),
+            Sock::Raw(raw) => raw.register_recv_waker(&waker),
+            // These sockets wake the tasks polling them by themselves.
+            Sock::Unix(_) | Sock::Netlink(_) | Sock::Packet(_) => {}
         }
     }
 
@@ -267,10 +454,9 @@ impl Sock {
         match self {
             Sock::Tcp(tcp) => tcp.register_send_waker(&waker),
             Sock::Udp(udp) => udp.register_send_waker(&waker),
-            Sock::Raw(_raw) => {
-                todo!()
-            }
-            Sock::Unix(_unix) => unimplemented!(),
+            Sock::Raw(raw) => raw.register_send_waker(&waker),
+            // These sockets wake the tasks polling them by themselves.
+            Sock::Unix(_) | Sock::Netlink(_) | Sock::Packet(_) => {}
         }
     }
 
@@ -285,4 +471,76 @@ impl Sock {
             udp.reuse_port.store(val, Ordering::SeqCst);
         }
     }
+
+    /// Returns whether the socket is an `AF_INET6` socket.
+    pub fn is_ipv6(&self) -> bool {
+        match self {
+            Sock::Tcp(tcp) => tcp.is_ipv6(),
+            Sock::Udp(udp) => udp.is_ipv6(),
+            Sock::Raw(_) | Sock::Unix(_) | Sock::Netlink(_) | Sock::Packet(_) => false,
+        }
+    }
+
+    /// Converts `endpoint` to a socket address of the address family of the socket.
+    ///
+    /// An `AF_INET6` socket communicating with an ipv4 peer sees its address as an
+    /// ipv4-mapped ipv6 address.
+    fn sockaddr(&self, endpoint: IpEndpoint) -> SockAddr {
+        if self.is_ipv6() {
+            SockAddr::from_endpoint_v6(endpoint)
+        } else {
+            SockAddr::from_endpoint(endpoint)
+        }
+    }
+
+    pub fn ipv6_only(&self) -> SysResult<bool> {
+        match self {
+            Sock::Tcp(tcp) if tcp.is_ipv6() => Ok(tcp.ipv6_only()),
+            Sock::Udp(udp) if udp.is_ipv6() => Ok(udp.ipv6_only()),
+            _ => Err(SysError::ENOPROTOOPT),
+        }
+    }
+
+    pub fn set_ipv6_only(&self, val: bool) -> SysResult<()> {
+        match self {
+            Sock::Tcp(tcp) => tcp.set_ipv6_only(val),
+            Sock::Udp(udp) => udp.set_ipv6_only(val),
+            _ => Err(SysError::ENOPROTOOPT),
+        }
+    }
+
+    /// Returns the hop limit of outgoing packets, or `None` if it is the default one.
+    pub fn hop_limit(&self) -> SysResult<Option<u8>> {
+        match self {
+            Sock::Tcp(tcp) => Ok(tcp.hop_limit()),
+            Sock::Udp(udp) => Ok(udp.hop_limit()),
+            _ => Err(SysError::ENOPROTOOPT),
+        }
+    }
+
+    pub fn set_hop_limit(&self, hop_limit: Option<u8>) -> SysResult<()> {
+        match self {
+            Sock::Tcp(tcp) => tcp.set_hop_limit(hop_limit),
+            Sock::Udp(udp) => udp.set_hop_limit(hop_limit),
+            _ => Err(SysError::ENOPROTOOPT),
+        }
+    }
+
+    /// Returns the index of the interface the socket is bound to by `SO_BINDTODEVICE`.
+    pub fn bound_device(&self) -> SysResult<Option<u32>> {
+        match self {
+            Sock::Tcp(tcp) => Ok(tcp.bound_device()),
+            Sock::Udp(udp) => Ok(udp.bound_device()),
+            _ => Err(SysError::ENOPROTOOPT),
+        }
+    }
+
+    pub fn bind_to_device(&self, ifindex: Option<u32>) -> SysResult<()> {
+        match self {
+            Sock::Tcp(tcp) => tcp.bind_to_device(ifindex),
+            Sock::Udp(udp) => udp.bind_to_device(ifindex),
+            _ => return Err(SysError::ENOPROTOOPT),
+        }
+        Ok(())
+    }
 }
@@ -1,19 +1,83 @@
-use alloc::{boxed::Box, sync::Arc};
+use core::net::Ipv4Addr;
 
-use async_trait::async_trait;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
 
+use arch::time::get_time_duration;
+use async_trait::async_trait;
+use bitflags::bitflags;
 use config::vfs::{OpenFlags, PollEvents};
+use mutex::SpinNoIrqLock;
 use net::{
-    poll_interfaces, raw::RawSocket, tcp::core::TcpSocket, udp::UdpSocket, unix::UnixSocket,
+    netlink::NetlinkSocket, packet::PacketSocket, poll_interfaces, raw::RawSocket,
+    tcp::core::TcpSocket, udp::UdpSocket, unix::UnixSocket,
 };
-use systype::error::{SysError, SysResult};
+use smoltcp::wire::IpAddress;
+use systype::error::{SysError, SysResult, SyscallResult};
 use vfs::{
     file::{File, FileMeta},
     sys_root_dentry,
 };
 
-use super::{SocketType, addr::SaFamily, sock::Sock};
-use crate::processor::current_task;
+use super::{
+    SocketType,
+    addr::{SaFamily, SockAddr},
+    ioctl::{SockIoctlCmd, interface_ioctl},
+    msg::{ControlMessage, MsgFlags},
+    sock::Sock,
+};
+use crate::{
+    processor::current_task,
+    syscall::time::{CLOCK_DEVIATION, CLOCK_REALTIME},
+    task::{
+        cap::CapabilitiesFlags,
+        signal::sig_info::{Sig, SigDetails, SigInfo},
+    },
+};
+
+bitflags! {
+    /// Control messages a socket receives with each message, which are enabled by
+    /// socket options.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct RecvCmsgs: u8 {
+        /// `IP_PKTINFO`
+        const PKTINFO = 1 << 0;
+        /// `IP_RECVTTL`
+        const TTL = 1 << 1;
+        /// `SO_TIMESTAMP`
+        const TIMESTAMP = 1 << 2;
+        /// `IPV6_RECVPKTINFO`
+        const IPV6_PKTINFO = 1 << 3;
+    }
+}
+
+/// Maximum length of a datagram corked by `MSG_MORE`.
+const MAX_CORK_LEN: usize = u16::MAX as usize;
+
+/// TTL of received UDP datagrams, which is not kept by the network stack.
+const DEFAULT_TTL: u8 = 64;
+
+/// A datagram being corked by sends with `MSG_MORE`, which is sent to the destination
+/// and with the control messages of the first of the sends.
+struct Cork {
+    data: Vec<u8>,
+    dest: Option<SockAddr>,
+    cmsgs: Vec<ControlMessage>,
+}
+
+/// A message received by [`Socket::recv_msg`].
+pub struct RecvMsg {
+    /// Number of bytes received into the buffer, which is 0 if a stream socket
+    /// discards the received data by `MSG_TRUNC`.
+    pub copied: usize,
+    /// Return value of `recvmsg()`, which is the real length of a truncated datagram
+    /// with `MSG_TRUNC`.
+    pub ret: usize,
+    /// Source address of the message.
+    pub src: SockAddr,
+    pub cmsgs: Vec<ControlMessage>,
+    /// `msg_flags` of the message.
+    pub flags: MsgFlags,
+}
 
 /// Socket is for user, Sock is for kernel.
 pub struct Socket {
@@ -23,6 +87,10 @@ pub struct Socket {
     pub sk: Sock,
     /// File metadata, including metadata information related to sockets
     pub meta: FileMeta,
+    /// Control messages received with each message.
+    recv_cmsgs: SpinNoIrqLock<RecvCmsgs>,
+    /// Datagram being corked by `MSG_MORE`.
+    cork: SpinNoIrqLock<Option<Cork>>,
 }
 
 unsafe impl Sync for Socket {}
@@ -32,7 +100,7 @@ impl Socket {
     pub fn new(
         domain: SaFamily,
         types: SocketType,
-        protocol: u8,
+        protocol: u16,
         nonblock: bool,
     ) -> SysResult<Self> {
         let sk = match domain {
@@ -40,7 +108,7 @@ impl Socket {
             SaFamily::AF_INET => match types {
                 SocketType::STREAM => Sock::Tcp(TcpSocket::new_v4()),
                 SocketType::DGRAM => Sock::Udp(UdpSocket::new()),
-                SocketType::RAW => Sock::Raw(RawSocket::new(protocol)),
+                SocketType::RAW => Sock::Raw(RawSocket::new(protocol as u8)),
                 _ => {
                     log::error!(
                         "[Socket::new] Unsupported socket type: {types:?} for domain: {domain:?}"
@@ -51,7 +119,18 @@ impl Socket {
             SaFamily::AF_INET6 => match types {
                 SocketType::STREAM => Sock::Tcp(TcpSocket::new_v6()),
                 SocketType::DGRAM => Sock::Udp(UdpSocket::new_v6()),
-                SocketType::RAW => Sock::Raw(RawSocket::new_v6(protocol)),
+                SocketType::RAW => Sock::Raw(RawSocket::new_v6(protocol as u8)),
+                _ => {
+                    log::error!(
+                        "[Socket::new] Unsupported socket type: {types:?} for domain: {domain:?}"
+                    );
+                    return Err(SysError::EPROTONOSUPPORT);
+                }
+            },
+            SaFamily::AF_NETLINK => match types {
+                SocketType::RAW | SocketType::DGRAM => {
+                    Sock::Netlink(Arc::new(NetlinkSocket::new(protocol as u32)?))
+                }
                 _ => {
                     log::error!(
                         "[Socket::new] Unsupported socket type: {types:?} for domain: {domain:?}"
@@ -59,6 +138,21 @@ impl Socket {
                     return Err(SysError::EPROTONOSUPPORT);
                 }
             },
+            SaFamily::AF_PACKET => {
+                if !current_task().has_capability(CapabilitiesFlags::CAP_NET_RAW) {
+                    return Err(SysError::EPERM);
+                }
+                // The protocol is given in network byte order.
+                let protocol = u16::from_be(protocol);
+                match types {
+                    SocketType::RAW => Sock::Packet(PacketSocket::new(true, protocol)),
+                    SocketType::DGRAM => Sock::Packet(PacketSocket::new(false, protocol)),
+                    _ => {
+                        log::error!("[Socket::new] Unsupported packet socket type: {types:?}");
+                        return Err(SysError::EPROTONOSUPPORT);
+                    }
+                }
+            }
         };
 
         let flags = if nonblock {
@@ -70,7 +164,13 @@ impl Socket {
         let meta = FileMeta::new(sys_root_dentry());
         *meta.flags.lock() = flags;
 
-        Ok(Self { types, sk, meta })
+        Ok(Self {
+            types,
+            sk,
+            meta,
+            recv_cmsgs: SpinNoIrqLock::new(RecvCmsgs::empty()),
+            cork: SpinNoIrqLock::new(None),
+        })
     }
 
     pub fn from_another(another: &Self, sk: Sock) -> Self {
@@ -80,7 +180,187 @@ impl Socket {
             types: another.types,
             sk,
             meta,
+            recv_cmsgs: SpinNoIrqLock::new(*another.recv_cmsgs.lock()),
+            cork: SpinNoIrqLock::new(None),
+        }
+    }
+
+    /// Returns whether the socket receives the control messages `cmsgs`.
+    pub fn recv_cmsgs(&self, cmsgs: RecvCmsgs) -> bool {
+        self.recv_cmsgs.lock().contains(cmsgs)
+    }
+
+    /// Sets whether the socket receives the control messages `cmsgs`.
+    pub fn set_recv_cmsgs(&self, cmsgs: RecvCmsgs, enable: bool) {
+        self.recv_cmsgs.lock().set(cmsgs, enable);
+    }
+
+    /// Sends `buf` to `dest`, or to the peer of the socket if it is `None`, with the
+    /// control messages `cmsgs` and the `MSG_*` flags `flags`.
+    ///
+    /// A stream socket which can't send any more fails with `EPIPE` and raises
+    /// `SIGPIPE`, unless `MSG_NOSIGNAL` is given.
+    pub async fn send_msg(
+        &self,
+        buf: &[u8],
+        dest: Option<SockAddr>,
+        cmsgs: &[ControlMessage],
+        flags: MsgFlags,
+    ) -> SysResult<usize> {
+        if flags.contains(MsgFlags::OOB) {
+            return Err(SysError::EOPNOTSUPP);
+        }
+        if flags.contains(MsgFlags::DONTWAIT) && !self.is_ready(false).await {
+            return Err(SysError::EAGAIN);
+        }
+        let ret = match self.types {
+            SocketType::DGRAM | SocketType::RAW
+                if flags.contains(MsgFlags::MORE) || self.cork.lock().is_some() =>
+            {
+                self.send_corked(buf, dest, cmsgs, flags.contains(MsgFlags::MORE))
+                    .await
+            }
+            _ => self.sk.send_msg(buf, dest, cmsgs).await,
+        };
+        if self.types == SocketType::STREAM
+            && matches!(ret, Err(SysError::EPIPE))
+            && !flags.contains(MsgFlags::NOSIGNAL)
+        {
+            current_task().receive_siginfo(SigInfo {
+                sig: Sig::SIGPIPE,
+                code: SigInfo::KERNEL,
+                details: SigDetails::None,
+            });
+        }
+        ret
+    }
+
+    /// Appends `buf` to the datagram being corked, and sends the datagram unless `more`
+    /// is true, i.e., `MSG_MORE` is given.
+    ///
+    /// # Errors
+    /// Returns `EMSGSIZE` if the datagram would be too long, in which case `buf` is not
+    /// appended.
+    async fn send_corked(
+        &self,
+        buf: &[u8],
+        dest: Option<SockAddr>,
+        cmsgs: &[ControlMessage],
+        more: bool,
+    ) -> SysResult<usize> {
+        let cork = {
+            let mut cork = self.cork.lock();
+            let corked = cork.get_or_insert_with(|| Cork {
+                data: Vec::new(),
+                dest,
+                cmsgs: cmsgs.to_vec(),
+            });
+            if corked.data.len() + buf.len() > MAX_CORK_LEN {
+                return Err(SysError::EMSGSIZE);
+            }
+            corked.data.extend_from_slice(buf);
+            if more {
+                return Ok(buf.len());
+            }
+            cork.take().unwrap()
+        };
+        self.sk.send_msg(&cork.data, cork.dest, &cork.cmsgs).await?;
+        Ok(buf.len())
+    }
+
+    /// Receives a message into `buf` with the `MSG_*` flags `flags`, along with the
+    /// control messages enabled on the socket.
+    ///
+    /// `SO_TIMESTAMP` takes the time the message is received by the socket rather than
+    /// by the interface, and `IP_RECVTTL` reports the default TTL for UDP datagrams, of
+    /// which the network stack doesn't keep the TTL.
+    pub async fn recv_msg(&self, buf: &mut [u8], flags: MsgFlags) -> SysResult<RecvMsg> {
+        if flags.contains(MsgFlags::OOB) {
+            return Err(SysError::EINVAL);
+        }
+        if flags.contains(MsgFlags::DONTWAIT) && !self.is_ready(true).await {
+            return Err(SysError::EAGAIN);
+        }
+        let (len, meta) = self.sk.recv_msg(buf, flags).await?;
+
+        let mut msg = RecvMsg {
+            copied: len,
+            ret: len,
+            src: meta.src,
+            cmsgs: Vec::new(),
+            flags: MsgFlags::empty(),
+        };
+        if self.types == SocketType::STREAM {
+            // The received data is discarded.
+            if flags.contains(MsgFlags::TRUNC) {
+                msg.copied = 0;
+            }
+        } else {
+            if meta.len > len {
+                msg.flags |= MsgFlags::TRUNC;
+            }
+            if flags.contains(MsgFlags::TRUNC) {
+                msg.ret = meta.len;
+            }
+        }
+
+        let recv_cmsgs = *self.recv_cmsgs.lock();
+        if recv_cmsgs.contains(RecvCmsgs::TIMESTAMP) {
+            let now = get_time_duration() + unsafe { CLOCK_DEVIATION[CLOCK_REALTIME] };
+            msg.cmsgs.push(ControlMessage::Timestamp(now));
+        }
+        if let Some(dst) = meta.dst {
+            let ifindex = net::local_addr_interface(dst).unwrap_or(0);
+            if let IpAddress::Ipv4(addr) = dst {
+                if recv_cmsgs.contains(RecvCmsgs::PKTINFO) {
+                    msg.cmsgs.push(ControlMessage::PktInfo {
+                        ifindex,
+                        spec_dst: Self::spec_dst(addr, ifindex),
+                        addr,
+                    });
+                }
+                if recv_cmsgs.contains(RecvCmsgs::TTL) {
+                    let ttl = meta.hop_limit.unwrap_or(DEFAULT_TTL);
+                    msg.cmsgs.push(ControlMessage::Ttl(ttl));
+                }
+            }
+            if recv_cmsgs.contains(RecvCmsgs::IPV6_PKTINFO) && self.sk.is_ipv6() {
+                let addr = match dst {
+                    IpAddress::Ipv4(addr) => addr.to_ipv6_mapped(),
+                    IpAddress::Ipv6(addr) => addr,
+                };
+                msg.cmsgs
+                    .push(ControlMessage::Ipv6PktInfo { ifindex, addr });
+            }
         }
+        Ok(msg)
+    }
+
+    /// Returns the local address a datagram to `dst` arrives at on the interface
+    /// `ifindex`, which is `dst` itself unless it is a broadcast or multicast address.
+    fn spec_dst(dst: Ipv4Addr, ifindex: u32) -> Ipv4Addr {
+        if net::has_ip_addr(IpAddress::Ipv4(dst)) {
+            return dst;
+        }
+        net::interface_info(ifindex)
+            .and_then(|info| {
+                info.ip_addrs.iter().find_map(|cidr| match cidr.address() {
+                    IpAddress::Ipv4(addr) => Some(addr),
+                    IpAddress::Ipv6(_) => None,
+                })
+            })
+            .unwrap_or(dst)
+    }
+
+    /// Returns whether the socket can receive, or send if `recv` is false, without
+    /// blocking, which is checked for `MSG_DONTWAIT`. A unix socket never blocks.
+    async fn is_ready(&self, recv: bool) -> bool {
+        if matches!(self.sk, Sock::Unix(_)) {
+            return true;
+        }
+        poll_interfaces();
+        let state = self.sk.poll().await;
+        if recv { state.readable } else { state.writable }
     }
 }
 
@@ -107,7 +387,7 @@ impl File for Socket {
             return Ok(0);
         }
         log::warn!("[Socket::File::write_at] begin to send {}", buf.len());
-        let bytes = self.sk.sendto(buf, None).await?;
+        let bytes = self.send_msg(buf, None, &[], MsgFlags::empty()).await?;
         log::warn!(
             "[Socket::File::write_at] expect to send: {:?} bytes exact: {bytes}",
             buf.len()
@@ -142,4 +422,10 @@ impl File for Socket {
         );
         res
     }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> SyscallResult {
+        let cmd = SockIoctlCmd::from_repr(cmd).ok_or(SysError::ENOTTY)?;
+        log::info!("[Socket::ioctl] {cmd:?}");
+        interface_ioctl(cmd, arg)
+    }
 }
@@ -35,6 +35,8 @@ pub enum SocketOpt {
     BINDTODEVICE = 25,
     ATTACH_FILTER = 26,
     DETACH_FILTER = 27,
+    /// Receive the time each datagram is received in a `SCM_TIMESTAMP` control message
+    TIMESTAMP_OLD = 29,
     SNDBUFFORCE = 32,
     RCVBUFFORCE = 33,
 }
@@ -71,6 +73,7 @@ impl TryFrom<usize> for SocketOpt {
             25 => Ok(Self::BINDTODEVICE),
             26 => Ok(Self::ATTACH_FILTER),
             27 => Ok(Self::DETACH_FILTER),
+            29 => Ok(Self::TIMESTAMP_OLD),
             32 => Ok(Self::SNDBUFFORCE),
             33 => Ok(Self::RCVBUFFORCE),
             opt => {
@@ -107,6 +110,196 @@ impl TryFrom<usize> for TcpSocketOpt {
     }
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types)]
+/// used in `sys_setsockopt` and `sys_getsockopt` at the `IPPROTO_IP` level
+///
+/// see https://www.man7.org/linux/man-pages/man7/ip.7.html
+pub enum IpSocketOpt {
+    TOS = 1,
+    /// TTL of outgoing unicast packets, and type of the `IP_TTL` control message
+    TTL = 2,
+    /// Whether the IP header is included in the data of a raw socket
+    HDRINCL = 3,
+    /// Receive the interface and the destination address of each datagram in an
+    /// `IP_PKTINFO` control message
+    PKTINFO = 8,
+    /// Receive the TTL of each datagram in an `IP_TTL` control message
+    RECVTTL = 12,
+    MULTICAST_IF = 32,
+    MULTICAST_TTL = 33,
+    MULTICAST_LOOP = 34,
+    ADD_MEMBERSHIP = 35,
+    DROP_MEMBERSHIP = 36,
+}
+
+impl TryFrom<usize> for IpSocketOpt {
+    type Error = SysError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::TOS),
+            2 => Ok(Self::TTL),
+            3 => Ok(Self::HDRINCL),
+            8 => Ok(Self::PKTINFO),
+            12 => Ok(Self::RECVTTL),
+            32 => Ok(Self::MULTICAST_IF),
+            33 => Ok(Self::MULTICAST_TTL),
+            34 => Ok(Self::MULTICAST_LOOP),
+            35 => Ok(Self::ADD_MEMBERSHIP),
+            36 => Ok(Self::DROP_MEMBERSHIP),
+            opt => {
+                log::warn!("[IpSocketOpt] unsupported option: {opt}");
+                Err(Self::Error::ENOPROTOOPT)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types)]
+/// used in `sys_setsockopt` and `sys_getsockopt` at the `IPPROTO_IPV6` level
+///
+/// see https://www.man7.org/linux/man-pages/man7/ipv6.7.html
+pub enum Ipv6SocketOpt {
+    /// Hop limit of outgoing unicast packets
+    UNICAST_HOPS = 16,
+    /// Interface of outgoing multicast packets
+    MULTICAST_IF = 17,
+    /// Hop limit of outgoing multicast packets
+    MULTICAST_HOPS = 18,
+    /// Whether multicast packets are looped back to local sockets
+    MULTICAST_LOOP = 19,
+    ADD_MEMBERSHIP = 20,
+    DROP_MEMBERSHIP = 21,
+    /// Restrict the socket to IPv6 communication only
+    V6ONLY = 26,
+    /// Receive the interface and the destination address of each datagram in an
+    /// `IPV6_PKTINFO` control message
+    RECVPKTINFO = 49,
+    /// Type of the `IPV6_PKTINFO` control message
+    PKTINFO = 50,
+    /// Traffic class of outgoing packets
+    TCLASS = 67,
+}
+
+impl TryFrom<usize> for Ipv6SocketOpt {
+    type Error = SysError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            16 => Ok(Self::UNICAST_HOPS),
+            17 => Ok(Self::MULTICAST_IF),
+            18 => Ok(Self::MULTICAST_HOPS),
+            19 => Ok(Self::MULTICAST_LOOP),
+            20 => Ok(Self::ADD_MEMBERSHIP),
+            21 => Ok(Self::DROP_MEMBERSHIP),
+            26 => Ok(Self::V6ONLY),
+            49 => Ok(Self::RECVPKTINFO),
+            50 => Ok(Self::PKTINFO),
+            67 => Ok(Self::TCLASS),
+            opt => {
+                log::warn!("[Ipv6SocketOpt] unsupported option: {opt}");
+                Err(Self::Error::ENOPROTOOPT)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types)]
+/// used in `sys_setsockopt` and `sys_getsockopt` at the `SOL_NETLINK` level
+///
+/// see https://www.man7.org/linux/man-pages/man7/netlink.7.html
+pub enum NetlinkSocketOpt {
+    /// Join a multicast group
+    ADD_MEMBERSHIP = 1,
+    /// Leave a multicast group
+    DROP_MEMBERSHIP = 2,
+    /// Extended acknowledgements with error messages
+    EXT_ACK = 11,
+    /// Strict checking of dump requests
+    GET_STRICT_CHK = 12,
+}
+
+impl TryFrom<usize> for NetlinkSocketOpt {
+    type Error = SysError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::ADD_MEMBERSHIP),
+            2 => Ok(Self::DROP_MEMBERSHIP),
+            11 => Ok(Self::EXT_ACK),
+            12 => Ok(Self::GET_STRICT_CHK),
+            opt => {
+                log::warn!("[NetlinkSocketOpt] unsupported option: {opt}");
+                Err(Self::Error::ENOPROTOOPT)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[allow(non_camel_case_types)]
+/// used in `sys_setsockopt` and `sys_getsockopt` at the `SOL_PACKET` level
+///
+/// see https://www.man7.org/linux/man-pages/man7/packet.7.html
+pub enum PacketSocketOpt {
+    /// Join a multicast group or enter promiscuous mode
+    ADD_MEMBERSHIP = 1,
+    /// Leave a multicast group or promiscuous mode
+    DROP_MEMBERSHIP = 2,
+    /// Numbers of received and dropped packets
+    STATISTICS = 6,
+    AUXDATA = 8,
+    VERSION = 10,
+}
+
+impl TryFrom<usize> for PacketSocketOpt {
+    type Error = SysError;
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(Self::ADD_MEMBERSHIP),
+            2 => Ok(Self::DROP_MEMBERSHIP),
+            6 => Ok(Self::STATISTICS),
+            8 => Ok(Self::AUXDATA),
+            10 => Ok(Self::VERSION),
+            opt => {
+                log::warn!("[PacketSocketOpt] unsupported option: {opt}");
+                Err(Self::Error::ENOPROTOOPT)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+/// Argument of `PACKET_ADD_MEMBERSHIP` and `PACKET_DROP_MEMBERSHIP`
+pub struct PacketMreq {
+    pub ifindex: i32,
+    /// `PACKET_MR_*` type of the membership
+    pub ty: u16,
+    pub alen: u16,
+    pub address: [u8; 8],
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+/// Result of `PACKET_STATISTICS`
+pub struct TpacketStats {
+    pub packets: u32,
+    pub drops: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+/// Argument of `SO_ATTACH_FILTER`, a classic BPF program of `len` instructions
+pub struct SockFprog {
+    pub len: u16,
+    pub filter: usize,
+}
+
 /// Set O_NONBLOCK flag on the open fd
 pub const NONBLOCK: i32 = 0x800;
 /// Set FD_CLOEXEC flag on the new fd
@@ -122,6 +315,8 @@ pub enum SocketLevel {
     IPPROTO_TCP = 6,
     /// IPv6-in-IPv4 tunnelling
     IPPROTO_IPV6 = 41,
+    SOL_PACKET = 263,
+    SOL_NETLINK = 270,
 }
 
 impl TryFrom<usize> for SocketLevel {
@@ -133,6 +328,8 @@ impl TryFrom<usize> for SocketLevel {
             1 => Ok(Self::SOL_SOCKET),
             6 => Ok(Self::IPPROTO_TCP),
             41 => Ok(Self::IPPROTO_IPV6),
+            263 => Ok(Self::SOL_PACKET),
+            270 => Ok(Self::SOL_NETLINK),
             level => {
                 log::warn!("[SocketLevel] unsupported level: {level}");
                 Err(Self::Error::EINVAL)
@@ -1,7 +1,10 @@
 use alloc::sync::Arc;
 use config::mm::KERNEL_MAP_OFFSET;
 use driver::{
-    BLOCK_DEVICE, DeviceType, hal::VirtHalImpl, net::loopback::LoopbackDev, println,
+    BLOCK_DEVICE, DeviceType,
+    hal::VirtHalImpl,
+    net::{gmac::Gmac, virtnet::create_virt_net_dev},
+    println,
     qemu::QVirtBlkDevice,
 };
 use flat_device_tree::node::FdtNode;
@@ -23,6 +26,13 @@ use virtio_drivers::{
 
 use crate::osdriver::ioremap_if_need;
 
+/// PCI vendor ID of Loongson.
+const LOONGSON_VENDOR_ID: u16 = 0x0014;
+/// PCI device ID of the GMAC of Loongson 2K1000.
+const LOONGSON_GMAC_DEVICE_ID: u16 = 0x7a03;
+/// Interrupt number of the GMAC of Loongson 2K1000.
+const LOONGSON_GMAC_IRQ: usize = 12;
+
 /// Allocates 32-bit memory addresses for PCI BARs.
 pub(crate) struct PciMemory32Allocator {
     start: u32,
@@ -222,6 +232,11 @@ pub fn enumerate_pci(pci_node: FdtNode, cam: Cam) {
                     transport.read_device_features(),
                 );
                 virtio_device(transport);
+            } else if info.vendor_id == LOONGSON_VENDOR_ID
+                && info.device_id == LOONGSON_GMAC_DEVICE_ID
+            {
+                allocate_bars(&mut pci_root, device_function, &mut allocator);
+                gmac(&mut pci_root, device_function);
             }
         }
     }
@@ -258,7 +273,20 @@ fn virtio_console(transport: PciTransport) {
     println!("[CONSOLE_DEVICE] INIT SUCCESS");
 }
 
-fn virtio_net(_transport: PciTransport) {
-    init_network(LoopbackDev::new(), true);
-    log::info!("virtio-net test finished");
+fn virtio_net(transport: PciTransport) {
+    println!("Init virtio-net");
+    let dev = create_virt_net_dev(transport).expect("create virt net failed");
+    init_network(dev, false);
+}
+
+/// Registers the GMAC `device_function`, whose registers are mapped by BAR 0.
+fn gmac(root: &mut PciRoot<impl ConfigurationAccess>, device_function: DeviceFunction) {
+    let Ok(Some(BarInfo::Memory { address, size, .. })) = root.bar_info(device_function, 0) else {
+        log::warn!("[gmac] no memory BAR for GMAC at {}", device_function);
+        return;
+    };
+    let (address, size) = (address as usize, size as usize);
+    let vaddr = ioremap_if_need(address, size);
+    println!("Init GMAC at {:#x}", address);
+    init_network(Gmac::new(vaddr, size, LOONGSON_GMAC_IRQ), false);
 }
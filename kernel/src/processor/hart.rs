@@ -91,7 +91,9 @@ impl Hart {
         pps.auto_sum(); // `pps` is the user task's PPS which is to be enabled.
         core::mem::swap(self.get_mut_pps(), pps);
 
+        new_task.addr_space().activate_on(self.id);
         new_task.switch_addr_space();
+        new_task.trap_context_mut().disable_fx();
         new_task.timer_mut().record_switch_in();
         self.set_task(Arc::clone(new_task));
         enable_interrupt();
@@ -101,6 +103,10 @@ impl Hart {
         disable_interrupt();
         pps.auto_sum(); // `pps` is the hart's original PPS which is to be enabled.
         core::mem::swap(self.get_mut_pps(), pps);
+        if let Some(task) = &self.task {
+            task.trap_context_mut().save_fx();
+            task.addr_space().deactivate_on(self.id);
+        }
         unsafe {
             vm::switch_to_kernel_page_table();
         }
@@ -0,0 +1,202 @@
+//! Module for inter-processor interrupts (IPIs).
+//!
+//! A hart asks other harts to do some work by putting requests in their mailboxes
+//! and sending them an IPI. Each target hart takes the requests out of its mailbox in
+//! its interrupt handler and handles them, and then marks them as handled, which the
+//! sender waits for.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use arch::mm::{fence, fence_i, tlb_flush_all_except_global, tlb_flush_asid, tlb_flush_range};
+use bitflags::bitflags;
+use config::device::MAX_HARTS;
+use mutex::SpinNoIrqLock;
+
+use super::current_hart;
+
+bitflags! {
+    /// Requests that a hart can make to other harts with IPIs.
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct IpiRequest: usize {
+        /// Execute a full memory barrier.
+        const MEMORY_BARRIER = 1 << 0;
+        /// Synchronize the instruction stream with memory, so that code modified by
+        /// other harts is fetched afterwards.
+        const SYNC_CORE = 1 << 1;
+        /// Invalidate TLB entries, as described by the [`TlbFlush`] in the mailbox.
+        const TLB_FLUSH = 1 << 2;
+    }
+}
+
+/// TLB entries to be invalidated by a hart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlbFlush {
+    /// Invalidate the entries for the range `[start, end)` tagged with `asid`.
+    Range {
+        asid: usize,
+        start: usize,
+        end: usize,
+    },
+    /// Invalidate all non-global entries tagged with `asid`.
+    Asid(usize),
+    /// Invalidate all non-global entries.
+    All,
+}
+
+impl TlbFlush {
+    /// Returns the ASID whose entries are invalidated, or `None` for all entries.
+    fn asid(self) -> Option<usize> {
+        match self {
+            Self::Range { asid, .. } | Self::Asid(asid) => Some(asid),
+            Self::All => None,
+        }
+    }
+
+    /// Returns a flush which invalidates the entries of both `self` and `other`.
+    fn merge(self, other: Self) -> Self {
+        let Some(asid) = self.asid().filter(|&asid| other.asid() == Some(asid)) else {
+            return Self::All;
+        };
+        match (self, other) {
+            (
+                Self::Range { start, end, .. },
+                Self::Range {
+                    start: s, end: e, ..
+                },
+            ) => Self::Range {
+                asid,
+                start: start.min(s),
+                end: end.max(e),
+            },
+            _ => Self::Asid(asid),
+        }
+    }
+
+    /// Invalidates the TLB entries on the current hart.
+    pub fn execute(self) {
+        match self {
+            Self::Range { asid, start, end } => tlb_flush_range(asid, start, end - start),
+            Self::Asid(asid) => tlb_flush_asid(asid),
+            Self::All => tlb_flush_all_except_global(),
+        }
+    }
+}
+
+/// Requests sent to a hart that it has not taken yet.
+struct Mailbox {
+    requests: IpiRequest,
+    tlb_flush: Option<TlbFlush>,
+    /// Number of times that requests are sent to the hart.
+    sent: usize,
+}
+
+impl Mailbox {
+    const fn new() -> Self {
+        Self {
+            requests: IpiRequest::empty(),
+            tlb_flush: None,
+            sent: 0,
+        }
+    }
+}
+
+/// Mailbox of each hart.
+static MAILBOXES: [SpinNoIrqLock<Mailbox>; MAX_HARTS] =
+    [const { SpinNoIrqLock::new(Mailbox::new()) }; MAX_HARTS];
+
+/// Number of times that requests sent to each hart are handled, which catches up with
+/// [`Mailbox::sent`] when all the requests are handled.
+static HANDLED: [AtomicUsize; MAX_HARTS] = [const { AtomicUsize::new(0) }; MAX_HARTS];
+
+/// Returns the IDs of the harts whose bits are set in `hart_mask`.
+pub fn harts_in_mask(hart_mask: usize) -> impl Iterator<Item = usize> {
+    (0..MAX_HARTS).filter(move |&id| hart_mask & (1 << id) != 0)
+}
+
+/// Sends `request` to each hart whose bit is set in `hart_mask`, and waits until all
+/// of them have handled it.
+///
+/// If the current hart is in `hart_mask`, the request is handled directly.
+pub fn send_ipi_and_wait(hart_mask: usize, request: IpiRequest) {
+    send_and_wait(hart_mask, request, None);
+}
+
+/// Asks each hart whose bit is set in `hart_mask` to invalidate the TLB entries
+/// described by `tlb_flush`, and waits until all of them have done so.
+///
+/// If the current hart is in `hart_mask`, the TLB entries are invalidated directly.
+pub fn send_tlb_flush_and_wait(hart_mask: usize, tlb_flush: TlbFlush) {
+    send_and_wait(hart_mask, IpiRequest::TLB_FLUSH, Some(tlb_flush));
+}
+
+fn send_and_wait(hart_mask: usize, request: IpiRequest, tlb_flush: Option<TlbFlush>) {
+    let current_mask = 1 << current_hart().id;
+    if hart_mask & current_mask != 0 {
+        handle_requests(request, tlb_flush);
+    }
+
+    let remote_mask = hart_mask & !current_mask;
+    if remote_mask == 0 {
+        return;
+    }
+    let mut tickets = [0; MAX_HARTS];
+    for id in harts_in_mask(remote_mask) {
+        let mut mailbox = MAILBOXES[id].lock();
+        mailbox.requests |= request;
+        if let Some(tlb_flush) = tlb_flush {
+            mailbox.tlb_flush = Some(match mailbox.tlb_flush {
+                Some(pending) => pending.merge(tlb_flush),
+                None => tlb_flush,
+            });
+        }
+        mailbox.sent += 1;
+        tickets[id] = mailbox.sent;
+    }
+    arch::interrupt::send_ipi(remote_mask);
+    for id in harts_in_mask(remote_mask) {
+        while HANDLED[id].load(Ordering::Acquire) < tickets[id] {
+            // Serve the requests sent to this hart in the meantime, or two harts
+            // waiting for each other would deadlock.
+            handle_pending_requests();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Handles an IPI received by the current hart.
+///
+/// This function is called by the trap handlers.
+pub fn handle_ipi() {
+    arch::interrupt::clear_ipi();
+    handle_pending_requests();
+}
+
+/// Handles the requests in the mailbox of the current hart.
+fn handle_pending_requests() {
+    let id = current_hart().id;
+    let (requests, tlb_flush, sent) = {
+        let mut mailbox = MAILBOXES[id].lock();
+        if mailbox.sent == HANDLED[id].load(Ordering::Relaxed) {
+            return;
+        }
+        let requests = core::mem::take(&mut mailbox.requests);
+        (requests, mailbox.tlb_flush.take(), mailbox.sent)
+    };
+    handle_requests(requests, tlb_flush);
+    // Only mark the requests as handled after handling them, as the senders take it as
+    // the completion of their requests.
+    HANDLED[id].store(sent, Ordering::Release);
+}
+
+fn handle_requests(requests: IpiRequest, tlb_flush: Option<TlbFlush>) {
+    if requests.contains(IpiRequest::MEMORY_BARRIER) {
+        fence();
+    }
+    if requests.contains(IpiRequest::SYNC_CORE) {
+        fence();
+        fence_i();
+    }
+    if let Some(tlb_flush) = tlb_flush {
+        tlb_flush.execute();
+    }
+}
@@ -1,3 +1,4 @@
 pub mod hart;
+pub mod ipi;
 
 pub use hart::{current_hart, current_task};
@@ -92,7 +92,10 @@ pub enum SyscallNo {
     GETITIMER = 102,
     SETITIMER = 103,
     TIMER_CREATE = 107,
+    TIMER_GETTIME = 108,
+    TIMER_GETOVERRUN = 109,
     TIMER_SETTIME = 110,
+    TIMER_DELETE = 111,
     CLOCK_SETTIME = 112,
     CLOCK_GETTIME = 113,
     CLOCK_GETRES = 114,
@@ -130,6 +133,7 @@ pub enum SyscallNo {
     GETRUSAGE = 165,
     UMASK = 166,
     PRCTL = 167,
+    GETCPU = 168,
     GETTIMEOFDAY = 169,
     ADJTIMEX = 171,
     GETPID = 172,
@@ -157,6 +161,8 @@ pub enum SyscallNo {
     SETSOCKOPT = 208,
     GETSOCKOPT = 209,
     SHUTDOWN = 210,
+    SENDMSG = 211,
+    RECVMSG = 212,
     BRK = 214,
     MUNMAP = 215,
     MREMAP = 216,
@@ -170,6 +176,7 @@ pub enum SyscallNo {
     MSYNC = 227,
     MLOCK = 228,
     MUNLOCK = 229,
+    MINCORE = 232,
     MADVISE = 233,
     GETMEMPOLICY = 236,
     PERF_EVENT_OPEN = 241,
@@ -183,6 +190,8 @@ pub enum SyscallNo {
     OPEN_BY_HANDLE_AT = 265,
     CLOCK_ADJTIME = 266,
     SENDMMSG = 269,
+    PROCESS_VM_READV = 270,
+    PROCESS_VM_WRITEV = 271,
     RENAMEAT2 = 276,
     GETRANDOM = 278,
     MEMFD_CREATE = 279,
@@ -209,6 +218,7 @@ pub enum SyscallNo {
     CLOSE_RANGE = 436,
     PIDFD_GETFD = 438,
     MEMFD_SECRET = 447,
+    FUTEX_WAITV = 449,
 }
 
 impl core::fmt::Display for SyscallNo {
@@ -296,7 +306,10 @@ impl SyscallNo {
             GETITIMER => "getitimer",
             SETITIMER => "setitimer",
             TIMER_CREATE => "timercreate",
+            TIMER_GETTIME => "timer_gettime",
+            TIMER_GETOVERRUN => "timer_getoverrun",
             TIMER_SETTIME => "timersettime",
+            TIMER_DELETE => "timer_delete",
             CLOCK_SETTIME => "clock_settime",
             CLOCK_GETTIME => "clock_gettime",
             CLOCK_GETRES => "clock_getres",
@@ -334,6 +347,7 @@ impl SyscallNo {
             GETRUSAGE => "getrusage",
             UMASK => "umask",
             PRCTL => "prctl",
+            GETCPU => "getcpu",
             GETTIMEOFDAY => "gettimeofday",
             ADJTIMEX => "adjtimex",
             GETPID => "getpid",
@@ -361,6 +375,8 @@ impl SyscallNo {
             SETSOCKOPT => "setsockopt",
             GETSOCKOPT => "getsockopt",
             SHUTDOWN => "shutdown",
+            SENDMSG => "sendmsg",
+            RECVMSG => "recvmsg",
             BRK => "brk",
             MUNMAP => "munmap",
             MREMAP => "mremap",
@@ -374,6 +390,7 @@ impl SyscallNo {
             MSYNC => "msync",
             MLOCK => "mlock",
             MUNLOCK => "munlock",
+            MINCORE => "mincore",
             MADVISE => "madvise",
             GETMEMPOLICY => "getmempolicy",
             PERF_EVENT_OPEN => "perf_event_open",
@@ -385,6 +402,8 @@ impl SyscallNo {
             NAME_TO_HANDLE_AT => "name_to_handle_at",
             OPEN_BY_HANDLE_AT => "open_by_handle_at",
             CLOCK_ADJTIME => "clock_adjtime",
+            PROCESS_VM_READV => "process_vm_readv",
+            PROCESS_VM_WRITEV => "process_vm_writev",
             RENAMEAT2 => "renameat2",
             GETRANDOM => "getrandom",
             MEMFD_CREATE => "memfd_create",
@@ -408,6 +427,7 @@ impl SyscallNo {
             CLONE3 => "clone3",
             CLOSE_RANGE => "close_range",
             PIDFD_GETFD => "pidfd_getfd",
+            FUTEX_WAITV => "futex_waitv",
             _ => "unknown",
         }
     }
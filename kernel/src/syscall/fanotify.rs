@@ -42,9 +42,7 @@ pub fn sys_fanotify_init(flags: u32, event_f_flags: u32) -> SyscallResult {
         return Err(SysError::EINVAL);
     }
 
-    if flags.intersects(
-        FanInitFlags::UNLIMITED_MARKS | FanInitFlags::ENABLE_AUDIT | FanInitFlags::REPORT_PIDFD,
-    ) {
+    if flags.intersects(FanInitFlags::UNLIMITED_MARKS | FanInitFlags::REPORT_PIDFD) {
         unimplemented!("Unsupported fanotify flags: {flags:?}");
     }
 
@@ -118,14 +116,21 @@ pub fn sys_fanotify_mark(
         return Ok(0);
     }
 
+    // Permission events can only be reported to groups which are able to respond, and
+    // which report file descriptors rather than file handles.
     if mask.intersects(
-        FanEventMask::OPEN_EXEC
-            | FanEventMask::FS_ERROR
-            | FanEventMask::ACCESS_PERM
-            | FanEventMask::OPEN_PERM
-            | FanEventMask::OPEN_EXEC_PERM,
-    ) {
-        unimplemented!("Unsupported fanotify mask: {mask:?}");
+        FanEventMask::ACCESS_PERM | FanEventMask::OPEN_PERM | FanEventMask::OPEN_EXEC_PERM,
+    ) && (!group_flags.intersects(FanInitFlags::CLASS_CONTENT | FanInitFlags::CLASS_PRE_CONTENT)
+        || group_flags.intersects(FanInitFlags::REPORT_FID | FanInitFlags::REPORT_DIR_FID))
+    {
+        return Err(SysError::EINVAL);
+    }
+    // Filesystem errors are reported with file handles on a whole filesystem.
+    if mask.contains(FanEventMask::FS_ERROR)
+        && (!flags.contains(FanMarkFlags::FILESYSTEM)
+            || !group_flags.intersects(FanInitFlags::REPORT_FID | FanInitFlags::REPORT_DIR_FID))
+    {
+        return Err(SysError::EINVAL);
     }
 
     // Find the object to mark.
@@ -47,6 +47,7 @@ use osfs::{
         eventfd::file::EventFdFile,
         fscontext::FsContextFile,
         inotify::{
+            self,
             dentry::InotifyDentry,
             file::InotifyFile,
             flags::{InotifyFlags, InotifyMask},
@@ -62,6 +63,7 @@ use osfs::{
         opentree::OpenTreeFile,
         signalfd::file::SignalFdFile,
         timerfd::file::TimerFdFile,
+        userfaultfd::UffdIoctlCmd,
     },
 };
 use osfuture::{Select2Futures, SelectOutput};
@@ -72,8 +74,9 @@ use systype::{
 };
 use timer::{TimedTaskResult, TimeoutFuture};
 use vfs::{
+    acl,
     dentry::Dentry,
-    fanotify::fs::file::FanotifyGroupFile,
+    fanotify::{fs::file::FanotifyGroupFile, types::FanEventMask},
     file::File,
     handle::{self, FileHandleData, FileHandleHeader},
     inode::Inode,
@@ -85,7 +88,10 @@ use vfs::{
 use crate::{
     logging::enable_log,
     processor::current_task,
-    task::{TaskState, sig_members::IntrBySignalFuture, signal::sig_info::SigSet},
+    task::{
+        TaskState, cap::CapabilitiesFlags, sig_members::IntrBySignalFuture,
+        signal::sig_info::SigSet,
+    },
     vm::user_ptr::{UserReadPtr, UserReadWritePtr, UserWritePtr},
 };
 
@@ -218,6 +224,10 @@ pub async fn sys_openat(dirfd: usize, pathname: usize, flags: i32, mode: u32) ->
         return Err(SysError::EISDIR);
     }
 
+    // The credentials must not be locked while waiting for fanotify listeners.
+    drop(cred);
+    dentry.fanotify_permission(FanEventMask::OPEN_PERM).await?;
+
     if inode_type.is_reg() && flags.contains(OpenFlags::O_TRUNC) && flags.writable() {
         inode.set_size(0).unwrap();
     }
@@ -251,6 +261,7 @@ pub async fn sys_write(fd: usize, addr: usize, len: usize) -> SyscallResult {
     let mut data_ptr = UserReadPtr::<u8>::new(addr, &addr_space);
 
     let file = task.with_mut_fdtable(|ft| ft.get_file(fd))?;
+    let len = task.check_fsize_limit(&file, None, len)?;
     let buf = unsafe { data_ptr.try_into_slice(len) }?;
 
     // log::info!("[sys_write] buf: {:?}", buf);
@@ -723,6 +734,7 @@ pub async fn sys_getdents64(fd: usize, buf: usize, len: usize) -> SyscallResult
     let file = task.with_mut_fdtable(|table| table.get_file(fd))?;
     let mut ptr = UserWritePtr::<u8>::new(buf, &addr_space);
     let buf = unsafe { ptr.try_into_mut_slice(len) }?;
+    file.fanotify_permission(FanEventMask::ACCESS_PERM).await?;
     log::info!("[sys_getdents64] read_dir");
     file.read_dir(buf)
 }
@@ -888,6 +900,10 @@ pub async fn sys_umount2(target: usize, flags: u32) -> SyscallResult {
     let parent = task.walk_at(AtFd::FdCwd, parent)?;
     let child = parent.lookup(&name)?;
 
+    if let Some(superblock) = child.superblock() {
+        inotify::notify_unmount(&superblock);
+    }
+
     child.unbind_mount_dentry();
     let mdentry = child.fetch_mount_dentry();
     parent.remove_child(child.as_ref());
@@ -983,34 +999,6 @@ pub async fn sys_faccessat(dirfd: usize, pathname: usize, mode: i32) -> SyscallR
     }
 }
 
-/// set system robust mutex list
-///
-/// When mutex with attr `PTHREAD_MUTEX_ROBUST` is used, the kernel trace the mutex
-/// with this syscall and mark the mutex state as `FUTEX_OWNER_DIED` as the thread
-/// dies due to exception.
-///
-/// # Attention
-/// - Not Implemented
-pub fn sys_set_robust_list(_robust_list_head: usize, _len: usize) -> SyscallResult {
-    log::warn!("[sys_set_robust_list] unimplemented");
-    Ok(0)
-}
-
-pub fn sys_get_robust_list(_pid: i32, _robust_list_head: usize, _len_ptr: usize) -> SyscallResult {
-    // let Some(task) = TASK_MANAGER.get(pid as usize) else {
-    //     return Err(SysError::ESRCH);
-    // };
-    // if !task.is_leader() {
-    //     return Err(SysError::ESRCH);
-    // }
-    // // UserReadPtr::<RobustListHead>::from(value)
-    // len_ptr.write(&task, mem::size_of::<RobustListHead>())?;
-    // robust_list_head.write(&task, unsafe {
-    //     *task.with_futexes(|futexes| futexes.robust_list.load(Ordering::SeqCst))
-    // })?;
-    Ok(0)
-}
-
 /// `pipe2()` creates a `pipe`, a unidirectional data channel that can be used for interprocess
 /// communication with OpenFlags `flags`.
 ///
@@ -1092,6 +1080,8 @@ pub fn sys_ioctl(fd: usize, request: usize, argp: usize) -> SyscallResult {
                 BlkIoctlCmd::HDIOGETGEO => core::mem::size_of::<HdGeometry>(),
                 _ => 0,
             }
+        } else if let Some(cmd) = UffdIoctlCmd::from_repr(request32) {
+            cmd.arg_size()
         } else {
             0
         };
@@ -1137,6 +1127,7 @@ pub async fn sys_sendfile64(
         in_file.seek(SeekFrom::Start(offset as u64))?;
     }
 
+    count = task.check_fsize_limit(&out_file, None, count)?;
     let mut write_bytes = 0;
     while count > 0 {
         let mlen = count.min(4096);
@@ -1313,13 +1304,19 @@ pub async fn sys_writev(fd: usize, iov: usize, iovcnt: usize) -> SyscallResult {
 
     let mut write_bytes = 0;
     let file = task.with_mut_fdtable(|table| table.get_file(fd))?;
+    let total_len = iovs
+        .iter()
+        .fold(0usize, |acc, iov| acc.saturating_add(iov.len));
+    let mut remaining = task.check_fsize_limit(&file, None, total_len)?;
     for iov in iovs {
-        if iov.len == 0 {
+        let len = cmp::min(iov.len, remaining);
+        if len == 0 {
             continue;
         }
         let mut ptr = UserReadPtr::<u8>::new(iov.base, &addrspace);
-        let slice = unsafe { ptr.try_into_slice(iov.len)? };
+        let slice = unsafe { ptr.try_into_slice(len)? };
         write_bytes += file.write(slice).await?;
+        remaining -= len;
     }
 
     // log::info!("[sys_writev] write bytes: {:?}", write_bytes);
@@ -1559,15 +1556,14 @@ pub fn sys_utimensat(dirfd: usize, pathname: usize, times: usize, flags: i32) ->
     let mut times = UserReadPtr::<TimeSpec>::new(times, &addrspace);
     let dirfd = AtFd::from(dirfd);
 
-    let inode = if !pathname.is_null() {
+    let dentry = if !pathname.is_null() {
         let path = pathname
             .read_c_string(256)?
             .into_string()
             .expect("cstring convert failed");
         log::info!("[sys_utimensat] dirfd: {dirfd}, path: {path}");
         let flags = OpenFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
-        let dentry = task.walk_at(dirfd, path)?;
-        dentry.inode().ok_or(SysError::ENOENT)?
+        task.walk_at(dirfd, path)?
     } else {
         // NOTE: if `pathname` is NULL, acts as futimens
         log::info!("[sys_utimensat] fd: {dirfd}");
@@ -1575,10 +1571,11 @@ pub fn sys_utimensat(dirfd: usize, pathname: usize, times: usize, flags: i32) ->
             AtFd::FdCwd => return Err(SysError::EINVAL),
             AtFd::Normal(fd) => {
                 let file = task.with_mut_fdtable(|table| table.get_file(fd))?;
-                file.inode()
+                file.dentry()
             }
         }
     };
+    let inode = dentry.inode().ok_or(SysError::ENOENT)?;
 
     let mut inner = inode.get_meta().inner.lock();
     let current_time = TimeSpec::from(get_time_duration());
@@ -1602,6 +1599,9 @@ pub fn sys_utimensat(dirfd: usize, pathname: usize, times: usize, flags: i32) ->
         };
         inner.ctime = current_time;
     }
+    drop(inner);
+
+    dentry.inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -1804,6 +1804,10 @@ pub async fn sys_truncate64(path: usize, length: usize) -> SyscallResult {
 
     let dentry = task.walk_at(AtFd::FdCwd, path)?;
     let file = <dyn File>::open(dentry)?;
+    if length > file.inode().size() {
+        // Growing the file to `length` bytes is like writing its last byte.
+        task.check_fsize_limit(&file, Some(length - 1), 1)?;
+    }
     file.truncate(length).await?;
     Ok(0)
 }
@@ -1825,6 +1829,10 @@ pub async fn sys_ftruncate64(fd: usize, length: usize) -> SyscallResult {
     log::debug!("[sys_ftruncate64] fd: {fd}, length: {length}");
     let task = current_task();
     let file = task.with_mut_fdtable(|t| t.get_file(fd))?;
+    if length > file.inode().size() {
+        // Growing the file to `length` bytes is like writing its last byte.
+        task.check_fsize_limit(&file, Some(length - 1), 1)?;
+    }
     file.truncate(length).await?;
     Ok(0)
 }
@@ -2034,9 +2042,10 @@ pub async fn sys_pwrite64(fd: usize, buf: usize, count: usize, offset: usize) ->
     let addr_space = task.addr_space();
     let mut data_ptr = UserReadPtr::<u8>::new(buf, &addr_space);
 
+    let file = task.with_mut_fdtable(|ft| ft.get_file(fd))?;
+    let count = task.check_fsize_limit(&file, Some(offset), count)?;
     let buf = unsafe { data_ptr.try_into_slice(count) }?;
 
-    let file = task.with_mut_fdtable(|ft| ft.get_file(fd))?;
     file.seek(SeekFrom::Start(offset as u64))?;
 
     file.write(buf).await
@@ -2296,6 +2305,8 @@ pub fn sys_fchmodat(dirfd: isize, pathname_ptr: usize, mode: u32, flags: u32) ->
             .intersection(!InodeMode::S_PERM)
             .union(InodeMode::from_bits_retain(mode)),
     );
+    acl::chmod(&inode)?;
+    dentry.inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -2318,6 +2329,8 @@ pub fn sys_fchmod(fd: usize, mode: u32) -> SyscallResult {
             .intersection(!InodeMode::S_PERM)
             .union(InodeMode::from_bits_retain(mode)),
     );
+    acl::chmod(&inode)?;
+    dentry.inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -2361,6 +2374,7 @@ pub fn sys_fchown(fd: usize, owner: u32, group: u32) -> SyscallResult {
 
         inode.set_mode(mode);
     }
+    dentry.inotify_publish(FanEventMask::ATTRIB);
     Ok(0)
 }
 
@@ -2431,6 +2445,7 @@ pub fn sys_fchownat(
 
         inode.set_mode(mode);
     }
+    dentry.inotify_publish(FanEventMask::ATTRIB);
     Ok(0)
 }
 
@@ -2520,6 +2535,8 @@ pub async fn sys_copy_file_range(
         }
     }
 
+    let len = task.check_fsize_limit(&file_out, off_out.map(|off| off as usize), len)?;
+
     log::debug!("[sys_copy_file_range] off_in: {off_in:?}, off_out: {off_out:?}, len:{len}");
     log::info!(
         "[sys_copy_file_range] file_in pos: {:#x}, file_out pos: {:#x}",
@@ -2549,6 +2566,29 @@ pub async fn sys_copy_file_range(
     Ok(bytes_copied)
 }
 
+/// Checks whether the current task may set or remove the extended attribute `name` of
+/// `inode`. The ACLs of an inode decide who can access it, so they can only be changed
+/// by its owner or a task with `CAP_FOWNER`. `trusted.*` attributes can only be changed
+/// by a task with `CAP_SYS_ADMIN`.
+///
+/// # Errors
+/// Returns `EPERM` if the task may not change the attribute.
+fn check_xattr_write(inode: &Arc<dyn Inode>, name: &str) -> SysResult<()> {
+    let task = current_task();
+    let allowed = match name {
+        acl::XATTR_NAME_POSIX_ACL_ACCESS | acl::XATTR_NAME_POSIX_ACL_DEFAULT => {
+            task.perm_mut().lock().euid == inode.get_uid()
+                || task.has_capability(CapabilitiesFlags::CAP_FOWNER)
+        }
+        _ if name.starts_with("trusted.") => task.has_capability(CapabilitiesFlags::CAP_SYS_ADMIN),
+        _ => true,
+    };
+    if !allowed {
+        return Err(SysError::EPERM);
+    }
+    Ok(())
+}
+
 pub fn sys_fsetxattr(
     fd: usize,
     name_ptr: usize,
@@ -2599,7 +2639,9 @@ pub fn sys_fsetxattr(
         return Err(SysError::ENODATA);
     }
 
-    inode.set_xattr(&name, &value, flags)?;
+    check_xattr_write(&inode, &name)?;
+    acl::set_xattr(&inode, &name, &value, flags)?;
+    file.dentry().inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -2650,7 +2692,9 @@ pub fn sys_fremovexattr(fd: usize, name_ptr: usize) -> SyscallResult {
     let file = task.with_mut_fdtable(|ft| ft.get_file(fd))?;
     let inode = file.inode();
 
+    check_xattr_write(&inode, &name)?;
     inode.remove_xattr(&name)?;
+    file.dentry().inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -2688,6 +2732,9 @@ pub async fn sys_fallocate(fd: usize, mode: usize, offset: usize, len: usize) ->
     let inode = file.inode();
 
     if mode.is_empty() {
+        if offset + len > inode.size() {
+            task.check_fsize_limit(&file, Some(offset + len - 1), 1)?;
+        }
         let size = inode.size().max(offset + len);
         inode.set_size(size)?;
         return Ok(0);
@@ -2845,6 +2892,8 @@ pub async fn sys_splice(
         Some(offset)
     };
 
+    // Apply the limit before reading, so that no data is consumed from a pipe and lost.
+    let len = current_task().check_fsize_limit(&file_out, off_out.map(|off| off as usize), len)?;
     let mut buffer: Vec<u8> = vec![0; len];
 
     log::error!("[sys_splice] read");
@@ -2969,7 +3018,9 @@ pub fn sys_setxattr(
         return Err(SysError::ENODATA);
     }
 
-    inode.set_xattr(&name, &value, flags)?;
+    check_xattr_write(&inode, &name)?;
+    acl::set_xattr(&inode, &name, &value, flags)?;
+    dentry.inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -3034,7 +3085,9 @@ pub fn sys_lsetxattr(
         return Err(SysError::ENODATA);
     }
 
-    inode.set_xattr(&name, &value, flags)?;
+    check_xattr_write(&inode, &name)?;
+    acl::set_xattr(&inode, &name, &value, flags)?;
+    dentry.inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -3099,7 +3152,9 @@ pub fn sys_removexattr(path_ptr: usize, name_ptr: usize) -> SyscallResult {
     let dentry = task.walk_at(AtFd::FdCwd, path)?;
     let inode = dentry.inode().ok_or(SysError::ENOENT)?;
 
+    check_xattr_write(&inode, &name)?;
     inode.remove_xattr(&name)?;
+    dentry.inotify_publish(FanEventMask::ATTRIB);
 
     Ok(0)
 }
@@ -3528,12 +3583,16 @@ pub fn sys_inotify_add_watch(fd: usize, pathname_ptr: usize, mask: u32) -> Sysca
         valid_mask
     );
 
-    let target_inode = task
-        .walk_at(AtFd::FdCwd, path_string.clone())?
-        .inode()
-        .ok_or(SysError::ENOENT)?;
-
-    let inode_id = target_inode.get_meta().ino as u64;
+    let dentry = task.walk_at(AtFd::FdCwd, path_string.clone())?;
+    let dentry = if !valid_mask.contains(InotifyMask::IN_DONT_FOLLOW)
+        && !dentry.is_negative()
+        && dentry.inode().unwrap().inotype().is_symlink()
+    {
+        Path::resolve_symlink_through(dentry)?
+    } else {
+        dentry
+    };
+    let target_inode = dentry.inode().ok_or(SysError::ENOENT)?;
 
     if valid_mask.contains(InotifyMask::IN_ONLYDIR) {
         let stat = target_inode.get_attr()?;
@@ -3544,7 +3603,7 @@ pub fn sys_inotify_add_watch(fd: usize, pathname_ptr: usize, mask: u32) -> Sysca
         }
     }
 
-    let wd = inotify_file.add_watch(inode_id, valid_mask.bits(), Some(path_string))?;
+    let wd = inotify_file.add_watch(target_inode, valid_mask.bits(), Some(path_string))?;
     log::debug!("[sys_inotify_add_watch] assigned wd: {}", wd);
 
     Ok(wd as usize)
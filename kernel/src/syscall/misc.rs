@@ -1,8 +1,10 @@
 use alloc::vec::Vec;
 use arch::time::get_time_duration;
+use config::mm::PAGE_SIZE;
+use mm::frame::{free_frames, total_frames};
 use systype::error::SyscallResult;
 
-use crate::{processor::current_task, vm::user_ptr::UserWritePtr};
+use crate::{processor::current_task, task::manager::TASK_MANAGER, vm::user_ptr::UserWritePtr};
 
 // See in "sys/utsname.h"
 #[derive(Debug, Clone, Copy)]
@@ -120,21 +122,22 @@ pub struct Sysinfo {
 }
 
 impl Sysinfo {
+    /// Collects the system statistics, with memory sizes in bytes.
     pub fn collect() -> Self {
         Self {
             uptime: get_time_duration().as_secs() as i64,
             loads: [0; 3],
-            totalram: 0,
-            freeram: 0,
+            totalram: (total_frames() * PAGE_SIZE) as u64,
+            freeram: (free_frames() * PAGE_SIZE) as u64,
             sharedram: 0,
             bufferram: 0,
             totalswap: 0,
             freeswap: 0,
-            procs: 0,
+            procs: TASK_MANAGER.how_many_tasks() as u16,
             pad: 0,
             totalhigh: 0,
             freehigh: 0,
-            mem_uint: 0,
+            mem_uint: 1,
             _f: [0; _F_SIZE],
         }
     }
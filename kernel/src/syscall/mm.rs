@@ -1,6 +1,12 @@
-use alloc::sync::Arc;
+use alloc::{
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::cmp;
+
 use arch::mm::tlb_flush_all;
-use config::mm::PAGE_SIZE;
+use config::mm::{HUGE_PAGE_SIZE, PAGE_SIZE};
 use id_allocator::IdAllocator;
 use mm::address::VirtAddr;
 use mutex::new_share_mutex;
@@ -13,12 +19,19 @@ use shm::{
 };
 use systype::{
     error::{SysError, SyscallResult},
-    memory_flags::{MappingFlags, MmapFlags, MmapProt},
+    memory_flags::{MappingFlags, MmapFlags, MmapProt, MremapFlags},
+    rlimit::RLIM_INFINITY,
 };
 
 use crate::{
     processor::current_task,
-    vm::{trace_page_table_lookup, user_ptr::UserWritePtr},
+    syscall::{fs::IoVec, process::Resource},
+    task::{cap::CapabilitiesFlags, manager::TASK_MANAGER},
+    vm::{
+        madvise::Advice,
+        trace_page_table_lookup,
+        user_ptr::{UserReadPtr, UserWritePtr},
+    },
 };
 
 /// `mmap()` creates a new mapping in the virtual address space of the calling process.
@@ -57,6 +70,9 @@ use crate::{
 ///   however, some architectures may impose additional restrictions. If the memory region specified by
 ///   addr and length overlaps pages of any existing mapping(s), then the overlapped part of the existing
 ///   mapping(s) will be discarded. If the specified address cannot be used, mmap() will fail.
+/// - `MAP_HUGETLB`: Allocate the anonymous mapping using huge pages. Its address and length are
+///   aligned to the huge page size, which may be given as its base-2 logarithm shifted by
+///   `MAP_HUGE_SHIFT` in `flags`; only the default 2 MiB size is supported.
 pub async fn sys_mmap(
     addr: usize,
     length: usize,
@@ -66,6 +82,11 @@ pub async fn sys_mmap(
     offset: usize,
 ) -> SyscallResult {
     let task = current_task();
+    let huge_shift = (flags >> MmapFlags::MAP_HUGE_SHIFT) & MmapFlags::MAP_HUGE_MASK;
+    if huge_shift != 0 && huge_shift as u32 != HUGE_PAGE_SIZE.trailing_zeros() {
+        // Only the default huge page size is supported.
+        return Err(SysError::EINVAL);
+    }
     let flags = MmapFlags::from_bits_truncate(flags);
     let prot = MmapProt::from_bits_truncate(prot);
     let va = VirtAddr::new(addr);
@@ -100,6 +121,8 @@ pub async fn sys_mmap(
         return Err(SysError::EINVAL);
     }
 
+    task.check_as_limit(length)?;
+
     let result = task.addr_space().map_file(
         file,
         flags,
@@ -144,7 +167,11 @@ pub async fn sys_munmap(addr: usize, length: usize) -> SyscallResult {
 
     let task = current_task();
     let addr = VirtAddr::new(addr);
-    task.addr_space().remove_mapping(addr, length);
+    let addr_space = task.addr_space();
+    if let Some(end) = addr.to_usize().checked_add(length) {
+        addr_space.userfault_remove(addr, VirtAddr::new(end).round_up(), true);
+    }
+    addr_space.remove_mapping(addr, length);
     Ok(0)
 }
 
@@ -157,7 +184,18 @@ pub async fn sys_munmap(addr: usize, length: usize) -> SyscallResult {
 pub async fn sys_brk(addr: usize) -> SyscallResult {
     log::info!("[sys_brk] addr: {addr:#x}");
 
-    current_task().addr_space().change_heap_size(addr, 0)
+    let task = current_task();
+    let addr_space = task.addr_space();
+    let (heap_start, heap_end) = addr_space.heap_range().ok_or(SysError::ENOMEM)?;
+    if addr > heap_end {
+        let data_limit = task.get_rlimit(Resource::DATA).rlim_cur;
+        if addr - heap_start > data_limit {
+            return Err(SysError::ENOMEM);
+        }
+        task.check_as_limit(addr - heap_end)?;
+    }
+
+    addr_space.change_heap_size(addr, 0)
 }
 
 pub fn sys_mprotect(addr: usize, len: usize, prot: i32) -> SyscallResult {
@@ -183,11 +221,200 @@ pub fn sys_mprotect(addr: usize, len: usize, prot: i32) -> SyscallResult {
 /// Initially, the system call supported a set of "conventional" advice values, which are
 /// also available on several other implementations. (Note, though, that madvise() is not
 /// specified in POSIX.) Subsequently, a number of Linux-specific advice values have been added.
-pub fn sys_madvise(add: usize, length: usize, _advice: usize) -> SyscallResult {
-    log::warn!("[sys_madvise] not implemented add: {add:#x}, length: {length:#x}");
+pub async fn sys_madvise(addr: usize, length: usize, advice: usize) -> SyscallResult {
+    let advice = Advice::from_repr(advice).ok_or(SysError::EINVAL)?;
+    log::info!("[sys_madvise] addr: {addr:#x}, length: {length:#x}, advice: {advice:?}");
+
+    if addr % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let end = addr.checked_add(length).ok_or(SysError::EINVAL)?;
+    if length == 0 {
+        return Ok(0);
+    }
+
+    let end = VirtAddr::new(end).round_up();
+    let addr_space = current_task().addr_space();
+    addr_space.madvise(VirtAddr::new(addr), end, advice).await?;
+    if matches!(advice, Advice::MADV_DONTNEED | Advice::MADV_REMOVE) {
+        addr_space.userfault_remove(VirtAddr::new(addr), end, false);
+    }
+    Ok(0)
+}
+
+/// Number of pages whose residency `mincore()` reports at a time.
+const MINCORE_CHUNK_PAGES: usize = PAGE_SIZE;
+
+/// `mincore()` returns a vector that indicates whether pages of the calling process's
+/// virtual memory are resident in core (RAM), and so will not cause a disk access (page
+/// fault) if referenced.
+///
+/// The kernel returns residency information about the pages starting at the address
+/// `addr`, and continuing for `length` bytes. The `vec` argument must point to an array
+/// containing at least `(length+PAGE_SIZE-1) / PAGE_SIZE` bytes. On return, the least
+/// significant bit of each byte will be set if the corresponding page is currently
+/// resident in memory, and be clear otherwise.
+pub fn sys_mincore(addr: usize, length: usize, vec: usize) -> SyscallResult {
+    log::info!("[sys_mincore] addr: {addr:#x}, length: {length:#x}, vec: {vec:#x}");
+
+    if addr % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+    let end = addr.checked_add(length).ok_or(SysError::ENOMEM)?;
+    let end = VirtAddr::new(end).round_up();
+
+    let task = current_task();
+    let addr_space = task.addr_space();
+    // The residency is collected and copied out in chunks, so that a huge range does
+    // not need a huge buffer in the kernel.
+    let mut chunk_addr = addr;
+    while chunk_addr < end.to_usize() {
+        let chunk_end = usize::min(
+            end.to_usize(),
+            chunk_addr.saturating_add(MINCORE_CHUNK_PAGES * PAGE_SIZE),
+        );
+        let residency: Vec<u8> = addr_space
+            .mincore(VirtAddr::new(chunk_addr), VirtAddr::new(chunk_end))?
+            .into_iter()
+            .map(u8::from)
+            .collect();
+        let offset = (chunk_addr - addr) / PAGE_SIZE;
+        let mut chunk_vec = UserWritePtr::<u8>::new(vec.wrapping_add(offset), &addr_space);
+        unsafe { chunk_vec.write_array(&residency)? };
+        chunk_addr = chunk_end;
+    }
     Ok(0)
 }
 
+/// Maximum number of `iovec`s accepted by `process_vm_readv()` and `process_vm_writev()`.
+const IOV_MAX: usize = 1024;
+
+/// `process_vm_readv()` transfers data from the memory of the process `pid` to the
+/// calling process. The data to be read is described by `riovcnt` `iovec`s at
+/// `remote_iov` in the address space of `pid`, and it is written to the buffers
+/// described by `liovcnt` `iovec`s at `local_iov` in the calling process.
+///
+/// The caller must be allowed to access the memory of `pid` as a tracer, i.e., it must
+/// have the same user and group IDs as `pid`, or have `CAP_SYS_PTRACE`. Pages of `pid`
+/// which are not resident are faulted in.
+///
+/// Returns the number of bytes transferred, which may be less than requested if a page
+/// cannot be accessed after some bytes have been transferred.
+pub fn sys_process_vm_readv(
+    pid: usize,
+    local_iov: usize,
+    liovcnt: usize,
+    remote_iov: usize,
+    riovcnt: usize,
+    flags: usize,
+) -> SyscallResult {
+    log::info!(
+        "[sys_process_vm_readv] pid: {pid}, local_iov: {local_iov:#x}, liovcnt: {liovcnt}, \
+        remote_iov: {remote_iov:#x}, riovcnt: {riovcnt}, flags: {flags:#x}"
+    );
+    process_vm_access(pid, local_iov, liovcnt, remote_iov, riovcnt, flags, false)
+}
+
+/// `process_vm_writev()` transfers data from the calling process to the memory of the
+/// process `pid`. The data to be written is described by `liovcnt` `iovec`s at
+/// `local_iov` in the calling process, and it is written to the memory described by
+/// `riovcnt` `iovec`s at `remote_iov` in the address space of `pid`.
+///
+/// See [`sys_process_vm_readv`] for the permission check and the return value. The
+/// protection of the memory of `pid` is respected, so read-only pages cannot be
+/// written, and copy-on-write pages are copied before being written.
+pub fn sys_process_vm_writev(
+    pid: usize,
+    local_iov: usize,
+    liovcnt: usize,
+    remote_iov: usize,
+    riovcnt: usize,
+    flags: usize,
+) -> SyscallResult {
+    log::info!(
+        "[sys_process_vm_writev] pid: {pid}, local_iov: {local_iov:#x}, liovcnt: {liovcnt}, \
+        remote_iov: {remote_iov:#x}, riovcnt: {riovcnt}, flags: {flags:#x}"
+    );
+    process_vm_access(pid, local_iov, liovcnt, remote_iov, riovcnt, flags, true)
+}
+
+/// Transfers data between the calling process and the process `pid`, in the direction
+/// given by `write`. See [`sys_process_vm_readv`] and [`sys_process_vm_writev`].
+fn process_vm_access(
+    pid: usize,
+    local_iov: usize,
+    liovcnt: usize,
+    remote_iov: usize,
+    riovcnt: usize,
+    flags: usize,
+    write: bool,
+) -> SyscallResult {
+    if flags != 0 || liovcnt > IOV_MAX || riovcnt > IOV_MAX {
+        return Err(SysError::EINVAL);
+    }
+
+    let task = current_task();
+    let addr_space = task.addr_space();
+    let local_iovs =
+        unsafe { UserReadPtr::<IoVec>::new(local_iov, &addr_space).read_array(liovcnt)? };
+    let remote_iovs =
+        unsafe { UserReadPtr::<IoVec>::new(remote_iov, &addr_space).read_array(riovcnt)? };
+    for iovs in [&local_iovs, &remote_iovs] {
+        iovs.iter()
+            .try_fold(0usize, |acc, iov| acc.checked_add(iov.len))
+            .filter(|&total| total <= isize::MAX as usize)
+            .ok_or(SysError::EINVAL)?;
+    }
+
+    let target = TASK_MANAGER.get_task(pid).ok_or(SysError::ESRCH)?.process();
+    task.check_ptrace_access(&target)?;
+    let remote_space = target.addr_space();
+
+    // Data is transferred in chunks, each of which lies in one local `iovec`, one remote
+    // `iovec`, and at most two remote pages.
+    let mut buf = vec![0u8; PAGE_SIZE];
+    let mut local_iovs = local_iovs.into_iter().filter(|iov| iov.len != 0);
+    let mut remote_iovs = remote_iovs.into_iter().filter(|iov| iov.len != 0);
+    let (mut local, mut remote) = (local_iovs.next(), remote_iovs.next());
+    let mut transferred = 0;
+    while let (Some(l), Some(r)) = (local.as_mut(), remote.as_mut()) {
+        let chunk = cmp::min(cmp::min(l.len, r.len), PAGE_SIZE);
+        let result = if write {
+            let mut ptr = UserReadPtr::<u8>::new(l.base, &addr_space);
+            unsafe { ptr.try_into_slice(chunk) }
+                .and_then(|src| remote_space.write_remote(r.base, src))
+        } else {
+            remote_space
+                .read_remote(r.base, &mut buf[..chunk])
+                .and_then(|count| {
+                    let mut ptr = UserWritePtr::<u8>::new(l.base, &addr_space);
+                    unsafe { ptr.try_into_mut_slice(count)? }.copy_from_slice(&buf[..count]);
+                    Ok(count)
+                })
+        };
+        let count = match result {
+            Ok(count) => count,
+            Err(e) if transferred == 0 => return Err(e),
+            Err(_) => break,
+        };
+        transferred += count;
+        if count < chunk {
+            break;
+        }
+        l.base += count;
+        l.len -= count;
+        r.base += count;
+        r.len -= count;
+        if l.len == 0 {
+            local = local_iovs.next();
+        }
+        if r.len == 0 {
+            remote = remote_iovs.next();
+        }
+    }
+    Ok(transferred)
+}
+
 /// `shmget()` returns the identifier of the System V shared memory segment associated with
 /// the value of the argument key. It may be used either to obtain the identifier of a previously
 /// created shared memory segment (when `shmflg` is zero and key does not have the value IPC_PRIVATE),
@@ -368,12 +595,48 @@ pub fn sys_shmctl(shmid: usize, cmd: i32, buf: usize) -> SyscallResult {
 /// needs to be either matched with its memory barrier counterparts, or that the architecture's
 /// memory model doesn't require the matching barriers.
 ///
-/// Tips: Unimplement now.
-pub fn sys_membarrier(_cmd: usize, _flags: usize, _cpu_id: usize) -> SyscallResult {
-    log::error!("[sys_membarrier] not implemented");
-    Ok(0)
+/// # Commands
+/// - `MEMBARRIER_CMD_QUERY`: Returns the set of supported commands.
+/// - `MEMBARRIER_CMD_GLOBAL`: Makes all running threads on the system execute a memory
+///   barrier.
+/// - `MEMBARRIER_CMD_GLOBAL_EXPEDITED`: Makes all running threads of the processes
+///   registered with `MEMBARRIER_CMD_REGISTER_GLOBAL_EXPEDITED` execute a memory barrier.
+/// - `MEMBARRIER_CMD_PRIVATE_EXPEDITED`: Makes all running threads of the calling process
+///   execute a memory barrier. `MEMBARRIER_CMD_PRIVATE_EXPEDITED_SYNC_CORE` also makes
+///   them synchronize their instruction streams, and `MEMBARRIER_CMD_PRIVATE_EXPEDITED_RSEQ`
+///   may target only the CPU `cpu_id` with `MEMBARRIER_CMD_FLAG_CPU` in `flags`. The
+///   process must register for each of them with the matching `REGISTER` command first.
+/// - `MEMBARRIER_CMD_GET_REGISTRATIONS`: Returns the set of registered commands.
+pub fn sys_membarrier(cmd: usize, flags: usize, cpu_id: usize) -> SyscallResult {
+    log::info!("[sys_membarrier] cmd: {cmd:#x}, flags: {flags:#x}, cpu_id: {cpu_id}");
+    current_task().addr_space().membarrier(cmd, flags, cpu_id)
 }
 
+/// `mremap()` expands (or shrinks) an existing memory mapping, potentially moving it at
+/// the same time (controlled by the `flags` argument and the available virtual address
+/// space).
+///
+/// `old_addr` is the old address of the virtual memory block that you want to expand
+/// (or shrink). Note that `old_addr` has to be page aligned. `old_size` is the old size
+/// of the virtual memory block. `new_size` is the requested size of the virtual memory
+/// block after the resize. An optional fifth argument, `new_addr`, may be provided; see
+/// the description of `MREMAP_FIXED` below.
+///
+/// If the value of `old_size` is zero, and `old_addr` refers to a shareable mapping,
+/// then `mremap()` will create a new mapping of the same pages.
+///
+/// # Flags
+/// - `MREMAP_MAYMOVE`: By default, if there is not sufficient space to expand a mapping
+///   at its current location, then `mremap()` fails. If this flag is specified, then
+///   the kernel is permitted to relocate the mapping to a new virtual address.
+/// - `MREMAP_FIXED`: This flag serves a similar purpose to the `MAP_FIXED` flag of
+///   `mmap()`. If this flag is specified, then `mremap()` accepts a fifth argument,
+///   `new_addr`, which specifies a page-aligned address to which the mapping must be
+///   moved. Any previous mapping at the address range specified by `new_addr` and
+///   `new_size` is unmapped.
+/// - `MREMAP_DONTUNMAP`: This flag, which must be used in conjunction with
+///   `MREMAP_MAYMOVE`, remaps a mapping to a new address but does not unmap the mapping
+///   at `old_addr`.
 pub fn sys_mremap(
     old_addr: usize,
     old_size: usize,
@@ -381,47 +644,90 @@ pub fn sys_mremap(
     flags: i32,
     new_addr: usize, // when fixed
 ) -> SyscallResult {
-    log::error!("[sys_mremap] not implemented");
-    return Ok(0);
-    // if old_addr & (PAGE_SIZE - 1) != 0 {
-    //     return Err(SysError::EINVAL);
-    // }
-    // if new_size == 0 {
-    //     return Err(SysError::EINVAL);
-    // }
+    let flags = MremapFlags::from_bits(flags).ok_or(SysError::EINVAL)?;
+    log::info!(
+        "[sys_mremap] old_addr: {old_addr:#x}, old_size: {old_size:#x}, \
+        new_size: {new_size:#x}, flags: {flags:?}, new_addr: {new_addr:#x}"
+    );
 
-    // let task = current_task();
-    // let src_va = VirtAddr::new(old_addr);
-    // let copy_size = usize::min(old_size, new_size);
-
-    // let new_addr = task.addr_space().map_file(
-    //     None,
-    //     MmapFlags::MAP_ANONYMOUS | MmapFlags::MAP_PRIVATE,
-    //     MappingFlags::R | MappingFlags::W,
-    //     VirtAddr::new(0),
-    //     new_size,
-    //     0,
-    // )?;
-
-    // if copy_size > 0 {
-    //     unsafe {
-    //         let src_ptr = old_addr as *const u8;
-    //         let dst_ptr = new_addr as *mut u8;
-    //         core::ptr::copy_nonoverlapping(src_ptr, dst_ptr, copy_size);
-    //     }
-    // }
+    if old_addr % PAGE_SIZE != 0 || new_size == 0 {
+        return Err(SysError::EINVAL);
+    }
+    if flags.intersects(MremapFlags::MREMAP_FIXED | MremapFlags::MREMAP_DONTUNMAP)
+        && !flags.contains(MremapFlags::MREMAP_MAYMOVE)
+    {
+        return Err(SysError::EINVAL);
+    }
+    if flags.contains(MremapFlags::MREMAP_DONTUNMAP) && old_size != new_size {
+        return Err(SysError::EINVAL);
+    }
+    if flags.contains(MremapFlags::MREMAP_FIXED) && new_addr % PAGE_SIZE != 0 {
+        return Err(SysError::EINVAL);
+    }
+
+    let task = current_task();
+    if new_size > old_size {
+        task.check_as_limit(new_size - old_size)?;
+    }
+    let old_va = VirtAddr::new(old_addr);
+    let new_va =
+        task.addr_space()
+            .remap(old_va, old_size, new_size, flags, VirtAddr::new(new_addr))?;
+
+    let addr_space = task.addr_space();
+    let old_size = VirtAddr::new(old_size).round_up().to_usize();
+    let new_size = VirtAddr::new(new_size).round_up().to_usize();
+    if new_va != old_va && old_size != 0 {
+        addr_space.userfault_remap(old_va, new_va, usize::min(old_size, new_size));
+    } else if new_size < old_size {
+        let old_end = VirtAddr::new(old_addr + old_size);
+        addr_space.userfault_remove(VirtAddr::new(old_addr + new_size), old_end, true);
+    }
 
-    // task.addr_space().remove_mapping(src_va, old_size);
-    // Ok(new_addr)
+    // A moved System V shared memory segment is detached at its new address.
+    if new_va != old_va && !flags.contains(MremapFlags::MREMAP_DONTUNMAP) && old_size != 0 {
+        task.with_mut_shm_maps(|map| {
+            if let Some(shmid) = map.remove(&old_va) {
+                map.insert(new_va, shmid);
+            }
+        });
+    }
+    Ok(new_va.to_usize())
 }
 
-pub fn sys_mlock(_addr: usize, _len: usize) -> SyscallResult {
-    log::warn!("[sys_mlock] swap page mechanism not implemented");
+/// `mlock()` locks the pages in the range `[addr, addr + len)` in memory.
+///
+/// There is no swapping, so this only marks the VMAs as locked and enforces
+/// `RLIMIT_MEMLOCK` on the total size of locked VMAs, unless the task has
+/// `CAP_IPC_LOCK`.
+pub fn sys_mlock(addr: usize, len: usize) -> SyscallResult {
+    let task = current_task();
+    let addr_space = task.addr_space();
+    let start = VirtAddr::new(addr).round_down();
+    let end = addr.checked_add(len).ok_or(SysError::ENOMEM)?;
+    let len = VirtAddr::new(end).round_up().to_usize() - start.to_usize();
+
+    if !task.has_capability(CapabilitiesFlags::CAP_IPC_LOCK) {
+        let limit = task.get_rlimit(Resource::MEMLOCK).rlim_cur;
+        if limit == 0 {
+            return Err(SysError::EPERM);
+        }
+        if limit != RLIM_INFINITY && addr_space.locked_vm() + len > limit {
+            return Err(SysError::ENOMEM);
+        }
+    }
+
+    addr_space.set_locked(start, len, true)?;
     Ok(0)
 }
 
-pub fn sys_munlock(_addr: usize, _len: usize) -> SyscallResult {
-    log::warn!("[sys_munlock] swap page mechanism not implemented");
+/// `munlock()` unlocks the pages in the range `[addr, addr + len)`.
+pub fn sys_munlock(addr: usize, len: usize) -> SyscallResult {
+    let task = current_task();
+    let start = VirtAddr::new(addr).round_down();
+    let end = addr.checked_add(len).ok_or(SysError::ENOMEM)?;
+    let len = VirtAddr::new(end).round_up().to_usize() - start.to_usize();
+    task.addr_space().set_locked(start, len, false)?;
     Ok(0)
 }
 
@@ -503,12 +809,9 @@ pub fn sys_pkey_mprotect(addr: usize, len: usize, prot: i32, pkey: i32) -> Sysca
 }
 
 pub fn sys_userfaultfd(flags: u32) -> SyscallResult {
-    use config::vfs::OpenFlags;
     use osfs::special::userfaultfd::{
-        UserfaultfdDentry, UserfaultfdFile, UserfaultfdFlags, UserfaultfdInode,
+        UserfaultMm, UserfaultfdFile, UserfaultfdFlags, UserfaultfdInode,
     };
-    use vfs::inode::Inode;
-    use vfs::sys_root_dentry;
 
     let task = current_task();
 
@@ -525,34 +828,15 @@ pub fn sys_userfaultfd(flags: u32) -> SyscallResult {
 
     log::debug!("[sys_userfaultfd] flags: {:?}", uffd_flags);
 
-    // Get current process memory management ID
-    let mm_id = task.pid() as u64; // Simplified
-
-    // Create userfaultfd inode
-    let inode = UserfaultfdInode::new(uffd_flags, mm_id);
-    inode.set_mode(config::inode::InodeMode::REG);
-
-    // Create dentry
-    let dentry = UserfaultfdDentry::new(
-        "userfaultfd",
-        Some(inode),
-        Some(Arc::downgrade(&sys_root_dentry())),
-    );
-    sys_root_dentry().add_child(dentry.clone());
+    // The userfaultfd handles page faults in the address space of the current process
+    let addr_space = task.addr_space();
+    let mm: Weak<dyn UserfaultMm> = Arc::downgrade(&addr_space);
+    let inode = UserfaultfdInode::new(uffd_flags, mm);
+    addr_space.add_userfaultfd(&inode);
 
     // Create file
-    let file = UserfaultfdFile::new(dentry);
-
-    // Set file flags
-    let mut file_flags = OpenFlags::O_RDONLY;
-    if uffd_flags.contains(UserfaultfdFlags::UFFD_CLOEXEC) {
-        file_flags |= OpenFlags::O_CLOEXEC;
-    }
-
-    if uffd_flags.contains(UserfaultfdFlags::UFFD_NONBLOCK) {
-        file_flags |= OpenFlags::O_NONBLOCK;
-    }
+    let file = UserfaultfdFile::with_inode(inode);
 
     // Allocate file descriptor
-    task.with_mut_fdtable(|ft| ft.alloc(file, file_flags))
+    task.with_mut_fdtable(|ft| ft.alloc(file, uffd_flags.open_flags()))
 }
@@ -12,7 +12,7 @@ mod poll;
 pub mod process;
 mod sche;
 mod signal;
-mod time;
+pub mod time;
 mod user;
 
 use driver::print;
@@ -162,7 +162,17 @@ pub async fn syscall(syscall_no: usize, args: [usize; 6]) -> usize {
             )
             .await
         }
-        MADVISE => sys_madvise(args[0], args[1], args[2]),
+        FUTEX_WAITV => {
+            sys_futex_waitv(args[0], args[1] as u32, args[2] as u32, args[3], args[4]).await
+        }
+        MADVISE => sys_madvise(args[0], args[1], args[2]).await,
+        MINCORE => sys_mincore(args[0], args[1], args[2]),
+        PROCESS_VM_READV => {
+            sys_process_vm_readv(args[0], args[1], args[2], args[3], args[4], args[5])
+        }
+        PROCESS_VM_WRITEV => {
+            sys_process_vm_writev(args[0], args[1], args[2], args[3], args[4], args[5])
+        }
         SHMGET => sys_shmget(args[0], args[1], args[2] as i32),
         TKILL => sys_tkill(args[0] as isize, args[1] as i32),
         SOCKET => sys_socket(args[0], args[1] as i32, args[2]),
@@ -178,6 +188,8 @@ pub async fn syscall(syscall_no: usize, args: [usize; 6]) -> usize {
         GETSOCKOPT => sys_getsockopt(args[0], args[1], args[2], args[3], args[4]),
         SENDTO => sys_sendto(args[0], args[1], args[2], args[3], args[4], args[5]).await,
         RECVFROM => sys_recvfrom(args[0], args[1], args[2], args[3], args[4], args[5]).await,
+        SENDMSG => sys_sendmsg(args[0], args[1], args[2]).await,
+        RECVMSG => sys_recvmsg(args[0], args[1], args[2]).await,
         LISTEN => sys_listen(args[0], args[1]),
         CONNECT => sys_connect(args[0], args[1], args[2]).await,
         ACCEPT => sys_accept(args[0], args[1], args[2]).await,
@@ -189,6 +201,7 @@ pub async fn syscall(syscall_no: usize, args: [usize; 6]) -> usize {
         MREMAP => sys_mremap(args[0], args[1], args[2], args[3] as i32, args[4]),
         SETSID => sys_setsid(),
         SCHED_GETAFFINITY => sys_sched_getaffinity(args[0], args[1], args[2]),
+        GETCPU => sys_getcpu(args[0], args[1]),
         GETMEMPOLICY => sys_get_mempolicy(args[0], args[1], args[2], args[3], args[4] as isize),
         SCHED_GETSCHEDULER => sys_sched_getscheduler(),
         SCHED_GETPARAM => sys_sched_getparam(),
@@ -228,6 +241,9 @@ pub async fn syscall(syscall_no: usize, args: [usize; 6]) -> usize {
         CLOCK_SETTIME => sys_clock_settime(args[0], args[1]),
         TIMER_CREATE => sys_timer_create(args[0], args[1], args[2]),
         TIMER_SETTIME => sys_timer_settime(args[0], args[1], args[2], args[3]),
+        TIMER_GETTIME => sys_timer_gettime(args[0], args[1]),
+        TIMER_GETOVERRUN => sys_timer_getoverrun(args[0]),
+        TIMER_DELETE => sys_timer_delete(args[0]),
         PIDFD_GETFD => sys_pidfd_getfd(args[0], args[1], args[2] as u32),
         PIDFD_OPEN => sys_pidfd_open(args[0], args[1] as u32),
         PIDFD_SEND_SIGNAL => {
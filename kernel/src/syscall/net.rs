@@ -1,27 +1,43 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{string::String, sync::Arc, vec::Vec};
 
 use config::{mm::PAGE_SIZE, vfs::OpenFlags};
-use net::poll_interfaces;
+use net::{
+    packet::bpf::{BpfProgram, SockFilter},
+    poll_interfaces,
+};
 use osfs::pipe::new_pipe;
-use systype::error::{SysError, SyscallResult};
+use systype::error::{SysError, SysResult, SyscallResult};
 
 use crate::{
     net::{
         SocketType,
-        addr::{SaFamily, SockAddr, read_sockaddr, write_sockaddr},
-        msg::{IoVec, MmsgHdr},
+        addr::{SaFamily, SockAddr, read_sockaddr, write_sockaddr, write_sockaddr_buf},
+        ioctl::IFNAMSIZ,
+        msg::{ControlMessage, IoVec, MmsgHdr, MsgFlags, MsgHdr},
         sock::Sock,
-        socket::Socket,
-        sockopt::{SocketLevel, SocketOpt, TcpSocketOpt},
+        socket::{RecvCmsgs, Socket},
+        sockopt::{
+            IpSocketOpt, Ipv6SocketOpt, NetlinkSocketOpt, PacketMreq, PacketSocketOpt, SockFprog,
+            SocketLevel, SocketOpt, TcpSocketOpt, TpacketStats,
+        },
     },
     processor::current_task,
-    task::TaskState,
-    vm::user_ptr::{UserReadPtr, UserWritePtr},
+    task::{TaskState, cap::CapabilitiesFlags},
+    vm::{
+        addr_space::AddrSpace,
+        user_ptr::{UserReadPtr, UserWritePtr},
+    },
 };
 
 pub const NONBLOCK: i32 = 0x800;
 pub const CLOEXEC: i32 = 0x80000;
 
+/// Size of the send buffer of a socket, as reported by `SO_SNDBUF`.
+const SEND_BUFFER_SIZE: usize = 64 * 1024;
+/// Size of the receive buffer of a socket, as reported by `SO_RCVBUF`. No more than
+/// this is received by one `recvmsg()`.
+const RECV_BUFFER_SIZE: usize = 64 * 1024;
+
 pub fn sys_socket(domain: usize, types: i32, protocal: usize) -> SyscallResult {
     // if domain == 1 {
     //     log::error!("not support unix socket");
@@ -47,7 +63,7 @@ pub fn sys_socket(domain: usize, types: i32, protocal: usize) -> SyscallResult {
 
     let types = SocketType::from_repr(types as usize).ok_or(SysError::EINVAL)?;
 
-    let socket = Socket::new(domain, types, protocal as u8, nonblock)?;
+    let socket = Socket::new(domain, types, protocal as u16, nonblock)?;
     let fd = current_task().with_mut_fdtable(|table| table.alloc(Arc::new(socket), flags))?;
     log::info!("[sys_socket] new socket {types:?} {flags:?} in fd {fd}, nonblock:{nonblock}");
     Ok(fd)
@@ -108,12 +124,9 @@ pub fn sys_setsockopt(
         .downcast_arc::<Socket>()
         .map_err(|_| SysError::ENOTSOCK)?;
 
-    let level = SocketLevel::try_from(level)?;
-    let optname = SocketOpt::try_from(optname)?;
-
-    // 只支持 SOL_SOCKET 层的 SO_REUSEADDR/SO_REUSEPORT
-    if level == SocketLevel::SOL_SOCKET {
-        match optname {
+    match SocketLevel::try_from(level)? {
+        // 只支持 SOL_SOCKET 层的 SO_REUSEADDR/SO_REUSEPORT
+        SocketLevel::SOL_SOCKET => match SocketOpt::try_from(optname)? {
             SocketOpt::REUSEADDR => {
                 let val = if optlen >= 4 {
                     let mut buf = [0u8; 4];
@@ -140,17 +153,148 @@ pub fn sys_setsockopt(
                 };
                 socket.sk.set_reuse_port(val);
             }
-            _ => {
+            SocketOpt::ATTACH_FILTER => {
+                let Sock::Packet(packet) = &socket.sk else {
+                    log::warn!("[setsockopt] filters are only supported on packet sockets");
+                    return Err(SysError::ENOPROTOOPT);
+                };
+                if optlen < size_of::<SockFprog>() {
+                    return Err(SysError::EINVAL);
+                }
+                let fprog = unsafe { UserReadPtr::<SockFprog>::new(optval, &addrspace).read()? };
+                let insns = unsafe {
+                    UserReadPtr::<SockFilter>::new(fprog.filter, &addrspace)
+                        .read_array(fprog.len as usize)?
+                };
+                packet.set_filter(Some(BpfProgram::new(insns)?));
+            }
+            SocketOpt::DETACH_FILTER => {
+                let Sock::Packet(packet) = &socket.sk else {
+                    return Err(SysError::ENOPROTOOPT);
+                };
+                packet.set_filter(None);
+            }
+            SocketOpt::TIMESTAMP_OLD => {
+                if optlen < size_of::<i32>() {
+                    return Err(SysError::EINVAL);
+                }
+                let val = unsafe { UserReadPtr::<i32>::new(optval, &addrspace).read()? };
+                socket.set_recv_cmsgs(RecvCmsgs::TIMESTAMP, val != 0);
+            }
+            SocketOpt::BINDTODEVICE => {
+                if !task.has_capability(CapabilitiesFlags::CAP_NET_RAW) {
+                    return Err(SysError::EPERM);
+                }
+                let bytes = unsafe {
+                    UserReadPtr::<u8>::new(optval, &addrspace).read_array(optlen.min(IFNAMSIZ))?
+                };
+                let len = bytes.iter().position(|&c| c == 0).unwrap_or(bytes.len());
+                let name = String::from_utf8_lossy(&bytes[..len]);
+                // An empty name unbinds the socket.
+                let ifindex = if name.is_empty() {
+                    None
+                } else {
+                    Some(net::interface_index(&name).ok_or(SysError::ENODEV)?)
+                };
+                socket.sk.bind_to_device(ifindex)?;
+            }
+            optname => {
                 log::warn!("[setsockopt] unsupported optname: {:?}", optname);
                 // return Err(SysError::ENOPROTOOPT);
             }
+        },
+        SocketLevel::IPPROTO_IP => {
+            let val = if optlen >= size_of::<i32>() {
+                unsafe { UserReadPtr::<i32>::new(optval, &addrspace).read()? }
+            } else {
+                return Err(SysError::EINVAL);
+            };
+            match IpSocketOpt::try_from(optname)? {
+                IpSocketOpt::PKTINFO => socket.set_recv_cmsgs(RecvCmsgs::PKTINFO, val != 0),
+                IpSocketOpt::RECVTTL => socket.set_recv_cmsgs(RecvCmsgs::TTL, val != 0),
+                IpSocketOpt::TTL => {
+                    let ttl = match val {
+                        -1 => None,
+                        1..=255 => Some(val as u8),
+                        _ => return Err(SysError::EINVAL),
+                    };
+                    socket.sk.set_hop_limit(ttl)?;
+                }
+                optname => {
+                    log::warn!("[setsockopt] unsupported IPPROTO_IP optname: {optname:?}");
+                }
+            }
         }
+        SocketLevel::IPPROTO_IPV6 => {
+            let val = if optlen >= size_of::<i32>() {
+                unsafe { UserReadPtr::<i32>::new(optval, &addrspace).read()? }
+            } else {
+                return Err(SysError::EINVAL);
+            };
+            match Ipv6SocketOpt::try_from(optname)? {
+                Ipv6SocketOpt::V6ONLY => socket.sk.set_ipv6_only(val != 0)?,
+                Ipv6SocketOpt::RECVPKTINFO => {
+                    socket.set_recv_cmsgs(RecvCmsgs::IPV6_PKTINFO, val != 0)
+                }
+                Ipv6SocketOpt::UNICAST_HOPS => {
+                    let hop_limit = match val {
+                        -1 => None,
+                        0..=255 => Some(val as u8),
+                        _ => return Err(SysError::EINVAL),
+                    };
+                    socket.sk.set_hop_limit(hop_limit)?;
+                }
+                optname => {
+                    log::warn!("[setsockopt] unsupported IPPROTO_IPV6 optname: {optname:?}");
+                }
+            }
+        }
+        SocketLevel::SOL_PACKET => {
+            let Sock::Packet(packet) = &socket.sk else {
+                return Err(SysError::ENOPROTOOPT);
+            };
+            match PacketSocketOpt::try_from(optname)? {
+                opt @ (PacketSocketOpt::ADD_MEMBERSHIP | PacketSocketOpt::DROP_MEMBERSHIP) => {
+                    if optlen < size_of::<PacketMreq>() {
+                        return Err(SysError::EINVAL);
+                    }
+                    let mreq =
+                        unsafe { UserReadPtr::<PacketMreq>::new(optval, &addrspace).read()? };
+                    if opt == PacketSocketOpt::ADD_MEMBERSHIP {
+                        packet.add_membership(mreq.ifindex as u32, mreq.ty)?;
+                    } else {
+                        packet.drop_membership(mreq.ifindex as u32, mreq.ty)?;
+                    }
+                }
+                optname => {
+                    log::warn!("[setsockopt] ignored SOL_PACKET optname: {optname:?}");
+                }
+            }
+        }
+        SocketLevel::SOL_NETLINK => {
+            let Sock::Netlink(netlink) = &socket.sk else {
+                return Err(SysError::ENOPROTOOPT);
+            };
+            let val = if optlen >= size_of::<u32>() {
+                unsafe { UserReadPtr::<u32>::new(optval, &addrspace).read()? }
+            } else {
+                return Err(SysError::EINVAL);
+            };
+            match NetlinkSocketOpt::try_from(optname)? {
+                NetlinkSocketOpt::ADD_MEMBERSHIP => netlink.set_membership(val, true)?,
+                NetlinkSocketOpt::DROP_MEMBERSHIP => netlink.set_membership(val, false)?,
+                optname => {
+                    log::warn!("[setsockopt] ignored SOL_NETLINK optname: {optname:?}");
+                }
+            }
+        }
+        _ => {}
     }
     Ok(0)
 }
 
 pub fn sys_getsockopt(
-    _sockfd: usize,
+    sockfd: usize,
     level: usize,
     optname: usize,
     optval: usize,
@@ -160,8 +304,6 @@ pub fn sys_getsockopt(
     let addrspace = task.addr_space();
     match SocketLevel::try_from(level)? {
         SocketLevel::SOL_SOCKET => {
-            const SEND_BUFFER_SIZE: usize = 64 * 1024;
-            const RECV_BUFFER_SIZE: usize = 64 * 1024;
             let mut optval = UserWritePtr::<u32>::new(optval, &addrspace);
             let mut optlen = UserWritePtr::<u32>::new(optlen, &addrspace);
             unsafe {
@@ -178,13 +320,66 @@ pub fn sys_getsockopt(
                         optval.write(0)?;
                         optlen.write(core::mem::size_of::<u32>() as u32)?;
                     }
+                    SocketOpt::TIMESTAMP_OLD => {
+                        let socket: Arc<Socket> = task
+                            .with_mut_fdtable(|table| table.get_file(sockfd))?
+                            .downcast_arc::<Socket>()
+                            .map_err(|_| SysError::ENOTSOCK)?;
+                        optval.write(socket.recv_cmsgs(RecvCmsgs::TIMESTAMP) as u32)?;
+                        optlen.write(core::mem::size_of::<u32>() as u32)?;
+                    }
+                    SocketOpt::BINDTODEVICE => {
+                        let socket: Arc<Socket> = task
+                            .with_mut_fdtable(|table| table.get_file(sockfd))?
+                            .downcast_arc::<Socket>()
+                            .map_err(|_| SysError::ENOTSOCK)?;
+                        // The name of the device is NUL-terminated, and is empty if the
+                        // socket is not bound to a device.
+                        match socket.sk.bound_device()?.and_then(net::interface_info) {
+                            Some(info) => {
+                                let mut name = info.name.into_bytes();
+                                name.push(0);
+                                UserWritePtr::<u8>::new(optval.to_usize(), &addrspace)
+                                    .write_array(&name)?;
+                                optlen.write(name.len() as u32)?;
+                            }
+                            None => optlen.write(0)?,
+                        }
+                    }
                     opt => {
                         log::error!("[sys_getsockopt] unsupported SOL_SOCKET opt {opt:?}")
                     }
                 };
             }
         }
-        SocketLevel::IPPROTO_IP | SocketLevel::IPPROTO_TCP => {
+        SocketLevel::IPPROTO_IP => {
+            let socket: Arc<Socket> = task
+                .with_mut_fdtable(|table| table.get_file(sockfd))?
+                .downcast_arc::<Socket>()
+                .map_err(|_| SysError::ENOTSOCK)?;
+            let val = match IpSocketOpt::try_from(optname)? {
+                IpSocketOpt::PKTINFO => socket.recv_cmsgs(RecvCmsgs::PKTINFO) as i32,
+                IpSocketOpt::RECVTTL => socket.recv_cmsgs(RecvCmsgs::TTL) as i32,
+                IpSocketOpt::TTL => {
+                    const DEFAULT_TTL: u8 = 64;
+                    socket.sk.hop_limit()?.unwrap_or(DEFAULT_TTL) as i32
+                }
+                IpSocketOpt::HDRINCL => match &socket.sk {
+                    Sock::Raw(raw) => raw.is_hdr_included() as i32,
+                    _ => return Err(SysError::ENOPROTOOPT),
+                },
+                IpSocketOpt::MULTICAST_TTL | IpSocketOpt::MULTICAST_LOOP => 1,
+                IpSocketOpt::TOS | IpSocketOpt::MULTICAST_IF => 0,
+                IpSocketOpt::ADD_MEMBERSHIP | IpSocketOpt::DROP_MEMBERSHIP => {
+                    return Err(SysError::ENOPROTOOPT);
+                }
+            };
+            unsafe {
+                UserWritePtr::<i32>::new(optval, &addrspace).write(val)?;
+                UserWritePtr::<u32>::new(optlen, &addrspace).write(size_of::<i32>() as u32)?;
+            }
+        }
+        SocketLevel::IPPROTO_TCP => {
             const MAX_SEGMENT_SIZE: usize = 1460;
             let mut optval = UserWritePtr::<u32>::new(optval, &addrspace);
             let mut optlen = UserWritePtr::<u32>::new(optlen, &addrspace);
@@ -208,7 +403,64 @@ pub fn sys_getsockopt(
                 };
             }
         }
-        SocketLevel::IPPROTO_IPV6 => todo!(),
+        SocketLevel::IPPROTO_IPV6 => {
+            let socket: Arc<Socket> = task
+                .with_mut_fdtable(|table| table.get_file(sockfd))?
+                .downcast_arc::<Socket>()
+                .map_err(|_| SysError::ENOTSOCK)?;
+            let val = match Ipv6SocketOpt::try_from(optname)? {
+                Ipv6SocketOpt::V6ONLY => socket.sk.ipv6_only()? as i32,
+                Ipv6SocketOpt::UNICAST_HOPS => {
+                    const DEFAULT_HOP_LIMIT: u8 = 64;
+                    socket.sk.hop_limit()?.unwrap_or(DEFAULT_HOP_LIMIT) as i32
+                }
+                Ipv6SocketOpt::RECVPKTINFO => socket.recv_cmsgs(RecvCmsgs::IPV6_PKTINFO) as i32,
+                Ipv6SocketOpt::MULTICAST_HOPS | Ipv6SocketOpt::MULTICAST_LOOP => 1,
+                Ipv6SocketOpt::MULTICAST_IF | Ipv6SocketOpt::TCLASS => 0,
+                Ipv6SocketOpt::ADD_MEMBERSHIP
+                | Ipv6SocketOpt::DROP_MEMBERSHIP
+                | Ipv6SocketOpt::PKTINFO => {
+                    return Err(SysError::ENOPROTOOPT);
+                }
+            };
+            unsafe {
+                UserWritePtr::<i32>::new(optval, &addrspace).write(val)?;
+                UserWritePtr::<u32>::new(optlen, &addrspace).write(size_of::<i32>() as u32)?;
+            }
+        }
+        SocketLevel::SOL_PACKET => {
+            let socket: Arc<Socket> = task
+                .with_mut_fdtable(|table| table.get_file(sockfd))?
+                .downcast_arc::<Socket>()
+                .map_err(|_| SysError::ENOTSOCK)?;
+            let Sock::Packet(packet) = &socket.sk else {
+                return Err(SysError::ENOPROTOOPT);
+            };
+            match PacketSocketOpt::try_from(optname)? {
+                PacketSocketOpt::STATISTICS => {
+                    let (packets, drops) = packet.take_statistics();
+                    unsafe {
+                        UserWritePtr::<TpacketStats>::new(optval, &addrspace)
+                            .write(TpacketStats { packets, drops })?;
+                        UserWritePtr::<u32>::new(optlen, &addrspace)
+                            .write(size_of::<TpacketStats>() as u32)?;
+                    }
+                }
+                _ => return Err(SysError::ENOPROTOOPT),
+            }
+        }
+        SocketLevel::SOL_NETLINK => {
+            let val = match NetlinkSocketOpt::try_from(optname)? {
+                NetlinkSocketOpt::EXT_ACK | NetlinkSocketOpt::GET_STRICT_CHK => 0,
+                NetlinkSocketOpt::ADD_MEMBERSHIP | NetlinkSocketOpt::DROP_MEMBERSHIP => {
+                    return Err(SysError::ENOPROTOOPT);
+                }
+            };
+            unsafe {
+                UserWritePtr::<u32>::new(optval, &addrspace).write(val)?;
+                UserWritePtr::<u32>::new(optlen, &addrspace).write(size_of::<u32>() as u32)?;
+            }
+        }
     }
     Ok(0)
 }
@@ -230,13 +482,12 @@ pub async fn sys_sendto(
     dest_addr: usize,
     addrlen: usize,
 ) -> SyscallResult {
-    // debug_assert!(flags == 0, "unsupported flags");
-
     let task = current_task();
     log::debug!(
-        "[sys_sendto] thread: {}, sockfd: {sockfd:#x}, dest_addr: {dest_addr:#x}",
+        "[sys_sendto] thread: {}, sockfd: {sockfd:#x}, dest_addr: {dest_addr:#x}, flags: {flags:#x}",
         task.tid()
     );
+    let flags = MsgFlags::from_bits_truncate(flags as u32);
 
     let addrspace = task.addr_space();
     let mut read_ptr = UserReadPtr::<u8>::new(buf, &addrspace);
@@ -246,42 +497,16 @@ pub async fn sys_sendto(
         .downcast_arc::<Socket>()
         .map_err(|_| SysError::ENOTSOCK)?;
 
-    task.set_state(TaskState::Interruptible);
-
-    let bytes = match socket.types {
-        SocketType::STREAM => {
-            if dest_addr != 0 {
-                return Err(SysError::EISCONN);
-            }
-            socket.sk.sendto(buf, None).await?
-        }
-        SocketType::DGRAM => {
-            let sockaddr = if dest_addr != 0 {
-                Some(read_sockaddr(addrspace.clone(), dest_addr, addrlen)?)
-            } else {
-                None
-            };
-            socket.sk.sendto(buf, sockaddr).await?
-        }
-        SocketType::RAW => {
-            let sockaddr = if dest_addr != 0 {
-                Some(read_sockaddr(addrspace.clone(), dest_addr, addrlen)?)
-            } else {
-                None
-            };
-            socket.sk.sendto(buf, sockaddr).await?
-        }
-        _ => {
-            log::error!("unknown: {:?}", socket.types);
-            return Err(SysError::EOPNOTSUPP);
-        }
+    let dest_addr = match dest_addr {
+        0 => None,
+        _ if socket.types == SocketType::STREAM => return Err(SysError::EISCONN),
+        _ => Some(read_sockaddr(addrspace.clone(), dest_addr, addrlen)?),
     };
 
+    task.set_state(TaskState::Interruptible);
+    let ret = send_on(&socket, buf, dest_addr, &[], flags).await;
     task.set_state(TaskState::Running);
-
-    poll_interfaces();
-
-    Ok(bytes)
+    ret
 }
 
 pub async fn sys_recvfrom(
@@ -292,14 +517,11 @@ pub async fn sys_recvfrom(
     src_addr: usize,
     addrlen: usize,
 ) -> SyscallResult {
-    debug_assert!(flags == 0, "unsupported flags");
-
-    // poll_interfaces();
-
     let task = current_task();
     let tid = task.tid();
     log::debug!("[sys_recvfrom] tid: {tid} socket fd: {sockfd:#x}, src_addr: {src_addr:#x}");
-    log::debug!("[sys_recvfrom] buf: {buf:#x}, len: {len:#x}");
+    log::debug!("[sys_recvfrom] buf: {buf:#x}, len: {len:#x}, flags: {flags:#x}");
+    let flags = MsgFlags::from_bits_truncate(flags as u32);
     let addrspace = task.addr_space();
     let mut write_ptr = UserWritePtr::<u8>::new(buf, &addrspace);
     let buf = unsafe { write_ptr.try_into_mut_slice(len) }?;
@@ -312,13 +534,261 @@ pub async fn sys_recvfrom(
     let mut temp = vec![0; len];
 
     task.set_state(TaskState::Interruptible);
-    let (bytes, remote_addr) = socket.sk.recvfrom(&mut temp).await?;
+    let ret = socket.recv_msg(&mut temp, flags).await;
     task.set_state(TaskState::Running);
+    let msg = ret?;
 
-    buf[..bytes].copy_from_slice(&temp[..bytes]);
-    write_sockaddr(addrspace.clone(), src_addr, addrlen, remote_addr)?;
+    buf[..msg.copied].copy_from_slice(&temp[..msg.copied]);
+    write_sockaddr(addrspace.clone(), src_addr, addrlen, msg.src)?;
     // log::debug!("[sys_recvfrom] recv buf: {:?}", buf);
 
+    Ok(msg.ret)
+}
+
+/// Sends `buf` on `socket` to `dest_addr`, or to the peer of the socket if it is
+/// `None`, for `sendto()`, `sendmsg()` and `sendmmsg()`.
+async fn send_on(
+    socket: &Socket,
+    buf: &[u8],
+    dest_addr: Option<SockAddr>,
+    cmsgs: &[ControlMessage],
+    flags: MsgFlags,
+) -> SysResult<usize> {
+    let bytes = match socket.types {
+        SocketType::STREAM if dest_addr.is_some() => return Err(SysError::EISCONN),
+        SocketType::STREAM | SocketType::DGRAM | SocketType::RAW => {
+            socket.send_msg(buf, dest_addr, cmsgs, flags).await?
+        }
+        _ => {
+            log::error!("unknown: {:?}", socket.types);
+            return Err(SysError::EOPNOTSUPP);
+        }
+    };
+    poll_interfaces();
+    Ok(bytes)
+}
+
+/// Maximum number of iovecs of a message.
+const UIO_MAXIOV: usize = 1024;
+
+/// Reads the `iovlen` iovecs at `iov` of a message.
+fn read_iovecs(addrspace: &AddrSpace, iov: usize, iovlen: usize) -> SysResult<Vec<IoVec>> {
+    if iovlen > UIO_MAXIOV {
+        return Err(SysError::EMSGSIZE);
+    }
+    if iovlen == 0 {
+        return Ok(Vec::new());
+    }
+    unsafe { UserReadPtr::<IoVec>::new(iov, addrspace).read_array(iovlen) }
+}
+
+/// Maximum length of the control messages of a message, as `net.core.optmem_max` on
+/// Linux.
+const OPTMEM_MAX: usize = 20480;
+
+/// Sends the message described by `msg_hdr` on `socket`, which gathers the data in
+/// its iovecs and carries its control messages.
+///
+/// # Errors
+/// Returns [`SysError::ENOBUFS`] if `msg_controllen` exceeds [`OPTMEM_MAX`].
+async fn send_msghdr(
+    socket: &Socket,
+    addrspace: &Arc<AddrSpace>,
+    msg_hdr: &MsgHdr,
+    flags: MsgFlags,
+) -> SysResult<usize> {
+    let dest_addr = if msg_hdr.msg_name != 0 && msg_hdr.msg_namelen > 0 {
+        Some(read_sockaddr(
+            addrspace.clone(),
+            msg_hdr.msg_name,
+            msg_hdr.msg_namelen as usize,
+        )?)
+    } else {
+        None
+    };
+
+    let mut buf = Vec::new();
+    for iov in read_iovecs(addrspace, msg_hdr.msg_iov, msg_hdr.msg_iovlen)? {
+        if iov.iov_len > 0 {
+            let mut data_ptr = UserReadPtr::<u8>::new(iov.iov_base, addrspace);
+            let data = unsafe { data_ptr.try_into_slice(iov.iov_len)? };
+            buf.extend_from_slice(data);
+        }
+    }
+
+    let cmsgs = if msg_hdr.msg_control != 0 && msg_hdr.msg_controllen > 0 {
+        if msg_hdr.msg_controllen > OPTMEM_MAX {
+            return Err(SysError::ENOBUFS);
+        }
+        let control = unsafe {
+            UserReadPtr::<u8>::new(msg_hdr.msg_control, addrspace)
+                .read_array(msg_hdr.msg_controllen)?
+        };
+        ControlMessage::parse_all(&control)?
+    } else {
+        Vec::new()
+    };
+
+    send_on(socket, &buf, dest_addr, &cmsgs, flags).await
+}
+
+/// Receives a message on `socket` into the iovecs of `msg_hdr`, and writes its source
+/// address and control messages to `msg_name` and `msg_control`. The lengths of them
+/// and `msg_flags` are updated in `msg_hdr`. At most [`RECV_BUFFER_SIZE`] bytes are
+/// received at a time, so with `MSG_WAITALL`, a stream socket receives in chunks until
+/// the iovecs are full, or until a chunk comes up short.
+///
+/// Control messages which don't fit in `msg_control` are discarded, and `MSG_CTRUNC`
+/// is set in `msg_flags`.
+async fn recv_msghdr(
+    socket: &Socket,
+    addrspace: &Arc<AddrSpace>,
+    msg_hdr: &mut MsgHdr,
+    flags: MsgFlags,
+) -> SysResult<usize> {
+    let iovs = read_iovecs(addrspace, msg_hdr.msg_iov, msg_hdr.msg_iovlen)?;
+    let total_len = iovs
+        .iter()
+        .fold(0, |len: usize, iov| len.saturating_add(iov.iov_len));
+    let mut buf = vec![0u8; total_len.min(RECV_BUFFER_SIZE)];
+    let msg = socket.recv_msg(&mut buf, flags).await?;
+    scatter_iovecs(addrspace, &iovs, 0, &buf[..msg.copied])?;
+
+    let mut ret = msg.ret;
+    let mut received = msg.copied;
+    let wait_all = flags.contains(MsgFlags::WAITALL)
+        && !flags.contains(MsgFlags::PEEK)
+        && socket.types == SocketType::STREAM;
+    if wait_all && msg.copied == buf.len() {
+        while received < total_len {
+            let len = (total_len - received).min(RECV_BUFFER_SIZE);
+            // Return the data received so far if a later chunk fails.
+            let copied = match socket.recv_msg(&mut buf[..len], flags).await {
+                Ok(next) => next.copied,
+                Err(_) => break,
+            };
+            scatter_iovecs(addrspace, &iovs, received, &buf[..copied])?;
+            received += copied;
+            ret += copied;
+            if copied < len {
+                break;
+            }
+        }
+    }
+
+    if msg_hdr.msg_name != 0 {
+        let namelen = write_sockaddr_buf(
+            addrspace,
+            msg_hdr.msg_name,
+            msg_hdr.msg_namelen as usize,
+            msg.src,
+        )?;
+        msg_hdr.msg_namelen = namelen as u32;
+    }
+
+    let mut msg_flags = msg.flags;
+    let mut control = Vec::new();
+    for cmsg in msg.cmsgs {
+        let start = control.len();
+        cmsg.encode(&mut control);
+        if control.len() > msg_hdr.msg_controllen {
+            control.truncate(start);
+            msg_flags |= MsgFlags::CTRUNC;
+            break;
+        }
+    }
+    if !control.is_empty() {
+        unsafe {
+            UserWritePtr::<u8>::new(msg_hdr.msg_control, addrspace).write_array(&control)?;
+        }
+    }
+    msg_hdr.msg_controllen = control.len();
+    msg_hdr.msg_flags = msg_flags.bits() as i32;
+
+    Ok(ret)
+}
+
+/// Writes `data` to the iovecs `iovs`, starting at `offset` bytes into them.
+fn scatter_iovecs(
+    addrspace: &AddrSpace,
+    iovs: &[IoVec],
+    mut offset: usize,
+    mut data: &[u8],
+) -> SysResult<()> {
+    for iov in iovs {
+        if data.is_empty() {
+            break;
+        }
+        if offset >= iov.iov_len {
+            offset -= iov.iov_len;
+            continue;
+        }
+        let len = (iov.iov_len - offset).min(data.len());
+        unsafe {
+            UserWritePtr::<u8>::new(iov.iov_base + offset, addrspace).write_array(&data[..len])?;
+        }
+        data = &data[len..];
+        offset = 0;
+    }
+    Ok(())
+}
+
+/// Sends the message described by the `struct msghdr` at `msg` on the socket `sockfd`.
+///
+/// The data of the message is gathered from the iovecs of the message, and its control
+/// messages, e.g., `IP_PKTINFO` and `IP_TTL`, give the source address and the TTL of a
+/// UDP datagram. `flags` are the `MSG_*` flags as in `sendto()`.
+pub async fn sys_sendmsg(sockfd: usize, msg: usize, flags: usize) -> SyscallResult {
+    let task = current_task();
+    let addrspace = task.addr_space();
+    log::debug!(
+        "[sys_sendmsg] tid: {}, sockfd: {sockfd}, msg: {msg:#x}, flags: {flags:#x}",
+        task.tid()
+    );
+    let flags = MsgFlags::from_bits_truncate(flags as u32);
+
+    let socket: Arc<Socket> = task
+        .with_mut_fdtable(|table| table.get_file(sockfd))?
+        .downcast_arc::<Socket>()
+        .map_err(|_| SysError::ENOTSOCK)?;
+    let msg_hdr = unsafe { UserReadPtr::<MsgHdr>::new(msg, &addrspace).read()? };
+
+    task.set_state(TaskState::Interruptible);
+    task.set_wake_up_signal(!task.get_sig_mask());
+    let ret = send_msghdr(&socket, &addrspace, &msg_hdr, flags).await;
+    task.set_state(TaskState::Running);
+    ret
+}
+
+/// Receives a message on the socket `sockfd` into the `struct msghdr` at `msg`.
+///
+/// The data of the message is scattered into the iovecs of the message, and the
+/// control messages enabled on the socket, e.g., `IP_PKTINFO` and `SO_TIMESTAMP`, are
+/// written to its control buffer. `flags` are the `MSG_*` flags as in `recvfrom()`.
+pub async fn sys_recvmsg(sockfd: usize, msg: usize, flags: usize) -> SyscallResult {
+    let task = current_task();
+    let addrspace = task.addr_space();
+    log::debug!(
+        "[sys_recvmsg] tid: {}, sockfd: {sockfd}, msg: {msg:#x}, flags: {flags:#x}",
+        task.tid()
+    );
+    let flags = MsgFlags::from_bits_truncate(flags as u32);
+
+    let socket: Arc<Socket> = task
+        .with_mut_fdtable(|table| table.get_file(sockfd))?
+        .downcast_arc::<Socket>()
+        .map_err(|_| SysError::ENOTSOCK)?;
+    let mut msg_hdr = unsafe { UserReadPtr::<MsgHdr>::new(msg, &addrspace).read()? };
+
+    task.set_state(TaskState::Interruptible);
+    task.set_wake_up_signal(!task.get_sig_mask());
+    let ret = recv_msghdr(&socket, &addrspace, &mut msg_hdr, flags).await;
+    task.set_state(TaskState::Running);
+    let bytes = ret?;
+
+    unsafe {
+        UserWritePtr::<MsgHdr>::new(msg, &addrspace).write(msg_hdr)?;
+    }
     Ok(bytes)
 }
 
@@ -349,8 +819,9 @@ pub async fn sys_connect(sockfd: usize, addr: usize, addrlen: usize) -> SyscallR
     let remote_addr = read_sockaddr(addrspace.clone(), addr, addrlen)?;
 
     // not 0.0.0.0
-    if remote_addr.as_unix_path().is_none()
-        && !remote_addr.as_listen_endpoint().unwrap().is_specified()
+    if remote_addr
+        .as_listen_endpoint()
+        .is_some_and(|ep| !ep.is_specified())
     {
         return Err(SysError::EADDRNOTAVAIL);
     }
@@ -391,13 +862,12 @@ pub async fn sys_accept(sockfd: usize, addr: usize, addrlen: usize) -> SyscallRe
 
     task.set_state(TaskState::Interruptible);
     task.set_wake_up_signal(!task.get_sig_mask());
-    let new_sk = socket.sk.accept().await?;
+    let new_sk = Sock::Tcp(socket.sk.accept().await?);
     task.set_state(TaskState::Running);
 
     let peer_addr = new_sk.peer_addr()?;
-    let peer_addr = SockAddr::from_endpoint(peer_addr);
     write_sockaddr(addrspace, addr, addrlen, peer_addr)?;
-    let new_socket = Arc::new(Socket::from_another(&socket, Sock::Tcp(new_sk)));
+    let new_socket = Arc::new(Socket::from_another(&socket, new_sk));
     let fd = task.with_mut_fdtable(|table| table.alloc(new_socket, OpenFlags::empty()))?;
     Ok(fd)
 }
@@ -488,11 +958,10 @@ pub async fn sys_accept4(
 
     task.set_state(TaskState::Interruptible);
     task.set_wake_up_signal(!task.get_sig_mask());
-    let new_sk = socket.sk.accept().await?;
+    let new_sk = Sock::Tcp(socket.sk.accept().await?);
     task.set_state(TaskState::Running);
 
     let peer_addr = new_sk.peer_addr()?;
-    let peer_addr = SockAddr::from_endpoint(peer_addr);
     write_sockaddr(addrspace, addr, addrlen, peer_addr)?;
 
     let mut open_flags = OpenFlags::empty();
@@ -503,7 +972,7 @@ pub async fn sys_accept4(
         open_flags |= OpenFlags::O_CLOEXEC;
     }
 
-    let new_socket = Arc::new(Socket::from_another(&socket, Sock::Tcp(new_sk)));
+    let new_socket = Arc::new(Socket::from_another(&socket, new_sk));
     let fd = task.with_mut_fdtable(|table| table.alloc(new_socket, open_flags))?;
     Ok(fd)
 }
@@ -542,53 +1011,21 @@ pub async fn sys_sendmmsg(
     let mut msgvec_ptr = UserReadPtr::<MmsgHdr>::new(msgvec, &addrspace);
     let msgvec_array = unsafe { msgvec_ptr.read_array(vlen)? };
 
+    let flags = MsgFlags::from_bits_truncate(flags as u32);
     let mut sent_count = 0;
     let mut total_bytes = 0;
 
     task.set_state(TaskState::Interruptible);
+    task.set_wake_up_signal(!task.get_sig_mask());
 
     for (i, mmsg) in msgvec_array.iter().enumerate() {
-        let msg_hdr = &mmsg.msg_hdr;
-
-        let dest_addr = if msg_hdr.msg_name != 0 && msg_hdr.msg_namelen > 0 {
-            Some(read_sockaddr(
-                addrspace.clone(),
-                msg_hdr.msg_name,
-                msg_hdr.msg_namelen as usize,
-            )?)
-        } else {
-            None
-        };
-
-        if msg_hdr.msg_iov == 0 || msg_hdr.msg_iovlen == 0 {
-            sent_count += 1;
-            continue;
-        }
-
-        let mut iov_ptr = UserReadPtr::<IoVec>::new(msg_hdr.msg_iov, &addrspace);
-        let iov_array = unsafe { iov_ptr.read_array(msg_hdr.msg_iovlen)? };
-
-        let mut buf = Vec::new();
-        for iov in iov_array.iter() {
-            if iov.iov_len > 0 && iov.iov_base != 0 {
-                let mut data_ptr = UserReadPtr::<u8>::new(iov.iov_base, &addrspace);
-                let data = unsafe { data_ptr.try_into_slice(iov.iov_len)? };
-                buf.extend_from_slice(data);
-            }
-        }
-
-        let bytes_sent = match socket.types {
-            SocketType::STREAM => {
-                if dest_addr.is_some() {
-                    task.set_state(TaskState::Running);
-                    return Err(SysError::EISCONN);
-                }
-                socket.sk.sendto(&buf, None).await?
-            }
-            SocketType::DGRAM => socket.sk.sendto(&buf, dest_addr).await?,
-            _ => {
+        let bytes_sent = match send_msghdr(&socket, &addrspace, &mmsg.msg_hdr, flags).await {
+            Ok(bytes) => bytes,
+            // An error is only returned if no message is sent.
+            Err(_) if sent_count > 0 => break,
+            Err(e) => {
                 task.set_state(TaskState::Running);
-                return Err(SysError::EOPNOTSUPP);
+                return Err(e);
             }
         };
 
@@ -603,14 +1040,9 @@ pub async fn sys_sendmmsg(
 
         sent_count += 1;
         total_bytes += bytes_sent;
-
-        if bytes_sent < buf.len() {
-            break;
-        }
     }
 
     task.set_state(TaskState::Running);
-    poll_interfaces();
 
     log::debug!(
         "[sys_sendmmsg] sent {} messages, total {} bytes",
@@ -656,66 +1088,39 @@ pub async fn sys_recvmmsg(
     let mut msgvec_ptr = UserReadPtr::<MmsgHdr>::new(msgvec, &addrspace);
     let msgvec_array = unsafe { msgvec_ptr.read_array(vlen)? };
 
+    let mut flags = MsgFlags::from_bits_truncate(flags as u32);
     let mut recv_count = 0;
 
     task.set_state(TaskState::Interruptible);
+    task.set_wake_up_signal(!task.get_sig_mask());
 
     for (i, mmsg) in msgvec_array.iter().enumerate() {
-        let msg_hdr = &mmsg.msg_hdr;
-
-        if msg_hdr.msg_iov == 0 || msg_hdr.msg_iovlen == 0 {
-            recv_count += 1;
-            continue;
-        }
-
-        let mut iov_ptr = UserReadPtr::<IoVec>::new(msg_hdr.msg_iov, &addrspace);
-        let iov_array = unsafe { iov_ptr.read_array(msg_hdr.msg_iovlen)? };
-
-        let mut total_len = 0;
-        for iov in iov_array.iter() {
-            total_len += iov.iov_len;
-        }
-
-        if total_len == 0 {
-            recv_count += 1;
-            continue;
-        }
-
-        let mut temp_buf = vec![0u8; total_len];
-        let (bytes_received, remote_addr) = socket.sk.recvfrom(&mut temp_buf).await?;
-
-        let mut offset = 0;
-        for iov in iov_array.iter() {
-            if offset >= bytes_received || iov.iov_len == 0 || iov.iov_base == 0 {
-                break;
+        let mut msg_hdr = mmsg.msg_hdr;
+        let bytes_received = match recv_msghdr(&socket, &addrspace, &mut msg_hdr, flags).await {
+            Ok(bytes) => bytes,
+            // An error is only returned if no message is received.
+            Err(_) if recv_count > 0 => break,
+            Err(e) => {
+                task.set_state(TaskState::Running);
+                return Err(e);
             }
+        };
 
-            let copy_len = core::cmp::min(iov.iov_len, bytes_received - offset);
-            let mut data_ptr = UserWritePtr::<u8>::new(iov.iov_base, &addrspace);
-            let data_slice = unsafe { data_ptr.try_into_mut_slice(copy_len)? };
-            data_slice.copy_from_slice(&temp_buf[offset..offset + copy_len]);
-            offset += copy_len;
-        }
-
-        if msg_hdr.msg_name != 0 && msg_hdr.msg_namelen > 0 {
-            write_sockaddr(
-                addrspace.clone(),
-                msg_hdr.msg_name,
-                msg_hdr.msg_namelen as usize,
-                remote_addr,
-            )?;
-        }
-
-        let mut result_ptr = UserWritePtr::<u32>::new(
-            msgvec + i * core::mem::size_of::<MmsgHdr>() + core::mem::offset_of!(MmsgHdr, msg_len),
-            &addrspace,
-        );
+        let mut result_ptr =
+            UserWritePtr::<MmsgHdr>::new(msgvec + i * core::mem::size_of::<MmsgHdr>(), &addrspace);
         unsafe {
-            result_ptr.write(bytes_received as u32)?;
+            result_ptr.write(MmsgHdr {
+                msg_hdr,
+                msg_len: bytes_received as u32,
+            })?;
         }
 
         recv_count += 1;
 
+        // Only the first message is waited for with `MSG_WAITFORONE`.
+        if flags.contains(MsgFlags::WAITFORONE) {
+            flags |= MsgFlags::DONTWAIT;
+        }
         // if block and recv parts of data, continue
         if bytes_received == 0 {
             break;
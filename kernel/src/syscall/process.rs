@@ -23,6 +23,7 @@ use systype::{
     error::{SysError, SyscallResult},
     rlimit::RLimit,
 };
+use vfs::fanotify::types::FanEventMask;
 use vfs::file::File;
 use vfs::path::Path;
 
@@ -526,6 +527,7 @@ fn __sys_clone(
     let flags = CloneFlags::from_bits(flags as u64 & !0xff).ok_or(SysError::EINVAL)?;
     log::info!("[sys_clone] flags {flags:?}");
 
+    task.check_nproc_limit()?;
     let new_task = task.fork(flags);
     new_task.trap_context_mut().set_user_ret_val(0);
     let new_tid = new_task.tid();
@@ -682,6 +684,10 @@ pub async fn sys_execve(path: usize, argv: usize, envp: usize) -> SyscallResult
 
     log::info!("[sys_execve]: open file {}", dentry.path());
     let file = <dyn File>::open(dentry)?;
+    file.fanotify_permission(FanEventMask::OPEN_PERM).await?;
+    file.fanotify_permission(FanEventMask::OPEN_EXEC_PERM)
+        .await?;
+    file.fsnotify_publish(FanEventMask::OPEN | FanEventMask::OPEN_EXEC);
 
     let mut name = String::new();
 
@@ -866,7 +872,9 @@ pub enum Resource {
 ///
 ///
 /// The pid argument specifies the ID of the process on which the call is to operate.
-/// If pid is 0, then the call applies to the calling process.
+/// If pid is 0, then the call applies to the calling process. The limits of another
+/// process can only be accessed by a task with the same user and group IDs, or with
+/// `CAP_SYS_RESOURCE`, which is also needed to raise a hard limit.
 ///```c
 /// struct rlimit {
 ///     rlim_t rlim_cur;  /* Soft limit */
@@ -885,43 +893,50 @@ pub fn sys_prlimit64(
     let mut nlimit = UserReadPtr::<RLimit>::new(new_limit, &addrspace);
     let mut olimit = UserWritePtr::<RLimit>::new(old_limit, &addrspace);
 
-    let _ptask = if pid == 0 {
+    let ptask = if pid == 0 {
         task.clone()
     } else {
-        TASK_MANAGER.get_task(pid).ok_or(SysError::EINVAL)?
+        TASK_MANAGER.get_task(pid).ok_or(SysError::ESRCH)?
     };
+    task.check_prlimit_access(&ptask)?;
 
     let resource = Resource::from_repr(resource).ok_or(SysError::EINVAL)?;
 
     log::debug!("[prlimit64] pid: {pid}, resource: {resource:?}");
 
-    if !olimit.is_null() {
-        let limit = match resource {
-            Resource::STACK => RLimit::one(USER_STACK_SIZE, USER_STACK_SIZE),
-            Resource::NOFILE => task.with_mut_fdtable(|table| table.get_rlimit()),
-            r => {
-                log::error!("[sys_prlimit64] old limit {:?} not implemented", r);
-                RLimit::one(0, 0)
-            }
-        };
-        unsafe { olimit.write(limit)? };
-    }
-
-    if !nlimit.is_null() {
+    let rlimit = if nlimit.is_null() {
+        None
+    } else {
         let rlimit = unsafe { nlimit.read()? };
+        if rlimit.rlim_cur > rlimit.rlim_max {
+            return Err(SysError::EINVAL);
+        }
+        Some(rlimit)
+    };
+
+    let old = match resource {
+        Resource::NOFILE => ptask.with_mut_fdtable(|table| table.get_rlimit()),
+        r => ptask.get_rlimit(r),
+    };
+
+    if let Some(rlimit) = rlimit {
+        // Only a privileged task may raise a hard limit.
+        if rlimit.rlim_max > old.rlim_max
+            && !task.has_capability(CapabilitiesFlags::CAP_SYS_RESOURCE)
+        {
+            return Err(SysError::EPERM);
+        }
+        log::debug!("[sys_prlimit64] new limit {resource:?}: {rlimit:?}");
         match resource {
-            Resource::STACK => {
-                log::debug!("[sys_prlimit64] new limit STACK: {:?}", rlimit);
-            }
-            Resource::NOFILE => {
-                task.with_mut_fdtable(|table| table.set_rlimit(rlimit));
-            }
-            r => {
-                log::error!("[sys_prlimit64] new limit {:?} not implemented", r);
-            }
+            Resource::NOFILE => ptask.with_mut_fdtable(|table| table.set_rlimit(rlimit)),
+            r => ptask.rlimits_mut().lock()[r as usize] = rlimit,
         }
     }
 
+    if !olimit.is_null() {
+        unsafe { olimit.write(old)? };
+    }
+
     Ok(0)
 }
 
@@ -974,6 +989,7 @@ pub fn sys_clone3(user_args: usize, size: usize) -> SyscallResult {
     // log::error!("[sys_clone3] {:?}", args);
 
     let flags = CloneFlags::from_bits(args.flags & !0xff).ok_or(SysError::EINVAL)?;
+    task.check_nproc_limit()?;
     let new_task = task.fork(flags);
     new_task.trap_context_mut().set_user_ret_val(0);
     let new_tid = new_task.tid();
@@ -1211,6 +1227,10 @@ pub fn sys_prctl(
     pub const PR_GET_NO_NEW_PRIVS: usize = 39;
     pub const PR_SET_PDEATHSIG: usize = 1;
     pub const PR_GET_PDEATHSIG: usize = 2;
+    #[cfg(target_arch = "riscv64")]
+    pub const PR_RISCV_V_SET_CONTROL: usize = 69;
+    #[cfg(target_arch = "riscv64")]
+    pub const PR_RISCV_V_GET_CONTROL: usize = 70;
 
     let task = current_task();
     let addrspace = task.addr_space();
@@ -1234,10 +1254,13 @@ pub fn sys_prctl(
         }
         PR_SET_DUMPABLE => {
             // arg2: 0/1
-            task.dumpable.store(arg2 != 0, Relaxed);
+            if arg2 > 1 {
+                return Err(SysError::EINVAL);
+            }
+            task.process().dumpable.store(arg2 != 0, Relaxed);
             Ok(0)
         }
-        PR_GET_DUMPABLE => Ok(task.dumpable.load(Relaxed) as usize),
+        PR_GET_DUMPABLE => Ok(task.process().dumpable.load(Relaxed) as usize),
         PR_SET_NO_NEW_PRIVS => {
             // arg2: 0/1
             task.no_new_privs.store(arg2 != 0, Relaxed);
@@ -1256,6 +1279,35 @@ pub fn sys_prctl(
             }
             Ok(0)
         }
+        #[cfg(target_arch = "riscv64")]
+        PR_RISCV_V_SET_CONTROL => {
+            use crate::trap::trap_context::{
+                VSTATE_CTRL_CUR_MASK, VSTATE_CTRL_MASK, VSTATE_CTRL_NEXT_MASK, VSTATE_CTRL_OFF,
+            };
+            // arg2: PR_RISCV_V_VSTATE_CTRL_* flags
+            let cur = arg2 & VSTATE_CTRL_CUR_MASK;
+            let next = (arg2 & VSTATE_CTRL_NEXT_MASK) >> 2;
+            if !arch::fpu::has_vector()
+                || arg2 & !VSTATE_CTRL_MASK != 0
+                || cur == VSTATE_CTRL_CUR_MASK
+                || next == VSTATE_CTRL_CUR_MASK
+            {
+                return Err(SysError::EINVAL);
+            }
+            let cx = task.trap_context_mut();
+            if cur == VSTATE_CTRL_OFF && cx.vector_used() {
+                return Err(SysError::EPERM);
+            }
+            cx.set_vector_ctrl(arg2);
+            Ok(0)
+        }
+        #[cfg(target_arch = "riscv64")]
+        PR_RISCV_V_GET_CONTROL => {
+            if !arch::fpu::has_vector() {
+                return Err(SysError::EINVAL);
+            }
+            Ok(task.trap_context_mut().vector_ctrl())
+        }
         _ => Err(SysError::EINVAL),
     }
 }
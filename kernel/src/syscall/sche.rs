@@ -3,7 +3,7 @@ use bitflags::bitflags;
 use systype::error::{SysError, SyscallResult};
 
 use crate::{
-    processor::current_task,
+    processor::{current_hart, current_task},
     task::{manager::TASK_MANAGER, mask::CpuMask},
     vm::user_ptr::{UserReadPtr, UserWritePtr},
 };
@@ -73,6 +73,25 @@ pub fn sys_sched_getaffinity(pid: usize, cpusetsize: usize, mask: usize) -> Sysc
     Ok(1)
 }
 
+/// `getcpu()` determines the CPU and NUMA node on which the calling thread is running,
+/// and writes them into `cpu` and `node` if they are not NULL. There is only one NUMA
+/// node, so the node is always 0.
+pub fn sys_getcpu(cpu: usize, node: usize) -> SyscallResult {
+    let task = current_task();
+    let addrspace = task.addr_space();
+    let mut cpu = UserWritePtr::<u32>::new(cpu, &addrspace);
+    let mut node = UserWritePtr::<u32>::new(node, &addrspace);
+    unsafe {
+        if !cpu.is_null() {
+            cpu.write(current_hart().id as u32)?;
+        }
+        if !node.is_null() {
+            node.write(0)?;
+        }
+    }
+    Ok(0)
+}
+
 #[repr(i32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mempolicy {
@@ -1,5 +1,6 @@
 use core::{iter, time::Duration};
 
+use arch::time::get_time_duration;
 use config::{process::INIT_PROC_ID, sig, vfs::OpenFlags};
 use osfs::{
     fd_table::{FdFlags, FdInfo},
@@ -8,7 +9,8 @@ use osfs::{
 };
 use osfuture::suspend_now;
 use systype::{
-    error::{SysError, SyscallResult},
+    error::{SysError, SysResult, SyscallResult},
+    memory_flags::MappingFlags,
     time::{TimeSpec, TimeValue},
 };
 use timer::{TIMER_MANAGER, Timer};
@@ -19,7 +21,9 @@ use crate::{
     task::{
         Task, TaskState,
         futex::{
-            FutexAddr, FutexHashKey, FutexOp, FutexWaiter, futex_manager, single_futex_manager,
+            FUTEX_BITSET_MATCH_ANY, FUTEX_OWNER_DIED, FUTEX_TID_MASK, FUTEX_WAITERS, FutexAddr,
+            FutexHashKey, FutexManager, FutexOp, FutexWaiter, FutexWaitv, FutexWakeOp,
+            RobustListHead, futex_manager, lock_futex_words,
         },
         manager::TASK_MANAGER,
         sig_members::{
@@ -27,10 +31,16 @@ use crate::{
             SigAction, SigContext, SignalStack,
         },
         signal::{pidfd::PF_TABLE, sig_info::*},
+        tid::Tid,
+    },
+    vm::{
+        addr_space::AddrSpace,
+        user_ptr::{UserReadPtr, UserWritePtr},
     },
-    vm::user_ptr::{UserReadPtr, UserWritePtr},
 };
-use alloc::{boxed::Box, string::String, sync::Arc};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
+
+use super::time::{CLOCK_DEVIATION, CLOCK_MONOTONIC, CLOCK_REALTIME};
 
 /// futex - fast user-space locking
 ///
@@ -105,10 +115,7 @@ pub async fn sys_futex(
     let allop = FutexOp::exstract_futex_flags(futex_op);
     let futex_addr = FutexAddr::new_with_check(uaddr, &addrspace)?;
     let is_private = allop.contains(FutexOp::Private);
-    // For Debug
-    // let is_multi_group = false;
-    // let val3 = 0xffffffff;
-    let is_multi_group = allop.contains(FutexOp::WaitBitset) | allop.contains(FutexOp::WaitBitset);
+    let is_realtime = futex_op & FutexOp::ClockRealtime.bits() != 0;
 
     let key = FutexHashKey::new_key(futex_addr.addr(), addrspace.clone(), is_private)?;
 
@@ -127,59 +134,114 @@ pub async fn sys_futex(
     let op = FutexOp::exstract_main_futex_flags(futex_op);
     match op {
         FutexOp::WaitBitset | FutexOp::Wait => {
-            log::debug!("[sys_futex] Wait Get Locked op: {:?} mask: {:#x}", op, val3);
-            let r = futex_addr.read();
-            if r != val {
-                log::debug!("[sys_futex] r: {:#x} val: {:#x}", r, val);
-                return Err(SysError::EAGAIN);
-            }
-
-            let new_waker = FutexWaiter::new(&task);
-            futex_manager(is_multi_group, val3).add_waiter(&key, new_waker)?;
-
-            task.set_state(TaskState::Interruptible);
-            let wake_up_signal = !*task.sig_mask_mut();
-            task.set_wake_up_signal(wake_up_signal);
-            if timeout != 0 {
-                let ts = unsafe { UserReadPtr::<TimeSpec>::new(timeout, &addrspace).read() }?;
-                (!ts.is_valid()).then_some(SysError::EINVAL);
-                log::debug!("[sys_futex] Wait for {:?}", ts);
-
-                let rem = task.suspend_timeout(ts.into()).await;
-                let tid = task.tid();
-                rem.is_zero()
-                    .then(|| futex_manager(is_multi_group, val3).rm_waiter(&key, tid));
+            let mask = if op == FutexOp::Wait {
+                FUTEX_BITSET_MATCH_ANY
             } else {
-                log::warn!("[sys_futex] task {} has been suspended", task.get_name());
-                suspend_now().await;
-                log::warn!("[sys_futex] task {} has been woken", task.get_name());
+                val3
+            };
+            if mask == 0 {
+                return Err(SysError::EINVAL);
             }
-
-            if task.sig_manager_mut().has_expect_signals(wake_up_signal) {
-                let _ = futex_manager(is_multi_group, val3).rm_waiter(&key, task.tid());
-                return Err(SysError::EINTR);
-            }
-
-            if task.is_in_state(TaskState::Interruptible) {
-                task.set_state(TaskState::Running);
+            log::debug!("[sys_futex] Wait Get Locked op: {:?} mask: {:#x}", op, mask);
+            // FUTEX_WAIT takes a relative timeout, and FUTEX_WAIT_BITSET an absolute one.
+            let deadline = futex_deadline(timeout, &addrspace, op == FutexOp::Wait, is_realtime)?;
+
+            {
+                // The futex word is checked with the lock of the futex queues held, so
+                // that a wake-up after it changes can't be missed.
+                let mut manager = lock_futex_words(&addrspace, &[(&futex_addr, MappingFlags::R)])?;
+                let r = futex_addr.read();
+                if r != val {
+                    log::debug!("[sys_futex] r: {:#x} val: {:#x}", r, val);
+                    return Err(SysError::EAGAIN);
+                }
+                manager.add_waiter(&key, FutexWaiter::new(&task, mask));
             }
-            Ok(0)
+            futex_wait_keys(&task, &[key], deadline).await.map(|_| 0)
         }
         FutexOp::WakeBitset | FutexOp::Wake => {
+            let mask = if op == FutexOp::Wake {
+                FUTEX_BITSET_MATCH_ANY
+            } else {
+                val3
+            };
+            if mask == 0 {
+                return Err(SysError::EINVAL);
+            }
             log::debug!("[sys_futex] Wake");
-            let n_wake = futex_manager(is_multi_group, val3).wake(&key, val)?;
-            Ok(n_wake)
+            Ok(futex_manager().wake(&key, val as usize, mask))
         }
         FutexOp::Requeue | FutexOp::CmpRequeue => {
-            if op.contains(FutexOp::CmpRequeue) && futex_addr.read() as u32 != val3 {
+            // `timeout` is the max number of waiters to requeue.
+            let new_key = FutexHashKey::new_key(uaddr2, addrspace.clone(), is_private)?;
+            let mut manager = lock_futex_words(&addrspace, &[(&futex_addr, MappingFlags::R)])?;
+            if op == FutexOp::CmpRequeue && futex_addr.read() != val3 {
                 return Err(SysError::EAGAIN);
             }
 
-            let n_wake = single_futex_manager().wake(&key, val)?;
-            let new_key = FutexHashKey::new_key(uaddr2, addrspace.clone(), is_private)?;
-            single_futex_manager().requeue_waiters(key, new_key, timeout)?;
+            let n_wake = manager.wake(&key, val as usize, FUTEX_BITSET_MATCH_ANY);
+            let n_requeue = manager.requeue_waiters(key, new_key, timeout, None);
+            Ok(n_wake + n_requeue)
+        }
+        FutexOp::WakeOp => {
+            // `timeout` is the max number of waiters to wake up on `uaddr2`.
+            let wake_op = FutexWakeOp::decode(val3)?;
+            let futex_addr2 = FutexAddr::new_with_check(uaddr2, &addrspace)?;
+            futex_addr2.check_write(&addrspace)?;
+            let key2 = FutexHashKey::new_key(uaddr2, addrspace.clone(), is_private)?;
+
+            let mut manager = lock_futex_words(&addrspace, &[(&futex_addr2, MappingFlags::W)])?;
+            let old = futex_addr2.fetch_update(|old| wake_op.apply(old));
+            let mut n_wake = manager.wake(&key, val as usize, FUTEX_BITSET_MATCH_ANY);
+            if wake_op.compare(old) {
+                n_wake += manager.wake(&key2, timeout, FUTEX_BITSET_MATCH_ANY);
+            }
             Ok(n_wake)
         }
+        FutexOp::LockPi => {
+            // FUTEX_LOCK_PI takes an absolute timeout on CLOCK_REALTIME.
+            let deadline = futex_deadline(timeout, &addrspace, false, true)?;
+            futex_lock_pi(&task, &futex_addr, key, deadline, false).await
+        }
+        FutexOp::TrylockPi => futex_lock_pi(&task, &futex_addr, key, None, true).await,
+        FutexOp::UnlockPi => futex_unlock_pi(&task, &futex_addr, &key),
+        FutexOp::WaitRequeuePi => {
+            if uaddr == uaddr2 {
+                return Err(SysError::EINVAL);
+            }
+            let deadline = futex_deadline(timeout, &addrspace, false, is_realtime)?;
+            let futex_addr2 = FutexAddr::new_with_check(uaddr2, &addrspace)?;
+            futex_addr2.check_write(&addrspace)?;
+            let key2 = FutexHashKey::new_key(uaddr2, addrspace.clone(), is_private)?;
+
+            {
+                let mut manager = lock_futex_words(&addrspace, &[(&futex_addr, MappingFlags::R)])?;
+                if futex_addr.read() != val {
+                    return Err(SysError::EAGAIN);
+                }
+                manager.add_waiter(&key, FutexWaiter::new(&task, FUTEX_BITSET_MATCH_ANY));
+            }
+            futex_wait_requeue_pi(&task, &futex_addr2, key2, deadline).await
+        }
+        FutexOp::CmpRequeuePi => {
+            // Only the waiter which the PI futex is locked for may be woken up, and
+            // `timeout` is the max number of waiters to requeue.
+            if val != 1 || uaddr == uaddr2 {
+                return Err(SysError::EINVAL);
+            }
+            let futex_addr2 = FutexAddr::new_with_check(uaddr2, &addrspace)?;
+            futex_addr2.check_write(&addrspace)?;
+            let key2 = FutexHashKey::new_key(uaddr2, addrspace.clone(), is_private)?;
+            futex_cmp_requeue_pi(
+                &addrspace,
+                &futex_addr,
+                key,
+                &futex_addr2,
+                key2,
+                timeout,
+                val3,
+            )
+        }
 
         _ => {
             log::error!(
@@ -194,6 +256,398 @@ pub async fn sys_futex(
     }
 }
 
+/// Returns the deadline on the monotonic clock of a futex operation with the user
+/// `timeout`, or `None` if it is null.
+///
+/// `timeout` is relative if `relative` is set, or else absolute on `CLOCK_REALTIME`
+/// if `realtime` is set and on `CLOCK_MONOTONIC` otherwise.
+fn futex_deadline(
+    timeout: usize,
+    addrspace: &AddrSpace,
+    relative: bool,
+    realtime: bool,
+) -> SysResult<Option<Duration>> {
+    if timeout == 0 {
+        return Ok(None);
+    }
+    let ts = unsafe { UserReadPtr::<TimeSpec>::new(timeout, addrspace).read() }?;
+    if !ts.is_valid() {
+        return Err(SysError::EINVAL);
+    }
+    let ts = Duration::from(ts);
+    let deadline = if relative {
+        get_time_duration().saturating_add(ts)
+    } else if realtime {
+        ts.saturating_sub(unsafe { CLOCK_DEVIATION[CLOCK_REALTIME] })
+    } else {
+        ts
+    };
+    Ok(Some(deadline))
+}
+
+/// Sleeps until the task is woken up, interrupted by a signal, or the monotonic
+/// `deadline` passes.
+async fn futex_sleep(task: &Arc<Task>, deadline: Option<Duration>) -> SysResult<()> {
+    task.set_state(TaskState::Interruptible);
+    let wake_up_signal = !*task.sig_mask_mut();
+    task.set_wake_up_signal(wake_up_signal);
+    let timed_out = match deadline {
+        Some(deadline) => {
+            let limit = deadline.saturating_sub(get_time_duration());
+            task.suspend_timeout(limit).await.is_zero()
+        }
+        None => {
+            suspend_now().await;
+            false
+        }
+    };
+    if task.is_in_state(TaskState::Interruptible) {
+        task.set_state(TaskState::Running);
+    }
+
+    if task.sig_manager_mut().has_expect_signals(wake_up_signal) {
+        Err(SysError::EINTR)
+    } else if timed_out {
+        Err(SysError::ETIMEDOUT)
+    } else {
+        Ok(())
+    }
+}
+
+/// Waits until one of the waiters of the task queued on `keys` is woken up, and
+/// returns its index. The other waiters are removed, as are all of them if the wait
+/// is interrupted or times out.
+async fn futex_wait_keys(
+    task: &Arc<Task>,
+    keys: &[FutexHashKey],
+    deadline: Option<Duration>,
+) -> SyscallResult {
+    let tid = task.tid();
+    loop {
+        let result = futex_sleep(task, deadline).await;
+        let mut manager = futex_manager();
+        let woken = keys.iter().position(|key| !manager.is_queued(key, tid));
+        if woken.is_some() || result.is_err() {
+            for key in keys {
+                manager.rm_waiter(key, tid);
+            }
+        }
+        match woken {
+            Some(index) => return Ok(index),
+            None => result?,
+        }
+    }
+}
+
+/// Returns whether the task `tid` which owns a PI futex is alive.
+fn pi_owner_alive(tid: u32) -> bool {
+    TASK_MANAGER
+        .get_task(tid as Tid)
+        .is_some_and(|task| !task.is_in_state(TaskState::Zombie))
+}
+
+/// Removes the waiter of `tid` for the PI futex at `futex_addr`, and clears
+/// `FUTEX_WAITERS` of it if there are no more waiters.
+fn cancel_pi_wait(
+    manager: &mut FutexManager,
+    futex_addr: &FutexAddr,
+    key: &FutexHashKey,
+    tid: Tid,
+) {
+    manager.rm_waiter(key, tid);
+    if !manager.has_waiters(key) {
+        futex_addr.fetch_update(|word| word & !FUTEX_WAITERS);
+    }
+}
+
+/// Locks the PI futex at `futex_addr` for `task`, waiting until it is handed over by
+/// its owner unless `trylock` is set.
+///
+/// A futex whose owner died without unlocking it is taken over with
+/// `FUTEX_OWNER_DIED` set.
+async fn futex_lock_pi(
+    task: &Arc<Task>,
+    futex_addr: &FutexAddr,
+    key: FutexHashKey,
+    deadline: Option<Duration>,
+    trylock: bool,
+) -> SyscallResult {
+    let addrspace = task.addr_space();
+    let futex_word = [(futex_addr, MappingFlags::W)];
+    let tid = task.tid();
+    loop {
+        {
+            let mut manager = lock_futex_words(&addrspace, &futex_word)?;
+            let word = futex_addr.read();
+            let owner = word & FUTEX_TID_MASK;
+            if owner == tid as u32 {
+                return Err(SysError::EDEADLK);
+            }
+            if owner == 0 || !pi_owner_alive(owner) {
+                let mut new = tid as u32 | (word & FUTEX_OWNER_DIED);
+                if owner != 0 {
+                    new |= FUTEX_OWNER_DIED;
+                }
+                if manager.has_waiters(&key) {
+                    new |= FUTEX_WAITERS;
+                }
+                if futex_addr.cmpxchg(word, new).is_ok() {
+                    manager.set_pi_owner(&key, tid);
+                    return Ok(0);
+                }
+                continue;
+            }
+            if trylock {
+                return Err(SysError::EAGAIN);
+            }
+            if futex_addr.cmpxchg(word, word | FUTEX_WAITERS).is_err() {
+                continue;
+            }
+            manager.add_waiter(&key, FutexWaiter::new_pi(task, owner as Tid));
+        }
+
+        // The waiter is dequeued when the futex is handed over to it, or when its
+        // owner exits, after which it retries to lock the futex.
+        loop {
+            let result = futex_sleep(task, deadline).await;
+            let mut manager = match lock_futex_words(&addrspace, &futex_word) {
+                Ok(manager) => manager,
+                Err(e) => {
+                    futex_manager().rm_waiter(&key, tid);
+                    return Err(e);
+                }
+            };
+            if !manager.is_queued(&key, tid) {
+                break;
+            }
+            if let Err(e) = result {
+                cancel_pi_wait(&mut manager, futex_addr, &key, tid);
+                return Err(e);
+            }
+        }
+        if futex_addr.read() & FUTEX_TID_MASK == tid as u32 {
+            return Ok(0);
+        }
+    }
+}
+
+/// Unlocks the PI futex at `futex_addr` owned by `task`, and hands it over to its
+/// first waiter if any.
+fn futex_unlock_pi(task: &Task, futex_addr: &FutexAddr, key: &FutexHashKey) -> SyscallResult {
+    let mut manager = lock_futex_words(&task.addr_space(), &[(futex_addr, MappingFlags::W)])?;
+    if futex_addr.read() & FUTEX_TID_MASK != task.tid() as u32 {
+        return Err(SysError::EPERM);
+    }
+    match manager.pop_waiter(key) {
+        Some(waiter) => {
+            let mut word = waiter.tid as u32;
+            if manager.has_waiters(key) {
+                word |= FUTEX_WAITERS;
+            }
+            futex_addr.store(word);
+            manager.set_pi_owner(key, waiter.tid);
+            log::info!("[futex_unlock_pi] hand over to {}", waiter.tid);
+            waiter.wake();
+        }
+        None => futex_addr.store(0),
+    }
+    Ok(0)
+}
+
+/// Waits for the task to be requeued to the PI futex at `futex_addr2` of `key2`, and
+/// returns when the futex is locked for it.
+///
+/// If the task is woken up before the futex is locked for it, it locks the futex as
+/// by `FUTEX_LOCK_PI`.
+async fn futex_wait_requeue_pi(
+    task: &Arc<Task>,
+    futex_addr2: &FutexAddr,
+    key2: FutexHashKey,
+    deadline: Option<Duration>,
+) -> SyscallResult {
+    let addrspace = task.addr_space();
+    let tid = task.tid();
+    loop {
+        let result = futex_sleep(task, deadline).await;
+        let mut manager = match lock_futex_words(&addrspace, &[(futex_addr2, MappingFlags::W)]) {
+            Ok(manager) => manager,
+            Err(e) => {
+                let mut manager = futex_manager();
+                if let Some(key) = manager.find_waiter(tid) {
+                    manager.rm_waiter(&key, tid);
+                }
+                return Err(e);
+            }
+        };
+        let Some(key) = manager.find_waiter(tid) else {
+            break;
+        };
+        if let Err(e) = result {
+            if key == key2 {
+                cancel_pi_wait(&mut manager, futex_addr2, &key2, tid);
+            } else {
+                manager.rm_waiter(&key, tid);
+            }
+            return Err(e);
+        }
+    }
+    if futex_addr2.read() & FUTEX_TID_MASK == tid as u32 {
+        return Ok(0);
+    }
+    futex_lock_pi(task, futex_addr2, key2, deadline, false).await
+}
+
+/// Requeues at most `n_requeue` waiters of `FUTEX_WAIT_REQUEUE_PI` on the futex of
+/// `key` to the PI futex at `futex_addr2` of `key2`, after locking it for the first
+/// waiter and waking it up if the futex is free. Returns the number of woken and
+/// requeued waiters.
+///
+/// Returns `EAGAIN` if the futex word at `futex_addr` is not `val3`.
+fn futex_cmp_requeue_pi(
+    addrspace: &AddrSpace,
+    futex_addr: &FutexAddr,
+    key: FutexHashKey,
+    futex_addr2: &FutexAddr,
+    key2: FutexHashKey,
+    n_requeue: usize,
+    val3: u32,
+) -> SyscallResult {
+    let mut manager = lock_futex_words(addrspace, &[
+        (futex_addr, MappingFlags::R),
+        (futex_addr2, MappingFlags::W),
+    ])?;
+    if futex_addr.read() != val3 {
+        return Err(SysError::EAGAIN);
+    }
+
+    let mut n_wake = 0;
+    loop {
+        let word = futex_addr2.read();
+        let owner = word & FUTEX_TID_MASK;
+        if owner != 0 && pi_owner_alive(owner) {
+            break;
+        }
+        let Some(tid) = manager.first_waiter(&key) else {
+            break;
+        };
+        let mut new = tid as u32 | (word & FUTEX_OWNER_DIED);
+        if owner != 0 {
+            new |= FUTEX_OWNER_DIED;
+        }
+        if manager.has_waiters(&key2) || n_requeue > 0 {
+            new |= FUTEX_WAITERS;
+        }
+        if futex_addr2.cmpxchg(word, new).is_ok() {
+            manager.pop_waiter(&key).unwrap().wake();
+            manager.set_pi_owner(&key2, tid);
+            n_wake = 1;
+            break;
+        }
+    }
+
+    let owner = futex_addr2.read() & FUTEX_TID_MASK;
+    let n_requeued = manager.requeue_waiters(key, key2, n_requeue, Some(owner as Tid));
+    if n_requeued > 0 {
+        futex_addr2.fetch_update(|word| word | FUTEX_WAITERS);
+    }
+    Ok(n_wake + n_requeued)
+}
+
+/// Waits on several futexes at once, until one of them is woken up, and returns its
+/// index in `waiters`, an array of `nr_futexes` [`FutexWaitv`]s.
+///
+/// `timeout` is absolute on the clock `clockid`, which must be `CLOCK_MONOTONIC` or
+/// `CLOCK_REALTIME`. `flags` must be 0.
+pub async fn sys_futex_waitv(
+    waiters: usize,
+    nr_futexes: u32,
+    flags: u32,
+    timeout: usize,
+    clockid: usize,
+) -> SyscallResult {
+    let task = current_task();
+    let addrspace = task.addr_space();
+    let nr_futexes = nr_futexes as usize;
+    if flags != 0 || nr_futexes == 0 || nr_futexes > FutexWaitv::MAX {
+        return Err(SysError::EINVAL);
+    }
+    let deadline = match clockid {
+        CLOCK_REALTIME | CLOCK_MONOTONIC => {
+            futex_deadline(timeout, &addrspace, false, clockid == CLOCK_REALTIME)?
+        }
+        _ if timeout == 0 => None,
+        _ => return Err(SysError::EINVAL),
+    };
+
+    let waitv =
+        unsafe { UserReadPtr::<FutexWaitv>::new(waiters, &addrspace).read_array(nr_futexes)? };
+    let mut futexes = Vec::with_capacity(nr_futexes);
+    let mut keys = Vec::with_capacity(nr_futexes);
+    for waiter in waitv.iter() {
+        waiter.check()?;
+        let futex_addr = FutexAddr::new_with_check(waiter.uaddr as usize, &addrspace)?;
+        let is_private = waiter.flags & FutexWaitv::PRIVATE != 0;
+        keys.push(FutexHashKey::new_key(
+            futex_addr.addr(),
+            addrspace.clone(),
+            is_private,
+        )?);
+        futexes.push(futex_addr);
+    }
+    log::info!("[sys_futex_waitv] {} waits on {:?}", task.get_name(), keys);
+
+    {
+        let futex_words: Vec<_> = futexes
+            .iter()
+            .map(|futex_addr| (futex_addr, MappingFlags::R))
+            .collect();
+        let mut manager = lock_futex_words(&addrspace, &futex_words)?;
+        if futexes
+            .iter()
+            .zip(waitv.iter())
+            .any(|(futex_addr, waiter)| futex_addr.read() as u64 != waiter.val)
+        {
+            return Err(SysError::EAGAIN);
+        }
+        for key in keys.iter() {
+            manager.add_waiter(key, FutexWaiter::new(&task, FUTEX_BITSET_MATCH_ANY));
+        }
+    }
+    futex_wait_keys(&task, &keys, deadline).await
+}
+
+/// Sets the head of the list of robust futexes held by the calling thread.
+///
+/// When mutex with attr `PTHREAD_MUTEX_ROBUST` is used, the kernel trace the mutex
+/// with this syscall and mark the mutex state as `FUTEX_OWNER_DIED` as the thread
+/// dies due to exception.
+pub fn sys_set_robust_list(robust_list_head: usize, len: usize) -> SyscallResult {
+    if len != size_of::<RobustListHead>() {
+        return Err(SysError::EINVAL);
+    }
+    current_task().set_robust_list(robust_list_head);
+    Ok(0)
+}
+
+/// Gets the head of the list of robust futexes of the thread `pid`, or of the calling
+/// thread if `pid` is 0. The calling thread must be allowed to access `pid` as a
+/// tracer, or else `EPERM` is returned.
+pub fn sys_get_robust_list(pid: i32, robust_list_head: usize, len_ptr: usize) -> SyscallResult {
+    let task = current_task();
+    let target = match pid {
+        0 => task.clone(),
+        pid if pid > 0 => TASK_MANAGER.get_task(pid as Tid).ok_or(SysError::ESRCH)?,
+        _ => return Err(SysError::ESRCH),
+    };
+    task.check_ptrace_access(&target)?;
+    let addrspace = task.addr_space();
+    unsafe {
+        UserWritePtr::<usize>::new(robust_list_head, &addrspace).write(target.robust_list())?;
+        UserWritePtr::<usize>::new(len_ptr, &addrspace).write(size_of::<RobustListHead>())?;
+    }
+    Ok(0)
+}
+
 /// - if pid > 0, send a SigInfo built on sig_code to the process with pid
 /// - If pid = -1, then sig is sent to every process for which the calling
 ///   process has permission to send signals, except for process 1 (init)
@@ -297,6 +751,7 @@ pub fn sys_kill(pid: isize, sig_code: i32) -> SyscallResult {
     }
 
     // Send the signal.
+    let mut result = Ok(0);
     for process in processes_to_kill {
         process.with_thread_group(|tg| {
             for thread in tg.iter() {
@@ -306,7 +761,7 @@ pub fn sys_kill(pid: isize, sig_code: i32) -> SyscallResult {
                     thread.tid(),
                     process.pid(),
                 );
-                thread.receive_siginfo(SigInfo {
+                let queued = thread.queue_siginfo(SigInfo {
                     sig,
                     code: SigInfo::USER,
                     details: SigDetails::Kill {
@@ -314,10 +769,13 @@ pub fn sys_kill(pid: isize, sig_code: i32) -> SyscallResult {
                         siginfo: None,
                     },
                 });
+                if let Err(e) = queued {
+                    result = Err(e);
+                }
             }
         });
     }
-    Ok(0)
+    result
 }
 
 /// set a new action(including ignore) for current task and save the previous
@@ -420,9 +878,9 @@ pub async fn sys_sigreturn() -> SyscallResult {
     let task = current_task();
     let trap_cx = task.trap_context_mut();
     let mask = task.sig_mask_mut();
-    let sig_cx_ptr = task.get_sig_cx_ptr();
+    let sig_cx_addr = task.get_sig_cx_ptr();
     let addr_space = task.addr_space();
-    let mut sig_cx_ptr = UserReadPtr::<SigContext>::new(sig_cx_ptr, &addr_space);
+    let mut sig_cx_ptr = UserReadPtr::<SigContext>::new(sig_cx_addr, &addr_space);
     // restore trap context before sig handle
 
     let mut rs = String::new();
@@ -439,8 +897,19 @@ pub async fn sys_sigreturn() -> SyscallResult {
         let sig_stack = task.sig_stack_mut();
         sig_stack.ss_flags &= !SS_ONSTACK;
 
-        trap_cx.sepc = sig_cx.user_reg[0];
+        #[cfg(target_arch = "riscv64")]
+        {
+            trap_cx.sepc = sig_cx.user_reg[0];
+        }
+        #[cfg(target_arch = "loongarch64")]
+        {
+            trap_cx.sepc = sig_cx.pc;
+        }
         trap_cx.user_reg = sig_cx.user_reg;
+        trap_cx.set_fx(sig_cx.fpstate.to_regs());
+        if let Some(vregs) = sig_cx.read_vector_ext(sig_cx_addr, &addr_space)? {
+            trap_cx.set_vx(vregs);
+        }
     }
     let mut rs = String::new();
     trap_cx
@@ -631,14 +1100,14 @@ pub fn sys_tgkill(tgid: isize, tid: isize, signum: i32) -> SyscallResult {
         for thread in tg.iter() {
             if thread.tid() == tid as usize {
                 log::debug!("thread [{}] recv sig {:?}", thread.get_name(), sig);
-                thread.receive_siginfo(SigInfo {
+                thread.queue_siginfo(SigInfo {
                     sig,
                     code: SigInfo::TKILL,
                     details: SigDetails::Kill {
                         pid: task.pid(),
                         siginfo: None,
                     },
-                });
+                })?;
                 return Ok(0);
             }
         }
@@ -658,14 +1127,14 @@ pub fn sys_tkill(tid: isize, sig: i32) -> SyscallResult {
     }
 
     let task = TASK_MANAGER.get_task(tid as usize).ok_or(SysError::ESRCH)?;
-    task.receive_siginfo(SigInfo {
+    task.queue_siginfo(SigInfo {
         sig,
         code: SigInfo::TKILL,
         details: SigDetails::Kill {
             pid: task.pid(),
             siginfo: None,
         },
-    });
+    })?;
     Ok(0)
 }
 
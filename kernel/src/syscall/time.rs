@@ -4,21 +4,28 @@ use core::time::Duration;
 use mutex::SpinNoIrqLock;
 use osfs::special::timerfd::{file::TimerFdFile, flag::TimerFdFlags};
 
-use arch::time::{get_time_duration, get_time_ms, get_time_us};
+use arch::time::{get_time_duration, get_time_ms};
 use osfuture::{Select2Futures, SelectOutput, yield_now};
 use systype::{
-    error::{SysError, SyscallResult},
+    error::{SysError, SysResult, SyscallResult},
     time::{ITimerVal, TMS, TimeSpec, TimeVal, TimeValue},
 };
-use timer::{TIMER_MANAGER, Timer, TimerState};
+use timer::{TIMER_MANAGER, Timer};
 
 use crate::{
     processor::current_task,
     task::{
-        TaskState, sig_members::IntrBySignalFuture, signal::sig_exec::SigEvent, time::RealITimer,
+        Task, TaskState,
+        manager::TASK_MANAGER,
+        sig_members::IntrBySignalFuture,
+        signal::sig_info::Sig,
+        time::{PosixTimer, PosixTimerEvent, RealITimer, TimerClock, TimerNotify},
         timeid::timeid_alloc,
     },
-    vm::user_ptr::{UserReadPtr, UserWritePtr},
+    vm::{
+        user_ptr::{UserReadPtr, UserWritePtr},
+        vdso,
+    },
 };
 
 /// `gettimeofday()` get the time as well as a timezone.
@@ -46,7 +53,8 @@ pub async fn sys_gettimeofday(tv: usize, _tz: usize) -> SyscallResult {
     let mut tv_ptr = UserWritePtr::<TimeVal>::new(tv, &addr_space);
     if !tv_ptr.is_null() {
         unsafe {
-            tv_ptr.write(TimeVal::from_usec(get_time_us()))?;
+            let now = get_time_duration() + CLOCK_DEVIATION[CLOCK_REALTIME];
+            tv_ptr.write(TimeVal::from_usec(now.as_micros() as usize))?;
         }
     }
     Ok(0)
@@ -173,8 +181,10 @@ pub const CLOCK_THREAD_CPUTIME_ID: usize = 3;
 pub const CLOCK_MONOTONIC_RAW: usize = 4;
 /// `CLOCK_REALTIME_COARSE` is Rough version of the system clock.
 pub const CLOCK_REALTIME_COARSE: usize = 5;
-pub const CLOCK_BOOTTIME: usize = 6;
-pub const CLOCK_REALTIME_ALARM: usize = 7;
+/// `CLOCK_MONOTONIC_COARSE` is Rough version of the monotonic clock.
+pub const CLOCK_MONOTONIC_COARSE: usize = 6;
+pub const CLOCK_BOOTTIME: usize = 7;
+pub const CLOCK_REALTIME_ALARM: usize = 8;
 
 pub static mut CLOCK_DEVIATION: [Duration; SUPPORT_CLOCK] = [Duration::ZERO; SUPPORT_CLOCK];
 
@@ -189,6 +199,9 @@ pub static mut CLOCK_DEVIATION: [Duration; SUPPORT_CLOCK] = [Duration::ZERO; SUP
 /// - 3 = `CLOCK_THREAD_CPUTIME_ID`: The CPU time consumed by the calling thread.
 /// - 4 = `CLOCK_MONOTONIC_RAW`: The original value of the monotonic clock is not affected by ntp or adjustments.
 /// - 5 = `CLOCK_REALTIME_COARSE`: Rough version of the system clock.
+/// - 6 = `CLOCK_MONOTONIC_COARSE`: Rough version of the monotonic clock.
+/// - 7 = `CLOCK_BOOTTIME`: Like `CLOCK_MONOTONIC`, but also counts time spent suspended.
+/// - 8 = `CLOCK_REALTIME_ALARM`: Like `CLOCK_REALTIME`, but may wake the system up.
 pub fn sys_clock_gettime(clockid: usize, tp: usize) -> SyscallResult {
     let task = current_task();
     let tid = task.tid();
@@ -201,7 +214,7 @@ pub fn sys_clock_gettime(clockid: usize, tp: usize) -> SyscallResult {
     // }
 
     match clockid {
-        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_REALTIME_COARSE => {
+        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_REALTIME_COARSE | CLOCK_MONOTONIC_COARSE => {
             let current = get_time_duration();
             // let current = get_time_duration() * 4;
             unsafe {
@@ -259,8 +272,6 @@ pub async fn sys_clock_nanosleep(
     let mut t = UserReadPtr::<TimeSpec>::new(t, &addrspace);
     let mut rem = UserWritePtr::<TimeSpec>::new(rem, &addrspace);
 
-    /// for clock_nanosleep
-    pub const TIMER_ABSTIME: usize = 1;
     match clockid {
         CLOCK_REALTIME | CLOCK_MONOTONIC => {
             let ts = unsafe { t.read()? };
@@ -517,7 +528,8 @@ pub fn sys_clock_getres(_clockid: usize, res: usize) -> SyscallResult {
     let mut resptr = UserWritePtr::<TimeSpec>::new(res, &addrspace);
     unsafe {
         if !resptr.is_null() {
-            resptr.write(Duration::from_nanos(1).into())?;
+            // All clocks are read from the timer counter in microseconds.
+            resptr.write(Duration::from_micros(1).into())?;
         }
     }
     Ok(0)
@@ -772,6 +784,7 @@ pub fn sys_clock_settime(clockid: usize, tp: usize) -> SyscallResult {
             // }
             unsafe {
                 CLOCK_DEVIATION[clockid] = Duration::from(tp).saturating_sub(get_time_duration());
+                vdso::set_clock_offset(clockid, CLOCK_DEVIATION[clockid]);
             }
         }
         _ => {
@@ -782,25 +795,110 @@ pub fn sys_clock_settime(clockid: usize, tp: usize) -> SyscallResult {
     Ok(0)
 }
 
+pub const CLOCK_BOOTTIME_ALARM: usize = 9;
+
 pub const SIGEV_NONE: i32 = 0;
 pub const SIGEV_SIGNAL: i32 = 1;
 pub const SIGEV_THREAD: i32 = 2;
+pub const SIGEV_THREAD_ID: i32 = 4;
+
+/// for clock_nanosleep and timer_settime
+pub const TIMER_ABSTIME: usize = 1;
 
+/// The `struct sigevent` passed to `timer_create()`.
+/// ```c
+/// struct sigevent {
+///     union sigval sigev_value;
+///     int sigev_signo;
+///     int sigev_notify;
+///     union {
+///         int _pad[SIGEV_PAD_SIZE];
+///         int _tid;
+///         ...
+///     } _sigev_un;
+/// };
+/// ```
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct Sigevent {
-    pub sigev_notify: i32,
-    pub sigev_signo: i32,
     pub sigev_value: usize,
+    pub sigev_signo: i32,
+    pub sigev_notify: i32,
+    pub sigev_notify_thread_id: i32,
 }
 
-pub fn sys_timer_create(clockid: usize, sevp_ptr: usize, timerid_ptr: usize) -> SyscallResult {
-    if clockid != CLOCK_REALTIME && clockid != CLOCK_MONOTONIC {
-        return Err(SysError::EINVAL);
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Itimerspec {
+    pub it_interval: TimeSpec,
+    pub it_value: TimeSpec,
+}
+
+/// Maps a clockid accepted by `timer_create()` to the clock the timer counts on.
+fn timer_clock(clockid: usize) -> SysResult<TimerClock> {
+    match clockid {
+        CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME | CLOCK_REALTIME_ALARM
+        | CLOCK_BOOTTIME_ALARM => Ok(TimerClock::Wall),
+        CLOCK_PROCESS_CPUTIME_ID => Ok(TimerClock::ProcessCpu),
+        CLOCK_THREAD_CPUTIME_ID => Ok(TimerClock::ThreadCpu),
+        _ => {
+            log::error!("[timer_clock] unsupported clockid {}", clockid);
+            Err(SysError::EINVAL)
+        }
     }
+}
+
+/// Returns the current time of the clock `timer` counts on.
+fn timer_clock_now(task: &Arc<Task>, timer: &PosixTimer) -> Duration {
+    match timer.clock {
+        TimerClock::Wall => get_time_duration(),
+        TimerClock::ProcessCpu => task.get_process_cputime(),
+        TimerClock::ThreadCpu => TASK_MANAGER
+            .get_task(timer.owner)
+            .map_or(Duration::ZERO, |t| t.timer_mut().cpu_time()),
+    }
+}
+
+/// Returns the task that receives the signals of `timer`.
+fn timer_target(process: &Arc<Task>, timer: &PosixTimer) -> Option<Arc<Task>> {
+    match timer.notify {
+        TimerNotify::Thread(tid) => TASK_MANAGER.get_task(tid),
+        _ => Some(process.clone()),
+    }
+}
+
+fn timer_current_value(task: &Arc<Task>, timer: &PosixTimer) -> Itimerspec {
+    let it_value = if timer.is_armed() {
+        timer
+            .next_expire
+            .saturating_sub(timer_clock_now(task, timer))
+            .max(Duration::from_nanos(1))
+    } else {
+        Duration::ZERO
+    };
+    Itimerspec {
+        it_interval: timer.interval.into(),
+        it_value: it_value.into(),
+    }
+}
+
+/// `timer_create()` creates a new per-process interval timer. The ID of the new
+/// timer is returned in the buffer pointed to by `timerid`.
+///
+/// The clockid argument specifies the clock that the new timer uses to measure
+/// time. `CLOCK_REALTIME`, `CLOCK_MONOTONIC`, `CLOCK_BOOTTIME` and the alarm
+/// clocks count wall time, `CLOCK_PROCESS_CPUTIME_ID` and `CLOCK_THREAD_CPUTIME_ID`
+/// count CPU time consumed by the process or the calling thread.
+///
+/// The sevp argument points to a `sigevent` structure that specifies how the caller
+/// should be notified when the timer expires. If sevp is NULL, it is treated as
+/// `SIGEV_SIGNAL` with `SIGALRM` and the timer ID as `sigev_value`.
+pub fn sys_timer_create(clockid: usize, sevp_ptr: usize, timerid_ptr: usize) -> SyscallResult {
+    let clock = timer_clock(clockid)?;
 
     let task = current_task();
     let addr_space = task.addr_space();
+    let process = task.process();
 
     let sevp = if sevp_ptr != 0 {
         let mut sevp_ptr = UserReadPtr::<Sigevent>::new(sevp_ptr, &addr_space);
@@ -809,75 +907,206 @@ pub fn sys_timer_create(clockid: usize, sevp_ptr: usize, timerid_ptr: usize) ->
         None
     };
 
-    let lock = task.timers_mut();
+    let lock = process.timers_mut();
     let mut timers = lock.lock();
-    let mut timer = Timer::new(Duration::from_secs(u64::MAX));
-    timer.signal = sevp.and_then(|sev| {
-        if sev.sigev_notify == SIGEV_SIGNAL {
-            timer.set_callback(Arc::new(SigEvent {
-                tid: task.tid(),
-                sig: sev.sigev_signo,
-            }));
-            Some((sev.sigev_signo, sev.sigev_value))
-        } else {
-            None
+    let id = timers
+        .iter()
+        .position(|t| t.is_none())
+        .unwrap_or(timers.len());
+
+    let (notify, signo, value) = match sevp {
+        None => (TimerNotify::Signal, Sig::SIGALRM.raw() as i32, id),
+        Some(sev) => {
+            let notify = match sev.sigev_notify {
+                SIGEV_NONE => TimerNotify::None,
+                SIGEV_SIGNAL | SIGEV_THREAD => TimerNotify::Signal,
+                n if n == SIGEV_SIGNAL | SIGEV_THREAD_ID => {
+                    let tid = sev.sigev_notify_thread_id as usize;
+                    match TASK_MANAGER.get_task(tid) {
+                        Some(t) if t.pid() == process.pid() => TimerNotify::Thread(tid),
+                        _ => return Err(SysError::EINVAL),
+                    }
+                }
+                _ => return Err(SysError::EINVAL),
+            };
+            if notify != TimerNotify::None
+                && (sev.sigev_signo <= 0 || sev.sigev_signo > Sig::SIGRTMAX.raw() as i32)
+            {
+                return Err(SysError::EINVAL);
+            }
+            (notify, sev.sigev_signo, sev.sigev_value)
         }
-    });
-
-    let id = timers.iter().position(|t| t.is_none()).unwrap_or_else(|| {
-        timers.push(None);
-        timers.len() - 1
-    });
-    timers[id] = Some(timer);
+    };
 
     let mut id_ptr = UserWritePtr::<i32>::new(timerid_ptr, &addr_space);
     unsafe {
         id_ptr.write(id as i32)?;
     }
 
+    let timer = PosixTimer::new(clock, clockid, notify, signo, value, task.tid());
+    log::info!("[sys_timer_create] create timer {id}: {timer:?}");
+    if id == timers.len() {
+        timers.push(Some(timer));
+    } else {
+        timers[id] = Some(timer);
+    }
+
     Ok(0)
 }
 
+/// `timer_settime()` arms or disarms the timer identified by `timerid`.
+///
+/// If `new_value->it_value` is nonzero, the timer is armed to expire after that
+/// time, or at that absolute time on the timer's clock if `TIMER_ABSTIME` is set
+/// in flags. A zero `it_value` disarms the timer. `it_interval` is the period for
+/// following expirations. If `old_value` is not NULL, the previous setting is
+/// returned in it, as `timer_gettime()` does.
 pub fn sys_timer_settime(
     timerid: usize,
     flags: usize,
     new_value_ptr: usize,
     old_value_ptr: usize,
 ) -> SyscallResult {
-    #[repr(C)]
-    #[derive(Debug, Clone, Copy)]
-    pub struct Itimerspec {
-        pub it_interval: TimeSpec,
-        pub it_value: TimeSpec,
-    }
-
     let task = current_task();
     let addr_space = task.addr_space();
+    let process = task.process();
+
+    let mut new_value = UserReadPtr::<Itimerspec>::new(new_value_ptr, &addr_space);
+    let new_value = unsafe { new_value.read()? };
+    if !new_value.it_value.is_valid() || !new_value.it_interval.is_valid() {
+        return Err(SysError::EINVAL);
+    }
 
-    let lock = task.timers_mut();
+    let lock = process.timers_mut();
     let mut timers = lock.lock();
     let timer = timers
         .get_mut(timerid)
         .and_then(|t| t.as_mut())
         .ok_or(SysError::EINVAL)?;
 
-    let mut new_value = UserReadPtr::<Itimerspec>::new(new_value_ptr, &addr_space);
-    let new_value = unsafe { new_value.read()? };
+    let old_value = timer_current_value(&process, timer);
 
-    let now = get_time_duration();
-    let first = Duration::from(new_value.it_value);
+    let now = timer_clock_now(&process, timer);
+    let value = Duration::from(new_value.it_value);
     let interval = Duration::from(new_value.it_interval);
 
-    timer.expire = now + first;
-    timer.periodic = interval != Duration::ZERO;
-    timer.period = if timer.periodic { Some(interval) } else { None };
-    timer.state = TimerState::Active;
+    timer.id = timeid_alloc().0;
+    timer.overrun = 0;
+    timer.last_overrun = 0;
+    timer.interval = interval;
+    timer.next_expire = if value.is_zero() {
+        Duration::ZERO
+    } else if flags & TIMER_ABSTIME != 0 {
+        // Absolute CLOCK_REALTIME values are converted to the monotonic clock
+        // the timers are driven by. Expirations in the past fire at once.
+        let value = match timer.clockid {
+            CLOCK_REALTIME | CLOCK_REALTIME_ALARM => {
+                value.saturating_sub(unsafe { CLOCK_DEVIATION[CLOCK_REALTIME] })
+            }
+            _ => value,
+        };
+        value.max(now).max(Duration::from_nanos(1))
+    } else {
+        now.checked_add(value).unwrap_or(Duration::MAX)
+    };
+
+    log::info!(
+        "[sys_timer_settime] timer {timerid}: expire {:?}, interval {:?}",
+        timer.next_expire,
+        timer.interval
+    );
+
+    let wall_timer = (timer.clock == TimerClock::Wall && timer.is_armed()).then(|| {
+        let mut t = Timer::new(timer.next_expire);
+        t.periodic = !interval.is_zero();
+        t.period = t.periodic.then_some(interval);
+        t.set_callback(Arc::new(PosixTimerEvent {
+            task: Arc::downgrade(&process),
+            timerid,
+            id: timer.id,
+        }));
+        t
+    });
+    // `TIMER_MANAGER` calls back into the timer table with its own lock held,
+    // so the table must be released before adding the timer.
+    drop(timers);
+    if let Some(t) = wall_timer {
+        TIMER_MANAGER.add_timer(t);
+    }
 
-    TIMER_MANAGER.add_timer(timer.clone());
+    let mut old_value_ptr = UserWritePtr::<Itimerspec>::new(old_value_ptr, &addr_space);
+    if !old_value_ptr.is_null() {
+        unsafe { old_value_ptr.write(old_value)? };
+    }
 
     Ok(0)
 }
 
+/// `timer_gettime()` returns the time until next expiration, and the interval,
+/// for the timer specified by `timerid`, in the buffer pointed to by `curr_value`.
+///
+/// If the value returned in `curr_value->it_value` is zero, then the timer is
+/// currently disarmed.
+pub fn sys_timer_gettime(timerid: usize, curr_value: usize) -> SyscallResult {
+    let task = current_task();
+    let addr_space = task.addr_space();
+    let process = task.process();
+
+    let curr = {
+        let lock = process.timers_mut();
+        let timers = lock.lock();
+        let timer = timers
+            .get(timerid)
+            .and_then(|t| t.as_ref())
+            .ok_or(SysError::EINVAL)?;
+        timer_current_value(&process, timer)
+    };
+
+    let mut curr_ptr = UserWritePtr::<Itimerspec>::new(curr_value, &addr_space);
+    unsafe { curr_ptr.write(curr)? };
+    Ok(0)
+}
+
+/// `timer_getoverrun()` returns the overrun count for the timer referred to by
+/// `timerid`, i.e. the number of extra expirations that occurred between the time
+/// a signal was generated and the time it was delivered.
+pub fn sys_timer_getoverrun(timerid: usize) -> SyscallResult {
+    let task = current_task();
+    let process = task.process();
+
+    let lock = process.timers_mut();
+    let timers = lock.lock();
+    let timer = timers
+        .get(timerid)
+        .and_then(|t| t.as_ref())
+        .ok_or(SysError::EINVAL)?;
+
+    let pending = timer.notify != TimerNotify::None
+        && timer_target(&process, timer).is_some_and(|t| {
+            t.sig_manager_mut()
+                .bitmap
+                .contain_signal(Sig::from_i32(timer.signo))
+        });
+    Ok(timer.overrun(pending))
+}
+
+/// `timer_delete()` deletes the timer whose ID is given in `timerid`. If the timer
+/// was armed at the time of this call, it is disarmed before being deleted.
+pub fn sys_timer_delete(timerid: usize) -> SyscallResult {
+    let task = current_task();
+    let process = task.process();
+
+    let lock = process.timers_mut();
+    let mut timers = lock.lock();
+    let timer = timers
+        .get_mut(timerid)
+        .filter(|t| t.is_some())
+        .ok_or(SysError::EINVAL)?;
+    // Stale `PosixTimerEvent`s find an empty slot and cancel themselves.
+    *timer = None;
+    Ok(0)
+}
+
 pub async fn sys_timerfd_create(clockid: usize, flags: u32) -> SyscallResult {
     if clockid != CLOCK_REALTIME && clockid != CLOCK_MONOTONIC {
         return Err(SysError::EINVAL);
@@ -1,4 +1,4 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
 use bitflags::bitflags;
 use core::{
     cmp::min,
@@ -11,16 +11,33 @@ use mutex::SpinNoIrqLock;
 use spin::Lazy;
 
 use mm::address::{PhysAddr, VirtAddr};
-use systype::error::{SysError, SysResult, SyscallResult};
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MappingFlags,
+};
 
 use crate::{
-    task::{Task, tid::Tid},
+    task::{Task, manager::TASK_MANAGER, tid::Tid},
+    trap::trap_env::{set_kernel_trap_entry, set_user_rw_trap_entry},
     vm::{
         addr_space::AddrSpace,
-        user_ptr::{SumGuard, UserReadPtr},
+        user_ptr::{SumGuard, UserReadPtr, UserWritePtr, try_read, try_write},
     },
 };
 
+/// Bit of a PI or robust futex word set if there are tasks waiting for it.
+pub const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// Bit of a PI or robust futex word set if its owner died without unlocking it.
+pub const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// Bits of a PI or robust futex word which hold the TID of its owner.
+pub const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+/// Bitset of a waiter or a wake-up which matches any other.
+pub const FUTEX_BITSET_MATCH_ANY: u32 = 0xffff_ffff;
+
+/// Max number of entries of the robust list walked when a thread exits, which
+/// protects against circular lists.
+const ROBUST_LIST_LIMIT: usize = 2048;
+
 pub struct FutexAddr {
     pub addr: VirtAddr,
     _guard: SumGuard,
@@ -28,6 +45,9 @@ pub struct FutexAddr {
 
 impl FutexAddr {
     pub fn new_with_check(addr: usize, addrspace: &AddrSpace) -> SysResult<Self> {
+        if addr % size_of::<u32>() != 0 {
+            return Err(SysError::EINVAL);
+        }
         let futexaddr = Self {
             addr: VirtAddr::new(addr),
             _guard: SumGuard::new(),
@@ -42,146 +62,292 @@ impl FutexAddr {
 
     pub fn check(&self, addrspace: &AddrSpace) -> SysResult<()> {
         unsafe {
-            UserReadPtr::<VirtAddr>::new(self.addr.to_usize(), addrspace).read()?;
+            UserReadPtr::<u32>::new(self.addr.to_usize(), addrspace).read()?;
         }
         Ok(())
     }
 
-    pub fn read(&self) -> u32 {
+    /// Checks that the futex word is writable, which the atomic operations which
+    /// modify it need.
+    pub fn check_write(&self, addrspace: &AddrSpace) -> SysResult<()> {
         unsafe {
-            let ptr = self.addr.to_usize() as *const AtomicU32;
-            (*ptr).load(Ordering::Acquire)
+            UserWritePtr::<u32>::new(self.addr.to_usize(), addrspace).try_into_mut_ref()?;
         }
+        Ok(())
+    }
+
+    /// Returns whether the futex word can be accessed with `perm`, which is `R` or
+    /// `W`, without a page fault.
+    fn is_accessible(&self, perm: MappingFlags) -> bool {
+        set_user_rw_trap_entry();
+        let accessible = unsafe {
+            if perm.contains(MappingFlags::W) {
+                try_write(self.addr.to_usize())
+            } else {
+                try_read(self.addr.to_usize())
+            }
+        };
+        set_kernel_trap_entry();
+        accessible
+    }
+
+    fn atomic(&self) -> &AtomicU32 {
+        unsafe { &*(self.addr.to_usize() as *const AtomicU32) }
     }
+
+    pub fn read(&self) -> u32 {
+        self.atomic().load(Ordering::Acquire)
+    }
+
+    pub fn store(&self, val: u32) {
+        self.atomic().store(val, Ordering::Release);
+    }
+
+    /// Sets the futex word to `new` if it is `old`. Returns the previous value, as
+    /// `Err` if it was not `old`.
+    pub fn cmpxchg(&self, old: u32, new: u32) -> Result<u32, u32> {
+        self.atomic()
+            .compare_exchange(old, new, Ordering::AcqRel, Ordering::Acquire)
+    }
+
+    /// Atomically sets the futex word to `f` of it, and returns its previous value.
+    pub fn fetch_update(&self, mut f: impl FnMut(u32) -> u32) -> u32 {
+        self.atomic()
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |val| Some(f(val)))
+            .unwrap()
+    }
+}
+
+pub static FUTEX_MANAGER: Lazy<SpinNoIrqLock<FutexManager>> =
+    Lazy::new(|| SpinNoIrqLock::new(FutexManager::new()));
+
+pub fn futex_manager() -> impl DerefMut<Target = FutexManager> {
+    FUTEX_MANAGER.lock()
 }
 
-pub static FUTEX_MANAGER: Lazy<Vec<SpinNoIrqLock<FutexManager>>> = Lazy::new(|| {
-    // driver::println!("when FUTEX_MANAGER init");
-    let mut v = Vec::new();
-    for i in 0..2 {
-        let fm = SpinNoIrqLock::new(FutexManager::new(i == 1));
-        v.push(fm);
+/// Locks the futex queues, after making sure that each futex word in `futexes` can
+/// be accessed with its permission, which is `R` or `W`, without a page fault.
+///
+/// A page fault must not be handled with the lock held, so a futex word which is not
+/// accessible is faulted in with the lock released, and checked again under the lock.
+///
+/// # Errors
+/// Returns [`SysError::EFAULT`] if a futex word cannot be faulted in.
+pub fn lock_futex_words(
+    addrspace: &AddrSpace,
+    futexes: &[(&FutexAddr, MappingFlags)],
+) -> SysResult<impl DerefMut<Target = FutexManager> + use<>> {
+    loop {
+        let manager = futex_manager();
+        let Some(&(futex_addr, perm)) = futexes
+            .iter()
+            .find(|(futex_addr, perm)| !futex_addr.is_accessible(*perm))
+        else {
+            return Ok(manager);
+        };
+        drop(manager);
+        if perm.contains(MappingFlags::W) {
+            futex_addr.check_write(addrspace)?;
+        } else {
+            futex_addr.check(addrspace)?;
+        }
     }
-    v
-});
+}
+
+/// Marks the task `owner` as boosted by the waiters for the PI futexes it owns, or
+/// as not boosted any more.
+fn set_pi_boosted(owner: Tid, boosted: bool) {
+    if let Some(task) = TASK_MANAGER.get_task(owner) {
+        task.set_pi_boosted(boosted);
+    }
+}
 
-pub fn single_futex_manager() -> impl DerefMut<Target = FutexManager> {
-    FUTEX_MANAGER.first().unwrap().lock()
+/// Counts a waiter for a PI futex in the boosts of its owner.
+fn boost(pi_boosts: &mut HashMap<Tid, usize>, waiter: &FutexWaiter) {
+    if let Some(owner) = waiter.pi_owner {
+        let count = pi_boosts.entry(owner).or_default();
+        *count += 1;
+        if *count == 1 {
+            set_pi_boosted(owner, true);
+        }
+    }
 }
 
-pub fn futex_manager(is_multi_group: bool, val32: u32) -> impl DerefMut<Target = FutexManager> {
-    if !is_multi_group {
-        FUTEX_MANAGER.first().unwrap().lock()
-    } else {
-        let mut r = FUTEX_MANAGER.get(1).unwrap().lock();
-        r.val32 = val32;
-        r
+/// Uncounts a waiter for a PI futex from the boosts of its owner.
+fn unboost(pi_boosts: &mut HashMap<Tid, usize>, waiter: &FutexWaiter) {
+    if let Some(owner) = waiter.pi_owner {
+        if let Some(count) = pi_boosts.get_mut(&owner) {
+            *count -= 1;
+            if *count == 0 {
+                pi_boosts.remove(&owner);
+                set_pi_boosted(owner, false);
+            }
+        }
     }
 }
 
+/// Queues of the tasks waiting on futexes, which are woken up in FIFO order.
+///
+/// A task which owns a PI futex other tasks are waiting for inherits their priority,
+/// which the scheduler grants by not preempting it when its time slice runs out, so
+/// that it releases the futex as soon as possible. The boost is recorded in the task
+/// itself, so that the scheduler checks it without taking the lock of the manager.
+#[derive(Default)]
 pub struct FutexManager {
-    hash: HashMap<FutexHashKey, Vec<FutexWaiter>>,
-    pub(crate) val32: u32,
-    is_mask: bool,
+    hash: HashMap<FutexHashKey, VecDeque<FutexWaiter>>,
+    /// Number of tasks waiting for the PI futexes owned by each task.
+    pi_boosts: HashMap<Tid, usize>,
 }
 
 impl FutexManager {
-    pub fn new(is_mask: bool) -> Self {
+    pub fn new() -> Self {
         Self {
             hash: HashMap::new(),
-            val32: 0,
-            is_mask,
+            pi_boosts: HashMap::new(),
         }
     }
 
-    pub fn add_waiter(&mut self, key: &FutexHashKey, mut waiter: FutexWaiter) -> SysResult<()> {
+    pub fn add_waiter(&mut self, key: &FutexHashKey, waiter: FutexWaiter) {
         log::info!("[futex::add_waiter] {:?} in {:?} ", waiter, key);
-        if self.is_mask {
-            waiter.mask = self.val32;
-        }
+        boost(&mut self.pi_boosts, &waiter);
+        self.hash.entry(*key).or_default().push_back(waiter);
+    }
 
-        if let Some(waiters) = self.hash.get_mut(key) {
-            waiters.push(waiter);
-        } else {
-            let waiters = vec![waiter];
-            self.hash.insert(*key, waiters);
+    /// Removes the waiter of `tid` on `key`. Returns whether it was queued.
+    pub fn rm_waiter(&mut self, key: &FutexHashKey, tid: Tid) -> bool {
+        let Some(waiters) = self.hash.get_mut(key) else {
+            return false;
+        };
+        let Some(index) = waiters.iter().position(|waiter| waiter.tid == tid) else {
+            return false;
+        };
+        let waiter = waiters.remove(index).unwrap();
+        unboost(&mut self.pi_boosts, &waiter);
+        if waiters.is_empty() {
+            self.hash.remove(key);
         }
-        Ok(())
+        true
     }
 
-    pub fn rm_waiter(&mut self, key: &FutexHashKey, tid: Tid) -> SysResult<()> {
-        if let Some(waiters) = self.hash.get_mut(key) {
-            let index = waiters
-                .iter()
-                .enumerate()
-                .find(|(_, waiter)| waiter.tid == tid)
-                .map(|(i, _)| i)
-                .ok_or(SysError::EINVAL)?;
-            waiters.swap_remove(index);
-        }
-        Ok(())
+    /// Returns whether the task `tid` is waiting on `key`.
+    pub fn is_queued(&self, key: &FutexHashKey, tid: Tid) -> bool {
+        self.hash
+            .get(key)
+            .is_some_and(|waiters| waiters.iter().any(|waiter| waiter.tid == tid))
     }
 
-    pub fn wake(&mut self, key: &FutexHashKey, n: u32) -> SyscallResult {
-        // log::info!("[futex::wake] {:?} in {:?} ", n, key);
-        if let Some(waiters) = self.hash.get_mut(key) {
-            let n = min(n as usize, waiters.len());
-            // log::debug!("[futex::wake] waiters: {:?}", waiters);
-            for _ in 0..n {
-                if self.is_mask {
-                    let waiter = waiters.pop().unwrap();
-                    log::info!("[futex_wake] {:?} has been woken masked", waiter);
-                    if (waiter.mask & self.val32) != 0 {
-                        waiter.wake();
-                    } else {
-                        waiters.push(waiter);
-                    }
-                } else {
-                    let waiter = waiters.pop().unwrap();
-                    log::warn!("[futex_wake] {:?} has been woken", waiter);
-                    waiter.wake();
-                }
+    /// Returns the key which the task `tid` is waiting on.
+    pub fn find_waiter(&self, tid: Tid) -> Option<FutexHashKey> {
+        self.hash
+            .iter()
+            .find(|(_, waiters)| waiters.iter().any(|waiter| waiter.tid == tid))
+            .map(|(key, _)| *key)
+    }
 
-                if waiters.is_empty() {
-                    break;
-                }
-            }
-            log::warn!("[futex_wake] {} success", n);
+    pub fn has_waiters(&self, key: &FutexHashKey) -> bool {
+        self.hash.contains_key(key)
+    }
 
-            Ok(n)
-        } else {
-            // log::error!("can not find key {key:?}");
-            Err(SysError::EINVAL)
+    /// Returns the TID of the first waiter on `key`.
+    pub fn first_waiter(&self, key: &FutexHashKey) -> Option<Tid> {
+        self.hash.get(key)?.front().map(|waiter| waiter.tid)
+    }
+
+    /// Removes the first waiter on `key`.
+    pub fn pop_waiter(&mut self, key: &FutexHashKey) -> Option<FutexWaiter> {
+        let waiters = self.hash.get_mut(key)?;
+        let waiter = waiters.pop_front()?;
+        unboost(&mut self.pi_boosts, &waiter);
+        if waiters.is_empty() {
+            self.hash.remove(key);
         }
+        Some(waiter)
     }
 
+    /// Wakes up at most `n` waiters on `key` whose bitsets intersect with `mask`, and
+    /// returns the number of woken waiters.
+    pub fn wake(&mut self, key: &FutexHashKey, n: usize, mask: u32) -> usize {
+        let Some(waiters) = self.hash.get_mut(key) else {
+            return 0;
+        };
+        let mut woken = 0;
+        let mut index = 0;
+        while woken < n && index < waiters.len() {
+            if waiters[index].mask & mask == 0 {
+                index += 1;
+                continue;
+            }
+            let waiter = waiters.remove(index).unwrap();
+            unboost(&mut self.pi_boosts, &waiter);
+            log::info!("[futex_wake] {:?} has been woken", waiter);
+            waiter.wake();
+            woken += 1;
+        }
+        if waiters.is_empty() {
+            self.hash.remove(key);
+        }
+        woken
+    }
+
+    /// Moves at most `n_req` waiters from `old` to `new`, and returns the number of
+    /// moved waiters. They become waiters for the PI futex of `new` owned by
+    /// `pi_owner` if it is set.
     pub fn requeue_waiters(
         &mut self,
         old: FutexHashKey,
         new: FutexHashKey,
         n_req: usize,
-    ) -> SyscallResult {
-        let mut old_waiters = self.hash.remove(&old).ok_or_else(|| {
+        pi_owner: Option<Tid>,
+    ) -> usize {
+        let Some(mut old_waiters) = self.hash.remove(&old) else {
             log::info!("[futex] no waiters in key {:?}", old);
-            SysError::EINVAL
-        })?;
-
+            return 0;
+        };
         let n = min(n_req, old_waiters.len());
-
-        let iter = 0..n;
-        if let Some(new_waiters) = self.hash.get_mut(&new) {
-            iter.for_each(|_| new_waiters.push(old_waiters.pop().unwrap()));
-        } else {
-            let mut new_waiters = Vec::with_capacity(n);
-            iter.for_each(|_| new_waiters.push(old_waiters.pop().unwrap()));
-            self.hash.insert(new, new_waiters);
-        }
-
+        let moved: Vec<_> = old_waiters.drain(..n).collect();
         if !old_waiters.is_empty() {
             self.hash.insert(old, old_waiters);
         }
+        for mut waiter in moved {
+            unboost(&mut self.pi_boosts, &waiter);
+            if pi_owner.is_some() {
+                waiter.pi_owner = pi_owner;
+            }
+            self.add_waiter(&new, waiter);
+        }
+        n
+    }
+
+    /// Makes the waiters on `key` wait for `owner`, which has taken the PI futex.
+    pub fn set_pi_owner(&mut self, key: &FutexHashKey, owner: Tid) {
+        let Some(waiters) = self.hash.get_mut(key) else {
+            return;
+        };
+        for waiter in waiters.iter_mut() {
+            unboost(&mut self.pi_boosts, waiter);
+            waiter.pi_owner = Some(owner);
+            boost(&mut self.pi_boosts, waiter);
+        }
+    }
 
-        Ok(n)
+    /// Wakes up the waiters for the PI futexes owned by `owner`, which exits without
+    /// unlocking them. They retry to lock the futexes, and take them over with
+    /// `FUTEX_OWNER_DIED` set.
+    pub fn release_pi_owner(&mut self, owner: Tid) {
+        if self.pi_boosts.remove(&owner).is_none() {
+            return;
+        }
+        set_pi_boosted(owner, false);
+        for waiters in self.hash.values_mut() {
+            waiters.retain(|waiter| {
+                if waiter.pi_owner != Some(owner) {
+                    return true;
+                }
+                waiter.waker.wake_by_ref();
+                false
+            });
+        }
+        self.hash.retain(|_, waiters| !waiters.is_empty());
     }
 }
 
@@ -196,9 +362,8 @@ impl FutexHashKey {
         let vaddr = VirtAddr::new(vaddr);
         let ppn = addrspace
             .page_table
-            .find_entry(vaddr.page_number())
-            .ok_or(SysError::EFAULT)?
-            .ppn();
+            .translate(vaddr.page_number())
+            .ok_or(SysError::EFAULT)?;
         let paddr = PhysAddr::new(ppn.address().to_usize() + vaddr.page_offset());
         Ok(FutexHashKey::Shared { paddr })
     }
@@ -227,14 +392,25 @@ pub struct FutexWaiter {
     pub tid: Tid,
     pub waker: Waker,
     pub mask: u32,
+    /// Owner of the PI futex the task waits for, which inherits its priority.
+    pub pi_owner: Option<Tid>,
 }
 
 impl FutexWaiter {
-    pub fn new(task: &Task) -> Self {
+    pub fn new(task: &Task, mask: u32) -> Self {
         Self {
             tid: task.tid(),
             waker: task.get_waker(),
-            mask: 0,
+            mask,
+            pi_owner: None,
+        }
+    }
+
+    /// Creates a waiter for a PI futex owned by `owner`.
+    pub fn new_pi(task: &Task, owner: Tid) -> Self {
+        Self {
+            pi_owner: Some(owner),
+            ..Self::new(task, FUTEX_BITSET_MATCH_ANY)
         }
     }
 
@@ -243,6 +419,217 @@ impl FutexWaiter {
     }
 }
 
+/// The operation of `FUTEX_WAKE_OP` encoded in `val3`, which modifies the futex word
+/// at `uaddr2` and compares its old value with an argument.
+pub struct FutexWakeOp {
+    op: u32,
+    oparg: u32,
+    cmp: u32,
+    cmparg: i32,
+}
+
+impl FutexWakeOp {
+    const SET: u32 = 0;
+    const ADD: u32 = 1;
+    const OR: u32 = 2;
+    const ANDN: u32 = 3;
+    const XOR: u32 = 4;
+    /// Flag of the operation to use `1 << oparg` as its argument.
+    const OPARG_SHIFT: u32 = 8;
+
+    const CMP_EQ: u32 = 0;
+    const CMP_NE: u32 = 1;
+    const CMP_LT: u32 = 2;
+    const CMP_LE: u32 = 3;
+    const CMP_GT: u32 = 4;
+    const CMP_GE: u32 = 5;
+
+    /// Decodes the operation, whose arguments are sign-extended 12-bit values.
+    ///
+    /// Returns `ENOSYS` if the operation or comparison is unknown.
+    pub fn decode(val3: u32) -> SysResult<Self> {
+        let op = val3 >> 28;
+        let cmp = (val3 >> 24) & 0xf;
+        let mut oparg = (((val3 << 8) as i32) >> 20) as u32;
+        let cmparg = ((val3 << 20) as i32) >> 20;
+        if op & Self::OPARG_SHIFT != 0 {
+            oparg = 1 << (oparg & 31);
+        }
+        let op = op & !Self::OPARG_SHIFT;
+        if op > Self::XOR || cmp > Self::CMP_GE {
+            return Err(SysError::ENOSYS);
+        }
+        Ok(Self {
+            op,
+            oparg,
+            cmp,
+            cmparg,
+        })
+    }
+
+    /// Returns the new value of the futex word whose old value is `old`.
+    pub fn apply(&self, old: u32) -> u32 {
+        match self.op {
+            Self::SET => self.oparg,
+            Self::ADD => old.wrapping_add(self.oparg),
+            Self::OR => old | self.oparg,
+            Self::ANDN => old & !self.oparg,
+            _ => old ^ self.oparg,
+        }
+    }
+
+    /// Returns whether the waiters on `uaddr2` are to be woken up, after the futex
+    /// word there is modified from `old`.
+    pub fn compare(&self, old: u32) -> bool {
+        let old = old as i32;
+        match self.cmp {
+            Self::CMP_EQ => old == self.cmparg,
+            Self::CMP_NE => old != self.cmparg,
+            Self::CMP_LT => old < self.cmparg,
+            Self::CMP_LE => old <= self.cmparg,
+            Self::CMP_GT => old > self.cmparg,
+            _ => old >= self.cmparg,
+        }
+    }
+}
+
+/// `struct robust_list_head`, the head of the list of robust futexes held by a
+/// thread, which are released when the thread exits.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct RobustListHead {
+    /// Pointer to the first entry, or to the head itself if the list is empty. The
+    /// lowest bit of a pointer to an entry is set if its futex is a PI futex.
+    pub list: usize,
+    /// Offset of the futex word from each entry.
+    pub futex_offset: isize,
+    /// Entry which the thread is adding to or removing from the list.
+    pub list_op_pending: usize,
+}
+
+/// Releases the futexes held by `task`, which exits or execs.
+///
+/// Each robust futex it owns is marked with `FUTEX_OWNER_DIED`, and one of its
+/// waiters is woken up. The waiters for the PI futexes it owns are woken up to take
+/// them over.
+pub fn release_futexes(task: &Task) {
+    let head = task.robust_list();
+    if head != 0 {
+        task.set_robust_list(0);
+        let addrspace = task.addr_space();
+        if let Err(e) = walk_robust_list(task.tid(), head, &addrspace) {
+            log::warn!("[release_futexes] bad robust list at {head:#x}: {e:?}");
+        }
+    }
+    futex_manager().release_pi_owner(task.tid());
+}
+
+fn walk_robust_list(tid: Tid, head: usize, addrspace: &Arc<AddrSpace>) -> SysResult<()> {
+    let list_head = unsafe { UserReadPtr::<RobustListHead>::new(head, addrspace).read()? };
+    let mut entry = list_head.list;
+    let mut limit = ROBUST_LIST_LIMIT;
+    while entry != head && limit > 0 {
+        // The next entry is read first, since the entry may be freed by another
+        // thread once its futex is released.
+        let next = unsafe { UserReadPtr::<usize>::new(entry & !1, addrspace).read()? };
+        if entry != list_head.list_op_pending {
+            handle_futex_death(tid, entry, list_head.futex_offset, addrspace, false);
+        }
+        entry = next;
+        limit -= 1;
+    }
+    if list_head.list_op_pending != 0 {
+        let pending = list_head.list_op_pending;
+        handle_futex_death(tid, pending, list_head.futex_offset, addrspace, true);
+    }
+    Ok(())
+}
+
+/// Marks the robust futex of `entry` with `FUTEX_OWNER_DIED` if it is owned by the
+/// task `tid`, and wakes up one of its waiters unless it is a PI futex.
+///
+/// `pending` is set for the entry which the task was adding or removing, whose futex
+/// may have been unlocked without waking up its waiters.
+fn handle_futex_death(
+    tid: Tid,
+    entry: usize,
+    futex_offset: isize,
+    addrspace: &Arc<AddrSpace>,
+    pending: bool,
+) {
+    let pi = entry & 1 != 0;
+    let uaddr = (entry & !1).wrapping_add_signed(futex_offset);
+    let Ok(futex_addr) = FutexAddr::new_with_check(uaddr, addrspace) else {
+        return;
+    };
+    if futex_addr.check_write(addrspace).is_err() {
+        return;
+    }
+
+    let Ok(mut manager) = lock_futex_words(addrspace, &[(&futex_addr, MappingFlags::W)]) else {
+        return;
+    };
+    loop {
+        let word = futex_addr.read();
+        if pending && !pi && word == 0 {
+            wake_any_key(&mut manager, uaddr, addrspace);
+            return;
+        }
+        if word & FUTEX_TID_MASK != tid as u32 {
+            return;
+        }
+        let new = (word & FUTEX_WAITERS) | FUTEX_OWNER_DIED;
+        if futex_addr.cmpxchg(word, new).is_ok() {
+            if !pi && word & FUTEX_WAITERS != 0 {
+                wake_any_key(&mut manager, uaddr, addrspace);
+            }
+            return;
+        }
+    }
+}
+
+/// Wakes up a waiter on the futex at `uaddr`, which may be private or shared.
+fn wake_any_key(manager: &mut FutexManager, uaddr: usize, addrspace: &Arc<AddrSpace>) {
+    if let Ok(key) = FutexHashKey::new_share_key(uaddr, addrspace) {
+        if manager.wake(&key, 1, FUTEX_BITSET_MATCH_ANY) > 0 {
+            return;
+        }
+    }
+    if let Ok(key) = FutexHashKey::new_private_key(uaddr, addrspace.clone()) {
+        manager.wake(&key, 1, FUTEX_BITSET_MATCH_ANY);
+    }
+}
+
+/// `struct futex_waitv`, a futex to wait on by `futex_waitv()`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct FutexWaitv {
+    pub val: u64,
+    pub uaddr: u64,
+    pub flags: u32,
+    pub __reserved: u32,
+}
+
+impl FutexWaitv {
+    /// Max number of futexes to wait on at once.
+    pub const MAX: usize = 128;
+    const SIZE_MASK: u32 = 0x3;
+    const SIZE_U32: u32 = 0x2;
+    pub const PRIVATE: u32 = 128;
+
+    /// Checks that the futex is a 32-bit futex, which is the only supported size.
+    pub fn check(&self) -> SysResult<()> {
+        if self.__reserved != 0
+            || self.flags & !(Self::SIZE_MASK | Self::PRIVATE) != 0
+            || self.flags & Self::SIZE_MASK != Self::SIZE_U32
+            || self.val > u32::MAX as u64
+        {
+            return Err(SysError::EINVAL);
+        }
+        Ok(())
+    }
+}
+
 bitflags! {
     #[repr(C)]
     #[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
@@ -279,8 +666,12 @@ bitflags! {
         /// Waits using a bit mask to match waiters.
         const WaitBitset = 9;
         const WakeBitset = 10;
-        /// Requeues PI waiters.
+        /// Waits on a non-PI futex, to be requeued to a PI futex by
+        /// `FUTEX_CMP_REQUEUE_PI`, and returns with the PI futex locked.
         const WaitRequeuePi = 11;
+        /// Requeues the waiters of `FUTEX_WAIT_REQUEUE_PI` to the PI futex at
+        /// `uaddr2`, after locking it for the first waiter and waking it up.
+        const CmpRequeuePi = 12;
         const MAINOPMASK = 15;
         /// Tells the kernel that the futex is process-private and not shared
         /// with another process.
@@ -296,6 +687,7 @@ impl FutexOp {
             (FutexOp::DEBUG, FutexOp::DEBUG.bits()),
             (FutexOp::ClockRealtime, FutexOp::ClockRealtime.bits()),
             (FutexOp::Private, FutexOp::Private.bits()),
+            (FutexOp::CmpRequeuePi, FutexOp::CmpRequeuePi.bits()),
             (FutexOp::WaitRequeuePi, FutexOp::WaitRequeuePi.bits()),
             (FutexOp::WakeBitset, FutexOp::WakeBitset.bits()),
             (FutexOp::WaitBitset, FutexOp::WaitBitset.bits()),
@@ -328,6 +720,7 @@ impl FutexOp {
 
     pub fn exstract_main_futex_flags(val: i32) -> FutexOp {
         let flag_values = [
+            (FutexOp::CmpRequeuePi, FutexOp::CmpRequeuePi.bits()),
             (FutexOp::WaitRequeuePi, FutexOp::WaitRequeuePi.bits()),
             (FutexOp::WakeBitset, FutexOp::WakeBitset.bits()),
             (FutexOp::WaitBitset, FutexOp::WaitBitset.bits()),
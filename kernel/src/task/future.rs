@@ -184,10 +184,20 @@ pub async fn task_executor_unit(task: Arc<Task>) {
         // be some instructions with risks between trap_return and trap_handler.
         trap::trap_handler(&task);
 
+        // A page fault to be handled by a userfaultfd is waited for out of the trap
+        // handler, and the faulting instruction is retried after it is resolved.
+        if let Some(userfault) = task.take_userfault() {
+            userfault.wait(&task).await;
+        }
+
         let mut interrupted = async_syscall(&task).await;
         TIMER_MANAGER.check(get_time_duration());
+        task.check_cpu_timers();
+        task.check_cpu_limit();
 
-        if task.timer_mut().schedule_time_out()
+        // A task owning a PI futex which other tasks wait for inherits their priority,
+        // and is not preempted until it unlocks the futex.
+        if task.timer_mut().schedule_time_out() && !task.pi_boosted()
         // && executor::has_waiting_task_alone(current_hart().id)
         {
             // log::debug!(
@@ -1,4 +1,10 @@
-use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use alloc::{
+    collections::BTreeMap,
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::Ordering;
 
 use config::vfs::OpenFlags;
 use mutex::ShareMutex;
@@ -8,6 +14,7 @@ use osfs::{
     proc::{
         KernelProcIf,
         fdinfo::info::{ExtraFdInfo, FanotifyFdInfo, FanotifyMarkInfo, ProcFdInfo},
+        mem::ProcMem,
     },
 };
 use systype::{
@@ -21,8 +28,12 @@ use vfs::{
     file::File,
 };
 
-use super::{TaskState, manager::TASK_MANAGER};
-use crate::{processor::current_task, trap::trap_handler::TRAP_STATS};
+use super::{Task, TaskState, cap::CapabilitiesFlags, manager::TASK_MANAGER};
+use crate::{
+    processor::current_task,
+    trap::trap_handler::TRAP_STATS,
+    vm::{self, addr_space::AddrSpace, oom},
+};
 
 struct KernelProcIfImpl;
 
@@ -124,6 +135,93 @@ impl KernelProcIf for KernelProcIfImpl {
             extra_info,
         })
     }
+
+    fn oom_score(tid: usize) -> SysResult<usize> {
+        Ok(oom::oom_score(&proc_task(tid)?))
+    }
+
+    fn oom_score_adj(tid: usize) -> SysResult<isize> {
+        Ok(proc_task(tid)?.oom_score_adj.load(Ordering::Relaxed) as isize)
+    }
+
+    fn set_oom_score_adj(tid: usize, adj: isize) -> SysResult<()> {
+        let task = proc_task(tid)?;
+        let current = current_task();
+        // Only a privileged task may make a process less likely to be killed, or change
+        // a process of another user.
+        let privileged = current.has_capability(CapabilitiesFlags::CAP_SYS_RESOURCE);
+        if !privileged && !Arc::ptr_eq(&current.process(), &task) {
+            let euid = current.perm_mut().lock().euid;
+            let target = task.perm_mut().lock().clone();
+            if euid != target.euid && euid != target.ruid {
+                return Err(SysError::EACCES);
+            }
+        }
+        task.oom_score_adj
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+                (privileged || adj >= old as isize).then_some(adj as i32)
+            })
+            .map_err(|_| SysError::EACCES)?;
+        Ok(())
+    }
+
+    fn committed_pages() -> usize {
+        oom::committed_pages()
+    }
+
+    fn anon_huge_pages() -> usize {
+        vm::anon_huge_pages()
+    }
+
+    fn open_mem(tid: usize) -> SysResult<Weak<dyn ProcMem>> {
+        let addr_space = mem_task(tid)?.addr_space();
+        Ok(Arc::downgrade(&addr_space) as Weak<dyn ProcMem>)
+    }
+
+    fn check_sysctl_write() -> SysResult<()> {
+        if !current_task().has_capability(CapabilitiesFlags::CAP_SYS_ADMIN) {
+            return Err(SysError::EPERM);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the process of the task with `tid`, or of the current task if `tid` is 0.
+fn proc_task(tid: usize) -> SysResult<Arc<Task>> {
+    let task = match tid {
+        0 => current_task(),
+        tid => TASK_MANAGER.get_task(tid).ok_or(SysError::ESRCH)?,
+    };
+    Ok(task.process())
+}
+
+/// Returns the process of the task with `tid` as [`proc_task`] does, if the current task
+/// may access its memory through `/proc/<pid>/mem`.
+fn mem_task(tid: usize) -> SysResult<Arc<Task>> {
+    let target = proc_task(tid)?;
+    current_task()
+        .check_ptrace_access(&target)
+        .map_err(|_| SysError::EACCES)?;
+    Ok(target)
+}
+
+/// Converts an error in accessing the memory of a process into the error returned by
+/// `/proc/<pid>/mem`, which is `EIO` for an address that cannot be accessed.
+fn mem_error(e: SysError) -> SysError {
+    match e {
+        SysError::EFAULT => SysError::EIO,
+        e => e,
+    }
+}
+
+impl ProcMem for AddrSpace {
+    fn read(&self, addr: usize, buf: &mut [u8]) -> SysResult<usize> {
+        self.read_remote(addr, buf).map_err(mem_error)
+    }
+
+    fn write(&self, addr: usize, buf: &[u8]) -> SysResult<usize> {
+        self.write_remote_force(addr, buf).map_err(mem_error)
+    }
 }
 
 struct KernelTableIfImpl;
@@ -1,16 +1,26 @@
 use core::{
+    cell::Cell,
     fmt::Debug,
     pin::Pin,
     task::{Context, Poll},
 };
 
-use alloc::collections::vec_deque::VecDeque;
+use alloc::{collections::vec_deque::VecDeque, vec::Vec};
+use arch::fpu::{self, FloatRegs, VectorRegs};
 use bitflags::bitflags;
 
 use alloc::sync::Arc;
 use osfs::special::signalfd::file::SignalFdFile;
+use systype::{
+    error::{SysError, SysResult},
+    rlimit::RLIM_INFINITY,
+};
 
-use crate::task::{TaskState, signal::sig_info::*};
+use crate::{
+    syscall::process::Resource,
+    task::{TaskState, manager::TASK_MANAGER, signal::sig_info::*},
+    vm::{addr_space::AddrSpace, user_ptr::UserReadPtr},
+};
 
 use super::Task;
 
@@ -56,6 +66,39 @@ impl Task {
         self.recv(si)
     }
 
+    /// Queues a signal sent by `kill()` and its relatives.
+    ///
+    /// Real-time signals are limited by `RLIMIT_SIGPENDING`, counted as the number of
+    /// signals pending for all tasks of the real user of the task, whatever sent them.
+    /// Standard signals are always delivered, as on Linux.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EAGAIN`] if a real-time signal exceeds the limit.
+    pub fn queue_siginfo(&self, si: SigInfo) -> SysResult<()> {
+        if si.sig.raw() >= Sig::SIGRTMIN.raw() {
+            let limit = self.get_rlimit(Resource::SIGPENDING).rlim_cur;
+            if limit != RLIM_INFINITY && self.pending_signals_of_user() >= limit {
+                return Err(SysError::EAGAIN);
+            }
+        }
+        self.recv(si);
+        Ok(())
+    }
+
+    /// Returns the number of signals pending for all tasks of the real user of the
+    /// task.
+    fn pending_signals_of_user(&self) -> usize {
+        let ruid = self.perm_mut().lock().ruid;
+        let count = Cell::new(0);
+        let _ = TASK_MANAGER.for_each(|task| {
+            if task.perm_mut().lock().ruid == ruid {
+                count.set(count.get() + task.sig_manager_mut().queue.len());
+            }
+            Ok(())
+        });
+        count.get()
+    }
+
     /// `recv()` can add signal to task signal manager. If
     /// the relevant signal is `should wake` and task is in
     /// Interrupt state, task will be waken and handle the
@@ -384,6 +427,8 @@ impl Default for SignalStack {
     }
 }
 
+/// The `ucontext_t` pushed onto the user stack when a signal handler is run, laid out
+/// as glibc and musl expect.
 #[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct SigContext {
@@ -394,12 +439,253 @@ pub struct SigContext {
     pub stack: SignalStack,
     // 当前上下文活跃时被阻塞的信号集
     pub mask: SigSet,
-    // don't know why, struct need to be exact the same with musl libc
+    // sigset_t in libc is 1024 bits, the rest of which is padding here
     pub sig: [usize; 16],
-    // common register
+    // the pc, i.e., `sc_pc` of the `struct sigcontext` in LoongArch
+    #[cfg(target_arch = "loongarch64")]
+    pub pc: usize,
+    // common register, where user_reg[0] is the pc in RISC-V
     pub user_reg: [usize; 32],
-    //
-    pub fpstate: [usize; 66],
+    #[cfg(target_arch = "loongarch64")]
+    pub sc_flags: u32,
+    pub fpstate: FpState,
+}
+
+/// Floating-point registers in `mcontext_t`, i.e., `union __riscv_mc_fp_state`, of which
+/// the D extension state is used.
+///
+/// Its last 8 bytes are the header of the first extension record, which follows the
+/// `SigContext`, or an empty header if there is none.
+#[cfg(target_arch = "riscv64")]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(16))]
+pub struct FpState {
+    pub f: [u64; 32],
+    pub fcsr: u32,
+    reserved: [u32; 65],
+    ext_magic: u32,
+    ext_size: u32,
+}
+
+/// Extended context records following `mcontext_t` in LoongArch, which are the FPU
+/// context followed by the header of the next record, which follows the `SigContext`,
+/// or an empty header marking the end.
+#[cfg(target_arch = "loongarch64")]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(16))]
+pub struct FpState {
+    info: SctxInfo,
+    pub regs: [u64; 32],
+    pub fcc: u64,
+    pub fcsr: u32,
+    next: SctxInfo,
+}
+
+/// Header of an extended context record in LoongArch.
+#[cfg(target_arch = "loongarch64")]
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(16))]
+struct SctxInfo {
+    magic: u32,
+    size: u32,
+    padding: u64,
+}
+
+#[cfg(target_arch = "loongarch64")]
+const FPU_CTX_MAGIC: u32 = 0x46505501;
+#[cfg(target_arch = "loongarch64")]
+const LSX_CTX_MAGIC: u32 = 0x53580001;
+#[cfg(target_arch = "loongarch64")]
+const LASX_CTX_MAGIC: u32 = 0x41535801;
+
+#[cfg(target_arch = "riscv64")]
+const RISCV_V_MAGIC: u32 = 0x53465457;
+
+/// `struct __riscv_v_ext_state`, which is followed by the vector registers.
+#[cfg(target_arch = "riscv64")]
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct RiscvVExtState {
+    vstart: usize,
+    vl: usize,
+    vtype: usize,
+    vcsr: usize,
+    vlenb: usize,
+    datap: usize,
+}
+
+impl SigContext {
+    /// Returns the size of the extension records following the `SigContext` in a
+    /// signal frame, which hold the vector registers if `vector` is set.
+    pub fn ext_size(vector: bool) -> usize {
+        if !vector {
+            return 0;
+        }
+        #[cfg(target_arch = "riscv64")]
+        {
+            // the state, the registers and the end header
+            size_of::<RiscvVExtState>() + 32 * fpu::vlenb() + 8
+        }
+        // the context without its header, which is in `fpstate`, and the end header
+        #[cfg(target_arch = "loongarch64")]
+        {
+            Self::vector_ctx_size()
+        }
+    }
+
+    /// Size of `struct lsx_context` or `struct lasx_context` with its header.
+    #[cfg(target_arch = "loongarch64")]
+    fn vector_ctx_size() -> usize {
+        let words = if fpu::has_lasx() { 4 } else { 2 };
+        size_of::<SctxInfo>() + (32 * words * 8 + 12).next_multiple_of(16)
+    }
+
+    /// Links an extension record of the vector registers from `fpstate`, and returns
+    /// the records to be written right after the `SigContext`, which is at `addr`.
+    pub fn vector_ext(&mut self, vregs: &VectorRegs, fregs: &FloatRegs, addr: usize) -> Vec<u8> {
+        let mut ext = Vec::with_capacity(Self::ext_size(true));
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            let _ = fregs;
+            let vlenb = fpu::vlenb();
+            let datap = addr + size_of::<Self>() + size_of::<RiscvVExtState>();
+            // struct __riscv_v_ext_state
+            for value in [vregs.vstart, vregs.vl, vregs.vtype, vregs.vcsr, vlenb, datap] {
+                ext.extend_from_slice(&value.to_ne_bytes());
+            }
+            ext.extend_from_slice(&vregs.v[..32 * vlenb]);
+            self.fpstate.ext_magic = RISCV_V_MAGIC;
+            self.fpstate.ext_size = (8 + ext.len()) as u32;
+        }
+
+        #[cfg(target_arch = "loongarch64")]
+        {
+            let _ = addr;
+            let (magic, words) = if fpu::has_lasx() {
+                (LASX_CTX_MAGIC, 4)
+            } else {
+                (LSX_CTX_MAGIC, 2)
+            };
+            for reg in vregs.v.iter() {
+                for word in &reg[..words] {
+                    ext.extend_from_slice(&word.to_ne_bytes());
+                }
+            }
+            ext.extend_from_slice(&fregs.fcc.to_ne_bytes());
+            ext.extend_from_slice(&fregs.fcsr.to_ne_bytes());
+            let size = Self::vector_ctx_size();
+            ext.resize(size - size_of::<SctxInfo>(), 0);
+            self.fpstate.next = SctxInfo {
+                magic,
+                size: size as u32,
+                padding: 0,
+            };
+        }
+
+        // the end header, which is all zero
+        ext.resize(Self::ext_size(true), 0);
+        ext
+    }
+
+    /// Reads the vector registers from the extension record following the `SigContext`,
+    /// which is at `addr`, if there is one.
+    pub fn read_vector_ext(
+        &self,
+        addr: usize,
+        addr_space: &AddrSpace,
+    ) -> SysResult<Option<VectorRegs>> {
+        let ext_addr = addr + size_of::<Self>();
+        let mut vregs = VectorRegs::new();
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            if self.fpstate.ext_magic != RISCV_V_MAGIC {
+                return Ok(None);
+            }
+            let state =
+                unsafe { UserReadPtr::<RiscvVExtState>::new(ext_addr, addr_space).read()? };
+            let vlenb = fpu::vlenb();
+            if state.vlenb != vlenb {
+                return Err(SysError::EINVAL);
+            }
+            let data = unsafe {
+                UserReadPtr::<u8>::new(state.datap, addr_space).read_array(32 * vlenb)?
+            };
+            vregs.v[..32 * vlenb].copy_from_slice(&data);
+            vregs.vstart = state.vstart;
+            vregs.vl = state.vl;
+            vregs.vtype = state.vtype;
+            vregs.vcsr = state.vcsr;
+        }
+
+        #[cfg(target_arch = "loongarch64")]
+        {
+            let words = match self.fpstate.next.magic {
+                LSX_CTX_MAGIC => 2,
+                LASX_CTX_MAGIC if fpu::has_lasx() => 4,
+                0 => return Ok(None),
+                _ => return Err(SysError::EINVAL),
+            };
+            let data = unsafe {
+                UserReadPtr::<u64>::new(ext_addr, addr_space).read_array(32 * words)?
+            };
+            for (reg, chunk) in vregs.v.iter_mut().zip(data.chunks(words)) {
+                reg[..words].copy_from_slice(chunk);
+            }
+        }
+
+        Ok(Some(vregs))
+    }
+}
+
+impl FpState {
+    #[cfg(target_arch = "riscv64")]
+    pub fn from_regs(regs: &FloatRegs) -> Self {
+        Self {
+            f: regs.f,
+            fcsr: regs.fcsr,
+            reserved: [0; 65],
+            ext_magic: 0,
+            ext_size: 0,
+        }
+    }
+
+    #[cfg(target_arch = "loongarch64")]
+    pub fn from_regs(regs: &FloatRegs) -> Self {
+        Self {
+            info: SctxInfo {
+                magic: FPU_CTX_MAGIC,
+                size: (size_of::<Self>() - size_of::<SctxInfo>()) as u32,
+                padding: 0,
+            },
+            regs: regs.f,
+            fcc: regs.fcc,
+            fcsr: regs.fcsr,
+            next: SctxInfo {
+                magic: 0,
+                size: 0,
+                padding: 0,
+            },
+        }
+    }
+
+    #[cfg(target_arch = "riscv64")]
+    pub fn to_regs(&self) -> FloatRegs {
+        FloatRegs {
+            f: self.f,
+            fcsr: self.fcsr,
+        }
+    }
+
+    #[cfg(target_arch = "loongarch64")]
+    pub fn to_regs(&self) -> FloatRegs {
+        FloatRegs {
+            f: self.regs,
+            fcc: self.fcc,
+            fcsr: self.fcsr,
+        }
+    }
 }
 
 pub struct IntrBySignalFuture {
@@ -0,0 +1,629 @@
+//! Module for writing core dumps.
+//!
+//! When a process is killed by a signal whose default action is to dump core, the
+//! kernel writes an ELF file of type `ET_CORE` describing the process, which can be
+//! loaded into gdb together with the executable for post-mortem debugging.
+//!
+//! The core file starts with a `PT_NOTE` segment holding the following notes:
+//! - `NT_PRSTATUS` of the thread which received the signal,
+//! - `NT_PRPSINFO`, `NT_SIGINFO`, `NT_AUXV` and `NT_FILE` of the process,
+//! - `NT_PRSTATUS` of every other thread in the thread group.
+//!
+//! It is followed by a `PT_LOAD` segment for every VMA of the process. Anonymous
+//! memory and private writable mappings are dumped in full; read-only file mappings
+//! are left to the files they map, except for the first page of a file, which holds
+//! the ELF header gdb needs to identify the file. Pages which are not resident are
+//! faulted in, except in private anonymous VMAs, where they are left as holes.
+//!
+//! The location of the core file is given by `/proc/sys/kernel/core_pattern`, and its
+//! size is limited by `RLIMIT_CORE`. Nothing is dumped if the process is not dumpable
+//! (see `prctl(PR_SET_DUMPABLE)`). A new core file is only readable and writable by
+//! the dumping user, and an existing one is only overwritten if that user owns it.
+//!
+//! Registers of threads other than the dumping thread are the ones saved at their
+//! last trap into the kernel.
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::sync::atomic::Ordering;
+
+use arch::time::get_time_duration;
+use config::{
+    inode::InodeMode,
+    mm::PAGE_SIZE,
+    vfs::{AtFd, OpenFlags, SeekFrom},
+};
+use mm::{
+    address::{VirtAddr, VirtPageNum},
+    page_cache::page::Page,
+};
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MappingFlags,
+};
+use vfs::file::File;
+
+use crate::{
+    syscall::{
+        process::Resource,
+        time::{CLOCK_DEVIATION, CLOCK_REALTIME},
+    },
+    task::{
+        Task,
+        signal::sig_info::{LinuxSigInfo, SigDetails, SigInfo},
+    },
+    vm::{
+        addr_space::AddrSpace,
+        vm_area::{FileBackedArea, TypedArea, VmaFlags},
+    },
+};
+
+const ET_CORE: u16 = 4;
+#[cfg(target_arch = "riscv64")]
+const EM_ARCH: u16 = 243;
+#[cfg(target_arch = "loongarch64")]
+const EM_ARCH: u16 = 258;
+
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_SIGINFO: u32 = 0x5349_4749;
+const NT_FILE: u32 = 0x4649_4c45;
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+
+/// Number of registers in `elf_gregset_t`: `pc` followed by `x1` to `x31`.
+#[cfg(target_arch = "riscv64")]
+const ELF_NGREG: usize = 32;
+/// Number of registers in `elf_gregset_t`: `r0` to `r31`, `orig_a0`, `csr_era`,
+/// `csr_badv` and 10 reserved slots.
+#[cfg(target_arch = "loongarch64")]
+const ELF_NGREG: usize = 45;
+
+/// Path of the file holding the core file name pattern.
+const CORE_PATTERN_PATH: &str = "/proc/sys/kernel/core_pattern";
+
+/// Writes a core dump of the process of `task`, which is being killed by the signal
+/// described by `si`.
+///
+/// Returns an error if no core file is written. A core file truncated by
+/// `RLIMIT_CORE` is still considered written.
+pub async fn do_coredump(task: &Arc<Task>, si: &SigInfo) -> SysResult<()> {
+    let process = task.process();
+    if !process.dumpable.load(Ordering::Relaxed) {
+        return Err(SysError::EPERM);
+    }
+    let limit = task.rlimits_mut().lock()[Resource::CORE as usize].rlim_cur;
+    if limit == 0 {
+        return Err(SysError::EFBIG);
+    }
+
+    let pattern = read_core_pattern(task).await?;
+    if pattern.is_empty() || pattern.starts_with('|') {
+        // Piping core dumps to a user helper is not supported.
+        return Err(SysError::EOPNOTSUPP);
+    }
+    let path = expand_core_pattern(task, &pattern, si, limit);
+    log::info!("[do_coredump] task {} dumps core to {}", task.tid(), path);
+
+    // The core file is opened as with `O_CREAT | O_EXCL | O_NOFOLLOW` and mode 0600,
+    // except that an existing regular file owned by the dumping user and not linked
+    // elsewhere is overwritten, so that core dumps of privileged processes are never
+    // written through symbolic links or to files of other users.
+    let (euid, egid) = {
+        let perm = task.perm_mut();
+        let cred = perm.lock();
+        (cred.euid, cred.egid)
+    };
+    let dentry = task.walk_at(AtFd::FdCwd, path)?;
+    if dentry.is_negative() {
+        dentry
+            .parent()
+            .ok_or(SysError::ENOENT)?
+            .create(&dentry, InodeMode::REG)?;
+        let inode = dentry.inode().ok_or(SysError::ENOENT)?;
+        inode.set_mode(InodeMode::REG | InodeMode::OWNER_READ | InodeMode::OWNER_WRITE);
+        inode.set_uid(euid);
+        inode.set_gid(egid);
+    }
+    let inode = dentry.inode().ok_or(SysError::ENOENT)?;
+    let inode_type = inode.inotype();
+    if inode_type.is_symlink() {
+        return Err(SysError::ELOOP);
+    }
+    if !inode_type.is_reg() {
+        return Err(SysError::EISDIR);
+    }
+    if inode.get_uid() != euid || inode.get_meta().inner.lock().nlink > 1 {
+        return Err(SysError::EEXIST);
+    }
+    inode.set_size(0)?;
+    let file = <dyn File>::open(dentry)?;
+    file.set_flags(OpenFlags::O_WRONLY);
+
+    let addr_space = task.addr_space();
+    let segments = collect_segments(&addr_space);
+    let notes = build_notes(task, si, &addr_space, &segments);
+
+    let phnum = segments.len() + 1;
+    let notes_offset = EHDR_SIZE + phnum * PHDR_SIZE;
+    let data_offset = (notes_offset + notes.len()).next_multiple_of(PAGE_SIZE);
+
+    let mut headers = Buf::new();
+    // ELF header
+    headers.bytes(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    headers.u16(ET_CORE);
+    headers.u16(EM_ARCH);
+    headers.u32(1);
+    headers.u64(0);
+    headers.u64(EHDR_SIZE as u64);
+    headers.u64(0);
+    headers.u32(0);
+    headers.u16(EHDR_SIZE as u16);
+    headers.u16(PHDR_SIZE as u16);
+    headers.u16(phnum.min(u16::MAX as usize) as u16);
+    headers.u16(64);
+    headers.u16(0);
+    headers.u16(0);
+    // PT_NOTE
+    headers.u32(PT_NOTE);
+    headers.u32(0);
+    headers.u64(notes_offset as u64);
+    headers.u64(0);
+    headers.u64(0);
+    headers.u64(notes.len() as u64);
+    headers.u64(0);
+    headers.u64(4);
+    // PT_LOAD
+    let mut offset = data_offset;
+    for seg in &segments {
+        headers.u32(PT_LOAD);
+        headers.u32(seg.flags);
+        headers.u64(offset as u64);
+        headers.u64(seg.start as u64);
+        headers.u64(0);
+        headers.u64(seg.dump_len as u64);
+        headers.u64((seg.end - seg.start) as u64);
+        headers.u64(PAGE_SIZE as u64);
+        offset += seg.dump_len;
+    }
+
+    let mut writer = CoreWriter {
+        file,
+        pos: 0,
+        limit,
+    };
+    let result = async {
+        writer.write(&headers.0).await?;
+        writer.write(&notes.0).await?;
+        writer.pad_to(data_offset).await?;
+        let mut buf = [0u8; PAGE_SIZE];
+        for seg in &segments {
+            for va in (seg.start..seg.start + seg.dump_len).step_by(PAGE_SIZE) {
+                if seg.read_page(&addr_space, va, &mut buf) {
+                    writer.write(&buf).await?;
+                } else {
+                    writer.skip(PAGE_SIZE)?;
+                }
+            }
+        }
+        Ok::<_, SysError>(())
+    }
+    .await;
+    // A hole at the end of the core file is not allocated by skipping it.
+    if inode.size() < writer.pos {
+        inode.set_size(writer.pos)?;
+    }
+    match result {
+        Err(SysError::EFBIG) => {
+            log::info!("[do_coredump] core file truncated to {limit} bytes");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Reads the core file name pattern, without the trailing newline.
+async fn read_core_pattern(task: &Arc<Task>) -> SysResult<String> {
+    let dentry = task.walk_at(AtFd::FdCwd, CORE_PATTERN_PATH.to_string())?;
+    let file = <dyn File>::open(dentry)?;
+    let mut buf = [0u8; 128];
+    let len = file.read(&mut buf).await?;
+    let pattern = buf[..len]
+        .split(|&b| b == 0 || b == b'\n')
+        .next()
+        .unwrap_or_default();
+    Ok(String::from_utf8_lossy(pattern).into_owned())
+}
+
+/// Expands the `%` specifiers in a core file name pattern, as described in
+/// `core(5)`.
+fn expand_core_pattern(task: &Arc<Task>, pattern: &str, si: &SigInfo, limit: usize) -> String {
+    let process = task.process();
+    let perm = task.perm_mut().lock().clone();
+    let exe = unsafe { process.elf() }.dentry();
+
+    let mut path = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            path.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => path.push('%'),
+            Some('p') | Some('P') => path += &process.tid().to_string(),
+            Some('i') | Some('I') => path += &task.tid().to_string(),
+            Some('u') => path += &perm.euid.to_string(),
+            Some('g') => path += &perm.egid.to_string(),
+            Some('s') => path += &si.sig.raw().to_string(),
+            Some('t') => {
+                let now = get_time_duration() + unsafe { CLOCK_DEVIATION[CLOCK_REALTIME] };
+                path += &now.as_secs().to_string();
+            }
+            Some('h') => path += "Linux",
+            Some('e') => path += exe.name(),
+            Some('E') => path += &exe.path().replace('/', "!"),
+            Some('c') => path += &limit.to_string(),
+            Some('d') => path += "1",
+            // Unknown specifiers and a trailing `%` are dropped, as Linux does.
+            _ => {}
+        }
+    }
+    path
+}
+
+/// Part of the address space to be written to the core file as a `PT_LOAD` segment.
+struct Segment {
+    start: usize,
+    end: usize,
+    /// `p_flags` of the segment.
+    flags: u32,
+    /// Number of bytes dumped from the start of the segment.
+    dump_len: usize,
+    /// Resident pages of the VMA.
+    pages: BTreeMap<VirtPageNum, Arc<Page>>,
+    /// Whether the pages of the VMA which are not resident are zeros, as in a private
+    /// anonymous VMA.
+    zero_when_absent: bool,
+    /// Backing file of the VMA.
+    file: Option<FileBackedArea>,
+}
+
+impl Segment {
+    /// Reads the page at `va` of `addr_space` into `buf`, faulting it in as a read
+    /// from the user would if it is not resident.
+    ///
+    /// Returns false if the page reads as zeros without being faulted in, or cannot be
+    /// read at all, in which case it is left as a hole in the core file.
+    fn read_page(&self, addr_space: &AddrSpace, va: usize, buf: &mut [u8; PAGE_SIZE]) -> bool {
+        if let Some(page) = self.pages.get(&VirtAddr::new(va).page_number()) {
+            buf.copy_from_slice(page.as_slice());
+            return true;
+        }
+        if self.zero_when_absent {
+            return false;
+        }
+        match addr_space.read_remote(va, buf) {
+            Ok(len) => {
+                buf[len..].fill(0);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Takes a snapshot of the VMAs of an address space.
+fn collect_segments(addr_space: &AddrSpace) -> Vec<Segment> {
+    let vm_areas = addr_space.vm_areas.lock();
+    vm_areas
+        .values()
+        .map(|vma| {
+            let prot = vma.prot();
+            let mut flags = 0;
+            for (bit, flag) in [
+                (MappingFlags::R, PF_R),
+                (MappingFlags::W, PF_W),
+                (MappingFlags::X, PF_X),
+            ] {
+                if prot.contains(bit) {
+                    flags |= flag;
+                }
+            }
+            let file = match &vma.map_type {
+                TypedArea::FileBacked(area) => Some(area.clone()),
+                _ => None,
+            };
+            let dump_len = match &file {
+                _ if !prot.contains(MappingFlags::R) => 0,
+                _ if vma.flags().contains(VmaFlags::DONTDUMP) => 0,
+                Some(_) if vma.flags().contains(VmaFlags::SHARED) => 0,
+                Some(area) if !prot.contains(MappingFlags::W) => {
+                    if area.offset() == 0 {
+                        PAGE_SIZE
+                    } else {
+                        0
+                    }
+                }
+                _ => vma.length(),
+            };
+            Segment {
+                start: vma.start_va().to_usize(),
+                end: vma.end_va().to_usize(),
+                flags,
+                dump_len,
+                pages: vma.pages().clone(),
+                zero_when_absent: vma.is_private_anonymous(),
+                file,
+            }
+        })
+        .collect()
+}
+
+/// Builds the contents of the `PT_NOTE` segment.
+fn build_notes(
+    task: &Arc<Task>,
+    si: &SigInfo,
+    addr_space: &AddrSpace,
+    segments: &[Segment],
+) -> Buf {
+    let process = task.process();
+    let signo = si.sig.raw() as i32;
+    let siginfo = match si.details {
+        SigDetails::Kill {
+            siginfo: Some(info),
+            ..
+        } => info,
+        _ => LinuxSigInfo {
+            si_signo: signo,
+            si_code: si.code,
+            si_pid: si.details.get_sender_pid() as _,
+            ..Default::default()
+        },
+    };
+
+    let mut notes = Buf::new();
+    notes.note(NT_PRSTATUS, &prstatus(task, signo));
+    notes.note(NT_PRPSINFO, &prpsinfo(&process));
+    // SAFETY: `LinuxSigInfo` is a plain `repr(C)` struct.
+    notes.note(NT_SIGINFO, unsafe {
+        core::slice::from_raw_parts(
+            &siginfo as *const LinuxSigInfo as *const u8,
+            size_of::<LinuxSigInfo>(),
+        )
+    });
+
+    let mut auxv = Buf::new();
+    for &val in addr_space.saved_auxv.lock().iter() {
+        auxv.u64(val as u64);
+    }
+    notes.note(NT_AUXV, &auxv.0);
+
+    let files: Vec<_> = segments
+        .iter()
+        .filter_map(|seg| seg.file.as_ref().map(|area| (seg, area)))
+        .collect();
+    let mut nt_file = Buf::new();
+    nt_file.u64(files.len() as u64);
+    nt_file.u64(PAGE_SIZE as u64);
+    for (seg, area) in &files {
+        nt_file.u64(seg.start as u64);
+        nt_file.u64(seg.end as u64);
+        nt_file.u64((area.offset() / PAGE_SIZE) as u64);
+    }
+    for (_, area) in &files {
+        nt_file.bytes(area.file().dentry().path().as_bytes());
+        nt_file.u8(0);
+    }
+    notes.note(NT_FILE, &nt_file.0);
+
+    let threads: Vec<_> = task.with_thread_group(|tg| tg.iter().collect());
+    for thread in threads.iter().filter(|t| t.tid() != task.tid()) {
+        notes.note(NT_PRSTATUS, &prstatus(thread, signo));
+    }
+    notes
+}
+
+/// Builds `struct elf_prstatus` of a thread.
+fn prstatus(thread: &Arc<Task>, signo: i32) -> Vec<u8> {
+    let perm = thread.perm_mut().lock().clone();
+    let ppid = thread
+        .parent_mut()
+        .lock()
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.pid());
+    let (utime, stime) = thread.timer_mut().user_and_system_time();
+    let (cutime, cstime) = thread.timer_mut().child_user_system_time();
+
+    let mut desc = Buf::new();
+    // pr_info: si_signo, si_code, si_errno
+    desc.u32(signo as u32);
+    desc.u32(0);
+    desc.u32(0);
+    // pr_cursig
+    desc.u16(signo as u16);
+    desc.align(8);
+    desc.u64(thread.sig_manager_mut().bitmap.bits());
+    desc.u64(thread.get_sig_mask().bits());
+    desc.u32(thread.tid() as u32);
+    desc.u32(ppid as u32);
+    desc.u32(thread.get_pgid() as u32);
+    desc.u32(perm.sid);
+    for time in [utime, stime, cutime, cstime] {
+        desc.u64(time.as_secs());
+        desc.u64(time.subsec_micros() as u64);
+    }
+
+    let cx = thread.trap_context_mut();
+    let mut regs = [0usize; ELF_NGREG];
+    #[cfg(target_arch = "riscv64")]
+    {
+        regs.copy_from_slice(&cx.user_reg);
+        regs[0] = cx.sepc;
+    }
+    #[cfg(target_arch = "loongarch64")]
+    {
+        regs[..32].copy_from_slice(&cx.user_reg);
+        regs[32] = cx.last_a0;
+        regs[33] = cx.sepc;
+    }
+    for reg in regs {
+        desc.u64(reg as u64);
+    }
+    // pr_fpvalid
+    desc.u32(0);
+    desc.align(8);
+    desc.0
+}
+
+/// Builds `struct elf_prpsinfo` of a process.
+fn prpsinfo(process: &Arc<Task>) -> Vec<u8> {
+    let perm = process.perm_mut().lock().clone();
+    let ppid = process
+        .parent_mut()
+        .lock()
+        .as_ref()
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.pid());
+    let exe = unsafe { process.elf() }.dentry();
+
+    let mut desc = Buf::new();
+    // pr_state, pr_sname, pr_zomb, pr_nice
+    desc.bytes(&[0, b'R', 0, 0]);
+    desc.align(8);
+    // pr_flag
+    desc.u64(0);
+    desc.u32(perm.euid);
+    desc.u32(perm.egid);
+    desc.u32(process.tid() as u32);
+    desc.u32(ppid as u32);
+    desc.u32(process.get_pgid() as u32);
+    desc.u32(perm.sid);
+    let mut fname = [0u8; 16];
+    let name = exe.name().as_bytes();
+    let len = name.len().min(fname.len() - 1);
+    fname[..len].copy_from_slice(&name[..len]);
+    desc.bytes(&fname);
+    let mut psargs = [0u8; 80];
+    let args = process.get_name();
+    let len = args.len().min(psargs.len() - 1);
+    psargs[..len].copy_from_slice(&args.as_bytes()[..len]);
+    desc.bytes(&psargs);
+    desc.0
+}
+
+/// Little-endian byte buffer used to build headers and notes.
+struct Buf(Vec<u8>);
+
+impl Buf {
+    fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, val: u8) {
+        self.0.push(val);
+    }
+
+    fn u16(&mut self, val: u16) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    fn u32(&mut self, val: u32) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    fn u64(&mut self, val: u64) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    /// Pads the buffer with zeros to a multiple of `align` bytes.
+    fn align(&mut self, align: usize) {
+        self.0.resize(self.0.len().next_multiple_of(align), 0);
+    }
+
+    /// Appends an ELF note named `CORE`.
+    fn note(&mut self, note_type: u32, desc: &[u8]) {
+        const NAME: &[u8] = b"CORE\0";
+        self.u32(NAME.len() as u32);
+        self.u32(desc.len() as u32);
+        self.u32(note_type);
+        self.bytes(NAME);
+        self.align(4);
+        self.bytes(desc);
+        self.align(4);
+    }
+}
+
+/// Writer of the core file, which stops at `RLIMIT_CORE`.
+struct CoreWriter {
+    file: Arc<dyn File>,
+    pos: usize,
+    limit: usize,
+}
+
+impl CoreWriter {
+    /// Writes `buf` to the core file.
+    ///
+    /// Returns [`SysError::EFBIG`] if the core file reaches the size limit.
+    async fn write(&mut self, buf: &[u8]) -> SysResult<()> {
+        let len = buf.len().min(self.limit.saturating_sub(self.pos));
+        let mut written = 0;
+        while written < len {
+            match self.file.write(&buf[written..len]).await? {
+                0 => return Err(SysError::EIO),
+                n => written += n,
+            }
+        }
+        self.pos += len;
+        if len < buf.len() {
+            Err(SysError::EFBIG)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Skips `len` bytes in the core file, leaving a hole which reads as zeros.
+    ///
+    /// Returns [`SysError::EFBIG`] if the core file reaches the size limit.
+    fn skip(&mut self, len: usize) -> SysResult<()> {
+        let skipped = len.min(self.limit.saturating_sub(self.pos));
+        self.file.seek(SeekFrom::Current(skipped as i64))?;
+        self.pos += skipped;
+        if skipped < len {
+            Err(SysError::EFBIG)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Writes zeros up to offset `pos` in the core file.
+    async fn pad_to(&mut self, pos: usize) -> SysResult<()> {
+        let zeros = [0u8; PAGE_SIZE];
+        while self.pos < pos {
+            let len = (pos - self.pos).min(PAGE_SIZE);
+            self.write(&zeros[..len]).await?;
+        }
+        Ok(())
+    }
+}
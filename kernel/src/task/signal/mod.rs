@@ -1,3 +1,4 @@
+pub mod coredump;
 pub mod has_signal;
 pub mod pidfd;
 pub mod sig_exec;
@@ -4,11 +4,11 @@ use timer::{IEvent, TimerState};
 
 use systype::error::SysResult;
 
-use super::sig_info::Sig;
+use super::{coredump, sig_info::Sig};
 use crate::task::{
     Task, TaskState,
     manager::TASK_MANAGER,
-    sig_members::{ActionType, SS_DISABLE, SS_ONSTACK, SigActionFlag, SigContext},
+    sig_members::{ActionType, FpState, SS_DISABLE, SS_ONSTACK, SigActionFlag, SigContext},
     signal::sig_info::{LinuxSigInfo, SigDetails, SigInfo, SigSet},
 };
 use crate::vm::user_ptr::UserWritePtr;
@@ -55,7 +55,15 @@ async fn sig_exec(task: Arc<Task>, si: SigInfo, interrupted: &mut bool) -> SysRe
                 log::warn!("[sig_exec] kill init task, ignored");
                 return Ok(false);
             }
-            kill(&task, si.sig);
+            let core_dumped = SigSet::DUMP_MASK.contain_signal(si.sig)
+                && match coredump::do_coredump(&task, &si).await {
+                    Ok(()) => true,
+                    Err(e) => {
+                        log::info!("[sig_exec] no core dumped: {:?}", e);
+                        false
+                    }
+                };
+            kill(&task, si.sig, core_dumped);
             Ok(false)
         }
         ActionType::Stop => {
@@ -79,7 +87,6 @@ async fn sig_exec(task: Arc<Task>, si: SigInfo, interrupted: &mut bool) -> SysRe
             // 信号定义中可能包含了在处理该信号时需要阻塞的其他信号集。
             // 这些信息定义在Action的mask字段
             *task.sig_mask_mut() |= action.mask;
-            // TODO: cx.user_fx.encounter_signal();
             // TODO: sig_stack isn't actually used for now (Even so in Phoenix, because sig_stack is always None)
             // let sig_stack = task.sig_stack_mut().take();
             // let sp = match sig_stack {
@@ -111,7 +118,12 @@ async fn sig_exec(task: Arc<Task>, si: SigInfo, interrupted: &mut bool) -> SysRe
             // extend the sig_stack
             // 在栈上压入一个sig_cx，存储trap frame里的寄存器信息
 
-            let mut new_sp = sp - size_of::<SigContext>();
+            // the handler may clobber the FPU, so save the interrupted FP state
+            cx.save_fx();
+            let vector = cx.vector_used();
+
+            // `SigContext` is 16-byte aligned as `mcontext_t` is
+            let mut new_sp = (sp - size_of::<SigContext>() - SigContext::ext_size(vector)) & !0xf;
             let addr_space = task.addr_space();
             let mut sig_cx_ptr = UserWritePtr::<SigContext>::new(new_sp, &addr_space);
             // TODO: should increase the size of the sig_stack? It seems umi doesn't
@@ -121,12 +133,26 @@ async fn sig_exec(task: Arc<Task>, si: SigInfo, interrupted: &mut bool) -> SysRe
                 stack: *sig_stack,
                 mask: old_mask,
                 sig: [0; 16],
+                #[cfg(target_arch = "loongarch64")]
+                pc: cx.sepc,
                 user_reg: cx.user_reg,
-                fpstate: [0; 66],
+                #[cfg(target_arch = "loongarch64")]
+                sc_flags: 0,
+                fpstate: FpState::from_regs(&cx.user_fx.regs),
             };
-            sig_cx.user_reg[0] = cx.sepc;
+            #[cfg(target_arch = "riscv64")]
+            {
+                sig_cx.user_reg[0] = cx.sepc;
+            }
             // log::debug!("[sig context] sig_cx_ptr: {sig_cx_ptr:?}");
 
+            if vector {
+                let ext = sig_cx.vector_ext(&cx.user_vx.regs, &cx.user_fx.regs, new_sp);
+                let mut ext_ptr =
+                    UserWritePtr::<u8>::new(new_sp + size_of::<SigContext>(), &addr_space);
+                unsafe { ext_ptr.write_array(&ext)? };
+            }
+
             unsafe { sig_cx_ptr.write(sig_cx)? };
 
             // restore the new stack pointer in Task for sigreturn to recover
@@ -217,7 +243,7 @@ unsafe extern "C" {
 }
 
 /// kill the process
-fn kill(task: &Arc<Task>, sig: Sig) {
+fn kill(task: &Arc<Task>, sig: Sig, core_dumped: bool) {
     // exit all the memers of a thread group
     task.with_thread_group(|tg| {
         for t in tg.iter() {
@@ -231,7 +257,7 @@ fn kill(task: &Arc<Task>, sig: Sig) {
     });
     // 将信号放入低7位 (第8位是core dump标志,在gdb调试崩溃程序中用到)
     let mut exit_code = sig.raw() as i32 & 0x7F;
-    if SigSet::DUMP_MASK.contain_signal(sig) {
+    if core_dumped {
         exit_code |= 0x80;
     }
     task.set_exit_code(exit_code);
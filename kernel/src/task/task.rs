@@ -4,14 +4,14 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use config::mm::USER_STACK_SIZE;
 use core::{
     cell::SyncUnsafeCell,
     fmt::Debug,
-    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicUsize, Ordering},
     task::Waker,
 };
 use id_allocator::VecIdAllocator;
-use timer::Timer;
 
 use mm::address::VirtAddr;
 use mutex::{ShareMutex, SpinNoIrqLock, new_share_mutex};
@@ -19,7 +19,10 @@ use osfs::{
     fd_table::{Fd, FdTable},
     sys_root_dentry,
 };
-use systype::time::ITimer;
+use systype::{
+    rlimit::{RLIM_INFINITY, RLIM_NLIMITS, RLimit},
+    time::ITimer,
+};
 use vfs::{dentry::Dentry, file::File};
 
 use super::{
@@ -28,14 +31,24 @@ use super::{
     signal::sig_info::SigSet,
     threadgroup::ThreadGroup,
     tid::{PGid, Pid, Tid, TidAddress, TidHandle, tid_alloc},
+    time::PosixTimer,
     time_stat::TaskTimeStat,
 };
 use crate::{
+    syscall::process::Resource,
     task::{cap::Capabilities, mask::CpuMask, tid::Uid},
     trap::trap_context::TrapContext,
-    vm::addr_space::AddrSpace,
+    vm::{addr_space::AddrSpace, userfault::Userfault},
 };
 
+/// Returns the resource limits of the init process, which all other processes
+/// inherit.
+fn default_rlimits() -> [RLimit; RLIM_NLIMITS] {
+    let mut rlimits = [RLimit::new(RLIM_INFINITY); RLIM_NLIMITS];
+    rlimits[Resource::STACK as usize] = RLimit::one(USER_STACK_SIZE, RLIM_INFINITY);
+    rlimits
+}
+
 /// State of Task
 ///
 /// - Running: When the task is running, in task_executor_unit loop
@@ -147,6 +160,10 @@ pub struct Task {
     // tid_address is the pointer to thread ID.
     tid_address: SyncUnsafeCell<TidAddress>,
 
+    // robust_list is the head of the list of robust futexes held by the thread,
+    // which are released when it exits.
+    robust_list: AtomicUsize,
+
     // fd_table stores open files fd and kernel can
     // find file by its fd and write or read it.
     fd_table: ShareMutex<FdTable>,
@@ -172,12 +189,23 @@ pub struct Task {
 
     is_yield: AtomicBool,
 
+    // pi_boosted is set if the task owns a PI futex which other tasks wait for, and
+    // inherits their priority by not being preempted.
+    pi_boosted: AtomicBool,
+
+    // userfault is the page fault of the task to be handled by a userfaultfd,
+    // which the task waits for after returning from the trap handler.
+    userfault: SpinNoIrqLock<Option<Userfault>>,
+
     itimers: ShareMutex<[ITimer; 3]>,
 
     caps: SyncUnsafeCell<Capabilities>,
 
     pub dumpable: AtomicBool,
 
+    /// Adjustment of the OOM killer badness score, in `-1000..=1000`.
+    pub oom_score_adj: AtomicI32,
+
     pub no_new_privs: AtomicBool,
 
     pub pdeathsig: AtomicU32,
@@ -187,7 +215,13 @@ pub struct Task {
     /// Mask of CPUs allowed for the task.
     cpus_on: SyncUnsafeCell<CpuMask>,
 
-    timers: ShareMutex<Vec<Option<Timer>>>,
+    // POSIX timers created by `timer_create`, indexed by timer id. They are
+    // owned by the process, so threads use the table of their leader.
+    timers: ShareMutex<Vec<Option<PosixTimer>>>,
+
+    // Resource limits, indexed by resource number. Threads share the limits of
+    // their process.
+    rlimits: ShareMutex<[RLimit; RLIM_NLIMITS]>,
 
     perm: ShareMutex<TaskPerm>,
 
@@ -267,6 +301,7 @@ impl Task {
             sig_cx_ptr: AtomicUsize::new(0),
 
             tid_address: SyncUnsafeCell::new(TidAddress::new()),
+            robust_list: AtomicUsize::new(0),
             fd_table: new_share_mutex(FdTable::new(1)),
             pkey_table: new_share_mutex(PKeyTable::new()),
             sigfd_queue: new_share_mutex(Vec::new()),
@@ -275,10 +310,13 @@ impl Task {
             elf: SyncUnsafeCell::new(elf_file),
             is_syscall,
             is_yield: AtomicBool::new(false),
+            pi_boosted: AtomicBool::new(false),
+            userfault: SpinNoIrqLock::new(None),
             itimers: new_share_mutex([ITimer::default(); 3]),
             caps: SyncUnsafeCell::new(Capabilities::new()),
 
             dumpable: AtomicBool::new(true),
+            oom_score_adj: AtomicI32::new(0),
             no_new_privs: AtomicBool::new(false),
             pdeathsig: AtomicU32::new(0),
 
@@ -286,6 +324,7 @@ impl Task {
 
             cpus_on: SyncUnsafeCell::new(CpuMask::CPU0),
             timers: new_share_mutex(Vec::new()),
+            rlimits: new_share_mutex(default_rlimits()),
 
             perm: new_share_mutex(perm),
             debug_buf: AtomicU32::new(0),
@@ -332,10 +371,13 @@ impl Task {
 
         itimers: ShareMutex<[ITimer; 3]>,
         caps: SyncUnsafeCell<Capabilities>,
+        dumpable: AtomicBool,
+        oom_score_adj: AtomicI32,
 
         vfork_parent: Option<Weak<Task>>,
 
         cpus_on: SyncUnsafeCell<CpuMask>,
+        rlimits: ShareMutex<[RLimit; RLIM_NLIMITS]>,
         perm: ShareMutex<TaskPerm>,
 
         name: SyncUnsafeCell<String>,
@@ -366,6 +408,7 @@ impl Task {
             sig_cx_ptr,
 
             tid_address,
+            robust_list: AtomicUsize::new(0),
             fd_table,
             pkey_table: new_share_mutex(PKeyTable::new()),
 
@@ -376,16 +419,20 @@ impl Task {
             elf,
             is_syscall: AtomicBool::new(false),
             is_yield: AtomicBool::new(false),
+            pi_boosted: AtomicBool::new(false),
+            userfault: SpinNoIrqLock::new(None),
             itimers,
             caps,
 
-            dumpable: AtomicBool::new(false),
+            dumpable,
+            oom_score_adj,
             no_new_privs: AtomicBool::new(false),
             pdeathsig: AtomicU32::new(0),
             vfork_parent,
 
             cpus_on,
             timers: new_share_mutex(Vec::new()),
+            rlimits,
             perm,
             debug_buf: AtomicU32::new(0),
 
@@ -499,6 +546,14 @@ impl Task {
         unsafe { &mut *self.tid_address.get() }
     }
 
+    pub fn robust_list(&self) -> usize {
+        self.robust_list.load(Ordering::Relaxed)
+    }
+
+    pub fn set_robust_list(&self, head: usize) {
+        self.robust_list.store(head, Ordering::Relaxed);
+    }
+
     pub fn parent_mut(&self) -> &ShareMutex<Option<Weak<Task>>> {
         &self.parent
     }
@@ -560,10 +615,18 @@ impl Task {
         self.threadgroup.clone()
     }
 
-    pub fn timers_mut(&self) -> ShareMutex<Vec<Option<Timer>>> {
+    pub fn timers_mut(&self) -> ShareMutex<Vec<Option<PosixTimer>>> {
         self.timers.clone()
     }
 
+    pub fn rlimits_mut(&self) -> ShareMutex<[RLimit; RLIM_NLIMITS]> {
+        self.rlimits.clone()
+    }
+
+    pub fn get_rlimit(&self, resource: Resource) -> RLimit {
+        self.rlimits.lock()[resource as usize]
+    }
+
     pub fn pkeytable_mut(&self) -> ShareMutex<PKeyTable> {
         self.pkey_table.clone()
     }
@@ -618,6 +681,15 @@ impl Task {
     pub fn is_yield(&self) -> bool {
         self.is_yield.load(Ordering::Relaxed)
     }
+
+    /// Returns whether the task owns a PI futex which other tasks wait for.
+    pub fn pi_boosted(&self) -> bool {
+        self.pi_boosted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pi_boosted(&self, boosted: bool) {
+        self.pi_boosted.store(boosted, Ordering::Relaxed);
+    }
     // ========== This Part You Can Check the State of Task  ===========
     pub fn is_process(&self) -> bool {
         self.is_process
@@ -667,6 +739,14 @@ impl Task {
         self.is_yield.store(is_yield, Ordering::Relaxed);
     }
 
+    pub fn set_userfault(&self, userfault: Userfault) {
+        *self.userfault.lock() = Some(userfault);
+    }
+
+    pub fn take_userfault(&self) -> Option<Userfault> {
+        self.userfault.lock().take()
+    }
+
     pub fn set_cwd(&self, dentry: Arc<dyn Dentry>) {
         *self.cwd.lock() = dentry;
     }
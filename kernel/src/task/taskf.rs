@@ -4,27 +4,39 @@ use alloc::{
     sync::Arc,
     vec::Vec,
 };
-use core::{cell::SyncUnsafeCell, sync::atomic::AtomicUsize, time::Duration};
+use core::{
+    cell::{Cell, SyncUnsafeCell},
+    cmp,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use arch::{
     hart::hart_shutdown,
     time::{get_time_duration, get_time_ms, get_time_us},
 };
 use config::{
+    inode::InodeType,
+    mm::{PAGE_SIZE, USER_STACK_SIZE},
     process::{CloneFlags, INIT_PROC_ID},
-    vfs::AtFd,
+    vfs::{AtFd, OpenFlags},
 };
 use mutex::{SpinNoIrqLock, new_share_mutex};
 use osfs::{FS_MANAGER, proc::create_thread_stat_file, sys_root_dentry};
 use osfuture::suspend_now;
 use shm::manager::SHARED_MEMORY_MANAGER;
-use systype::{error::SysResult, memory_flags::MappingFlags, time::ITimer};
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MappingFlags,
+    rlimit::RLIM_INFINITY,
+    time::ITimer,
+};
 use timer::{TIMER_MANAGER, Timer};
 use vfs::{dentry::Dentry, file::File, fstype::FileSystemType, path::Path};
 
 use super::{
     cap::CapabilitiesFlags,
-    futex::{FutexHashKey, futex_manager},
+    futex::{FUTEX_BITSET_MATCH_ANY, FutexHashKey, futex_manager, release_futexes},
     future,
     manager::TASK_MANAGER,
     process_manager::PROCESS_GROUP_MANAGER,
@@ -41,6 +53,8 @@ use crate::vm::{
     vm_area::{TypedArea, VmaFlags},
 };
 use crate::task::wait_queue::WAIT_QUEUE_MANAGER;
+use crate::syscall::process::Resource;
+use crate::processor::current_hart;
 
 impl Task {
     /// Suspends the Task until it is waken or time out
@@ -122,14 +136,20 @@ impl Task {
         let addrspace = AddrSpace::build_user()?;
         let (entry_point, auxv) = addrspace.load_elf(elf_file.clone())?;
         // log::debug!("[execve] load elf: over");
-        let stack_top = addrspace.map_stack()?;
+        let stack_top = addrspace.map_stack(self.get_rlimit(Resource::STACK).rlim_cur)?;
         addrspace.map_heap()?;
 
+        // The robust futexes are released with the old address space.
+        release_futexes(self);
+
+        let hart_id = current_hart().id;
+        self.addr_space().deactivate_on(hart_id);
         // SAFETY: We should destroy other threads of this process before,
         // but multi-threading is not supported now, so this is safe.
         unsafe {
             self.set_addrspace(addrspace);
         }
+        self.addr_space().activate_on(hart_id);
         self.switch_addr_space();
 
         // Use current time as random seed
@@ -150,6 +170,8 @@ impl Task {
         *self.name_mut() = name;
         self.with_mut_fdtable(|table| table.close_cloexec());
         self.with_mut_sig_handler(|handlers| handlers.reset_user_defined());
+        self.clear_posix_timers();
+        self.dumpable.store(true, Ordering::Relaxed);
 
         Ok(())
     }
@@ -159,7 +181,7 @@ impl Task {
         let addrspace = AddrSpace::build_user().unwrap();
         let (entry_point, _) = addrspace.load_elf(elf_file.clone()).unwrap();
         log::debug!("[spawn_from_elf] entry: {:#x}", entry_point.to_usize());
-        let stack = addrspace.map_stack().unwrap();
+        let stack = addrspace.map_stack(USER_STACK_SIZE).unwrap();
         addrspace.map_heap().unwrap();
         log::debug!("[spawn_from_elf] before created");
         let _task = Task::new(
@@ -204,6 +226,7 @@ impl Task {
     /// fork a application
     pub fn fork(self: &Arc<Self>, cloneflags: CloneFlags) -> Arc<Self> {
         let tid = tid_alloc();
+        self.trap_context_mut().save_fx();
         let trap_context = SyncUnsafeCell::new(*self.trap_context_mut());
         let state = SpinNoIrqLock::new(self.get_state());
 
@@ -304,7 +327,19 @@ impl Task {
         };
         fd_table.lock().set_tid(tid.0 as u64);
 
+        // The userfaultfds which ask for fork events get a userfaultfd for the child.
+        if !cloneflags.contains(CloneFlags::VM) {
+            self.addr_space().fork_userfaultfds(&addr_space);
+        }
+
         let perm = (*self.perm_mut().lock()).clone();
+        let rlimits = if cloneflags.contains(CloneFlags::THREAD) {
+            self.rlimits_mut()
+        } else {
+            new_share_mutex(*self.rlimits_mut().lock())
+        };
+        let dumpable = AtomicBool::new(self.process().dumpable.load(Ordering::Relaxed));
+        let oom_score_adj = AtomicI32::new(self.process().oom_score_adj.load(Ordering::Relaxed));
 
         let cpus_on = *self.cpus_on_mut();
         let name = SyncUnsafeCell::new(name);
@@ -337,8 +372,11 @@ impl Task {
             elf,
             itimers,
             caps,
+            dumpable,
+            oom_score_adj,
             vfork_parent,
             SyncUnsafeCell::new(cpus_on),
+            rlimits,
             new_share_mutex(perm),
             name,
         ));
@@ -447,6 +485,8 @@ impl Task {
             hart_shutdown();
         }
 
+        release_futexes(self);
+
         // release futexes in dropped threads.
         if let Some(address) = self.tid_address_mut().clear_child_tid {
             log::info!("[exit] clear_child_tid: {:#x}", address);
@@ -462,13 +502,11 @@ impl Task {
                     .is_ok()
             } {
                 if let Ok(key) = FutexHashKey::new_share_key(address, &self.addr_space()) {
-                    let _ = futex_manager(false, 0xffffffff).wake(&key, 1);
-                    let _ = futex_manager(true, 0xffffffff).wake(&key, 1);
+                    futex_manager().wake(&key, 1, FUTEX_BITSET_MATCH_ANY);
                 }
 
                 if let Ok(key) = FutexHashKey::new_private_key(address, self.addr_space()) {
-                    let _ = futex_manager(false, 0xffffffff).wake(&key, 1);
-                    let _ = futex_manager(true, 0xffffffff).wake(&key, 1);
+                    futex_manager().wake(&key, 1, FUTEX_BITSET_MATCH_ANY);
                 }
             }
         }
@@ -598,6 +636,10 @@ impl Task {
 
         self.with_mut_fdtable(|table| table.clear());
 
+        // CPU-time timers lock the thread group with the timer table held.
+        drop(threadgroup);
+        process.clear_posix_timers();
+
         log::debug!("[Task::exit] task {} exit finished", self.tid());
     }
 
@@ -610,6 +652,7 @@ impl Task {
              Pid:\t{}\n\
              PPid:\t{}\n\
              Threads:\t{}\n\
+             VmRSS:\t{} kB\n\
              Cpus_allowed:\t1\n\
              Cpus_allowed_list:\t0\n\
              Mems_allowed:\t1\n\
@@ -619,6 +662,7 @@ impl Task {
             task.tid(),
             task.ppid(),
             1,
+            task.addr_space().rss() * PAGE_SIZE / 1024,
         );
 
         content
@@ -738,6 +782,7 @@ impl Task {
                 TypedArea::Heap(_) => ("[heap]".to_string(), 0, 0, 0, 0),
                 TypedArea::SharedMemory(_) => ("[shared]".to_string(), 0, 0, 0, 0),
                 TypedArea::Offset(_) => ("[kernel]".to_string(), 0, 0, 0, 0),
+                TypedArea::Special(special) => (special.name().to_string(), 0, 0, 0, 0),
             };
 
             // Format the maps line:
@@ -778,7 +823,146 @@ impl Task {
         self.tid() == pid || self.has_capability(CapabilitiesFlags::CAP_SYS_PTRACE)
     }
 
+    /// Checks whether the task may access the memory of the process `target` as a
+    /// tracer would, as required by `process_vm_readv()`, `process_vm_writev()`,
+    /// `get_robust_list()` and `/proc/<pid>/mem`. The access is allowed within a
+    /// process, if the real, effective and saved user and group IDs of `target` all
+    /// match the effective IDs of the task and `target` is dumpable, or if the task has
+    /// `CAP_SYS_PTRACE`.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EPERM`] if the access is not allowed.
+    pub fn check_ptrace_access(self: &Arc<Self>, target: &Arc<Task>) -> SysResult<()> {
+        if Arc::ptr_eq(&self.process(), &target.process())
+            || self.has_capability(CapabilitiesFlags::CAP_SYS_PTRACE)
+        {
+            return Ok(());
+        }
+        let (euid, egid) = {
+            let perm = self.perm_mut();
+            let perm = perm.lock();
+            (perm.euid, perm.egid)
+        };
+        let target_perm = target.perm_mut();
+        let target_perm = target_perm.lock();
+        let uids = [target_perm.ruid, target_perm.euid, target_perm.suid];
+        let gids = [target_perm.rgid, target_perm.egid, target_perm.sgid];
+        if uids.iter().all(|&uid| uid == euid)
+            && gids.iter().all(|&gid| gid == egid)
+            && target.process().dumpable.load(Ordering::Relaxed)
+        {
+            Ok(())
+        } else {
+            Err(SysError::EPERM)
+        }
+    }
+
+    /// Checks whether the task may get or set the resource limits of the process
+    /// `target`, as required by `prlimit()`. It is allowed within a process, if the
+    /// real, effective and saved user and group IDs of `target` all match the real IDs
+    /// of the task, or if the task has `CAP_SYS_RESOURCE`.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EPERM`] if it is not allowed.
+    pub fn check_prlimit_access(self: &Arc<Self>, target: &Arc<Task>) -> SysResult<()> {
+        if Arc::ptr_eq(&self.process(), &target.process())
+            || self.has_capability(CapabilitiesFlags::CAP_SYS_RESOURCE)
+        {
+            return Ok(());
+        }
+        let (ruid, rgid) = {
+            let perm = self.perm_mut();
+            let perm = perm.lock();
+            (perm.ruid, perm.rgid)
+        };
+        let target_perm = target.perm_mut();
+        let target_perm = target_perm.lock();
+        let uids = [target_perm.ruid, target_perm.euid, target_perm.suid];
+        let gids = [target_perm.rgid, target_perm.egid, target_perm.sgid];
+        if uids.iter().all(|&uid| uid == ruid) && gids.iter().all(|&gid| gid == rgid) {
+            Ok(())
+        } else {
+            Err(SysError::EPERM)
+        }
+    }
+
     pub fn has_capability(&self, cap: CapabilitiesFlags) -> bool {
         self.capability().has_effective(cap)
     }
+
+    /// Checks whether the real user of the task may own one more task under
+    /// `RLIMIT_NPROC`. Root and tasks with `CAP_SYS_RESOURCE` or `CAP_SYS_ADMIN` are
+    /// not limited.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EAGAIN`] if the limit is reached.
+    pub fn check_nproc_limit(&self) -> SysResult<()> {
+        let limit = self.get_rlimit(Resource::NPROC).rlim_cur;
+        let ruid = self.perm_mut().lock().ruid;
+        if limit == RLIM_INFINITY
+            || ruid == 0
+            || self.has_capability(CapabilitiesFlags::CAP_SYS_RESOURCE)
+            || self.has_capability(CapabilitiesFlags::CAP_SYS_ADMIN)
+        {
+            return Ok(());
+        }
+        let count = Cell::new(0);
+        let _ = TASK_MANAGER.for_each(|task| {
+            if task.perm_mut().lock().ruid == ruid {
+                count.set(count.get() + 1);
+            }
+            Ok(())
+        });
+        if count.get() >= limit {
+            return Err(SysError::EAGAIN);
+        }
+        Ok(())
+    }
+
+    /// Checks whether `len` more bytes may be mapped into the address space under
+    /// `RLIMIT_AS`.
+    ///
+    /// # Errors
+    /// Returns [`SysError::ENOMEM`] if the limit would be exceeded.
+    pub fn check_as_limit(&self, len: usize) -> SysResult<()> {
+        let limit = self.get_rlimit(Resource::AS).rlim_cur;
+        if limit != RLIM_INFINITY && self.addr_space().total_vm().saturating_add(len) > limit {
+            return Err(SysError::ENOMEM);
+        }
+        Ok(())
+    }
+
+    /// Applies `RLIMIT_FSIZE` to writing `len` bytes to `file` at `offset`, or at the
+    /// current write position if `offset` is `None`. Only regular files are limited.
+    ///
+    /// Returns the number of bytes which may be written.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EFBIG`] and sends `SIGXFSZ` to the task if the write starts
+    /// at or beyond the limit.
+    pub fn check_fsize_limit(
+        &self,
+        file: &Arc<dyn File>,
+        offset: Option<usize>,
+        len: usize,
+    ) -> SysResult<usize> {
+        let limit = self.get_rlimit(Resource::FSIZE).rlim_cur;
+        if limit == RLIM_INFINITY || len == 0 || file.inode().inotype() != InodeType::File {
+            return Ok(len);
+        }
+        let offset = match offset {
+            Some(offset) => offset,
+            None if file.flags().contains(OpenFlags::O_APPEND) => file.inode().size(),
+            None => file.pos(),
+        };
+        if offset >= limit {
+            self.receive_siginfo(SigInfo {
+                sig: Sig::SIGXFSZ,
+                code: SigInfo::KERNEL,
+                details: SigDetails::None,
+            });
+            return Err(SysError::EFBIG);
+        }
+        Ok(cmp::min(len, limit - offset))
+    }
 }
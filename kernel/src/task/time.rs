@@ -1,12 +1,18 @@
 use alloc::sync::{Arc, Weak};
 use core::time::Duration;
 
+use arch::time::get_time_duration;
+use systype::rlimit::RLIM_INFINITY;
+
 use timer::{IEvent, TimerState};
 
 use super::{
     Task,
-    signal::sig_info::{Sig, SigDetails, SigInfo},
+    manager::TASK_MANAGER,
+    signal::sig_info::{LinuxSigInfo, Sig, SigDetails, SigInfo},
+    tid::Tid,
 };
+use crate::syscall::process::Resource;
 
 #[derive(Default, Debug)]
 pub struct RealITimer {
@@ -46,7 +52,265 @@ impl IEvent for RealITimer {
     }
 }
 
+/// The clock a [`PosixTimer`] counts against.
+///
+/// Wall clocks (`CLOCK_REALTIME`, `CLOCK_MONOTONIC`, `CLOCK_BOOTTIME`, ...) are
+/// driven by the global `TIMER_MANAGER`, while CPU-time clocks are checked
+/// against `TaskTimeStat` by [`Task::check_cpu_timers`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerClock {
+    Wall,
+    ProcessCpu,
+    ThreadCpu,
+}
+
+/// How a [`PosixTimer`] notifies the process on expiration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerNotify {
+    /// `SIGEV_NONE`: nothing is delivered, the timer can only be polled.
+    None,
+    /// `SIGEV_SIGNAL`: a signal is sent to the process.
+    Signal,
+    /// `SIGEV_SIGNAL | SIGEV_THREAD_ID`: a signal is sent to a specific thread.
+    Thread(Tid),
+}
+
+/// Overrun counts are capped to `DELAYTIMER_MAX` as POSIX requires.
+pub const DELAYTIMER_MAX: usize = i32::MAX as usize;
+
+/// `PosixTimer` is a per-process interval timer created by `timer_create()`.
+///
+/// `next_expire` is measured on the clock selected by `clock`: for wall timers
+/// it is compared with `get_time_duration()`, for CPU-time timers with the
+/// consumed CPU time. A zero `next_expire` means the timer is disarmed.
+///
+/// Every `timer_settime()` allocates a fresh `id`. Timers already queued in
+/// `TIMER_MANAGER` carry the id they were armed with, and are dropped when it
+/// no longer matches, as [`RealITimer`] does.
+#[derive(Debug, Clone)]
+pub struct PosixTimer {
+    pub clock: TimerClock,
+    /// Clockid passed to `timer_create()`, which tells how absolute times of the
+    /// timer are measured.
+    pub clockid: usize,
+    pub notify: TimerNotify,
+    pub signo: i32,
+    pub value: usize,
+    /// Thread that created the timer, used for `CLOCK_THREAD_CPUTIME_ID`.
+    pub owner: Tid,
+    pub interval: Duration,
+    pub next_expire: Duration,
+    pub id: usize,
+    /// Expirations accumulated while the last signal was still pending.
+    pub overrun: usize,
+    /// Overrun count of the previously delivered signal.
+    pub last_overrun: usize,
+}
+
+impl PosixTimer {
+    pub fn new(
+        clock: TimerClock,
+        clockid: usize,
+        notify: TimerNotify,
+        signo: i32,
+        value: usize,
+        owner: Tid,
+    ) -> Self {
+        Self {
+            clock,
+            clockid,
+            notify,
+            signo,
+            value,
+            owner,
+            interval: Duration::ZERO,
+            next_expire: Duration::ZERO,
+            id: 0,
+            overrun: 0,
+            last_overrun: 0,
+        }
+    }
+
+    pub fn is_armed(&self) -> bool {
+        !self.next_expire.is_zero()
+    }
+
+    /// Returns the overrun count reported by `timer_getoverrun()`.
+    ///
+    /// While a signal of this timer is still queued, the count of the
+    /// previously delivered one is reported.
+    pub fn overrun(&self, pending: bool) -> usize {
+        if pending {
+            self.last_overrun
+        } else {
+            self.overrun
+        }
+    }
+
+    /// Handles an expiration of the timer at `now` (on the timer's own clock).
+    ///
+    /// Periods missed since `next_expire` are counted as overruns. Returns
+    /// whether the timer remains armed.
+    fn expire(&mut self, now: Duration, target: &Task, timerid: usize) -> bool {
+        let mut expirations = 1;
+        if self.interval.is_zero() {
+            self.next_expire = Duration::ZERO;
+        } else {
+            let late = now.saturating_sub(self.next_expire);
+            let missed = (late.as_nanos() / self.interval.as_nanos()) as u32;
+            expirations += missed as usize;
+            self.next_expire += self.interval * (missed + 1);
+        }
+
+        if self.notify != TimerNotify::None {
+            let sig = Sig::from_i32(self.signo);
+            if target.sig_manager_mut().bitmap.contain_signal(sig) {
+                self.overrun = (self.overrun + expirations).min(DELAYTIMER_MAX);
+            } else {
+                self.last_overrun = self.overrun;
+                self.overrun = (expirations - 1).min(DELAYTIMER_MAX);
+                target.receive_siginfo(self.siginfo(timerid, target));
+            }
+        }
+
+        self.is_armed()
+    }
+
+    fn siginfo(&self, timerid: usize, target: &Task) -> SigInfo {
+        // `si_timerid`, `si_overrun` and `si_sigval` overlay `si_pid`, `si_uid`
+        // and the following 8 bytes of the Linux `siginfo_t` union.
+        let info = LinuxSigInfo {
+            si_signo: self.signo,
+            si_code: SigInfo::TIMER,
+            si_pid: timerid as i32,
+            si_uid: self.overrun as u32,
+            si_status: self.value as i32,
+            si_utime: (self.value >> 32) as u32,
+            si_value: self.value as u64,
+            ..Default::default()
+        };
+        SigInfo {
+            sig: Sig::from_i32(self.signo),
+            code: SigInfo::TIMER,
+            details: SigDetails::Kill {
+                pid: target.tid(),
+                siginfo: Some(info),
+            },
+        }
+    }
+}
+
+/// `PosixTimerEvent` is the `TIMER_MANAGER` callback of a wall-clock
+/// [`PosixTimer`].
+#[derive(Debug)]
+pub struct PosixTimerEvent {
+    pub task: Weak<Task>,
+    pub timerid: usize,
+    pub id: usize,
+}
+
+impl IEvent for PosixTimerEvent {
+    fn callback(self: Arc<Self>) -> TimerState {
+        let Some(task) = self.task.upgrade() else {
+            return TimerState::Cancelled;
+        };
+        let lock = task.timers_mut();
+        let mut timers = lock.lock();
+        let Some(Some(timer)) = timers.get_mut(self.timerid) else {
+            return TimerState::Cancelled;
+        };
+        if timer.id != self.id || !timer.is_armed() {
+            log::debug!("[PosixTimerEvent] timer {} is stale", self.timerid);
+            return TimerState::Cancelled;
+        }
+
+        let target = match timer.notify {
+            TimerNotify::Thread(tid) => TASK_MANAGER.get_task(tid),
+            _ => Some(task.clone()),
+        };
+        let Some(target) = target else {
+            timer.next_expire = Duration::ZERO;
+            return TimerState::Cancelled;
+        };
+
+        if timer.expire(get_time_duration(), &target, self.timerid) {
+            TimerState::Active
+        } else {
+            TimerState::Cancelled
+        }
+    }
+}
+
 impl Task {
+    /// Fires the CPU-time POSIX timers of the process that have expired.
+    ///
+    /// CPU-time clocks only advance while a thread runs, so this is checked
+    /// from the task loop instead of the global `TIMER_MANAGER`.
+    pub fn check_cpu_timers(self: &Arc<Self>) {
+        let process = self.process();
+        let lock = process.timers_mut();
+        let mut timers = lock.lock();
+        let mut process_cputime = None;
+        for (timerid, timer) in timers.iter_mut().enumerate() {
+            let Some(timer) = timer.as_mut().filter(|t| t.is_armed()) else {
+                continue;
+            };
+            let now = match timer.clock {
+                TimerClock::Wall => continue,
+                TimerClock::ProcessCpu => {
+                    *process_cputime.get_or_insert_with(|| self.get_process_cputime())
+                }
+                TimerClock::ThreadCpu if timer.owner == self.tid() => self.timer_mut().cpu_time(),
+                TimerClock::ThreadCpu => continue,
+            };
+            if now < timer.next_expire {
+                continue;
+            }
+            let target = match timer.notify {
+                TimerNotify::Thread(tid) => TASK_MANAGER.get_task(tid),
+                _ => Some(process.clone()),
+            };
+            match target {
+                Some(target) => {
+                    timer.expire(now, &target, timerid);
+                }
+                None => timer.next_expire = Duration::ZERO,
+            }
+        }
+    }
+
+    /// Enforces `RLIMIT_CPU` on the process.
+    ///
+    /// As on Linux, `SIGXCPU` is sent when the CPU time reaches the soft limit, and the
+    /// soft limit is then raised by one second, so that the signal repeats every second
+    /// until the hard limit is reached, where `SIGKILL` is sent.
+    pub fn check_cpu_limit(self: &Arc<Self>) {
+        let process = self.process();
+        let limit = process.get_rlimit(Resource::CPU);
+        if limit.rlim_cur == RLIM_INFINITY {
+            return;
+        }
+        let cputime = process.get_process_cputime().as_secs() as usize;
+        let sig = if cputime >= limit.rlim_max {
+            Sig::SIGKILL
+        } else if cputime >= limit.rlim_cur {
+            process.rlimits_mut().lock()[Resource::CPU as usize].rlim_cur += 1;
+            Sig::SIGXCPU
+        } else {
+            return;
+        };
+        process.receive_siginfo(SigInfo {
+            sig,
+            code: SigInfo::KERNEL,
+            details: SigDetails::None,
+        });
+    }
+
+    /// Deletes all POSIX timers of the process, as on `execve()` and exit.
+    pub fn clear_posix_timers(&self) {
+        self.timers_mut().lock().clear();
+    }
+
     pub fn get_process_ustime(&self) -> (Duration, Duration) {
         self.with_thread_group(|tg| -> (Duration, Duration) {
             tg.iter()
@@ -2,9 +2,12 @@ use driver::{print, println};
 #[cfg(target_arch = "loongarch64")]
 use loongArch64::register::{CpuMode, prmd};
 #[cfg(target_arch = "riscv64")]
-use riscv::register::sstatus::{self, SPP, Sstatus};
+use riscv::register::sstatus::{self, FS, SPP, Sstatus};
 
-use arch::trap::disable_interrupt;
+use arch::{
+    fpu::{self, FloatRegs, VectorRegs},
+    trap::disable_interrupt,
+};
 
 /// when sp points to user stack of a task/process,
 /// sscratch(in RISCV) points to the start
@@ -39,6 +42,72 @@ pub struct TrapContext {
     pub k_tp: usize, // 49, thread pointer, the kernel hart(which records CPU status) address, useless for now
 
     pub last_a0: usize,
+
+    // floating-point and vector contexts, not touched by the trap assembly
+    pub user_fx: UserFloatContext,
+    pub user_vx: UserVectorContext,
+}
+
+/// Floating-point context of a user task.
+///
+/// The floating-point registers are switched lazily. They are saved only if the task
+/// has dirtied them, and are restored only when the task first uses the FPU after it
+/// is switched in, which traps because the FPU is then disabled for the task.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct UserFloatContext {
+    pub regs: FloatRegs,
+    /// Whether the hart holds floating-point registers newer than `regs`.
+    need_save: bool,
+    /// Whether `regs` must be loaded into the hart on the next use of the FPU.
+    need_restore: bool,
+}
+
+impl UserFloatContext {
+    pub const fn new() -> Self {
+        Self {
+            regs: FloatRegs::new(),
+            need_save: false,
+            need_restore: true,
+        }
+    }
+}
+
+/// `PR_RISCV_V_VSTATE_CTRL_*` flags which control whether a task may use the vector
+/// unit, now and after `execve()`.
+pub const VSTATE_CTRL_DEFAULT: usize = 0;
+pub const VSTATE_CTRL_OFF: usize = 1;
+pub const VSTATE_CTRL_ON: usize = 2;
+pub const VSTATE_CTRL_INHERIT: usize = 1 << 4;
+pub const VSTATE_CTRL_CUR_MASK: usize = 0x3;
+pub const VSTATE_CTRL_NEXT_MASK: usize = 0xc;
+pub const VSTATE_CTRL_MASK: usize = 0x1f;
+
+/// Vector context of a user task, which is switched lazily like [`UserFloatContext`].
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct UserVectorContext {
+    pub regs: VectorRegs,
+    /// Whether the hart holds vector registers newer than `regs`.
+    need_save: bool,
+    /// Whether `regs` must be loaded into the hart on the next use of the vector unit.
+    need_restore: bool,
+    /// Whether the task has used the vector unit since `execve()`.
+    used: bool,
+    /// `PR_RISCV_V_VSTATE_CTRL_*` flags, whose current mode is never the default.
+    ctrl: usize,
+}
+
+impl UserVectorContext {
+    pub const fn new() -> Self {
+        Self {
+            regs: VectorRegs::new(),
+            need_save: false,
+            need_restore: true,
+            used: false,
+            ctrl: VSTATE_CTRL_ON,
+        }
+    }
 }
 
 impl TrapContext {
@@ -88,6 +157,8 @@ impl TrapContext {
             k_fp: 0,
             k_tp: 0,
             last_a0: 0,
+            user_fx: UserFloatContext::new(),
+            user_vx: UserVectorContext::new(),
         };
 
         context.set_user_sp(sp);
@@ -101,8 +172,8 @@ impl TrapContext {
     /// will have a new user stack and pass it into `init_user()` to initializes
     /// it.
     ///
-    /// Then `init_user()` call `clear_fx()` to clean its float environment and
-    /// initializes float regs for `execve()` function.
+    /// Then `init_user()` calls `clear_fx()` to clean its float environment, so
+    /// that the new program starts with zeroed float regs.
     pub fn init_user(
         &mut self,
         user_sp: usize,
@@ -128,6 +199,192 @@ impl TrapContext {
             self.user_reg[6] = envp; // a2存放在6*8
             self.sepc = entry;
         }
+
+        self.clear_fx();
+    }
+
+    /// Records whether the task has dirtied the FPU or the vector unit, called when it
+    /// traps into the kernel.
+    pub fn mark_fx_save(&mut self) {
+        #[cfg(target_arch = "riscv64")]
+        {
+            if self.sstatus.fs() == FS::Dirty {
+                self.user_fx.need_save = true;
+                self.sstatus.set_fs(FS::Clean);
+            }
+            if fpu::vector_state(&self.sstatus) == FS::Dirty {
+                self.user_vx.need_save = true;
+                fpu::set_vector_state(&mut self.sstatus, FS::Clean);
+            }
+        }
+
+        // LoongArch has no dirty state; the units are enabled only after they have been
+        // restored, and are then assumed to be dirty.
+        #[cfg(target_arch = "loongarch64")]
+        {
+            if fpu::fpu_enabled() && !self.user_fx.need_restore {
+                self.user_fx.need_save = true;
+            }
+            if fpu::vector_enabled() && !self.user_vx.need_restore {
+                self.user_vx.need_save = true;
+            }
+        }
+    }
+
+    /// Saves the floating-point and vector registers of the hart if the task has
+    /// dirtied them.
+    ///
+    /// This must be called before the task is switched out, and before its floating-
+    /// point context is read, e.g., when it forks.
+    pub fn save_fx(&mut self) {
+        if self.user_fx.need_save {
+            self.user_fx.regs.save();
+            self.user_fx.need_save = false;
+        }
+        if self.user_vx.need_save {
+            self.user_vx.regs.save();
+            self.user_vx.need_save = false;
+        }
+    }
+
+    /// Disables the FPU and the vector unit for the task, so that their registers are
+    /// restored when it first uses them. Called when the task is switched in.
+    pub fn disable_fx(&mut self) {
+        self.user_fx.need_restore = true;
+        self.user_vx.need_restore = true;
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            self.sstatus.set_fs(FS::Off);
+            fpu::set_vector_state(&mut self.sstatus, FS::Off);
+        }
+
+        #[cfg(target_arch = "loongarch64")]
+        {
+            fpu::set_fpu_enabled(false);
+            fpu::set_vector_enabled(false);
+        }
+    }
+
+    /// Restores the floating-point registers of the task into the hart and enables the
+    /// FPU for it, called on the trap raised by its first use of the FPU.
+    ///
+    /// Returns `false` if the registers were already restored, in which case the trap
+    /// was not caused by the disabled FPU.
+    pub fn restore_fx(&mut self) -> bool {
+        if !self.user_fx.need_restore {
+            return false;
+        }
+
+        // The floating-point registers alias the vector registers in LoongArch, so both
+        // are restored at once if the task uses the vector unit.
+        #[cfg(target_arch = "loongarch64")]
+        if self.user_vx.used && self.user_vx.need_restore {
+            return self.restore_vx();
+        }
+
+        self.user_fx.regs.restore();
+        self.user_fx.need_restore = false;
+
+        #[cfg(target_arch = "riscv64")]
+        self.sstatus.set_fs(FS::Clean);
+
+        #[cfg(target_arch = "loongarch64")]
+        fpu::set_fpu_enabled(true);
+
+        true
+    }
+
+    /// Restores the vector registers of the task into the hart and enables the vector
+    /// unit for it, called on the trap raised by its first use of the vector unit.
+    ///
+    /// Returns `false` if the registers were already restored, or if the task may not
+    /// use the vector unit.
+    pub fn restore_vx(&mut self) -> bool {
+        if !self.vector_allowed() || !self.user_vx.need_restore {
+            return false;
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        {
+            self.user_vx.regs.restore();
+            fpu::set_vector_state(&mut self.sstatus, FS::Clean);
+        }
+
+        // Restoring the vector registers clobbers the floating-point registers, which
+        // are therefore restored after them.
+        #[cfg(target_arch = "loongarch64")]
+        {
+            if !self.user_fx.need_restore {
+                self.user_fx.regs.save();
+            }
+            self.user_vx.regs.restore();
+            self.user_fx.regs.restore();
+            self.user_fx.need_restore = false;
+            fpu::set_fpu_enabled(true);
+        }
+
+        self.user_vx.need_restore = false;
+        self.user_vx.used = true;
+        true
+    }
+
+    /// Returns whether the task may use the vector unit.
+    pub fn vector_allowed(&self) -> bool {
+        fpu::has_vector() && self.user_vx.ctrl & VSTATE_CTRL_CUR_MASK != VSTATE_CTRL_OFF
+    }
+
+    /// Returns whether the task has used the vector unit since `execve()`, in which
+    /// case its vector registers are saved in signal frames.
+    pub fn vector_used(&self) -> bool {
+        self.user_vx.used
+    }
+
+    /// Returns the `PR_RISCV_V_VSTATE_CTRL_*` flags of the task.
+    pub fn vector_ctrl(&self) -> usize {
+        self.user_vx.ctrl
+    }
+
+    /// Sets the `PR_RISCV_V_VSTATE_CTRL_*` flags of the task. A default current mode
+    /// means on.
+    pub fn set_vector_ctrl(&mut self, ctrl: usize) {
+        self.user_vx.ctrl = match ctrl & VSTATE_CTRL_CUR_MASK {
+            VSTATE_CTRL_DEFAULT => ctrl | VSTATE_CTRL_ON,
+            _ => ctrl,
+        };
+    }
+
+    /// Replaces the floating-point registers of the task, e.g., on `sigreturn()`. The
+    /// current registers of the hart are discarded.
+    pub fn set_fx(&mut self, regs: FloatRegs) {
+        self.user_fx.regs = regs;
+        self.user_fx.need_save = false;
+        self.disable_fx();
+    }
+
+    /// Replaces the vector registers of the task, e.g., on `sigreturn()`. The current
+    /// registers of the hart are discarded.
+    pub fn set_vx(&mut self, regs: VectorRegs) {
+        self.user_vx.regs = regs;
+        self.user_vx.need_save = false;
+        self.disable_fx();
+    }
+
+    /// Resets the floating-point and vector contexts on `execve()`, where the vector
+    /// control for the next program takes effect.
+    pub fn clear_fx(&mut self) {
+        let ctrl = self.user_vx.ctrl;
+        let next = (ctrl & VSTATE_CTRL_NEXT_MASK) >> 2;
+        let cur = match next {
+            VSTATE_CTRL_DEFAULT if ctrl & VSTATE_CTRL_INHERIT != 0 => ctrl & VSTATE_CTRL_CUR_MASK,
+            VSTATE_CTRL_DEFAULT => VSTATE_CTRL_ON,
+            next => next,
+        };
+
+        self.user_fx = UserFloatContext::new();
+        self.user_vx = UserVectorContext::new();
+        self.user_vx.ctrl = cur | ctrl & VSTATE_CTRL_INHERIT;
+        self.disable_fx();
     }
 
     /// this function can be called to get syscall number
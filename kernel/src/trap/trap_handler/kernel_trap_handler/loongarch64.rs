@@ -10,7 +10,7 @@ use mm::address::VirtAddr;
 use timer::TIMER_MANAGER;
 
 use crate::osdriver::manager::device_manager;
-use crate::processor::current_task;
+use crate::processor::{current_task, ipi};
 use crate::{task::TaskState, trap::trap_handler::TRAP_STATS};
 
 use super::unaligned_la::emulate_load_store_insn;
@@ -55,6 +55,10 @@ fn kernel_interrupt_handler(i: Interrupt) {
             device_manager().handle_irq();
             TRAP_STATS.inc(i as usize);
         }
+        Interrupt::IPI => {
+            ipi::handle_ipi();
+            TRAP_STATS.inc(i as usize);
+        }
         _ => trap_panic(),
     }
 }
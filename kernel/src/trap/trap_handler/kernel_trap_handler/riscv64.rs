@@ -9,6 +9,7 @@ use arch::time::{get_time_duration, set_nx_timer_irq};
 use mm::address::{PhysPageNum, VirtAddr};
 use timer::TIMER_MANAGER;
 
+use crate::processor::ipi;
 use crate::trap::trap_context::KernelTrapContext;
 use crate::vm::trace_page_table_lookup;
 use crate::{osdriver::manager::device_manager, trap::trap_handler::TRAP_STATS};
@@ -39,6 +40,10 @@ fn interrupt_handler(i: Interrupt) {
             device_manager().handle_irq();
             TRAP_STATS.inc(i.number());
         }
+        Interrupt::SupervisorSoft => {
+            ipi::handle_ipi();
+            TRAP_STATS.inc(i.number());
+        }
         _ => trap_panic(),
     }
 }
@@ -11,7 +11,7 @@ use loongArch64::register::{
 use crate::osdriver::manager::device_manager;
 use crate::trap::trap_context::KernelTrapContext;
 use crate::{
-    processor::current_hart,
+    processor::{current_hart, ipi},
     task::{
         Task, TaskState,
         signal::sig_info::{Sig, SigDetails, SigInfo},
@@ -20,7 +20,7 @@ use crate::{
     vm::user_ptr::UserReadPtr,
 };
 use arch::{
-    mm::tlb_fill,
+    mm::{tlb_fill, tlb_flush_addr},
     time::{get_time_duration, set_nx_timer_irq},
     trap::TIMER_IRQ,
 };
@@ -39,6 +39,18 @@ pub fn trap_handler(task: &Task) {
     let current = get_time_duration();
     TIMER_MANAGER.check(current);
 
+    // LSX and LASX disabled exceptions, which are unknown to `estat`.
+    const ECODE_SXD: usize = 0x10;
+    const ECODE_ASXD: usize = 0x11;
+    let ecode = (estat.raw() >> 16) & 0x3f;
+    if ecode == ECODE_SXD || ecode == ECODE_ASXD {
+        // The first use of the vector unit since the task was switched in.
+        if !task.trap_context_mut().restore_vx() {
+            task.receive_siginfo(SigInfo::fault(Sig::SIGILL, SigInfo::ILL_ILLOPC, era.pc()));
+        }
+        return;
+    }
+
     match estat.cause() {
         Trap::Exception(e) => user_exception_handler(task, e, badv, era),
         Trap::Interrupt(i) => user_interrupt_handler(task, i),
@@ -72,6 +84,10 @@ pub fn user_exception_handler(task: &Task, e: Exception, badv: Badv, era: Era) {
             let fault_addr = VirtAddr::new(badv.vaddr());
             let inst_addr = era.pc();
 
+            if let Some(userfault) = addr_space.userfault(fault_addr, access) {
+                task.set_userfault(userfault);
+                return;
+            }
             match addr_space.handle_page_fault(fault_addr, access) {
                 Ok(()) => {
                     // Fill the TLB if the page fault is resolved successfully.
@@ -79,6 +95,12 @@ pub fn user_exception_handler(task: &Task, e: Exception, badv: Badv, era: Era) {
                     // because the method may be called by methods of `UserPtr`, which
                     // is not from a page fault handling context.
                     let fault_vpn = fault_addr.page_number();
+                    if addr_space.page_table.find_huge_entry(fault_vpn).is_some() {
+                        // The TLB refill exception handler fills the TLB with the two
+                        // halves of a huge page on the next access.
+                        tlb_flush_addr(fault_addr.to_usize());
+                        return;
+                    }
                     let fault_vpn0 = VirtPageNum::new(fault_vpn.to_usize() & !0x1);
                     let fault_vpn1 = VirtPageNum::new(fault_vpn.to_usize() | 0x1);
                     let pte0 = *addr_space.page_table.find_entry(fault_vpn0).unwrap();
@@ -118,6 +140,12 @@ pub fn user_exception_handler(task: &Task, e: Exception, badv: Badv, era: Era) {
                 },
             });
         }
+        Exception::FloatingPointUnavailable => {
+            // The first use of the FPU since the task was switched in.
+            if !task.trap_context_mut().restore_fx() {
+                log::error!("[trap_handler] FPU disabled but already restored");
+            }
+        }
         Exception::AddressNotAligned => unsafe {
             // panic!("User Exception::AddressNotAligned");
             log::error!("User Exception::AddressNotAligned");
@@ -163,6 +191,10 @@ pub fn user_interrupt_handler(task: &Task, i: Interrupt) {
             device_manager().handle_irq();
             TRAP_STATS.inc(i as usize);
         }
+        Interrupt::IPI => {
+            ipi::handle_ipi();
+            TRAP_STATS.inc(i as usize);
+        }
         _ => panic!("Unknown user interrupt: {:?}", i),
     }
 }
@@ -11,6 +11,7 @@ use timer::TIMER_MANAGER;
 
 use crate::osdriver;
 use crate::osdriver::manager::device_manager;
+use crate::processor::ipi;
 use crate::{
     task::{
         Task,
@@ -60,6 +61,10 @@ pub fn user_exception_handler(task: &Task, e: Exception, stval: usize, sepc: usi
             };
             let fault_addr = VirtAddr::new(stval);
             let addr_space = task.addr_space();
+            if let Some(userfault) = addr_space.userfault(fault_addr, access) {
+                task.set_userfault(userfault);
+                return;
+            }
             if let Err(e) = addr_space.handle_page_fault(fault_addr, access) {
                 log::error!(
                     "[user_exception_handler] task [{}] {} unsolved page fault at {:#x}, \
@@ -82,6 +87,11 @@ pub fn user_exception_handler(task: &Task, e: Exception, stval: usize, sepc: usi
             }
         }
         Exception::IllegalInstruction => {
+            // The first use of the FPU or the vector unit since the task was switched in.
+            let cx = task.trap_context_mut();
+            if cx.restore_fx() || cx.restore_vx() {
+                return;
+            }
             log::error!(
                 "[trap_handler] illegal instruction {:#x} at {:#x}",
                 stval,
@@ -114,6 +124,10 @@ pub fn user_interrupt_handler(task: &Task, i: Interrupt) {
             device_manager().handle_irq();
             TRAP_STATS.inc(i.number());
         }
+        Interrupt::SupervisorSoft => {
+            ipi::handle_ipi();
+            TRAP_STATS.inc(i.number());
+        }
         _ => {
             panic!("[trap_handler] Unsupported interrupt {:?}", i);
         }
@@ -29,4 +29,5 @@ pub fn trap_return(task: &Arc<Task>) {
         __return_to_user(ptr);
     }
     task.timer_mut().switch_to_kernel();
+    task.trap_context_mut().mark_fx_save();
 }
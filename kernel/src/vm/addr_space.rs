@@ -17,23 +17,26 @@
 //! The kernel creates a new page table for the address space and maps its kernel part
 //! directly. VMAs are then created to manage the user part of the address space.
 
-use alloc::{collections::btree_map::BTreeMap, vec::Vec};
+use alloc::{collections::btree_map::BTreeMap, sync::Weak, vec::Vec};
 use config::mm::PAGE_SIZE;
-use core::{cmp, ops::Bound};
-
-use arch::{
-    mm::{fence, tlb_shootdown_all},
-    pte::PteFlags,
+use core::{
+    cmp,
+    ops::Bound,
+    sync::atomic::{AtomicUsize, Ordering},
 };
-use mm::address::VirtAddr;
+
+use arch::pte::PteFlags;
+use mm::{address::VirtAddr, stat::VmStat};
 use mutex::SpinLock;
+use osfs::special::userfaultfd::UserfaultfdInode;
 use systype::{
     error::{SysError, SysResult},
     memory_flags::MappingFlags,
 };
 
 use super::{
-    page_table::{self, PageTable},
+    oom,
+    page_table::{self, PageTable, TlbBatch},
     vm_area::{PageFaultInfo, VmArea, VmaFlags},
 };
 
@@ -48,6 +51,21 @@ pub struct AddrSpace {
     ///
     /// Note: Be careful when using this field directly.
     pub vm_areas: SpinLock<BTreeMap<VirtAddr, VmArea>>,
+    /// Auxiliary vector passed to the program, as `(a_type, a_val)` pairs ending with
+    /// `AT_NULL`. It is kept for core dumps.
+    pub saved_auxv: SpinLock<Vec<usize>>,
+    /// Number of resident pages in the VMAs, i.e. the resident set size in pages.
+    ///
+    /// It is kept outside of `vm_areas`, so that it can be read while the VMAs are
+    /// locked, e.g. by the OOM killer.
+    rss: AtomicUsize,
+    /// Userfaultfds which may have ranges registered in the address space.
+    pub(super) userfaultfds: SpinLock<Vec<Weak<UserfaultfdInode>>>,
+    /// Mask of the harts on which the address space is active, i.e. the harts running
+    /// threads of the address space.
+    active_harts: AtomicUsize,
+    /// `membarrier()` commands that the process has registered for.
+    pub(super) membarrier_registrations: AtomicUsize,
 }
 
 impl AddrSpace {
@@ -63,6 +81,11 @@ impl AddrSpace {
         Ok(Self {
             page_table: PageTable::build()?,
             vm_areas: SpinLock::new(BTreeMap::new()),
+            saved_auxv: SpinLock::new(Vec::new()),
+            rss: AtomicUsize::new(0),
+            userfaultfds: SpinLock::new(Vec::new()),
+            active_harts: AtomicUsize::new(0),
+            membarrier_registrations: AtomicUsize::new(0),
         })
     }
 
@@ -107,10 +130,101 @@ impl AddrSpace {
             return Err(SysError::EINVAL);
         }
 
+        self.rss.fetch_add(area.pages().len(), Ordering::Relaxed);
         vm_areas_lock.insert(area.start_va(), area);
         Ok(())
     }
 
+    /// Returns the resident set size of the address space in pages.
+    pub fn rss(&self) -> usize {
+        self.rss.load(Ordering::Relaxed)
+    }
+
+    /// Accounts for `pages` resident pages released from the VMAs.
+    pub(super) fn sub_rss(&self, pages: usize) {
+        self.rss.fetch_sub(pages, Ordering::Relaxed);
+    }
+
+    /// Adds `pages` to the resident set size, for pages mapped outside of
+    /// [`handle_page_fault`](Self::handle_page_fault).
+    pub(super) fn add_rss(&self, pages: usize) {
+        self.rss.fetch_add(pages, Ordering::Relaxed);
+    }
+
+    /// Returns the mask of the harts on which the address space is active.
+    pub fn active_harts(&self) -> usize {
+        self.active_harts.load(Ordering::Acquire)
+    }
+
+    /// Marks the address space as active on the hart `hart_id`. This must be done
+    /// before the hart switches to the address space, so that TLB shootdowns cannot
+    /// miss the hart.
+    pub fn activate_on(&self, hart_id: usize) {
+        self.page_table.activate_on(hart_id);
+        self.active_harts.fetch_or(1 << hart_id, Ordering::AcqRel);
+    }
+
+    /// Marks the address space as no longer active on the hart `hart_id`.
+    pub fn deactivate_on(&self, hart_id: usize) {
+        self.active_harts
+            .fetch_and(!(1 << hart_id), Ordering::AcqRel);
+        self.page_table.deactivate_on(hart_id);
+    }
+
+    /// Returns the total size of the VMAs in bytes.
+    pub fn total_vm(&self) -> usize {
+        self.vm_areas.lock().values().map(|vma| vma.length()).sum()
+    }
+
+    /// Returns the total size of the VMAs locked by `mlock()` in bytes.
+    pub fn locked_vm(&self) -> usize {
+        self.vm_areas
+            .lock()
+            .values()
+            .filter(|vma| vma.flags().contains(VmaFlags::LOCKED))
+            .map(|vma| vma.length())
+            .sum()
+    }
+
+    /// Returns the start and the end of the heap, or `None` if the address space has
+    /// no heap.
+    pub fn heap_range(&self) -> Option<(usize, usize)> {
+        let vm_areas_lock = self.vm_areas.lock();
+        let heap = vm_areas_lock.values().find(|vma| vma.is_heap())?;
+        Some((heap.start_va().to_usize(), heap.end_va().to_usize()))
+    }
+
+    /// Returns the number of pages committed by the address space, i.e. the size of
+    /// its private writable mappings, which must eventually be backed by private
+    /// memory.
+    pub fn committed_pages(&self) -> usize {
+        self.vm_areas
+            .lock()
+            .values()
+            .filter(|vma| vma.is_committed())
+            .map(|vma| vma.length() / PAGE_SIZE)
+            .sum()
+    }
+
+    /// Releases the private memory of the address space, while keeping its VMAs.
+    ///
+    /// This is used by the OOM killer to free the memory of a victim at once, instead of
+    /// waiting for the victim to exit. Accessing the released pages afterwards gives
+    /// zero-filled pages.
+    ///
+    /// Returns the number of pages released, or `None` if the VMAs are locked by
+    /// someone else.
+    pub fn reap(&self) -> Option<usize> {
+        let mut vm_areas_lock = self.vm_areas.try_lock()?;
+        let reaped = vm_areas_lock
+            .values_mut()
+            .filter(|vma| vma.flags().contains(VmaFlags::PRIVATE))
+            .map(|vma| vma.drop_pages(&self.page_table))
+            .sum();
+        self.rss.fetch_sub(reaped, Ordering::Relaxed);
+        Some(reaped)
+    }
+
     /// Finds a vacant memory region in the user address space.
     ///
     /// This function first tries to find a vacant memory region that starts from `start_va`
@@ -126,11 +240,22 @@ impl AddrSpace {
         length: usize,
         find_from: VirtAddr,
         find_to: VirtAddr,
+    ) -> Option<VirtAddr> {
+        Self::find_vacant_in(&self.vm_areas.lock(), start_va, length, find_from, find_to)
+    }
+
+    /// Finds a vacant memory region among `vm_areas`, which are the locked VMAs of an
+    /// address space. See [`Self::find_vacant_memory`].
+    pub(super) fn find_vacant_in(
+        vm_areas_lock: &BTreeMap<VirtAddr, VmArea>,
+        start_va: VirtAddr,
+        length: usize,
+        find_from: VirtAddr,
+        find_to: VirtAddr,
     ) -> Option<VirtAddr> {
         let length = (length + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
         let mem_start = start_va.round_up();
         let mem_end = VirtAddr::new(mem_start.to_usize() + length);
-        let vm_areas_lock = self.vm_areas.lock();
 
         // Check if the specified range is vacant.
         if mem_start.to_usize() >= find_from.to_usize() && mem_end.to_usize() <= find_to.to_usize()
@@ -192,7 +317,26 @@ impl AddrSpace {
     pub fn remove_mapping(&self, addr: VirtAddr, length: usize) {
         let length = VirtAddr::new(length).round_up().to_usize();
         let end_addr = VirtAddr::new(addr.to_usize() + length);
-        let mut vm_areas_lock = self.vm_areas.lock();
+        let mut tlb_batch = self.page_table.batch_tlb_shootdowns();
+        self.remove_range(&mut self.vm_areas.lock(), &mut tlb_batch, addr, end_addr);
+    }
+
+    /// Removes mappings for the page-aligned range `[addr, end_addr)` from `vm_areas`,
+    /// which are the locked VMAs of the address space. See [`Self::remove_mapping`].
+    ///
+    /// The TLB shootdown is deferred to `tlb_batch`, which the caller should drop after
+    /// releasing the lock of the VMAs, since waiting for other harts to flush their
+    /// TLBs while holding it may deadlock with a hart spinning on the lock.
+    pub(super) fn remove_range(
+        &self,
+        vm_areas_lock: &mut BTreeMap<VirtAddr, VmArea>,
+        tlb_batch: &mut TlbBatch,
+        addr: VirtAddr,
+        end_addr: VirtAddr,
+    ) {
+        if addr >= end_addr {
+            return;
+        }
 
         // Find VMAs that overlap with the specified range.
         let mut keys = vm_areas_lock
@@ -217,7 +361,8 @@ impl AddrSpace {
                 vm_areas_lock.insert(vma_low.start_va(), vma_low);
             }
             if let Some(vma_mid) = vma_mid {
-                vma_mid.unmap_area(&self.page_table);
+                self.rss.fetch_sub(vma_mid.pages().len(), Ordering::Relaxed);
+                vma_mid.unmap_area(tlb_batch);
             }
             if let Some(vma_high) = vma_high {
                 vm_areas_lock.insert(vma_high.start_va(), vma_high);
@@ -238,9 +383,15 @@ impl AddrSpace {
     /// should be a valid address.
     ///
     /// `prot` needs to have `RWX` bits set; other bits must be zero.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EACCES`] if `prot` has `W` and the region covers a special
+    /// VMA such as the vDSO.
     pub fn change_prot(&self, addr: VirtAddr, length: usize, prot: MappingFlags) -> SysResult<()> {
         let length = VirtAddr::new(length).round_up().to_usize();
         let end_addr = VirtAddr::new(addr.to_usize() + length);
+        // The batch is dropped after the lock of the VMAs. See `Self::remove_range`.
+        let mut tlb_batch = self.page_table.batch_tlb_shootdowns();
         let mut vm_areas_lock = self.vm_areas.lock();
 
         // Find VMAs that overlap with the specified range.
@@ -258,6 +409,13 @@ impl AddrSpace {
             _ => {}
         }
 
+        // The pages of special VMAs such as the vDSO are shared by all processes, so no
+        // process may write to them.
+        if prot.contains(MappingFlags::W) && keys.iter().any(|key| vm_areas_lock[key].is_special())
+        {
+            return Err(SysError::EACCES);
+        }
+
         // Change protection for these VMAs.
         for key in keys {
             let vma = vm_areas_lock.remove(&key).unwrap();
@@ -272,7 +430,7 @@ impl AddrSpace {
                     vm_areas_lock.insert(vma_mid.start_va(), vma_mid);
                     return Err(e);
                 }
-                vma_mid.change_prot(&self.page_table, prot);
+                vma_mid.change_prot(&mut tlb_batch, prot);
                 vm_areas_lock.insert(vma_mid.start_va(), vma_mid);
             }
             if let Some(vma_high) = vma_high {
@@ -282,6 +440,62 @@ impl AddrSpace {
         Ok(())
     }
 
+    /// Returns the keys of the VMAs among `vm_areas`, which are the locked VMAs of the
+    /// address space, that overlap with the range `[addr, end_addr)`.
+    ///
+    /// # Errors
+    /// Returns [`SysError::ENOMEM`] if some part of the range is not mapped.
+    pub(super) fn covering_areas(
+        vm_areas_lock: &BTreeMap<VirtAddr, VmArea>,
+        addr: VirtAddr,
+        end_addr: VirtAddr,
+    ) -> SysResult<Vec<VirtAddr>> {
+        let mut keys = Vec::new();
+        let mut covered = addr;
+        if let Some((&va, vma)) = vm_areas_lock
+            .upper_bound(Bound::Excluded(&addr))
+            .peek_prev()
+            .filter(|(_, vma)| vma.end_va() > addr)
+        {
+            keys.push(va);
+            covered = vma.end_va();
+        }
+        for (&va, vma) in vm_areas_lock.range(addr..end_addr) {
+            if va > covered {
+                return Err(SysError::ENOMEM);
+            }
+            keys.push(va);
+            covered = vma.end_va();
+        }
+        if covered < end_addr {
+            return Err(SysError::ENOMEM);
+        }
+        Ok(keys)
+    }
+
+    /// Locks or unlocks the VMAs in the range `[addr, addr + length)` in memory, splitting
+    /// VMAs at the boundaries of the range.
+    ///
+    /// # Errors
+    /// Returns [`SysError::ENOMEM`] if some part of the range is not mapped.
+    pub fn set_locked(&self, addr: VirtAddr, length: usize, locked: bool) -> SysResult<()> {
+        let length = VirtAddr::new(length).round_up().to_usize();
+        let end_addr = VirtAddr::new(addr.to_usize() + length);
+        let mut vm_areas_lock = self.vm_areas.lock();
+        let keys = Self::covering_areas(&vm_areas_lock, addr, end_addr)?;
+        for key in keys {
+            let vma = vm_areas_lock.remove(&key).unwrap();
+            let (vma_low, vma_mid, vma_high) = vma.split_area(addr, end_addr);
+            for mut vma in [vma_low, vma_mid, vma_high].into_iter().flatten() {
+                if vma.start_va() >= addr && vma.end_va() <= end_addr {
+                    vma.set_locked(locked);
+                }
+                vm_areas_lock.insert(vma.start_va(), vma);
+            }
+        }
+        Ok(())
+    }
+
     /// Clones the address space.
     ///
     /// This function creates a new address space with the same mappings as the original
@@ -300,7 +514,16 @@ impl AddrSpace {
 
         for (va, area) in &(*lock) {
             log::debug!("copy area: {:?}", area);
-            let narea = area.clone();
+            if area.flags().contains(VmaFlags::DONTFORK) {
+                continue;
+            }
+            let mut narea = if area.flags().contains(VmaFlags::WIPEONFORK) {
+                area.unpopulated()
+            } else {
+                area.clone()
+            };
+            // Memory locks are not inherited by the child.
+            narea.set_locked(false);
             log::debug!("copy narea: {:?}", narea);
 
             new_vm_areas.insert(*va, narea);
@@ -327,28 +550,42 @@ impl AddrSpace {
                 *new_pte = pte;
             }
         }
+        new_space.rss.store(
+            new_vm_areas.values().map(|vma| vma.pages().len()).sum(),
+            Ordering::Relaxed,
+        );
         new_space.vm_areas = SpinLock::new(new_vm_areas);
+        new_space.saved_auxv = SpinLock::new(self.saved_auxv.lock().clone());
+        new_space.membarrier_registrations.store(
+            self.membarrier_registrations.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
 
         log::debug!("finish clone_cow");
         // Because the permission of PTEs is downgraded, we need to do a TLB shootdown.
-        fence();
-        tlb_shootdown_all();
+        self.page_table.tlb_shootdown_all();
 
         // this makes init_proc work, maybe cache?
         {
             let mut lock = new_space.vm_areas.lock();
             for (_va, area) in &mut (*lock) {
                 if area.end_va().to_usize() == 0x15000 {
+                    let resident = area.pages().len();
                     if area
                         .handle_page_fault(PageFaultInfo {
                             fault_addr: VirtAddr::new(0x11110),
                             page_table: &new_space.page_table,
                             access: MappingFlags::R,
+                            huge: false,
                         })
                         .is_err()
                     {
                         log::error!("fail to get narea info");
                     }
+                    new_space.rss.fetch_add(
+                        area.pages().len().saturating_sub(resident),
+                        Ordering::Relaxed,
+                    );
                 }
             }
         }
@@ -371,10 +608,24 @@ impl AddrSpace {
             return Err(SysError::ENOMEM);
         }
 
+        // Check the overcommit policy before growing the heap
+        let (_, heap_end) = self.heap_range().ok_or(SysError::ENOMEM)?;
+        let new_end = if addr == 0 {
+            heap_end.saturating_add_signed(incr)
+        } else {
+            addr
+        };
+        if new_end > heap_end {
+            oom::vm_enough_memory((new_end - heap_end).div_ceil(PAGE_SIZE))?;
+        }
+
         // Find the heap area
         let mut vm_areas_lock = self.vm_areas.lock();
         let mut vma_iter = vm_areas_lock.iter_mut();
-        let heap_area = vma_iter.find(|(_, vma)| vma.is_heap()).unwrap().1;
+        let heap_area = vma_iter
+            .find(|(_, vma)| vma.is_heap())
+            .ok_or(SysError::ENOMEM)?
+            .1;
         let heap_start = heap_area.start_va().to_usize();
         let heap_end = heap_area.end_va().to_usize();
 
@@ -410,9 +661,15 @@ impl AddrSpace {
     ///
     /// # Errors
     /// Returns [`SysError::EFAULT`] if the fault address is invalid or the access permission
-    /// is not allowed. Otherwise, returns [`SysError::ENOMEM`] if memory allocation fails
-    /// when handling the page fault.
+    /// is not allowed, or if the page fault is to be handled by a userfaultfd, which a
+    /// kernel access cannot wait for. Otherwise, returns [`SysError::ENOMEM`] if memory
+    /// allocation fails when handling the page fault.
     pub fn handle_page_fault(&self, fault_addr: VirtAddr, access: MappingFlags) -> SysResult<()> {
+        VmStat::PgFault.inc();
+        if self.userfault(fault_addr, access).is_some() {
+            return Err(SysError::EFAULT);
+        }
+        let huge = !self.huge_page_registered(fault_addr);
         let mut vm_areas_lock = self.vm_areas.lock();
 
         let vma = vm_areas_lock
@@ -425,9 +682,16 @@ impl AddrSpace {
             fault_addr,
             page_table: &self.page_table,
             access,
+            huge,
         };
 
-        vma.handle_page_fault(page_fault_info)
+        let resident = vma.pages().len();
+        let result = vma.handle_page_fault(page_fault_info);
+        self.rss.fetch_add(
+            vma.pages().len().saturating_sub(resident),
+            Ordering::Relaxed,
+        );
+        result
     }
 }
 
@@ -0,0 +1,55 @@
+//! Module for allocating address space identifiers (ASIDs).
+//!
+//! An ASID tags the TLB entries of an address space, so that switching between
+//! address spaces needs no TLB flush. ASID 0 is shared by the page tables without an
+//! ASID of their own, which is the case for all page tables if the hardware supports
+//! no ASIDs, or for the ones created after all ASIDs are in use. Switching to such a
+//! page table flushes the TLB.
+
+use id_allocator::{IdAllocator, VecIdAllocator};
+use lazy_static::lazy_static;
+use mutex::SpinNoIrqLock;
+
+lazy_static! {
+    /// Allocator of nonzero ASIDs, or `None` if the hardware supports no ASIDs.
+    static ref ASID_ALLOCATOR: SpinNoIrqLock<Option<VecIdAllocator>> = {
+        let asid_count = 1 << arch::mm::asid_bits();
+        log::info!("[asid] {asid_count} ASIDs supported");
+        SpinNoIrqLock::new((asid_count > 1).then(|| VecIdAllocator::new(1, asid_count)))
+    };
+}
+
+/// An ASID owned by a page table, which is released when dropped.
+#[derive(Debug)]
+pub struct AsidHandle(usize);
+
+impl AsidHandle {
+    /// The shared ASID 0.
+    pub const SHARED: Self = Self(0);
+
+    /// Returns the ASID.
+    pub fn asid(&self) -> usize {
+        self.0
+    }
+}
+
+impl Drop for AsidHandle {
+    fn drop(&mut self) {
+        if self.0 != 0 {
+            let mut allocator = ASID_ALLOCATOR.lock();
+            unsafe { allocator.as_mut().unwrap().dealloc(self.0) };
+        }
+    }
+}
+
+/// Allocates an ASID, or returns the shared ASID 0 if there is none left.
+///
+/// The TLB entries tagged with a released ASID must have been flushed on all harts
+/// before the ASID is released, so a newly allocated ASID tags no TLB entries.
+pub fn asid_alloc() -> AsidHandle {
+    ASID_ALLOCATOR
+        .lock()
+        .as_mut()
+        .and_then(|allocator| allocator.alloc())
+        .map_or(AsidHandle::SHARED, AsidHandle)
+}
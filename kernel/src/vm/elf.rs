@@ -4,7 +4,9 @@ use alloc::{ffi::CString, string::String, sync::Arc, vec::Vec};
 
 use aux::*;
 use config::{
-    mm::{USER_END, USER_HEAP_SIZE, USER_INTERP_BASE, USER_STACK_LOWER, USER_STACK_UPPER},
+    mm::{
+        PAGE_SIZE, USER_END, USER_HEAP_SIZE, USER_INTERP_BASE, USER_STACK_SIZE, USER_STACK_UPPER,
+    },
     vfs::SeekFrom,
 };
 use elf::{self, ElfStream, ParseError as ElfParseError, endian::LittleEndian, file::FileHeader};
@@ -22,6 +24,10 @@ use super::{
 };
 use crate::vm::user_ptr::UserWritePtr;
 
+/// Smallest user stack mapped by [`AddrSpace::map_stack`], which leaves room for the
+/// arguments and environment variables even under a tiny `RLIMIT_STACK`.
+pub const MIN_STACK_SIZE: usize = 128 * 1024;
+
 impl AddrSpace {
     /// Loads an ELF executable into given address space.
     ///
@@ -40,6 +46,7 @@ impl AddrSpace {
     /// - AT_PHDR: address of program headers
     /// - AT_ENTRY: entry point of the user program
     /// - AT_BASE: base address of the dynamic linker (if loaded)
+    /// - AT_SYSINFO_EHDR: address of the vDSO
     ///
     /// and all entries that are initialized in [`construct_init_auxv`].
     ///
@@ -131,6 +138,9 @@ impl AddrSpace {
             auxv.push(AuxHeader::new(AT_BASE, USER_INTERP_BASE));
         }
 
+        let vdso = self.map_vdso()?;
+        auxv.push(AuxHeader::new(AT_SYSINFO_EHDR, vdso.to_usize()));
+
         Ok((entry, auxv))
     }
 
@@ -219,15 +229,19 @@ impl AddrSpace {
         Ok(VirtAddr::new(entry))
     }
 
-    /// Maps a stack into the address space.
+    /// Maps a stack of `size` bytes into the address space.
     ///
     /// Returns the address of the stack bottom, i.e., one byte exceeding the highest address of
     /// the stack.
     ///
-    /// Current implementation hardcodes the stack size and position in [`config::mm`] module.
-    pub fn map_stack(&self) -> SysResult<VirtAddr> {
+    /// The stack always ends at `USER_STACK_UPPER` in [`config::mm`] module. `size` is
+    /// usually `RLIMIT_STACK`; it is rounded down to pages and clamped between
+    /// [`MIN_STACK_SIZE`] and `USER_STACK_SIZE`, so a stack never grows past
+    /// `USER_STACK_LOWER`.
+    pub fn map_stack(&self, size: usize) -> SysResult<VirtAddr> {
+        let size = (size & !(PAGE_SIZE - 1)).clamp(MIN_STACK_SIZE, USER_STACK_SIZE);
         let stack = VmArea::new_stack(
-            VirtAddr::new(USER_STACK_LOWER),
+            VirtAddr::new(USER_STACK_UPPER - size),
             VirtAddr::new(USER_STACK_UPPER),
         );
         let stack_bottom = stack.end_va();
@@ -327,6 +341,11 @@ impl AddrSpace {
 
         // Push the auxiliary vector to the stack.
         let null_aux = AuxHeader::new(aux::AT_NULL, 0);
+        *self.saved_auxv.lock() = auxv
+            .iter()
+            .chain(core::iter::once(&null_aux))
+            .flat_map(|aux| [aux.a_type, aux.a_val])
+            .collect();
         push_aux(null_aux, &mut sp)?;
         for aux in auxv.into_iter().rev() {
             push_aux(aux, &mut sp)?;
@@ -402,7 +421,7 @@ mod aux {
         auxv.push(AuxHeader::new(AT_GID, 0));
         auxv.push(AuxHeader::new(AT_EGID, 0));
         auxv.push(AuxHeader::new(AT_PLATFORM, 0));
-        auxv.push(AuxHeader::new(AT_HWCAP, 0));
+        auxv.push(AuxHeader::new(AT_HWCAP, arch::hart::hwcap()));
         auxv.push(AuxHeader::new(AT_CLKTCK, 100));
         auxv.push(AuxHeader::new(AT_SECURE, 0));
         auxv
@@ -454,4 +473,6 @@ mod aux {
     pub const AT_HWCAP2: usize = 26;
     /// Filename of program
     pub const AT_EXECFN: usize = 31;
+    /// Address of the vDSO
+    pub const AT_SYSINFO_EHDR: usize = 33;
 }
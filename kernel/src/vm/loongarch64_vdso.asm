@@ -0,0 +1,237 @@
+    # The vDSO image for LoongArch.
+    #
+    # This is a complete ELF shared object, which is copied into the vDSO pages as is.
+    # Everything in it is addressed relative to `_vdso_start`, so the image works at
+    # whatever address it is mapped. The data page is the page right below the image.
+
+    .equ VDSO_SEQ, 0
+    .equ VDSO_CLOCK_MASK, 4
+    .equ VDSO_TICKS_PER_US, 8
+    .equ VDSO_OFFSET_US, 16
+    .equ VDSO_CLOCKS, 8
+
+    .equ SYS_CLOCK_GETTIME, 113
+    .equ SYS_CLOCK_GETRES, 114
+
+    .equ SYMBOL_COUNT, 5
+
+    .section .rodata.vdso, "a"
+    .balign 4096
+    .global _vdso_start
+_vdso_start:
+    # ELF header
+    .byte 0x7f, 'E', 'L', 'F', 2, 1, 1, 0
+    .byte 0, 0, 0, 0, 0, 0, 0, 0
+    .half 3                                 # e_type: ET_DYN
+    .half 258                               # e_machine: EM_LOONGARCH
+    .word 1                                 # e_version
+    .dword 0                                # e_entry
+    .dword .Lphdr - _vdso_start             # e_phoff
+    .dword 0                                # e_shoff
+    .word 0x43                              # e_flags: object ABI v1, double-float ABI
+    .half 64                                # e_ehsize
+    .half 56                                # e_phentsize
+    .half 2                                 # e_phnum
+    .half 64                                # e_shentsize
+    .half 0                                 # e_shnum
+    .half 0                                 # e_shstrndx
+
+.Lphdr:
+    # PT_LOAD
+    .word 1, 5                              # p_type, p_flags: R | X
+    .dword 0, 0, 0                          # p_offset, p_vaddr, p_paddr
+    .dword _vdso_end - _vdso_start          # p_filesz
+    .dword _vdso_end - _vdso_start          # p_memsz
+    .dword 4096                             # p_align
+    # PT_DYNAMIC
+    .word 2, 4                              # p_type, p_flags: R
+    .dword .Ldynamic - _vdso_start          # p_offset
+    .dword .Ldynamic - _vdso_start          # p_vaddr
+    .dword .Ldynamic - _vdso_start          # p_paddr
+    .dword .Ldynamic_end - .Ldynamic        # p_filesz
+    .dword .Ldynamic_end - .Ldynamic        # p_memsz
+    .dword 8                                # p_align
+
+.Ldynamic:
+    .dword 4, .Lhash - _vdso_start          # DT_HASH
+    .dword 5, .Ldynstr - _vdso_start        # DT_STRTAB
+    .dword 6, .Ldynsym - _vdso_start        # DT_SYMTAB
+    .dword 10, .Ldynstr_end - .Ldynstr      # DT_STRSZ
+    .dword 11, 24                           # DT_SYMENT
+    .dword 14, .Lstr_soname - .Ldynstr      # DT_SONAME
+    .dword 0x6ffffff0, .Lversym - _vdso_start   # DT_VERSYM
+    .dword 0x6ffffffc, .Lverdef - _vdso_start   # DT_VERDEF
+    .dword 0x6ffffffd, 2                    # DT_VERDEFNUM
+    .dword 0, 0                             # DT_NULL
+.Ldynamic_end:
+
+    # A single hash bucket chaining all symbols, so that no symbol hashes are needed.
+.Lhash:
+    .word 1, SYMBOL_COUNT                   # nbucket, nchain
+    .word 1                                 # bucket[0]
+    .word 0, 2, 3, 4, 0                     # chain
+
+    .balign 8
+.Ldynsym:
+    .word 0
+    .byte 0, 0
+    .half 0
+    .dword 0, 0
+    .macro vdso_symbol name, func
+    .word \name - .Ldynstr                  # st_name
+    .byte 0x12, 0                           # st_info: STB_GLOBAL | STT_FUNC, st_other
+    .half 1                                 # st_shndx
+    .dword \func - _vdso_start              # st_value
+    .dword \func\()_end - \func             # st_size
+    .endm
+    vdso_symbol .Lstr_clock_gettime, __vdso_clock_gettime
+    vdso_symbol .Lstr_gettimeofday, __vdso_gettimeofday
+    vdso_symbol .Lstr_clock_getres, __vdso_clock_getres
+    vdso_symbol .Lstr_getcpu, __vdso_getcpu
+
+.Lversym:
+    .half 0, 2, 2, 2, 2
+
+    .balign 4
+.Lverdef:
+    # Version of the object itself
+    .half 1, 1, 1, 1                        # vd_version, vd_flags: VER_FLG_BASE, vd_ndx, vd_cnt
+    .word 0x0deebfa1                        # vd_hash: elf_hash("linux-vdso.so.1")
+    .word 20, 28                            # vd_aux, vd_next
+    .word .Lstr_soname - .Ldynstr, 0        # vda_name, vda_next
+    # LINUX_5.10
+    .half 1, 0, 2, 1                        # vd_version, vd_flags, vd_ndx, vd_cnt
+    .word 0x0ae78f70                        # vd_hash: elf_hash("LINUX_5.10")
+    .word 20, 0                             # vd_aux, vd_next
+    .word .Lstr_version - .Ldynstr, 0       # vda_name, vda_next
+
+.Ldynstr:
+    .byte 0
+.Lstr_soname:
+    .asciz "linux-vdso.so.1"
+.Lstr_version:
+    .asciz "LINUX_5.10"
+.Lstr_clock_gettime:
+    .asciz "__vdso_clock_gettime"
+.Lstr_gettimeofday:
+    .asciz "__vdso_gettimeofday"
+.Lstr_clock_getres:
+    .asciz "__vdso_clock_getres"
+.Lstr_getcpu:
+    .asciz "__vdso_getcpu"
+.Ldynstr_end:
+
+    # Loads the address of the data page into `reg`.
+    .macro vdso_data reg, tmp
+    la.pcrel \reg, _vdso_start
+    lu12i.w \tmp, 1
+    sub.d \reg, \reg, \tmp
+    .endm
+
+    # Reads the clock `clk` from the data page at `data` in microseconds into `us`,
+    # retrying while the kernel is updating the data page.
+    .macro vdso_read_us us, clk, data, seq, t0, t1
+1:
+    ld.w \seq, \data, VDSO_SEQ
+    andi \t0, \seq, 1
+    bnez \t0, 1b
+    dbar 0
+    rdtime.d \us, $zero
+    slli.d \t1, \clk, 3
+    add.d \t1, \t1, \data
+    ld.d \t1, \t1, VDSO_OFFSET_US
+    dbar 0
+    ld.w \t0, \data, VDSO_SEQ
+    bne \t0, \seq, 1b
+    ld.d \t0, \data, VDSO_TICKS_PER_US
+    div.du \us, \us, \t0
+    add.d \us, \us, \t1
+    .endm
+
+    .balign 4
+    # int __vdso_clock_gettime(clockid_t clk, struct timespec *ts)
+__vdso_clock_gettime:
+    li.d $t0, VDSO_CLOCKS
+    bgeu $a0, $t0, 2f
+    vdso_data $t1, $t0
+    ld.w $t0, $t1, VDSO_CLOCK_MASK
+    srl.d $t0, $t0, $a0
+    andi $t0, $t0, 1
+    beqz $t0, 2f
+    vdso_read_us $t2, $a0, $t1, $t3, $t4, $t5
+    li.d $t0, 1000000
+    div.du $t3, $t2, $t0
+    mod.du $t2, $t2, $t0
+    li.d $t0, 1000
+    mul.d $t2, $t2, $t0
+    st.d $t3, $a1, 0
+    st.d $t2, $a1, 8
+    move $a0, $zero
+    jr $ra
+2:
+    li.d $a7, SYS_CLOCK_GETTIME
+    syscall 0
+    jr $ra
+__vdso_clock_gettime_end:
+
+    # int __vdso_gettimeofday(struct timeval *tv, struct timezone *tz)
+__vdso_gettimeofday:
+    beqz $a0, 2f
+    vdso_data $t1, $t0
+    move $t6, $zero
+    vdso_read_us $t2, $t6, $t1, $t3, $t4, $t5
+    li.d $t0, 1000000
+    div.du $t3, $t2, $t0
+    mod.du $t2, $t2, $t0
+    st.d $t3, $a0, 0
+    st.d $t2, $a0, 8
+2:
+    beqz $a1, 3f
+    st.w $zero, $a1, 0
+    st.w $zero, $a1, 4
+3:
+    move $a0, $zero
+    jr $ra
+__vdso_gettimeofday_end:
+
+    # int __vdso_clock_getres(clockid_t clk, struct timespec *res)
+__vdso_clock_getres:
+    li.d $t0, VDSO_CLOCKS
+    bgeu $a0, $t0, 3f
+    vdso_data $t1, $t0
+    ld.w $t0, $t1, VDSO_CLOCK_MASK
+    srl.d $t0, $t0, $a0
+    andi $t0, $t0, 1
+    beqz $t0, 3f
+    beqz $a1, 2f
+    # The clocks count in microseconds, as `sys_clock_getres` reports.
+    li.d $t0, 1000
+    st.d $zero, $a1, 0
+    st.d $t0, $a1, 8
+2:
+    move $a0, $zero
+    jr $ra
+3:
+    li.d $a7, SYS_CLOCK_GETRES
+    syscall 0
+    jr $ra
+__vdso_clock_getres_end:
+
+    # int __vdso_getcpu(unsigned *cpu, unsigned *node, void *cache)
+    #
+    # `rdtime.d` also returns the ID of the counter, which is the ID of the current core.
+__vdso_getcpu:
+    rdtime.d $zero, $t0
+    beqz $a0, 2f
+    st.w $t0, $a0, 0
+2:
+    beqz $a1, 3f
+    st.w $zero, $a1, 0
+3:
+    move $a0, $zero
+    jr $ra
+__vdso_getcpu_end:
+
+    .balign 16
+    .global _vdso_end
+_vdso_end:
@@ -0,0 +1,317 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use config::mm::PAGE_SIZE;
+use mm::{
+    address::{VirtAddr, VirtPageNum},
+    stat::VmStat,
+};
+use strum::FromRepr;
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MappingFlags,
+};
+use vfs::file::File;
+
+use super::{
+    addr_space::AddrSpace,
+    vm_area::{TypedArea, VmArea, VmaFlags},
+};
+
+/// Advice given by `madvise()`, defined in <asm-generic/mman-common.h>.
+#[allow(non_camel_case_types)]
+#[derive(FromRepr, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum Advice {
+    /// No special treatment.
+    MADV_NORMAL = 0,
+    /// Expect random page references.
+    MADV_RANDOM = 1,
+    /// Expect sequential page references.
+    MADV_SEQUENTIAL = 2,
+    /// Expect access in the near future, so read the pages of files ahead.
+    MADV_WILLNEED = 3,
+    /// Do not expect access in the near future, so release the pages.
+    MADV_DONTNEED = 4,
+    /// The pages of a private anonymous mapping may be released.
+    MADV_FREE = 8,
+    /// Release the pages and their backing store of a shared mapping.
+    MADV_REMOVE = 9,
+    /// Do not make the pages available to the child after `fork()`.
+    MADV_DONTFORK = 10,
+    /// Undo `MADV_DONTFORK`.
+    MADV_DOFORK = 11,
+    /// Pages may be merged with identical pages.
+    MADV_MERGEABLE = 12,
+    /// Undo `MADV_MERGEABLE`.
+    MADV_UNMERGEABLE = 13,
+    /// Prefer huge pages.
+    MADV_HUGEPAGE = 14,
+    /// Never use huge pages.
+    MADV_NOHUGEPAGE = 15,
+    /// Leave the pages out of core dumps.
+    MADV_DONTDUMP = 16,
+    /// Undo `MADV_DONTDUMP`.
+    MADV_DODUMP = 17,
+    /// Zero-fill the pages in the child after `fork()`.
+    MADV_WIPEONFORK = 18,
+    /// Undo `MADV_WIPEONFORK`.
+    MADV_KEEPONFORK = 19,
+    /// The pages are less likely to be accessed, so they may be reclaimed first.
+    MADV_COLD = 20,
+    /// Reclaim the pages now.
+    MADV_PAGEOUT = 21,
+    /// Fault in the pages for reading.
+    MADV_POPULATE_READ = 22,
+    /// Fault in the pages for writing, breaking copy-on-write.
+    MADV_POPULATE_WRITE = 23,
+}
+
+impl AddrSpace {
+    /// Applies the `advice` to the page-aligned range `[addr, end)`.
+    ///
+    /// Without swap, readahead policy or page merging, `MADV_NORMAL`, `MADV_RANDOM`,
+    /// `MADV_SEQUENTIAL`, `MADV_MERGEABLE`, `MADV_UNMERGEABLE` and `MADV_COLD` have no
+    /// effect, and `MADV_PAGEOUT` only reclaims pages that are still the cached pages of
+    /// files.
+    ///
+    /// # Errors
+    /// Returns [`SysError::ENOMEM`] if some part of the range is not mapped, or
+    /// [`SysError::EINVAL`] if the advice does not apply to a VMA in the range, such as
+    /// releasing the pages of a locked VMA, or [`SysError::EACCES`] if `MADV_REMOVE`
+    /// applies to a VMA that is not writable or maps a file not opened for writing.
+    pub async fn madvise(&self, addr: VirtAddr, end: VirtAddr, advice: Advice) -> SysResult<()> {
+        match advice {
+            Advice::MADV_NORMAL
+            | Advice::MADV_RANDOM
+            | Advice::MADV_SEQUENTIAL
+            | Advice::MADV_MERGEABLE
+            | Advice::MADV_UNMERGEABLE
+            | Advice::MADV_COLD => {
+                Self::covering_areas(&self.vm_areas.lock(), addr, end)?;
+                Ok(())
+            }
+            Advice::MADV_WILLNEED => self.read_ahead(addr, end).await,
+            Advice::MADV_DONTNEED | Advice::MADV_FREE => self.release(addr, end, |vma| {
+                advice == Advice::MADV_DONTNEED || vma.is_private_anonymous()
+            }),
+            Advice::MADV_REMOVE => self.remove_backing(addr, end).await,
+            Advice::MADV_PAGEOUT => {
+                let mut vm_areas_lock = self.vm_areas.lock();
+                let keys = Self::covering_areas(&vm_areas_lock, addr, end)?;
+                for key in keys {
+                    let vma = vm_areas_lock.get_mut(&key).unwrap();
+                    if vma.flags().contains(VmaFlags::LOCKED) {
+                        continue;
+                    }
+                    // Only the pages that can be read back from the files are reclaimed.
+                    let released = vma.drop_range(&self.page_table, addr, end, |vma, vpn| {
+                        vma.cached_file_page(vpn)
+                            .is_some_and(|page| Arc::ptr_eq(&page, &vma.pages()[&vpn]))
+                    });
+                    self.sub_rss(released);
+                    VmStat::PgSteal.add(released);
+                }
+                Ok(())
+            }
+            Advice::MADV_DONTFORK => self.advise_flags(addr, end, VmaFlags::DONTFORK, true),
+            Advice::MADV_DOFORK => self.advise_flags(addr, end, VmaFlags::DONTFORK, false),
+            Advice::MADV_WIPEONFORK => self.advise_flags(addr, end, VmaFlags::WIPEONFORK, true),
+            Advice::MADV_KEEPONFORK => self.advise_flags(addr, end, VmaFlags::WIPEONFORK, false),
+            Advice::MADV_DONTDUMP => self.advise_flags(addr, end, VmaFlags::DONTDUMP, true),
+            Advice::MADV_DODUMP => self.advise_flags(addr, end, VmaFlags::DONTDUMP, false),
+            Advice::MADV_HUGEPAGE => {
+                self.advise_flags(addr, end, VmaFlags::NOHUGEPAGE, false)?;
+                self.advise_flags(addr, end, VmaFlags::HUGEPAGE, true)
+            }
+            Advice::MADV_NOHUGEPAGE => {
+                self.advise_flags(addr, end, VmaFlags::HUGEPAGE, false)?;
+                self.advise_flags(addr, end, VmaFlags::NOHUGEPAGE, true)
+            }
+            Advice::MADV_POPULATE_READ => self.populate(addr, end, MappingFlags::R),
+            Advice::MADV_POPULATE_WRITE => self.populate(addr, end, MappingFlags::W),
+        }
+    }
+
+    /// Returns the residency of each page in the page-aligned range `[addr, end)`,
+    /// where a page is resident if it is mapped, or if it is cached in the page cache of
+    /// the file it maps.
+    ///
+    /// # Errors
+    /// Returns [`SysError::ENOMEM`] if some part of the range is not mapped.
+    pub fn mincore(&self, addr: VirtAddr, end: VirtAddr) -> SysResult<Vec<bool>> {
+        let vm_areas_lock = self.vm_areas.lock();
+        let keys = Self::covering_areas(&vm_areas_lock, addr, end)?;
+        let mut residency = Vec::with_capacity((end.to_usize() - addr.to_usize()) / PAGE_SIZE);
+        for vma in keys.iter().map(|key| &vm_areas_lock[key]) {
+            let start = VirtAddr::max(addr, vma.start_va()).page_number().to_usize();
+            let stop = VirtAddr::min(end, vma.end_va()).page_number().to_usize();
+            residency.extend((start..stop).map(|vpn| {
+                let vpn = VirtPageNum::new(vpn);
+                vma.pages().contains_key(&vpn) || vma.cached_file_page(vpn).is_some()
+            }));
+        }
+        Ok(residency)
+    }
+
+    /// Releases the resident pages of the VMAs in `[addr, end)`, which are refilled on
+    /// the next access, after checking that `allowed` returns true for all of them.
+    fn release(
+        &self,
+        addr: VirtAddr,
+        end: VirtAddr,
+        allowed: impl Fn(&VmArea) -> bool,
+    ) -> SysResult<()> {
+        let mut vm_areas_lock = self.vm_areas.lock();
+        let keys = Self::covering_areas(&vm_areas_lock, addr, end)?;
+        for vma in keys.iter().map(|key| &vm_areas_lock[key]) {
+            if vma.flags().contains(VmaFlags::LOCKED)
+                || matches!(vma.map_type, TypedArea::Special(_))
+                || !allowed(vma)
+            {
+                return Err(SysError::EINVAL);
+            }
+        }
+        let released = keys
+            .iter()
+            .map(|key| {
+                let vma = vm_areas_lock.get_mut(key).unwrap();
+                vma.drop_range(&self.page_table, addr, end, |_, _| true)
+            })
+            .sum();
+        self.sub_rss(released);
+        Ok(())
+    }
+
+    /// Releases the pages of the shared VMAs in `[addr, end)` along with their backing
+    /// store, which is the shared memory for an anonymous VMA, or a hole punched in the
+    /// file for a file-backed VMA.
+    async fn remove_backing(&self, addr: VirtAddr, end: VirtAddr) -> SysResult<()> {
+        let mut holes: Vec<(Arc<dyn File>, usize, usize)> = Vec::new();
+        {
+            let mut vm_areas_lock = self.vm_areas.lock();
+            let keys = Self::covering_areas(&vm_areas_lock, addr, end)?;
+            for vma in keys.iter().map(|key| &vm_areas_lock[key]) {
+                if vma.flags().contains(VmaFlags::LOCKED) || !vma.flags().contains(VmaFlags::SHARED)
+                {
+                    return Err(SysError::EINVAL);
+                }
+                // Removing the backing store writes to it, like punching a hole.
+                let file_writable = match &vma.map_type {
+                    TypedArea::FileBacked(file_backed) => file_backed.file().flags().writable(),
+                    _ => true,
+                };
+                if !vma.prot().contains(MappingFlags::W) || !file_writable {
+                    return Err(SysError::EACCES);
+                }
+            }
+            for key in keys {
+                let vma = vm_areas_lock.get_mut(&key).unwrap();
+                holes.extend(file_range(vma, addr, end));
+                vma.remove_shared_pages(addr, end);
+                let released = vma.drop_range(&self.page_table, addr, end, |_, _| true);
+                self.sub_rss(released);
+            }
+        }
+        for (file, offset, len) in holes {
+            let len = usize::min(len, file.size().saturating_sub(offset));
+            file.fill_zeros(offset, len).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads the pages of the files mapped in `[addr, end)` into their page caches.
+    async fn read_ahead(&self, addr: VirtAddr, end: VirtAddr) -> SysResult<()> {
+        let mut reads: Vec<(Arc<dyn File>, usize, usize)> = Vec::new();
+        {
+            let vm_areas_lock = self.vm_areas.lock();
+            let keys = Self::covering_areas(&vm_areas_lock, addr, end)?;
+            for vma in keys.iter().map(|key| &vm_areas_lock[key]) {
+                reads.extend(file_range(vma, addr, end));
+            }
+        }
+        for (file, offset, len) in reads {
+            let len = usize::min(len, file.size().saturating_sub(offset));
+            for pos in (offset..offset + len).step_by(PAGE_SIZE) {
+                file.read_page(pos).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets or clears `flags` of the VMAs in `[addr, end)`, splitting VMAs at the
+    /// boundaries of the range.
+    fn advise_flags(
+        &self,
+        addr: VirtAddr,
+        end: VirtAddr,
+        flags: VmaFlags,
+        value: bool,
+    ) -> SysResult<()> {
+        let mut vm_areas_lock = self.vm_areas.lock();
+        let keys = Self::covering_areas(&vm_areas_lock, addr, end)?;
+        if flags.contains(VmaFlags::WIPEONFORK)
+            && value
+            && keys
+                .iter()
+                .any(|key| !vm_areas_lock[key].is_private_anonymous())
+        {
+            return Err(SysError::EINVAL);
+        }
+        for key in keys {
+            let vma = vm_areas_lock.remove(&key).unwrap();
+            let (vma_low, vma_mid, vma_high) = vma.split_area(addr, end);
+            for mut vma in [vma_low, vma_mid, vma_high].into_iter().flatten() {
+                if vma.start_va() >= addr && vma.end_va() <= end {
+                    vma.set_advice(flags, value);
+                }
+                vm_areas_lock.insert(vma.start_va(), vma);
+            }
+        }
+        Ok(())
+    }
+
+    /// Faults in the pages in `[addr, end)` for `access`.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EINVAL`] if a VMA in the range does not allow `access`.
+    fn populate(&self, addr: VirtAddr, end: VirtAddr, access: MappingFlags) -> SysResult<()> {
+        {
+            let vm_areas_lock = self.vm_areas.lock();
+            let keys = Self::covering_areas(&vm_areas_lock, addr, end)?;
+            if keys
+                .iter()
+                .any(|key| !vm_areas_lock[key].prot().contains(access))
+            {
+                return Err(SysError::EINVAL);
+            }
+        }
+        for va in (addr.to_usize()..end.to_usize()).step_by(PAGE_SIZE) {
+            self.handle_page_fault(VirtAddr::new(va), access)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the file mapped by the part of `vma` in `[addr, end)`, with the offset and
+/// the length of the file region mapped by that part, if `vma` is file-backed.
+fn file_range(
+    vma: &VmArea,
+    addr: VirtAddr,
+    end: VirtAddr,
+) -> Option<(Arc<dyn File>, usize, usize)> {
+    let TypedArea::FileBacked(file_backed) = &vma.map_type else {
+        return None;
+    };
+    let start = VirtAddr::max(addr, vma.start_va()).to_usize();
+    let area_offset = start - vma.start_va().to_usize();
+    let area_end = VirtAddr::min(end, vma.end_va()).to_usize();
+    let len = usize::min(
+        file_backed.len().saturating_sub(area_offset),
+        area_end - start,
+    );
+    Some((
+        Arc::clone(file_backed.file()),
+        file_backed.offset() + area_offset,
+        len,
+    ))
+}
@@ -0,0 +1,160 @@
+use core::sync::atomic::Ordering;
+
+use bitflags::bitflags;
+use config::device::MAX_HARTS;
+use systype::error::{SysError, SysResult};
+
+use crate::processor::{
+    hart::get_hart,
+    ipi::{IpiRequest, send_ipi_and_wait},
+};
+
+use super::addr_space::AddrSpace;
+
+bitflags! {
+    /// Commands of `membarrier()`, defined in <linux/membarrier.h>.
+    ///
+    /// `MEMBARRIER_CMD_QUERY` is the empty set.
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MembarrierCmd: usize {
+        /// Make all threads on the system execute a memory barrier.
+        const GLOBAL = 1 << 0;
+        /// Make all running threads of the processes registered with
+        /// `REGISTER_GLOBAL_EXPEDITED` execute a memory barrier.
+        const GLOBAL_EXPEDITED = 1 << 1;
+        /// Register the process to receive `GLOBAL_EXPEDITED` barriers.
+        const REGISTER_GLOBAL_EXPEDITED = 1 << 2;
+        /// Make all running threads of the calling process execute a memory barrier.
+        const PRIVATE_EXPEDITED = 1 << 3;
+        /// Register the process to use `PRIVATE_EXPEDITED`.
+        const REGISTER_PRIVATE_EXPEDITED = 1 << 4;
+        /// Like `PRIVATE_EXPEDITED`, and also make the threads synchronize their
+        /// instruction streams.
+        const PRIVATE_EXPEDITED_SYNC_CORE = 1 << 5;
+        /// Register the process to use `PRIVATE_EXPEDITED_SYNC_CORE`.
+        const REGISTER_PRIVATE_EXPEDITED_SYNC_CORE = 1 << 6;
+        /// Like `PRIVATE_EXPEDITED`, and also restart the restartable sequence critical
+        /// sections of the threads.
+        const PRIVATE_EXPEDITED_RSEQ = 1 << 7;
+        /// Register the process to use `PRIVATE_EXPEDITED_RSEQ`.
+        const REGISTER_PRIVATE_EXPEDITED_RSEQ = 1 << 8;
+        /// Return the commands the process has registered for.
+        const GET_REGISTRATIONS = 1 << 9;
+    }
+}
+
+impl MembarrierCmd {
+    /// Flag of `PRIVATE_EXPEDITED_RSEQ` to only target the CPU given as the third
+    /// argument of `membarrier()`.
+    pub const MEMBARRIER_CMD_FLAG_CPU: usize = 1 << 0;
+
+    /// Commands that register the process for other commands.
+    const REGISTRATIONS: Self = Self::REGISTER_GLOBAL_EXPEDITED
+        .union(Self::REGISTER_PRIVATE_EXPEDITED)
+        .union(Self::REGISTER_PRIVATE_EXPEDITED_SYNC_CORE)
+        .union(Self::REGISTER_PRIVATE_EXPEDITED_RSEQ);
+}
+
+impl AddrSpace {
+    /// Executes the `membarrier()` command `cmd` on behalf of the process owning the
+    /// address space.
+    ///
+    /// Barriers are issued by sending IPIs to the harts running the targeted threads,
+    /// which execute a memory barrier when handling them. The calling hart executes
+    /// the barrier itself. As restartable sequences are not supported, no critical
+    /// section needs restarting, so `PRIVATE_EXPEDITED_RSEQ` only issues barriers.
+    ///
+    /// Returns the supported commands for `MEMBARRIER_CMD_QUERY`, the registered
+    /// commands for `GET_REGISTRATIONS`, and 0 otherwise.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EINVAL`] if `cmd` is not a single known command, if `flags`
+    /// is invalid for it, or if `cpu_id` is not a valid hart ID. Returns
+    /// [`SysError::EPERM`] if an expedited private command is used without registering
+    /// for it first.
+    pub fn membarrier(&self, cmd: usize, flags: usize, cpu_id: usize) -> SysResult<usize> {
+        let cmd = MembarrierCmd::from_bits(cmd).ok_or(SysError::EINVAL)?;
+        if cmd.bits().count_ones() > 1 {
+            return Err(SysError::EINVAL);
+        }
+        let valid_flags = match cmd {
+            MembarrierCmd::PRIVATE_EXPEDITED_RSEQ => MembarrierCmd::MEMBARRIER_CMD_FLAG_CPU,
+            _ => 0,
+        };
+        if flags & !valid_flags != 0 {
+            return Err(SysError::EINVAL);
+        }
+
+        let registrations = MembarrierCmd::from_bits_truncate(
+            self.membarrier_registrations.load(Ordering::Acquire),
+        );
+        let require = |registration: MembarrierCmd| {
+            if registrations.contains(registration) {
+                Ok(())
+            } else {
+                Err(SysError::EPERM)
+            }
+        };
+
+        match cmd {
+            MembarrierCmd::GLOBAL => {
+                send_ipi_and_wait(running_harts(|_| true), IpiRequest::MEMORY_BARRIER);
+            }
+            MembarrierCmd::GLOBAL_EXPEDITED => {
+                let registered = |addr_space: &AddrSpace| {
+                    MembarrierCmd::from_bits_truncate(
+                        addr_space.membarrier_registrations.load(Ordering::Acquire),
+                    )
+                    .contains(MembarrierCmd::REGISTER_GLOBAL_EXPEDITED)
+                };
+                send_ipi_and_wait(running_harts(registered), IpiRequest::MEMORY_BARRIER);
+            }
+            MembarrierCmd::PRIVATE_EXPEDITED => {
+                require(MembarrierCmd::REGISTER_PRIVATE_EXPEDITED)?;
+                send_ipi_and_wait(self.active_harts(), IpiRequest::MEMORY_BARRIER);
+            }
+            MembarrierCmd::PRIVATE_EXPEDITED_SYNC_CORE => {
+                require(MembarrierCmd::REGISTER_PRIVATE_EXPEDITED_SYNC_CORE)?;
+                send_ipi_and_wait(
+                    self.active_harts(),
+                    IpiRequest::MEMORY_BARRIER | IpiRequest::SYNC_CORE,
+                );
+            }
+            MembarrierCmd::PRIVATE_EXPEDITED_RSEQ => {
+                require(MembarrierCmd::REGISTER_PRIVATE_EXPEDITED_RSEQ)?;
+                let mut hart_mask = self.active_harts();
+                if flags & MembarrierCmd::MEMBARRIER_CMD_FLAG_CPU != 0 {
+                    if cpu_id >= MAX_HARTS {
+                        return Err(SysError::EINVAL);
+                    }
+                    hart_mask &= 1 << cpu_id;
+                }
+                send_ipi_and_wait(hart_mask, IpiRequest::MEMORY_BARRIER);
+            }
+            MembarrierCmd::GET_REGISTRATIONS => {
+                return Ok(registrations.bits());
+            }
+            cmd if cmd.is_empty() => {
+                return Ok(MembarrierCmd::all().bits());
+            }
+            registration => {
+                debug_assert!(MembarrierCmd::REGISTRATIONS.contains(registration));
+                self.membarrier_registrations
+                    .fetch_or(registration.bits(), Ordering::AcqRel);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Returns the mask of the harts running user threads whose address spaces satisfy
+/// `predicate`.
+fn running_harts(predicate: impl Fn(&AddrSpace) -> bool) -> usize {
+    (0..MAX_HARTS)
+        .filter(|&id| {
+            get_hart(id)
+                .try_get_task()
+                .is_some_and(|task| predicate(&task.addr_space()))
+        })
+        .fold(0, |mask, id| mask | 1 << id)
+}
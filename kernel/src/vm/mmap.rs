@@ -1,6 +1,6 @@
 use alloc::sync::Arc;
 
-use config::mm::{MMAP_END, MMAP_START, PAGE_SIZE};
+use config::mm::{HUGE_PAGE_SIZE, MMAP_END, MMAP_START, PAGE_SIZE};
 use mm::address::VirtAddr;
 use osfs::special::memfd::flags::MemfdSeals;
 use systype::{
@@ -11,6 +11,7 @@ use vfs::file::File;
 
 use super::{
     addr_space::AddrSpace,
+    oom,
     vm_area::{VmArea, VmaFlags},
 };
 
@@ -26,6 +27,8 @@ impl AddrSpace {
     ///
     /// # Attention
     /// - If `va` is 0, the system automatically finds a suitable virtual address range
+    /// - With `MAP_HUGETLB`, the mapping must be anonymous, and both its address and
+    ///   length are aligned to the size of a huge page
     pub fn map_file(
         &self,
         file: Option<Arc<dyn File>>,
@@ -36,22 +39,34 @@ impl AddrSpace {
         offset: usize,
         seals: Option<MemfdSeals>,
     ) -> SysResult<usize> {
+        let hugetlb = flags.contains(MmapFlags::MAP_HUGETLB);
+        let align = if hugetlb { HUGE_PAGE_SIZE } else { PAGE_SIZE };
+        let misaligned =
+            flags.contains(MmapFlags::MAP_FIXED) && addr.to_usize() % HUGE_PAGE_SIZE != 0;
+        if hugetlb && (file.is_some() || misaligned) {
+            return Err(SysError::EINVAL);
+        }
+
+        let length = length
+            .checked_next_multiple_of(align)
+            .ok_or(SysError::ENOMEM)?;
         if !flags.contains(MmapFlags::MAP_FIXED) {
+            // Find a region large enough to hold an aligned region of `length` bytes.
             addr = self
                 .find_vacant_memory(
                     addr,
-                    length,
+                    length + align - PAGE_SIZE,
                     VirtAddr::new(MMAP_START),
                     VirtAddr::new(MMAP_END),
                 )
                 .ok_or(SysError::ENOMEM)?;
+            addr = VirtAddr::new(addr.to_usize().next_multiple_of(align));
         }
 
-        let length = (length + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
         let va_start = addr;
         let va_end = VirtAddr::new(addr.to_usize() + length);
 
-        let vma_flags = match flags.intersection(MmapFlags::MAP_TYPE_MASK) {
+        let mut vma_flags = match flags.intersection(MmapFlags::MAP_TYPE_MASK) {
             MmapFlags::MAP_PRIVATE => Ok(VmaFlags::PRIVATE),
             MmapFlags::MAP_SHARED => Ok(VmaFlags::SHARED),
             e => {
@@ -59,6 +74,16 @@ impl AddrSpace {
                 Err(SysError::EINVAL)
             }
         }?;
+        if hugetlb {
+            vma_flags |= VmaFlags::HUGETLB;
+        }
+
+        if vma_flags.contains(VmaFlags::PRIVATE)
+            && prot.contains(MappingFlags::W)
+            && (!flags.contains(MmapFlags::MAP_NORESERVE) || oom::overcommit_never())
+        {
+            oom::vm_enough_memory(length / PAGE_SIZE)?;
+        }
 
         let area = match file {
             Some(file) => VmArea::new_file_backed(
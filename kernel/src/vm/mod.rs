@@ -1,8 +1,16 @@
 pub mod addr_space;
+pub mod asid;
 pub mod elf;
+pub mod madvise;
+pub mod membarrier;
 pub mod mmap;
+pub mod mremap;
+pub mod oom;
+pub mod remote;
 pub mod shm;
 pub mod user_ptr;
+pub mod userfault;
+pub mod vdso;
 pub mod vm_area;
 
 #[cfg(target_arch = "riscv64")]
@@ -10,7 +18,7 @@ pub mod iomap;
 
 mod page_table;
 
-pub use page_table::switch_to_kernel_page_table;
+pub use page_table::{anon_huge_pages, switch_to_kernel_page_table};
 
 #[allow(unused_imports)]
 pub use page_table::trace_page_table_lookup;
@@ -0,0 +1,211 @@
+use alloc::collections::btree_map::BTreeMap;
+
+use config::mm::{MMAP_END, MMAP_START, PAGE_SIZE};
+use mm::address::VirtAddr;
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MremapFlags,
+};
+
+use super::{
+    addr_space::AddrSpace,
+    oom,
+    page_table::TlbBatch,
+    vm_area::{VmArea, VmaFlags},
+};
+
+/// Returns the key of the VMA in `vm_areas` which contains `addr`.
+fn area_containing(vm_areas: &BTreeMap<VirtAddr, VmArea>, addr: VirtAddr) -> Option<VirtAddr> {
+    vm_areas
+        .range(..=addr)
+        .next_back()
+        .filter(|(_, vma)| vma.contains(addr))
+        .map(|(&va, _)| va)
+}
+
+/// Returns whether `[addr, end)` is a valid range of user addresses.
+fn is_user_range(addr: VirtAddr, end: usize) -> bool {
+    VirtAddr::check_validity(end) && end > addr.to_usize() && VirtAddr::new(end - 1).in_user_space()
+}
+
+impl AddrSpace {
+    /// Expands, shrinks or moves the mapping at `[old_addr, old_addr + old_size)` to
+    /// `new_size` bytes, and returns its new address.
+    ///
+    /// The mapping is resized in place if it shrinks, or if the range after it is free.
+    /// Otherwise it is moved if `MREMAP_MAYMOVE` is set, to `new_addr` if
+    /// `MREMAP_FIXED` is set, or else to a vacant region. Moving a mapping moves its
+    /// pages and their page table entries, so that its contents are never copied. With
+    /// `MREMAP_DONTUNMAP`, the old mapping is kept without its pages.
+    ///
+    /// If `old_size` is 0, the shared mapping at `old_addr` is mapped again at the new
+    /// address, sharing the same pages.
+    ///
+    /// `old_addr` and `new_addr` must be page-aligned. The sizes need not be, and are
+    /// rounded up to page size.
+    ///
+    /// `flags` must be valid: `MREMAP_FIXED` and `MREMAP_DONTUNMAP` require
+    /// `MREMAP_MAYMOVE`, and `MREMAP_DONTUNMAP` requires the sizes to be equal.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EFAULT`] if the old range is not within a single VMA,
+    /// [`SysError::EINVAL`] if the old range overlaps with `new_addr`, or if `old_size`
+    /// is 0 for a private mapping, and [`SysError::ENOMEM`] if the mapping can't grow
+    /// in place and may not move, or there is no room to move it to.
+    pub fn remap(
+        &self,
+        old_addr: VirtAddr,
+        old_size: usize,
+        new_size: usize,
+        flags: MremapFlags,
+        new_addr: VirtAddr,
+    ) -> SysResult<VirtAddr> {
+        let old_size = VirtAddr::new(old_size).round_up().to_usize();
+        let new_size = VirtAddr::new(new_size).round_up().to_usize();
+        let old_end = VirtAddr::new(old_addr.to_usize() + old_size);
+        let may_move = flags.contains(MremapFlags::MREMAP_MAYMOVE);
+        let fixed = flags.contains(MremapFlags::MREMAP_FIXED);
+        let dontunmap = flags.contains(MremapFlags::MREMAP_DONTUNMAP);
+
+        if fixed {
+            let new_end = new_addr.to_usize() + new_size;
+            if !is_user_range(new_addr, new_end) {
+                return Err(SysError::EINVAL);
+            }
+            if new_addr < old_end && old_addr.to_usize() < new_end {
+                return Err(SysError::EINVAL);
+            }
+        }
+
+        // Check the overcommit policy before growing a private writable mapping, which
+        // must not be done while holding the lock of the VMAs.
+        let committed = {
+            let vm_areas_lock = self.vm_areas.lock();
+            let key = area_containing(&vm_areas_lock, old_addr).ok_or(SysError::EFAULT)?;
+            vm_areas_lock[&key].is_committed()
+        };
+        if committed && old_size != 0 && new_size > old_size {
+            oom::vm_enough_memory((new_size - old_size) / PAGE_SIZE)?;
+        }
+
+        // The batch is dropped after the lock of the VMAs. See `Self::remove_range`.
+        let mut tlb_batch = self.page_table.batch_tlb_shootdowns();
+        let mut vm_areas_lock = self.vm_areas.lock();
+        let key = area_containing(&vm_areas_lock, old_addr).ok_or(SysError::EFAULT)?;
+        let vma = &vm_areas_lock[&key];
+        if old_end > vma.end_va() {
+            return Err(SysError::EFAULT);
+        }
+
+        if old_size == 0 {
+            if !may_move || !vma.flags().contains(VmaFlags::SHARED) {
+                return Err(SysError::EINVAL);
+            }
+            let (_, dup, _) = vma.unpopulated().split_area(old_addr, vma.end_va());
+            let mut dup = dup.unwrap();
+            if dup.length() > new_size {
+                let new_end = VirtAddr::new(old_addr.to_usize() + new_size);
+                dup = dup.split_area(old_addr, new_end).1.unwrap();
+            }
+            let target = self.remap_target(
+                &mut vm_areas_lock,
+                &mut tlb_batch,
+                fixed,
+                new_addr,
+                new_size,
+            )?;
+            dup.move_to(&self.page_table, target)?;
+            dup.expand(VirtAddr::new(target.to_usize() + new_size));
+            log::info!("[remap] duplicate {old_addr:?} at {target:?}");
+            vm_areas_lock.insert(target, dup);
+            return Ok(target);
+        }
+
+        if !fixed && !dontunmap {
+            let new_end = old_addr.to_usize() + new_size;
+            if new_size <= old_size {
+                self.remove_range(
+                    &mut vm_areas_lock,
+                    &mut tlb_batch,
+                    VirtAddr::new(new_end),
+                    old_end,
+                );
+                return Ok(old_addr);
+            }
+            let next_start = vm_areas_lock
+                .range(old_end..)
+                .next()
+                .map(|(&va, _)| va.to_usize())
+                .unwrap_or(usize::MAX);
+            if old_end == vma.end_va() && new_end <= next_start && is_user_range(old_addr, new_end)
+            {
+                let vma = vm_areas_lock.get_mut(&key).unwrap();
+                vma.expand(VirtAddr::new(new_end));
+                log::info!("[remap] expand {old_addr:?} in place to {new_size:#x}");
+                return Ok(old_addr);
+            }
+        }
+
+        if !may_move {
+            return Err(SysError::ENOMEM);
+        }
+
+        // The old range is shrunk before it moves, and is expanded after it moves.
+        let moved_end = VirtAddr::new(old_addr.to_usize() + usize::min(old_size, new_size));
+        self.remove_range(&mut vm_areas_lock, &mut tlb_batch, moved_end, old_end);
+        let target = self.remap_target(
+            &mut vm_areas_lock,
+            &mut tlb_batch,
+            fixed,
+            new_addr,
+            new_size,
+        )?;
+
+        let key = area_containing(&vm_areas_lock, old_addr).unwrap();
+        let vma = vm_areas_lock.remove(&key).unwrap();
+        let (vma_low, vma_mid, vma_high) = vma.split_area(old_addr, moved_end);
+        let mut vma_mid = vma_mid.unwrap();
+        for vma in [vma_low, vma_high].into_iter().flatten() {
+            vm_areas_lock.insert(vma.start_va(), vma);
+        }
+
+        let old_vma = dontunmap.then(|| vma_mid.unpopulated());
+        if let Err(e) = vma_mid.move_to(&self.page_table, target) {
+            vm_areas_lock.insert(vma_mid.start_va(), vma_mid);
+            return Err(e);
+        }
+        vma_mid.expand(VirtAddr::new(target.to_usize() + new_size));
+        vm_areas_lock.insert(target, vma_mid);
+        if let Some(old_vma) = old_vma {
+            vm_areas_lock.insert(old_vma.start_va(), old_vma);
+        }
+        log::info!("[remap] move {old_addr:?} to {target:?} with size {new_size:#x}");
+        Ok(target)
+    }
+
+    /// Returns the address to move a mapping of `size` bytes to, which is `new_addr`
+    /// whose mappings are removed if `fixed` is true, or else a vacant region.
+    fn remap_target(
+        &self,
+        vm_areas_lock: &mut BTreeMap<VirtAddr, VmArea>,
+        tlb_batch: &mut TlbBatch,
+        fixed: bool,
+        new_addr: VirtAddr,
+        size: usize,
+    ) -> SysResult<VirtAddr> {
+        if fixed {
+            let new_end = VirtAddr::new(new_addr.to_usize() + size);
+            self.remove_range(vm_areas_lock, tlb_batch, new_addr, new_end);
+            Ok(new_addr)
+        } else {
+            Self::find_vacant_in(
+                vm_areas_lock,
+                VirtAddr::new(0),
+                size,
+                VirtAddr::new(MMAP_START),
+                VirtAddr::new(MMAP_END),
+            )
+            .ok_or(SysError::ENOMEM)
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Module for the out-of-memory (OOM) killer and overcommit accounting.
+//!
+//! When the frame allocator or the kernel heap runs out of memory, it calls
+//! [`out_of_memory`] through [`KernelMmOperations`]. The OOM killer then picks the
+//! process with the highest badness score, kills it with `SIGKILL` and releases its
+//! private memory at once, so that the failed allocation can be retried.
+//!
+//! The badness score of a process is its resident set size in pages, adjusted by its
+//! `oom_score_adj`, which is in `-1000..=1000` and is scaled to the total memory. A
+//! process with `oom_score_adj` of -1000 is never killed, nor is the init process.
+//!
+//! Overcommit accounting decides whether a private writable mapping may be created,
+//! according to `/proc/sys/vm/overcommit_memory`:
+//! - `OVERCOMMIT_GUESS`: refuse only mappings larger than the whole memory.
+//! - [`OVERCOMMIT_ALWAYS`]: never refuse.
+//! - [`OVERCOMMIT_NEVER`]: refuse if the committed memory of all processes would
+//!   exceed `overcommit_ratio` percent of the memory.
+
+use alloc::sync::{Arc, Weak};
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use config::process::INIT_PROC_ID;
+use mm::{frame::total_frames, kinterface::KernelMmOperations, stat::VmStat};
+use osfs::proc::{
+    oom::OOM_SCORE_ADJ_MIN,
+    sysctl::{OVERCOMMIT_ALWAYS, OVERCOMMIT_MEMORY, OVERCOMMIT_NEVER, OVERCOMMIT_RATIO},
+};
+use systype::error::{SysError, SysResult};
+
+use crate::task::{
+    Task, TaskState,
+    manager::TASK_MANAGER,
+    signal::sig_info::{Sig, SigDetails, SigInfo},
+};
+
+/// Guard against running the OOM killer on several harts at once, or recursively
+/// when the OOM killer itself fails to allocate memory.
+static OOM_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Returns the badness score of `task`, or `None` if it must not be killed.
+fn badness(task: &Arc<Task>) -> Option<usize> {
+    if !task.is_process() || task.tid() == INIT_PROC_ID || task.is_in_state(TaskState::Zombie) {
+        return None;
+    }
+    let adj = task.oom_score_adj.load(Ordering::Relaxed) as isize;
+    if adj == OOM_SCORE_ADJ_MIN {
+        return None;
+    }
+    let points = task.addr_space().rss() as isize + adj * total_frames() as isize / 1000;
+    Some(points.max(1) as usize)
+}
+
+/// Returns the OOM score of `task` as shown in `/proc/<pid>/oom_score`, which is its
+/// badness score normalized to `0..=1000`.
+pub fn oom_score(task: &Arc<Task>) -> usize {
+    let total = total_frames().max(1);
+    badness(task).map_or(0, |points| (points * 1000 / total).min(1000))
+}
+
+/// Picks the process with the highest badness score.
+///
+/// Returns `None` if there is no killable process, or if the task list is locked, e.g.,
+/// when the allocation failed while adding a task.
+fn select_victim() -> Option<Arc<Task>> {
+    let tasks = TASK_MANAGER.inner().try_lock()?;
+    tasks
+        .values()
+        .filter_map(Weak::upgrade)
+        .filter_map(|task| badness(&task).map(|points| (points, task)))
+        .max_by_key(|(points, _)| *points)
+        .map(|(_, task)| task)
+}
+
+/// Kills a process to free memory.
+///
+/// Returns `true` if some memory has been freed.
+pub fn out_of_memory() -> bool {
+    if OOM_LOCK
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        return false;
+    }
+
+    let freed = match select_victim() {
+        Some(victim) => {
+            victim.receive_siginfo(SigInfo {
+                sig: Sig::SIGKILL,
+                code: SigInfo::KERNEL,
+                details: SigDetails::None,
+            });
+            VmStat::OomKill.inc();
+            // The victim may be the task whose page fault ran out of memory, in which case
+            // its VMAs are locked and its memory is freed when it exits.
+            let reaped = victim.addr_space().reap().unwrap_or(0);
+            log::warn!(
+                "[out_of_memory] killed process {} ({}), reaped {} pages",
+                victim.tid(),
+                victim.get_name(),
+                reaped
+            );
+            reaped > 0
+        }
+        None => {
+            log::error!("[out_of_memory] no killable process");
+            false
+        }
+    };
+
+    OOM_LOCK.store(false, Ordering::Release);
+    freed
+}
+
+/// Returns the number of pages committed by all processes.
+pub fn committed_pages() -> usize {
+    let committed = Cell::new(0);
+    let _ = TASK_MANAGER.for_each(|task| {
+        if task.is_process() && !task.is_in_state(TaskState::Zombie) {
+            committed.set(committed.get() + task.addr_space().committed_pages());
+        }
+        Ok(())
+    });
+    committed.get()
+}
+
+/// Returns whether the overcommit policy is [`OVERCOMMIT_NEVER`], in which case
+/// `MAP_NORESERVE` is ignored.
+pub fn overcommit_never() -> bool {
+    OVERCOMMIT_MEMORY.load(Ordering::Relaxed) == OVERCOMMIT_NEVER
+}
+
+/// Checks whether `pages` more pages may be committed under the overcommit policy.
+///
+/// # Errors
+/// Returns [`SysError::ENOMEM`] if the policy refuses the commitment.
+pub fn vm_enough_memory(pages: usize) -> SysResult<()> {
+    let total = total_frames();
+    let enough = match OVERCOMMIT_MEMORY.load(Ordering::Relaxed) {
+        OVERCOMMIT_ALWAYS => true,
+        OVERCOMMIT_NEVER => {
+            let limit = total * OVERCOMMIT_RATIO.load(Ordering::Relaxed) / 100;
+            committed_pages() + pages <= limit
+        }
+        _ => pages <= total,
+    };
+    if enough {
+        Ok(())
+    } else {
+        log::info!("[vm_enough_memory] refused to commit {} pages", pages);
+        Err(SysError::ENOMEM)
+    }
+}
+
+struct KernelMmOperationsImpl;
+
+#[crate_interface::impl_interface]
+impl KernelMmOperations for KernelMmOperationsImpl {
+    fn out_of_memory() -> bool {
+        out_of_memory()
+    }
+}
@@ -2,17 +2,24 @@
 //!
 //! This module provides a `PageTable` struct that manipulates page tables in momery
 //! and tracking allocated pages.
+//!
+//! Besides 4 KiB base pages, a page table can map 2 MiB huge pages (Sv39 megapages or
+//! LoongArch huge pages) by leaf entries in level-1 page tables. Methods that look up
+//! base page entries split a huge page into base pages on the way, so code that deals
+//! only in base pages keeps working on huge pages, at the cost of the huge mapping.
 
-use alloc::vec::Vec;
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use arch::{
-    mm::{fence, tlb_shootdown},
+    mm::fence,
     pte::{PageTableEntry, PteFlags},
 };
-use config::mm::PTE_PER_TABLE;
+use config::mm::{HUGE_PAGE_PAGES, PAGE_SIZE, PTE_PER_TABLE};
 use mm::{
     address::{PhysPageNum, VirtAddr, VirtPageNum},
     page_cache::page::Page,
+    stat::VmStat,
 };
 use mutex::SpinLock;
 use systype::error::{SysError, SysResult};
@@ -21,7 +28,7 @@ use systype::error::{SysError, SysResult};
 use loongArch64::register::pgdl;
 
 #[cfg(target_arch = "riscv64")]
-use arch::mm::tlb_flush_all_except_global;
+use arch::mm::tlb_flush_asid;
 #[cfg(target_arch = "riscv64")]
 use config::mm::{
     VIRT_END, bss_end, bss_start, data_end, data_start, kernel_end, kernel_start, rodata_end,
@@ -31,6 +38,10 @@ use config::mm::{
 use systype::memory_flags::MappingFlags;
 
 use crate::frame::FrameTracker;
+#[cfg(target_arch = "loongarch64")]
+use crate::processor::ipi::{self, TlbFlush};
+
+use super::asid::{AsidHandle, asid_alloc};
 
 #[cfg(target_arch = "riscv64")]
 use super::iomap::IO_MAPPINGS;
@@ -50,12 +61,79 @@ use crate::vm::vm_area::{OffsetArea, VmArea};
 /// this struct. User-used tables are exclusively allocated for each process,
 /// and are tracked by this struct. When a `PageTable` is dropped, all user-used
 /// tables are dropped.
+///
+/// A page table also tracks the harts whose TLBs may cache its translations, so that
+/// TLB shootdowns only target these harts. With an ASID, the translations stay in the
+/// TLB of a hart after it switches to another page table, so the hart is tracked until
+/// the page table is dropped.
 #[derive(Debug)]
 pub struct PageTable {
     /// Physical page number of the root page table.
     root: PhysPageNum,
     /// Frames allocated for user-used tables
     frames: SpinLock<Vec<FrameTracker>>,
+    /// Frames deposited for splitting huge pages mapped by [`Self::map_huge_page_to`],
+    /// keyed by the first VPN of each huge page, so that splitting such a huge page
+    /// never fails for lack of memory.
+    deposits: SpinLock<BTreeMap<VirtPageNum, FrameTracker>>,
+    /// ASID tagging the translations of the page table in the TLB.
+    asid: AsidHandle,
+    /// Mask of the harts whose TLBs may cache translations of the page table.
+    tlb_harts: AtomicUsize,
+}
+
+/// A batch of TLB shootdowns of a page table, created by
+/// [`PageTable::batch_tlb_shootdowns`].
+///
+/// The batch lives on the stack of the caller changing the mappings, and only the
+/// shootdowns passed to [`Self::tlb_shootdown`] are deferred, so the shootdowns of
+/// other threads sharing the page table are never delayed. When the batch is dropped,
+/// a single shootdown covering the ranges of all deferred shootdowns is issued, and
+/// then the pages unmapped in the batch are released, since other harts may still
+/// access them through stale TLB entries before the shootdown.
+pub struct TlbBatch<'a> {
+    page_table: &'a PageTable,
+    /// Range `[start, end)` covering the deferred shootdowns, if any.
+    range: Option<(usize, usize)>,
+    /// Pages unmapped in the batch, released after the shootdown.
+    pages: Vec<Arc<Page>>,
+}
+
+impl<'a> TlbBatch<'a> {
+    /// Returns the page table whose shootdowns are batched.
+    pub fn page_table(&self) -> &'a PageTable {
+        self.page_table
+    }
+
+    /// Defers a TLB shootdown for the range `[addr, addr + length)` until the batch
+    /// is dropped.
+    pub fn tlb_shootdown(&mut self, addr: usize, length: usize) {
+        let (start, end) = self.range.unwrap_or((addr, addr + length));
+        self.range = Some((start.min(addr), end.max(addr + length)));
+    }
+
+    /// Keeps `page`, which is unmapped in the batch, until the shootdown is issued.
+    pub fn release_page(&mut self, page: Arc<Page>) {
+        self.pages.push(page);
+    }
+}
+
+impl Drop for TlbBatch<'_> {
+    fn drop(&mut self) {
+        if self.range.is_some() {
+            self.page_table.issue_tlb_shootdown(self.range);
+        }
+        self.pages.clear();
+    }
+}
+
+/// Number of huge pages mapped by [`PageTable::map_huge_page_to`] in all page tables,
+/// which are all in anonymous areas.
+static ANON_HUGE_PAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of huge pages mapped in anonymous areas.
+pub fn anon_huge_pages() -> usize {
+    ANON_HUGE_PAGES.load(Ordering::Relaxed)
 }
 
 #[cfg(target_arch = "riscv64")]
@@ -75,14 +153,26 @@ impl PageTable {
     /// # Errors
     /// Returns an [`ENOMEM`] error if memory allocation for the root page table fails.
     pub fn build() -> SysResult<Self> {
+        Self::build_with_asid(asid_alloc())
+    }
+
+    /// Builds a new `PageTable` with an empty root page table, tagged with `asid`.
+    ///
+    /// # Errors
+    /// Returns an [`ENOMEM`] error if memory allocation for the root page table fails.
+    fn build_with_asid(asid: AsidHandle) -> SysResult<Self> {
         let root_frame = FrameTracker::build()?;
         // SAFETY: the frame is newly allocated for the root page table.
         unsafe {
             PageTableMem::new(root_frame.ppn()).clear();
         }
+        VmStat::PageTablePages.inc();
         Ok(PageTable {
             root: root_frame.ppn(),
             frames: SpinLock::new(alloc::vec![root_frame]),
+            deposits: SpinLock::new(BTreeMap::new()),
+            asid,
+            tlb_harts: AtomicUsize::new(0),
         })
     }
 
@@ -105,7 +195,9 @@ impl PageTable {
     /// frames, which should not happen in practice.
     #[cfg(target_arch = "riscv64")]
     unsafe fn build_kernel_page_table() -> Self {
-        let mut page_table = Self::build().unwrap();
+        // The kernel page table is switched to with ASID 0, and used by all harts.
+        let mut page_table = Self::build_with_asid(AsidHandle::SHARED).unwrap();
+        page_table.tlb_harts = AtomicUsize::new(usize::MAX);
 
         /* Map the kernel's .text, .rodata, .data, and .bss sections */
 
@@ -172,7 +264,8 @@ impl PageTable {
     /// `inner_flags` decides the flags for non-leaf entries. Some bits may not be
     /// used.
     ///
-    /// This function only support 4 KiB pages, 3-level page tables.
+    /// This function only support 4 KiB pages, 3-level page tables. A huge page
+    /// covering `vpn` is split into base pages.
     ///
     /// Returns a mutable reference to the leaf page table entry, and a boolean
     /// indicating whether any non-leaf entry is created. If any non-leaf entry is
@@ -188,17 +281,7 @@ impl PageTable {
         inner_flags: PteFlags,
     ) -> SysResult<(&mut PageTableEntry, bool)> {
         let mut ppn = self.root;
-        let inner_flags = {
-            #[cfg(target_arch = "riscv64")]
-            {
-                (inner_flags & PteFlags::G) | PteFlags::V
-            }
-            #[cfg(target_arch = "loongarch64")]
-            {
-                let _ = inner_flags;
-                PteFlags::empty()
-            }
-        };
+        let inner_flags = non_leaf_flags(inner_flags);
         let mut inner_created = false;
         for (i, index) in vpn.indices().into_iter().enumerate().rev() {
             let mut page_table = unsafe { PageTableMem::new(ppn) };
@@ -226,6 +309,9 @@ impl PageTable {
                 *entry = PageTableEntry::new(frame.ppn(), inner_flags);
                 self.track_frame(frame);
                 inner_created = true;
+            } else if entry.is_huge() {
+                self.split_huge_entry(vpn, entry)?;
+                inner_created = true;
             }
             ppn = entry.ppn();
         }
@@ -236,7 +322,11 @@ impl PageTable {
     /// If any non-leaf entry is not present, returns `None`. Note that the returned
     /// entry may be invalid.
     ///
-    /// This function only support 4 KiB pages, 3-level page tables.
+    /// This function only support 4 KiB pages, 3-level page tables. A huge page
+    /// covering `vpn` is split into base pages, and `None` is returned if it cannot
+    /// be split for lack of memory, which only happens to huge pages not mapped by
+    /// [`Self::map_huge_page_to`]. Use [`Self::translate`] to look up a VPN without
+    /// splitting huge pages.
     pub fn find_entry(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
         let mut ppn = self.root;
         for (i, index) in vpn.indices().into_iter().enumerate().rev() {
@@ -250,11 +340,95 @@ impl PageTable {
             if *entry == PageTableEntry::default() {
                 return None;
             }
+            if entry.is_huge() {
+                self.split_huge_entry(vpn, entry).ok()?;
+            }
             ppn = entry.ppn();
         }
         unreachable!();
     }
 
+    /// Returns a mutable reference to the level-1 entry covering a given VPN, if it is
+    /// a leaf mapping a huge page. Note that the returned entry may be invalid.
+    pub fn find_huge_entry(&self, vpn: VirtPageNum) -> Option<&mut PageTableEntry> {
+        let [_, index1, index2] = vpn.indices();
+        let entry = unsafe { PageTableMem::new(self.root) }.get_entry_mut(index2);
+        if *entry == PageTableEntry::default() || entry.is_huge() {
+            return None;
+        }
+        let entry = unsafe { PageTableMem::new(entry.ppn()) }.get_entry_mut(index1);
+        entry.is_huge().then_some(entry)
+    }
+
+    /// Returns the physical page number a given VPN is mapped to, or `None` if it is
+    /// not mapped by a valid entry. Unlike [`Self::find_entry`], this method does not
+    /// split huge pages.
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PhysPageNum> {
+        if let Some(entry) = self.find_huge_entry(vpn) {
+            let base = entry.huge_to_base();
+            let offset = vpn.to_usize() % HUGE_PAGE_PAGES;
+            return base
+                .is_valid()
+                .then(|| PhysPageNum::new(base.ppn().to_usize() + offset));
+        }
+        self.find_entry(vpn)
+            .filter(|entry| entry.is_valid())
+            .map(|entry| entry.ppn())
+    }
+
+    /// Returns a mutable reference to the level-1 entry covering a given VPN, which
+    /// maps a huge page if it is a leaf. This method creates the absent level-2 entry
+    /// using `inner_flags`, as [`Self::find_entry_force`] does.
+    fn find_huge_entry_force(
+        &self,
+        vpn: VirtPageNum,
+        inner_flags: PteFlags,
+    ) -> SysResult<(&mut PageTableEntry, bool)> {
+        let [_, index1, index2] = vpn.indices();
+        let entry = unsafe { PageTableMem::new(self.root) }.get_entry_mut(index2);
+        let mut inner_created = false;
+        if *entry == PageTableEntry::default() {
+            let frame = FrameTracker::build()?;
+            unsafe {
+                PageTableMem::new(frame.ppn()).clear();
+            }
+            *entry = PageTableEntry::new(frame.ppn(), non_leaf_flags(inner_flags));
+            self.track_frame(frame);
+            inner_created = true;
+        }
+        let entry = unsafe { PageTableMem::new(entry.ppn()) }.get_entry_mut(index1);
+        Ok((entry, inner_created))
+    }
+
+    /// Splits the huge page mapped by `entry`, the level-1 entry covering `vpn`, into
+    /// base pages with the same flags, making `entry` point to a page table mapping
+    /// them. The page table is the one deposited for the huge page, or a newly
+    /// allocated one if there is none.
+    ///
+    /// The translations do not change, so no TLB flush is needed for the split itself.
+    fn split_huge_entry(&self, vpn: VirtPageNum, entry: &mut PageTableEntry) -> SysResult<()> {
+        let huge_vpn = VirtPageNum::new(vpn.to_usize() & !(HUGE_PAGE_PAGES - 1));
+        let deposit = self.deposits.lock().remove(&huge_vpn);
+        let frame = match deposit {
+            Some(frame) => {
+                ANON_HUGE_PAGES.fetch_sub(1, Ordering::Relaxed);
+                VmStat::PageTablePages.sub(1);
+                frame
+            }
+            None => FrameTracker::build()?,
+        };
+        let base = entry.huge_to_base();
+        let mut table = unsafe { PageTableMem::new(frame.ppn()) };
+        for (i, pte) in table.as_slice_mut().iter_mut().enumerate() {
+            *pte = PageTableEntry::new(PhysPageNum::new(base.ppn().to_usize() + i), base.flags());
+        }
+        fence();
+        *entry = PageTableEntry::new(frame.ppn(), non_leaf_flags(base.flags()));
+        self.track_frame(frame);
+        VmStat::ThpSplitPage.inc();
+        Ok(())
+    }
+
     /// Maps a leaf page by specifying VPN and page table entry flags, to a newly
     /// allocated frame.
     ///
@@ -290,7 +464,7 @@ impl PageTable {
 
         #[cfg(target_arch = "riscv64")]
         if non_leaf_created {
-            tlb_flush_all_except_global();
+            tlb_flush_asid(self.asid());
         }
 
         Ok(Ok(page))
@@ -338,7 +512,7 @@ impl PageTable {
 
         #[cfg(target_arch = "riscv64")]
         if non_leaf_created {
-            tlb_flush_all_except_global();
+            tlb_flush_asid(self.asid());
         }
 
         Ok(())
@@ -353,11 +527,86 @@ impl PageTable {
     pub fn unmap_page(&self, vpn: VirtPageNum) {
         if let Some(entry) = self.find_entry(vpn) {
             *entry = PageTableEntry::default();
-            // Flush TLB entries for the page for all harts.
-            // We can optimize this by only flushing the TLB for harts that execute
-            // the current process.
-            fence();
-            tlb_shootdown(vpn.address().to_usize(), 1);
+            self.tlb_shootdown(vpn.address().to_usize(), PAGE_SIZE);
+        }
+    }
+
+    /// Maps a huge page by specifying its first VPN, its first PPN, and the page table
+    /// entry flags. Both `vpn` and `ppn` must be aligned to the size of a huge page.
+    ///
+    /// This method does not allocate the frames for the huge page. It deposits a page
+    /// table frame for splitting the huge page later, so that splitting never fails.
+    ///
+    /// # Errors
+    /// Returns an [`EINVAL`] error if any page in the huge page is already mapped.
+    /// Returns an [`ENOMEM`] error if the method needs to allocate a frame but fails
+    /// to do so.
+    pub fn map_huge_page_to(
+        &self,
+        vpn: VirtPageNum,
+        ppn: PhysPageNum,
+        flags: PteFlags,
+    ) -> SysResult<()> {
+        debug_assert!(vpn.to_usize() % HUGE_PAGE_PAGES == 0);
+        debug_assert!(ppn.to_usize() % HUGE_PAGE_PAGES == 0);
+
+        #[allow(unused)]
+        let (entry, mut flush_all) = self.find_huge_entry_force(vpn, flags)?;
+        let deposit = if *entry == PageTableEntry::default() {
+            let frame = FrameTracker::build()?;
+            VmStat::PageTablePages.inc();
+            frame
+        } else if entry.is_huge() {
+            return Err(SysError::EINVAL);
+        } else {
+            // Take back the page table left empty by unmapping its base pages, and
+            // deposit it for the huge page.
+            let table = unsafe { PageTableMem::new(entry.ppn()) };
+            if table
+                .as_slice()
+                .iter()
+                .any(|pte| *pte != PageTableEntry::default())
+            {
+                return Err(SysError::EINVAL);
+            }
+            let mut frames = self.frames.lock();
+            let index = frames
+                .iter()
+                .position(|frame| frame.ppn() == entry.ppn())
+                .ok_or(SysError::EINVAL)?;
+            // The entry pointing to the page table may be cached.
+            flush_all = true;
+            frames.swap_remove(index)
+        };
+        *entry = PageTableEntry::new_huge(ppn, flags);
+        self.deposits.lock().insert(vpn, deposit);
+        ANON_HUGE_PAGES.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(target_arch = "riscv64")]
+        if flush_all {
+            tlb_flush_asid(self.asid());
+        }
+
+        Ok(())
+    }
+
+    /// Unmaps the huge page covering a given VPN, if any, releasing the page table
+    /// frame deposited for it.
+    ///
+    /// This method does not deallocate the frames for the huge page, and does not
+    /// flush the TLB. Returns whether a huge page is unmapped.
+    pub fn unmap_huge_page(&self, vpn: VirtPageNum) -> bool {
+        match self.find_huge_entry(vpn) {
+            Some(entry) => {
+                *entry = PageTableEntry::default();
+                let huge_vpn = VirtPageNum::new(vpn.to_usize() & !(HUGE_PAGE_PAGES - 1));
+                if self.deposits.lock().remove(&huge_vpn).is_some() {
+                    ANON_HUGE_PAGES.fetch_sub(1, Ordering::Relaxed);
+                    VmStat::PageTablePages.sub(1);
+                }
+                true
+            }
+            None => false,
         }
     }
 
@@ -372,6 +621,11 @@ impl PageTable {
     /// mappings by calling this method. Be careful that calling this method with an
     /// already mapped `vpn` will overwrite the existing mapping.
     ///
+    /// Any huge-page-sized and aligned part of the range mapped to physically
+    /// contiguous and aligned frames, and not mapped by a page table yet, is mapped
+    /// as a huge page, which is never split for lack of memory in practice because
+    /// this method is used for kernel mappings.
+    ///
     /// # Note
     /// By the current implementation, any non-leaf entry is created with the same
     /// `G` bit as the leaf entries. This design is sufficient for the current
@@ -384,23 +638,35 @@ impl PageTable {
     ) -> SysResult<()> {
         // Optimization is applied to cut down redundant lookups to entries in the
         // same leaf page table.
-        let mut entry = self.find_entry_force(start_vpn, flags)?.0;
-        *entry = PageTableEntry::new(ppns[0], flags);
-        for (i, &ppn) in ppns.iter().enumerate().skip(1) {
+        let mut entry: Option<&mut PageTableEntry> = None;
+        let mut i = 0;
+        while i < ppns.len() {
             let vpn = start_vpn.to_usize() + i;
-            entry = if vpn % PTE_PER_TABLE == 0 {
-                self.find_entry_force(VirtPageNum::new(vpn), flags)?.0
-            } else {
+            if vpn % HUGE_PAGE_PAGES == 0 && is_huge_run(&ppns[i..]) {
+                let huge_entry = self.find_huge_entry_force(VirtPageNum::new(vpn), flags)?.0;
+                if *huge_entry == PageTableEntry::default() {
+                    *huge_entry = PageTableEntry::new_huge(ppns[i], flags);
+                    entry = None;
+                    i += HUGE_PAGE_PAGES;
+                    continue;
+                }
+            }
+            let next = match entry {
                 // SAFETY: the entry is not the last one in its page table, so the
                 // next entry of `entry` is valid.
-                unsafe { &mut *(entry as *mut PageTableEntry).add(1) }
+                Some(entry) if vpn % PTE_PER_TABLE != 0 => unsafe {
+                    &mut *(entry as *mut PageTableEntry).add(1)
+                },
+                _ => self.find_entry_force(VirtPageNum::new(vpn), flags)?.0,
             };
-            *entry = PageTableEntry::new(ppn, flags);
+            *next = PageTableEntry::new(ppns[i], flags);
+            entry = Some(next);
+            i += 1;
         }
 
         // Simply flush all TLB entries, as the range is likely to be large.
         #[cfg(target_arch = "riscv64")]
-        tlb_flush_all_except_global();
+        tlb_flush_asid(self.asid());
 
         Ok(())
     }
@@ -416,13 +682,89 @@ impl PageTable {
     pub fn unmap_range(&self, start_vpn: VirtPageNum, count: usize) {
         for i in 0..count {
             let vpn = VirtPageNum::new(start_vpn.to_usize() + i);
-            self.unmap_page(vpn);
+            if let Some(entry) = self.find_entry(vpn) {
+                *entry = PageTableEntry::default();
+            }
+        }
+        // Perform a single TLB shootdown for the range.
+        self.tlb_shootdown(start_vpn.address().to_usize(), count * PAGE_SIZE);
+    }
+
+    /// Returns the ASID of the page table, which is 0 if it has none.
+    pub fn asid(&self) -> usize {
+        self.asid.asid()
+    }
+
+    /// Marks the hart `hart_id` as one whose TLB may cache translations of the page
+    /// table. This must be done before the hart switches to the page table.
+    pub fn activate_on(&self, hart_id: usize) {
+        self.tlb_harts.fetch_or(1 << hart_id, Ordering::AcqRel);
+    }
+
+    /// Notes that the hart `hart_id` has switched away from the page table.
+    ///
+    /// Without an ASID, the TLB of the hart is flushed before it switches to any user
+    /// page table, so the hart is no longer tracked.
+    pub fn deactivate_on(&self, hart_id: usize) {
+        if self.asid() == 0 {
+            self.tlb_harts.fetch_and(!(1 << hart_id), Ordering::AcqRel);
+        }
+    }
+
+    /// Performs a TLB shootdown for the range `[addr, addr + length)` on the harts
+    /// whose TLBs may cache translations of the page table.
+    pub fn tlb_shootdown(&self, addr: usize, length: usize) {
+        self.issue_tlb_shootdown(Some((addr, addr + length)));
+    }
+
+    /// Performs a TLB shootdown for the whole user part of the page table on the harts
+    /// whose TLBs may cache translations of the page table.
+    pub fn tlb_shootdown_all(&self) {
+        self.issue_tlb_shootdown(None);
+    }
+
+    /// Returns a batch deferring the TLB shootdowns passed to it until it is dropped,
+    /// so that changing the mappings of many pages issues a single shootdown.
+    pub fn batch_tlb_shootdowns(&self) -> TlbBatch<'_> {
+        TlbBatch {
+            page_table: self,
+            range: None,
+            pages: Vec::new(),
         }
-        // Perfrom a TLB shootdown for the range.
-        // TODO: Optimize this by only flushing the TLB for harts that execute the current
-        // process.
+    }
+
+    /// Performs a TLB shootdown for the range `[start, end)`, or the whole user part if
+    /// `range` is `None`, on the harts whose TLBs may cache translations of the page
+    /// table.
+    ///
+    /// RISC-V harts are shot down by the SBI, while LoongArch harts are asked to flush
+    /// their own TLBs with IPIs.
+    fn issue_tlb_shootdown(&self, range: Option<(usize, usize)>) {
+        // Make the page table changes visible before other harts refill their TLBs.
         fence();
-        tlb_shootdown(start_vpn.address().to_usize(), count);
+        let hart_mask = self.tlb_harts.load(Ordering::Acquire);
+        if hart_mask == 0 {
+            return;
+        }
+        #[cfg(target_arch = "riscv64")]
+        match range {
+            Some((start, end)) => {
+                arch::mm::tlb_shootdown(hart_mask, self.asid(), start, end - start)
+            }
+            None => arch::mm::tlb_shootdown_asid(hart_mask, self.asid()),
+        }
+        #[cfg(target_arch = "loongarch64")]
+        {
+            let tlb_flush = match range {
+                Some((start, end)) => TlbFlush::Range {
+                    asid: self.asid(),
+                    start,
+                    end,
+                },
+                None => TlbFlush::Asid(self.asid()),
+            };
+            ipi::send_tlb_flush_and_wait(hart_mask, tlb_flush);
+        }
     }
 
     /// Maps the kernel part of the address space into this page table.
@@ -471,9 +813,47 @@ impl PageTable {
     /// table must be tracked by calling this method.
     fn track_frame(&self, frame: FrameTracker) {
         self.frames.lock().push(frame);
+        VmStat::PageTablePages.inc();
+    }
+}
+
+impl Drop for PageTable {
+    fn drop(&mut self) {
+        // Flush the translations tagged with the ASID before it is released and reused.
+        if self.asid() != 0 {
+            self.tlb_shootdown_all();
+        }
+        let huge_pages = self.deposits.lock().len();
+        ANON_HUGE_PAGES.fetch_sub(huge_pages, Ordering::Relaxed);
+        VmStat::PageTablePages.sub(self.frames.lock().len() + huge_pages);
     }
 }
 
+/// Returns the flags for a non-leaf entry pointing to a page table, given the flags
+/// for the leaf entries under it.
+fn non_leaf_flags(leaf_flags: PteFlags) -> PteFlags {
+    #[cfg(target_arch = "riscv64")]
+    {
+        (leaf_flags & PteFlags::G) | PteFlags::V
+    }
+    #[cfg(target_arch = "loongarch64")]
+    {
+        let _ = leaf_flags;
+        PteFlags::empty()
+    }
+}
+
+/// Returns whether `ppns` starts with a run of physically contiguous frames which
+/// can be mapped as a huge page.
+fn is_huge_run(ppns: &[PhysPageNum]) -> bool {
+    ppns.len() >= HUGE_PAGE_PAGES
+        && ppns[0].to_usize() % HUGE_PAGE_PAGES == 0
+        && ppns[..HUGE_PAGE_PAGES]
+            .iter()
+            .enumerate()
+            .all(|(i, ppn)| ppn.to_usize() == ppns[0].to_usize() + i)
+}
+
 /// A helper struct for manipulating a page table in memory temporarily.
 ///
 /// # Discussion
@@ -530,7 +910,7 @@ impl PageTableMem {
 #[cfg(target_arch = "riscv64")]
 pub unsafe fn switch_to_kernel_page_table() {
     let root = KERNEL_PAGE_TABLE.lock().root.to_usize();
-    arch::mm::switch_page_table(root);
+    arch::mm::switch_page_table(root, 0);
 }
 
 /// Switch to the kernel page table.
@@ -551,7 +931,7 @@ pub unsafe fn switch_to_kernel_page_table() {
 /// This function must be called before the current page table is dropped,
 /// or the kernel may lose its memory mappings.
 pub unsafe fn switch_page_table(page_table: &PageTable) {
-    arch::mm::switch_page_table(page_table.root().to_usize());
+    arch::mm::switch_page_table(page_table.root().to_usize(), page_table.asid());
 }
 
 /// Prints the lookup process of a virtual address in the specific page table.
@@ -570,6 +950,9 @@ pub fn trace_page_table_lookup(root: PhysPageNum, va: VirtAddr) {
             index * size_of::<PageTableEntry>(),
             entry
         );
+        if i == 1 && entry.is_huge() {
+            return;
+        }
         // if entry.bits() == 0 {
         //     return;
         // }
@@ -0,0 +1,146 @@
+//! Module for accessing the memory of another process.
+//!
+//! [`UserPtr`](super::user_ptr::UserPtr) accesses the address space of the current task
+//! by dereferencing user addresses directly, which does not work for an address space
+//! that is not the current one. Instead, the pages of such an address space are looked
+//! up in its VMAs and accessed through the kernel mapping of physical memory. A missing
+//! page is faulted in as if the owner of the address space touched it, and a
+//! copy-on-write page is copied before it is written to.
+//!
+//! This is used by `process_vm_readv()`, `process_vm_writev()` and `/proc/<pid>/mem`.
+//! Writes to `/proc/<pid>/mem` are forced as in Linux, so that a debugger can insert
+//! breakpoints into read-only text, while `process_vm_writev()` respects the protection
+//! of the memory.
+
+use alloc::sync::Arc;
+use core::{cmp, ops::Range};
+
+use config::mm::PAGE_SIZE;
+use mm::{address::VirtAddr, page_cache::page::Page};
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MappingFlags,
+};
+
+use super::addr_space::AddrSpace;
+
+impl AddrSpace {
+    /// Reads the memory starting at `addr` in the address space into `buf`, faulting in
+    /// pages as needed.
+    ///
+    /// Returns the number of bytes read, which is less than the length of `buf` if a
+    /// page after the first one cannot be read.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EFAULT`] if the first page cannot be read, or
+    /// [`SysError::ENOMEM`] if it cannot be faulted in for lack of memory.
+    pub fn read_remote(&self, addr: usize, buf: &mut [u8]) -> SysResult<usize> {
+        let read = |page: &Page, offset: usize, range: Range<usize>| {
+            let len = range.len();
+            buf[range].copy_from_slice(&page.as_slice()[offset..offset + len]);
+        };
+        self.access_remote(addr, buf.len(), MappingFlags::R, false, read)
+    }
+
+    /// Writes `buf` into the memory starting at `addr` in the address space, faulting
+    /// in pages as needed.
+    ///
+    /// Returns the number of bytes written, which is less than the length of `buf` if a
+    /// page after the first one cannot be written.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EFAULT`] if the first page cannot be written, or
+    /// [`SysError::ENOMEM`] if it cannot be faulted in for lack of memory.
+    pub fn write_remote(&self, addr: usize, buf: &[u8]) -> SysResult<usize> {
+        self.write_remote_impl(addr, buf, false)
+    }
+
+    /// Writes `buf` into the memory starting at `addr` in the address space as
+    /// [`Self::write_remote`] does, but also into readable pages of private mappings
+    /// that are not writable, each of which is copied to a private page first.
+    pub fn write_remote_force(&self, addr: usize, buf: &[u8]) -> SysResult<usize> {
+        self.write_remote_impl(addr, buf, true)
+    }
+
+    fn write_remote_impl(&self, addr: usize, buf: &[u8], force: bool) -> SysResult<usize> {
+        let write = |page: &Page, offset: usize, range: Range<usize>| {
+            let len = range.len();
+            page.as_mut_slice()[offset..offset + len].copy_from_slice(&buf[range]);
+        };
+        self.access_remote(addr, buf.len(), MappingFlags::W, force, write)
+    }
+
+    /// Accesses `len` bytes of memory starting at `addr` in the address space page by
+    /// page, with `access` being `R` or `W`, which is forced if `force` is true.
+    ///
+    /// `f` is called with each page, the offset in the page where the access starts, and
+    /// the range of bytes in the whole access that fall in the page.
+    fn access_remote<F>(
+        &self,
+        addr: usize,
+        len: usize,
+        access: MappingFlags,
+        force: bool,
+        mut f: F,
+    ) -> SysResult<usize>
+    where
+        F: FnMut(&Page, usize, Range<usize>),
+    {
+        addr.checked_add(len).ok_or(SysError::EFAULT)?;
+        let mut done = 0;
+        while done < len {
+            let va = VirtAddr::new(addr + done);
+            let page = match self.remote_page(va, access, force) {
+                Ok(page) => page,
+                Err(e) if done == 0 => return Err(e),
+                Err(_) => break,
+            };
+            let offset = va.page_offset();
+            let count = cmp::min(PAGE_SIZE - offset, len - done);
+            f(&page, offset, done..done + count);
+            done += count;
+        }
+        Ok(done)
+    }
+
+    /// Returns the page mapped at `va` with `access` allowed, faulting it in if it is
+    /// not resident, or copying it if it is a copy-on-write page to be written.
+    ///
+    /// With `force`, a write to a private mapping that is not writable faults in the
+    /// page for reading, and copies it to a private page if it is shared.
+    fn remote_page(&self, va: VirtAddr, access: MappingFlags, force: bool) -> SysResult<Arc<Page>> {
+        if !va.in_user_space() {
+            return Err(SysError::EFAULT);
+        }
+        if force && access == MappingFlags::W && !self.prot_at(va)?.contains(MappingFlags::W) {
+            self.handle_page_fault(va, MappingFlags::R)?;
+            let mut vm_areas_lock = self.vm_areas.lock();
+            let vma = vm_areas_lock
+                .range_mut(..=va)
+                .next_back()
+                .filter(|(_, vma)| vma.contains(va))
+                .map(|(_, vma)| vma)
+                .ok_or(SysError::EFAULT)?;
+            return vma.force_cow_page(&self.page_table, va);
+        }
+        self.handle_page_fault(va, access)?;
+        self.vm_areas
+            .lock()
+            .range(..=va)
+            .next_back()
+            .filter(|(_, vma)| vma.contains(va))
+            .and_then(|(_, vma)| vma.pages().get(&va.page_number()).cloned())
+            .ok_or(SysError::EFAULT)
+    }
+
+    /// Returns the protection of the VMA containing `va`.
+    fn prot_at(&self, va: VirtAddr) -> SysResult<MappingFlags> {
+        self.vm_areas
+            .lock()
+            .range(..=va)
+            .next_back()
+            .filter(|(_, vma)| vma.contains(va))
+            .map(|(_, vma)| vma.prot())
+            .ok_or(SysError::EFAULT)
+    }
+}
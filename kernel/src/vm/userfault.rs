@@ -0,0 +1,298 @@
+//! Module for handling page faults by userfaultfds.
+//!
+//! A userfaultfd registers ranges of an address space, whose missing-page faults, minor
+//! faults and write-protect faults are reported to the user, who resolves them with
+//! ioctls on the userfaultfd. The address space implements [`UserfaultMm`] for the
+//! userfaultfd to resolve the faults in it.
+//!
+//! A user task which faults on such a page records the fault in the trap handler, and
+//! then waits in its task future until the fault is resolved, or the task is interrupted
+//! by a signal. Either way, the task retries the faulting instruction, which faults
+//! again if the page is still not resolved. An access by the kernel to such a page,
+//! e.g. by a syscall, cannot wait, and fails with `EFAULT` instead.
+
+use alloc::{
+    collections::btree_map::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use config::mm::{HUGE_PAGE_SIZE, PAGE_SIZE};
+use mm::{address::VirtAddr, page_cache::page::Page};
+use osfs::special::userfaultfd::{
+    UserfaultMm, UserfaultfdFeatures, UserfaultfdInode, UserfaultfdPagefaultFlags,
+    UserfaultfdRegisterMode,
+};
+use osfuture::Select2Futures;
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MappingFlags,
+};
+
+use super::{
+    addr_space::AddrSpace,
+    user_ptr::UserReadPtr,
+    vm_area::{PageFaultInfo, VmArea},
+};
+use crate::{
+    processor::current_task,
+    task::{
+        Task, TaskState,
+        sig_members::IntrBySignalFuture,
+        signal::sig_info::{Sig, SigInfo},
+    },
+};
+
+/// A page fault of a user task to be handled by a userfaultfd.
+pub struct Userfault {
+    uffd: Arc<UserfaultfdInode>,
+    /// Address of the faulting page.
+    address: VirtAddr,
+    /// Address which the faulting access is to.
+    fault_addr: VirtAddr,
+    flags: UserfaultfdPagefaultFlags,
+}
+
+impl Userfault {
+    /// Reports the page fault to the userfaultfd, and waits until it is resolved or
+    /// `task` is interrupted by a signal. With `UFFD_FEATURE_SIGBUS`, the fault is not
+    /// reported, and `SIGBUS` with `BUS_ADRERR` and the faulting address is sent to
+    /// `task` instead.
+    pub async fn wait(self, task: &Arc<Task>) {
+        if self
+            .uffd
+            .has_feature(UserfaultfdFeatures::UFFD_FEATURE_SIGBUS)
+        {
+            task.receive_siginfo(SigInfo::fault(
+                Sig::SIGBUS,
+                SigInfo::BUS_ADRERR,
+                self.fault_addr.to_usize(),
+            ));
+            return;
+        }
+
+        log::info!(
+            "[userfault] task {} waits for {:?} at {:#x}",
+            task.tid(),
+            self.flags,
+            self.address.to_usize()
+        );
+        let fault_future = self.uffd.handle_pagefault(
+            self.address.to_usize() as u64,
+            self.flags.bits(),
+            task.tid() as u32,
+        );
+        let intr_future = IntrBySignalFuture::new(task.clone(), task.get_sig_mask());
+        task.set_state(TaskState::Interruptible);
+        task.set_wake_up_signal(!task.get_sig_mask());
+        Select2Futures::new(fault_future, intr_future).await;
+        task.set_state(TaskState::Running);
+    }
+}
+
+/// Returns the VMA in `vm_areas` which contains `addr`.
+fn area_mut(vm_areas: &mut BTreeMap<VirtAddr, VmArea>, addr: VirtAddr) -> Option<&mut VmArea> {
+    vm_areas
+        .range_mut(..=addr)
+        .next_back()
+        .map(|(_, vma)| vma)
+        .filter(|vma| vma.contains(addr))
+}
+
+impl AddrSpace {
+    /// Adds a userfaultfd, which may register ranges in the address space.
+    pub fn add_userfaultfd(&self, uffd: &Arc<UserfaultfdInode>) {
+        let mut userfaultfds = self.userfaultfds.lock();
+        userfaultfds.retain(|uffd| uffd.strong_count() > 0);
+        userfaultfds.push(Arc::downgrade(uffd));
+    }
+
+    /// Calls `f` with each userfaultfd of the address space which has a range
+    /// registered in `[start, end)`.
+    fn for_each_userfaultfd(&self, start: VirtAddr, end: VirtAddr, f: impl Fn(&UserfaultfdInode)) {
+        let (start, end) = (start.to_usize() as u64, end.to_usize() as u64);
+        let userfaultfds = self.userfaultfds.lock().clone();
+        for uffd in userfaultfds.iter().filter_map(Weak::upgrade) {
+            if uffd.overlaps(start, end) {
+                f(&uffd);
+            }
+        }
+    }
+
+    /// Returns the page fault to be handled by a userfaultfd, if a page fault at
+    /// `fault_addr` with `access` is in a range registered for its kind.
+    ///
+    /// The fault is a missing-page fault if the page does not exist, a minor fault if
+    /// it is not resident but exists in shared memory, or a write-protect fault if it
+    /// is a write to a resident page write-protected by the userfaultfd. A fault whose
+    /// access is not allowed is never handled by a userfaultfd.
+    pub fn userfault(&self, fault_addr: VirtAddr, access: MappingFlags) -> Option<Userfault> {
+        let address = fault_addr.round_down();
+        let (uffd, mode) = self
+            .userfaultfds
+            .lock()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find_map(|uffd| {
+                let mode = uffd.mode_at(address.to_usize() as u64)?;
+                Some((uffd, mode))
+            })?;
+
+        let vm_areas_lock = self.vm_areas.lock();
+        let vma = vm_areas_lock
+            .range(..=fault_addr)
+            .next_back()
+            .map(|(_, vma)| vma)
+            .filter(|vma| vma.contains(fault_addr) && vma.prot().contains(access))?;
+        let vpn = fault_addr.page_number();
+        let mut flags = UserfaultfdPagefaultFlags::empty();
+        if access == MappingFlags::W {
+            flags |= UserfaultfdPagefaultFlags::UFFD_PAGEFAULT_FLAG_WRITE;
+        }
+        if vma.pages().contains_key(&vpn) {
+            if access != MappingFlags::W
+                || !mode.contains(UserfaultfdRegisterMode::UFFDIO_REGISTER_MODE_WP)
+                || !uffd.is_write_protected(address.to_usize() as u64)
+            {
+                return None;
+            }
+            flags |= UserfaultfdPagefaultFlags::UFFD_PAGEFAULT_FLAG_WP;
+        } else if vma.shared_page(vpn).is_some() {
+            if !mode.contains(UserfaultfdRegisterMode::UFFDIO_REGISTER_MODE_MINOR) {
+                return None;
+            }
+            flags |= UserfaultfdPagefaultFlags::UFFD_PAGEFAULT_FLAG_MINOR;
+        } else if !mode.contains(UserfaultfdRegisterMode::UFFDIO_REGISTER_MODE_MISSING) {
+            return None;
+        }
+
+        Some(Userfault {
+            uffd,
+            address,
+            fault_addr,
+            flags,
+        })
+    }
+
+    /// Returns whether any page in the huge page covering `addr` is in a range registered
+    /// with a userfaultfd, in which case a page fault must not fill the missing pages
+    /// around the faulting one with a huge page.
+    pub(super) fn huge_page_registered(&self, addr: VirtAddr) -> bool {
+        let start = addr.to_usize() & !(HUGE_PAGE_SIZE - 1);
+        self.is_registered(start, start + HUGE_PAGE_SIZE)
+    }
+
+    /// Sends fork events to the userfaultfds of the address space which enabled them,
+    /// each with a new userfaultfd for the address space `child` forked from this one,
+    /// which has the same ranges registered. The new userfaultfd is opened in the file
+    /// descriptor table of the task which reads the event, as Linux does.
+    ///
+    /// The other userfaultfds do not handle page faults in `child`.
+    pub fn fork_userfaultfds(&self, child: &Arc<AddrSpace>) {
+        let userfaultfds = self.userfaultfds.lock().clone();
+        for uffd in userfaultfds.iter().filter_map(Weak::upgrade) {
+            if !uffd.has_feature(UserfaultfdFeatures::UFFD_FEATURE_EVENT_FORK) {
+                continue;
+            }
+            let child_mm: Weak<dyn UserfaultMm> = Arc::downgrade(child);
+            let child_uffd = uffd.fork(child_mm);
+            child.add_userfaultfd(&child_uffd);
+            uffd.push_fork_event(child_uffd);
+        }
+    }
+
+    /// Moves the registered ranges in `[from, from + len)` to `[to, to + len)` after
+    /// the mapping there is moved by `mremap()`, sending remap events.
+    pub fn userfault_remap(&self, from: VirtAddr, to: VirtAddr, len: usize) {
+        let end = VirtAddr::new(from.to_usize() + len);
+        self.for_each_userfaultfd(from, end, |uffd| {
+            uffd.remap(from.to_usize() as u64, to.to_usize() as u64, len as u64);
+        });
+    }
+
+    /// Sends remove events for `[start, end)` whose pages are dropped by `madvise()`,
+    /// or unmap events if it is unmapped, which also unregisters it.
+    pub fn userfault_remove(&self, start: VirtAddr, end: VirtAddr, unmap: bool) {
+        self.for_each_userfaultfd(start, end, |uffd| {
+            uffd.remove(start.to_usize() as u64, end.to_usize() as u64, unmap);
+        });
+    }
+}
+
+impl UserfaultMm for AddrSpace {
+    fn check_register(&self, start: usize, end: usize, minor: bool) -> SysResult<()> {
+        let vm_areas_lock = self.vm_areas.lock();
+        let keys = Self::covering_areas(&vm_areas_lock, VirtAddr::new(start), VirtAddr::new(end))?;
+        if keys
+            .iter()
+            .any(|key| !vm_areas_lock[key].supports_userfault(minor))
+        {
+            return Err(SysError::EINVAL);
+        }
+        Ok(())
+    }
+
+    fn is_registered(&self, start: usize, end: usize) -> bool {
+        self.userfaultfds
+            .lock()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .any(|uffd| uffd.overlaps(start as u64, end as u64))
+    }
+
+    fn fill_page(&self, dst: usize, src: Option<usize>, wp: bool) -> SysResult<()> {
+        // The source is read before locking the VMAs, which its page faults need.
+        let page = Page::build()?;
+        match src {
+            Some(src) => {
+                let addr_space = current_task().addr_space();
+                let data =
+                    unsafe { UserReadPtr::<u8>::new(src, &addr_space).read_array(PAGE_SIZE)? };
+                page.as_mut_slice().copy_from_slice(&data);
+            }
+            None => page.as_mut_slice().fill(0),
+        }
+
+        let dst = VirtAddr::new(dst);
+        let mut vm_areas_lock = self.vm_areas.lock();
+        let vma = area_mut(&mut vm_areas_lock, dst).ok_or(SysError::ENOENT)?;
+        vma.install_page(&self.page_table, dst.page_number(), Arc::new(page), wp)?;
+        self.add_rss(1);
+        Ok(())
+    }
+
+    fn continue_page(&self, dst: usize, wp: bool) -> SysResult<()> {
+        let dst = VirtAddr::new(dst);
+        let vpn = dst.page_number();
+        let mut vm_areas_lock = self.vm_areas.lock();
+        let vma = area_mut(&mut vm_areas_lock, dst).ok_or(SysError::ENOENT)?;
+        if vma.pages().contains_key(&vpn) {
+            return Err(SysError::EEXIST);
+        }
+        if vma.shared_page(vpn).is_none() {
+            return Err(SysError::EFAULT);
+        }
+        // The page fault handler maps the existing shared page.
+        vma.handle_page_fault(PageFaultInfo {
+            fault_addr: dst,
+            page_table: &self.page_table,
+            access: MappingFlags::R,
+            huge: false,
+        })?;
+        if wp {
+            let end = VirtAddr::new(dst.to_usize() + PAGE_SIZE);
+            vma.write_protect(&self.page_table, dst, end, true);
+        }
+        self.add_rss(1);
+        Ok(())
+    }
+
+    fn write_protect(&self, start: usize, end: usize, wp: bool) -> SysResult<()> {
+        let (start, end) = (VirtAddr::new(start), VirtAddr::new(end));
+        let mut vm_areas_lock = self.vm_areas.lock();
+        for key in Self::covering_areas(&vm_areas_lock, start, end)? {
+            let vma = vm_areas_lock.get_mut(&key).unwrap();
+            vma.write_protect(&self.page_table, start, end, wp);
+        }
+        Ok(())
+    }
+}
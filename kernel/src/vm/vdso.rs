@@ -0,0 +1,188 @@
+//! Module for the virtual dynamic shared object (vDSO).
+//!
+//! The vDSO is a small shared library which the kernel maps into every user address
+//! space, so that user programs can read clocks without trapping into the kernel.
+//! It consists of two parts:
+//! - The data page (`[vvar]`), which is shared by all processes and is updated by the
+//!   kernel whenever a clock it describes is set.
+//! - The code pages (`[vdso]`), which hold a hand-written ELF image for the current
+//!   architecture. Its functions read the timer counter directly and convert it with
+//!   the parameters in the data page.
+//!
+//! The data page is mapped right below the code pages, so the code finds it at a fixed
+//! offset from itself. Clocks which are not described by the data page, such as CPU-time
+//! clocks, fall back to the corresponding system calls.
+//!
+//! The address of the ELF image is passed to user programs in the `AT_SYSINFO_EHDR`
+//! entry of the auxiliary vector.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    arch::global_asm,
+    sync::atomic::{AtomicU32, Ordering, fence},
+    time::Duration,
+};
+
+use config::mm::{MMAP_END, MMAP_START, PAGE_SIZE};
+use mm::{address::VirtAddr, page_cache::page::Page};
+use mutex::SpinNoIrqLock;
+use spin::Lazy;
+use systype::{
+    error::{SysError, SysResult},
+    memory_flags::MappingFlags,
+};
+
+use super::{addr_space::AddrSpace, vm_area::VmArea};
+use crate::syscall::time::{
+    CLOCK_BOOTTIME, CLOCK_MONOTONIC, CLOCK_MONOTONIC_COARSE, CLOCK_MONOTONIC_RAW, CLOCK_REALTIME,
+    CLOCK_REALTIME_COARSE,
+};
+
+#[cfg(target_arch = "riscv64")]
+global_asm!(include_str!("riscv64_vdso.asm"));
+#[cfg(target_arch = "loongarch64")]
+global_asm!(include_str!("loongarch64_vdso.asm"));
+
+unsafe extern "C" {
+    static _vdso_start: u8;
+    static _vdso_end: u8;
+}
+
+/// Number of clocks described by the data page. Calls on clocks with larger IDs always
+/// fall back to system calls.
+const VDSO_CLOCKS: usize = 8;
+
+/// Clocks which are read by the vDSO itself: the clocks below `VDSO_CLOCKS` which
+/// `sys_clock_gettime` derives from the timer counter. The CPU-time clocks are per task
+/// and always fall back to system calls.
+const VDSO_CLOCK_MASK: u32 = (1 << CLOCK_REALTIME)
+    | (1 << CLOCK_MONOTONIC)
+    | (1 << CLOCK_MONOTONIC_RAW)
+    | (1 << CLOCK_REALTIME_COARSE)
+    | (1 << CLOCK_MONOTONIC_COARSE)
+    | (1 << CLOCK_BOOTTIME);
+
+/// Layout of the data page.
+///
+/// The vDSO images access the fields by hard-coded offsets, so keep them in sync with
+/// this struct.
+#[repr(C)]
+struct VdsoData {
+    /// Sequence counter, which is odd while the kernel is updating the data page.
+    seq: AtomicU32,
+    /// Bitmap of clocks read by the vDSO itself.
+    clock_mask: u32,
+    /// Number of timer counter ticks per microsecond.
+    ticks_per_us: u64,
+    /// Offset of each clock from the timer counter, in microseconds.
+    offset_us: [u64; VDSO_CLOCKS],
+}
+
+/// Pages of the vDSO, shared by all address spaces.
+struct Vdso {
+    /// The data page.
+    data: Arc<Page>,
+    /// The code pages holding the ELF image.
+    text: Vec<Arc<Page>>,
+    /// Lock serializing updates to the data page.
+    update_lock: SpinNoIrqLock<()>,
+}
+
+static VDSO: Lazy<Vdso> = Lazy::new(|| {
+    let data = Page::build().expect("vdso: failed to allocate the data page");
+    data.as_mut_slice().fill(0);
+
+    let image = unsafe {
+        let start = &raw const _vdso_start;
+        let len = (&raw const _vdso_end).offset_from(start) as usize;
+        core::slice::from_raw_parts(start, len)
+    };
+    let text = image
+        .chunks(PAGE_SIZE)
+        .map(|chunk| {
+            let page = Page::build().expect("vdso: failed to allocate a code page");
+            let slice = page.as_mut_slice();
+            slice[..chunk.len()].copy_from_slice(chunk);
+            slice[chunk.len()..].fill(0);
+            Arc::new(page)
+        })
+        .collect();
+
+    let vdso = Vdso {
+        data: Arc::new(data),
+        text,
+        update_lock: SpinNoIrqLock::new(()),
+    };
+    vdso.update(|data| {
+        data.clock_mask = VDSO_CLOCK_MASK;
+        data.ticks_per_us = arch::time::get_ticks_per_us() as u64;
+    });
+    vdso
+});
+
+impl Vdso {
+    /// Updates the data page with `f`, following the seqlock protocol expected by the
+    /// vDSO code.
+    fn update(&self, f: impl FnOnce(&mut VdsoData)) {
+        let _guard = self.update_lock.lock();
+        // SAFETY: the data page is large enough for `VdsoData`, and writers are
+        // serialized by `update_lock`. User programs only read the page, and retry
+        // when they see the sequence counter change.
+        let data = unsafe { &mut *(self.data.as_mut_slice().as_mut_ptr() as *mut VdsoData) };
+        let seq = data.seq.load(Ordering::Relaxed);
+        data.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        f(data);
+        data.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+}
+
+/// Sets the offset of clock `clockid` from the timer counter, as seen by the vDSO.
+///
+/// This must be called whenever a clock read by the vDSO is set.
+pub fn set_clock_offset(clockid: usize, offset: Duration) {
+    if clockid < VDSO_CLOCKS {
+        VDSO.update(|data| data.offset_us[clockid] = offset.as_micros() as u64);
+    }
+}
+
+impl AddrSpace {
+    /// Maps the vDSO into the address space.
+    ///
+    /// Returns the address of the vDSO ELF image, which should be passed to the user
+    /// program as `AT_SYSINFO_EHDR`.
+    pub fn map_vdso(&self) -> SysResult<VirtAddr> {
+        let vdso = &*VDSO;
+        let length = (vdso.text.len() + 1) * PAGE_SIZE;
+        let start = self
+            .find_vacant_memory(
+                VirtAddr::new(MMAP_START),
+                length,
+                VirtAddr::new(MMAP_START),
+                VirtAddr::new(MMAP_END),
+            )
+            .ok_or(SysError::ENOMEM)?;
+        let text_start = VirtAddr::new(start.to_usize() + PAGE_SIZE);
+
+        let vvar = VmArea::new_special(
+            start,
+            MappingFlags::R,
+            core::slice::from_ref(&vdso.data),
+            "[vvar]",
+        );
+        let text = VmArea::new_special(
+            text_start,
+            MappingFlags::R | MappingFlags::X,
+            &vdso.text,
+            "[vdso]",
+        );
+        for area in [vvar, text] {
+            for (&vpn, page) in area.pages() {
+                self.page_table
+                    .map_page_to(vpn, page.ppn(), area.pte_flags())?;
+            }
+            self.add_area(area)?;
+        }
+        Ok(text_start)
+    }
+}
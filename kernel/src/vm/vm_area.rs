@@ -24,19 +24,24 @@
 //! maintaining modularization and extensibility.
 
 use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
-use core::{cmp, fmt::Debug, mem};
-use osfs::special::memfd::flags::MemfdSeals;
+use core::{cmp, fmt::Debug, mem, sync::atomic::Ordering};
+use osfs::{
+    special::memfd::flags::MemfdSeals,
+    sys::thp::{THP_ALWAYS, THP_ENABLED, THP_MADVISE},
+};
 
 use bitflags::bitflags;
 
 use arch::{
-    mm::{tlb_flush_addr, tlb_shootdown},
+    mm::tlb_flush_range,
     pte::{PageTableEntry, PteFlags},
 };
-use config::mm::PAGE_SIZE;
+use config::mm::{HUGE_PAGE_PAGES, PAGE_SIZE};
 use mm::{
     address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum},
+    frame::FrameTracker,
     page_cache::page::Page,
+    stat::VmStat,
 };
 use mutex::{ShareMutex, new_share_mutex};
 use osfuture::block_on;
@@ -48,11 +53,11 @@ use systype::{
 use vfs::file::File;
 
 #[cfg(target_arch = "riscv64")]
-use arch::mm::tlb_flush_all_except_global;
+use arch::mm::tlb_flush_asid;
 #[cfg(target_arch = "riscv64")]
 use config::mm::KERNEL_MAP_OFFSET;
 
-use super::page_table::PageTable;
+use super::page_table::{PageTable, TlbBatch};
 
 /// A virtual memory area (VMA).
 ///
@@ -108,6 +113,11 @@ pub enum TypedArea {
     /// A heap VMA representing a user heap. This is just a special case of an
     /// anonymous area.
     Heap(AnonymousArea),
+    /// A special VMA provided by the kernel, such as the vDSO.
+    ///
+    /// A special VMA is populated with pages owned by the kernel when it is created,
+    /// so a page fault never needs to allocate a page in it.
+    Special(SpecialArea),
 }
 
 /// Page fault handler function type.
@@ -132,16 +142,33 @@ pub struct PageFaultInfo<'a> {
     /// Type of memory access that caused the page fault. Only one of `R`, `W`, and `X`
     /// can be set.
     pub access: MappingFlags,
+    /// Whether the page fault may be handled by mapping a huge page covering the faulting
+    /// address, which fills the missing pages around the faulting one as well.
+    pub huge: bool,
 }
 
 bitflags! {
     /// Flags of a VMA.
     #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-    pub struct VmaFlags: u8 {
+    pub struct VmaFlags: u16 {
         /// The VMA is shared.
         const SHARED = 1 << 0;
         /// The VMA is private.
         const PRIVATE = 1 << 1;
+        /// The VMA is locked in memory by `mlock()`.
+        const LOCKED = 1 << 2;
+        /// The VMA is not copied to the child on `fork()`, set by `MADV_DONTFORK`.
+        const DONTFORK = 1 << 3;
+        /// The VMA is empty in the child after `fork()`, set by `MADV_WIPEONFORK`.
+        const WIPEONFORK = 1 << 4;
+        /// The VMA is left out of core dumps, set by `MADV_DONTDUMP`.
+        const DONTDUMP = 1 << 5;
+        /// The VMA prefers huge pages, set by `MADV_HUGEPAGE`.
+        const HUGEPAGE = 1 << 6;
+        /// The VMA never uses huge pages, set by `MADV_NOHUGEPAGE`.
+        const NOHUGEPAGE = 1 << 7;
+        /// The VMA is backed by huge pages, created by `mmap` with `MAP_HUGETLB`.
+        const HUGETLB = 1 << 8;
     }
 }
 
@@ -400,6 +427,54 @@ impl VmArea {
         }
     }
 
+    /// Constructs a user space [`VmArea`] whose specific type is [`SpecialArea`].
+    ///
+    /// The VMA starts at `start_va` and is populated with `pages` in order, so its
+    /// length is decided by the number of pages. The caller is responsible for mapping
+    /// the pages in the page table.
+    ///
+    /// `start_va` must be page-aligned.
+    ///
+    /// `prot` needs to have `RWX` bits set properly; other bits must be zero.
+    ///
+    /// `name` is the name shown for the VMA in `/proc/<pid>/maps`.
+    pub fn new_special(
+        start_va: VirtAddr,
+        prot: MappingFlags,
+        pages: &[Arc<Page>],
+        name: &'static str,
+    ) -> Self {
+        debug_assert!(start_va.to_usize() % PAGE_SIZE == 0);
+        debug_assert!(MappingFlags::RWX.contains(prot));
+
+        let prot = prot | MappingFlags::U;
+        let start_vpn = start_va.page_number().to_usize();
+        Self {
+            start: start_va,
+            end: VirtAddr::new(start_va.to_usize() + pages.len() * PAGE_SIZE),
+            flags: VmaFlags::PRIVATE,
+            pte_flags: {
+                let mapping_flags = prot | MappingFlags::V | MappingFlags::U;
+                #[cfg(target_arch = "riscv64")]
+                {
+                    PteFlags::from(mapping_flags) | PteFlags::A | PteFlags::D
+                }
+                #[cfg(target_arch = "loongarch64")]
+                {
+                    PteFlags::from(mapping_flags)
+                }
+            },
+            prot,
+            pages: pages
+                .iter()
+                .enumerate()
+                .map(|(i, page)| (VirtPageNum::new(start_vpn + i), Arc::clone(page)))
+                .collect(),
+            map_type: TypedArea::Special(SpecialArea { name }),
+            handler: Some(SpecialArea::fault_handler),
+        }
+    }
+
     /// Splits a virtual memory area at the given boundaries.
     ///
     /// The area is split at `split_start` and `split_end`, creating three potential areas:
@@ -502,23 +577,199 @@ impl VmArea {
     ///
     /// This function invalidates all valid page table entries in the VMA, and drops
     /// the `VmArea` itself. This is the proper way to drop a `VmArea` which is
-    /// associated with a [`AddrSpace`].
-    pub fn unmap_area(mut self, page_table: &PageTable) {
-        for (vpn, _) in mem::take(&mut self.pages) {
+    /// associated with a [`AddrSpace`]. The TLB shootdown is deferred to `tlb_batch`,
+    /// which also keeps the pages of the VMA until the shootdown is issued.
+    pub fn unmap_area(mut self, tlb_batch: &mut TlbBatch) {
+        let page_table = tlb_batch.page_table();
+        for (vpn, page) in mem::take(&mut self.pages) {
+            tlb_batch.release_page(page);
+            // A huge page lying entirely in the VMA is unmapped as a whole, after which
+            // its other pages have no entries.
+            if self.huge_page_in_area(vpn) && page_table.unmap_huge_page(vpn) {
+                continue;
+            }
+            if let Some(pte) = page_table.find_entry(vpn) {
+                *pte = PageTableEntry::default();
+            }
+        }
+        tlb_batch.tlb_shootdown(self.start_va().to_usize(), self.length());
+    }
+
+    /// Unmaps and releases all resident pages of the VMA, while keeping the VMA itself.
+    ///
+    /// Returns the number of pages released.
+    pub fn drop_pages(&mut self, page_table: &PageTable) -> usize {
+        self.drop_range(page_table, self.start, self.end, |_, _| true)
+    }
+
+    /// Unmaps and releases the resident pages of the VMA in the page-aligned range
+    /// `[start, end)` for which `filter` returns true, while keeping the VMA itself.
+    /// A released page is refilled on the next access, from the file for a file-backed
+    /// VMA, or with zeros for a private anonymous VMA.
+    ///
+    /// Returns the number of pages released.
+    pub fn drop_range(
+        &mut self,
+        page_table: &PageTable,
+        start: VirtAddr,
+        end: VirtAddr,
+        mut filter: impl FnMut(&VmArea, VirtPageNum) -> bool,
+    ) -> usize {
+        let start = VirtAddr::max(self.start, start);
+        let end = VirtAddr::min(self.end, end);
+        if start >= end {
+            return 0;
+        }
+        let vpns: Vec<VirtPageNum> = self
+            .pages
+            .range(start.page_number()..end.page_number())
+            .map(|(&vpn, _)| vpn)
+            .filter(|&vpn| filter(self, vpn))
+            .collect();
+        // The pages are released only after the TLB shootdown, since other harts may
+        // still access them through stale TLB entries before it.
+        let mut released = Vec::with_capacity(vpns.len());
+        for &vpn in vpns.iter() {
+            released.extend(self.pages.remove(&vpn));
             let pte = page_table.find_entry(vpn).unwrap();
             *pte = PageTableEntry::default();
         }
-        tlb_shootdown(self.start_va().to_usize(), self.length());
+        page_table.tlb_shootdown(start.to_usize(), end.to_usize() - start.to_usize());
+        drop(released);
+        vpns.len()
+    }
+
+    /// Releases the pages of a shared anonymous or shared memory VMA in the page-aligned
+    /// range `[start, end)` from the memory shared with other processes, so that all of
+    /// them get zeros on the next access. The pages stay mapped in other processes until
+    /// they drop them.
+    pub fn remove_shared_pages(&mut self, start: VirtAddr, end: VirtAddr) {
+        let start = VirtAddr::max(self.start, start);
+        let end = VirtAddr::min(self.end, end);
+        if start >= end {
+            return;
+        }
+        match &self.map_type {
+            TypedArea::Anonymous(AnonymousArea {
+                mappings: Some(mappings),
+                vpn_offset,
+            }) => {
+                let key = |va: VirtAddr| {
+                    let vpn = va.page_number().to_usize();
+                    VirtPageNum::new(vpn.wrapping_add_signed(*vpn_offset))
+                };
+                let mut mappings = mappings.lock();
+                let removed: Vec<VirtPageNum> = mappings
+                    .range(key(start)..key(end))
+                    .map(|(&key, _)| key)
+                    .collect();
+                for key in removed {
+                    mappings.remove(&key);
+                }
+            }
+            TypedArea::SharedMemory(SharedMemoryArea { shm }) => {
+                let first = (start.to_usize() - self.start.to_usize()) / PAGE_SIZE;
+                let last = (end.to_usize() - self.start.to_usize()) / PAGE_SIZE;
+                let pages = &mut shm.lock().pages;
+                let last = cmp::min(last, pages.len());
+                for page in pages[cmp::min(first, last)..last].iter_mut() {
+                    *page = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns a copy of the VMA without any of its pages, whose page faults refill it
+    /// as if it is newly mapped.
+    pub fn unpopulated(&self) -> Self {
+        let mut vma = self.clone();
+        vma.pages.clear();
+        vma
+    }
+
+    /// Moves the VMA to start at `new_start`, moving its pages along with their page
+    /// table entries instead of copying them.
+    ///
+    /// `new_start` must be page-aligned, and the new range of the VMA must not overlap
+    /// with other VMAs or with the old range.
+    ///
+    /// # Errors
+    /// Returns [`SysError::ENOMEM`] if a page table cannot be allocated for the new
+    /// range, or a huge page in the old range cannot be split, in which case the VMA
+    /// is left unchanged.
+    pub fn move_to(&mut self, page_table: &PageTable, new_start: VirtAddr) -> SysResult<()> {
+        debug_assert!(new_start.to_usize() % PAGE_SIZE == 0);
+
+        let old_start = self.start;
+        let length = self.length();
+        let delta = new_start.page_number().to_usize() as isize
+            - old_start.page_number().to_usize() as isize;
+        let moved = |vpn: VirtPageNum| VirtPageNum::new(vpn.to_usize().wrapping_add_signed(delta));
+
+        // Allocate the page tables of the new range and split the huge pages of the old
+        // range beforehand, so that a failure does not leave the pages half moved.
+        #[allow(unused_variables)]
+        let flush_all = self.pages.keys().try_fold(false, |flush_all, &vpn| {
+            page_table.find_entry(vpn).ok_or(SysError::ENOMEM)?;
+            let (_, created) = page_table.find_entry_force(moved(vpn), self.pte_flags)?;
+            SysResult::Ok(flush_all || created)
+        })?;
+        for (vpn, page) in mem::take(&mut self.pages) {
+            let old_pte = page_table.find_entry(vpn).unwrap();
+            let pte = mem::take(old_pte);
+            *page_table.find_entry(moved(vpn)).unwrap() = pte;
+            self.pages.insert(moved(vpn), page);
+        }
+
+        if let TypedArea::Anonymous(anonymous) = &mut self.map_type {
+            anonymous.vpn_offset -= delta;
+        }
+        self.start = new_start;
+        self.end = VirtAddr::new(new_start.to_usize() + length);
+
+        page_table.tlb_shootdown(old_start.to_usize(), length);
+        #[cfg(target_arch = "riscv64")]
+        if flush_all {
+            tlb_flush_asid(page_table.asid());
+        }
+        Ok(())
+    }
+
+    /// Expands the VMA to end at `new_end`. A file-backed VMA which maps a file region
+    /// as long as itself maps a longer file region.
+    ///
+    /// `new_end` must be page-aligned and not below the current end, and the new range
+    /// of the VMA must not overlap with other VMAs.
+    pub fn expand(&mut self, new_end: VirtAddr) {
+        debug_assert!(new_end.to_usize() % PAGE_SIZE == 0);
+        debug_assert!(new_end >= self.end);
+
+        if let TypedArea::FileBacked(file_backed) = &mut self.map_type {
+            if file_backed.len == self.end.to_usize() - self.start.to_usize() {
+                file_backed.len = new_end.to_usize() - self.start.to_usize();
+            }
+        }
+        self.end = new_end;
     }
 
     /// Changes the protection flags of a user space VMA, possibly updating page table
     /// entries.
     ///
     /// `new_prot` needs to have `RWX` bits set properly; other bits must be zero. This
-    /// function cannot change the `U` bit.
-    pub fn change_prot(&mut self, page_table: &PageTable, new_prot: MappingFlags) {
+    /// function cannot change the `U` bit. The TLB shootdown, if any, is deferred to
+    /// `tlb_batch`.
+    pub fn change_prot(&mut self, tlb_batch: &mut TlbBatch, new_prot: MappingFlags) {
+        let page_table = tlb_batch.page_table();
         debug_assert!(MappingFlags::RWX.contains(new_prot));
 
+        // The pages of a special VMA are shared by all processes, so they are never
+        // mapped writable.
+        let new_prot = if self.is_special() {
+            new_prot - MappingFlags::W
+        } else {
+            new_prot
+        };
         let old_prot = self.prot;
         self.prot = new_prot | MappingFlags::U;
         self.pte_flags = {
@@ -533,13 +784,114 @@ impl VmArea {
             }
         };
         for &vpn in self.pages.keys() {
+            // A huge page lying entirely in the VMA keeps being a huge page, unless
+            // the VMA is no longer accessible, in which case an Sv39 entry without any
+            // of `RWX` would point to a page table.
+            if self.huge_page_in_area(vpn) && !new_prot.is_empty() {
+                if let Some(entry) = page_table.find_huge_entry(vpn) {
+                    *entry = PageTableEntry::new_huge(entry.huge_to_base().ppn(), self.pte_flags);
+                    continue;
+                }
+            }
             let pte = page_table.find_entry(vpn).unwrap();
             pte.set_flags(self.pte_flags);
         }
         // Flush the TLB if any kind of permission is downgraded.
         if !new_prot.contains(old_prot) {
-            tlb_shootdown(self.start_va().to_usize(), self.length());
+            tlb_batch.tlb_shootdown(self.start_va().to_usize(), self.length());
+        }
+    }
+
+    /// Maps `page` at the missing page `vpn`, as the page supplied by a userfaultfd to
+    /// resolve a page fault, which is also shared with other processes for a shared
+    /// VMA. The page is mapped write-protected if `write_protect` is set.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EEXIST`] if the page is resident, or a shared page exists at
+    /// `vpn`, [`SysError::EINVAL`] if the VMA is not anonymous or shared memory, and
+    /// [`SysError::ENOMEM`] if a page table cannot be allocated.
+    pub fn install_page(
+        &mut self,
+        page_table: &PageTable,
+        vpn: VirtPageNum,
+        page: Arc<Page>,
+        write_protect: bool,
+    ) -> SysResult<()> {
+        if !self.supports_userfault(false) {
+            return Err(SysError::EINVAL);
+        }
+        if self.pages.contains_key(&vpn) || self.shared_page(vpn).is_some() {
+            return Err(SysError::EEXIST);
+        }
+        if let TypedArea::SharedMemory(SharedMemoryArea { shm }) = &self.map_type {
+            let page_index = (vpn.address().to_usize() - self.start.to_usize()) / PAGE_SIZE;
+            if page_index >= shm.lock().pages.len() {
+                return Err(SysError::EFAULT);
+            }
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        let write = PteFlags::W;
+        #[cfg(target_arch = "loongarch64")]
+        let write = PteFlags::W | PteFlags::D;
+        let pte_flags = if write_protect {
+            self.pte_flags.difference(write)
+        } else {
+            self.pte_flags
+        };
+        page_table.map_page_to(vpn, page.ppn(), pte_flags)?;
+
+        match &self.map_type {
+            TypedArea::Anonymous(AnonymousArea {
+                mappings: Some(mappings),
+                vpn_offset,
+            }) => {
+                let key = VirtPageNum::new(vpn.to_usize().wrapping_add_signed(*vpn_offset));
+                mappings.lock().insert(key, Arc::clone(&page));
+            }
+            TypedArea::SharedMemory(SharedMemoryArea { shm }) => {
+                let page_index = (vpn.address().to_usize() - self.start.to_usize()) / PAGE_SIZE;
+                shm.lock().pages[page_index] = Some(Arc::clone(&page));
+            }
+            _ => {}
+        }
+        self.pages.insert(vpn, page);
+        page_table.tlb_shootdown(vpn.address().to_usize(), PAGE_SIZE);
+        Ok(())
+    }
+
+    /// Write-protects the resident pages of the VMA in the page-aligned range
+    /// `[start, end)` for a userfaultfd, or unprotects them if `protect` is not set.
+    /// An unprotected page is writable again if the VMA is writable and the page is
+    /// not shared by copy-on-write.
+    pub fn write_protect(
+        &mut self,
+        page_table: &PageTable,
+        start: VirtAddr,
+        end: VirtAddr,
+        protect: bool,
+    ) {
+        let start = VirtAddr::max(self.start, start);
+        let end = VirtAddr::min(self.end, end);
+        if start >= end {
+            return;
+        }
+
+        #[cfg(target_arch = "riscv64")]
+        let write = PteFlags::W;
+        #[cfg(target_arch = "loongarch64")]
+        let write = PteFlags::W | PteFlags::D;
+        let writable = self.prot.contains(MappingFlags::W);
+        let shared = self.flags.contains(VmaFlags::SHARED);
+        for (&vpn, page) in self.pages.range(start.page_number()..end.page_number()) {
+            let pte = page_table.find_entry(vpn).unwrap();
+            if protect {
+                pte.set_flags(pte.flags().difference(write));
+            } else if writable && (shared || Arc::strong_count(page) == 1) {
+                pte.set_flags(pte.flags().union(write));
+            }
         }
+        page_table.tlb_shootdown(start.to_usize(), end.to_usize() - start.to_usize());
     }
 
     /// Handles a page fault happened in this VMA.
@@ -555,6 +907,7 @@ impl VmArea {
             fault_addr,
             page_table,
             access,
+            ..
         } = info;
 
         // Check the protection bits.
@@ -568,13 +921,23 @@ impl VmArea {
             return Err(SysError::EFAULT);
         }
 
+        if let Some(entry) = page_table.find_huge_entry(fault_addr.page_number()) {
+            let entry = entry.huge_to_base();
+            if entry.is_valid() && MappingFlags::from(entry.flags()).contains(access) {
+                // The huge page is already mapped by another thread, so just flush the
+                // TLB. Otherwise, the huge page is split below to handle the fault.
+                tlb_flush_range(page_table.asid(), fault_addr.to_usize(), PAGE_SIZE);
+                return Ok(());
+            }
+        }
+
         let pte = {
             #[allow(unused_variables)]
             let (pte, flush_all) =
                 page_table.find_entry_force(fault_addr.page_number(), pte_flags)?;
             #[cfg(target_arch = "riscv64")]
             if flush_all {
-                tlb_flush_all_except_global();
+                tlb_flush_asid(page_table.asid());
             }
             pte
         };
@@ -598,7 +961,7 @@ impl VmArea {
             // log::warn!("handle_fault: pte not valid");
             self.handler.unwrap()(self, info)?;
         }
-        tlb_flush_addr(fault_addr.to_usize());
+        tlb_flush_range(page_table.asid(), fault_addr.to_usize(), PAGE_SIZE);
 
         Ok(())
     }
@@ -649,6 +1012,38 @@ impl VmArea {
         Ok(())
     }
 
+    /// Breaks copy-on-write of the resident page at `va` for a forced write, which
+    /// `/proc/<pid>/mem` does even if the VMA is not writable, and returns the page to
+    /// be written.
+    ///
+    /// A page shared with the page cache or other processes is replaced by a private
+    /// copy. The page is mapped without write permission still, so that the address
+    /// space cannot write it by itself.
+    ///
+    /// # Errors
+    /// Returns [`SysError::EFAULT`] if the VMA is shared or the page is not resident.
+    /// Otherwise, returns [`SysError::ENOMEM`] if a new frame cannot be allocated.
+    pub fn force_cow_page(&mut self, page_table: &PageTable, va: VirtAddr) -> SysResult<Arc<Page>> {
+        if self.flags.contains(VmaFlags::SHARED) {
+            return Err(SysError::EFAULT);
+        }
+        let vpn = va.page_number();
+        let page = self.pages.get(&vpn).ok_or(SysError::EFAULT)?;
+        if Arc::strong_count(page) == 1 {
+            return Ok(Arc::clone(page));
+        }
+        let pte = page_table
+            .find_entry(vpn)
+            .filter(|pte| pte.is_valid())
+            .ok_or(SysError::EFAULT)?;
+        let new_page = Arc::new(Page::build()?);
+        new_page.copy_from_page(page);
+        pte.set_ppn(new_page.ppn());
+        self.pages.insert(vpn, Arc::clone(&new_page));
+        page_table.tlb_shootdown(va.round_down().to_usize(), PAGE_SIZE);
+        Ok(new_page)
+    }
+
     pub fn contains(&self, va: VirtAddr) -> bool {
         va >= self.start && va < self.end
     }
@@ -712,6 +1107,18 @@ impl VmArea {
         self.flags
     }
 
+    /// Sets or clears the [`VmaFlags::LOCKED`] flag of the VMA.
+    pub fn set_locked(&mut self, locked: bool) {
+        self.flags.set(VmaFlags::LOCKED, locked);
+    }
+
+    /// Sets or clears the flags given by `madvise()` in `flags`, which must not include
+    /// [`VmaFlags::SHARED`], [`VmaFlags::PRIVATE`] or [`VmaFlags::LOCKED`].
+    pub fn set_advice(&mut self, flags: VmaFlags, value: bool) {
+        debug_assert!(!flags.intersects(VmaFlags::SHARED | VmaFlags::PRIVATE | VmaFlags::LOCKED));
+        self.flags.set(flags, value);
+    }
+
     /// Returns the PTE flags of the VMA.
     pub fn pte_flags(&self) -> PteFlags {
         self.pte_flags
@@ -727,15 +1134,104 @@ impl VmArea {
         &self.pages
     }
 
+    /// Returns whether the huge page covering `vpn` lies entirely in the VMA.
+    fn huge_page_in_area(&self, vpn: VirtPageNum) -> bool {
+        let start = vpn.to_usize() & !(HUGE_PAGE_PAGES - 1);
+        start >= self.start.page_number().to_usize()
+            && start + HUGE_PAGE_PAGES <= self.end.page_number().to_usize()
+    }
+
+    /// Returns whether page faults in the VMA are handled by mapping huge pages, because
+    /// the VMA is created with `MAP_HUGETLB`, or under the transparent huge page policy
+    /// in `/sys/kernel/mm/transparent_hugepage/enabled` and the advice of the VMA.
+    pub fn wants_huge_pages(&self) -> bool {
+        if self.flags.contains(VmaFlags::HUGETLB) {
+            return true;
+        }
+        if self.flags.contains(VmaFlags::NOHUGEPAGE) {
+            return false;
+        }
+        match THP_ENABLED.load(Ordering::Relaxed) {
+            THP_ALWAYS => true,
+            THP_MADVISE => self.flags.contains(VmaFlags::HUGEPAGE),
+            _ => false,
+        }
+    }
+
     /// Returns whether this VMA is a heap.
     pub fn is_heap(&self) -> bool {
         matches!(self.map_type, TypedArea::Heap(_))
     }
 
+    /// Returns whether this VMA is a special VMA provided by the kernel, such as the
+    /// vDSO, whose pages are shared by all processes.
+    pub fn is_special(&self) -> bool {
+        matches!(self.map_type, TypedArea::Special(_))
+    }
+
     /// Returns whether this VMA is a shared memory area.
     pub fn is_shared_memory(&self) -> bool {
         matches!(self.map_type, TypedArea::SharedMemory(_))
     }
+
+    /// Returns whether this VMA is a private anonymous area, including a heap or a
+    /// stack.
+    pub fn is_private_anonymous(&self) -> bool {
+        matches!(self.map_type, TypedArea::Anonymous(_) | TypedArea::Heap(_))
+            && self.flags.contains(VmaFlags::PRIVATE)
+    }
+
+    /// Returns the file page cached for the page at `vpn` of a file-backed VMA, if the
+    /// page is within the mapped file region and the file page is cached.
+    pub fn cached_file_page(&self, vpn: VirtPageNum) -> Option<Arc<Page>> {
+        let TypedArea::FileBacked(file_backed) = &self.map_type else {
+            return None;
+        };
+        let area_offset = vpn.address().to_usize() - self.start.to_usize();
+        if area_offset >= file_backed.len {
+            return None;
+        }
+        let file_offset = file_backed.offset + area_offset;
+        file_backed.file.inode().page_cache().get_page(file_offset)
+    }
+
+    /// Returns the page at `vpn` of a shared anonymous or shared memory VMA, which is
+    /// shared with other processes, if it exists whether or not it is resident in this
+    /// VMA.
+    pub fn shared_page(&self, vpn: VirtPageNum) -> Option<Arc<Page>> {
+        match &self.map_type {
+            TypedArea::Anonymous(AnonymousArea {
+                mappings: Some(mappings),
+                vpn_offset,
+            }) => {
+                let key = VirtPageNum::new(vpn.to_usize().wrapping_add_signed(*vpn_offset));
+                mappings.lock().get(&key).cloned()
+            }
+            TypedArea::SharedMemory(SharedMemoryArea { shm }) => {
+                let page_index = (vpn.address().to_usize() - self.start.to_usize()) / PAGE_SIZE;
+                shm.lock().pages.get(page_index).cloned().flatten()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether page faults in this VMA can be handled by a userfaultfd, which is
+    /// the case for anonymous and shared memory VMAs. If `minor` is set, only shared
+    /// VMAs can handle minor faults, whose pages exist apart from the mapping.
+    pub fn supports_userfault(&self, minor: bool) -> bool {
+        match &self.map_type {
+            TypedArea::Anonymous(anonymous) => !minor || anonymous.mappings.is_some(),
+            TypedArea::Heap(_) => !minor,
+            TypedArea::SharedMemory(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns whether the pages of this VMA count towards the committed memory, which
+    /// is the case for private writable mappings.
+    pub fn is_committed(&self) -> bool {
+        self.flags.contains(VmaFlags::PRIVATE) && self.prot.contains(MappingFlags::W)
+    }
 }
 
 impl Debug for VmArea {
@@ -955,6 +1451,7 @@ impl FileBackedArea {
             fault_addr,
             page_table,
             access,
+            ..
         } = info;
         // log::error!("[FileBackedArea] fault_addr: {:?}", fault_addr);
 
@@ -971,6 +1468,9 @@ impl FileBackedArea {
 
         // Offset from the start of the file to the start of the page to be mapped.
         let file_offset = offset + area_offset;
+        if file.inode().page_cache().get_page(file_offset).is_none() {
+            VmStat::PgMajFault.inc();
+        }
         let cached_page = block_on(async { file.read_page(file_offset).await })?;
 
         // if fault_addr.to_usize() >= 0x10000 && fault_addr.to_usize() < 0x15000 {
@@ -1095,6 +1595,10 @@ impl SharedMemoryArea {
         let page_num = fault_addr.page_number();
 
         let pages = &mut shm.lock().pages;
+        if page_index >= pages.len() {
+            // The area has been expanded by `mremap()` beyond the end of the segment.
+            return Err(SysError::EFAULT);
+        }
         let page = match &pages[page_index] {
             Some(page) => {
                 log::warn!("Found page at index {:#x}", page_index);
@@ -1124,7 +1628,13 @@ impl SharedMemoryArea {
 #[derive(Clone, Debug)]
 pub struct AnonymousArea {
     /// The mappings from virtual page numbers to physical pages, if the area is shared.
+    ///
+    /// The virtual page numbers are those the area was created at, which are shared by
+    /// all processes mapping the area even after one of them moves it by `mremap()`.
     mappings: Option<ShareMutex<BTreeMap<VirtPageNum, Arc<Page>>>>,
+    /// Number of pages to add to a virtual page number in the area to get its key in
+    /// `mappings`, which is nonzero after the area is moved.
+    vpn_offset: isize,
 }
 
 impl AnonymousArea {
@@ -1135,14 +1645,25 @@ impl AnonymousArea {
         } else {
             Some(new_share_mutex(BTreeMap::new()))
         };
-        Self { mappings }
+        Self {
+            mappings,
+            vpn_offset: 0,
+        }
     }
 
     /// Handles a page fault.
     fn fault_handler(area: &mut VmArea, info: PageFaultInfo) -> SysResult<()> {
-        let mappings = match &area.map_type {
-            TypedArea::Anonymous(anonymous) => anonymous.mappings.as_ref(),
-            TypedArea::Heap(anonymous) => None,
+        if info.huge
+            && area.flags.contains(VmaFlags::PRIVATE)
+            && area.wants_huge_pages()
+            && Self::huge_fault_handler(area, info)?
+        {
+            return Ok(());
+        }
+
+        let (mappings, vpn_offset) = match &area.map_type {
+            TypedArea::Anonymous(anonymous) => (anonymous.mappings.as_ref(), anonymous.vpn_offset),
+            TypedArea::Heap(_) => (None, 0),
             _ => panic!("AnonymousArea::fault_handler: not an anonymous area or a heap area"),
         };
         let &mut VmArea {
@@ -1163,13 +1684,14 @@ impl AnonymousArea {
             page.as_mut_slice().fill(0);
             Arc::new(page)
         } else {
+            let key = VirtPageNum::new(vpn.to_usize().wrapping_add_signed(vpn_offset));
             let mut mappings_lock = mappings.unwrap().lock();
-            match mappings_lock.get(&vpn).cloned() {
+            match mappings_lock.get(&key).cloned() {
                 Some(page) => page,
                 None => {
                     let page = Arc::new(Page::build()?);
                     page.as_mut_slice().fill(0);
-                    mappings_lock.insert(vpn, Arc::clone(&page));
+                    mappings_lock.insert(key, Arc::clone(&page));
                     page
                 }
             }
@@ -1179,4 +1701,83 @@ impl AnonymousArea {
 
         Ok(())
     }
+
+    /// Handles a page fault in a private area by mapping a zeroed huge page covering
+    /// the faulting address, if the huge page lies entirely in the area and none of its
+    /// pages is resident. Each base page in the huge page is tracked as a [`Page`], so
+    /// that the huge page can be split by partial `munmap()` or `mprotect()`, or by
+    /// copy-on-write after `fork()`.
+    ///
+    /// Returns whether a huge page is mapped; if not, the page fault is to be handled
+    /// with a base page. Failing to allocate a huge page is not an error, because it is
+    /// more likely due to fragmentation than lack of memory, unless the area is created
+    /// with `MAP_HUGETLB`.
+    fn huge_fault_handler(area: &mut VmArea, info: PageFaultInfo) -> SysResult<bool> {
+        let fault_vpn = info.fault_addr.page_number();
+        if !area.huge_page_in_area(fault_vpn) {
+            return Ok(false);
+        }
+        let start_vpn = fault_vpn.to_usize() & !(HUGE_PAGE_PAGES - 1);
+        let vpns = VirtPageNum::new(start_vpn)..VirtPageNum::new(start_vpn + HUGE_PAGE_PAGES);
+        if area.pages.range(vpns).next().is_some() {
+            return Ok(false);
+        }
+
+        let frames =
+            match FrameTracker::try_build_contiguous_aligned(HUGE_PAGE_PAGES, HUGE_PAGE_PAGES) {
+                Ok(frames) => frames,
+                Err(e) if area.flags.contains(VmaFlags::HUGETLB) => return Err(e),
+                Err(_) => return Ok(false),
+            };
+        let ppn = frames[0].ppn();
+        let pages: Vec<Arc<Page>> = frames
+            .into_iter()
+            .map(|frame| {
+                let page = Page::from_frame(frame);
+                page.as_mut_slice().fill(0);
+                Arc::new(page)
+            })
+            .collect();
+        // The page table may still have base page entries there, or lack memory for a
+        // deposit, in which case the pages are freed and a base page is mapped instead.
+        let start = VirtPageNum::new(start_vpn);
+        if info
+            .page_table
+            .map_huge_page_to(start, ppn, area.pte_flags)
+            .is_err()
+        {
+            return Ok(false);
+        }
+        for (i, page) in pages.into_iter().enumerate() {
+            area.pages.insert(VirtPageNum::new(start_vpn + i), page);
+        }
+        VmStat::ThpFaultAlloc.inc();
+        Ok(true)
+    }
+}
+
+/// A special VMA provided by the kernel. See [`TypedArea::Special`].
+#[derive(Clone, Debug)]
+pub struct SpecialArea {
+    /// Name of the VMA, such as `[vdso]`.
+    name: &'static str,
+}
+
+impl SpecialArea {
+    /// Handles a page fault.
+    ///
+    /// All pages of a special VMA are mapped when it is created, so a page fault here
+    /// means that the pages have been unmapped, and they are not to be brought back.
+    fn fault_handler(_area: &mut VmArea, info: PageFaultInfo) -> SysResult<()> {
+        log::warn!(
+            "SpecialArea::fault_handler: page fault at {:#x}",
+            info.fault_addr.to_usize()
+        );
+        Err(SysError::EFAULT)
+    }
+
+    /// Returns the name of the VMA.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
 }
@@ -0,0 +1,433 @@
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// `EUEN.FPE`, which enables floating-point instructions.
+const EUEN_FPE: usize = 1 << 0;
+/// `EUEN.SXE`, which enables LSX instructions.
+const EUEN_SXE: usize = 1 << 1;
+/// `EUEN.ASXE`, which enables LASX instructions.
+const EUEN_ASXE: usize = 1 << 2;
+
+/// Whether the harts support LSX, i.e., 128-bit vector instructions.
+static HAS_LSX: AtomicBool = AtomicBool::new(false);
+/// Whether the harts support LASX, i.e., 256-bit vector instructions.
+static HAS_LASX: AtomicBool = AtomicBool::new(false);
+
+/// Probes LSX and LASX through the configuration word 2 of `cpucfg`.
+pub fn init() {
+    let cfg2: usize;
+    unsafe { asm!("cpucfg {}, {}", out(reg) cfg2, in(reg) 2) };
+    HAS_LSX.store(cfg2 & (1 << 6) != 0, Ordering::Relaxed);
+    HAS_LASX.store(cfg2 & (1 << 7) != 0, Ordering::Relaxed);
+}
+
+/// Returns whether LSX is supported.
+pub fn has_vector() -> bool {
+    HAS_LSX.load(Ordering::Relaxed)
+}
+
+/// Returns whether LASX is supported.
+pub fn has_lasx() -> bool {
+    HAS_LASX.load(Ordering::Relaxed)
+}
+
+/// Floating-point registers `f0`-`f31`, condition flags `fcc0`-`fcc7` and `fcsr0`.
+///
+/// `fcc` holds the condition flag `fccN` in its byte `N`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct FloatRegs {
+    pub f: [u64; 32],
+    pub fcc: u64,
+    pub fcsr: u32,
+}
+
+impl FloatRegs {
+    pub const fn new() -> Self {
+        Self {
+            f: [0; 32],
+            fcc: 0,
+            fcsr: 0,
+        }
+    }
+
+    /// Saves the floating-point registers of the current hart into `self`.
+    pub fn save(&mut self) {
+        let fcc: usize;
+        let fcsr: usize;
+        set_fpu_enabled(true);
+        unsafe {
+            asm!(
+                "fst.d $f0, {0}, 0*8",
+                "fst.d $f1, {0}, 1*8",
+                "fst.d $f2, {0}, 2*8",
+                "fst.d $f3, {0}, 3*8",
+                "fst.d $f4, {0}, 4*8",
+                "fst.d $f5, {0}, 5*8",
+                "fst.d $f6, {0}, 6*8",
+                "fst.d $f7, {0}, 7*8",
+                "fst.d $f8, {0}, 8*8",
+                "fst.d $f9, {0}, 9*8",
+                "fst.d $f10, {0}, 10*8",
+                "fst.d $f11, {0}, 11*8",
+                "fst.d $f12, {0}, 12*8",
+                "fst.d $f13, {0}, 13*8",
+                "fst.d $f14, {0}, 14*8",
+                "fst.d $f15, {0}, 15*8",
+                "fst.d $f16, {0}, 16*8",
+                "fst.d $f17, {0}, 17*8",
+                "fst.d $f18, {0}, 18*8",
+                "fst.d $f19, {0}, 19*8",
+                "fst.d $f20, {0}, 20*8",
+                "fst.d $f21, {0}, 21*8",
+                "fst.d $f22, {0}, 22*8",
+                "fst.d $f23, {0}, 23*8",
+                "fst.d $f24, {0}, 24*8",
+                "fst.d $f25, {0}, 25*8",
+                "fst.d $f26, {0}, 26*8",
+                "fst.d $f27, {0}, 27*8",
+                "fst.d $f28, {0}, 28*8",
+                "fst.d $f29, {0}, 29*8",
+                "fst.d $f30, {0}, 30*8",
+                "fst.d $f31, {0}, 31*8",
+                "movcf2gr {1}, $fcc0",
+                "movcf2gr {2}, $fcc1",
+                "bstrins.d {1}, {2}, 15, 8",
+                "movcf2gr {2}, $fcc2",
+                "bstrins.d {1}, {2}, 23, 16",
+                "movcf2gr {2}, $fcc3",
+                "bstrins.d {1}, {2}, 31, 24",
+                "movcf2gr {2}, $fcc4",
+                "bstrins.d {1}, {2}, 39, 32",
+                "movcf2gr {2}, $fcc5",
+                "bstrins.d {1}, {2}, 47, 40",
+                "movcf2gr {2}, $fcc6",
+                "bstrins.d {1}, {2}, 55, 48",
+                "movcf2gr {2}, $fcc7",
+                "bstrins.d {1}, {2}, 63, 56",
+                "movfcsr2gr {3}, $fcsr0",
+                in(reg) self.f.as_mut_ptr(),
+                out(reg) fcc,
+                out(reg) _,
+                out(reg) fcsr,
+            );
+        }
+        self.fcc = fcc as u64;
+        self.fcsr = fcsr as u32;
+    }
+
+    /// Loads `self` into the floating-point registers of the current hart.
+    pub fn restore(&self) {
+        set_fpu_enabled(true);
+        unsafe {
+            asm!(
+                "fld.d $f0, {0}, 0*8",
+                "fld.d $f1, {0}, 1*8",
+                "fld.d $f2, {0}, 2*8",
+                "fld.d $f3, {0}, 3*8",
+                "fld.d $f4, {0}, 4*8",
+                "fld.d $f5, {0}, 5*8",
+                "fld.d $f6, {0}, 6*8",
+                "fld.d $f7, {0}, 7*8",
+                "fld.d $f8, {0}, 8*8",
+                "fld.d $f9, {0}, 9*8",
+                "fld.d $f10, {0}, 10*8",
+                "fld.d $f11, {0}, 11*8",
+                "fld.d $f12, {0}, 12*8",
+                "fld.d $f13, {0}, 13*8",
+                "fld.d $f14, {0}, 14*8",
+                "fld.d $f15, {0}, 15*8",
+                "fld.d $f16, {0}, 16*8",
+                "fld.d $f17, {0}, 17*8",
+                "fld.d $f18, {0}, 18*8",
+                "fld.d $f19, {0}, 19*8",
+                "fld.d $f20, {0}, 20*8",
+                "fld.d $f21, {0}, 21*8",
+                "fld.d $f22, {0}, 22*8",
+                "fld.d $f23, {0}, 23*8",
+                "fld.d $f24, {0}, 24*8",
+                "fld.d $f25, {0}, 25*8",
+                "fld.d $f26, {0}, 26*8",
+                "fld.d $f27, {0}, 27*8",
+                "fld.d $f28, {0}, 28*8",
+                "fld.d $f29, {0}, 29*8",
+                "fld.d $f30, {0}, 30*8",
+                "fld.d $f31, {0}, 31*8",
+                "bstrpick.d {2}, {1}, 7, 0",
+                "movgr2cf $fcc0, {2}",
+                "bstrpick.d {2}, {1}, 15, 8",
+                "movgr2cf $fcc1, {2}",
+                "bstrpick.d {2}, {1}, 23, 16",
+                "movgr2cf $fcc2, {2}",
+                "bstrpick.d {2}, {1}, 31, 24",
+                "movgr2cf $fcc3, {2}",
+                "bstrpick.d {2}, {1}, 39, 32",
+                "movgr2cf $fcc4, {2}",
+                "bstrpick.d {2}, {1}, 47, 40",
+                "movgr2cf $fcc5, {2}",
+                "bstrpick.d {2}, {1}, 55, 48",
+                "movgr2cf $fcc6, {2}",
+                "bstrpick.d {2}, {1}, 63, 56",
+                "movgr2cf $fcc7, {2}",
+                "movgr2fcsr $fcsr0, {3}",
+                in(reg) self.f.as_ptr(),
+                in(reg) self.fcc as usize,
+                out(reg) _,
+                in(reg) self.fcsr as usize,
+            );
+        }
+    }
+}
+
+/// Returns whether floating-point instructions are enabled, i.e., `EUEN.FPE` is set.
+pub fn fpu_enabled() -> bool {
+    read_euen() & EUEN_FPE != 0
+}
+
+/// Sets or clears `EUEN.FPE`. A floating-point instruction executed while it is clear
+/// raises a floating-point disabled exception.
+pub fn set_fpu_enabled(enabled: bool) {
+    set_euen(EUEN_FPE, enabled);
+}
+
+/// Returns whether LSX instructions are enabled, i.e., `EUEN.SXE` is set.
+pub fn vector_enabled() -> bool {
+    read_euen() & EUEN_SXE != 0
+}
+
+/// Sets or clears `EUEN.SXE` and, if LASX is supported, `EUEN.ASXE`. An LSX or LASX
+/// instruction executed while they are clear raises an LSX or LASX disabled exception.
+pub fn set_vector_enabled(enabled: bool) {
+    let mask = if has_lasx() {
+        EUEN_SXE | EUEN_ASXE
+    } else {
+        EUEN_SXE
+    };
+    set_euen(mask, enabled);
+}
+
+fn read_euen() -> usize {
+    let euen: usize;
+    unsafe { asm!("csrrd {}, 0x2", out(reg) euen) };
+    euen
+}
+
+fn set_euen(mask: usize, enabled: bool) {
+    let value = if enabled { mask } else { 0 };
+    unsafe { asm!("csrxchg {}, {}, 0x2", inout(reg) value => _, in(reg) mask) };
+}
+
+/// Vector registers `vr0`-`vr31` of LSX, or `xr0`-`xr31` of LASX.
+///
+/// Each register is 256 bits, of which only the lower 128 bits are used without LASX.
+/// The lowest 64 bits of each register alias the floating-point register of the same
+/// number, so they must be restored before the floating-point registers.
+#[derive(Clone, Copy, Debug)]
+#[repr(C, align(32))]
+pub struct VectorRegs {
+    pub v: [[u64; 4]; 32],
+}
+
+impl VectorRegs {
+    pub const fn new() -> Self {
+        Self { v: [[0; 4]; 32] }
+    }
+
+    /// Saves the vector registers of the current hart into `self`.
+    pub fn save(&mut self) {
+        if !has_vector() {
+            return;
+        }
+        set_vector_enabled(true);
+        let ptr = self.v.as_mut_ptr();
+        unsafe {
+            if has_lasx() {
+                save_lasx(ptr);
+            } else {
+                save_lsx(ptr);
+            }
+        }
+    }
+
+    /// Loads `self` into the vector registers of the current hart.
+    pub fn restore(&self) {
+        if !has_vector() {
+            return;
+        }
+        set_vector_enabled(true);
+        let ptr = self.v.as_ptr();
+        unsafe {
+            if has_lasx() {
+                restore_lasx(ptr);
+            } else {
+                restore_lsx(ptr);
+            }
+        }
+    }
+}
+
+#[target_feature(enable = "lsx")]
+unsafe fn save_lsx(ptr: *mut [u64; 4]) {
+    unsafe {
+        asm!(
+            "vst $vr0, {0}, 0*32",
+            "vst $vr1, {0}, 1*32",
+            "vst $vr2, {0}, 2*32",
+            "vst $vr3, {0}, 3*32",
+            "vst $vr4, {0}, 4*32",
+            "vst $vr5, {0}, 5*32",
+            "vst $vr6, {0}, 6*32",
+            "vst $vr7, {0}, 7*32",
+            "vst $vr8, {0}, 8*32",
+            "vst $vr9, {0}, 9*32",
+            "vst $vr10, {0}, 10*32",
+            "vst $vr11, {0}, 11*32",
+            "vst $vr12, {0}, 12*32",
+            "vst $vr13, {0}, 13*32",
+            "vst $vr14, {0}, 14*32",
+            "vst $vr15, {0}, 15*32",
+            "vst $vr16, {0}, 16*32",
+            "vst $vr17, {0}, 17*32",
+            "vst $vr18, {0}, 18*32",
+            "vst $vr19, {0}, 19*32",
+            "vst $vr20, {0}, 20*32",
+            "vst $vr21, {0}, 21*32",
+            "vst $vr22, {0}, 22*32",
+            "vst $vr23, {0}, 23*32",
+            "vst $vr24, {0}, 24*32",
+            "vst $vr25, {0}, 25*32",
+            "vst $vr26, {0}, 26*32",
+            "vst $vr27, {0}, 27*32",
+            "vst $vr28, {0}, 28*32",
+            "vst $vr29, {0}, 29*32",
+            "vst $vr30, {0}, 30*32",
+            "vst $vr31, {0}, 31*32",
+            in(reg) ptr,
+        );
+    }
+}
+
+#[target_feature(enable = "lasx")]
+unsafe fn save_lasx(ptr: *mut [u64; 4]) {
+    unsafe {
+        asm!(
+            "xvst $xr0, {0}, 0*32",
+            "xvst $xr1, {0}, 1*32",
+            "xvst $xr2, {0}, 2*32",
+            "xvst $xr3, {0}, 3*32",
+            "xvst $xr4, {0}, 4*32",
+            "xvst $xr5, {0}, 5*32",
+            "xvst $xr6, {0}, 6*32",
+            "xvst $xr7, {0}, 7*32",
+            "xvst $xr8, {0}, 8*32",
+            "xvst $xr9, {0}, 9*32",
+            "xvst $xr10, {0}, 10*32",
+            "xvst $xr11, {0}, 11*32",
+            "xvst $xr12, {0}, 12*32",
+            "xvst $xr13, {0}, 13*32",
+            "xvst $xr14, {0}, 14*32",
+            "xvst $xr15, {0}, 15*32",
+            "xvst $xr16, {0}, 16*32",
+            "xvst $xr17, {0}, 17*32",
+            "xvst $xr18, {0}, 18*32",
+            "xvst $xr19, {0}, 19*32",
+            "xvst $xr20, {0}, 20*32",
+            "xvst $xr21, {0}, 21*32",
+            "xvst $xr22, {0}, 22*32",
+            "xvst $xr23, {0}, 23*32",
+            "xvst $xr24, {0}, 24*32",
+            "xvst $xr25, {0}, 25*32",
+            "xvst $xr26, {0}, 26*32",
+            "xvst $xr27, {0}, 27*32",
+            "xvst $xr28, {0}, 28*32",
+            "xvst $xr29, {0}, 29*32",
+            "xvst $xr30, {0}, 30*32",
+            "xvst $xr31, {0}, 31*32",
+            in(reg) ptr,
+        );
+    }
+}
+
+#[target_feature(enable = "lsx")]
+unsafe fn restore_lsx(ptr: *const [u64; 4]) {
+    unsafe {
+        asm!(
+            "vld $vr0, {0}, 0*32",
+            "vld $vr1, {0}, 1*32",
+            "vld $vr2, {0}, 2*32",
+            "vld $vr3, {0}, 3*32",
+            "vld $vr4, {0}, 4*32",
+            "vld $vr5, {0}, 5*32",
+            "vld $vr6, {0}, 6*32",
+            "vld $vr7, {0}, 7*32",
+            "vld $vr8, {0}, 8*32",
+            "vld $vr9, {0}, 9*32",
+            "vld $vr10, {0}, 10*32",
+            "vld $vr11, {0}, 11*32",
+            "vld $vr12, {0}, 12*32",
+            "vld $vr13, {0}, 13*32",
+            "vld $vr14, {0}, 14*32",
+            "vld $vr15, {0}, 15*32",
+            "vld $vr16, {0}, 16*32",
+            "vld $vr17, {0}, 17*32",
+            "vld $vr18, {0}, 18*32",
+            "vld $vr19, {0}, 19*32",
+            "vld $vr20, {0}, 20*32",
+            "vld $vr21, {0}, 21*32",
+            "vld $vr22, {0}, 22*32",
+            "vld $vr23, {0}, 23*32",
+            "vld $vr24, {0}, 24*32",
+            "vld $vr25, {0}, 25*32",
+            "vld $vr26, {0}, 26*32",
+            "vld $vr27, {0}, 27*32",
+            "vld $vr28, {0}, 28*32",
+            "vld $vr29, {0}, 29*32",
+            "vld $vr30, {0}, 30*32",
+            "vld $vr31, {0}, 31*32",
+            in(reg) ptr,
+        );
+    }
+}
+
+#[target_feature(enable = "lasx")]
+unsafe fn restore_lasx(ptr: *const [u64; 4]) {
+    unsafe {
+        asm!(
+            "xvld $xr0, {0}, 0*32",
+            "xvld $xr1, {0}, 1*32",
+            "xvld $xr2, {0}, 2*32",
+            "xvld $xr3, {0}, 3*32",
+            "xvld $xr4, {0}, 4*32",
+            "xvld $xr5, {0}, 5*32",
+            "xvld $xr6, {0}, 6*32",
+            "xvld $xr7, {0}, 7*32",
+            "xvld $xr8, {0}, 8*32",
+            "xvld $xr9, {0}, 9*32",
+            "xvld $xr10, {0}, 10*32",
+            "xvld $xr11, {0}, 11*32",
+            "xvld $xr12, {0}, 12*32",
+            "xvld $xr13, {0}, 13*32",
+            "xvld $xr14, {0}, 14*32",
+            "xvld $xr15, {0}, 15*32",
+            "xvld $xr16, {0}, 16*32",
+            "xvld $xr17, {0}, 17*32",
+            "xvld $xr18, {0}, 18*32",
+            "xvld $xr19, {0}, 19*32",
+            "xvld $xr20, {0}, 20*32",
+            "xvld $xr21, {0}, 21*32",
+            "xvld $xr22, {0}, 22*32",
+            "xvld $xr23, {0}, 23*32",
+            "xvld $xr24, {0}, 24*32",
+            "xvld $xr25, {0}, 25*32",
+            "xvld $xr26, {0}, 26*32",
+            "xvld $xr27, {0}, 27*32",
+            "xvld $xr28, {0}, 28*32",
+            "xvld $xr29, {0}, 29*32",
+            "xvld $xr30, {0}, 30*32",
+            "xvld $xr31, {0}, 31*32",
+            in(reg) ptr,
+        );
+    }
+}
@@ -0,0 +1,9 @@
+//! Floating-point and vector registers of user tasks.
+//!
+//! The kernel itself uses neither the FPU nor the vector unit, so their registers on a
+//! hart always belong to the user task running on it. This module only provides the means
+//! to save and restore them; when to do so is decided by the trap context of the task.
+
+use polyhal_macro::define_arch_mods;
+
+define_arch_mods!();
@@ -0,0 +1,284 @@
+use core::{
+    arch::asm,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use riscv::register::sstatus::{self, FS, Sstatus};
+
+/// The largest `vlenb` supported, i.e., a VLEN of up to 1024 bits.
+pub const MAX_VLENB: usize = 128;
+
+/// Mask of `sstatus.VS`, which is to the vector unit what `sstatus.FS` is to the FPU.
+const SSTATUS_VS: usize = 3 << 9;
+const SSTATUS_VS_SHIFT: usize = 9;
+
+/// `vlenb` of the harts, or 0 if the V extension is not supported.
+static VLENB: AtomicUsize = AtomicUsize::new(0);
+
+/// Probes the V extension.
+///
+/// `sstatus.VS` is read-only zero if the V extension is not implemented.
+pub fn init() {
+    let status: usize;
+    unsafe {
+        asm!(
+            "csrs sstatus, {0}",
+            "csrr {1}, sstatus",
+            in(reg) 1 << SSTATUS_VS_SHIFT,
+            out(reg) status,
+        );
+    }
+    if status & SSTATUS_VS == 0 {
+        return;
+    }
+    let vlenb: usize;
+    unsafe {
+        // vlenb
+        asm!("csrr {}, 0xc22", out(reg) vlenb);
+        asm!("csrc sstatus, {}", in(reg) SSTATUS_VS);
+    }
+    if vlenb > MAX_VLENB {
+        log::warn!("[fpu] VLEN of {} bits is not supported", vlenb * 8);
+        return;
+    }
+    VLENB.store(vlenb, Ordering::Relaxed);
+}
+
+/// Returns whether the V extension is supported.
+pub fn has_vector() -> bool {
+    vlenb() != 0
+}
+
+/// Returns the length of a vector register in bytes, or 0 if the V extension is not
+/// supported.
+pub fn vlenb() -> usize {
+    VLENB.load(Ordering::Relaxed)
+}
+
+/// Returns `sstatus.VS` of `status`, whose values are the same as those of `sstatus.FS`.
+pub fn vector_state(status: &Sstatus) -> FS {
+    match (status.bits() & SSTATUS_VS) >> SSTATUS_VS_SHIFT {
+        0 => FS::Off,
+        1 => FS::Initial,
+        2 => FS::Clean,
+        _ => FS::Dirty,
+    }
+}
+
+/// Sets `sstatus.VS` of `status`.
+pub fn set_vector_state(status: &mut Sstatus, vs: FS) {
+    let bits = status.bits() & !SSTATUS_VS | (vs as usize) << SSTATUS_VS_SHIFT;
+    *status = Sstatus::from_bits(bits);
+}
+
+/// Sets `sstatus.VS` of the current hart to clean so that the kernel can access the
+/// vector registers.
+fn enable_vector() {
+    unsafe {
+        asm!(
+            "csrc sstatus, {0}",
+            "csrs sstatus, {1}",
+            in(reg) SSTATUS_VS,
+            in(reg) (FS::Clean as usize) << SSTATUS_VS_SHIFT,
+        );
+    }
+}
+
+/// Floating-point registers `f0`-`f31` and `fcsr`.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct FloatRegs {
+    pub f: [u64; 32],
+    pub fcsr: u32,
+}
+
+impl FloatRegs {
+    pub const fn new() -> Self {
+        Self {
+            f: [0; 32],
+            fcsr: 0,
+        }
+    }
+
+    /// Saves the floating-point registers of the current hart into `self`.
+    pub fn save(&mut self) {
+        let fcsr: usize;
+        unsafe {
+            // Make sure the FPU is accessible, whatever the user `sstatus.FS` is.
+            sstatus::set_fs(FS::Clean);
+            asm!(
+                "fsd f0,  0*8({0})",
+                "fsd f1,  1*8({0})",
+                "fsd f2,  2*8({0})",
+                "fsd f3,  3*8({0})",
+                "fsd f4,  4*8({0})",
+                "fsd f5,  5*8({0})",
+                "fsd f6,  6*8({0})",
+                "fsd f7,  7*8({0})",
+                "fsd f8,  8*8({0})",
+                "fsd f9,  9*8({0})",
+                "fsd f10, 10*8({0})",
+                "fsd f11, 11*8({0})",
+                "fsd f12, 12*8({0})",
+                "fsd f13, 13*8({0})",
+                "fsd f14, 14*8({0})",
+                "fsd f15, 15*8({0})",
+                "fsd f16, 16*8({0})",
+                "fsd f17, 17*8({0})",
+                "fsd f18, 18*8({0})",
+                "fsd f19, 19*8({0})",
+                "fsd f20, 20*8({0})",
+                "fsd f21, 21*8({0})",
+                "fsd f22, 22*8({0})",
+                "fsd f23, 23*8({0})",
+                "fsd f24, 24*8({0})",
+                "fsd f25, 25*8({0})",
+                "fsd f26, 26*8({0})",
+                "fsd f27, 27*8({0})",
+                "fsd f28, 28*8({0})",
+                "fsd f29, 29*8({0})",
+                "fsd f30, 30*8({0})",
+                "fsd f31, 31*8({0})",
+                "frcsr {1}",
+                in(reg) self.f.as_mut_ptr(),
+                out(reg) fcsr,
+            );
+        }
+        self.fcsr = fcsr as u32;
+    }
+
+    /// Loads `self` into the floating-point registers of the current hart.
+    pub fn restore(&self) {
+        unsafe {
+            sstatus::set_fs(FS::Clean);
+            asm!(
+                "fld f0,  0*8({0})",
+                "fld f1,  1*8({0})",
+                "fld f2,  2*8({0})",
+                "fld f3,  3*8({0})",
+                "fld f4,  4*8({0})",
+                "fld f5,  5*8({0})",
+                "fld f6,  6*8({0})",
+                "fld f7,  7*8({0})",
+                "fld f8,  8*8({0})",
+                "fld f9,  9*8({0})",
+                "fld f10, 10*8({0})",
+                "fld f11, 11*8({0})",
+                "fld f12, 12*8({0})",
+                "fld f13, 13*8({0})",
+                "fld f14, 14*8({0})",
+                "fld f15, 15*8({0})",
+                "fld f16, 16*8({0})",
+                "fld f17, 17*8({0})",
+                "fld f18, 18*8({0})",
+                "fld f19, 19*8({0})",
+                "fld f20, 20*8({0})",
+                "fld f21, 21*8({0})",
+                "fld f22, 22*8({0})",
+                "fld f23, 23*8({0})",
+                "fld f24, 24*8({0})",
+                "fld f25, 25*8({0})",
+                "fld f26, 26*8({0})",
+                "fld f27, 27*8({0})",
+                "fld f28, 28*8({0})",
+                "fld f29, 29*8({0})",
+                "fld f30, 30*8({0})",
+                "fld f31, 31*8({0})",
+                "fscsr {1}",
+                in(reg) self.f.as_ptr(),
+                in(reg) self.fcsr as usize,
+            );
+        }
+    }
+}
+
+/// Vector registers `v0`-`v31` and vector CSRs.
+///
+/// Only the first `32 * vlenb` bytes of `v` are used.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct VectorRegs {
+    pub vstart: usize,
+    pub vl: usize,
+    pub vtype: usize,
+    pub vcsr: usize,
+    pub v: [u8; 32 * MAX_VLENB],
+}
+
+impl VectorRegs {
+    pub const fn new() -> Self {
+        Self {
+            vstart: 0,
+            vl: 0,
+            // vill, as after reset
+            vtype: 1 << 63,
+            vcsr: 0,
+            v: [0; 32 * MAX_VLENB],
+        }
+    }
+
+    /// Saves the vector registers of the current hart into `self`.
+    pub fn save(&mut self) {
+        let vlenb = vlenb();
+        if vlenb == 0 {
+            return;
+        }
+        enable_vector();
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "csrr {vstart}, vstart",
+                "csrr {vl}, vl",
+                "csrr {vtype}, vtype",
+                "csrr {vcsr}, vcsr",
+                "vs8r.v v0, ({ptr})",
+                "add {ptr}, {ptr}, {stride}",
+                "vs8r.v v8, ({ptr})",
+                "add {ptr}, {ptr}, {stride}",
+                "vs8r.v v16, ({ptr})",
+                "add {ptr}, {ptr}, {stride}",
+                "vs8r.v v24, ({ptr})",
+                ".option pop",
+                ptr = inout(reg) self.v.as_mut_ptr() => _,
+                stride = in(reg) vlenb * 8,
+                vstart = out(reg) self.vstart,
+                vl = out(reg) self.vl,
+                vtype = out(reg) self.vtype,
+                vcsr = out(reg) self.vcsr,
+            );
+        }
+    }
+
+    /// Loads `self` into the vector registers of the current hart.
+    pub fn restore(&self) {
+        let vlenb = vlenb();
+        if vlenb == 0 {
+            return;
+        }
+        enable_vector();
+        unsafe {
+            asm!(
+                ".option push",
+                ".option arch, +v",
+                "vl8re8.v v0, ({ptr})",
+                "add {ptr}, {ptr}, {stride}",
+                "vl8re8.v v8, ({ptr})",
+                "add {ptr}, {ptr}, {stride}",
+                "vl8re8.v v16, ({ptr})",
+                "add {ptr}, {ptr}, {stride}",
+                "vl8re8.v v24, ({ptr})",
+                "vsetvl x0, {vl}, {vtype}",
+                "csrw vstart, {vstart}",
+                "csrw vcsr, {vcsr}",
+                ".option pop",
+                ptr = inout(reg) self.v.as_ptr() => _,
+                stride = in(reg) vlenb * 8,
+                vstart = in(reg) self.vstart,
+                vl = in(reg) self.vl,
+                vtype = in(reg) self.vtype,
+                vcsr = in(reg) self.vcsr,
+            );
+        }
+    }
+}
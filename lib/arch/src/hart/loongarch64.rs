@@ -24,3 +24,23 @@ pub fn hart_shutdown() -> ! {
         unsafe { loongArch64::asm::idle() }
     }
 }
+
+/// Returns the `AT_HWCAP` bits.
+pub fn hwcap() -> usize {
+    const HWCAP_LOONGARCH_CPUCFG: usize = 1 << 0;
+    const HWCAP_LOONGARCH_LAM: usize = 1 << 1;
+    const HWCAP_LOONGARCH_UAL: usize = 1 << 2;
+    const HWCAP_LOONGARCH_FPU: usize = 1 << 3;
+    const HWCAP_LOONGARCH_LSX: usize = 1 << 4;
+    const HWCAP_LOONGARCH_LASX: usize = 1 << 5;
+
+    let mut hwcap =
+        HWCAP_LOONGARCH_CPUCFG | HWCAP_LOONGARCH_LAM | HWCAP_LOONGARCH_UAL | HWCAP_LOONGARCH_FPU;
+    if crate::fpu::has_vector() {
+        hwcap |= HWCAP_LOONGARCH_LSX;
+    }
+    if crate::fpu::has_lasx() {
+        hwcap |= HWCAP_LOONGARCH_LASX;
+    }
+    hwcap
+}
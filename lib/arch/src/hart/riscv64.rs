@@ -8,3 +8,14 @@ pub fn hart_shutdown() -> ! {
     sbi_rt::system_reset(sbi_rt::Shutdown, sbi_rt::NoReason);
     unreachable!()
 }
+
+/// Returns the `AT_HWCAP` bits, one for each single-letter ISA extension.
+pub fn hwcap() -> usize {
+    let mut hwcap = ['i', 'm', 'a', 'f', 'd', 'c']
+        .iter()
+        .fold(0, |hwcap, &ext| hwcap | 1 << (ext as u8 - b'a'));
+    if crate::fpu::has_vector() {
+        hwcap |= 1 << (b'v' - b'a');
+    }
+    hwcap
+}
@@ -1,5 +1,17 @@
 use core::arch::asm;
 
+/// IOCSR holding the pending inter-processor interrupt vectors of the current core.
+const IOCSR_IPI_STATUS: usize = 0x1000;
+/// IOCSR enabling inter-processor interrupt vectors of the current core.
+const IOCSR_IPI_EN: usize = 0x1004;
+/// IOCSR clearing pending inter-processor interrupt vectors of the current core.
+const IOCSR_IPI_CLEAR: usize = 0x100c;
+/// IOCSR sending an inter-processor interrupt, with the target core in bits 16..26 and
+/// the vector in bits 0..5.
+const IOCSR_IPI_SEND: usize = 0x1040;
+/// Bit of the inter-processor interrupt in `ECFG.LIE` and `ESTAT.IS`.
+const LIE_IPI: usize = 1 << 12;
+
 pub fn enable_external_interrupt() {
     const LIE_ALL_EXT: usize = 0x3f;
     let ecfg: usize;
@@ -15,3 +27,35 @@ pub fn enable_external_interrupt() {
 pub fn is_interrupt_on() -> bool {
     true
 }
+
+/// Enables inter-processor interrupts, with all vectors enabled.
+pub fn enable_ipi() {
+    let ecfg: usize;
+    unsafe {
+        asm!("csrrd {}, 0x4", out(reg) ecfg); // 0x4 is ECFG
+        asm!(
+            "csrwr {val}, 0x4",
+            val = in(reg) (ecfg | LIE_IPI)
+        );
+        asm!("iocsrwr.w {}, {}", in(reg) u32::MAX, in(reg) IOCSR_IPI_EN);
+    }
+}
+
+/// Sends an inter-processor interrupt to each core whose bit is set in `hart_mask`,
+/// using vector 0.
+pub fn send_ipi(hart_mask: usize) {
+    for hart_id in (0..usize::BITS as usize).filter(|&i| hart_mask & (1 << i) != 0) {
+        unsafe {
+            asm!("iocsrwr.w {}, {}", in(reg) hart_id << 16, in(reg) IOCSR_IPI_SEND);
+        }
+    }
+}
+
+/// Acknowledges the pending inter-processor interrupts of the current core.
+pub fn clear_ipi() {
+    unsafe {
+        let status: usize;
+        asm!("iocsrrd.w {}, {}", out(reg) status, in(reg) IOCSR_IPI_STATUS);
+        asm!("iocsrwr.w {}, {}", in(reg) status, in(reg) IOCSR_IPI_CLEAR);
+    }
+}
@@ -9,3 +9,23 @@ pub fn enable_external_interrupt() {
 pub fn is_interrupt_on() -> bool {
     riscv::register::sstatus::read().sie()
 }
+
+/// Enables inter-processor interrupts, which are supervisor software interrupts
+/// raised by the SBI.
+pub fn enable_ipi() {
+    unsafe {
+        sie::set_ssoft();
+    }
+}
+
+/// Sends an inter-processor interrupt to each hart whose bit is set in `hart_mask`.
+pub fn send_ipi(hart_mask: usize) {
+    sbi_rt::send_ipi(sbi_rt::HartMask::from_mask_base(hart_mask, 0));
+}
+
+/// Acknowledges the pending inter-processor interrupt of the current hart.
+pub fn clear_ipi() {
+    unsafe {
+        riscv::register::sip::clear_ssoft();
+    }
+}
@@ -1,9 +1,11 @@
 #![no_std]
 #![no_main]
 #![allow(unused)]
+#![cfg_attr(target_arch = "loongarch64", feature(loongarch_target_feature))]
 // #![feature(riscv_ext_intrinsics)]
 
 pub mod console;
+pub mod fpu;
 pub mod hart;
 pub mod interrupt;
 pub mod mm;
@@ -1,14 +1,46 @@
 use core::arch::asm;
 
+use config::mm::PAGE_SIZE;
 use loongArch64::register::pgdl;
 
 use crate::pte::PageTableEntry;
 
+use super::TLB_FLUSH_RANGE_MAX_PAGES;
+
+/// Mask of the ASID field in the `ASID` CSR.
+const ASID_MASK: usize = 0x3ff;
+
 /// Switches the current page table being used by the MMU to the one
-/// at the given physical page number `root`.
-pub fn switch_page_table(root: usize) {
+/// at the given physical page number `root`, tagged with `asid`.
+///
+/// ASID 0 is shared by all page tables without an ASID of their own, so the TLB
+/// is flushed when switching to such a page table.
+pub fn switch_page_table(root: usize, asid: usize) {
     pgdl::set_base(root << 12);
-    tlb_flush_all_except_global();
+    unsafe {
+        asm!("csrwr {}, 0x18", inout(reg) asid & ASID_MASK => _); // 0x18 is ASID
+    }
+    if asid == 0 {
+        tlb_flush_all_except_global();
+    }
+}
+
+/// Returns the number of ASID bits supported by the hardware.
+pub fn asid_bits() -> usize {
+    (read_asid_csr() >> 16) & 0xff
+}
+
+/// Returns the ASID of the current address space.
+fn current_asid() -> usize {
+    read_asid_csr() & ASID_MASK
+}
+
+fn read_asid_csr() -> usize {
+    let asid: usize;
+    unsafe {
+        asm!("csrrd {}, 0x18", out(reg) asid); // 0x18 is ASID
+    }
+    asid
 }
 
 pub fn fence() {
@@ -33,34 +65,44 @@ pub fn tlb_flush_all_except_global() {
     tlb_flush_all();
 }
 
+/// Flushes the TLB entries for `addr` in the current address space on the current hart.
 pub fn tlb_flush_addr(addr: usize) {
     unsafe {
         asm!(
-            "invtlb 0x5, $r0, {reg}",
+            "invtlb 0x5, {asid}, {reg}",
+            asid = in(reg) current_asid(),
             reg = in(reg) addr
         );
     }
 }
 
-/// TLB shootdown for the specified address range.
-///
-/// # TODO
-/// This function currently only flushes the TLB for the current hart.
-/// In the future, it should be extended to support real TLB shootdown
-/// mechanism for LoongArch64.
-pub fn tlb_shootdown(_addr: usize, _length: usize) {
-    tlb_flush_all_except_global();
-    // TODO: Implement TLB shootdown mechanism for LoongArch64.
+/// Flushes the non-global TLB entries tagged with `asid` on the current hart.
+pub fn tlb_flush_asid(asid: usize) {
+    unsafe {
+        asm!("invtlb 0x4, {asid}, $r0", asid = in(reg) asid);
+    }
 }
 
-/// TLB shootdown for the whole user address space.
+/// Flushes the TLB entries for the range `[addr, addr + length)` tagged with `asid`
+/// on the current hart.
 ///
-/// # TODO
-/// This function currently only flushes the TLB for the current hart.
-/// In the future, it should be extended to support real TLB shootdown
-pub fn tlb_shootdown_all() {
-    tlb_flush_all_except_global();
-    // TODO: Implement TLB shootdown mechanism for LoongArch64.
+/// There is no remote TLB flush on LoongArch, so the kernel shoots down the TLBs of
+/// other harts by asking them to call this function with IPIs.
+pub fn tlb_flush_range(asid: usize, addr: usize, length: usize) {
+    let pages = length.div_ceil(PAGE_SIZE);
+    if pages > TLB_FLUSH_RANGE_MAX_PAGES {
+        tlb_flush_asid(asid);
+        return;
+    }
+    for page in 0..pages {
+        unsafe {
+            asm!(
+                "invtlb 0x5, {asid}, {reg}",
+                asid = in(reg) asid,
+                reg = in(reg) addr + page * PAGE_SIZE
+            );
+        }
+    }
 }
 
 /// Fills the TLB with the given page table entries for the current faulting
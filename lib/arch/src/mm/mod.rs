@@ -1,3 +1,7 @@
 use polyhal_macro::define_arch_mods;
 
 define_arch_mods!();
+
+/// Maximum number of pages that a ranged TLB flush invalidates one by one. Larger
+/// ranges are flushed by invalidating the whole ASID, which is cheaper.
+pub const TLB_FLUSH_RANGE_MAX_PAGES: usize = 64;
@@ -1,19 +1,42 @@
 use core::arch::asm;
 
-use config::mm::{USER_END, USER_START};
+use config::mm::{PAGE_SIZE, USER_END, USER_START};
 use riscv::register::satp::{self, Satp};
 
+use super::TLB_FLUSH_RANGE_MAX_PAGES;
+
 /// Switches the current page table being used by the MMU to the one
-/// at the given physical page number `root`.
-pub fn switch_page_table(root: usize) {
+/// at the given physical page number `root`, tagged with `asid`.
+///
+/// ASID 0 is shared by all page tables without an ASID of their own, so the TLB
+/// is flushed when switching to such a page table.
+pub fn switch_page_table(root: usize, asid: usize) {
     let mut satp = Satp::from_bits(0);
     satp.set_mode(riscv::register::satp::Mode::Sv39);
+    satp.set_asid(asid);
     satp.set_ppn(root);
     unsafe {
         satp::write(satp);
     }
-    // The board only supports ASID=0, so we need to flush the TLB.
-    tlb_flush_all_except_global();
+    if asid == 0 {
+        tlb_flush_asid(0);
+    }
+}
+
+/// Returns the number of ASID bits supported by the hardware.
+///
+/// The bits are probed by writing all ones to the ASID field of `satp` and reading
+/// back the bits that stick, so this function must be called with paging enabled.
+pub fn asid_bits() -> usize {
+    let old = satp::read();
+    let mut probe = old;
+    probe.set_asid(u16::MAX as usize);
+    unsafe {
+        satp::write(probe);
+        let bits = satp::read().asid().count_ones() as usize;
+        satp::write(old);
+        bits
+    }
 }
 
 pub fn fence() {
@@ -28,32 +51,53 @@ pub fn tlb_flush_all() {
     riscv::asm::sfence_vma_all();
 }
 
+/// Flushes the non-global TLB entries of all ASIDs on the current hart.
+///
+/// `sfence.vma` with a nonzero `rs2` register only flushes the entries of the ASID in
+/// it, and cannot spare global entries across ASIDs, so the whole TLB is flushed.
 pub fn tlb_flush_all_except_global() {
+    riscv::asm::sfence_vma_all();
+}
+
+/// Flushes the TLB entries for `addr` of all ASIDs on the current hart.
+pub fn tlb_flush_addr(addr: usize) {
     unsafe {
-        asm!("sfence.vma x0, {0}", in(reg) 0);
+        asm!("sfence.vma {0}, x0", in(reg) addr);
     }
 }
 
-pub fn tlb_flush_addr(addr: usize) {
-    riscv::asm::sfence_vma(0, addr);
-}
-
-/// TLB shootdown for the specified address range.
-pub fn tlb_shootdown(addr: usize, length: usize) {
-    sbi_rt::remote_sfence_vma_asid(
-        sbi_rt::HartMask::from_mask_base(0, usize::MAX),
-        addr,
-        length,
-        0,
-    );
-}
-
-/// TLB shootdown for the whole user address space.
-pub fn tlb_shootdown_all() {
-    sbi_rt::remote_sfence_vma_asid(
-        sbi_rt::HartMask::from_mask_base(0, usize::MAX),
-        USER_START,
-        USER_END - USER_START,
-        0,
-    );
+/// Flushes the non-global TLB entries tagged with `asid` on the current hart.
+pub fn tlb_flush_asid(asid: usize) {
+    unsafe {
+        asm!("sfence.vma x0, {0}", in(reg) asid);
+    }
+}
+
+/// Flushes the TLB entries for the range `[addr, addr + length)` tagged with `asid`
+/// on the current hart.
+pub fn tlb_flush_range(asid: usize, addr: usize, length: usize) {
+    let pages = length.div_ceil(PAGE_SIZE);
+    if pages > TLB_FLUSH_RANGE_MAX_PAGES {
+        tlb_flush_asid(asid);
+        return;
+    }
+    for page in 0..pages {
+        riscv::asm::sfence_vma(asid, addr + page * PAGE_SIZE);
+    }
+}
+
+/// TLB shootdown for the specified address range tagged with `asid`, on each hart
+/// whose bit is set in `hart_mask`. A `hart_mask` of `usize::MAX` stands for all harts.
+pub fn tlb_shootdown(hart_mask: usize, asid: usize, addr: usize, length: usize) {
+    let hart_mask = match hart_mask {
+        usize::MAX => sbi_rt::HartMask::from_mask_base(0, usize::MAX),
+        hart_mask => sbi_rt::HartMask::from_mask_base(hart_mask, 0),
+    };
+    sbi_rt::remote_sfence_vma_asid(hart_mask, addr, length, asid);
+}
+
+/// TLB shootdown for the whole user address space tagged with `asid`, on each hart
+/// whose bit is set in `hart_mask`.
+pub fn tlb_shootdown_asid(hart_mask: usize, asid: usize) {
+    tlb_shootdown(hart_mask, asid, USER_START, USER_END - USER_START);
 }
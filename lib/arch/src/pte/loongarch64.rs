@@ -1,5 +1,6 @@
 use bitflags::bitflags;
 
+use mm::address::PhysPageNum;
 use systype::memory_flags::MappingFlags;
 
 use super::PageTableEntry;
@@ -163,3 +164,42 @@ impl PageTableEntry {
         self.flags().contains(PteFlags::D)
     }
 }
+
+/// Bit in a directory entry which marks it as a leaf mapping a huge page.
+const HUGE: u64 = 1 << 6;
+/// Global bit in a huge page entry, which is moved from bit 6 taken by [`HUGE`].
+const HGLOBAL: u64 = 1 << 12;
+
+impl PageTableEntry {
+    /// Creates a huge page entry in a level-1 directory, which maps a 2 MiB page
+    /// starting at `ppn`. `ppn` must be aligned to 512 pages.
+    ///
+    /// A huge page entry has the `HUGE` bit set at bit 6, where a base page entry has
+    /// its `G` bit, so the `G` bit is moved to bit 12 as `HGLOBAL`, which is always
+    /// zero in the physical address of a huge page. `LDDIR` and `LDPTE` recognize
+    /// such entries in the TLB refill exception handler and fill the TLB with the
+    /// two halves of the huge page.
+    pub fn new_huge(ppn: PhysPageNum, flags: PteFlags) -> Self {
+        let mut bits = Self::new(ppn, flags.difference(PteFlags::G)).bits | HUGE;
+        if flags.contains(PteFlags::G) {
+            bits |= HGLOBAL;
+        }
+        PageTableEntry { bits }
+    }
+
+    /// Returns whether an entry in a directory is a leaf mapping a huge page, rather
+    /// than a pointer to the next-level directory.
+    pub fn is_huge(self) -> bool {
+        self.bits & HUGE != 0
+    }
+
+    /// Converts a huge page entry to the leaf entry mapping the first base page in
+    /// the huge page.
+    pub fn huge_to_base(self) -> Self {
+        let mut bits = self.bits & !(HUGE | HGLOBAL);
+        if self.bits & HGLOBAL != 0 {
+            bits |= PteFlags::G.bits();
+        }
+        PageTableEntry { bits }
+    }
+}
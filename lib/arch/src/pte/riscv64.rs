@@ -2,6 +2,7 @@ use core::fmt::Debug;
 
 use bitflags::bitflags;
 
+use mm::address::PhysPageNum;
 use systype::memory_flags::MappingFlags;
 
 use super::PageTableEntry;
@@ -111,3 +112,27 @@ impl PageTableEntry {
         self.flags().contains(PteFlags::D)
     }
 }
+
+impl PageTableEntry {
+    /// Creates a leaf entry in a level-1 page table, which maps a 2 MiB megapage
+    /// starting at `ppn`. `ppn` must be aligned to 512 pages, and `flags` must have
+    /// at least one of `R`, `W`, and `X` set, or the entry points to a page table.
+    pub fn new_huge(ppn: PhysPageNum, flags: PteFlags) -> Self {
+        debug_assert!(flags.intersects(PteFlags::RWX_MASK));
+        Self::new(ppn, flags)
+    }
+
+    /// Returns whether an entry in a non-leaf page table is a leaf mapping a huge
+    /// page, rather than a pointer to the next-level page table.
+    ///
+    /// In Sv39, a valid entry with any of `R`, `W`, and `X` set is a leaf.
+    pub fn is_huge(self) -> bool {
+        self.is_valid() && self.flags().intersects(PteFlags::RWX_MASK)
+    }
+
+    /// Converts a huge page entry to the leaf entry mapping the first base page in
+    /// the huge page. In Sv39, they have the same layout.
+    pub fn huge_to_base(self) -> Self {
+        self
+    }
+}
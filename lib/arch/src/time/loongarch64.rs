@@ -30,6 +30,11 @@ pub fn get_time_duration() -> Duration {
     Duration::from_micros(get_time_us() as u64)
 }
 
+/// Returns the number of timer ticks per microsecond.
+pub fn get_ticks_per_us() -> usize {
+    *FREQ / 1_000_000
+}
+
 /// Set the next timer interrupt.
 ///
 /// This function sets the next timer interrupt to occur after a specified number of
@@ -1,6 +1,6 @@
 use core::time::Duration;
 
-use riscv::register::time;
+use riscv::register::{scounteren, time};
 
 use config::board::{CLOCK_FREQ, INTERRUPTS_PER_SEC};
 
@@ -24,6 +24,11 @@ pub fn get_time_duration() -> Duration {
     Duration::from_micros(get_time_us() as u64)
 }
 
+/// Returns the number of timer ticks per microsecond.
+pub fn get_ticks_per_us() -> usize {
+    unsafe { CLOCK_FREQ / 1_000_000 }
+}
+
 /// Set the next timer interrupt.
 ///
 /// This function sets the next timer interrupt to occur after a specified number of
@@ -40,6 +45,6 @@ pub fn set_nx_timer_irq() {
 /// This function must be called once to set up the timer.
 pub fn init_timer() {
     set_nx_timer_irq();
-    // This function does nothing on RISC-V, as there is nothing to do before
-    // calls to `set_nx_timer_irq`.
+    // Allow user mode to read the `time` CSR, which the vDSO relies on.
+    unsafe { scounteren::set_tm() };
 }
@@ -1,6 +1,6 @@
 use loongArch64::register::{crmd, ecfg, eentry};
 
-use crate::interrupt::enable_external_interrupt;
+use crate::interrupt::{enable_external_interrupt, enable_ipi};
 
 use super::TrapMode;
 
@@ -10,6 +10,7 @@ pub const TIMER_IRQ: usize = 11;
 pub fn init() {
     enable_interrupt();
     enable_external_interrupt();
+    enable_ipi();
 }
 
 pub fn enable_interrupt() {
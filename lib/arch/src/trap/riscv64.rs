@@ -18,6 +18,8 @@ pub fn init() {
         log::debug!("Enable timer interrupt");
         // Enable timer interrupt
         riscv::register::sie::set_stimer();
+        // Enable inter-processor interrupt
+        crate::interrupt::enable_ipi();
 
         log::debug!("Enable interrupt");
         // Enable interrupt
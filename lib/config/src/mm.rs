@@ -108,6 +108,11 @@ pub const PTE_WIDTH: usize = 8;
 /// Number of page table entries in a page table
 pub const PTE_PER_TABLE: usize = PAGE_SIZE / PTE_WIDTH;
 
+/// Size of a huge page, which is mapped by a single level-1 page table entry
+pub const HUGE_PAGE_SIZE: usize = PAGE_SIZE * PTE_PER_TABLE;
+/// Number of base pages in a huge page
+pub const HUGE_PAGE_PAGES: usize = PTE_PER_TABLE;
+
 /// Start of user space
 pub const USER_START: usize = 0x0;
 /// End of user space (avoid using the last page)
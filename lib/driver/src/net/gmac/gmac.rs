@@ -6,7 +6,6 @@ use alloc::{
     string::ToString,
     vec::{self, Vec},
 };
-use arch::mm::tlb_shootdown_all;
 use mutex::SpinNoIrqLock;
 use smoltcp::phy::{DeviceCapabilities, Medium};
 use virtio_drivers::transport::DeviceType;
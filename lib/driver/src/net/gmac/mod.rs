@@ -7,4 +7,6 @@ mod eth_dev;
 mod gmac;
 mod platform;
 
+pub use gmac::Gmac;
+
 use core::panic::PanicInfo;
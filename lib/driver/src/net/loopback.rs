@@ -1,5 +1,4 @@
 use alloc::{boxed::Box, collections::VecDeque, vec, vec::Vec};
-use arch::mm::tlb_shootdown_all;
 use smoltcp::phy::{DeviceCapabilities, Medium};
 
 use super::{DevError, DevResult, EthernetAddress, NetDevice, netbuf::NetBufPtrOps};
@@ -20,17 +20,20 @@ use super::{
 const QS: usize = 32;
 const NET_BUF_LEN: usize = 1526;
 
-pub fn create_virt_net_dev(transport: MmioTransport<'static>) -> DevResult<Box<VirtIoNetDevImpl>> {
+/// Creates a virtio-net device over `transport`, which is an MMIO transport or a PCI
+/// transport.
+pub fn create_virt_net_dev<T: Transport + 'static>(
+    transport: T,
+) -> DevResult<Box<VirtIoNetDev<T, QS>>> {
     const NONE_BUF: Option<Box<NetBuf>> = None;
 
-    let inner =
-        VirtIONetRaw::<VirtHalImpl, MmioTransport, QS>::new(transport).map_err(as_dev_err)?;
+    let inner = VirtIONetRaw::<VirtHalImpl, T, QS>::new(transport).map_err(as_dev_err)?;
     let rx_buffers = [NONE_BUF; QS];
     let tx_buffers = [NONE_BUF; QS];
     let buf_pool = NetBufPool::new(2 * QS, NET_BUF_LEN)?;
     let free_tx_bufs = Vec::with_capacity(QS);
 
-    let mut dev = VirtIoNetDevImpl {
+    let mut dev = VirtIoNetDev {
         rx_buffers,
         inner,
         tx_buffers,
@@ -77,9 +80,9 @@ pub struct VirtIoNetDev<T: Transport, const QS: usize> {
 
 pub type VirtIoNetDevImpl = VirtIoNetDev<MmioTransport<'static>, 32>;
 
-impl Debug for VirtIoNetDevImpl {
+impl<T: Transport, const QS: usize> Debug for VirtIoNetDev<T, QS> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("VirtIoNetDevImpl")
+        f.debug_struct("VirtIoNetDev")
             .field("rx_buffers", &self.rx_buffers)
             .field("tx_buffers", &self.tx_buffers)
             .field("free_tx_bufs", &self.free_tx_bufs)
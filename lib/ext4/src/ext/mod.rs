@@ -6,3 +6,4 @@
 pub mod dir;
 pub mod file;
 pub mod inode;
+pub mod xattr;
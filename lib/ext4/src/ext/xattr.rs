@@ -0,0 +1,354 @@
+//! Module for accessing extended attributes of files in the ext4 filesystem.
+//!
+//! Extended attributes are stored by `lwext4` in the inode body or in a separate xattr
+//! block. Each attribute is stored with the index of its namespace and its name without
+//! the namespace prefix. POSIX ACLs are stored in the compact on-disk format of ext4,
+//! and are converted from and to the extended attribute format used by the VFS.
+
+use alloc::{vec, vec::Vec};
+use core::{
+    ffi::{c_char, c_int, c_void},
+    mem::MaybeUninit,
+};
+
+use lwext4_rust::bindings::{
+    ext4_fs, ext4_fs_get_inode_ref, ext4_fs_put_inode_ref, ext4_inode_ref,
+};
+
+use systype::error::{SysError, SysResult};
+use vfs::acl::{
+    ACL_GROUP, ACL_GROUP_OBJ, ACL_MASK, ACL_OTHER, ACL_UNDEFINED_ID, ACL_USER, ACL_USER_OBJ,
+    PosixAcl, PosixAclEntry, XATTR_NAME_POSIX_ACL_ACCESS, XATTR_NAME_POSIX_ACL_DEFAULT,
+};
+
+use super::file::ExtFile;
+
+unsafe extern "C" {
+    fn ext4_xattr_get(
+        inode_ref: *mut ext4_inode_ref,
+        name_index: u8,
+        name: *const c_char,
+        name_len: usize,
+        buf: *mut c_void,
+        buf_len: usize,
+        data_len: *mut usize,
+    ) -> c_int;
+
+    fn ext4_xattr_set(
+        inode_ref: *mut ext4_inode_ref,
+        name_index: u8,
+        name: *const c_char,
+        name_len: usize,
+        value: *const c_void,
+        value_len: usize,
+    ) -> c_int;
+
+    fn ext4_xattr_remove(
+        inode_ref: *mut ext4_inode_ref,
+        name_index: u8,
+        name: *const c_char,
+        name_len: usize,
+    ) -> c_int;
+}
+
+// Indices of the namespaces of extended attributes on disk.
+const EXT4_XATTR_INDEX_USER: u8 = 1;
+const EXT4_XATTR_INDEX_POSIX_ACL_ACCESS: u8 = 2;
+const EXT4_XATTR_INDEX_POSIX_ACL_DEFAULT: u8 = 3;
+const EXT4_XATTR_INDEX_TRUSTED: u8 = 4;
+const EXT4_XATTR_INDEX_SECURITY: u8 = 6;
+
+/// Version of the on-disk format of ACLs.
+const EXT4_ACL_VERSION: u32 = 1;
+
+/// Maximum size of the value of an extended attribute.
+const XATTR_SIZE_MAX: usize = 65536;
+
+const XATTR_CREATE: i32 = 1;
+const XATTR_REPLACE: i32 = 2;
+
+/// Splits the full name of an extended attribute into the index of its namespace and
+/// its name in the namespace.
+///
+/// The only `system.*` attributes are the ACLs. Other names in the namespace are
+/// rejected, so that they cannot clobber attributes `lwext4` keeps there itself, e.g.,
+/// `system.data` holding inline data.
+///
+/// # Errors
+/// Returns `EOPNOTSUPP` if the namespace or the `system.*` name is not supported, or
+/// `EINVAL` if the name in the namespace is empty.
+fn split_name(name: &str) -> SysResult<(u8, &str)> {
+    match name {
+        XATTR_NAME_POSIX_ACL_ACCESS => return Ok((EXT4_XATTR_INDEX_POSIX_ACL_ACCESS, "")),
+        XATTR_NAME_POSIX_ACL_DEFAULT => return Ok((EXT4_XATTR_INDEX_POSIX_ACL_DEFAULT, "")),
+        _ => {}
+    }
+
+    let (index, suffix) = [
+        ("user.", EXT4_XATTR_INDEX_USER),
+        ("trusted.", EXT4_XATTR_INDEX_TRUSTED),
+        ("security.", EXT4_XATTR_INDEX_SECURITY),
+    ]
+    .into_iter()
+    .find_map(|(prefix, index)| name.strip_prefix(prefix).map(|suffix| (index, suffix)))
+    .ok_or(SysError::EOPNOTSUPP)?;
+
+    if suffix.is_empty() {
+        return Err(SysError::EINVAL);
+    }
+    Ok((index, suffix))
+}
+
+/// Returns whether `index` is the index of a namespace holding an ACL.
+fn is_acl_index(index: u8) -> bool {
+    index == EXT4_XATTR_INDEX_POSIX_ACL_ACCESS || index == EXT4_XATTR_INDEX_POSIX_ACL_DEFAULT
+}
+
+/// Converts an ACL in the on-disk format to the extended attribute format.
+///
+/// On disk, an ACL is a 4-byte version followed by its entries, where an `ACL_USER` or
+/// `ACL_GROUP` entry takes 8 bytes with its qualifier, and other entries take 4 bytes.
+fn acl_from_disk(value: &[u8]) -> SysResult<Vec<u8>> {
+    if value.len() < 4 || u32::from_le_bytes(value[0..4].try_into().unwrap()) != EXT4_ACL_VERSION {
+        return Err(SysError::EINVAL);
+    }
+
+    let mut entries = Vec::new();
+    let mut rest = &value[4..];
+    while !rest.is_empty() {
+        if rest.len() < 4 {
+            return Err(SysError::EINVAL);
+        }
+        let tag = u16::from_le_bytes([rest[0], rest[1]]);
+        let perm = u16::from_le_bytes([rest[2], rest[3]]);
+        let id = match tag {
+            ACL_USER | ACL_GROUP => {
+                if rest.len() < 8 {
+                    return Err(SysError::EINVAL);
+                }
+                let id = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+                rest = &rest[8..];
+                id
+            }
+            ACL_USER_OBJ | ACL_GROUP_OBJ | ACL_MASK | ACL_OTHER => {
+                rest = &rest[4..];
+                ACL_UNDEFINED_ID
+            }
+            _ => return Err(SysError::EINVAL),
+        };
+        entries.push(PosixAclEntry { tag, perm, id });
+    }
+
+    Ok(PosixAcl { entries }.to_xattr())
+}
+
+/// Converts an ACL in the extended attribute format to the on-disk format.
+fn acl_to_disk(value: &[u8]) -> SysResult<Vec<u8>> {
+    let acl = PosixAcl::from_xattr(value)?;
+    let mut disk = Vec::with_capacity(4 + acl.entries.len() * 8);
+    disk.extend_from_slice(&EXT4_ACL_VERSION.to_le_bytes());
+    for entry in &acl.entries {
+        disk.extend_from_slice(&entry.tag.to_le_bytes());
+        disk.extend_from_slice(&entry.perm.to_le_bytes());
+        if entry.tag == ACL_USER || entry.tag == ACL_GROUP {
+            disk.extend_from_slice(&entry.id.to_le_bytes());
+        }
+    }
+    Ok(disk)
+}
+
+impl ExtFile {
+    /// Gets the extended attribute `name` of the file.
+    ///
+    /// # Errors
+    /// Returns `ENODATA` if the file does not have the attribute, or `EOPNOTSUPP` if
+    /// the namespace of the attribute is not supported.
+    pub(crate) fn get_xattr(&self, name: &str) -> SysResult<Vec<u8>> {
+        let (index, suffix) = split_name(name)?;
+
+        // An attribute is stored in the inode body or in a single filesystem block, so a
+        // value is never larger than `XATTR_SIZE_MAX`.
+        let mut value = vec![0; XATTR_SIZE_MAX];
+        let mut len = 0;
+        let err = self.with_inode_ref(|inode_ref| unsafe {
+            ext4_xattr_get(
+                inode_ref,
+                index,
+                suffix.as_ptr() as *const c_char,
+                suffix.len(),
+                value.as_mut_ptr() as *mut c_void,
+                value.len(),
+                &mut len,
+            )
+        })?;
+        match err {
+            0 => {}
+            e if e == SysError::ENODATA as i32 => return Err(SysError::ENODATA),
+            e => {
+                let err = SysError::from_i32(e);
+                log::warn!(
+                    "ext4_xattr_get failed: ino = {}, name = {}, error = {:?}",
+                    self.ino(),
+                    name,
+                    err
+                );
+                return Err(err);
+            }
+        }
+
+        value.truncate(len);
+        value.shrink_to_fit();
+        if is_acl_index(index) {
+            acl_from_disk(&value)
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Sets the extended attribute `name` of the file to `value`.
+    ///
+    /// `flags` can be `XATTR_CREATE` to fail if the attribute exists, or `XATTR_REPLACE`
+    /// to fail if it does not.
+    ///
+    /// # Errors
+    /// Returns `EEXIST` or `ENODATA` if `flags` is not satisfied, `EOPNOTSUPP` if the
+    /// namespace of the attribute is not supported, or `ENOSPC` if there is no room for
+    /// the attribute.
+    pub(crate) fn set_xattr(&self, name: &str, value: &[u8], flags: i32) -> SysResult<()> {
+        let (index, suffix) = split_name(name)?;
+        if flags & (XATTR_CREATE | XATTR_REPLACE) != 0 {
+            let exists = match self.get_xattr(name) {
+                Ok(_) => true,
+                Err(SysError::ENODATA) => false,
+                Err(e) => return Err(e),
+            };
+            if flags & XATTR_CREATE != 0 && exists {
+                return Err(SysError::EEXIST);
+            }
+            if flags & XATTR_REPLACE != 0 && !exists {
+                return Err(SysError::ENODATA);
+            }
+        }
+
+        let disk_value;
+        let value = if is_acl_index(index) {
+            disk_value = acl_to_disk(value)?;
+            &disk_value[..]
+        } else {
+            value
+        };
+
+        let err = self.with_inode_ref(|inode_ref| unsafe {
+            ext4_xattr_set(
+                inode_ref,
+                index,
+                suffix.as_ptr() as *const c_char,
+                suffix.len(),
+                value.as_ptr() as *const c_void,
+                value.len(),
+            )
+        })?;
+        match err {
+            0 => Ok(()),
+            e => {
+                let err = SysError::from_i32(e);
+                log::warn!(
+                    "ext4_xattr_set failed: ino = {}, name = {}, error = {:?}",
+                    self.ino(),
+                    name,
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+
+    /// Removes the extended attribute `name` of the file.
+    ///
+    /// # Errors
+    /// Returns `ENODATA` if the file does not have the attribute, or `EOPNOTSUPP` if
+    /// the namespace of the attribute is not supported.
+    pub(crate) fn remove_xattr(&self, name: &str) -> SysResult<()> {
+        let (index, suffix) = split_name(name)?;
+        let err = self.with_inode_ref(|inode_ref| unsafe {
+            ext4_xattr_remove(
+                inode_ref,
+                index,
+                suffix.as_ptr() as *const c_char,
+                suffix.len(),
+            )
+        })?;
+        match err {
+            0 => Ok(()),
+            e if e == SysError::ENODATA as i32 => Err(SysError::ENODATA),
+            e => {
+                let err = SysError::from_i32(e);
+                log::warn!(
+                    "ext4_xattr_remove failed: ino = {}, name = {}, error = {:?}",
+                    self.ino(),
+                    name,
+                    err
+                );
+                Err(err)
+            }
+        }
+    }
+
+    /// Calls `f` with a reference to the on-disk inode of the file, and puts the
+    /// reference afterwards, which writes the inode back if `f` has modified it.
+    ///
+    /// The lock of the mount point is held meanwhile, as the entry points of `lwext4`
+    /// such as `ext4_fopen` do.
+    ///
+    /// Returns the value returned by `f`.
+    fn with_inode_ref<T>(&self, f: impl FnOnce(*mut ext4_inode_ref) -> T) -> SysResult<T> {
+        let (mountpoint, ino) = unsafe {
+            let file = &*self.0.get();
+            (file.mp, file.inode)
+        };
+        let mountpoint = unsafe { mountpoint.as_mut_unchecked() };
+        let locks = unsafe { mountpoint.os_locks.as_ref() };
+        if let Some(lock) = locks.and_then(|locks| locks.lock) {
+            unsafe { lock() };
+        }
+        let result = Self::with_inode_ref_locked(&mut mountpoint.fs, ino, f);
+        if let Some(unlock) = locks.and_then(|locks| locks.unlock) {
+            unsafe { unlock() };
+        }
+        result
+    }
+
+    /// Does the same as [`Self::with_inode_ref`] with the lock of the mount point held.
+    fn with_inode_ref_locked<T>(
+        filesystem: &mut ext4_fs,
+        ino: u32,
+        f: impl FnOnce(*mut ext4_inode_ref) -> T,
+    ) -> SysResult<T> {
+        let mut inode_ref: MaybeUninit<ext4_inode_ref> = MaybeUninit::uninit();
+        let err = unsafe { ext4_fs_get_inode_ref(filesystem, ino, inode_ref.as_mut_ptr()) };
+        if err != 0 {
+            let err = SysError::from_i32(err);
+            log::warn!(
+                "ext4_fs_get_inode_ref failed: ino = {}, error = {:?}",
+                ino,
+                err
+            );
+            return Err(err);
+        }
+        let mut inode_ref = unsafe { inode_ref.assume_init() };
+
+        let result = f(&mut inode_ref);
+
+        let err = unsafe { ext4_fs_put_inode_ref(&mut inode_ref) };
+        if err != 0 {
+            let err = SysError::from_i32(err);
+            log::warn!(
+                "ext4_fs_put_inode_ref failed: ino = {}, error = {:?}",
+                ino,
+                err
+            );
+            return Err(err);
+        }
+        Ok(result)
+    }
+}
@@ -1,4 +1,4 @@
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 
 use config::device::BLOCK_SIZE;
 use mutex::{ShareMutex, new_share_mutex};
@@ -54,4 +54,20 @@ impl Inode for ExtDirInode {
             unused: 0,
         })
     }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: i32) -> SysResult<()> {
+        self.dir.lock().as_file().set_xattr(name, value, flags)?;
+        self.meta.inner.lock().invalidate_acl(name);
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str) -> SysResult<Vec<u8>> {
+        self.dir.lock().as_file().get_xattr(name)
+    }
+
+    fn remove_xattr(&self, name: &str) -> SysResult<()> {
+        self.dir.lock().as_file().remove_xattr(name)?;
+        self.meta.inner.lock().invalidate_acl(name);
+        Ok(())
+    }
 }
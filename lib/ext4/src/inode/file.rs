@@ -1,4 +1,4 @@
-use alloc::sync::Arc;
+use alloc::{sync::Arc, vec::Vec};
 
 use config::device::BLOCK_SIZE;
 use mutex::{ShareMutex, new_share_mutex};
@@ -57,4 +57,20 @@ impl Inode for ExtFileInode {
             unused: 0,
         })
     }
+
+    fn set_xattr(&self, name: &str, value: &[u8], flags: i32) -> SysResult<()> {
+        self.file.lock().set_xattr(name, value, flags)?;
+        self.meta.inner.lock().invalidate_acl(name);
+        Ok(())
+    }
+
+    fn get_xattr(&self, name: &str) -> SysResult<Vec<u8>> {
+        self.file.lock().get_xattr(name)
+    }
+
+    fn remove_xattr(&self, name: &str) -> SysResult<()> {
+        self.file.lock().remove_xattr(name)?;
+        self.meta.inner.lock().invalidate_acl(name);
+        Ok(())
+    }
 }
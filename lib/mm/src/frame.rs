@@ -17,32 +17,57 @@
 //! which is not efficient when allocating or deallocating multiple frames at once.
 //! Use [`FrameTracker::build_batch`] and [`FrameDropper`] to allocate and deallocate
 //! frames in batch, respectively.
+//!
+//! When no frame is free, the allocation functions ask the kernel to free memory,
+//! which may kill a process, and retry the allocation. See
+//! [`KernelMmOperations`](crate::kinterface::KernelMmOperations).
 
-use alloc::vec::Vec;
-use core::{cell::SyncUnsafeCell, mem::ManuallyDrop};
+use alloc::{vec, vec::Vec};
+use core::{
+    cell::SyncUnsafeCell,
+    mem::ManuallyDrop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use bitmap_allocator::{BitAlloc, BitAlloc1M};
+use crate_interface::call_interface;
 
 use config::mm::{PAGE_SIZE, RAM_END, kernel_end_phys};
 use mutex::SpinNoIrqLock;
 use systype::error::{SysError, SysResult};
 
-use crate::address::{PhysAddr, PhysPageNum, VirtPageNum};
+use crate::{
+    address::{PhysAddr, PhysPageNum, VirtPageNum},
+    kinterface::__KernelMmOperations_mod,
+    stat::VmStat,
+};
+
+/// Number of times an allocation is retried after the kernel frees memory.
+const MAX_OOM_RETRIES: usize = 3;
+
+/// Largest order of the free blocks counted by [`free_blocks_by_order`].
+pub const MAX_ORDER: usize = 10;
 
 /// Global frame allocator. It allocates and deallocates allocatable frames.
 ///
 /// It is protected by a lock to be used in a multi-threaded environment.
 static FRAME_ALLOCATOR: FrameAllocator = FrameAllocator {
-    allocator: SpinNoIrqLock::new(BitAlloc1M::DEFAULT),
+    allocator: SpinNoIrqLock::new(FrameBitmap::new()),
     offset: SyncUnsafeCell::new(0),
+    total: SyncUnsafeCell::new(0),
+    free: AtomicUsize::new(0),
 };
 
 /// Frame allocator type.
 struct FrameAllocator {
     /// Bitmap allocator.
-    allocator: SpinNoIrqLock<BitAlloc1M>,
+    allocator: SpinNoIrqLock<FrameBitmap>,
     /// Offset between PPNs and bit indices.
     offset: SyncUnsafeCell<usize>,
+    /// Number of allocatable frames.
+    total: SyncUnsafeCell<usize>,
+    /// Number of free frames.
+    free: AtomicUsize,
 }
 
 impl FrameAllocator {
@@ -53,6 +78,201 @@ impl FrameAllocator {
     }
 }
 
+/// Bitmap allocator of frames, which also counts the free blocks of each order as
+/// frames are allocated and deallocated.
+///
+/// A free block of order `n` is a run of `2^n` free frames whose first PPN is aligned
+/// to `2^n`, and is counted only if it is not part of a free block of a higher order
+/// up to [`MAX_ORDER`]. These are the blocks a buddy allocator would have merged the
+/// frames into.
+struct FrameBitmap {
+    bitmap: BitAlloc1M,
+    /// Offset between PPNs and bit indices.
+    offset: usize,
+    /// Number of free frames in each aligned block of order 1 to `MAX_ORDER`, indexed
+    /// by the order minus 1, and then by [`Self::block_index`].
+    block_free: [Vec<u16>; MAX_ORDER],
+    /// Number of free blocks of each order.
+    free_blocks: [usize; MAX_ORDER + 1],
+}
+
+impl FrameBitmap {
+    const fn new() -> Self {
+        Self {
+            bitmap: BitAlloc1M::DEFAULT,
+            offset: 0,
+            block_free: [const { Vec::new() }; MAX_ORDER],
+            free_blocks: [0; MAX_ORDER + 1],
+        }
+    }
+
+    /// Returns the number of aligned blocks of `order` which cover `count` frames
+    /// from PPN `offset`.
+    fn block_count(offset: usize, count: usize, order: usize) -> usize {
+        ((offset + count - 1) >> order) - (offset >> order) + 1
+    }
+
+    /// Makes the `count` frames from PPN `offset` free. `block_free` must be as long
+    /// as [`Self::block_count`] for each order.
+    fn init(&mut self, offset: usize, count: usize, block_free: [Vec<u16>; MAX_ORDER]) {
+        self.offset = offset;
+        self.block_free = block_free;
+        self.bitmap.insert(0..count);
+        let end = offset + count;
+        for (order, blocks) in (1..=MAX_ORDER).zip(self.block_free.iter_mut()) {
+            for (index, free) in blocks.iter_mut().enumerate() {
+                let block_start = ((offset >> order) + index) << order;
+                let block_end = block_start + (1 << order);
+                *free = (block_end.min(end) - block_start.max(offset)) as u16;
+            }
+        }
+        // The free frames form a single run, which is split into the largest aligned
+        // blocks it contains.
+        let mut ppn = offset;
+        while ppn < end {
+            let order = (ppn.trailing_zeros() as usize)
+                .min((end - ppn).ilog2() as usize)
+                .min(MAX_ORDER);
+            self.free_blocks[order] += 1;
+            ppn += 1 << order;
+        }
+    }
+
+    /// Returns the index of the aligned block of `order` which contains the frame of bit
+    /// index `i`.
+    fn block_index(&self, i: usize, order: usize) -> usize {
+        ((self.offset + i) >> order) - (self.offset >> order)
+    }
+
+    /// Returns the order of the largest free block which contains the free frame of
+    /// bit index `i`.
+    fn free_block_order(&self, i: usize) -> usize {
+        (1..=MAX_ORDER)
+            .take_while(|&order| {
+                self.block_free[order - 1][self.block_index(i, order)] == 1 << order
+            })
+            .last()
+            .unwrap_or(0)
+    }
+
+    /// Updates the counters for the frame of bit index `i`, which was free, being
+    /// allocated.
+    fn count_alloc(&mut self, i: usize) {
+        // The free block containing the frame is split into a free block of each lower
+        // order, besides the frame itself.
+        let order = self.free_block_order(i);
+        self.free_blocks[order] -= 1;
+        for lower in 0..order {
+            self.free_blocks[lower] += 1;
+        }
+        for order in 1..=MAX_ORDER {
+            let index = self.block_index(i, order);
+            self.block_free[order - 1][index] -= 1;
+        }
+    }
+
+    /// Updates the counters for the frame of bit index `i` being deallocated.
+    fn count_dealloc(&mut self, i: usize) {
+        for order in 1..=MAX_ORDER {
+            let index = self.block_index(i, order);
+            self.block_free[order - 1][index] += 1;
+        }
+        // The frame merges a free block of each lower order into a free block.
+        let order = self.free_block_order(i);
+        self.free_blocks[order] += 1;
+        for lower in 0..order {
+            self.free_blocks[lower] -= 1;
+        }
+    }
+
+    fn alloc(&mut self) -> Option<usize> {
+        let i = self.bitmap.alloc()?;
+        self.count_alloc(i);
+        Some(i)
+    }
+
+    fn alloc_contiguous(&mut self, count: usize) -> Option<usize> {
+        let start = self.bitmap.alloc_contiguous(None, count, 0)?;
+        for i in start..start + count {
+            self.count_alloc(i);
+        }
+        Some(start)
+    }
+
+    fn dealloc(&mut self, i: usize) {
+        self.bitmap.dealloc(i);
+        self.count_dealloc(i);
+    }
+}
+
+/// Returns the number of allocatable frames.
+pub fn total_frames() -> usize {
+    // SAFETY: `total` is never mutated after initialization.
+    unsafe { *FRAME_ALLOCATOR.total.get() }
+}
+
+/// Returns the number of free frames.
+pub fn free_frames() -> usize {
+    FRAME_ALLOCATOR.free.load(Ordering::Relaxed)
+}
+
+/// Returns the number of free blocks of each order, where a block of order `n` is a
+/// run of `2^n` free frames whose first PPN is aligned to `2^n`.
+///
+/// The frame allocator keeps no free lists as a buddy allocator does, so it counts the
+/// largest aligned blocks each run of free frames contains, which are the blocks a
+/// buddy allocator would have merged the frames into.
+pub fn free_blocks_by_order() -> [usize; MAX_ORDER + 1] {
+    FRAME_ALLOCATOR.allocator.lock().free_blocks
+}
+
+/// Allocates `count` physically contiguous frames, the first of which has a PPN
+/// aligned to `align` frames, and returns the bit index of the first one. `align`
+/// must be a power of two.
+///
+/// The frames are not counted as allocated yet.
+fn alloc_contiguous_aligned(count: usize, align: usize) -> Option<usize> {
+    debug_assert!(align.is_power_of_two());
+    let offset = FRAME_ALLOCATOR.offset();
+    let mut allocator_lock = FRAME_ALLOCATOR.allocator.lock();
+    // Bit indices are not aligned as PPNs are, so allocate extra frames to cover an
+    // aligned run, and give back the frames outside the run.
+    let padded = count + align - 1;
+    let start = allocator_lock.alloc_contiguous(padded)?;
+    let base = (offset + start).next_multiple_of(align) - offset;
+    for i in (start..base).chain(base + count..start + padded) {
+        allocator_lock.dealloc(i);
+    }
+    Some(base)
+}
+
+/// Allocates `count` physically contiguous frames aligned to `count` frames for the
+/// kernel heap, which keeps them forever, and returns the PPN of the first one.
+/// `count` must be a power of two.
+///
+/// Unlike [`FrameTracker`]s, this allocates nothing from the heap.
+pub(crate) fn alloc_heap_frames(count: usize) -> Option<PhysPageNum> {
+    let base = alloc_contiguous_aligned(count, count)?;
+    FRAME_ALLOCATOR.free.fetch_sub(count, Ordering::Relaxed);
+    VmStat::PgAlloc.add(count);
+    Some(PhysPageNum::new(FRAME_ALLOCATOR.offset() + base))
+}
+
+/// Runs `alloc` until it succeeds, asking the kernel to free memory after each
+/// failure.
+fn alloc_or_oom<T>(mut alloc: impl FnMut() -> Option<T>) -> SysResult<T> {
+    let mut retries = 0;
+    loop {
+        if let Some(val) = alloc() {
+            return Ok(val);
+        }
+        if retries == MAX_OOM_RETRIES || !call_interface!(KernelMmOperations::out_of_memory()) {
+            return Err(SysError::ENOMEM);
+        }
+        retries += 1;
+    }
+}
+
 /// Initializes the frame allocator.
 ///
 /// # Safety
@@ -65,8 +285,16 @@ pub unsafe fn init_frame_allocator() {
     // SAFETY: `offset` is mutate only once here when initialization.
     unsafe {
         *FRAME_ALLOCATOR.offset.get() = offset;
+        *FRAME_ALLOCATOR.total.get() = frame_count;
     }
-    FRAME_ALLOCATOR.allocator.lock().insert(0..frame_count);
+    let block_free = core::array::from_fn(|order| {
+        vec![0; FrameBitmap::block_count(offset, frame_count, order + 1)]
+    });
+    FRAME_ALLOCATOR
+        .allocator
+        .lock()
+        .init(offset, frame_count, block_free);
+    FRAME_ALLOCATOR.free.store(frame_count, Ordering::Relaxed);
     log::debug!(
         "frame allocator: allocatable frames from {:#x} - {:#x}",
         frames_ppn_start * PAGE_SIZE,
@@ -96,14 +324,12 @@ impl FrameTracker {
     /// Returns a `FrameTracker` if the frame is successfully allocated, or an `ENOMEM` error
     /// if there are no free frames.
     pub fn build() -> SysResult<Self> {
-        FRAME_ALLOCATOR
-            .allocator
-            .lock()
-            .alloc()
-            .map(|i| FrameTracker {
-                ppn: PhysPageNum::new(FRAME_ALLOCATOR.offset() + i),
-            })
-            .ok_or(SysError::ENOMEM)
+        let i = alloc_or_oom(|| FRAME_ALLOCATOR.allocator.lock().alloc())?;
+        FRAME_ALLOCATOR.free.fetch_sub(1, Ordering::Relaxed);
+        VmStat::PgAlloc.inc();
+        Ok(FrameTracker {
+            ppn: PhysPageNum::new(FRAME_ALLOCATOR.offset() + i),
+        })
     }
 
     /// Allocates a batch of frames.
@@ -122,36 +348,67 @@ impl FrameTracker {
     /// # Errors
     /// Returns `ENOMEM` if there are no free frames.
     pub fn build_batch(count: usize) -> SysResult<Vec<Self>> {
-        let mut allocator_lock = FRAME_ALLOCATOR.allocator.lock();
-        let mut frames = Vec::with_capacity(count);
-        for _ in 0..count {
-            if let Some(i) = allocator_lock.alloc() {
-                frames.push(FrameTracker {
-                    ppn: PhysPageNum::new(FRAME_ALLOCATOR.offset() + i),
-                });
-            } else {
-                FrameDropper::drop(frames);
-                return Err(SysError::ENOMEM);
+        let indices = alloc_or_oom(|| {
+            // Allocate the vector before taking the lock, as the heap grows with frames.
+            let mut indices = Vec::with_capacity(count);
+            let mut allocator_lock = FRAME_ALLOCATOR.allocator.lock();
+            for _ in 0..count {
+                match allocator_lock.alloc() {
+                    Some(i) => indices.push(i),
+                    None => {
+                        for i in indices {
+                            allocator_lock.dealloc(i);
+                        }
+                        return None;
+                    }
+                }
             }
-        }
-        Ok(frames)
+            Some(indices)
+        })?;
+        FRAME_ALLOCATOR.free.fetch_sub(count, Ordering::Relaxed);
+        VmStat::PgAlloc.add(count);
+        Ok(indices
+            .into_iter()
+            .map(|i| FrameTracker {
+                ppn: PhysPageNum::new(FRAME_ALLOCATOR.offset() + i),
+            })
+            .collect())
     }
 
+    /// Allocates `count` physically contiguous frames.
+    ///
+    /// # Errors
+    /// Returns `ENOMEM` if there are not enough contiguous free frames.
     pub fn build_contiguous(count: usize) -> SysResult<Vec<Self>> {
-        let base = FRAME_ALLOCATOR
-            .allocator
-            .lock()
-            .alloc_contiguous(None, count, 0);
-        if let Some(base) = base {
-            let frames = (FRAME_ALLOCATOR.offset() + base..FRAME_ALLOCATOR.offset() + base + count)
-                .map(|frame| FrameTracker {
-                    ppn: PhysPageNum::new(frame),
-                })
-                .collect();
-            Ok(frames)
-        } else {
-            Err(SysError::ENOMEM)
-        }
+        let base = alloc_or_oom(|| FRAME_ALLOCATOR.allocator.lock().alloc_contiguous(count))?;
+        Ok(Self::contiguous_from(base, count))
+    }
+
+    /// Allocates `count` physically contiguous frames, the first of which has a PPN
+    /// aligned to `align` frames. `align` must be a power of two. This is used to
+    /// allocate huge pages.
+    ///
+    /// Unlike other allocation functions, this function does not ask the kernel to free
+    /// memory on failure, because the failure is likely due to fragmentation rather than
+    /// lack of memory, and the caller usually falls back to allocating base pages.
+    ///
+    /// # Errors
+    /// Returns `ENOMEM` if there are not enough contiguous free frames.
+    pub fn try_build_contiguous_aligned(count: usize, align: usize) -> SysResult<Vec<Self>> {
+        let base = alloc_contiguous_aligned(count, align).ok_or(SysError::ENOMEM)?;
+        Ok(Self::contiguous_from(base, count))
+    }
+
+    /// Constructs `FrameTracker`s for `count` frames allocated from bit index `base`.
+    fn contiguous_from(base: usize, count: usize) -> Vec<Self> {
+        FRAME_ALLOCATOR.free.fetch_sub(count, Ordering::Relaxed);
+        VmStat::PgAlloc.add(count);
+        let offset = FRAME_ALLOCATOR.offset();
+        (offset + base..offset + base + count)
+            .map(|frame| FrameTracker {
+                ppn: PhysPageNum::new(frame),
+            })
+            .collect()
     }
 
     /// Gets the physical page number of the frame.
@@ -186,6 +443,8 @@ impl Drop for FrameTracker {
             .allocator
             .lock()
             .dealloc(self.ppn.to_usize() - FRAME_ALLOCATOR.offset());
+        FRAME_ALLOCATOR.free.fetch_add(1, Ordering::Relaxed);
+        VmStat::PgFree.inc();
     }
 }
 
@@ -229,6 +488,10 @@ impl Drop for FrameDropper {
         for frame in self.frames.iter() {
             frame_allocator_locked.dealloc(frame.ppn.to_usize() - FRAME_ALLOCATOR.offset());
         }
+        FRAME_ALLOCATOR
+            .free
+            .fetch_add(self.frames.len(), Ordering::Relaxed);
+        VmStat::PgFree.add(self.frames.len());
     }
 }
 
@@ -2,18 +2,26 @@
 //!
 //! Currently, we use the buddy system allocator for the kernel heap and use it to
 //! allocate memory for all kernel objects that require dynamic memory allocation.
+//!
+//! When the heap is exhausted, the allocator grows it with free frames. If there are
+//! not enough free frames, it asks the kernel to free memory once before giving up,
+//! since killing a process frees both the frames and the kernel objects owned by it.
+//! See [`KernelMmOperations`](crate::kinterface::KernelMmOperations). The kernel
+//! panics if an allocation still fails.
 
 use core::{
     alloc::{GlobalAlloc, Layout},
     ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use buddy_system_allocator as buddy;
+use crate_interface::call_interface;
 
-use config::mm::KERNEL_HEAP_SIZE;
+use config::mm::{KERNEL_HEAP_SIZE, PAGE_SIZE};
 use mutex::SpinNoIrqLock;
 
-use crate::address::VirtAddr;
+use crate::{address::VirtAddr, frame, kinterface::__KernelMmOperations_mod};
 
 /// A heap allocator protected by a spin lock.
 struct NoIrqLockedHeap<const ORDER: usize>(SpinNoIrqLock<buddy::Heap<ORDER>>);
@@ -34,16 +42,51 @@ impl<const ORDER: usize> NoIrqLockedHeap<ORDER> {
             self.0.lock().init(start_addr, size);
         }
     }
+
+    /// Grows the heap with free frames, enough for an allocation with `layout`.
+    ///
+    /// Returns `false` if there are not enough contiguous free frames.
+    fn grow(&self, layout: Layout) -> bool {
+        let size = layout.size().max(layout.align()).next_power_of_two();
+        let count = (size / PAGE_SIZE).max(HEAP_GROW_FRAMES).next_power_of_two();
+        let Some(ppn) = frame::alloc_heap_frames(count) else {
+            return false;
+        };
+        let start = ppn.to_vpn_kernel().address().to_usize();
+        // SAFETY: the frames are allocated for the heap, and never freed.
+        unsafe {
+            self.0.lock().add_to_heap(start, start + count * PAGE_SIZE);
+        }
+        true
+    }
 }
 
+/// Minimum number of frames the heap grows by when it is exhausted.
+const HEAP_GROW_FRAMES: usize = 256;
+
 unsafe impl GlobalAlloc for NoIrqLockedHeap<32> {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let buf = self
-            .0
-            .lock()
-            .alloc(layout)
-            .ok()
-            .map_or(ptr::null_mut(), |allocation| allocation.as_ptr());
+        let try_alloc = || {
+            self.0
+                .lock()
+                .alloc(layout)
+                .ok()
+                .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+        };
+        let mut buf = try_alloc();
+        if buf.is_null() && self.grow(layout) {
+            buf = try_alloc();
+        }
+        if buf.is_null() && call_interface!(KernelMmOperations::out_of_memory()) {
+            buf = try_alloc();
+            if buf.is_null() && self.grow(layout) {
+                buf = try_alloc();
+            }
+        }
+        if buf.is_null() {
+            return buf;
+        }
+        HEAP_OBJECTS[size_class(layout)].fetch_add(1, Ordering::Relaxed);
 
         let sz = layout.size();
         let zbuf = [0u8; 512];
@@ -68,9 +111,25 @@ unsafe impl GlobalAlloc for NoIrqLockedHeap<32> {
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
         unsafe { self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout) }
+        HEAP_OBJECTS[size_class(layout)].fetch_sub(1, Ordering::Relaxed);
     }
 }
 
+/// Number of objects allocated from the kernel heap in each size class, where an
+/// object of class `n` takes a block of `2^n` bytes.
+static HEAP_OBJECTS: [AtomicUsize; 32] = [const { AtomicUsize::new(0) }; 32];
+
+/// Returns the size class of an allocation with `layout`, which the buddy allocator
+/// rounds up to a power of two no less than its alignment and the size of a pointer.
+fn size_class(layout: Layout) -> usize {
+    let size = layout
+        .size()
+        .next_power_of_two()
+        .max(layout.align())
+        .max(size_of::<usize>());
+    size.trailing_zeros() as usize
+}
+
 #[repr(align(4096))]
 struct HeapMemory([u8; KERNEL_HEAP_SIZE]);
 
@@ -83,7 +142,10 @@ static HEAP_ALLOCATOR: NoIrqLockedHeap<32> = NoIrqLockedHeap::new();
 
 #[alloc_error_handler]
 fn alloc_error_handler(layout: Layout) -> ! {
-    panic!("heap allocation error, layout = {:?}", layout)
+    panic!(
+        "kernel heap exhausted: no frames to grow the heap and no memory to free, layout = {:?}",
+        layout
+    )
 }
 
 /// Initializes the kernel heap allocator.
@@ -108,6 +170,18 @@ pub unsafe fn init_heap_allocator() {
     );
 }
 
+/// Returns the number of bytes of the kernel heap in use, including the bytes lost by
+/// rounding up the allocations to their size classes.
+pub fn heap_used() -> usize {
+    HEAP_ALLOCATOR.0.lock().stats_alloc_actual()
+}
+
+/// Returns the number of objects allocated from the kernel heap in each size class,
+/// where an object of class `n` takes a block of `2^n` bytes.
+pub fn heap_objects() -> [usize; 32] {
+    core::array::from_fn(|class| HEAP_OBJECTS[class].load(Ordering::Relaxed))
+}
+
 pub fn allocate_align_memory(size: usize, align: usize) -> *mut u8 {
     unsafe {
         let layout = Layout::from_size_align_unchecked(size, align);
@@ -0,0 +1,11 @@
+use crate_interface::def_interface;
+
+#[def_interface]
+pub trait KernelMmOperations {
+    /// Tries to free memory after an allocation failed, by killing a process if
+    /// needed.
+    ///
+    /// Returns `true` if some memory may have been freed, so that the allocation
+    /// is worth retrying.
+    fn out_of_memory() -> bool;
+}
@@ -7,6 +7,8 @@
 pub mod address;
 pub mod frame;
 pub mod heap;
+pub mod kinterface;
 pub mod page_cache;
+pub mod stat;
 
 extern crate alloc;
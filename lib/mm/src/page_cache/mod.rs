@@ -34,6 +34,7 @@ impl PageCache {
     /// `offset` must be aligned to the page size.
     pub fn insert_page(&self, offset: usize, page: Arc<Page>) {
         debug_assert!(offset % PAGE_SIZE == 0);
+        page.mark_cached();
         self.pages.lock().insert(offset, page);
     }
 
@@ -24,12 +24,15 @@
 //! A [`Page`] also provides a way to find which process, file, or device it is tracked by. This is
 //! necessary, because a [`Page`] may be destroyed when it is swapped out or flushed to disk.
 
-use core::cell::SyncUnsafeCell;
+use core::{
+    cell::SyncUnsafeCell,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use config::mm::PAGE_SIZE;
 use systype::error::SysResult;
 
-use crate::{address::PhysPageNum, frame::FrameTracker};
+use crate::{address::PhysPageNum, frame::FrameTracker, stat::VmStat};
 
 /// A physical page in the system.
 ///
@@ -42,6 +45,9 @@ pub struct Page {
     /// This is a `SyncUnsafeCell` because we do not care about synchronization
     /// when accessing the page data simultaneously from multiple threads.
     frame: SyncUnsafeCell<FrameTracker>,
+    /// Whether the page has been inserted into a page cache, which decides whether it
+    /// is counted in [`VmStat::FilePages`] or [`VmStat::AnonPages`].
+    cached: AtomicBool,
     // /// Which mapping this page comes from.
     // mapping: Mapping,
 }
@@ -65,10 +71,26 @@ impl Page {
     /// # Errors
     /// Returns an [`ENOMEM`] error if the allocation fails.
     pub fn build() -> SysResult<Self> {
-        Ok(Self {
-            frame: SyncUnsafeCell::new(FrameTracker::build()?),
+        Ok(Self::from_frame(FrameTracker::build()?))
+    }
+
+    /// Creates a page from an allocated frame, such as one of the frames in a huge page
+    /// allocated by [`FrameTracker::try_build_contiguous_aligned`].
+    pub fn from_frame(frame: FrameTracker) -> Self {
+        VmStat::AnonPages.inc();
+        Self {
+            frame: SyncUnsafeCell::new(frame),
+            cached: AtomicBool::new(false),
             // mapping: Mapping::Anonymous,
-        })
+        }
+    }
+
+    /// Accounts the page as a page in a page cache rather than an anonymous page.
+    pub(crate) fn mark_cached(&self) {
+        if !self.cached.swap(true, Ordering::Relaxed) {
+            VmStat::AnonPages.sub(1);
+            VmStat::FilePages.inc();
+        }
     }
 
     /// Copies the contents of another [`Page`] into this [`Page`].
@@ -94,3 +116,13 @@ impl Page {
         unsafe { self.frame.get().as_mut_unchecked().as_mut_slice() }
     }
 }
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        if *self.cached.get_mut() {
+            VmStat::FilePages.sub(1);
+        } else {
+            VmStat::AnonPages.sub(1);
+        }
+    }
+}
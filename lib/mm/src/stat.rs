@@ -0,0 +1,93 @@
+//! Module for kernel-wide memory statistics.
+//!
+//! The statistics are counters of pages in each use and of memory management events,
+//! which are updated where the pages are allocated, freed or faulted in, and published
+//! in `/proc/meminfo`, `/proc/vmstat` and `sysinfo()`.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A memory statistic counter, named as in `/proc/vmstat`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VmStat {
+    /// Pages in the page caches of files.
+    FilePages,
+    /// Pages which are not in any page cache, e.g., private anonymous pages,
+    /// shared anonymous pages and copies of file pages made by copy-on-write.
+    AnonPages,
+    /// Frames used by page tables, including the ones deposited for huge pages.
+    PageTablePages,
+    /// Frames allocated.
+    PgAlloc,
+    /// Frames freed.
+    PgFree,
+    /// Page faults handled.
+    PgFault,
+    /// Page faults which read the page from the file.
+    PgMajFault,
+    /// Resident pages reclaimed from processes, which are read back on the next access.
+    PgSteal,
+    /// Processes killed by the OOM killer.
+    OomKill,
+    /// Huge pages allocated on page faults.
+    ThpFaultAlloc,
+    /// Huge pages split into base pages.
+    ThpSplitPage,
+}
+
+impl VmStat {
+    /// All counters, in the order they are published.
+    pub const ALL: [Self; 11] = [
+        Self::FilePages,
+        Self::AnonPages,
+        Self::PageTablePages,
+        Self::PgAlloc,
+        Self::PgFree,
+        Self::PgFault,
+        Self::PgMajFault,
+        Self::PgSteal,
+        Self::OomKill,
+        Self::ThpFaultAlloc,
+        Self::ThpSplitPage,
+    ];
+
+    /// Returns the name of the counter in `/proc/vmstat`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FilePages => "nr_file_pages",
+            Self::AnonPages => "nr_anon_pages",
+            Self::PageTablePages => "nr_page_table_pages",
+            Self::PgAlloc => "pgalloc_normal",
+            Self::PgFree => "pgfree",
+            Self::PgFault => "pgfault",
+            Self::PgMajFault => "pgmajfault",
+            Self::PgSteal => "pgsteal_direct",
+            Self::OomKill => "oom_kill",
+            Self::ThpFaultAlloc => "thp_fault_alloc",
+            Self::ThpSplitPage => "thp_split_page",
+        }
+    }
+
+    /// Returns the value of the counter.
+    pub fn get(self) -> usize {
+        COUNTERS[self as usize].load(Ordering::Relaxed)
+    }
+
+    /// Adds `n` to the counter.
+    pub fn add(self, n: usize) {
+        COUNTERS[self as usize].fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Subtracts `n` from the counter.
+    pub fn sub(self, n: usize) {
+        COUNTERS[self as usize].fetch_sub(n, Ordering::Relaxed);
+    }
+
+    /// Adds 1 to the counter.
+    pub fn inc(self) {
+        self.add(1);
+    }
+}
+
+/// Values of the counters, indexed by [`VmStat`].
+static COUNTERS: [AtomicUsize; VmStat::ALL.len()] =
+    [const { AtomicUsize::new(0) }; VmStat::ALL.len()];
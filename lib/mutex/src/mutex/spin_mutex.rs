@@ -79,6 +79,27 @@ impl<T, S: MutexSupport> SpinMutex<T, S> {
         }
     }
 
+    /// Tries to lock without spinning.
+    ///
+    /// Returns `None` if the lock is already held.
+    #[inline(always)]
+    pub fn try_lock(&self) -> Option<impl DerefMut<Target = T> + '_> {
+        let mut support_guard = S::before_lock();
+        if self
+            .lock
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(MutexGuard {
+                mutex: self,
+                support_guard,
+            })
+        } else {
+            S::after_unlock(&mut support_guard);
+            None
+        }
+    }
+
     /// # Safety
     ///
     /// This is highly unsafe.
@@ -1,6 +1,6 @@
 use core::net::Ipv6Addr;
 
-use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
+use smoltcp::wire::{EthernetAddress, IpAddress, IpEndpoint, IpListenEndpoint};
 
 /// `is_unspecified()` checks whether a ip address is unspecified, which means that
 /// ip is filled with 0.
@@ -58,3 +58,19 @@ pub const LOCAL_IPV4: IpAddress = IpAddress::v4(127, 0, 0, 1);
 
 /// `LOCAL_ENDPOINT_V4` is local loop endpoint address. Its addr is Some([`LOCAL_IPV4`]).
 pub const LOCAL_ENDPOINT_V4: IpEndpoint = IpEndpoint::new(LOCAL_IPV4, 0);
+
+/// `LOCAL_IPV6` is the ipv6 local loop address `::1`.
+pub const LOCAL_IPV6: IpAddress = IpAddress::Ipv6(Ipv6Addr::LOCALHOST);
+
+/// `link_local_ipv6()` autoconfigures the ipv6 link-local address of a network card
+/// from its MAC address `mac`, which is `fe80::/64` followed by the modified EUI-64
+/// interface identifier (see RFC 4291 Appendix A).
+pub fn link_local_ipv6(mac: EthernetAddress) -> Ipv6Addr {
+    let mac = mac.0;
+    let mut octets = [0u8; 16];
+    octets[..2].copy_from_slice(&[0xfe, 0x80]);
+    octets[8..11].copy_from_slice(&[mac[0] ^ 0x02, mac[1], mac[2]]);
+    octets[11..13].copy_from_slice(&[0xff, 0xfe]);
+    octets[13..].copy_from_slice(&mac[3..]);
+    Ipv6Addr::from(octets)
+}
@@ -33,13 +33,16 @@ impl TcpState {
 /// trait and modify inner member.
 pub(crate) struct DeviceWrapper {
     inner: RefCell<Box<dyn NetDevice>>,
+    /// Index of the interface of the device, which is passed to packet sockets.
+    ifindex: u32,
     pub state: TcpState,
 }
 
 impl DeviceWrapper {
-    pub fn new(inner: Box<dyn NetDevice>) -> Self {
+    pub fn new(inner: Box<dyn NetDevice>, ifindex: u32) -> Self {
         Self {
             inner: RefCell::new(inner),
+            ifindex,
             state: TcpState::new(),
         }
     }
@@ -90,9 +93,9 @@ impl phy::Device for DeviceWrapper {
                 return None;
             }
         };
-        let rxtoken = NetRxToken(&self.inner, rx_buf);
+        let rxtoken = NetRxToken(&self.inner, rx_buf, self.ifindex);
 
-        Some((rxtoken, NetTxToken(&self.inner)))
+        Some((rxtoken, NetTxToken(&self.inner, self.ifindex)))
     }
 
     /// Constructs a transmit token.
@@ -106,7 +109,7 @@ impl phy::Device for DeviceWrapper {
             return None;
         }
         if dev.can_transmit() {
-            Some(NetTxToken(&self.inner))
+            Some(NetTxToken(&self.inner, self.ifindex))
         } else {
             None
         }
@@ -1,6 +1,10 @@
-use core::{ops::DerefMut, time::Duration};
+use core::{
+    ops::DerefMut,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::Duration,
+};
 
-use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use alloc::{boxed::Box, string::String, sync::Arc, vec::Vec};
 use arch::{
     mm::tlb_flush_all,
     time::{get_time_duration, get_time_us},
@@ -8,19 +12,32 @@ use arch::{
 use driver::net::NetDevice;
 use mutex::{ShareMutex, SpinNoIrqLock};
 use smoltcp::{
-    iface::{Config, Interface, SocketSet},
-    phy::Medium,
+    iface::{Config, Interface, Route, SocketSet},
+    phy::{Device, Medium, TxToken},
     wire::{EthernetAddress, HardwareAddress, IpAddress, IpCidr},
 };
 use timer::{TIMER_MANAGER, Timer};
 
-use crate::{PollTimer, device::DeviceWrapper, tcp::LISTEN_TABLE};
+use crate::{PollTimer, device::DeviceWrapper, packet::ETH_HLEN, tcp::LISTEN_TABLE};
 
 type SmolInstant = smoltcp::time::Instant;
 type SmolDuration = smoltcp::time::Duration;
 
 const RANDOM_SEED: u64 = 83198713;
 
+/// The interface is administratively up.
+pub const IFF_UP: u32 = 0x1;
+/// The interface supports broadcast.
+pub const IFF_BROADCAST: u32 = 0x2;
+/// The interface is a loopback device.
+pub const IFF_LOOPBACK: u32 = 0x8;
+/// The interface is running.
+pub const IFF_RUNNING: u32 = 0x40;
+/// The interface supports multicast.
+pub const IFF_MULTICAST: u32 = 0x1000;
+/// The carrier of the interface is up.
+pub const IFF_LOWER_UP: u32 = 0x10000;
+
 /// `InterfaceWrapper` connects the `smoltcp` network protocol stack
 /// and the network card `driver` adaptation layer.
 ///
@@ -33,21 +50,33 @@ const RANDOM_SEED: u64 = 83198713;
 ///
 /// # Member
 /// - `name`: name of network card, logic name, used to debug(such as "eth0", "usb0")
+/// - `index`: interface index of network card, which starts from 1.
 /// - `ether_addr`: the MAC address of network card.
+/// - `loopback`: whether network card is a loopback device.
+/// - `up`: whether network card is administratively up. a card that is down is not
+///   polled, so it neither receives nor sends packets.
+/// - `poll_rank`: the shortest prefix among the routes of network card, which decides
+///   the order of polling (see [`crate::polled_interfaces`]).
 /// - `dev`: wrapper of network card, used to interact with physical device.
 /// - `iface`: smoltcp::Interface
 pub(crate) struct InterfaceWrapper {
-    name: &'static str,
+    name: String,
+    index: u32,
     ether_addr: EthernetAddress,
+    loopback: bool,
+    up: AtomicBool,
+    poll_rank: AtomicU8,
     dev: SpinNoIrqLock<DeviceWrapper>,
     pub(crate) iface: SpinNoIrqLock<Box<Interface>>,
 }
 
 impl InterfaceWrapper {
-    /// Creates a new `InterfaceWrapper`. In fact, this function is only called by `ETH0`
-    /// global variable. It will set network card's name, refer-dev and MAC address.
+    /// Creates a new `InterfaceWrapper` of index `index`, which is registered by
+    /// [`crate::init_network`]. It will set network card's name, refer-dev and MAC
+    /// address.
     pub(crate) fn new(
-        name: &'static str,
+        name: String,
+        index: u32,
         dev: Box<dyn NetDevice>,
         ether_addr: EthernetAddress,
     ) -> Self {
@@ -60,12 +89,17 @@ impl InterfaceWrapper {
 
         config.random_seed = RANDOM_SEED;
 
-        let mut dev = DeviceWrapper::new(dev);
+        let loopback = cap.medium == Medium::Ip;
+        let mut dev = DeviceWrapper::new(dev, index);
         let interface = Interface::new(config, Self::current_time(), cap);
         let iface = SpinNoIrqLock::new(interface);
         Self {
             name,
+            index,
             ether_addr,
+            loopback,
+            up: AtomicBool::new(true),
+            poll_rank: AtomicU8::new(0),
             dev: SpinNoIrqLock::new(dev),
             iface,
         }
@@ -88,7 +122,62 @@ impl InterfaceWrapper {
 
     /// gets the name of network card
     pub fn name(&self) -> &str {
-        self.name
+        &self.name
+    }
+
+    /// gets the interface index of network card
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// checks whether network card is administratively up.
+    pub fn is_up(&self) -> bool {
+        self.up.load(Ordering::Relaxed)
+    }
+
+    /// sets network card up or down, and returns whether it was up.
+    pub fn set_up(&self, up: bool) -> bool {
+        self.up.swap(up, Ordering::Relaxed)
+    }
+
+    /// gets the `IFF_*` flags of network card.
+    pub fn flags(&self) -> u32 {
+        let mut flags = if self.loopback {
+            IFF_LOOPBACK
+        } else {
+            IFF_BROADCAST | IFF_MULTICAST
+        };
+        if self.is_up() {
+            flags |= IFF_UP | IFF_RUNNING | IFF_LOWER_UP;
+        }
+        flags
+    }
+
+    /// gets the rank of network card in the order of polling.
+    pub fn poll_rank(&self) -> u8 {
+        self.poll_rank.load(Ordering::Relaxed)
+    }
+
+    /// replaces the routes of network card with `routes`, and updates its rank in the
+    /// order of polling, which is the shortest prefix among `routes` and the subnets of
+    /// its ip addresses.
+    ///
+    /// returns false if network card can't hold all the routes.
+    pub fn set_routes(&self, routes: Vec<Route>) -> bool {
+        let mut iface = self.iface.lock();
+        let rank = routes
+            .iter()
+            .map(|route| route.cidr.prefix_len())
+            .chain(iface.ip_addrs().iter().map(|cidr| cidr.prefix_len()))
+            .min()
+            .unwrap_or(u8::MAX);
+        let mut fits = true;
+        iface.routes_mut().update(|table| {
+            table.clear();
+            fits = routes.into_iter().all(|route| table.push(route).is_ok());
+        });
+        self.poll_rank.store(rank, Ordering::Relaxed);
+        fits
     }
 
     /// gets the ethernet address(MAC address) of network card
@@ -102,15 +191,74 @@ impl InterfaceWrapper {
         iface.update_ip_addrs(|ip_addrs| ip_addrs.extend(ips));
     }
 
-    /// adds a `gateway` in the network card. When network card trys to
-    /// send a packet to non-local address, this packet will be sent to
-    /// the router in (best) `gateway` address to forward.
-    pub fn setup_gateway(&self, gateway: IpAddress) {
-        let mut iface = self.iface.lock();
-        match gateway {
-            IpAddress::Ipv4(v4) => iface.routes_mut().add_default_ipv4_route(v4).unwrap(),
-            IpAddress::Ipv6(_) => unimplemented!(),
+    /// checks whether `addr` is one of the ip addresses of network card.
+    pub fn has_ip_addr(&self, addr: IpAddress) -> bool {
+        self.iface.lock().has_ip_addr(addr)
+    }
+
+    /// gets the ip addresses of network card.
+    pub fn ip_addrs(&self) -> Vec<IpCidr> {
+        self.iface.lock().ip_addrs().to_vec()
+    }
+
+    /// adds an ip address `cidr` into network card.
+    ///
+    /// returns false if network card can't hold more ip addresses.
+    pub fn add_ip_addr(&self, cidr: IpCidr) -> bool {
+        let mut added = false;
+        self.iface
+            .lock()
+            .update_ip_addrs(|ip_addrs| added = ip_addrs.push(cidr).is_ok());
+        added
+    }
+
+    /// removes an ip address `cidr` from network card.
+    ///
+    /// returns false if network card doesn't have the ip address.
+    pub fn remove_ip_addr(&self, cidr: IpCidr) -> bool {
+        let mut removed = false;
+        self.iface.lock().update_ip_addrs(|ip_addrs| {
+            if let Some(i) = ip_addrs.iter().position(|&addr| addr == cidr) {
+                ip_addrs.remove(i);
+                removed = true;
+            }
+        });
+        removed
+    }
+
+    /// checks whether network card is a loopback device, which has no hardware address.
+    pub fn is_loopback(&self) -> bool {
+        self.loopback
+    }
+
+    /// gets the maximum transmission unit of network card, which is the maximum size of
+    /// an ip packet it sends.
+    pub fn mtu(&self) -> usize {
+        let cap = self.dev.lock().capabilities();
+        match cap.medium {
+            Medium::Ethernet => cap.max_transmission_unit - ETH_HLEN,
+            Medium::Ip => cap.max_transmission_unit,
+        }
+    }
+
+    /// sends a link-layer `frame` through network card directly, bypassing the protocol
+    /// stack. a loopback device has no link layer, so the ethernet header of `frame` is
+    /// stripped for it.
+    ///
+    /// returns false if network card can't transmit now.
+    pub fn transmit_frame(&self, frame: &[u8]) -> bool {
+        let mut dev = self.dev.lock();
+        let packet = match dev.capabilities().medium {
+            Medium::Ethernet => frame,
+            Medium::Ip => &frame[ETH_HLEN..],
         };
+        match dev.transmit(Self::current_time()) {
+            Some(token) => {
+                token.consume(packet.len(), |buf| buf.copy_from_slice(packet));
+                true
+            }
+            None => false,
+        }
     }
 
     /// the Most important event handle loop in `Interface`.
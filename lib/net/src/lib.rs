@@ -4,12 +4,20 @@
 #![allow(unused)]
 #![feature(box_as_ptr)]
 
-use alloc::{boxed::Box, sync::Arc, vec};
-use driver::{net::NetDevice, println};
+use addr::{LOCAL_IPV6, link_local_ipv6};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
+use core::cmp::Reverse;
+use driver::{
+    net::{NetDevice, loopback::LoopbackDev},
+    println,
+};
 use interface::InterfaceWrapper;
-use smoltcp::wire::{EthernetAddress, IpCidr};
+use mutex::SpinNoIrqLock;
+use route::RouteEntry;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr, Ipv4Address};
 use socketset::SocketSetWrapper;
-use spin::{lazy::Lazy, once::Once};
+use spin::lazy::Lazy;
+use systype::error::{SysError, SysResult};
 use timer::{IEvent, Timer, TimerState};
 
 extern crate alloc;
@@ -19,8 +27,11 @@ pub mod bench;
 pub mod device;
 pub mod externf;
 pub mod interface;
+pub mod netlink;
+pub mod packet;
 pub mod portmap;
 pub mod raw;
+pub mod route;
 pub mod rttoken;
 pub mod socketset;
 pub mod tcp;
@@ -33,42 +44,133 @@ const IP: &str = "192.168.0.100";
 const GATEWAY: &str = "192.168.0.1";
 const IP_PREFIX: u8 = 24;
 
+/// The ipv6 site-local address and gateway, which match the default ipv6 network
+/// `fec0::/64` of the QEMU user mode network.
+const IPV6: &str = "fec0::100";
+const IPV6_GATEWAY: &str = "fec0::2";
+const IPV6_PREFIX: u8 = 64;
+
+/// Metric of the default route of the first network card. The default routes of later
+/// cards get higher metrics, so the first card is preferred.
+const DEFAULT_ROUTE_METRIC: u32 = 100;
+
 /// `SOCKET_SET` is a global socket manager, used to manage multi-type sockets,
 /// such as tcp, udp and unix.
 pub(crate) static SOCKET_SET: Lazy<SocketSetWrapper> = Lazy::new(SocketSetWrapper::new);
 
-/// `ETH0` is a wrapper of network card and protocol stack. It can poll receiving
-/// and sending event. Also, some important attributes(such as MAC address, gateway
-/// address and network card name) about network card is stored in `ETH0`.
-pub(crate) static ETH0: Once<InterfaceWrapper> = Once::new();
+/// `INTERFACES` holds the network interfaces, where the one of index `i` is at `i - 1`.
+/// The loopback interface `lo` comes first, followed by an interface for each network
+/// card probed. Each interface is a wrapper of network card and protocol stack, and is
+/// never removed, so it is leaked to be referenced statically.
+static INTERFACES: SpinNoIrqLock<Vec<&'static InterfaceWrapper>> = SpinNoIrqLock::new(Vec::new());
 
-/// This funtion is used to initialize `ETH0`, setting correct device, ips and gateway.
-pub fn init_network(net_dev: Box<dyn NetDevice>, is_loopback: bool) {
-    log::debug!("init_network begin");
+/// Returns all network interfaces, in the order of their indices.
+pub(crate) fn interfaces() -> Vec<&'static InterfaceWrapper> {
+    INTERFACES.lock().clone()
+}
+
+/// Returns the network interface of index `index`.
+pub(crate) fn interface(index: u32) -> Option<&'static InterfaceWrapper> {
+    let i = (index as usize).checked_sub(1)?;
+    INTERFACES.lock().get(i).copied()
+}
+
+/// Returns the network interfaces that are up in the order they are polled, from the
+/// one with the longest shortest prefix among its routes to the one with the shortest,
+/// so that the interface with the default route is polled last (see [`route`]).
+pub(crate) fn polled_interfaces() -> Vec<&'static InterfaceWrapper> {
+    let mut ifaces: Vec<_> = interfaces()
+        .into_iter()
+        .filter(|iface| iface.is_up())
+        .collect();
+    ifaces.sort_by_key(|iface| Reverse(iface.poll_rank()));
+    ifaces
+}
+
+/// Registers an interface named `name` of `net_dev` with ip addresses `ip_addrs`.
+fn register_interface(
+    name: String,
+    net_dev: Box<dyn NetDevice>,
+    ip_addrs: Vec<IpCidr>,
+) -> &'static InterfaceWrapper {
     let ether_addr = EthernetAddress(net_dev.mac_address().0);
-    log::debug!("eth0 init");
-    let eth0 = InterfaceWrapper::new("eth0", net_dev, ether_addr);
+    let iface = {
+        let mut ifaces = INTERFACES.lock();
+        let index = ifaces.len() as u32 + 1;
+        let iface: &'static InterfaceWrapper = Box::leak(Box::new(InterfaceWrapper::new(
+            name, index, net_dev, ether_addr,
+        )));
+        ifaces.push(iface);
+        iface
+    };
+    iface.setup_ip_addr(ip_addrs);
+    route::sync_interfaces();
+    log::info!("[net] register {} of index {}", iface.name(), iface.index());
+    iface
+}
 
-    log::debug!("gateway parse");
-    let gateway = GATEWAY.parse().unwrap();
-    let ip_addrs = if is_loopback {
+/// This funtion is used to register the network card `net_dev` as an interface, setting
+/// correct ips and default gateway.
+///
+/// A loopback device becomes `lo`, which is also created before the first other card
+/// if it has not been registered. Other cards are named `eth0`, `eth1` and so on. Only
+/// `eth0` gets an ipv6 address, and the default route of each card has a higher
+/// metric than that of the card before.
+pub fn init_network(net_dev: Box<dyn NetDevice>, is_loopback: bool) {
+    log::debug!("init_network begin");
+    if is_loopback {
+        if interfaces().iter().any(|iface| iface.is_loopback()) {
+            log::warn!("[init_network] lo is already registered");
+            return;
+        }
         let ip = "127.0.0.1".parse().unwrap();
-        vec![IpCidr::new(ip, 8)]
-    } else {
+        register_interface(String::from("lo"), net_dev, vec![
+            IpCidr::new(ip, 8),
+            IpCidr::new(LOCAL_IPV6, 128),
+        ]);
+        return;
+    }
+    if interfaces().is_empty() {
+        init_network(LoopbackDev::new(), true);
+    }
+
+    let n = interfaces()
+        .iter()
+        .filter(|iface| !iface.is_loopback())
+        .count();
+    let ether_addr = EthernetAddress(net_dev.mac_address().0);
+    let link_local = IpCidr::new(IpAddress::Ipv6(link_local_ipv6(ether_addr)), 64);
+    let (ip_addrs, gateways) = if n == 0 {
         let ip = IP.parse().expect("invalid IP address");
-        vec![
+        let ipv6 = IPV6.parse().expect("invalid IPv6 address");
+        let ip_addrs = vec![
             IpCidr::new(IP.parse().unwrap(), 8),
             IpCidr::new(ip, IP_PREFIX),
-        ]
+            link_local,
+            IpCidr::new(ipv6, IPV6_PREFIX),
+        ];
+        let gateways = vec![GATEWAY.parse().unwrap(), IPV6_GATEWAY.parse().unwrap()];
+        (ip_addrs, gateways)
+    } else {
+        let ip = IpAddress::Ipv4(Ipv4Address::new(192, 168, n as u8, 100));
+        let gateway = IpAddress::Ipv4(Ipv4Address::new(192, 168, n as u8, 1));
+        (vec![IpCidr::new(ip, IP_PREFIX), link_local], vec![gateway])
     };
 
-    log::debug!("eth0 setup_ip_addr");
-    eth0.setup_ip_addr(ip_addrs);
-    log::debug!("eth0 setup_gateway");
-    eth0.setup_gateway(gateway);
-
-    log::debug!("ETH0 INIT...");
-    ETH0.call_once(|| eth0);
+    let iface = register_interface(format!("eth{n}"), net_dev, ip_addrs);
+    log::debug!("{} setup_gateway", iface.name());
+    for gateway in gateways {
+        let route = RouteEntry {
+            dst: IpCidr::new(gateway, 0),
+            gateway: Some(gateway),
+            ifindex: iface.index(),
+            metric: DEFAULT_ROUTE_METRIC * (n as u32 + 1),
+            prefsrc: None,
+        };
+        if let Err(e) = route::add_route(route, true) {
+            log::warn!("[init_network] failed to add default route via {gateway}: {e:?}");
+        }
+    }
 }
 
 /// net poll results, used for referring udp/tcp poll state.
@@ -92,7 +194,9 @@ pub fn poll_interfaces() -> smoltcp::time::Instant {
 
 pub fn net_bench() {
     println!("net bench start!");
-    ETH0.get().unwrap().bench_test();
+    if let Some(iface) = interfaces().into_iter().find(|iface| !iface.is_loopback()) {
+        iface.bench_test();
+    }
     println!("net bench end!");
 }
 
@@ -107,5 +211,114 @@ impl IEvent for PollTimer {
 }
 
 pub fn net_device_exist() -> bool {
-    ETH0.get().is_some()
+    !INTERFACES.lock().is_empty()
+}
+
+/// Returns whether `addr` is one of the ip addresses of the network interfaces.
+pub fn has_ip_addr(addr: IpAddress) -> bool {
+    interfaces().iter().any(|iface| iface.has_ip_addr(addr))
+}
+
+/// Returns whether a socket can bind to `addr`, which is unspecified, an ip address of
+/// a network interface, or an address on the subnets of the loopback interface.
+pub fn is_local_addr(addr: IpAddress) -> bool {
+    addr.is_unspecified()
+        || interfaces().iter().any(|iface| {
+            iface.has_ip_addr(addr)
+                || iface.is_loopback()
+                    && iface
+                        .ip_addrs()
+                        .iter()
+                        .any(|cidr| cidr.contains_addr(&addr))
+        })
+}
+
+/// Returns the index of the interface a packet to `addr` arrives on, which is the
+/// interface that has the address, or the loopback interface for an address on its
+/// subnets. For other addresses, e.g., broadcast ones, it is the interface of the
+/// route to the address.
+pub fn local_addr_interface(addr: IpAddress) -> Option<u32> {
+    let ifaces = interfaces();
+    ifaces
+        .iter()
+        .find(|iface| iface.has_ip_addr(addr))
+        .or_else(|| {
+            ifaces.iter().find(|iface| {
+                iface.is_loopback()
+                    && iface
+                        .ip_addrs()
+                        .iter()
+                        .any(|cidr| cidr.contains_addr(&addr))
+            })
+        })
+        .map(|iface| iface.index())
+        .or_else(|| route::lookup(addr, None).map(|route| route.ifindex))
+}
+
+/// Information about a network interface.
+#[derive(Debug, Clone)]
+pub struct InterfaceInfo {
+    pub index: u32,
+    pub name: String,
+    /// `IFF_*` flags of the interface.
+    pub flags: u32,
+    pub mtu: usize,
+    /// Ethernet address of the interface, which is zero for the loopback interface.
+    pub hw_addr: [u8; 6],
+    pub ip_addrs: Vec<IpCidr>,
+}
+
+impl InterfaceInfo {
+    fn new(iface: &InterfaceWrapper) -> Self {
+        let hw_addr = if iface.is_loopback() {
+            [0; 6]
+        } else {
+            iface.ethernet_address().0
+        };
+        Self {
+            index: iface.index(),
+            name: String::from(iface.name()),
+            flags: iface.flags(),
+            mtu: iface.mtu(),
+            hw_addr,
+            ip_addrs: iface.ip_addrs(),
+        }
+    }
+}
+
+/// Returns information about all network interfaces, in the order of their indices.
+pub fn interface_infos() -> Vec<InterfaceInfo> {
+    interfaces().into_iter().map(InterfaceInfo::new).collect()
+}
+
+/// Returns information about the network interface of index `index`.
+pub fn interface_info(index: u32) -> Option<InterfaceInfo> {
+    interface(index).map(InterfaceInfo::new)
+}
+
+/// Returns the index of the network interface named `name`.
+pub fn interface_index(name: &str) -> Option<u32> {
+    interfaces()
+        .into_iter()
+        .find(|iface| iface.name() == name)
+        .map(|iface| iface.index())
+}
+
+/// Sets the network interface of index `index` up or down. An interface that is down
+/// is not polled, and its routes are not used.
+///
+/// # Errors
+/// Returns `ENODEV` if there is no such interface.
+pub fn set_interface_up(index: u32, up: bool) -> SysResult<()> {
+    let iface = interface(index).ok_or(SysError::ENODEV)?;
+    if iface.set_up(up) != up {
+        log::info!(
+            "[net] set link {} {}",
+            iface.name(),
+            if up { "up" } else { "down" }
+        );
+        route::sync_interfaces();
+        netlink::route::notify_link(iface);
+    }
+    Ok(())
 }
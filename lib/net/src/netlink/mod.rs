@@ -0,0 +1,373 @@
+//! Netlink sockets.
+//!
+//! Only the `NETLINK_ROUTE` protocol is supported, which dumps and updates the links,
+//! addresses and routes of the network card (see [`route`]). A request sent to the
+//! kernel is handled right away, and its replies are queued on the sending socket in
+//! datagrams of at most [`NLMSG_GOODSIZE`] bytes, so a large dump spans several of them
+//! ending in `NLMSG_DONE`. Sockets subscribed to multicast groups also receive a
+//! notification when a link, an address or a route changes.
+
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+    task::Waker,
+};
+
+use mutex::SpinNoIrqLock;
+use osfuture::{suspend_now, take_waker};
+use systype::error::{SysError, SysResult};
+
+use crate::{NetPollState, tcp::has_signal};
+
+use msg::{NLMSG_HDRLEN, NlMsgBuilder, NlMsgHdr, nlmsg_align, parse_msgs};
+
+pub mod msg;
+pub mod route;
+
+/// Routing and link information.
+pub const NETLINK_ROUTE: u32 = 0;
+
+/// Nothing, the message is ignored.
+pub const NLMSG_NOOP: u16 = 1;
+/// Error or acknowledgement of a request.
+pub const NLMSG_ERROR: u16 = 2;
+/// End of a dump.
+pub const NLMSG_DONE: u16 = 3;
+/// Types below it are reserved for control messages.
+pub const NLMSG_MIN_TYPE: u16 = 0x10;
+
+/// The message is a request.
+pub const NLM_F_REQUEST: u16 = 0x1;
+/// The message is part of a dump ended by `NLMSG_DONE`.
+pub const NLM_F_MULTI: u16 = 0x2;
+/// The request asks for an acknowledgement.
+pub const NLM_F_ACK: u16 = 0x4;
+/// The request asks to dump all objects.
+pub const NLM_F_DUMP: u16 = 0x300;
+/// A new request fails if the object already exists.
+pub const NLM_F_EXCL: u16 = 0x200;
+
+/// Option of `SOL_NETLINK` to join a multicast group.
+pub const NETLINK_ADD_MEMBERSHIP: usize = 1;
+/// Option of `SOL_NETLINK` to leave a multicast group.
+pub const NETLINK_DROP_MEMBERSHIP: usize = 2;
+
+/// The first port ID assigned to a socket bound automatically, when the preferred one
+/// is in use. Later ones count down from it, as Linux does.
+const AUTOBIND_PORTID_START: u32 = -4096i32 as u32;
+
+/// Maximum length of a datagram of replies, unless a single message is longer.
+pub const NLMSG_GOODSIZE: usize = 8192;
+
+/// Maximum number of datagrams queued on a socket. Replies and notifications to a
+/// socket with a full queue are dropped, and the next receive fails with `ENOBUFS`.
+const RX_QUEUE_MAX_LEN: usize = 256;
+
+/// Bound netlink sockets, indexed by their port IDs.
+static NETLINK_TABLE: SpinNoIrqLock<BTreeMap<u32, Weak<NetlinkSocket>>> =
+    SpinNoIrqLock::new(BTreeMap::new());
+
+/// `NetlinkSocket` is a datagram socket for communicating with the kernel.
+pub struct NetlinkSocket {
+    /// Port ID of the socket, which is 0 if it is not bound.
+    portid: AtomicU32,
+    /// Bitmask of the multicast groups the socket is subscribed to, where bit `n - 1`
+    /// stands for group `n`.
+    groups: AtomicU32,
+    /// Datagrams waiting to be received.
+    rx_queue: SpinNoIrqLock<VecDeque<Vec<u8>>>,
+    /// Waker of the task waiting for a datagram.
+    waker: SpinNoIrqLock<Option<Waker>>,
+    nonblock: AtomicBool,
+    /// Whether datagrams are dropped since the last receive.
+    overrun: AtomicBool,
+}
+
+impl NetlinkSocket {
+    /// Creates a netlink socket of `protocol`.
+    ///
+    /// # Errors
+    /// Returns `EPROTONOSUPPORT` if the protocol is not `NETLINK_ROUTE`.
+    pub fn new(protocol: u32) -> SysResult<Self> {
+        if protocol != NETLINK_ROUTE {
+            log::warn!("[NetlinkSocket::new] unsupported protocol {protocol}");
+            return Err(SysError::EPROTONOSUPPORT);
+        }
+        Ok(Self {
+            portid: AtomicU32::new(0),
+            groups: AtomicU32::new(0),
+            rx_queue: SpinNoIrqLock::new(VecDeque::new()),
+            waker: SpinNoIrqLock::new(None),
+            nonblock: AtomicBool::new(false),
+            overrun: AtomicBool::new(false),
+        })
+    }
+
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Returns the port ID of the socket, which is 0 if it is not bound.
+    pub fn portid(&self) -> u32 {
+        self.portid.load(Ordering::Relaxed)
+    }
+
+    /// Returns the bitmask of the multicast groups the socket is subscribed to.
+    pub fn groups(&self) -> u32 {
+        self.groups.load(Ordering::Relaxed)
+    }
+
+    /// Binds the socket to `portid` and subscribes it to the multicast groups in the
+    /// bitmask `groups`.
+    ///
+    /// If `portid` is 0, the socket is bound to `preferred` if it is free, or to a free
+    /// port ID chosen by the kernel otherwise. Binding a bound socket again only
+    /// changes its groups.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if the socket is bound to another port ID, or `EADDRINUSE` if
+    /// `portid` is used by another socket.
+    pub fn bind(self: &Arc<Self>, portid: u32, groups: u32, preferred: u32) -> SysResult<()> {
+        let mut table = NETLINK_TABLE.lock();
+        let bound = self.portid();
+        if bound != 0 {
+            if portid != 0 && portid != bound {
+                return Err(SysError::EINVAL);
+            }
+        } else {
+            let is_free = |table: &BTreeMap<u32, Weak<NetlinkSocket>>, id: u32| {
+                table.get(&id).is_none_or(|sock| sock.strong_count() == 0)
+            };
+            let portid = if portid != 0 {
+                if !is_free(&table, portid) {
+                    return Err(SysError::EADDRINUSE);
+                }
+                portid
+            } else if preferred != 0 && is_free(&table, preferred) {
+                preferred
+            } else {
+                (0..)
+                    .map(|i: u32| AUTOBIND_PORTID_START - i)
+                    .find(|&id| is_free(&table, id))
+                    .unwrap()
+            };
+            table.insert(portid, Arc::downgrade(self));
+            self.portid.store(portid, Ordering::Relaxed);
+            log::info!("[NetlinkSocket::bind] bound to port ID {portid:#x}");
+        }
+        self.groups.store(groups, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Subscribes the socket to the multicast `group`, or unsubscribes it if `join` is
+    /// false.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if `group` is 0 or out of the supported range.
+    pub fn set_membership(&self, group: u32, join: bool) -> SysResult<()> {
+        if group == 0 || group > u32::BITS {
+            return Err(SysError::EINVAL);
+        }
+        let bit = 1 << (group - 1);
+        if join {
+            self.groups.fetch_or(bit, Ordering::Relaxed);
+        } else {
+            self.groups.fetch_and(!bit, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Sends the netlink messages in `buf` to the kernel, which handles the requests
+    /// among them and queues the replies on the socket.
+    ///
+    /// `net_admin` tells whether the sender is allowed to change the network
+    /// configuration. The socket is bound automatically to `preferred` or a free port
+    /// ID if it is not bound.
+    pub fn send(self: &Arc<Self>, buf: &[u8], net_admin: bool, preferred: u32) -> SysResult<usize> {
+        if self.portid() == 0 {
+            self.bind(0, self.groups(), preferred)?;
+        }
+        let portid = self.portid();
+
+        let mut replies = Vec::new();
+        for (hdr, payload) in parse_msgs(buf)? {
+            if hdr.flags & NLM_F_REQUEST == 0 || hdr.ty < NLMSG_MIN_TYPE {
+                continue;
+            }
+            match route::handle_request(&hdr, payload, portid, net_admin) {
+                Ok(reply) => {
+                    replies.extend(reply);
+                    if hdr.flags & NLM_F_ACK != 0 {
+                        error_msg(&hdr, portid, 0, &mut replies);
+                    }
+                }
+                Err(e) => {
+                    log::info!("[NetlinkSocket::send] request {} failed: {e:?}", hdr.ty);
+                    error_msg(&hdr, portid, -e.code(), &mut replies);
+                }
+            }
+        }
+
+        for datagram in split_datagrams(&replies) {
+            self.push(datagram.to_vec());
+        }
+        Ok(buf.len())
+    }
+
+    /// Receives a datagram into `buf`, and returns the number of bytes copied. The rest
+    /// of a datagram longer than `buf` is discarded.
+    ///
+    /// It blocks until a datagram arrives, unless the socket is nonblocking.
+    pub async fn recv(&self, buf: &mut [u8]) -> SysResult<usize> {
+        let len = self.recv_msg(buf, false).await?;
+        Ok(len.min(buf.len()))
+    }
+
+    /// Receives a datagram into `buf`, and returns the length of the datagram, which
+    /// is longer than the bytes copied if the datagram is truncated. The datagram is
+    /// left in the queue if `peek` is true, and the rest of it is discarded otherwise.
+    ///
+    /// It blocks until a datagram arrives, unless the socket is nonblocking.
+    ///
+    /// # Errors
+    /// Returns `ENOBUFS` once if datagrams are dropped since the last receive.
+    pub async fn recv_msg(&self, buf: &mut [u8], peek: bool) -> SysResult<usize> {
+        loop {
+            {
+                let mut queue = self.rx_queue.lock();
+                if self.overrun.swap(false, Ordering::Relaxed) {
+                    return Err(SysError::ENOBUFS);
+                }
+                if let Some(datagram) = queue.front() {
+                    let len = buf.len().min(datagram.len());
+                    buf[..len].copy_from_slice(&datagram[..len]);
+                    let len = datagram.len();
+                    if !peek {
+                        queue.pop_front();
+                    }
+                    return Ok(len);
+                }
+            }
+            if self.is_nonblocking() {
+                return Err(SysError::EAGAIN);
+            }
+            let waker = take_waker().await;
+            {
+                let queue = self.rx_queue.lock();
+                if !queue.is_empty() || self.overrun.load(Ordering::Relaxed) {
+                    continue;
+                }
+                *self.waker.lock() = Some(waker);
+            }
+            suspend_now().await;
+            if has_signal() {
+                log::warn!("[NetlinkSocket::recv] has signal");
+                return Err(SysError::EINTR);
+            }
+        }
+    }
+
+    /// Checks whether the socket is readable, and registers the waker of the polling
+    /// task if it is not. A netlink socket is always writable.
+    pub async fn poll(&self) -> NetPollState {
+        let waker = take_waker().await;
+        let queue = self.rx_queue.lock();
+        let readable = !queue.is_empty() || self.overrun.load(Ordering::Relaxed);
+        if !readable {
+            *self.waker.lock() = Some(waker);
+        }
+        NetPollState {
+            readable,
+            writable: true,
+            hangup: false,
+        }
+    }
+
+    /// Queues a datagram on the socket and wakes the task waiting for it, unless the
+    /// queue is full, in which case the datagram is dropped.
+    fn push(&self, datagram: Vec<u8>) {
+        {
+            let mut queue = self.rx_queue.lock();
+            if queue.len() >= RX_QUEUE_MAX_LEN {
+                self.overrun.store(true, Ordering::Relaxed);
+            } else {
+                queue.push_back(datagram);
+            }
+        }
+        self.wake();
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        let portid = self.portid();
+        if portid != 0 {
+            let mut table = NETLINK_TABLE.lock();
+            if table
+                .get(&portid)
+                .is_some_and(|sock| sock.strong_count() == 0)
+            {
+                table.remove(&portid);
+            }
+        }
+    }
+}
+
+/// Appends to `buf` an `NLMSG_ERROR` message answering the request of `hdr`, where
+/// `error` is 0 for an acknowledgement or a negative error code.
+fn error_msg(hdr: &NlMsgHdr, portid: u32, error: i32, buf: &mut Vec<u8>) {
+    NlMsgBuilder::new(NLMSG_ERROR, 0, hdr.seq, portid)
+        .push(&error.to_ne_bytes())
+        .push(&hdr.to_bytes())
+        .finish_into(buf);
+}
+
+/// Sends the notification `msg` to the sockets subscribed to the multicast `group`.
+pub(crate) fn notify(group: u32, msg: &[u8]) {
+    let bit = 1 << (group - 1);
+    let sockets: Vec<_> = NETLINK_TABLE
+        .lock()
+        .values()
+        .filter_map(Weak::upgrade)
+        .filter(|sock| sock.groups() & bit != 0)
+        .collect();
+    for sock in sockets {
+        sock.push(msg.to_vec());
+    }
+}
+
+/// Splits the netlink messages in `buf` into datagrams of at most [`NLMSG_GOODSIZE`]
+/// bytes, where a message longer than that makes a datagram of its own.
+fn split_datagrams(mut buf: &[u8]) -> Vec<&[u8]> {
+    let mut datagrams = Vec::new();
+    while !buf.is_empty() {
+        let mut len = 0;
+        while let Some(hdr) = NlMsgHdr::parse(&buf[len..]) {
+            let msg_len = nlmsg_align((hdr.len as usize).max(NLMSG_HDRLEN)).min(buf.len() - len);
+            if len > 0 && len + msg_len > NLMSG_GOODSIZE {
+                break;
+            }
+            len += msg_len;
+        }
+        if len == 0 {
+            break;
+        }
+        datagrams.push(&buf[..len]);
+        buf = &buf[len..];
+    }
+    datagrams
+}
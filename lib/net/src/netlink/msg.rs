@@ -0,0 +1,187 @@
+//! Netlink message format.
+//!
+//! A netlink message is a [`NlMsgHdr`] followed by its payload, and the payload of a
+//! routing message is a fixed family header followed by attributes. Each attribute is
+//! a 4-byte header of its length and type followed by its data. Messages and
+//! attributes are aligned to 4 bytes, and all integers are in host byte order.
+
+use alloc::{string::String, vec::Vec};
+
+use systype::error::{SysError, SysResult};
+
+/// Alignment of messages and attributes.
+const NLMSG_ALIGNTO: usize = 4;
+
+/// Length of [`NlMsgHdr`].
+pub const NLMSG_HDRLEN: usize = 16;
+
+/// Length of the header of an attribute.
+const NLA_HDRLEN: usize = 4;
+
+/// Rounds `len` up to the alignment of messages and attributes.
+pub const fn nlmsg_align(len: usize) -> usize {
+    (len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1)
+}
+
+/// Header of a netlink message.
+#[derive(Debug, Clone, Copy)]
+pub struct NlMsgHdr {
+    /// Length of the message including the header.
+    pub len: u32,
+    /// Type of the message.
+    pub ty: u16,
+    /// `NLM_F_*` flags.
+    pub flags: u16,
+    /// Sequence number chosen by the sender.
+    pub seq: u32,
+    /// Port ID of the sender, which is 0 for the kernel.
+    pub pid: u32,
+}
+
+impl NlMsgHdr {
+    /// Parses the header at the beginning of `buf`.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        if buf.len() < NLMSG_HDRLEN {
+            return None;
+        }
+        Some(Self {
+            len: u32::from_ne_bytes(buf[0..4].try_into().unwrap()),
+            ty: u16::from_ne_bytes(buf[4..6].try_into().unwrap()),
+            flags: u16::from_ne_bytes(buf[6..8].try_into().unwrap()),
+            seq: u32::from_ne_bytes(buf[8..12].try_into().unwrap()),
+            pid: u32::from_ne_bytes(buf[12..16].try_into().unwrap()),
+        })
+    }
+
+    /// Returns the header in bytes.
+    pub fn to_bytes(&self) -> [u8; NLMSG_HDRLEN] {
+        let mut bytes = [0; NLMSG_HDRLEN];
+        bytes[0..4].copy_from_slice(&self.len.to_ne_bytes());
+        bytes[4..6].copy_from_slice(&self.ty.to_ne_bytes());
+        bytes[6..8].copy_from_slice(&self.flags.to_ne_bytes());
+        bytes[8..12].copy_from_slice(&self.seq.to_ne_bytes());
+        bytes[12..16].copy_from_slice(&self.pid.to_ne_bytes());
+        bytes
+    }
+}
+
+/// Splits `buf` into netlink messages, returning the header and the payload of each.
+///
+/// # Errors
+/// Returns `EINVAL` if a message is truncated.
+pub fn parse_msgs(mut buf: &[u8]) -> SysResult<Vec<(NlMsgHdr, &[u8])>> {
+    let mut msgs = Vec::new();
+    while buf.len() >= NLMSG_HDRLEN {
+        let hdr = NlMsgHdr::parse(buf).unwrap();
+        let len = hdr.len as usize;
+        if len < NLMSG_HDRLEN || len > buf.len() {
+            return Err(SysError::EINVAL);
+        }
+        msgs.push((hdr, &buf[NLMSG_HDRLEN..len]));
+        buf = &buf[nlmsg_align(len).min(buf.len())..];
+    }
+    Ok(msgs)
+}
+
+/// Splits `buf` into attributes, returning the type and the data of each.
+///
+/// Attributes with invalid lengths end the parsing.
+pub fn parse_attrs(mut buf: &[u8]) -> Vec<(u16, &[u8])> {
+    let mut attrs = Vec::new();
+    while buf.len() >= NLA_HDRLEN {
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]);
+        if len < NLA_HDRLEN || len > buf.len() {
+            break;
+        }
+        attrs.push((ty, &buf[NLA_HDRLEN..len]));
+        buf = &buf[nlmsg_align(len).min(buf.len())..];
+    }
+    attrs
+}
+
+/// Returns the data of the attribute of type `ty` in `attrs`.
+pub fn find_attr<'a>(attrs: &[(u16, &'a [u8])], ty: u16) -> Option<&'a [u8]> {
+    attrs.iter().find(|(t, _)| *t == ty).map(|(_, data)| *data)
+}
+
+/// Reads a `u32` from the data of an attribute.
+pub fn attr_u32(data: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(data.get(..4)?.try_into().unwrap()))
+}
+
+/// Reads a string from the data of an attribute, which may be terminated by a NUL.
+pub fn attr_str(data: &[u8]) -> String {
+    let len = data.iter().position(|&c| c == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..len]).into_owned()
+}
+
+/// Builder of a netlink message.
+pub struct NlMsgBuilder {
+    hdr: NlMsgHdr,
+    payload: Vec<u8>,
+}
+
+impl NlMsgBuilder {
+    /// Creates a message of type `ty` with `flags`, answering a request with sequence
+    /// number `seq` to the socket of port ID `pid`.
+    pub fn new(ty: u16, flags: u16, seq: u32, pid: u32) -> Self {
+        Self {
+            hdr: NlMsgHdr {
+                len: 0,
+                ty,
+                flags,
+                seq,
+                pid,
+            },
+            payload: Vec::new(),
+        }
+    }
+
+    /// Appends `data` to the payload, padded to the alignment.
+    pub fn push(&mut self, data: &[u8]) -> &mut Self {
+        self.payload.extend_from_slice(data);
+        self.payload.resize(nlmsg_align(self.payload.len()), 0);
+        self
+    }
+
+    /// Appends an attribute of type `ty` with `data`.
+    pub fn attr(&mut self, ty: u16, data: &[u8]) -> &mut Self {
+        let len = (NLA_HDRLEN + data.len()) as u16;
+        self.payload.extend_from_slice(&len.to_ne_bytes());
+        self.payload.extend_from_slice(&ty.to_ne_bytes());
+        self.push(data)
+    }
+
+    /// Appends an attribute of type `ty` with a `u32`.
+    pub fn attr_u32(&mut self, ty: u16, value: u32) -> &mut Self {
+        self.attr(ty, &value.to_ne_bytes())
+    }
+
+    /// Appends an attribute of type `ty` with a `u8`.
+    pub fn attr_u8(&mut self, ty: u16, value: u8) -> &mut Self {
+        self.attr(ty, &[value])
+    }
+
+    /// Appends an attribute of type `ty` with a NUL-terminated string.
+    pub fn attr_str(&mut self, ty: u16, value: &str) -> &mut Self {
+        let mut data = Vec::with_capacity(value.len() + 1);
+        data.extend_from_slice(value.as_bytes());
+        data.push(0);
+        self.attr(ty, &data)
+    }
+
+    /// Appends the message to `buf`.
+    pub fn finish_into(&mut self, buf: &mut Vec<u8>) {
+        self.hdr.len = (NLMSG_HDRLEN + self.payload.len()) as u32;
+        buf.extend_from_slice(&self.hdr.to_bytes());
+        buf.extend_from_slice(&self.payload);
+    }
+
+    /// Returns the message in bytes.
+    pub fn finish(&mut self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.finish_into(&mut buf);
+        buf
+    }
+}
@@ -0,0 +1,555 @@
+//! Requests of the `NETLINK_ROUTE` protocol.
+//!
+//! Each network interface is presented as a link of its interface index. The
+//! addresses of a link are those of its `smoltcp` interface, and the routes are those
+//! of the routing table (see [`crate::route`]), so an address or a route added here
+//! is used by the protocol stack right away.
+
+use alloc::vec::Vec;
+
+use smoltcp::wire::{IpAddress, IpCidr};
+use systype::error::{SysError, SysResult};
+
+use super::{
+    NLM_F_DUMP, NLM_F_EXCL, NLM_F_MULTI, NLMSG_DONE,
+    msg::{NlMsgBuilder, NlMsgHdr, attr_str, attr_u32, find_attr, parse_attrs},
+    notify,
+};
+use crate::{
+    interface,
+    interface::{IFF_UP, InterfaceWrapper},
+    interfaces,
+    route::{self, RouteEntry, subnet},
+    set_interface_up,
+};
+
+pub const RTM_NEWLINK: u16 = 16;
+pub const RTM_DELLINK: u16 = 17;
+pub const RTM_GETLINK: u16 = 18;
+pub const RTM_SETLINK: u16 = 19;
+pub const RTM_NEWADDR: u16 = 20;
+pub const RTM_DELADDR: u16 = 21;
+pub const RTM_GETADDR: u16 = 22;
+pub const RTM_NEWROUTE: u16 = 24;
+pub const RTM_DELROUTE: u16 = 25;
+pub const RTM_GETROUTE: u16 = 26;
+
+/// Multicast group of link changes.
+pub const RTNLGRP_LINK: u32 = 1;
+/// Multicast group of IPv4 address changes.
+pub const RTNLGRP_IPV4_IFADDR: u32 = 5;
+/// Multicast group of IPv4 route changes.
+pub const RTNLGRP_IPV4_ROUTE: u32 = 7;
+/// Multicast group of IPv6 address changes.
+pub const RTNLGRP_IPV6_IFADDR: u32 = 9;
+/// Multicast group of IPv6 route changes.
+pub const RTNLGRP_IPV6_ROUTE: u32 = 11;
+
+const AF_UNSPEC: u8 = 0;
+const AF_INET: u8 = 2;
+const AF_INET6: u8 = 10;
+
+pub(crate) const ARPHRD_ETHER: u16 = 1;
+pub(crate) const ARPHRD_LOOPBACK: u16 = 772;
+
+const IFLA_ADDRESS: u16 = 1;
+const IFLA_BROADCAST: u16 = 2;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_MTU: u16 = 4;
+const IFLA_TXQLEN: u16 = 13;
+const IFLA_OPERSTATE: u16 = 16;
+
+/// `IFLA_OPERSTATE` of a link that is down.
+const IF_OPER_DOWN: u8 = 2;
+/// `IFLA_OPERSTATE` of a link that is up.
+const IF_OPER_UP: u8 = 6;
+
+const IFA_ADDRESS: u16 = 1;
+const IFA_LOCAL: u16 = 2;
+const IFA_LABEL: u16 = 3;
+const IFA_BROADCAST: u16 = 4;
+const IFA_FLAGS: u16 = 8;
+
+const IFA_F_PERMANENT: u32 = 0x80;
+
+const RT_SCOPE_UNIVERSE: u8 = 0;
+const RT_SCOPE_LINK: u8 = 253;
+const RT_SCOPE_HOST: u8 = 254;
+
+const RTA_DST: u16 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_GATEWAY: u16 = 5;
+const RTA_PRIORITY: u16 = 6;
+const RTA_PREFSRC: u16 = 7;
+const RTA_TABLE: u16 = 15;
+
+const RT_TABLE_MAIN: u8 = 254;
+const RTPROT_KERNEL: u8 = 2;
+const RTPROT_BOOT: u8 = 3;
+const RTN_UNICAST: u8 = 1;
+
+/// Length of `struct ifinfomsg`.
+const IFINFOMSG_LEN: usize = 16;
+/// Length of `struct ifaddrmsg`.
+const IFADDRMSG_LEN: usize = 8;
+/// Length of `struct rtmsg`.
+const RTMSG_LEN: usize = 12;
+
+/// Metric of a route added without `RTA_PRIORITY`, as Linux does for ipv4 routes.
+const DEFAULT_METRIC: u32 = 0;
+
+/// Handles the routing request of `hdr` with `payload` from the socket of port ID
+/// `portid`, and returns the replies.
+///
+/// `net_admin` tells whether the sender is allowed to change the network
+/// configuration.
+///
+/// # Errors
+/// Returns `EPERM` if a request that changes the configuration is not allowed,
+/// `EOPNOTSUPP` if the request is not supported, or the error of the request.
+pub(super) fn handle_request(
+    hdr: &NlMsgHdr,
+    payload: &[u8],
+    portid: u32,
+    net_admin: bool,
+) -> SysResult<Vec<u8>> {
+    if interfaces().is_empty() {
+        return Err(SysError::ENODEV);
+    }
+    let is_dump = hdr.flags & NLM_F_DUMP == NLM_F_DUMP;
+    let modifies = matches!(
+        hdr.ty,
+        RTM_NEWLINK | RTM_SETLINK | RTM_NEWADDR | RTM_DELADDR | RTM_NEWROUTE | RTM_DELROUTE
+    );
+    if modifies && !net_admin {
+        return Err(SysError::EPERM);
+    }
+
+    let mut reply = Vec::new();
+    match hdr.ty {
+        RTM_GETLINK => get_link(hdr, payload, portid, is_dump, &mut reply)?,
+        RTM_NEWLINK | RTM_SETLINK => set_link(payload)?,
+        RTM_GETADDR => {
+            let family = payload.first().copied().unwrap_or(AF_UNSPEC);
+            for iface in interfaces() {
+                for cidr in iface.ip_addrs() {
+                    if family_matches(family, cidr) {
+                        addr_msg(iface, RTM_NEWADDR, NLM_F_MULTI, hdr.seq, portid, cidr)
+                            .finish_into(&mut reply);
+                    }
+                }
+            }
+            done_msg(hdr, portid, &mut reply);
+        }
+        RTM_NEWADDR => new_addr(hdr, payload)?,
+        RTM_DELADDR => del_addr(payload)?,
+        RTM_GETROUTE => get_route(hdr, payload, portid, is_dump, &mut reply)?,
+        RTM_NEWROUTE => new_route(hdr, payload)?,
+        RTM_DELROUTE => del_route(payload)?,
+        ty => {
+            log::warn!("[netlink] unsupported request type {ty}");
+            return Err(SysError::EOPNOTSUPP);
+        }
+    }
+    Ok(reply)
+}
+
+/// Appends to `buf` the `NLMSG_DONE` message ending a dump.
+fn done_msg(hdr: &NlMsgHdr, portid: u32, buf: &mut Vec<u8>) {
+    NlMsgBuilder::new(NLMSG_DONE, NLM_F_MULTI, hdr.seq, portid)
+        .push(&0i32.to_ne_bytes())
+        .finish_into(buf);
+}
+
+fn family_matches(family: u8, cidr: IpCidr) -> bool {
+    match family {
+        AF_INET => matches!(cidr, IpCidr::Ipv4(_)),
+        AF_INET6 => matches!(cidr, IpCidr::Ipv6(_)),
+        _ => true,
+    }
+}
+
+fn family_of(addr: IpAddress) -> u8 {
+    match addr {
+        IpAddress::Ipv4(_) => AF_INET,
+        IpAddress::Ipv6(_) => AF_INET6,
+    }
+}
+
+fn is_loopback(addr: IpAddress) -> bool {
+    match addr {
+        IpAddress::Ipv4(v4) => v4.is_loopback(),
+        IpAddress::Ipv6(v6) => v6.is_loopback(),
+    }
+}
+
+fn addr_bytes(addr: IpAddress) -> Vec<u8> {
+    match addr {
+        IpAddress::Ipv4(v4) => v4.octets().to_vec(),
+        IpAddress::Ipv6(v6) => v6.octets().to_vec(),
+    }
+}
+
+/// Parses an address of `family` from the data of an attribute.
+fn parse_addr(family: u8, data: &[u8]) -> SysResult<IpAddress> {
+    match family {
+        AF_INET => {
+            let octets: [u8; 4] = data.try_into().map_err(|_| SysError::EINVAL)?;
+            Ok(IpAddress::Ipv4(octets.into()))
+        }
+        AF_INET6 => {
+            let octets: [u8; 16] = data.try_into().map_err(|_| SysError::EINVAL)?;
+            Ok(IpAddress::Ipv6(octets.into()))
+        }
+        _ => Err(SysError::EAFNOSUPPORT),
+    }
+}
+
+/// Creates the cidr of `addr` with `prefix_len`.
+///
+/// # Errors
+/// Returns `EINVAL` if `prefix_len` is too long for the address.
+fn new_cidr(addr: IpAddress, prefix_len: u8) -> SysResult<IpCidr> {
+    let max_len = match addr {
+        IpAddress::Ipv4(_) => 32,
+        IpAddress::Ipv6(_) => 128,
+    };
+    if prefix_len > max_len {
+        return Err(SysError::EINVAL);
+    }
+    Ok(IpCidr::new(addr, prefix_len))
+}
+
+/// Creates a link message of type `ty` describing the interface `iface`.
+fn link_msg(iface: &InterfaceWrapper, ty: u16, flags: u16, seq: u32, portid: u32) -> NlMsgBuilder {
+    let (link_type, hw_addr, broadcast) = if iface.is_loopback() {
+        (ARPHRD_LOOPBACK, [0; 6], [0; 6])
+    } else {
+        (ARPHRD_ETHER, iface.ethernet_address().0, [0xff; 6])
+    };
+    let mut info = [0; IFINFOMSG_LEN];
+    info[0] = AF_UNSPEC;
+    info[2..4].copy_from_slice(&link_type.to_ne_bytes());
+    info[4..8].copy_from_slice(&iface.index().to_ne_bytes());
+    info[8..12].copy_from_slice(&iface.flags().to_ne_bytes());
+    info[12..16].copy_from_slice(&u32::MAX.to_ne_bytes());
+
+    let operstate = if iface.is_up() {
+        IF_OPER_UP
+    } else {
+        IF_OPER_DOWN
+    };
+    let mut msg = NlMsgBuilder::new(ty, flags, seq, portid);
+    msg.push(&info)
+        .attr_str(IFLA_IFNAME, iface.name())
+        .attr_u32(IFLA_MTU, iface.mtu() as u32)
+        .attr_u32(IFLA_TXQLEN, 1000)
+        .attr_u8(IFLA_OPERSTATE, operstate)
+        .attr(IFLA_ADDRESS, &hw_addr)
+        .attr(IFLA_BROADCAST, &broadcast);
+    msg
+}
+
+/// Sends the notification of a change of the link of `iface`.
+pub(crate) fn notify_link(iface: &InterfaceWrapper) {
+    notify(
+        RTNLGRP_LINK,
+        &link_msg(iface, RTM_NEWLINK, 0, 0, 0).finish(),
+    );
+}
+
+/// Returns the interface of index `index`.
+///
+/// # Errors
+/// Returns `ENODEV` if there is no such interface.
+fn link(index: u32) -> SysResult<&'static InterfaceWrapper> {
+    interface(index).ok_or(SysError::ENODEV)
+}
+
+fn get_link(
+    hdr: &NlMsgHdr,
+    payload: &[u8],
+    portid: u32,
+    is_dump: bool,
+    reply: &mut Vec<u8>,
+) -> SysResult<()> {
+    if is_dump {
+        for iface in interfaces() {
+            link_msg(iface, RTM_NEWLINK, NLM_F_MULTI, hdr.seq, portid).finish_into(reply);
+        }
+        done_msg(hdr, portid, reply);
+        return Ok(());
+    }
+
+    let index = payload
+        .get(4..8)
+        .map(|index| u32::from_ne_bytes(index.try_into().unwrap()))
+        .unwrap_or(0);
+    let attrs = parse_attrs(payload.get(IFINFOMSG_LEN..).unwrap_or(&[]));
+    let iface = match find_attr(&attrs, IFLA_IFNAME) {
+        _ if index != 0 => interface(index),
+        Some(name) => {
+            let name = attr_str(name);
+            interfaces().into_iter().find(|iface| iface.name() == name)
+        }
+        None => None,
+    };
+    let iface = iface.ok_or(SysError::ENODEV)?;
+    link_msg(iface, RTM_NEWLINK, 0, hdr.seq, portid).finish_into(reply);
+    Ok(())
+}
+
+/// Sets a link up or down, following the `IFF_UP` bit of the flags and the change
+/// mask in `struct ifinfomsg`.
+fn set_link(payload: &[u8]) -> SysResult<()> {
+    if payload.len() < IFINFOMSG_LEN {
+        return Err(SysError::EINVAL);
+    }
+    let index = u32::from_ne_bytes(payload[4..8].try_into().unwrap());
+    link(index)?;
+    let flags = u32::from_ne_bytes(payload[8..12].try_into().unwrap());
+    let change = u32::from_ne_bytes(payload[12..16].try_into().unwrap());
+    if change & IFF_UP != 0 {
+        set_interface_up(index, flags & IFF_UP != 0)?;
+    }
+    Ok(())
+}
+
+/// Creates an address message of type `ty` describing `cidr`.
+fn addr_msg(
+    iface: &InterfaceWrapper,
+    ty: u16,
+    flags: u16,
+    seq: u32,
+    portid: u32,
+    cidr: IpCidr,
+) -> NlMsgBuilder {
+    let addr = cidr.address();
+    let scope = match addr {
+        _ if is_loopback(addr) => RT_SCOPE_HOST,
+        IpAddress::Ipv6(v6) if v6.is_unicast_link_local() => RT_SCOPE_LINK,
+        _ => RT_SCOPE_UNIVERSE,
+    };
+    let mut info = [0; IFADDRMSG_LEN];
+    info[0] = family_of(addr);
+    info[1] = cidr.prefix_len();
+    info[2] = IFA_F_PERMANENT as u8;
+    info[3] = scope;
+    info[4..8].copy_from_slice(&iface.index().to_ne_bytes());
+
+    let mut msg = NlMsgBuilder::new(ty, flags, seq, portid);
+    msg.push(&info)
+        .attr(IFA_ADDRESS, &addr_bytes(addr))
+        .attr_u32(IFA_FLAGS, IFA_F_PERMANENT);
+    if let IpCidr::Ipv4(v4) = cidr {
+        msg.attr(IFA_LOCAL, &addr_bytes(addr))
+            .attr_str(IFA_LABEL, iface.name());
+        if let Some(broadcast) = v4.broadcast() {
+            msg.attr(IFA_BROADCAST, &broadcast.octets());
+        }
+    }
+    msg
+}
+
+/// Parses the interface and the address of an address message, preferring
+/// `IFA_LOCAL` to `IFA_ADDRESS` as Linux does.
+fn parse_addr_msg(payload: &[u8]) -> SysResult<(&'static InterfaceWrapper, IpCidr)> {
+    if payload.len() < IFADDRMSG_LEN {
+        return Err(SysError::EINVAL);
+    }
+    let iface = link(u32::from_ne_bytes(payload[4..8].try_into().unwrap()))?;
+    let attrs = parse_attrs(&payload[IFADDRMSG_LEN..]);
+    let data = find_attr(&attrs, IFA_LOCAL)
+        .or_else(|| find_attr(&attrs, IFA_ADDRESS))
+        .ok_or(SysError::EINVAL)?;
+    Ok((iface, new_cidr(parse_addr(payload[0], data)?, payload[1])?))
+}
+
+fn addr_group(cidr: IpCidr) -> u32 {
+    match cidr {
+        IpCidr::Ipv4(_) => RTNLGRP_IPV4_IFADDR,
+        IpCidr::Ipv6(_) => RTNLGRP_IPV6_IFADDR,
+    }
+}
+
+fn new_addr(hdr: &NlMsgHdr, payload: &[u8]) -> SysResult<()> {
+    let (iface, cidr) = parse_addr_msg(payload)?;
+    if iface.ip_addrs().contains(&cidr) {
+        if hdr.flags & NLM_F_EXCL != 0 {
+            return Err(SysError::EEXIST);
+        }
+        return Ok(());
+    }
+    if !iface.add_ip_addr(cidr) {
+        return Err(SysError::ENOSPC);
+    }
+    route::sync_interfaces();
+    log::info!("[netlink] add address {cidr} to {}", iface.name());
+    let msg = addr_msg(iface, RTM_NEWADDR, 0, 0, 0, cidr).finish();
+    notify(addr_group(cidr), &msg);
+    Ok(())
+}
+
+fn del_addr(payload: &[u8]) -> SysResult<()> {
+    let (iface, cidr) = parse_addr_msg(payload)?;
+    if !iface.remove_ip_addr(cidr) {
+        return Err(SysError::EADDRNOTAVAIL);
+    }
+    route::sync_interfaces();
+    log::info!("[netlink] remove address {cidr} from {}", iface.name());
+    let msg = addr_msg(iface, RTM_DELADDR, 0, 0, 0, cidr).finish();
+    notify(addr_group(cidr), &msg);
+    Ok(())
+}
+
+/// Creates a route message of type `ty` describing `route`. A connected route is
+/// reported with the kernel as its origin and a gateway route with the boot.
+fn route_msg(ty: u16, flags: u16, seq: u32, portid: u32, route: &RouteEntry) -> NlMsgBuilder {
+    let (protocol, scope) = match route.gateway {
+        Some(_) => (RTPROT_BOOT, RT_SCOPE_UNIVERSE),
+        None if is_loopback(route.dst.address()) => (RTPROT_KERNEL, RT_SCOPE_HOST),
+        None => (RTPROT_KERNEL, RT_SCOPE_LINK),
+    };
+    let mut info = [0; RTMSG_LEN];
+    info[0] = family_of(route.dst.address());
+    info[1] = route.dst.prefix_len();
+    info[4] = RT_TABLE_MAIN;
+    info[5] = protocol;
+    info[6] = scope;
+    info[7] = RTN_UNICAST;
+
+    let mut msg = NlMsgBuilder::new(ty, flags, seq, portid);
+    msg.push(&info).attr_u32(RTA_TABLE, RT_TABLE_MAIN as u32);
+    if route.dst.prefix_len() != 0 {
+        msg.attr(RTA_DST, &addr_bytes(route.dst.address()));
+    }
+    if let Some(gateway) = route.gateway {
+        msg.attr(RTA_GATEWAY, &addr_bytes(gateway))
+            .attr_u32(RTA_PRIORITY, route.metric);
+    }
+    if let Some(prefsrc) = route.prefsrc {
+        msg.attr(RTA_PREFSRC, &addr_bytes(prefsrc));
+    }
+    msg.attr_u32(RTA_OIF, route.ifindex);
+    msg
+}
+
+fn get_route(
+    hdr: &NlMsgHdr,
+    payload: &[u8],
+    portid: u32,
+    is_dump: bool,
+    reply: &mut Vec<u8>,
+) -> SysResult<()> {
+    let family = payload.first().copied().unwrap_or(AF_UNSPEC);
+    if is_dump {
+        for route in route::routes()
+            .iter()
+            .filter(|r| family_matches(family, r.dst))
+        {
+            route_msg(RTM_NEWROUTE, NLM_F_MULTI, hdr.seq, portid, route).finish_into(reply);
+        }
+        done_msg(hdr, portid, reply);
+        return Ok(());
+    }
+
+    // Looks up the route to the destination out of the given interface, if any.
+    if payload.len() < RTMSG_LEN {
+        return Err(SysError::EINVAL);
+    }
+    let attrs = parse_attrs(&payload[RTMSG_LEN..]);
+    let dst = parse_addr(family, find_attr(&attrs, RTA_DST).ok_or(SysError::EINVAL)?)?;
+    let oif = find_attr(&attrs, RTA_OIF).and_then(attr_u32);
+    let route = route::lookup(dst, oif).ok_or(SysError::ENETUNREACH)?;
+    let prefix_len = if family == AF_INET { 32 } else { 128 };
+    let found = RouteEntry {
+        dst: IpCidr::new(dst, prefix_len),
+        prefsrc: route::source_addr(&route, dst),
+        ..route
+    };
+    route_msg(RTM_NEWROUTE, 0, hdr.seq, portid, &found).finish_into(reply);
+    Ok(())
+}
+
+/// Parses a route message into a route, whose interface index is 0 if it is not
+/// given.
+fn parse_route_msg(payload: &[u8]) -> SysResult<RouteEntry> {
+    if payload.len() < RTMSG_LEN {
+        return Err(SysError::EINVAL);
+    }
+    let family = payload[0];
+    let attrs = parse_attrs(&payload[RTMSG_LEN..]);
+    let ifindex = find_attr(&attrs, RTA_OIF).and_then(attr_u32).unwrap_or(0);
+    if ifindex != 0 {
+        link(ifindex)?;
+    }
+    let dst = match find_attr(&attrs, RTA_DST) {
+        Some(data) => parse_addr(family, data)?,
+        None => match family {
+            AF_INET => IpAddress::Ipv4([0; 4].into()),
+            AF_INET6 => IpAddress::Ipv6([0; 16].into()),
+            _ => return Err(SysError::EAFNOSUPPORT),
+        },
+    };
+    let parse = |ty| {
+        find_attr(&attrs, ty)
+            .map(|data| parse_addr(family, data))
+            .transpose()
+    };
+    Ok(RouteEntry {
+        dst: subnet(new_cidr(dst, payload[1])?),
+        gateway: parse(RTA_GATEWAY)?,
+        ifindex,
+        metric: find_attr(&attrs, RTA_PRIORITY)
+            .and_then(attr_u32)
+            .unwrap_or(DEFAULT_METRIC),
+        prefsrc: parse(RTA_PREFSRC)?,
+    })
+}
+
+fn route_group(cidr: IpCidr) -> u32 {
+    match cidr {
+        IpCidr::Ipv4(_) => RTNLGRP_IPV4_ROUTE,
+        IpCidr::Ipv6(_) => RTNLGRP_IPV6_ROUTE,
+    }
+}
+
+/// Adds a gateway route. Connected routes follow the addresses of the interfaces, so
+/// they can't be added.
+fn new_route(hdr: &NlMsgHdr, payload: &[u8]) -> SysResult<()> {
+    let route = parse_route_msg(payload)?;
+    if route.gateway.is_none() {
+        log::warn!(
+            "[netlink] route to {} without gateway is not supported",
+            route.dst
+        );
+        return Err(SysError::EOPNOTSUPP);
+    }
+    let route = route::add_route(route, hdr.flags & NLM_F_EXCL != 0)?;
+    notify(
+        route_group(route.dst),
+        &route_msg(RTM_NEWROUTE, 0, 0, 0, &route).finish(),
+    );
+    Ok(())
+}
+
+/// Removes a gateway route, matching the interface, the metric and the gateway only
+/// if they are given.
+fn del_route(payload: &[u8]) -> SysResult<()> {
+    if payload.len() < RTMSG_LEN {
+        return Err(SysError::EINVAL);
+    }
+    let attrs = parse_attrs(&payload[RTMSG_LEN..]);
+    let given = parse_route_msg(payload)?;
+    let route = route::remove_route(
+        given.dst,
+        (given.ifindex != 0).then_some(given.ifindex),
+        find_attr(&attrs, RTA_PRIORITY).and(Some(given.metric)),
+        given.gateway,
+    )?;
+    notify(
+        route_group(route.dst),
+        &route_msg(RTM_DELROUTE, 0, 0, 0, &route).finish(),
+    );
+    Ok(())
+}
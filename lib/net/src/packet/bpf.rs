@@ -0,0 +1,262 @@
+//! Classic BPF filters attached to sockets.
+//!
+//! A filter is a program of [`SockFilter`] instructions run on each packet before it
+//! is queued on the socket. The program works on an accumulator `A`, an index
+//! register `X` and 16 words of scratch memory, and returns the number of bytes of
+//! the packet to keep, where 0 drops the packet.
+
+use alloc::vec::Vec;
+
+use systype::error::{SysError, SysResult};
+
+/// Maximum number of instructions in a program.
+const BPF_MAXINSNS: usize = 4096;
+/// Number of words of scratch memory.
+const BPF_MEMWORDS: usize = 16;
+
+// Instruction classes.
+const BPF_LD: u16 = 0x00;
+const BPF_LDX: u16 = 0x01;
+const BPF_ST: u16 = 0x02;
+const BPF_STX: u16 = 0x03;
+const BPF_ALU: u16 = 0x04;
+const BPF_JMP: u16 = 0x05;
+const BPF_RET: u16 = 0x06;
+const BPF_MISC: u16 = 0x07;
+
+// Sizes of loads.
+const BPF_W: u16 = 0x00;
+const BPF_H: u16 = 0x08;
+const BPF_B: u16 = 0x10;
+
+// Modes of loads.
+const BPF_IMM: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_IND: u16 = 0x40;
+const BPF_MEM: u16 = 0x60;
+const BPF_LEN: u16 = 0x80;
+const BPF_MSH: u16 = 0xa0;
+
+// Operations of `BPF_ALU` and `BPF_JMP` instructions.
+const BPF_ADD: u16 = 0x00;
+const BPF_SUB: u16 = 0x10;
+const BPF_MUL: u16 = 0x20;
+const BPF_DIV: u16 = 0x30;
+const BPF_OR: u16 = 0x40;
+const BPF_AND: u16 = 0x50;
+const BPF_LSH: u16 = 0x60;
+const BPF_RSH: u16 = 0x70;
+const BPF_NEG: u16 = 0x80;
+const BPF_MOD: u16 = 0x90;
+const BPF_XOR: u16 = 0xa0;
+const BPF_JA: u16 = 0x00;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGT: u16 = 0x20;
+const BPF_JGE: u16 = 0x30;
+const BPF_JSET: u16 = 0x40;
+
+// Operand sources.
+const BPF_K: u16 = 0x00;
+const BPF_X: u16 = 0x08;
+const BPF_A: u16 = 0x10;
+
+// Operations of `BPF_MISC` instructions.
+const BPF_TAX: u16 = 0x00;
+const BPF_TXA: u16 = 0x80;
+
+/// Offset of the ancillary data, which is loaded by absolute loads beyond it.
+const SKF_AD_OFF: u32 = -0x1000i32 as u32;
+const SKF_AD_PROTOCOL: u32 = 0;
+const SKF_AD_PKTTYPE: u32 = 4;
+const SKF_AD_IFINDEX: u32 = 8;
+
+/// An instruction of a classic BPF program, as in `struct sock_filter`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct SockFilter {
+    pub code: u16,
+    /// Offset of the next instruction if a jump is taken.
+    pub jt: u8,
+    /// Offset of the next instruction if a jump is not taken.
+    pub jf: u8,
+    pub k: u32,
+}
+
+/// Information about a packet beyond its bytes, which a program reads as ancillary
+/// data.
+#[derive(Debug, Clone, Copy)]
+pub struct BpfContext {
+    /// Ethernet protocol of the packet, in host byte order.
+    pub protocol: u16,
+    pub pkttype: u8,
+    pub ifindex: u32,
+}
+
+/// A checked classic BPF program.
+#[derive(Debug, Clone)]
+pub struct BpfProgram {
+    insns: Vec<SockFilter>,
+}
+
+impl BpfProgram {
+    /// Checks the instructions `insns` and creates a program of them.
+    ///
+    /// Every jump of the program must go forward and stay in the program, and the
+    /// program must end with a return, so it always terminates.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if the program is empty, too long or invalid.
+    pub fn new(insns: Vec<SockFilter>) -> SysResult<Self> {
+        let len = insns.len();
+        if len == 0 || len > BPF_MAXINSNS {
+            return Err(SysError::EINVAL);
+        }
+        for (pc, insn) in insns.iter().enumerate() {
+            let k = insn.k as usize;
+            let valid = match insn.code & 0x07 {
+                BPF_LD | BPF_LDX => match insn.code & 0xe0 {
+                    BPF_MEM => k < BPF_MEMWORDS,
+                    BPF_IMM | BPF_LEN => true,
+                    BPF_ABS | BPF_IND => insn.code & 0x07 == BPF_LD && insn.code & 0x18 != 0x18,
+                    BPF_MSH => insn.code == BPF_LDX | BPF_B | BPF_MSH,
+                    _ => false,
+                },
+                BPF_ST | BPF_STX => k < BPF_MEMWORDS,
+                BPF_ALU => match insn.code & 0xf0 {
+                    BPF_DIV | BPF_MOD => insn.code & BPF_X != 0 || insn.k != 0,
+                    BPF_ADD | BPF_SUB | BPF_MUL | BPF_OR | BPF_AND | BPF_LSH | BPF_RSH
+                    | BPF_NEG | BPF_XOR => true,
+                    _ => false,
+                },
+                BPF_JMP => match insn.code & 0xf0 {
+                    BPF_JA => pc + 1 + k < len,
+                    BPF_JEQ | BPF_JGT | BPF_JGE | BPF_JSET => {
+                        pc + 1 + (insn.jt as usize) < len && pc + 1 + (insn.jf as usize) < len
+                    }
+                    _ => false,
+                },
+                BPF_RET => matches!(insn.code & 0x18, BPF_K | BPF_A),
+                BPF_MISC => matches!(insn.code & 0xf8, BPF_TAX | BPF_TXA),
+                _ => unreachable!(),
+            };
+            if !valid {
+                log::warn!("[BpfProgram::new] invalid instruction {pc}: {insn:?}");
+                return Err(SysError::EINVAL);
+            }
+        }
+        if insns[len - 1].code & 0x07 != BPF_RET {
+            return Err(SysError::EINVAL);
+        }
+        Ok(Self { insns })
+    }
+
+    /// Runs the program on `pkt`, and returns the number of bytes of the packet to
+    /// keep. A load beyond the packet drops it, as Linux does.
+    pub fn run(&self, pkt: &[u8], ctx: &BpfContext) -> u32 {
+        let mut a: u32 = 0;
+        let mut x: u32 = 0;
+        let mut mem = [0u32; BPF_MEMWORDS];
+        let mut pc = 0;
+        loop {
+            let insn = self.insns[pc];
+            let k = insn.k;
+            pc += 1;
+            match insn.code & 0x07 {
+                BPF_LD => {
+                    a = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_LEN => pkt.len() as u32,
+                        BPF_MEM => mem[k as usize],
+                        BPF_ABS if k >= SKF_AD_OFF => match k - SKF_AD_OFF {
+                            SKF_AD_PROTOCOL => ctx.protocol as u32,
+                            SKF_AD_PKTTYPE => ctx.pkttype as u32,
+                            SKF_AD_IFINDEX => ctx.ifindex,
+                            _ => 0,
+                        },
+                        mode => {
+                            let offset = if mode == BPF_IND {
+                                x.wrapping_add(k)
+                            } else {
+                                k
+                            };
+                            match load(pkt, offset, insn.code & 0x18) {
+                                Some(value) => value,
+                                None => return 0,
+                            }
+                        }
+                    };
+                }
+                BPF_LDX => {
+                    x = match insn.code & 0xe0 {
+                        BPF_IMM => k,
+                        BPF_LEN => pkt.len() as u32,
+                        BPF_MEM => mem[k as usize],
+                        // Loads 4 times the low nibble of a byte, which is the length of
+                        // an ipv4 header.
+                        _ => match pkt.get(k as usize) {
+                            Some(&byte) => ((byte & 0xf) as u32) << 2,
+                            None => return 0,
+                        },
+                    };
+                }
+                BPF_ST => mem[k as usize] = a,
+                BPF_STX => mem[k as usize] = x,
+                BPF_ALU => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    a = match insn.code & 0xf0 {
+                        BPF_ADD => a.wrapping_add(operand),
+                        BPF_SUB => a.wrapping_sub(operand),
+                        BPF_MUL => a.wrapping_mul(operand),
+                        BPF_DIV | BPF_MOD if operand == 0 => return 0,
+                        BPF_DIV => a / operand,
+                        BPF_MOD => a % operand,
+                        BPF_OR => a | operand,
+                        BPF_AND => a & operand,
+                        BPF_LSH => a.checked_shl(operand).unwrap_or(0),
+                        BPF_RSH => a.checked_shr(operand).unwrap_or(0),
+                        BPF_NEG => a.wrapping_neg(),
+                        _ => a ^ operand,
+                    };
+                }
+                BPF_JMP => {
+                    let operand = if insn.code & BPF_X != 0 { x } else { k };
+                    let taken = match insn.code & 0xf0 {
+                        BPF_JA => {
+                            pc += k as usize;
+                            continue;
+                        }
+                        BPF_JEQ => a == operand,
+                        BPF_JGT => a > operand,
+                        BPF_JGE => a >= operand,
+                        _ => a & operand != 0,
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                }
+                BPF_RET => return if insn.code & 0x18 == BPF_A { a } else { k },
+                _ => {
+                    if insn.code & 0xf8 == BPF_TAX {
+                        x = a;
+                    } else {
+                        a = x;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Loads a big-endian word, half word or byte at `offset` of `pkt`.
+fn load(pkt: &[u8], offset: u32, size: u16) -> Option<u32> {
+    let offset = offset as usize;
+    let len = match size {
+        BPF_W => 4,
+        BPF_H => 2,
+        _ => 1,
+    };
+    let bytes = pkt.get(offset..offset.checked_add(len)?)?;
+    Some(
+        bytes
+            .iter()
+            .fold(0, |value, &byte| (value << 8) | byte as u32),
+    )
+}
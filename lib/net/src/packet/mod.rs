@@ -0,0 +1,467 @@
+//! Packet sockets.
+//!
+//! A packet socket sees the link-layer frames received and sent by the network card,
+//! before the protocol stack handles them, and sends frames to the card directly. A
+//! `SOCK_RAW` socket gets and sends whole Ethernet frames, while a `SOCK_DGRAM`
+//! socket gets them without the Ethernet header, which the kernel builds when it
+//! sends them. A loopback device has no link layer, so its frames are given an
+//! Ethernet header with zero addresses, as Linux does.
+//!
+//! Frames addressed to other hosts on an interface are only seen while a socket has
+//! put its card in promiscuous mode. A classic BPF filter attached to a socket selects the frames
+//! queued on it.
+
+use alloc::{
+    collections::{btree_map::BTreeMap, vec_deque::VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU32, Ordering},
+    task::Waker,
+};
+
+use mutex::SpinNoIrqLock;
+use osfuture::{suspend_now, take_waker};
+use systype::error::{SysError, SysResult};
+
+use crate::{
+    NetPollState,
+    interface::InterfaceWrapper,
+    netlink::route::{ARPHRD_ETHER, ARPHRD_LOOPBACK},
+    tcp::has_signal,
+};
+
+use bpf::{BpfContext, BpfProgram};
+
+pub mod bpf;
+
+/// Protocol matching every frame.
+pub const ETH_P_ALL: u16 = 0x0003;
+const ETH_P_IP: u16 = 0x0800;
+const ETH_P_IPV6: u16 = 0x86dd;
+
+/// Length of an Ethernet header.
+pub const ETH_HLEN: usize = 14;
+/// Length of an Ethernet address.
+const ETH_ALEN: usize = 6;
+
+/// The frame is addressed to this host.
+pub const PACKET_HOST: u8 = 0;
+/// The frame is broadcast.
+pub const PACKET_BROADCAST: u8 = 1;
+/// The frame is multicast.
+pub const PACKET_MULTICAST: u8 = 2;
+/// The frame is addressed to another host.
+pub const PACKET_OTHERHOST: u8 = 3;
+/// The frame is sent by this host.
+pub const PACKET_OUTGOING: u8 = 4;
+
+/// Membership of a multicast address.
+pub const PACKET_MR_MULTICAST: u16 = 0;
+/// Membership putting the card in promiscuous mode.
+pub const PACKET_MR_PROMISC: u16 = 1;
+/// Membership of all multicast addresses.
+pub const PACKET_MR_ALLMULTI: u16 = 2;
+
+/// Maximum number of frames queued on a socket. Later frames are dropped until the
+/// socket receives some.
+const RX_QUEUE_CAPACITY: usize = 256;
+
+/// All packet sockets.
+static PACKET_SOCKETS: SpinNoIrqLock<Vec<Weak<PacketSocket>>> = SpinNoIrqLock::new(Vec::new());
+
+/// Number of memberships putting the network card of each interface in promiscuous
+/// mode, by interface index. Interfaces without such memberships are absent.
+static PROMISC_COUNTS: SpinNoIrqLock<BTreeMap<u32, usize>> = SpinNoIrqLock::new(BTreeMap::new());
+
+/// Link-layer address of a frame, as in `struct sockaddr_ll`.
+#[derive(Debug, Clone, Copy)]
+pub struct LinkAddr {
+    /// Ethernet protocol, in host byte order.
+    pub protocol: u16,
+    /// Index of the interface, which is 0 for any interface.
+    pub ifindex: u32,
+    /// `ARPHRD_*` type of the interface.
+    pub hatype: u16,
+    /// `PACKET_*` type of the frame.
+    pub pkttype: u8,
+    /// Ethernet address.
+    pub addr: [u8; ETH_ALEN],
+}
+
+/// `PacketSocket` is a socket of the `AF_PACKET` family.
+pub struct PacketSocket {
+    /// Whether the socket gets and sends frames with their Ethernet headers.
+    raw: bool,
+    /// Ethernet protocol of the frames to get, in host byte order, which is 0 for
+    /// none and [`ETH_P_ALL`] for all.
+    protocol: AtomicU16,
+    /// Index of the interface the socket is bound to, which is 0 for all interfaces.
+    ifindex: AtomicU32,
+    /// Frames waiting to be received, with their addresses.
+    rx_queue: SpinNoIrqLock<VecDeque<(Vec<u8>, LinkAddr)>>,
+    /// Waker of the task waiting for a frame.
+    waker: SpinNoIrqLock<Option<Waker>>,
+    filter: SpinNoIrqLock<Option<BpfProgram>>,
+    /// Memberships of the socket, each of an interface index and a `PACKET_MR_*` type.
+    memberships: SpinNoIrqLock<Vec<(u32, u16)>>,
+    nonblock: AtomicBool,
+    /// Number of frames queued since the statistics were last read.
+    received: AtomicU32,
+    /// Number of frames dropped since the statistics were last read.
+    dropped: AtomicU32,
+}
+
+impl PacketSocket {
+    /// Creates a packet socket getting frames of `protocol` in host byte order.
+    /// `raw` tells whether it is a `SOCK_RAW` socket or a `SOCK_DGRAM` one.
+    pub fn new(raw: bool, protocol: u16) -> Arc<Self> {
+        let socket = Arc::new(Self {
+            raw,
+            protocol: AtomicU16::new(protocol),
+            ifindex: AtomicU32::new(0),
+            rx_queue: SpinNoIrqLock::new(VecDeque::new()),
+            waker: SpinNoIrqLock::new(None),
+            filter: SpinNoIrqLock::new(None),
+            memberships: SpinNoIrqLock::new(Vec::new()),
+            nonblock: AtomicBool::new(false),
+            received: AtomicU32::new(0),
+            dropped: AtomicU32::new(0),
+        });
+        PACKET_SOCKETS.lock().push(Arc::downgrade(&socket));
+        log::info!("[PacketSocket::new] raw: {raw}, protocol: {protocol:#x}");
+        socket
+    }
+
+    pub fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Acquire)
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) {
+        self.nonblock.store(nonblocking, Ordering::Release);
+    }
+
+    /// Binds the socket to the interface `ifindex`, or to all interfaces if it is 0,
+    /// and makes it get frames of `protocol` unless it is 0.
+    ///
+    /// # Errors
+    /// Returns `ENODEV` if there is no interface `ifindex`.
+    pub fn bind(&self, protocol: u16, ifindex: u32) -> SysResult<()> {
+        if ifindex != 0 {
+            interface(ifindex)?;
+        }
+        if protocol != 0 {
+            self.protocol.store(protocol, Ordering::Relaxed);
+        }
+        self.ifindex.store(ifindex, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the address the socket is bound to.
+    pub fn local_addr(&self) -> LinkAddr {
+        let ifindex = self.ifindex.load(Ordering::Relaxed);
+        let (hatype, addr) = match crate::interface(ifindex) {
+            Some(iface) if iface.is_loopback() => (ARPHRD_LOOPBACK, [0; ETH_ALEN]),
+            Some(iface) => (ARPHRD_ETHER, iface.ethernet_address().0),
+            None => (0, [0; ETH_ALEN]),
+        };
+        LinkAddr {
+            protocol: self.protocol.load(Ordering::Relaxed),
+            ifindex,
+            hatype,
+            pkttype: PACKET_HOST,
+            addr,
+        }
+    }
+
+    /// Attaches a BPF `filter` to the socket, replacing the previous one, or detaches
+    /// the filter if it is `None`.
+    pub fn set_filter(&self, filter: Option<BpfProgram>) {
+        *self.filter.lock() = filter;
+    }
+
+    /// Adds a membership of `ty` on the interface `ifindex`.
+    ///
+    /// Multicast frames are always seen by packet sockets, so only promiscuous mode
+    /// changes what they get.
+    ///
+    /// # Errors
+    /// Returns `ENODEV` if there is no interface `ifindex`, or `EINVAL` if `ty` is
+    /// unknown.
+    pub fn add_membership(&self, ifindex: u32, ty: u16) -> SysResult<()> {
+        interface(ifindex)?;
+        match ty {
+            PACKET_MR_PROMISC => enter_promisc(ifindex),
+            PACKET_MR_MULTICAST | PACKET_MR_ALLMULTI => {}
+            _ => return Err(SysError::EINVAL),
+        }
+        self.memberships.lock().push((ifindex, ty));
+        Ok(())
+    }
+
+    /// Removes a membership of `ty` on the interface `ifindex`.
+    ///
+    /// # Errors
+    /// Returns `EADDRNOTAVAIL` if the socket has no such membership.
+    pub fn drop_membership(&self, ifindex: u32, ty: u16) -> SysResult<()> {
+        let mut memberships = self.memberships.lock();
+        let i = memberships
+            .iter()
+            .position(|&m| m == (ifindex, ty))
+            .ok_or(SysError::EADDRNOTAVAIL)?;
+        memberships.remove(i);
+        if ty == PACKET_MR_PROMISC {
+            leave_promisc(ifindex);
+        }
+        Ok(())
+    }
+
+    /// Returns the numbers of frames queued on the socket and dropped since the last
+    /// call, as `PACKET_STATISTICS` does. Dropped frames are counted in both.
+    pub fn take_statistics(&self) -> (u32, u32) {
+        let received = self.received.swap(0, Ordering::Relaxed);
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        (received + dropped, dropped)
+    }
+
+    /// Sends the frame in `buf` to `dst`, or to the interface the socket is bound to
+    /// if `dst` is `None`. A `SOCK_DGRAM` socket sends `buf` as the payload of a frame
+    /// to the Ethernet address in `dst`.
+    ///
+    /// # Errors
+    /// Returns `ENXIO` if no interface is given, `EINVAL` if the frame is too short
+    /// or a `SOCK_DGRAM` socket has no destination, `EMSGSIZE` if it is longer than
+    /// the MTU, or `ENOBUFS` if the card can't transmit now.
+    pub fn send(&self, buf: &[u8], dst: Option<LinkAddr>) -> SysResult<usize> {
+        let ifindex = match dst {
+            Some(dst) if dst.ifindex != 0 => dst.ifindex,
+            _ => self.ifindex.load(Ordering::Relaxed),
+        };
+        if ifindex == 0 {
+            return Err(SysError::ENXIO);
+        }
+        let iface = interface(ifindex)?;
+
+        let frame = if self.raw {
+            if buf.len() < ETH_HLEN {
+                return Err(SysError::EINVAL);
+            }
+            buf.to_vec()
+        } else {
+            let dst = dst.ok_or(SysError::EINVAL)?;
+            let protocol = match dst.protocol {
+                0 => self.protocol.load(Ordering::Relaxed),
+                protocol => protocol,
+            };
+            let mut frame = Vec::with_capacity(ETH_HLEN + buf.len());
+            frame.extend_from_slice(&dst.addr);
+            frame.extend_from_slice(&iface.ethernet_address().0);
+            frame.extend_from_slice(&protocol.to_be_bytes());
+            frame.extend_from_slice(buf);
+            frame
+        };
+        if frame.len() > ETH_HLEN + iface.mtu() {
+            return Err(SysError::EMSGSIZE);
+        }
+        if !iface.transmit_frame(&frame) {
+            return Err(SysError::ENOBUFS);
+        }
+        Ok(buf.len())
+    }
+
+    /// Receives a frame into `buf`, and returns the number of bytes copied and the
+    /// address of the frame. The rest of a frame longer than `buf` is discarded.
+    ///
+    /// It blocks until a frame arrives, unless the socket is nonblocking.
+    pub async fn recv(&self, buf: &mut [u8]) -> SysResult<(usize, LinkAddr)> {
+        loop {
+            if let Some((frame, addr)) = self.rx_queue.lock().pop_front() {
+                let len = buf.len().min(frame.len());
+                buf[..len].copy_from_slice(&frame[..len]);
+                return Ok((len, addr));
+            }
+            if self.is_nonblocking() {
+                return Err(SysError::EAGAIN);
+            }
+            let waker = take_waker().await;
+            {
+                let queue = self.rx_queue.lock();
+                if !queue.is_empty() {
+                    continue;
+                }
+                *self.waker.lock() = Some(waker);
+            }
+            suspend_now().await;
+            if has_signal() {
+                log::warn!("[PacketSocket::recv] has signal");
+                return Err(SysError::EINTR);
+            }
+        }
+    }
+
+    /// Checks whether the socket is readable, and registers the waker of the polling
+    /// task if it is not. A packet socket is always writable.
+    pub async fn poll(&self) -> NetPollState {
+        let waker = take_waker().await;
+        let queue = self.rx_queue.lock();
+        let readable = !queue.is_empty();
+        if !readable {
+            *self.waker.lock() = Some(waker);
+        }
+        NetPollState {
+            readable,
+            writable: true,
+            hangup: false,
+        }
+    }
+
+    /// Queues `frame` of `addr` on the socket if it wants the frame.
+    fn deliver(&self, frame: &[u8], addr: &LinkAddr) {
+        let protocol = self.protocol.load(Ordering::Relaxed);
+        let ifindex = self.ifindex.load(Ordering::Relaxed);
+        if protocol != ETH_P_ALL && protocol != addr.protocol
+            || ifindex != 0 && ifindex != addr.ifindex
+        {
+            return;
+        }
+
+        let data = if self.raw { frame } else { &frame[ETH_HLEN..] };
+        let len = match self.filter.lock().as_ref() {
+            Some(filter) => {
+                let ctx = BpfContext {
+                    protocol: addr.protocol,
+                    pkttype: addr.pkttype,
+                    ifindex: addr.ifindex,
+                };
+                filter.run(data, &ctx) as usize
+            }
+            None => data.len(),
+        };
+        if len == 0 {
+            return;
+        }
+
+        let mut queue = self.rx_queue.lock();
+        if queue.len() >= RX_QUEUE_CAPACITY {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        queue.push_back((data[..len.min(data.len())].to_vec(), *addr));
+        self.received.fetch_add(1, Ordering::Relaxed);
+        drop(queue);
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        self.memberships
+            .lock()
+            .iter()
+            .filter(|&&(_, ty)| ty == PACKET_MR_PROMISC)
+            .for_each(|&(ifindex, _)| leave_promisc(ifindex));
+        PACKET_SOCKETS
+            .lock()
+            .retain(|socket| socket.strong_count() > 0);
+    }
+}
+
+/// Adds a membership putting the card of the interface `ifindex` in promiscuous mode.
+fn enter_promisc(ifindex: u32) {
+    let mut counts = PROMISC_COUNTS.lock();
+    let count = counts.entry(ifindex).or_insert(0);
+    if *count == 0 {
+        log::info!("[PacketSocket] interface {ifindex} enters promiscuous mode");
+    }
+    *count += 1;
+}
+
+/// Removes a membership putting the card of the interface `ifindex` in promiscuous
+/// mode.
+fn leave_promisc(ifindex: u32) {
+    let mut counts = PROMISC_COUNTS.lock();
+    let Some(count) = counts.get_mut(&ifindex) else {
+        return;
+    };
+    *count -= 1;
+    if *count == 0 {
+        counts.remove(&ifindex);
+        log::info!("[PacketSocket] interface {ifindex} leaves promiscuous mode");
+    }
+}
+
+/// Returns the interface of index `ifindex`.
+///
+/// # Errors
+/// Returns `ENODEV` if there is no such interface.
+fn interface(ifindex: u32) -> SysResult<&'static InterfaceWrapper> {
+    crate::interface(ifindex).ok_or(SysError::ENODEV)
+}
+
+/// Queues a frame received or sent by the network card of the interface `ifindex` on
+/// the packet sockets that want it. `is_ethernet` tells whether `packet` starts with an
+/// Ethernet header or is an IP packet of a loopback device.
+///
+/// It is called with the device locked, so it must not lock the interface.
+pub(crate) fn deliver_frame(packet: &[u8], ifindex: u32, is_ethernet: bool, outgoing: bool) {
+    let sockets: Vec<_> = PACKET_SOCKETS
+        .lock()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    if sockets.is_empty() {
+        return;
+    }
+    let Some(iface) = crate::interface(ifindex) else {
+        return;
+    };
+
+    let loopback_frame;
+    let frame = if is_ethernet {
+        packet
+    } else {
+        let protocol = match packet.first().map(|byte| byte >> 4) {
+            Some(6) => ETH_P_IPV6,
+            _ => ETH_P_IP,
+        };
+        loopback_frame = [&[0; 2 * ETH_ALEN][..], &protocol.to_be_bytes(), packet].concat();
+        &loopback_frame[..]
+    };
+    if frame.len() < ETH_HLEN {
+        return;
+    }
+
+    let dst: [u8; ETH_ALEN] = frame[..ETH_ALEN].try_into().unwrap();
+    let pkttype = if outgoing {
+        PACKET_OUTGOING
+    } else if dst == [0xff; ETH_ALEN] {
+        PACKET_BROADCAST
+    } else if dst[0] & 1 != 0 {
+        PACKET_MULTICAST
+    } else if !is_ethernet || dst == iface.ethernet_address().0 {
+        PACKET_HOST
+    } else {
+        PACKET_OTHERHOST
+    };
+    if pkttype == PACKET_OTHERHOST && !PROMISC_COUNTS.lock().contains_key(&ifindex) {
+        return;
+    }
+
+    let addr = LinkAddr {
+        protocol: u16::from_be_bytes([frame[12], frame[13]]),
+        ifindex,
+        hatype: if is_ethernet {
+            ARPHRD_ETHER
+        } else {
+            ARPHRD_LOOPBACK
+        },
+        pkttype,
+        addr: frame[ETH_ALEN..2 * ETH_ALEN].try_into().unwrap(),
+    };
+    for socket in sockets {
+        socket.deliver(frame, &addr);
+    }
+}
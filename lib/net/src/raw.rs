@@ -8,7 +8,7 @@ use crate_interface::call_interface;
 use smoltcp::{
     iface::SocketHandle,
     socket::raw::{self, Socket as RawSocketInner},
-    wire::{IpProtocol, IpVersion, Ipv4Packet},
+    wire::{IpAddress, IpProtocol, IpVersion, Ipv4Packet},
 };
 
 use mutex::SpinNoIrqLock;
@@ -23,6 +23,19 @@ use crate::{
     tcp::has_signal,
 };
 
+/// Metadata of a packet received by [`RawSocket::recv_msg`], which is parsed from
+/// its IP header.
+#[derive(Debug, Clone, Copy)]
+pub struct RawRecvMeta {
+    /// Length of the packet, which is larger than the number of bytes copied if the
+    /// buffer is too small.
+    pub len: usize,
+    pub src_addr: Option<IpAddress>,
+    pub dst_addr: Option<IpAddress>,
+    /// TTL of an IPv4 packet or hop limit of an IPv6 packet.
+    pub hop_limit: Option<u8>,
+}
+
 /// RAW socket implementation for direct packet transmission
 /// Allows sending/receiving raw IP packets bypassing TCP/UDP layers
 pub struct RawSocket {
@@ -32,6 +45,8 @@ pub struct RawSocket {
     /// Whether to include IP header in user data
     hdr_included: AtomicBool,
     ipv6_only: AtomicBool,
+    /// Default destination set by `connect`.
+    peer: SpinNoIrqLock<Option<IpAddress>>,
 }
 
 impl RawSocket {
@@ -55,12 +70,13 @@ impl RawSocket {
             nonblock: AtomicBool::new(false),
             hdr_included: AtomicBool::new(false),
             ipv6_only: AtomicBool::new(false),
+            peer: SpinNoIrqLock::new(None),
         }
     }
 
     pub fn new_v6(protocol: u8) -> Self {
         let ip_protocol = IpProtocol::from(protocol);
-        let socket = SocketSetWrapper::new_raw_socket(ip_protocol, IpVersion::Ipv4);
+        let socket = SocketSetWrapper::new_raw_socket(ip_protocol, IpVersion::Ipv6);
         let handle = SOCKET_SET.add(socket);
         log::info!(
             "[RawSocket::new] add handle {}, protocol: {:?}",
@@ -74,6 +90,7 @@ impl RawSocket {
             nonblock: AtomicBool::new(false),
             hdr_included: AtomicBool::new(false),
             ipv6_only: AtomicBool::new(true),
+            peer: SpinNoIrqLock::new(None),
         }
     }
 
@@ -102,6 +119,32 @@ impl RawSocket {
         self.hdr_included.load(Ordering::Acquire)
     }
 
+    /// Sets the default destination of the socket to `addr`.
+    ///
+    /// # Errors
+    /// Returns `EAFNOSUPPORT` if `addr` is not of the ip version of the socket.
+    pub fn connect(&self, addr: IpAddress) -> SysResult<()> {
+        let is_ipv6 = matches!(addr, IpAddress::Ipv6(_));
+        if is_ipv6 != self.ipv6_only.load(Ordering::Relaxed) {
+            return Err(SysError::EAFNOSUPPORT);
+        }
+        *self.peer.lock() = Some(addr);
+        Ok(())
+    }
+
+    /// Returns the default destination of the socket.
+    ///
+    /// # Errors
+    /// Returns `ENOTCONN` if the socket is not connected.
+    pub fn peer_addr(&self) -> SysResult<IpAddress> {
+        self.peer.lock().ok_or(SysError::ENOTCONN)
+    }
+
+    /// Returns whether the socket sends and receives ipv6 packets.
+    pub fn is_ipv6(&self) -> bool {
+        self.ipv6_only.load(Ordering::Relaxed)
+    }
+
     /// Send raw packet data
     ///
     /// # Arguments
@@ -224,6 +267,51 @@ impl RawSocket {
         .await
     }
 
+    /// Receive a raw packet, or peek at it without removing it from buffer if `peek`
+    /// is true, and parse its metadata from the IP header. The rest of a packet longer
+    /// than `buf` is discarded unless it is peeked.
+    pub async fn recv_msg(&self, buf: &mut [u8], peek: bool) -> SysResult<RawRecvMeta> {
+        self.recv_impl(|socket| {
+            let ret = if peek { socket.peek() } else { socket.recv() };
+            let data = ret.map_err(|_| {
+                log::warn!("[RawSocket::recv_msg] recv failed");
+                SysError::EAGAIN
+            })?;
+            let len = data.len().min(buf.len());
+            buf[..len].copy_from_slice(&data[..len]);
+            Ok(Self::packet_meta(data))
+        })
+        .await
+    }
+
+    /// Parses the metadata of a received packet from its IP header.
+    fn packet_meta(packet: &[u8]) -> RawRecvMeta {
+        let mut meta = RawRecvMeta {
+            len: packet.len(),
+            src_addr: None,
+            dst_addr: None,
+            hop_limit: None,
+        };
+        match packet.first().map(|b| b >> 4) {
+            Some(4) => {
+                if let Ok(ipv4_packet) = Ipv4Packet::new_checked(packet) {
+                    meta.src_addr = Some(IpAddress::Ipv4(ipv4_packet.src_addr()));
+                    meta.dst_addr = Some(IpAddress::Ipv4(ipv4_packet.dst_addr()));
+                    meta.hop_limit = Some(ipv4_packet.hop_limit());
+                }
+            }
+            Some(6) => {
+                if let Ok(ipv6_packet) = smoltcp::wire::Ipv6Packet::new_checked(packet) {
+                    meta.src_addr = Some(IpAddress::Ipv6(ipv6_packet.src_addr()));
+                    meta.dst_addr = Some(IpAddress::Ipv6(ipv6_packet.dst_addr()));
+                    meta.hop_limit = Some(ipv6_packet.hop_limit());
+                }
+            }
+            _ => {}
+        }
+        meta
+    }
+
     /// Private function for recv operations
     async fn recv_impl<F, T>(&self, mut op: F) -> SysResult<T>
     where
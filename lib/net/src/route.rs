@@ -0,0 +1,260 @@
+//! Routing table.
+//!
+//! A route sends the packets to its destination subnet out of an interface, through a
+//! router if it has a gateway. Each address of an interface that is up gives a
+//! connected route to its subnet, while the other routes are added by user space and
+//! go through a gateway. A destination is routed by the route with the longest
+//! matching prefix, and among those by the one with the lowest metric.
+//!
+//! `smoltcp` routes the packets of an interface by its own table, so the best gateway
+//! route to each destination is mirrored into the interface it goes out of. As all
+//! interfaces poll the same sockets, they are polled from the one with the most
+//! specific routes to the one with the least specific (see
+//! [`crate::polled_interfaces`]), and a packet is sent by the first interface which
+//! has a route to it.
+
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use mutex::SpinNoIrqLock;
+use smoltcp::{
+    iface::Route,
+    wire::{IpAddress, IpCidr},
+};
+use systype::error::{SysError, SysResult};
+
+use crate::{interface, interfaces};
+
+/// Metric of connected routes.
+const CONNECTED_METRIC: u32 = 0;
+
+/// A route of the routing table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteEntry {
+    /// Destination subnet.
+    pub dst: IpCidr,
+    /// Router the packets are sent to, which is `None` for a connected route.
+    pub gateway: Option<IpAddress>,
+    /// Index of the interface the packets go out of.
+    pub ifindex: u32,
+    /// Priority of the route, where a lower metric is preferred.
+    pub metric: u32,
+    /// Preferred source address of the packets.
+    pub prefsrc: Option<IpAddress>,
+}
+
+/// Gateway routes, in the order they were added.
+static ROUTES: SpinNoIrqLock<Vec<RouteEntry>> = SpinNoIrqLock::new(Vec::new());
+
+/// Returns the cidr of the subnet of `cidr`, whose host bits are cleared.
+pub fn subnet(cidr: IpCidr) -> IpCidr {
+    match cidr {
+        IpCidr::Ipv4(v4) => IpCidr::Ipv4(v4.network()),
+        IpCidr::Ipv6(v6) => {
+            let bits = u128::from_be_bytes(v6.address().octets());
+            let mask = u128::MAX
+                .checked_shl(128 - v6.prefix_len() as u32)
+                .unwrap_or(0);
+            let addr = IpAddress::Ipv6((bits & mask).to_be_bytes().into());
+            IpCidr::new(addr, v6.prefix_len())
+        }
+    }
+}
+
+fn is_link_local(addr: IpAddress) -> bool {
+    match addr {
+        IpAddress::Ipv4(v4) => v4.is_link_local(),
+        IpAddress::Ipv6(v6) => v6.is_unicast_link_local(),
+    }
+}
+
+/// Returns the connected routes to the subnets of the addresses of the interfaces
+/// that are up.
+fn connected_routes() -> Vec<RouteEntry> {
+    let mut routes: Vec<RouteEntry> = Vec::new();
+    for iface in interfaces().into_iter().filter(|iface| iface.is_up()) {
+        for cidr in iface.ip_addrs() {
+            let dst = subnet(cidr);
+            if routes
+                .iter()
+                .any(|r| r.dst == dst && r.ifindex == iface.index())
+            {
+                continue;
+            }
+            routes.push(RouteEntry {
+                dst,
+                gateway: None,
+                ifindex: iface.index(),
+                metric: CONNECTED_METRIC,
+                prefsrc: Some(cidr.address()),
+            });
+        }
+    }
+    routes
+}
+
+/// Returns all routes, the connected routes first and then the gateway routes.
+pub fn routes() -> Vec<RouteEntry> {
+    let mut routes = connected_routes();
+    routes.extend(ROUTES.lock().iter().copied());
+    routes
+}
+
+/// Looks up the route to `dst` by the longest matching prefix and then the lowest
+/// metric. Only the routes out of the interface `oif` are taken if it is given, as
+/// for a socket bound to a device.
+pub fn lookup(dst: IpAddress, oif: Option<u32>) -> Option<RouteEntry> {
+    routes()
+        .into_iter()
+        .filter(|r| r.dst.contains_addr(&dst) && oif.is_none_or(|oif| r.ifindex == oif))
+        .filter(|r| interface(r.ifindex).is_some_and(|iface| iface.is_up()))
+        .min_by_key(|r| (Reverse(r.dst.prefix_len()), r.metric))
+}
+
+/// Selects the source address of the packets sent to `dst` by `route`.
+///
+/// It is the preferred source of the route if it has one. Otherwise it is an address
+/// of the interface of the route in the same family as `dst`, preferring the one on
+/// the subnet of the next hop. A link-local address is only selected for a link-local
+/// destination.
+pub fn source_addr(route: &RouteEntry, dst: IpAddress) -> Option<IpAddress> {
+    if let Some(prefsrc) = route.prefsrc {
+        return Some(prefsrc);
+    }
+    let next_hop = route.gateway.unwrap_or(dst);
+    let addrs: Vec<IpCidr> = interface(route.ifindex)?
+        .ip_addrs()
+        .into_iter()
+        .filter(|cidr| cidr.address().version() == dst.version())
+        .filter(|cidr| !is_link_local(cidr.address()) || is_link_local(dst))
+        .collect();
+    addrs
+        .iter()
+        .find(|cidr| cidr.contains_addr(&next_hop))
+        .or(addrs.first())
+        .map(|cidr| cidr.address())
+}
+
+/// Looks up the route to `dst` out of the interface `oif` if it is given, and
+/// selects the source address of the packets sent by it.
+///
+/// # Errors
+/// Returns `ENETUNREACH` if there is no route to `dst`, or `EADDRNOTAVAIL` if the
+/// interface of the route has no address to send from.
+pub fn select_source(dst: IpAddress, oif: Option<u32>) -> SysResult<IpAddress> {
+    let route = lookup(dst, oif).ok_or(SysError::ENETUNREACH)?;
+    source_addr(&route, dst).ok_or(SysError::EADDRNOTAVAIL)
+}
+
+/// Adds the gateway route `route`, and returns it with its interface.
+///
+/// If the interface index of `route` is 0, the route goes out of the interface on
+/// whose subnet the gateway is. A route to the same destination with the same metric
+/// out of the same interface is replaced, unless `exclusive` is true.
+///
+/// # Errors
+/// Returns `EINVAL` if `route` has no gateway, `ENODEV` if there is no such
+/// interface, `ENETUNREACH` if the gateway is not on a subnet of the interface,
+/// `EEXIST` if `exclusive` is true and the route exists, or `ENOSPC` if the interface
+/// can't hold more routes.
+pub fn add_route(mut route: RouteEntry, exclusive: bool) -> SysResult<RouteEntry> {
+    let gateway = route.gateway.ok_or(SysError::EINVAL)?;
+    if route.ifindex != 0 && interface(route.ifindex).is_none() {
+        return Err(SysError::ENODEV);
+    }
+    let link = connected_routes()
+        .into_iter()
+        .filter(|r| r.dst.contains_addr(&gateway))
+        .find(|r| route.ifindex == 0 || r.ifindex == route.ifindex)
+        .ok_or(SysError::ENETUNREACH)?;
+    route.ifindex = link.ifindex;
+    route.dst = subnet(route.dst);
+
+    let old = {
+        let mut routes = ROUTES.lock();
+        let same = |r: &RouteEntry| {
+            r.dst == route.dst && r.metric == route.metric && r.ifindex == route.ifindex
+        };
+        let old = routes.iter().position(same).map(|i| routes.remove(i));
+        if old.is_some() && exclusive {
+            routes.extend(old);
+            return Err(SysError::EEXIST);
+        }
+        routes.push(route);
+        old
+    };
+    if !sync_interfaces() {
+        let mut routes = ROUTES.lock();
+        routes.retain(|r| *r != route);
+        routes.extend(old);
+        drop(routes);
+        sync_interfaces();
+        return Err(SysError::ENOSPC);
+    }
+    log::info!("[route] add route {route:?}");
+    Ok(route)
+}
+
+/// Removes the first gateway route to `dst` out of the interface `ifindex` with
+/// `metric` and `gateway`, where each of them matches any route if it is `None`, and
+/// returns the removed route.
+///
+/// # Errors
+/// Returns `ESRCH` if there is no such route.
+pub fn remove_route(
+    dst: IpCidr,
+    ifindex: Option<u32>,
+    metric: Option<u32>,
+    gateway: Option<IpAddress>,
+) -> SysResult<RouteEntry> {
+    let dst = subnet(dst);
+    let route = {
+        let mut routes = ROUTES.lock();
+        let i = routes
+            .iter()
+            .position(|r| {
+                r.dst == dst
+                    && ifindex.is_none_or(|ifindex| r.ifindex == ifindex)
+                    && metric.is_none_or(|metric| r.metric == metric)
+                    && gateway.is_none_or(|gateway| r.gateway == Some(gateway))
+            })
+            .ok_or(SysError::ESRCH)?;
+        routes.remove(i)
+    };
+    sync_interfaces();
+    log::info!("[route] remove route {route:?}");
+    Ok(route)
+}
+
+/// Mirrors the best gateway route to each destination among the interfaces that are
+/// up into the `smoltcp` interface it goes out of, and updates the order of polling.
+///
+/// Returns false if an interface can't hold all its routes.
+pub(crate) fn sync_interfaces() -> bool {
+    let mut best: Vec<RouteEntry> = Vec::new();
+    for route in ROUTES.lock().iter() {
+        if !interface(route.ifindex).is_some_and(|iface| iface.is_up()) {
+            continue;
+        }
+        match best.iter_mut().find(|r| r.dst == route.dst) {
+            Some(r) if r.metric <= route.metric => {}
+            Some(r) => *r = *route,
+            None => best.push(*route),
+        }
+    }
+    let mut synced = true;
+    for iface in interfaces() {
+        let table = best
+            .iter()
+            .filter(|r| r.ifindex == iface.index())
+            .map(|r| Route {
+                cidr: r.dst,
+                via_router: r.gateway.unwrap(),
+                preferred_until: None,
+                expires_at: None,
+            })
+            .collect();
+        synced &= iface.set_routes(table);
+    }
+    synced
+}
@@ -13,18 +13,21 @@ use crate::tcp::LISTEN_TABLE;
 /// this token is the only chance that the kernel can process the packet
 /// when kernel receive the packet.
 ///
-/// kernel should consume the packet to get raw data slice.
+/// kernel should consume the packet to get raw data slice. The last field is the
+/// index of the interface of the device.
 pub(crate) struct NetRxToken<'a>(
     pub(crate) &'a RefCell<Box<dyn NetDevice>>,
     pub(crate) Box<dyn NetBufPtrOps>,
+    pub(crate) u32,
 );
 
 /// `NetTxToken` implement `TxToken` trait, which means that
 /// you can have a chance to send the packet and this is the
 /// only chance that you can write something into the packet.
 ///
-/// user can write sth to f closure and send it out.
-pub(crate) struct NetTxToken<'a>(pub(crate) &'a RefCell<Box<dyn NetDevice>>);
+/// user can write sth to f closure and send it out. The last field is the index of
+/// the interface of the device.
+pub(crate) struct NetTxToken<'a>(pub(crate) &'a RefCell<Box<dyn NetDevice>>, pub(crate) u32);
 
 impl RxToken for NetRxToken<'_> {
     /// receive net data and then pass raw data as bytes
@@ -36,6 +39,7 @@ impl RxToken for NetRxToken<'_> {
         let medium = self.0.borrow().capabilities().medium;
         let is_ethernet = medium == Medium::Ethernet;
         crate::tcp::snoop_tcp_packet(self.1.packet(), is_ethernet).ok();
+        crate::packet::deliver_frame(self.1.packet(), self.2, is_ethernet, false);
 
         let mut rx_buf = self.1;
         // log::debug!("[NetRxToken] receive {:?}", rx_buf);
@@ -58,9 +62,11 @@ impl TxToken for NetTxToken<'_> {
         F: FnOnce(&mut [u8]) -> R,
     {
         let mut dev = self.0.borrow_mut();
+        let is_ethernet = dev.capabilities().medium == Medium::Ethernet;
         let mut tx_buf = dev.alloc_tx_buffer(len).unwrap();
         // log::debug!("[NetTxToken] transmit {:?}", tx_buf);
         let ret = f(tx_buf.packet_mut());
+        crate::packet::deliver_frame(tx_buf.packet(), self.1, is_ethernet, true);
         dev.transmit(tx_buf).unwrap();
         ret
     }
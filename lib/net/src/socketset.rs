@@ -6,7 +6,7 @@ use smoltcp::{
     wire::{IpProtocol, IpVersion},
 };
 
-use crate::ETH0;
+use crate::{interface::InterfaceWrapper, polled_interfaces};
 
 pub const TCP_RX_BUF_LEN: usize = 64 * 1024;
 pub const TCP_TX_BUF_LEN: usize = 64 * 1024;
@@ -133,18 +133,25 @@ impl SocketSetWrapper {
         f(socket)
     }
 
-    /// The core function of net module. Poll and process data packets in waiting list.
+    /// The core function of net module. Poll and process data packets in waiting list
+    /// of each interface, in the order of [`polled_interfaces`].
     pub fn poll_interfaces(&self) -> SmolInstant {
         // {
         //     let lock = self.0.lock();
         //     lock.iter()
         //         .for_each(|s| log::debug!("[poll_interfaces] {}", s.0));
         // }
-        ETH0.get().unwrap().poll(self.0.clone())
+        let mut timestamp = InterfaceWrapper::current_time();
+        for iface in polled_interfaces() {
+            timestamp = iface.poll(self.0.clone());
+        }
+        timestamp
     }
 
     /// Different from `poll_interfaces`, it checks time and decides whether to poll.
     pub fn check_poll(&self, timestamp: SmolInstant) {
-        ETH0.get().unwrap().check_poll(timestamp, &self.0)
+        for iface in polled_interfaces() {
+            iface.check_poll(timestamp, &self.0);
+        }
     }
 }
@@ -1,7 +1,7 @@
 use alloc::vec::Vec;
 use core::{
     cell::UnsafeCell,
-    sync::atomic::{AtomicBool, AtomicU8},
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU32},
 };
 
 use smoltcp::{
@@ -47,7 +47,17 @@ pub struct TcpSocket {
     pub(crate) nonblock: AtomicBool,
     /// Just used to pass to ListenTable when listening
     pub(crate) listen_handles: ShareMutex<Vec<SocketHandle>>,
+    /// Whether the socket is an `AF_INET6` socket.
+    pub(crate) ipv6: AtomicBool,
+    /// Whether the IPv6 socket only communicates with IPv6 peers (`IPV6_V6ONLY`). If
+    /// not set, it also accepts IPv4 connections as IPv4-mapped IPv6 addresses.
     pub(crate) ipv6_only: AtomicBool,
+    /// The hop limit of outgoing packets set before the socket has a handle, where 0
+    /// means the default hop limit.
+    pub(crate) hop_limit: AtomicU8,
+    /// Index of the interface the socket is bound to by `SO_BINDTODEVICE`, where 0
+    /// means no interface.
+    pub(crate) bound_dev: AtomicU32,
 }
 
 unsafe impl Sync for TcpSocket {}
@@ -29,6 +29,9 @@ pub struct ListenTableEntry {
     pub(crate) waker: Waker,
     /// handles for listening handshake in tcp. (Different from Phoenix)
     pub(crate) handles: ShareMutex<Vec<SocketHandle>>,
+    /// Whether the listening socket is an IPv6 socket which does not accept IPv4
+    /// connections (`IPV6_V6ONLY`).
+    pub(crate) ipv6_only: bool,
 }
 
 impl ListenTableEntry {
@@ -36,12 +39,14 @@ impl ListenTableEntry {
         listen_endpoint: IpListenEndpoint,
         waker: &Waker,
         handles: ShareMutex<Vec<SocketHandle>>,
+        ipv6_only: bool,
     ) -> Self {
         Self {
             listen_endpoint,
             syn_queue: VecDeque::with_capacity(LISTEN_QUEUE_SIZE),
             waker: waker.clone(),
             handles,
+            ipv6_only,
         }
     }
 
@@ -4,7 +4,7 @@ use core::{ops::DerefMut, sync::atomic::Ordering, task::Waker};
 use smoltcp::{
     iface::{SocketHandle, SocketSet},
     socket::tcp::{self, State},
-    wire::{IpEndpoint, IpListenEndpoint},
+    wire::{IpAddress, IpEndpoint, IpListenEndpoint},
 };
 
 use mutex::{ShareMutex, SpinNoIrqLock};
@@ -62,8 +62,14 @@ impl ListenTable {
         }
     }
 
+    /// Returns the table of listen entries of IPv6 sockets if `v6` is true, or of IPv4
+    /// sockets otherwise.
+    fn table(&self, v6: bool) -> &[SpinNoIrqLock<Option<Box<ListenTableEntry>>>] {
+        if v6 { &self.tcpv6 } else { &self.tcp }
+    }
+
     pub fn can_listen(&self, port: u16) -> bool {
-        self.tcp[port as usize].lock().is_none()
+        self.tcp[port as usize].lock().is_none() && self.tcpv6[port as usize].lock().is_none()
     }
 
     /// A tcp socket uses this function to listen and build listening entry. Then the tcp
@@ -71,6 +77,9 @@ impl ListenTable {
     /// called only that the tcp handshake packet is recv by the socket and then `incoming_tcp_packet`
     /// is called).
     ///
+    /// An IPv6 socket which is not `ipv6_only` also accepts IPv4 connections on the port,
+    /// so it conflicts with an IPv4 socket listening on the same port.
+    ///
     /// After this function, listen handles can get ready when a tcp handshake msg comes.
     pub fn listen(
         &self,
@@ -78,65 +87,47 @@ impl ListenTable {
         waker: &Waker,
         handles: ShareMutex<Vec<SocketHandle>>,
         v6: bool,
+        ipv6_only: bool,
     ) -> SysResult<()> {
         let port = listen_endpoint.port;
         log::error!("[listen] port: {}", port);
         assert_ne!(port, 0);
 
-        if v6 {
-            let mut entry = self.tcpv6[port as usize].lock();
-
-            if entry.is_none() {
-                log::error!("[TABLE] add port {}", port);
-                *entry = Some(Box::new(ListenTableEntry::new(
-                    listen_endpoint,
-                    waker,
-                    handles,
-                )));
-            } else {
-                log::warn!("socket listen() failed");
-                return Err(SysError::EADDRINUSE);
-            }
+        // Always lock the IPv4 entry before the IPv6 one.
+        let mut entry_v4 = self.tcp[port as usize].lock();
+        let mut entry_v6 = self.tcpv6[port as usize].lock();
+        let (entry, conflict) = if v6 {
+            (&mut *entry_v6, !ipv6_only && entry_v4.is_some())
         } else {
-            let mut entry = self.tcp[port as usize].lock();
-
-            if entry.is_none() {
-                log::error!("[TABLE] add port {}", port);
-                *entry = Some(Box::new(ListenTableEntry::new(
-                    listen_endpoint,
-                    waker,
-                    handles,
-                )));
-            } else {
-                log::warn!("socket listen() failed");
-                return Err(SysError::EADDRINUSE);
-            }
+            let conflict = entry_v6.as_ref().is_some_and(|entry| !entry.ipv6_only);
+            (&mut *entry_v4, conflict)
+        };
+        if entry.is_some() || conflict {
+            log::warn!("socket listen() failed");
+            return Err(SysError::EADDRINUSE);
         }
 
+        log::error!("[TABLE] add port {}", port);
+        *entry = Some(Box::new(ListenTableEntry::new(
+            listen_endpoint,
+            waker,
+            handles,
+            ipv6_only,
+        )));
         Ok(())
     }
 
     pub fn unlisten(&self, port: u16, v6: bool) {
         log::info!("TCP socket unlisten on {}", port);
         log::error!("[TABLE] remove port {}", port);
-        if v6 {
-            if let Some(entry) = self.tcpv6[port as usize].lock().take() {
-                entry.waker.wake_by_ref()
-            }
-        } else {
-            if let Some(entry) = self.tcp[port as usize].lock().take() {
-                entry.waker.wake_by_ref()
-            }
+        if let Some(entry) = self.table(v6)[port as usize].lock().take() {
+            entry.waker.wake_by_ref()
         }
     }
 
     /// checks whether a entry about the port is in ListenTable. The entry is built in `listen()`.
-    pub fn can_accept(&self, port: u16) -> bool {
-        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
-            log::debug!("[can_accept] entry.syn_queue: {:?}", entry.syn_queue);
-            entry.syn_queue.iter().any(|&handle| is_connected(handle))
-            // true
-        } else if let Some(entry) = self.tcpv6[port as usize].lock().deref_mut() {
+    pub fn can_accept(&self, port: u16, v6: bool) -> bool {
+        if let Some(entry) = self.table(v6)[port as usize].lock().deref_mut() {
             log::debug!("[can_accept] entry.syn_queue: {:?}", entry.syn_queue);
             entry.syn_queue.iter().any(|&handle| is_connected(handle))
             // true
@@ -150,10 +141,14 @@ impl ListenTable {
 
     /// checks SYN queue in port and find handles which built connection successfully, take them
     /// from the queue and return to caller.
-    pub fn accept(&self, port: u16) -> SysResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
+    pub fn accept(
+        &self,
+        port: u16,
+        v6: bool,
+    ) -> SysResult<(SocketHandle, (IpEndpoint, IpEndpoint))> {
         log::debug!("[accept] port: {}", port);
 
-        if let Some(entry) = self.tcp[port as usize].lock().deref_mut() {
+        if let Some(entry) = self.table(v6)[port as usize].lock().deref_mut() {
             // log::error!("[accept] entry: {:?}", *entry);
             let syn_queue = &mut entry.syn_queue;
             syn_queue.iter().for_each(|&tuple| {
@@ -175,30 +170,6 @@ impl ListenTable {
             // 异常情况: 如果队列中第一个元素（索引为0）的连接请求没有完成，
             // 而后续的某个连接请求已经完成，这可能表明存在性能问题或异常情况,如网络延迟、
             // 资源争用
-            if idx > 0 {
-                log::warn!(
-                    "slow SYN queue enumeration: index = {}, len = {}!",
-                    idx,
-                    syn_queue.len()
-                );
-            }
-            log::debug!("[accept] success return resource");
-            let handle = syn_queue.swap_remove_front(idx).unwrap();
-            Ok((handle, addr_tuple))
-        } else if let Some(entry) = self.tcpv6[port as usize].lock().deref_mut() {
-            let syn_queue = &mut entry.syn_queue;
-            syn_queue.iter().for_each(|&tuple| {
-                log::debug!("[accept] {}, isconnect?{}", tuple, is_connected(tuple))
-            });
-
-            let (idx, addr_tuple) = syn_queue
-                .iter()
-                .enumerate()
-                .find_map(|(idx, &handle)| {
-                    is_connected(handle).then(|| (idx, get_addr_tuple(handle)))
-                })
-                .ok_or(SysError::EAGAIN)?; // wait for connection
-
             if idx > 0 {
                 log::warn!(
                     "slow SYN queue enumeration: index = {}, len = {}!",
@@ -219,33 +190,20 @@ impl ListenTable {
     ///  This function can add relevant ports into waiting list and the port will be checked and
     ///  processed in `check_after_poll`.
     pub fn incoming_tcp_packet(&self, src: IpEndpoint, dst: IpEndpoint) {
-        if let Some(entry) = self.tcp[dst.port as usize].lock().deref_mut() {
-            if !entry.can_accept(dst.addr) {
-                // not listening on this address
-                log::warn!(
-                    "[ListenTable::incoming_tcp_packet] not listening on address {}",
-                    dst.addr
-                );
-                return;
-            }
-            if entry.syn_queue.len() >= LISTEN_QUEUE_SIZE {
-                // SYN queue is full, drop the packet
-                log::warn!("SYN queue overflow!");
-                return;
+        // An IPv4 connection goes to the IPv4 listener of the port, or to a dual-stack IPv6
+        // listener if there is none.
+        let v6 = match dst.addr {
+            IpAddress::Ipv4(_) => {
+                self.tcp[dst.port as usize].lock().is_none()
+                    && self.tcpv6[dst.port as usize]
+                        .lock()
+                        .as_ref()
+                        .is_some_and(|entry| !entry.ipv6_only)
             }
-            entry.waker.wake_by_ref();
-            log::info!(
-                "[ListenTable::incoming_tcp_packet] wake the socket who listens port {}",
-                dst.port
-            );
-
-            let port = Port {
-                portid: dst.port as usize,
-                v4: true,
-            };
+            IpAddress::Ipv6(_) => true,
+        };
 
-            self.waiting_ports.lock().push(port);
-        } else if let Some(entry) = self.tcpv6[dst.port as usize].lock().deref_mut() {
+        if let Some(entry) = self.table(v6)[dst.port as usize].lock().deref_mut() {
             if !entry.can_accept(dst.addr) {
                 // not listening on this address
                 log::warn!(
@@ -267,7 +225,7 @@ impl ListenTable {
 
             let port = Port {
                 portid: dst.port as usize,
-                v4: true,
+                v4: !v6,
             };
 
             self.waiting_ports.lock().push(port);
@@ -285,7 +243,7 @@ impl ListenTable {
         while !list.is_empty() {
             let port = list.pop().unwrap();
             let portid = port.portid;
-            if let Some(entry) = self.tcp[portid].lock().deref_mut() {
+            if let Some(entry) = self.table(!port.v4)[portid].lock().deref_mut() {
                 // log::debug!("[check_after_poll] port: {}", portid);
                 let mut listen_handles = entry.handles.lock();
                 let mut ret = None;
@@ -315,7 +273,11 @@ impl ListenTable {
 
     /// `syn_wake` is used when the sleeping socket recv a tcp packet.
     pub fn syn_wake(&self, dst: IpEndpoint, ack: bool) {
-        if let Some(entry) = self.tcp[dst.port as usize].lock().deref_mut() {
+        for v6 in [false, true] {
+            let mut entry = self.table(v6)[dst.port as usize].lock();
+            let Some(entry) = entry.deref_mut() else {
+                continue;
+            };
             // let is_syn = entry.syn_recv_sleep.load(Ordering::Relaxed);
             // if is_syn {
             //     entry
@@ -58,20 +58,47 @@ pub fn snoop_tcp_packet(
     buf: &[u8],
     is_ethernet: bool,
 ) -> Result<Option<(IpEndpoint, IpEndpoint)>, smoltcp::wire::Error> {
-    use smoltcp::wire::{EthernetFrame, IpProtocol, Ipv4Packet, TcpPacket};
+    use smoltcp::wire::{
+        EthernetFrame, EthernetProtocol, IpAddress, IpProtocol, IpVersion, Ipv4Packet, Ipv6Packet,
+        TcpPacket,
+    };
 
     // let ether_frame = EthernetFrame::new_checked(buf)?;
     // let ipv4_packet = Ipv4Packet::new_checked(ether_frame.payload())?;
-    let ipv4_packet = if is_ethernet {
+    let ip_buf = if is_ethernet {
         let ether_frame = EthernetFrame::new_checked(buf)?;
-        Ipv4Packet::new_checked(ether_frame.payload())?
+        match ether_frame.ethertype() {
+            EthernetProtocol::Ipv4 | EthernetProtocol::Ipv6 => {}
+            _ => return Ok(None),
+        }
+        ether_frame.payload()
     } else {
-        Ipv4Packet::new_checked(buf)?
+        buf
+    };
+    let (src_ip, dst_ip, next_header, payload) = match IpVersion::of_packet(ip_buf)? {
+        IpVersion::Ipv4 => {
+            let ipv4_packet = Ipv4Packet::new_checked(ip_buf)?;
+            (
+                IpAddress::Ipv4(ipv4_packet.src_addr()),
+                IpAddress::Ipv4(ipv4_packet.dst_addr()),
+                ipv4_packet.next_header(),
+                ipv4_packet.payload(),
+            )
+        }
+        IpVersion::Ipv6 => {
+            let ipv6_packet = Ipv6Packet::new_checked(ip_buf)?;
+            (
+                IpAddress::Ipv6(ipv6_packet.src_addr()),
+                IpAddress::Ipv6(ipv6_packet.dst_addr()),
+                ipv6_packet.next_header(),
+                ipv6_packet.payload(),
+            )
+        }
     };
-    if ipv4_packet.next_header() == IpProtocol::Tcp {
-        let tcp_packet = TcpPacket::new_checked(ipv4_packet.payload())?;
-        let src_addr = (ipv4_packet.src_addr(), tcp_packet.src_port()).into();
-        let dst_addr = (ipv4_packet.dst_addr(), tcp_packet.dst_port()).into();
+    if next_header == IpProtocol::Tcp {
+        let tcp_packet = TcpPacket::new_checked(payload)?;
+        let src_addr = (src_ip, tcp_packet.src_port()).into();
+        let dst_addr = (dst_ip, tcp_packet.dst_port()).into();
         LISTEN_TABLE.syn_wake(dst_addr, tcp_packet.ack());
 
         let is_first = tcp_packet.syn() && !tcp_packet.ack();
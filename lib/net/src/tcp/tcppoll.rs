@@ -1,7 +1,7 @@
 use osfuture::take_waker;
 use smoltcp::socket::tcp::{self};
 
-use crate::{NetPollState, SOCKET_SET, addr::UNSPECIFIED_ENDPOINT_V4, tcp::LISTEN_TABLE};
+use crate::{NetPollState, SOCKET_SET, tcp::LISTEN_TABLE};
 
 use super::{STATE_CLOSED, STATE_CONNECTED, STATE_CONNECTING, STATE_LISTENING, core::TcpSocket};
 impl TcpSocket {
@@ -50,8 +50,8 @@ impl TcpSocket {
                 }
                 _ => {
                     unsafe {
-                        self.local_addr.get().write(UNSPECIFIED_ENDPOINT_V4);
-                        self.peer_addr.get().write(UNSPECIFIED_ENDPOINT_V4);
+                        self.local_addr.get().write(self.unspecified_endpoint());
+                        self.peer_addr.get().write(self.unspecified_endpoint());
                     }
                     self.set_state(STATE_CLOSED); // connection failed
                     true
@@ -109,7 +109,7 @@ impl TcpSocket {
         // SAFETY: `self.local_addr` should be initialized in a listening socket.
 
         let local_addr = unsafe { self.local_addr.get().read() };
-        let readable = LISTEN_TABLE.can_accept(local_addr.port);
+        let readable = LISTEN_TABLE.can_accept(local_addr.port, self.is_ipv6());
 
         NetPollState {
             readable,
@@ -11,6 +11,16 @@ use super::{RCV_SHUTDOWN, SEND_SHUTDOWN, core::TcpSocket};
 impl TcpSocket {
     /// Receives data from the socket, stores it in the given buffer.
     pub async fn recv(&self, buf: &mut [u8]) -> SysResult<usize> {
+        self.recv_impl(buf, false).await
+    }
+
+    /// Receives data from the socket as [`recv`](Self::recv) does, but leaves it in
+    /// the receive buffer so that it is received again.
+    pub async fn peek(&self, buf: &mut [u8]) -> SysResult<usize> {
+        self.recv_impl(buf, true).await
+    }
+
+    async fn recv_impl(&self, buf: &mut [u8], peek: bool) -> SysResult<usize> {
         let shutdown = unsafe { *self.shutdown.get() };
         if shutdown & RCV_SHUTDOWN != 0 {
             log::warn!("[TcpSocket::recv] shutdown closed read, recv return 0");
@@ -41,7 +51,12 @@ impl TcpSocket {
                 } else if socket.recv_queue() > 0 {
                     // data available
                     // TODO: use socket.recv(|buf| {...})
-                    let len = socket.recv_slice(buf).map_err(|_| {
+                    let ret = if peek {
+                        socket.peek_slice(buf)
+                    } else {
+                        socket.recv_slice(buf)
+                    };
+                    let len = ret.map_err(|_| {
                         log::warn!("socket recv() failed, badstate");
                         SysError::EBADF
                     })?;
@@ -62,8 +77,8 @@ impl TcpSocket {
         log::info!("[TcpSocket::send] begin to send to {:?}", self.peer_addr);
         let shutdown = unsafe { *self.shutdown.get() };
         if shutdown & SEND_SHUTDOWN != 0 {
-            log::warn!("[TcpSocket::send] shutdown closed write, send return 0");
-            return Ok(0);
+            log::warn!("[TcpSocket::send] shutdown closed write, send return EPIPE");
+            return Err(SysError::EPIPE);
         }
         if self.is_connecting() {
             return Err(SysError::EAGAIN);
@@ -77,10 +92,14 @@ impl TcpSocket {
         let waker = take_waker().await;
         let ret = self.block_on(|| {
             SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
-                if !socket.is_active() || !socket.may_send() {
+                if !socket.is_active() {
                     // closed by remote
                     log::warn!("socket send() failed, ECONNRESET");
                     Err(SysError::ECONNRESET)
+                } else if !socket.may_send() {
+                    // closed for writing
+                    log::warn!("socket send() failed, EPIPE");
+                    Err(SysError::EPIPE)
                 } else if socket.can_send() {
                     // connected, and the tx buffer is not full
                     // TODO: use socket.send(|buf| {...})
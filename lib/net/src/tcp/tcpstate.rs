@@ -1,14 +1,14 @@
 use alloc::vec::Vec;
 use core::{
     cell::UnsafeCell,
-    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU32, Ordering},
     task::Waker,
 };
 
 use smoltcp::{
     iface::SocketHandle,
     socket::tcp::{self, ConnectError},
-    wire::{IpAddress, IpEndpoint, IpListenEndpoint},
+    wire::{IpEndpoint, IpListenEndpoint, IpVersion},
 };
 
 use mutex::{SpinNoIrqLock, new_share_mutex};
@@ -20,8 +20,9 @@ use super::{
     STATE_CLOSED, STATE_CONNECTED, STATE_CONNECTING, STATE_LISTENING, core::TcpSocket, has_signal,
 };
 use crate::{
-    ETH0, NetPollState, SOCKET_SET, SocketSetWrapper,
-    addr::{UNSPECIFIED_ENDPOINT_V4, UNSPECIFIED_IPV4, is_unspecified},
+    NetPollState, SOCKET_SET, SocketSetWrapper,
+    addr::{UNSPECIFIED_ENDPOINT_V4, UNSPECIFIED_ENDPOINT_V6, is_unspecified},
+    interface, route,
     tcp::LISTEN_TABLE,
 };
 
@@ -38,7 +39,10 @@ impl TcpSocket {
             peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT_V4),
             nonblock: AtomicBool::new(false),
             listen_handles: new_share_mutex(Vec::new()),
+            ipv6: AtomicBool::new(false),
             ipv6_only: AtomicBool::new(false),
+            hop_limit: AtomicU8::new(0),
+            bound_dev: AtomicU32::new(0),
         }
     }
 
@@ -50,16 +54,24 @@ impl TcpSocket {
             state: AtomicU8::new(STATE_CLOSED),
             shutdown: UnsafeCell::new(0),
             handle: UnsafeCell::new(None),
-            local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT_V4),
-            peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT_V4),
+            local_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT_V6),
+            peer_addr: UnsafeCell::new(UNSPECIFIED_ENDPOINT_V6),
             nonblock: AtomicBool::new(false),
             listen_handles: new_share_mutex(Vec::new()),
-            ipv6_only: AtomicBool::new(true),
+            ipv6: AtomicBool::new(true),
+            ipv6_only: AtomicBool::new(false),
+            hop_limit: AtomicU8::new(0),
+            bound_dev: AtomicU32::new(0),
         }
     }
 
     /// Creates a new TCP socket that is already connected.
-    fn new_connected(handle: SocketHandle, local_addr: IpEndpoint, peer_addr: IpEndpoint) -> Self {
+    fn new_connected(
+        handle: SocketHandle,
+        local_addr: IpEndpoint,
+        peer_addr: IpEndpoint,
+        ipv6: bool,
+    ) -> Self {
         Self {
             state: AtomicU8::new(STATE_CONNECTED),
             shutdown: UnsafeCell::new(0),
@@ -68,7 +80,10 @@ impl TcpSocket {
             peer_addr: UnsafeCell::new(peer_addr),
             nonblock: AtomicBool::new(false),
             listen_handles: new_share_mutex(Vec::new()),
+            ipv6: AtomicBool::new(ipv6),
             ipv6_only: AtomicBool::new(false),
+            hop_limit: AtomicU8::new(0),
+            bound_dev: AtomicU32::new(0),
         }
     }
 
@@ -121,17 +136,31 @@ impl TcpSocket {
     ///
     /// The local port is generated automatically.
     pub async fn connect(&self, remote_addr: IpEndpoint) -> SysResult<()> {
+        if self.ipv6_only() && remote_addr.addr.version() == IpVersion::Ipv4 {
+            log::warn!("[TcpSocket::connect] IPv6-only socket can't connect to {remote_addr}");
+            return Err(SysError::ENETUNREACH);
+        }
         yield_now().await;
         // 将STATE_CLOSED改为STATE_CONNECTING，在poll_connect的时候，
         // 会再变为STATE_CONNECTED
         self.update_state(STATE_CLOSED, STATE_CONNECTING, || {
+            // The connection goes out of the interface of the route to the remote
+            // address, and is sent from the source address of the route unless the
+            // socket is bound to an address.
+            let mut bound_endpoint = self.bound_endpoint()?;
+            let route = route::lookup(remote_addr.addr, self.bound_device()).ok_or_else(|| {
+                log::warn!("[TcpSocket::connect] no route to {}", remote_addr.addr);
+                SysError::ENETUNREACH
+            })?;
+            if bound_endpoint.addr.is_none() {
+                bound_endpoint.addr = route::source_addr(&route, remote_addr.addr);
+            }
+            let iface = &interface(route.ifindex).ok_or(SysError::ENETUNREACH)?.iface;
             // SAFETY: no other threads can read or write these fields.
             let handle = unsafe { self.handle.get().read() }
                 .unwrap_or_else(|| SOCKET_SET.add(SocketSetWrapper::new_tcp_socket()));
             log::error!("[connect] add {}", handle);
-            // TODO: check remote addr unreachable
-            let bound_endpoint = self.bound_endpoint()?;
-            let iface = &ETH0.get().unwrap().iface;
+            self.apply_hop_limit(handle);
             let (local_endpoint, remote_endpoint) = SOCKET_SET
                 .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| {
                     socket
@@ -208,17 +237,10 @@ impl TcpSocket {
 
             unsafe {
                 let old = self.local_addr.get().read();
-                if old != UNSPECIFIED_ENDPOINT_V4 {
+                if old != self.unspecified_endpoint() {
                     log::warn!("socket bind() failed: {:?} already bound", local_addr);
                     return Err(SysError::EINVAL);
                 }
-                // FIXME
-                if let IpAddress::Ipv6(v6) = local_addr.addr {
-                    if v6.is_unspecified() {
-                        log::warn!("[TcpSocket::bind] Unstable: just use ipv4 instead of ipv6 when ipv6 is unspecified");
-                        local_addr.addr = UNSPECIFIED_IPV4;
-                    }
-                }
                 self.local_addr.get().write(local_addr);
             }
             Ok(())
@@ -243,12 +265,14 @@ impl TcpSocket {
                 bound_endpoint,
                 waker,
                 self.listen_handles.clone(),
-                self.ipv6_only.load(Ordering::Relaxed),
+                self.is_ipv6(),
+                self.ipv6_only(),
             )?;
 
             // log::info!("[TcpSocket::listen] listening on {bound_endpoint:?}");
             for _ in 0..24 {
                 let sock_handle = SOCKET_SET.add(SocketSetWrapper::new_tcp_socket());
+                self.apply_hop_limit(sock_handle);
                 SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(sock_handle, |sock| {
                     sock.listen(bound_endpoint).unwrap();
                 });
@@ -275,10 +299,13 @@ impl TcpSocket {
         }
 
         let local_port = unsafe { self.local_addr.get().read().port };
+        let ipv6 = self.is_ipv6();
         self.block_on(|| {
-            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port)?;
+            let (handle, (local_addr, peer_addr)) = LISTEN_TABLE.accept(local_port, ipv6)?;
             log::info!("TCP socket accepted a new connection {}", peer_addr);
-            Ok(TcpSocket::new_connected(handle, local_addr, peer_addr))
+            Ok(TcpSocket::new_connected(
+                handle, local_addr, peer_addr, ipv6,
+            ))
         })
         .await
     }
@@ -325,8 +352,9 @@ impl TcpSocket {
             // SAFETY: `self.local_addr` should be initialized in a listening socket,
             // and no other threads can read or write it.
             let local_port = unsafe { self.local_addr.get().read().port };
-            unsafe { self.local_addr.get().write(UNSPECIFIED_ENDPOINT_V4) }; // clear bound address
-            LISTEN_TABLE.unlisten(local_port, self.ipv6_only.load(Ordering::Relaxed));
+            // clear bound address
+            unsafe { self.local_addr.get().write(self.unspecified_endpoint()) };
+            LISTEN_TABLE.unlisten(local_port, self.is_ipv6());
             let timestamp = SOCKET_SET.poll_interfaces();
             SOCKET_SET.check_poll(timestamp);
             Ok(())
@@ -398,7 +426,101 @@ impl TcpSocket {
     }
 
     pub fn set_ipv6(&self) {
-        self.ipv6_only.store(true, Ordering::Relaxed);
+        self.ipv6.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the socket is an `AF_INET6` socket.
+    #[inline]
+    pub fn is_ipv6(&self) -> bool {
+        self.ipv6.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the socket only communicates with IPv6 peers.
+    #[inline]
+    pub fn ipv6_only(&self) -> bool {
+        self.ipv6_only.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether the socket only communicates with IPv6 peers (`IPV6_V6ONLY`).
+    ///
+    /// It can only be changed before the socket is bound or connected.
+    pub fn set_ipv6_only(&self, ipv6_only: bool) -> SysResult<()> {
+        if !self.is_ipv6() {
+            return Err(SysError::ENOPROTOOPT);
+        }
+        self.update_state(STATE_CLOSED, STATE_CLOSED, || {
+            // SAFETY: no other threads can read or write `self.local_addr`.
+            if unsafe { self.local_addr.get().read() } != self.unspecified_endpoint() {
+                return Err(SysError::EINVAL);
+            }
+            self.ipv6_only.store(ipv6_only, Ordering::Relaxed);
+            Ok(())
+        })
+        .unwrap_or(Err(SysError::EINVAL))
+    }
+
+    /// Returns the hop limit of outgoing packets, or `None` if it is the default one.
+    pub fn hop_limit(&self) -> Option<u8> {
+        match unsafe { self.handle.get().read() } {
+            Some(handle) => {
+                SOCKET_SET.with_socket_mut::<tcp::Socket, _, _>(handle, |socket| socket.hop_limit())
+            }
+            None => match self.hop_limit.load(Ordering::Relaxed) {
+                0 => None,
+                hop_limit => Some(hop_limit),
+            },
+        }
+    }
+
+    /// Sets the hop limit of outgoing packets, or restores the default one if
+    /// `hop_limit` is `None`.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if `hop_limit` is 0.
+    pub fn set_hop_limit(&self, hop_limit: Option<u8>) -> SysResult<()> {
+        if hop_limit == Some(0) {
+            return Err(SysError::EINVAL);
+        }
+        self.hop_limit.store(hop_limit.unwrap_or(0), Ordering::Relaxed);
+        if let Some(handle) = unsafe { self.handle.get().read() } {
+            self.apply_hop_limit(handle);
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the interface the socket is bound to by `SO_BINDTODEVICE`.
+    pub fn bound_device(&self) -> Option<u32> {
+        match self.bound_dev.load(Ordering::Relaxed) {
+            0 => None,
+            ifindex => Some(ifindex),
+        }
+    }
+
+    /// Binds the socket to the interface `ifindex`, or unbinds it if it is `None`, so
+    /// that its connection is only routed out of the interface.
+    pub fn bind_to_device(&self, ifindex: Option<u32>) {
+        self.bound_dev.store(ifindex.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Applies the hop limit set on the socket to the smoltcp socket of `handle`.
+    fn apply_hop_limit(&self, handle: SocketHandle) {
+        let hop_limit = match self.hop_limit.load(Ordering::Relaxed) {
+            0 => None,
+            hop_limit => Some(hop_limit),
+        };
+        SOCKET_SET
+            .with_socket_mut::<tcp::Socket, _, _>(handle, |socket| socket.set_hop_limit(hop_limit));
+    }
+
+    /// Returns the unspecified endpoint of the address family of the socket, which is
+    /// the local address of an unbound socket.
+    #[inline]
+    pub(crate) fn unspecified_endpoint(&self) -> IpEndpoint {
+        if self.is_ipv6() {
+            UNSPECIFIED_ENDPOINT_V6
+        } else {
+            UNSPECIFIED_ENDPOINT_V4
+        }
     }
 
     #[inline]
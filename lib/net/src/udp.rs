@@ -1,14 +1,14 @@
 use crate::externf::__NetSocketIf_mod;
 use core::{
     ops::Deref,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
     task::Waker,
 };
 use crate_interface::call_interface;
 use smoltcp::{
     iface::SocketHandle,
     socket::udp::{self, BindError, SendError},
-    wire::{IpEndpoint, IpListenEndpoint},
+    wire::{IpAddress, IpEndpoint, IpListenEndpoint, IpVersion},
 };
 
 use mutex::SpinNoIrqLock;
@@ -18,9 +18,10 @@ use systype::error::{SysError, SysResult};
 
 use crate::{
     NetPollState, SOCKET_SET, SocketSetWrapper,
-    addr::{UNSPECIFIED_LISTEN_ENDPOINT, is_unspecified, to_endpoint},
+    addr::{UNSPECIFIED_IPV6, UNSPECIFIED_LISTEN_ENDPOINT, is_unspecified, to_endpoint},
     externf::NetSocketIf,
     portmap::PORT_MAP,
+    route,
     tcp::has_signal,
 };
 
@@ -28,6 +29,28 @@ const PORT_START: u16 = 0xc000;
 const PORT_END: u16 = 0xffff;
 static CURR: SpinNoIrqLock<u16> = SpinNoIrqLock::new(PORT_START);
 
+/// Options of a datagram sent by [`UdpSocket::send_msg`], which are given in the
+/// control messages of `sendmsg()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UdpSendOpts {
+    /// Source address of the datagram, overriding the one selected by the route.
+    pub src_addr: Option<IpAddress>,
+    /// Hop limit of the datagram, overriding the one of the socket.
+    pub hop_limit: Option<u8>,
+}
+
+/// Metadata of a datagram received by [`UdpSocket::recv_msg`].
+#[derive(Debug, Clone, Copy)]
+pub struct UdpRecvMeta {
+    /// Length of the datagram, which is larger than the number of bytes copied if the
+    /// buffer is too small.
+    pub len: usize,
+    /// Source of the datagram.
+    pub remote: IpEndpoint,
+    /// Destination address of the datagram.
+    pub local_addr: Option<IpAddress>,
+}
+
 /// `UdpSocket` is a socket with udp protocal, used to
 /// bind a local address, connect to a peer address, recv from and send to
 /// a remote address.
@@ -38,7 +61,13 @@ pub struct UdpSocket {
     nonblock: AtomicBool,
     pub reuse_addr: AtomicBool,
     pub reuse_port: AtomicBool,
+    /// Whether the socket is an `AF_INET6` socket.
+    ipv6: AtomicBool,
+    /// Whether the IPv6 socket only communicates with IPv6 peers (`IPV6_V6ONLY`).
     ipv6_only: AtomicBool,
+    /// Index of the interface the socket is bound to by `SO_BINDTODEVICE`, where 0
+    /// means no interface.
+    bound_dev: AtomicU32,
 }
 
 impl UdpSocket {
@@ -53,7 +82,9 @@ impl UdpSocket {
             nonblock: AtomicBool::new(false),
             reuse_addr: AtomicBool::new(false),
             reuse_port: AtomicBool::new(false),
+            ipv6: AtomicBool::new(false),
             ipv6_only: AtomicBool::new(false),
+            bound_dev: AtomicU32::new(0),
         }
     }
 
@@ -68,16 +99,22 @@ impl UdpSocket {
             nonblock: AtomicBool::new(false),
             reuse_addr: AtomicBool::new(false),
             reuse_port: AtomicBool::new(false),
-            ipv6_only: AtomicBool::new(true),
+            ipv6: AtomicBool::new(true),
+            ipv6_only: AtomicBool::new(false),
+            bound_dev: AtomicU32::new(0),
         }
     }
 
     /// `local_addr` can return the udpsocket local address if binded.
     /// If not binded, it will return a ENOTCONN Error.
     pub fn local_addr(&self) -> SysResult<IpEndpoint> {
-        match self.local_addr.try_read() {
-            Some(addr) => addr.ok_or(SysError::ENOTCONN).map(to_endpoint),
-            None => Err(SysError::ENOTCONN),
+        let addr = match self.local_addr.try_read() {
+            Some(addr) => addr.ok_or(SysError::ENOTCONN)?,
+            None => return Err(SysError::ENOTCONN),
+        };
+        match addr.addr {
+            None if self.is_ipv6() => Ok(IpEndpoint::new(UNSPECIFIED_IPV6, addr.port)),
+            _ => Ok(to_endpoint(addr)),
         }
     }
 
@@ -99,7 +136,66 @@ impl UdpSocket {
     }
 
     pub fn set_ipv6(&self) {
-        self.ipv6_only.store(true, Ordering::Relaxed);
+        self.ipv6.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the socket is an `AF_INET6` socket.
+    pub fn is_ipv6(&self) -> bool {
+        self.ipv6.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the socket only communicates with IPv6 peers.
+    pub fn ipv6_only(&self) -> bool {
+        self.ipv6_only.load(Ordering::Relaxed)
+    }
+
+    /// Sets whether the socket only communicates with IPv6 peers (`IPV6_V6ONLY`).
+    ///
+    /// It can only be changed before the socket is bound.
+    pub fn set_ipv6_only(&self, ipv6_only: bool) -> SysResult<()> {
+        if !self.is_ipv6() {
+            return Err(SysError::ENOPROTOOPT);
+        }
+        if self.local_addr.read().is_some() {
+            return Err(SysError::EINVAL);
+        }
+        self.ipv6_only.store(ipv6_only, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the hop limit of outgoing packets, or `None` if it is the default one.
+    pub fn hop_limit(&self) -> Option<u8> {
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| socket.hop_limit())
+    }
+
+    /// Sets the hop limit of outgoing packets, or restores the default one if
+    /// `hop_limit` is `None`.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if `hop_limit` is 0.
+    pub fn set_hop_limit(&self, hop_limit: Option<u8>) -> SysResult<()> {
+        if hop_limit == Some(0) {
+            return Err(SysError::EINVAL);
+        }
+        SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+            socket.set_hop_limit(hop_limit)
+        });
+        Ok(())
+    }
+
+    /// Returns the index of the interface the socket is bound to by `SO_BINDTODEVICE`.
+    pub fn bound_device(&self) -> Option<u32> {
+        match self.bound_dev.load(Ordering::Relaxed) {
+            0 => None,
+            ifindex => Some(ifindex),
+        }
+    }
+
+    /// Binds the socket to the interface `ifindex`, or unbinds it if it is `None`, so
+    /// that its datagrams are only routed out of the interface.
+    pub fn bind_to_device(&self, ifindex: Option<u32>) {
+        self.bound_dev
+            .store(ifindex.unwrap_or(0), Ordering::Relaxed);
     }
 }
 
@@ -175,13 +271,39 @@ impl UdpSocket {
     /// - when `remote_addr`(not self.remote_addr) is 0 or unspecified, this function will return EINVAL Error.
     /// - when self.local_addr is none, it will be set as [`UNSPECIFIED_LISTEN_ENDPOINT`].
     pub async fn send_to(&self, buf: &[u8], remote_addr: IpEndpoint) -> SysResult<usize> {
+        self.send_msg(buf, remote_addr, UdpSendOpts::default())
+            .await
+    }
+
+    /// `send_msg` sends a buf to a specified remote_addr as [`send_to`](Self::send_to)
+    /// does, with the source address and the hop limit given in `opts`.
+    ///
+    /// - when `opts.src_addr` is not a local address or not of the version of
+    ///   `remote_addr`, or `opts.hop_limit` is 0, this function will return EINVAL Error.
+    pub async fn send_msg(
+        &self,
+        buf: &[u8],
+        remote_addr: IpEndpoint,
+        opts: UdpSendOpts,
+    ) -> SysResult<usize> {
         log::info!(
-            "[UdpSocket::send_to] {:?} send to {remote_addr:?}",
+            "[UdpSocket::send_msg] {:?} send to {remote_addr:?} with {opts:?}",
             self.local_addr
         );
         if remote_addr.port == 0 || remote_addr.addr.is_unspecified() {
             return Err(SysError::EINVAL);
         }
+        if self.ipv6_only() && remote_addr.addr.version() == IpVersion::Ipv4 {
+            return Err(SysError::ENETUNREACH);
+        }
+        if opts.src_addr.is_some_and(|src_addr| {
+            src_addr.version() != remote_addr.addr.version() || !crate::is_local_addr(src_addr)
+        }) {
+            return Err(SysError::EINVAL);
+        }
+        if opts.hop_limit == Some(0) {
+            return Err(SysError::EINVAL);
+        }
 
         if self.local_addr.read().is_none() {
             log::warn!(
@@ -190,13 +312,36 @@ impl UdpSocket {
             );
             self.bind(UNSPECIFIED_LISTEN_ENDPOINT)?;
         }
+        // A socket bound to no address sends from the source address of the route to
+        // the remote address.
+        let route = route::lookup(remote_addr.addr, self.bound_device()).ok_or_else(|| {
+            log::warn!("[UdpSocket::send_msg] no route to {}", remote_addr.addr);
+            SysError::ENETUNREACH
+        })?;
+        let mut meta = udp::UdpMetadata::from(remote_addr);
+        if let Some(src_addr) = opts.src_addr {
+            meta.local_address = Some(src_addr);
+        } else if self
+            .local_addr
+            .read()
+            .is_none_or(|addr| addr.addr.is_none())
+        {
+            meta.local_address = route::source_addr(&route, remote_addr.addr);
+        }
+        let prev_hop_limit = opts.hop_limit.map(|hop_limit| {
+            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                let prev = socket.hop_limit();
+                socket.set_hop_limit(Some(hop_limit));
+                prev
+            })
+        });
 
         let waker = take_waker().await;
-        let bytes = self
+        let ret = self
             .block_on(|| {
                 SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
                     if socket.can_send() {
-                        socket.send_slice(buf, remote_addr).map_err(|e| match e {
+                        socket.send_slice(buf, meta).map_err(|e| match e {
                             SendError::BufferFull => {
                                 log::warn!("socket send() failed, {e:?}");
                                 socket.register_send_waker(&waker);
@@ -218,11 +363,21 @@ impl UdpSocket {
                     }
                 })
             })
-            .await?;
-        log::info!("[UdpSocket::send_to] send {bytes} bytes to {remote_addr:?}");
-        log::debug!("[UdpSocket::send_to] I'will yield to let another thread to recv");
+            .await;
+        if let Some(prev_hop_limit) = prev_hop_limit {
+            // smoltcp takes the hop limit of the socket when the datagram is dispatched
+            // rather than when it is enqueued, so the datagram is dispatched before the
+            // hop limit of the socket is restored.
+            SOCKET_SET.poll_interfaces();
+            SOCKET_SET.with_socket_mut::<udp::Socket, _, _>(self.handle, |socket| {
+                socket.set_hop_limit(prev_hop_limit)
+            });
+        }
+        let bytes = ret?;
+        log::info!("[UdpSocket::send_msg] send {bytes} bytes to {remote_addr:?}");
+        log::debug!("[UdpSocket::send_msg] I'will yield to let another thread to recv");
         yield_now().await;
-        log::debug!("[UdpSocket::send_to] yield return");
+        log::debug!("[UdpSocket::send_msg] yield return");
 
         Ok(bytes)
     }
@@ -302,6 +457,12 @@ impl UdpSocket {
     /// - If socket endpoint is zero, this function returns `ENOTCONN` Error.
     pub async fn recv_from(&self, buf: &mut [u8]) -> SysResult<(usize, IpEndpoint)> {
         self.recv_impl(|socket| match socket.recv_slice(buf) {
+            // An IPv6-only socket drops datagrams from IPv4 peers.
+            Ok((_, meta))
+                if self.ipv6_only() && meta.endpoint.addr.version() == IpVersion::Ipv4 =>
+            {
+                Err(SysError::EAGAIN)
+            }
             Ok((len, meta)) => Ok((len, meta.endpoint)),
             Err(e) => {
                 log::warn!("[UdpSocket::recv_from] socket {} failed {e:?}", self.handle);
@@ -363,6 +524,48 @@ impl UdpSocket {
         .await
     }
 
+    /// `recv_msg` recvs a datagram as [`recv_from`](Self::recv_from) does, or peeks it
+    /// without removing it from rx_buffer if `peek` is true, and returns its metadata.
+    /// The rest of a datagram longer than `buf` is discarded unless it is peeked.
+    ///
+    /// - If self.local_addr is none, this function returns `ENOTCONN` Error.
+    /// - If socket endpoint is zero, this function returns `ENOTCONN` Error.
+    pub async fn recv_msg(&self, buf: &mut [u8], peek: bool) -> SysResult<UdpRecvMeta> {
+        self.recv_impl(|socket| {
+            let mut copy = |data: &[u8], remote, local_addr| {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                UdpRecvMeta {
+                    len: data.len(),
+                    remote,
+                    local_addr,
+                }
+            };
+            let ret = if peek {
+                socket
+                    .peek()
+                    .map(|(data, meta)| copy(data, meta.endpoint, meta.local_address))
+            } else {
+                socket
+                    .recv()
+                    .map(|(data, meta)| copy(data, meta.endpoint, meta.local_address))
+            };
+            let recv_meta = ret.map_err(|e| {
+                log::warn!("[UdpSocket::recv_msg] socket {} failed {e:?}", self.handle);
+                SysError::EAGAIN
+            })?;
+            // An IPv6-only socket drops datagrams from IPv4 peers.
+            if self.ipv6_only() && recv_meta.remote.addr.version() == IpVersion::Ipv4 {
+                if peek {
+                    let _ = socket.recv();
+                }
+                return Err(SysError::EAGAIN);
+            }
+            Ok(recv_meta)
+        })
+        .await
+    }
+
     /// `connect` can bind self.peer_addr with `addr`. If local address is not specified,
     /// it will be set as [`UNSPECIFIED_LISTEN_ENDPOINT`].
     pub fn connect(&self, addr: IpEndpoint) -> SysResult<()> {
@@ -56,7 +56,7 @@ impl FdInfo {
         self.flags = FdFlags::CLOEXEC;
     }
 
-    fn fanotify_close(&self) {
+    fn fsnotify_close(&self) {
         let event = if self.file.flags().writable() {
             FanEventMask::CLOSE_WRITE
         } else {
@@ -69,7 +69,7 @@ impl FdInfo {
             FanEventMask::empty()
         };
 
-        self.file.fanotify_publish(event | ondir_mask);
+        self.file.fsnotify_publish(event | ondir_mask);
     }
 }
 
@@ -132,14 +132,13 @@ impl FdTable {
             log::info!("alloc fd [{}]", fd);
             crate::proc::fd::create_self_fd_file(fd)?;
             crate::proc::fdinfo::create_thread_fdinfo_file(self.tid() as usize, fd);
-            crate::special::inotify::vfs_create_notify(file.clone());
 
             let ondir_mask = if file.inode().inotype().is_dir() {
                 FanEventMask::ONDIR
             } else {
                 FanEventMask::empty()
             };
-            file.fanotify_publish(FanEventMask::OPEN | ondir_mask);
+            file.fsnotify_publish(FanEventMask::OPEN | ondir_mask);
 
             self.table[fd] = Some(FdInfo::new(file, flags.into()));
             Ok(fd)
@@ -171,8 +170,7 @@ impl FdTable {
     pub fn clear(&mut self) {
         for slot in self.table.iter_mut() {
             if let Some(fd_info) = slot {
-                crate::special::inotify::vfs_delete_notify(fd_info.file.clone());
-                fd_info.fanotify_close();
+                fd_info.fsnotify_close();
                 *slot = None;
             }
         }
@@ -193,8 +191,7 @@ impl FdTable {
                 //     Arc::strong_count(&fd_info.file) - 1
                 // );
                 if fd_info.flags().contains(FdFlags::CLOEXEC) {
-                    crate::special::inotify::vfs_delete_notify(fd_info.file.clone());
-                    fd_info.fanotify_close();
+                    fd_info.fsnotify_close();
                     *slot = None;
                 }
             }
@@ -241,8 +238,7 @@ impl FdTable {
             Arc::strong_count(&self.table[fd].as_ref().unwrap().file) - 1
         );
         let fdinfo = self.get_mut(fd)?;
-        crate::special::inotify::vfs_delete_notify(fdinfo.file.clone());
-        fdinfo.fanotify_close();
+        fdinfo.fsnotify_close();
         self.table[fd] = None;
         Ok(())
     }
@@ -322,7 +318,7 @@ impl Default for FdTable {
 
 impl Drop for FdTable {
     fn drop(&mut self) {
-        // Call `clear` rather than simply dropping them to send fanotify events.
+        // Call `clear` rather than simply dropping them to send fanotify and inotify events.
         self.clear();
     }
 }
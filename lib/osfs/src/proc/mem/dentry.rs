@@ -0,0 +1,73 @@
+use alloc::sync::{Arc, Weak};
+
+use config::inode::InodeMode;
+use crate_interface::call_interface;
+use systype::error::{SysError, SysResult};
+use vfs::{
+    dentry::{Dentry, DentryMeta},
+    file::{File, FileMeta},
+    inode::Inode,
+};
+
+use super::{file::MemFile, inode::MemInode};
+use crate::proc::__KernelProcIf_mod;
+
+pub struct MemDentry {
+    meta: DentryMeta,
+}
+
+impl MemDentry {
+    pub fn new(inode: Option<Arc<MemInode>>, parent: Option<Weak<dyn Dentry>>) -> Arc<Self> {
+        Arc::new(Self {
+            meta: DentryMeta::new("mem", inode.map(|i| i as Arc<dyn Inode>), parent),
+        })
+    }
+}
+
+impl Dentry for MemDentry {
+    fn get_meta(&self) -> &DentryMeta {
+        &self.meta
+    }
+
+    fn base_open(self: Arc<Self>) -> SysResult<Arc<dyn File>> {
+        let inode = self
+            .inode()
+            .ok_or(SysError::ENOENT)?
+            .downcast_arc::<MemInode>()
+            .unwrap_or_else(|_| unreachable!());
+        let mem = call_interface!(KernelProcIf::open_mem(inode.thread_id))?;
+        Ok(Arc::new(MemFile {
+            meta: FileMeta::new(self),
+            mem,
+        }))
+    }
+
+    fn base_create(&self, _dentry: &dyn Dentry, _mode: InodeMode) -> SysResult<()> {
+        Err(SysError::EACCES)
+    }
+
+    fn base_lookup(&self, _dentry: &dyn Dentry) -> SysResult<()> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_link(&self, _dentry: &dyn Dentry, _old_dentry: &dyn Dentry) -> SysResult<()> {
+        Err(SysError::EACCES)
+    }
+
+    fn base_unlink(&self, _dentry: &dyn Dentry) -> SysResult<()> {
+        Err(SysError::EACCES)
+    }
+
+    fn base_new_neg_child(self: Arc<Self>, _name: &str) -> Arc<dyn Dentry> {
+        panic!("MemDentry does not support new_neg_child")
+    }
+
+    fn base_rename(
+        &self,
+        _dentry: &dyn Dentry,
+        _new_dir: &dyn Dentry,
+        _new_dentry: &dyn Dentry,
+    ) -> SysResult<()> {
+        Err(SysError::EACCES)
+    }
+}
@@ -0,0 +1,47 @@
+use alloc::{boxed::Box, sync::Weak};
+
+use async_trait::async_trait;
+
+use systype::error::{SysError, SysResult, SyscallResult};
+use vfs::{
+    direntry::DirEntry,
+    file::{File, FileMeta},
+};
+
+use super::ProcMem;
+
+/// An open `/proc/<pid>/mem`, whose offsets are addresses in the target process.
+pub struct MemFile {
+    pub(crate) meta: FileMeta,
+    /// Memory of the process when the file was opened.
+    pub(crate) mem: Weak<dyn ProcMem>,
+}
+
+#[async_trait]
+impl File for MemFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read(&self, buf: &mut [u8], offset: usize) -> SyscallResult {
+        match self.mem.upgrade() {
+            Some(mem) => mem.read(offset, buf),
+            None => Ok(0),
+        }
+    }
+
+    async fn base_write(&self, buf: &[u8], offset: usize) -> SyscallResult {
+        match self.mem.upgrade() {
+            Some(mem) => mem.write(offset, buf),
+            None => Ok(0),
+        }
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_readlink(&self, _buf: &mut [u8]) -> SysResult<usize> {
+        panic!("MemFile does not support readlink");
+    }
+}
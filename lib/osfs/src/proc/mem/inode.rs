@@ -0,0 +1,58 @@
+use alloc::sync::Arc;
+
+use config::{inode::InodeType, mm::USER_END};
+use systype::error::SysResult;
+use vfs::{
+    inode::{Inode, InodeMeta},
+    inoid::alloc_ino,
+    stat::Stat,
+    superblock::SuperBlock,
+};
+
+pub struct MemInode {
+    meta: InodeMeta,
+    pub thread_id: usize,
+}
+
+impl MemInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>, tid: usize) -> Arc<Self> {
+        let inode = Arc::new(Self {
+            meta: InodeMeta::new(alloc_ino(), super_block),
+            thread_id: tid,
+        });
+        inode.set_inotype(InodeType::File);
+        // The file spans the whole user address space, so that a write at any address
+        // does not extend it, and `SEEK_END` seeks to the end of user space.
+        inode.set_size(USER_END).unwrap();
+        inode
+    }
+}
+
+impl Inode for MemInode {
+    fn get_meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        Ok(Stat {
+            st_dev: 0, // non-real-file
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: 0,
+            st_gid: 0,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: 0,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
@@ -0,0 +1,27 @@
+//! `/proc/<pid>/mem`, the memory of a process.
+//!
+//! The offset in the file is an address in the process, and reading or writing the
+//! file accesses the memory of the process there, faulting in pages as needed. Opening
+//! the file requires the same permission as tracing the process; an address which
+//! cannot be accessed fails with `EIO`.
+//!
+//! The memory is that of the process when the file is opened, and the permission is
+//! only checked then, as in Linux. Once the process exits or execs, reading or writing
+//! the file returns 0.
+
+use systype::error::SysResult;
+
+pub mod dentry;
+pub mod file;
+pub mod inode;
+
+/// Memory of a process accessed through `/proc/<pid>/mem`, which is implemented by the
+/// address space of the process in the kernel.
+pub trait ProcMem: Send + Sync {
+    /// Reads the memory at `addr` into `buf`, and returns the number of bytes read.
+    fn read(&self, addr: usize, buf: &mut [u8]) -> SysResult<usize>;
+
+    /// Writes `buf` to the memory at `addr`, even if it is mapped read-only, and returns
+    /// the number of bytes written.
+    fn write(&self, addr: usize, buf: &[u8]) -> SysResult<usize>;
+}
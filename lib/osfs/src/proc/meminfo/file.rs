@@ -21,9 +21,13 @@ impl File for MemInfoFile {
     }
 
     async fn base_read(&self, buf: &mut [u8], pos: usize) -> SysResult<usize> {
-        let meminfo = MEM_INFO.lock();
+        let mut meminfo = MEM_INFO.lock();
+        meminfo.refresh();
         let info = meminfo.serialize();
-        let len = cmp::min(info.len() - pos, buf.len());
+        let len = cmp::min(info.len().saturating_sub(pos), buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
         buf[..len].copy_from_slice(&info.as_bytes()[pos..pos + len]);
         Ok(len)
     }
@@ -1,18 +1,22 @@
-use alloc::string::{String, ToString};
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use core::sync::atomic::Ordering;
+
+use config::mm::{HUGE_PAGE_SIZE, PAGE_SIZE};
+use crate_interface::call_interface;
+use mm::stat::VmStat;
 use mutex::SpinNoIrqLock;
 
+use super::{__KernelProcIf_mod, sysctl::OVERCOMMIT_RATIO};
+
 pub mod dentry;
 pub mod file;
 pub mod inode;
 
 pub static MEM_INFO: SpinNoIrqLock<MemInfo> = SpinNoIrqLock::new(MemInfo::new());
 
-const TOTAL_MEM: usize = 16251136;
-const FREE_MEM: usize = 327680;
-const BUFFER: usize = 373336;
-const CACHED: usize = 10391984;
-const TOTAL_SWAP: usize = 4194300;
-
 /// Mapping to free output: https://access.redhat.com/solutions/406773.
 pub struct MemInfo {
     /// General memory
@@ -22,29 +26,64 @@ pub struct MemInfo {
     /// Buffer and cache
     pub buffers: usize,
     pub cached: usize,
+    /// Anonymous memory and page tables of processes
+    pub anon_pages: usize,
+    pub page_tables: usize,
     /// Swap space
     pub total_swap: usize,
     pub free_swap: usize,
     /// Share memory
     pub shmem: usize,
     pub slab: usize,
+    /// Transparent huge pages in anonymous memory
+    pub anon_huge_pages: usize,
+    /// Overcommit accounting
+    pub commit_limit: usize,
+    pub committed_as: usize,
 }
 
 impl MemInfo {
     pub const fn new() -> Self {
         Self {
-            total_mem: TOTAL_MEM,
-            free_mem: FREE_MEM,
-            avail_mem: TOTAL_MEM - FREE_MEM,
-            buffers: BUFFER,
-            cached: CACHED,
-            total_swap: TOTAL_SWAP,
-            free_swap: TOTAL_SWAP,
+            total_mem: 0,
+            free_mem: 0,
+            avail_mem: 0,
+            buffers: 0,
+            cached: 0,
+            anon_pages: 0,
+            page_tables: 0,
+            total_swap: 0,
+            free_swap: 0,
             shmem: 0,
             slab: 0,
+            anon_huge_pages: 0,
+            commit_limit: 0,
+            committed_as: 0,
         }
     }
 
+    /// Updates the statistics from the frame allocator, the kernel heap and the memory
+    /// statistic counters.
+    ///
+    /// Pages in the page caches are counted as available, as they can be dropped.
+    pub fn refresh(&mut self) {
+        let to_kb = |pages: usize| pages * PAGE_SIZE / 1024;
+        let total = mm::frame::total_frames();
+        let free = mm::frame::free_frames();
+        let cached = VmStat::FilePages.get();
+        self.total_mem = to_kb(total);
+        self.free_mem = to_kb(free);
+        self.avail_mem = to_kb(free + cached);
+        self.cached = to_kb(cached);
+        self.anon_pages = to_kb(VmStat::AnonPages.get());
+        self.page_tables = to_kb(VmStat::PageTablePages.get());
+        self.slab = mm::heap::heap_used() / 1024;
+        self.commit_limit = to_kb(total * OVERCOMMIT_RATIO.load(Ordering::Relaxed) / 100);
+        self.committed_as = to_kb(call_interface!(KernelProcIf::committed_pages()));
+        self.anon_huge_pages =
+            call_interface!(KernelProcIf::anon_huge_pages()) * HUGE_PAGE_SIZE / 1024;
+    }
+
     pub fn serialize(&self) -> String {
         let mut res = "".to_string();
         let end = " KB\n";
@@ -56,8 +95,16 @@ impl MemInfo {
         let cached_swap = "SwapCached:\t".to_string() + 0.to_string().as_str() + end;
         let total_swap = "SwapTotal:\t".to_string() + self.total_swap.to_string().as_str() + end;
         let free_swap = "SwapFree:\t".to_string() + self.free_swap.to_string().as_str() + end;
+        let anon_pages = "AnonPages:\t".to_string() + self.anon_pages.to_string().as_str() + end;
         let shmem = "Shmem:\t".to_string() + self.shmem.to_string().as_str() + end;
         let slab = "Slab:\t".to_string() + self.slab.to_string().as_str() + end;
+        let page_tables = "PageTables:\t".to_string() + self.page_tables.to_string().as_str() + end;
+        let anon_huge_pages =
+            "AnonHugePages:\t".to_string() + self.anon_huge_pages.to_string().as_str() + end;
+        let commit_limit =
+            "CommitLimit:\t".to_string() + self.commit_limit.to_string().as_str() + end;
+        let committed_as =
+            "Committed_AS:\t".to_string() + self.committed_as.to_string().as_str() + end;
         res += total_mem.as_str();
         res += free_mem.as_str();
         res += avail_mem.as_str();
@@ -66,8 +113,14 @@ impl MemInfo {
         res += cached_swap.as_str();
         res += total_swap.as_str();
         res += free_swap.as_str();
+        res += anon_pages.as_str();
         res += shmem.as_str();
         res += slab.as_str();
+        res += page_tables.as_str();
+        res += anon_huge_pages.as_str();
+        res += format!("Hugepagesize:\t{}{}", HUGE_PAGE_SIZE / 1024, end).as_str();
+        res += commit_limit.as_str();
+        res += committed_as.as_str();
         res
     }
 }
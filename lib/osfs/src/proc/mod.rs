@@ -1,4 +1,9 @@
-use alloc::{collections::btree_map::BTreeMap, format, string::String, sync::Arc};
+use alloc::{
+    collections::btree_map::BTreeMap,
+    format,
+    string::String,
+    sync::{Arc, Weak},
+};
 
 use config::{
     inode::{InodeMode, InodeType},
@@ -9,11 +14,17 @@ use fdinfo::info::ProcFdInfo;
 use gconfig::init_config_file;
 use interrupts::{dentry::InterruptsDentry, inode::InterruptsInode};
 use maps::{dentry::MapsDentry, inode::MapsInode};
+use mem::{ProcMem, dentry::MemDentry, inode::MemInode};
 use meminfo::{dentry::MemInfoDentry, inode::MemInfoInode};
 use mounts::{dentry::MountsDentry, inode::MountsInode};
+use oom::{OomFileKind, dentry::OomDentry, inode::OomInode};
 use stat::{dentry::StatDentry, inode::StatInode};
+use sysctl::{
+    OVERCOMMIT_MEMORY, OVERCOMMIT_NEVER, OVERCOMMIT_RATIO, dentry::SysctlDentry, inode::SysctlInode,
+};
 use systype::error::SysResult;
 use vfs::{dentry::Dentry, inode::Inode, sys_root_dentry};
+use vmstat::{VmStatFileKind, dentry::VmStatDentry, inode::VmStatInode};
 
 use crate::{
     proc::status::{dentry::StatusDentry, inode::StatusInode},
@@ -26,10 +37,14 @@ pub mod fdinfo;
 pub mod gconfig;
 pub mod interrupts;
 pub mod maps;
+pub mod mem;
 pub mod meminfo;
 pub mod mounts;
+pub mod oom;
 pub mod stat;
 pub mod status;
+pub mod sysctl;
+pub mod vmstat;
 
 pub mod fs;
 pub mod superblock;
@@ -46,6 +61,13 @@ pub trait KernelProcIf {
     fn interrupts() -> BTreeMap<usize, usize>;
     fn fd(fd: usize) -> String;
     fn fdinfo_from_tid_and_fd(tid: usize, fd: usize) -> SysResult<ProcFdInfo>;
+    fn oom_score(tid: usize) -> SysResult<usize>;
+    fn oom_score_adj(tid: usize) -> SysResult<isize>;
+    fn set_oom_score_adj(tid: usize, adj: isize) -> SysResult<()>;
+    fn committed_pages() -> usize;
+    fn anon_huge_pages() -> usize;
+    fn open_mem(tid: usize) -> SysResult<Weak<dyn ProcMem>>;
+    fn check_sysctl_write() -> SysResult<()>;
 }
 
 pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
@@ -72,6 +94,17 @@ pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     );
     root_dentry.add_child(interrupts_dentry);
 
+    // /proc/vmstat, /proc/buddyinfo and /proc/slabinfo
+    for (name, kind) in [
+        ("vmstat", VmStatFileKind::VmStat),
+        ("buddyinfo", VmStatFileKind::BuddyInfo),
+        ("slabinfo", VmStatFileKind::SlabInfo),
+    ] {
+        let inode = VmStatInode::new(root_dentry.superblock().unwrap(), kind);
+        let dentry = VmStatDentry::new(name, Some(inode), Some(Arc::downgrade(&root_dentry)));
+        root_dentry.add_child(dentry);
+    }
+
     // /proc/sys
     let sys_inode = SimpleInode::new(root_dentry.superblock().unwrap());
     sys_inode.set_inotype(InodeType::Dir);
@@ -135,6 +168,39 @@ pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     core_pattern_file.set_flags(OpenFlags::O_WRONLY);
     osfuture::block_on(async { core_pattern_file.write("core\0".as_bytes()).await })?;
 
+    // /proc/sys/vm
+    let vm_inode = SimpleInode::new(root_dentry.superblock().unwrap());
+    vm_inode.set_inotype(InodeType::Dir);
+    let vm_dentry: Arc<dyn Dentry> =
+        SimpleDentry::new("vm", Some(vm_inode), Some(Arc::downgrade(&sys_dentry)));
+    sys_dentry.add_child(vm_dentry.clone());
+
+    // /proc/sys/vm/overcommit_memory
+    let overcommit_memory_inode = SysctlInode::new(
+        root_dentry.superblock().unwrap(),
+        &OVERCOMMIT_MEMORY,
+        OVERCOMMIT_NEVER,
+    );
+    let overcommit_memory_dentry: Arc<dyn Dentry> = SysctlDentry::new(
+        "overcommit_memory",
+        Some(overcommit_memory_inode),
+        Some(Arc::downgrade(&vm_dentry)),
+    );
+    vm_dentry.add_child(overcommit_memory_dentry);
+
+    // /proc/sys/vm/overcommit_ratio
+    let overcommit_ratio_inode = SysctlInode::new(
+        root_dentry.superblock().unwrap(),
+        &OVERCOMMIT_RATIO,
+        usize::MAX,
+    );
+    let overcommit_ratio_dentry: Arc<dyn Dentry> = SysctlDentry::new(
+        "overcommit_ratio",
+        Some(overcommit_ratio_inode),
+        Some(Arc::downgrade(&vm_dentry)),
+    );
+    vm_dentry.add_child(overcommit_ratio_dentry);
+
     // /proc/cpuinfo
     let cpuinfo_inode = SimpleInode::new(root_dentry.superblock().unwrap());
     cpuinfo_inode.set_inotype(InodeType::Dir);
@@ -189,6 +255,15 @@ pub fn init_procfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
         MapsDentry::new(Some(maps_inode), Some(Arc::downgrade(&self_dentry)));
     self_dentry.add_child(maps_dentry);
 
+    // /proc/self/mem
+    let mem_inode = MemInode::new(root_dentry.superblock().unwrap(), 0);
+    let mem_dentry: Arc<dyn Dentry> =
+        MemDentry::new(Some(mem_inode), Some(Arc::downgrade(&self_dentry)));
+    self_dentry.add_child(mem_dentry);
+
+    // /proc/self/oom_score and /proc/self/oom_score_adj
+    add_oom_files(&self_dentry, 0);
+
     // /proc/config.gz
     init_config_file(root_dentry.clone())?;
 
@@ -240,6 +315,15 @@ pub fn create_thread_stat_file(tid: usize) {
         MapsDentry::new(Some(maps_inode), Some(Arc::downgrade(&num_dentry)));
     num_dentry.add_child(maps_dentry);
 
+    // /proc/<tid>/mem
+    let mem_inode = MemInode::new(root_dentry.superblock().unwrap(), tid);
+    let mem_dentry: Arc<dyn Dentry> =
+        MemDentry::new(Some(mem_inode), Some(Arc::downgrade(&num_dentry)));
+    num_dentry.add_child(mem_dentry);
+
+    // /proc/<tid>/oom_score and /proc/<tid>/oom_score_adj
+    add_oom_files(&num_dentry, tid);
+
     // /proc/<tid>/fdinfo
     let fdinfo_inode = SimpleInode::new(root_dentry.superblock().unwrap());
     fdinfo_inode.set_inotype(InodeType::Dir);
@@ -250,3 +334,14 @@ pub fn create_thread_stat_file(tid: usize) {
     );
     num_dentry.add_child(fdinfo_dentry);
 }
+
+fn add_oom_files(dir: &Arc<dyn Dentry>, tid: usize) {
+    for (name, kind) in [
+        ("oom_score", OomFileKind::Score),
+        ("oom_score_adj", OomFileKind::ScoreAdj),
+    ] {
+        let inode = OomInode::new(dir.superblock().unwrap(), tid, kind);
+        let dentry: Arc<dyn Dentry> = OomDentry::new(name, Some(inode), Some(Arc::downgrade(dir)));
+        dir.add_child(dentry);
+    }
+}
@@ -0,0 +1,74 @@
+use alloc::{boxed::Box, format};
+use core::cmp;
+
+use async_trait::async_trait;
+use crate_interface::call_interface;
+
+use systype::error::{SysError, SysResult, SyscallResult};
+use vfs::{
+    direntry::DirEntry,
+    file::{File, FileMeta},
+};
+
+use super::{OOM_SCORE_ADJ_MAX, OOM_SCORE_ADJ_MIN, OomFileKind, inode::OomInode};
+use crate::proc::__KernelProcIf_mod;
+
+pub struct OomFile {
+    pub(crate) meta: FileMeta,
+}
+
+#[async_trait]
+impl File for OomFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read(&self, buf: &mut [u8], offset: usize) -> SyscallResult {
+        let inode = self
+            .inode()
+            .downcast_arc::<OomInode>()
+            .unwrap_or_else(|_| unreachable!());
+        let tid = inode.thread_id;
+        let content = match inode.kind {
+            OomFileKind::Score => format!("{}\n", call_interface!(KernelProcIf::oom_score(tid))?),
+            OomFileKind::ScoreAdj => {
+                format!("{}\n", call_interface!(KernelProcIf::oom_score_adj(tid))?)
+            }
+        };
+        let len = cmp::min(content.len().saturating_sub(offset), buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        buf[..len].copy_from_slice(&content.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write(&self, buf: &[u8], _offset: usize) -> SyscallResult {
+        let inode = self
+            .inode()
+            .downcast_arc::<OomInode>()
+            .unwrap_or_else(|_| unreachable!());
+        if inode.kind != OomFileKind::ScoreAdj {
+            return Err(SysError::EACCES);
+        }
+        let adj = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|s| {
+                s.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                    .parse()
+                    .ok()
+            })
+            .filter(|adj| (OOM_SCORE_ADJ_MIN..=OOM_SCORE_ADJ_MAX).contains(adj))
+            .ok_or(SysError::EINVAL)?;
+        call_interface!(KernelProcIf::set_oom_score_adj(inode.thread_id, adj))?;
+        Ok(buf.len())
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_readlink(&self, _buf: &mut [u8]) -> SysResult<usize> {
+        panic!("OomFile does not support readlink");
+    }
+}
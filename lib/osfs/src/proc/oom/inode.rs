@@ -0,0 +1,60 @@
+use alloc::sync::Arc;
+
+use config::inode::InodeType;
+use systype::error::SysResult;
+use vfs::{
+    inode::{Inode, InodeMeta},
+    inoid::alloc_ino,
+    stat::Stat,
+    superblock::SuperBlock,
+};
+
+use super::OomFileKind;
+
+pub struct OomInode {
+    meta: InodeMeta,
+    pub thread_id: usize,
+    pub kind: OomFileKind,
+}
+
+impl OomInode {
+    pub fn new(super_block: Arc<dyn SuperBlock>, tid: usize, kind: OomFileKind) -> Arc<Self> {
+        let inode = Arc::new(Self {
+            meta: InodeMeta::new(alloc_ino(), super_block),
+            thread_id: tid,
+            kind,
+        });
+        inode.set_inotype(InodeType::File);
+        inode
+    }
+}
+
+impl Inode for OomInode {
+    fn get_meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        let len = inner.size;
+        Ok(Stat {
+            st_dev: 0, // non-real-file
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: 0,
+            st_gid: 0,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: (len / 512) as u64,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
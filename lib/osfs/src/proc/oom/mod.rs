@@ -0,0 +1,18 @@
+//! `/proc/<pid>/oom_score` and `/proc/<pid>/oom_score_adj`.
+
+pub mod dentry;
+pub mod file;
+pub mod inode;
+
+/// Smallest `oom_score_adj`, which exempts a process from the OOM killer.
+pub const OOM_SCORE_ADJ_MIN: isize = -1000;
+/// Largest `oom_score_adj`, which makes a process the preferred victim.
+pub const OOM_SCORE_ADJ_MAX: isize = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OomFileKind {
+    /// `oom_score`, read-only.
+    Score,
+    /// `oom_score_adj`, read-write.
+    ScoreAdj,
+}
@@ -0,0 +1,66 @@
+use alloc::{boxed::Box, format};
+use core::{cmp, sync::atomic::Ordering};
+
+use async_trait::async_trait;
+use crate_interface::call_interface;
+
+use systype::error::{SysError, SysResult, SyscallResult};
+use vfs::{
+    direntry::DirEntry,
+    file::{File, FileMeta},
+};
+
+use super::inode::SysctlInode;
+use crate::proc::__KernelProcIf_mod;
+
+pub struct SysctlFile {
+    pub(crate) meta: FileMeta,
+}
+
+#[async_trait]
+impl File for SysctlFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read(&self, buf: &mut [u8], offset: usize) -> SyscallResult {
+        let inode = self
+            .inode()
+            .downcast_arc::<SysctlInode>()
+            .unwrap_or_else(|_| unreachable!());
+        let content = format!("{}\n", inode.value.load(Ordering::Relaxed));
+        let len = cmp::min(content.len().saturating_sub(offset), buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        buf[..len].copy_from_slice(&content.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write(&self, buf: &[u8], _offset: usize) -> SyscallResult {
+        call_interface!(KernelProcIf::check_sysctl_write())?;
+        let inode = self
+            .inode()
+            .downcast_arc::<SysctlInode>()
+            .unwrap_or_else(|_| unreachable!());
+        let value = core::str::from_utf8(buf)
+            .ok()
+            .and_then(|s| {
+                s.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                    .parse()
+                    .ok()
+            })
+            .filter(|&value| value <= inode.max)
+            .ok_or(SysError::EINVAL)?;
+        inode.value.store(value, Ordering::Relaxed);
+        Ok(buf.len())
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_readlink(&self, _buf: &mut [u8]) -> SysResult<usize> {
+        panic!("SysctlFile does not support readlink");
+    }
+}
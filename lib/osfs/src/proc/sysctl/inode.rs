@@ -0,0 +1,65 @@
+use alloc::sync::Arc;
+use core::sync::atomic::AtomicUsize;
+
+use config::inode::InodeType;
+use systype::error::SysResult;
+use vfs::{
+    inode::{Inode, InodeMeta},
+    inoid::alloc_ino,
+    stat::Stat,
+    superblock::SuperBlock,
+};
+
+pub struct SysctlInode {
+    meta: InodeMeta,
+    /// The variable backing the file.
+    pub value: &'static AtomicUsize,
+    /// The largest value which may be written to the file.
+    pub max: usize,
+}
+
+impl SysctlInode {
+    pub fn new(
+        super_block: Arc<dyn SuperBlock>,
+        value: &'static AtomicUsize,
+        max: usize,
+    ) -> Arc<Self> {
+        let inode = Arc::new(Self {
+            meta: InodeMeta::new(alloc_ino(), super_block),
+            value,
+            max,
+        });
+        inode.set_inotype(InodeType::File);
+        inode
+    }
+}
+
+impl Inode for SysctlInode {
+    fn get_meta(&self) -> &InodeMeta {
+        &self.meta
+    }
+
+    fn get_attr(&self) -> SysResult<Stat> {
+        let inner = self.meta.inner.lock();
+        let mode = inner.mode.bits();
+        let len = inner.size;
+        Ok(Stat {
+            st_dev: 0, // non-real-file
+            st_ino: self.meta.ino as u64,
+            st_mode: mode,
+            st_nlink: 1,
+            st_uid: 0,
+            st_gid: 0,
+            st_rdev: 0,
+            __pad: 0,
+            st_size: 0,
+            st_blksize: 512,
+            __pad2: 0,
+            st_blocks: (len / 512) as u64,
+            st_atime: inner.atime,
+            st_mtime: inner.mtime,
+            st_ctime: inner.ctime,
+            unused: 0,
+        })
+    }
+}
@@ -0,0 +1,23 @@
+//! Integer-valued sysctl files under `/proc/sys`.
+//!
+//! Each file is backed by a static atomic variable, which the kernel reads directly.
+
+use core::sync::atomic::AtomicUsize;
+
+pub mod dentry;
+pub mod file;
+pub mod inode;
+
+/// Overcommit heuristic: refuse only allocations larger than the whole memory.
+pub const OVERCOMMIT_GUESS: usize = 0;
+/// Always overcommit.
+pub const OVERCOMMIT_ALWAYS: usize = 1;
+/// Never overcommit: the committed memory may not exceed the commit limit.
+pub const OVERCOMMIT_NEVER: usize = 2;
+
+/// `/proc/sys/vm/overcommit_memory`
+pub static OVERCOMMIT_MEMORY: AtomicUsize = AtomicUsize::new(OVERCOMMIT_GUESS);
+
+/// `/proc/sys/vm/overcommit_ratio`, the percentage of memory which may be committed in
+/// [`OVERCOMMIT_NEVER`] mode.
+pub static OVERCOMMIT_RATIO: AtomicUsize = AtomicUsize::new(50);
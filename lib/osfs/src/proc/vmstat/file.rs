@@ -0,0 +1,45 @@
+use alloc::boxed::Box;
+use core::cmp;
+
+use async_trait::async_trait;
+
+use systype::error::{SysError, SysResult};
+use vfs::{
+    direntry::DirEntry,
+    file::{File, FileMeta},
+};
+
+use super::inode::VmStatInode;
+
+pub struct VmStatFile {
+    pub(crate) meta: FileMeta,
+}
+
+#[async_trait]
+impl File for VmStatFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read(&self, buf: &mut [u8], pos: usize) -> SysResult<usize> {
+        let inode = self
+            .inode()
+            .downcast_arc::<VmStatInode>()
+            .unwrap_or_else(|_| unreachable!());
+        let content = inode.kind.content();
+        let len = cmp::min(content.len().saturating_sub(pos), buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        buf[..len].copy_from_slice(&content.as_bytes()[pos..pos + len]);
+        Ok(len)
+    }
+
+    async fn base_write(&self, _buf: &[u8], _offset: usize) -> SysResult<usize> {
+        Err(SysError::EACCES)
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+}
@@ -0,0 +1,101 @@
+//! `/proc/vmstat`, `/proc/buddyinfo` and `/proc/slabinfo`.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+
+use config::mm::PAGE_SIZE;
+use crate_interface::call_interface;
+use mm::{
+    frame::{free_blocks_by_order, free_frames},
+    heap::{heap_objects, heap_used},
+    stat::VmStat,
+};
+
+use super::__KernelProcIf_mod;
+
+pub mod dentry;
+pub mod file;
+pub mod inode;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VmStatFileKind {
+    /// `vmstat`, counters of pages and memory management events.
+    VmStat,
+    /// `buddyinfo`, numbers of free blocks of each order.
+    BuddyInfo,
+    /// `slabinfo`, numbers of kernel heap objects of each size class.
+    SlabInfo,
+}
+
+impl VmStatFileKind {
+    /// Returns the contents of the file.
+    pub fn content(self) -> String {
+        match self {
+            Self::VmStat => vmstat(),
+            Self::BuddyInfo => buddyinfo(),
+            Self::SlabInfo => slabinfo(),
+        }
+    }
+}
+
+fn vmstat() -> String {
+    let mut res = format!("nr_free_pages {}\n", free_frames());
+    for stat in VmStat::ALL {
+        res += format!("{} {}\n", stat.name(), stat.get()).as_str();
+    }
+    res += format!(
+        "nr_slab_unreclaimable {}\n",
+        heap_used().div_ceil(PAGE_SIZE)
+    )
+    .as_str();
+    res += format!(
+        "nr_anon_transparent_hugepages {}\n",
+        call_interface!(KernelProcIf::anon_huge_pages())
+    )
+    .as_str();
+    res
+}
+
+fn buddyinfo() -> String {
+    let mut res = format!("Node 0, zone {:>8} ", "Normal");
+    for count in free_blocks_by_order() {
+        res += format!("{count:>6} ").as_str();
+    }
+    res += "\n";
+    res
+}
+
+/// Largest size class always listed in `/proc/slabinfo`, which is `kmalloc-8k`.
+/// Larger size classes are listed only if they have objects.
+const SLABINFO_MIN_CLASSES: usize = 13;
+
+fn slabinfo() -> String {
+    let mut res = "slabinfo - version: 2.1\n".to_string();
+    res += "# name            <active_objs> <num_objs> <objsize> <objperslab> \
+            <pagesperslab> : tunables <limit> <batchcount> <sharedfactor> \
+            : slabdata <active_slabs> <num_slabs> <sharedavail>\n";
+    // The smallest blocks of the buddy allocator hold a pointer.
+    let min_class = size_of::<usize>().trailing_zeros() as usize;
+    for (class, objects) in heap_objects().into_iter().enumerate().skip(min_class) {
+        if class > SLABINFO_MIN_CLASSES && objects == 0 {
+            continue;
+        }
+        let size = 1usize << class;
+        let name = match size {
+            size if size >= 1 << 20 => format!("kmalloc-{}M", size >> 20),
+            size if size >= 1 << 10 => format!("kmalloc-{}k", size >> 10),
+            size => format!("kmalloc-{size}"),
+        };
+        let objs_per_slab = (PAGE_SIZE / size).max(1);
+        let pages_per_slab = (size / PAGE_SIZE).max(1);
+        let slabs = objects.div_ceil(objs_per_slab);
+        res += format!(
+            "{name:<17} {objects:>6} {objects:>6} {size:>6} {objs_per_slab:>4} {pages_per_slab:>4} \
+             : tunables    0    0    0 : slabdata {slabs:>6} {slabs:>6}      0\n"
+        )
+        .as_str();
+    }
+    res
+}
@@ -1,5 +1,16 @@
-use alloc::{string::String, vec::Vec};
-use core::mem;
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    mem,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use vfs::{inode::Inode, inotify::InotifyMark};
+
+use super::{flags::InotifyMask, inode::InotifyInode};
 
 #[repr(C)]
 #[derive(Debug, Clone)]
@@ -77,21 +88,72 @@ impl InotifyEvent {
     }
 }
 
-#[derive(Debug, Clone)]
+/// A watch of an inotify instance on an inode.
+///
+/// The watch is attached to the inode as an [`InotifyMark`], and is detached when it
+/// is removed from its instance and dropped.
 pub struct InotifyWatch {
     pub wd: i32,
-    pub inode_id: u64,
-    pub mask: u32,
+    /// The watched inode.
+    pub inode: Arc<dyn Inode>,
+    /// The mask of the watch, which contains the watched events and the `IN_ONESHOT`
+    /// and `IN_EXCL_UNLINK` flags.
+    pub mask: AtomicU32,
     pub path: Option<String>,
+    /// The inotify instance that owns this watch.
+    instance: Weak<InotifyInode>,
 }
 
 impl InotifyWatch {
-    pub fn new(wd: i32, inode_id: u64, mask: u32, path: Option<String>) -> Self {
+    pub fn new(
+        wd: i32,
+        inode: Arc<dyn Inode>,
+        mask: u32,
+        path: Option<String>,
+        instance: Weak<InotifyInode>,
+    ) -> Self {
         Self {
             wd,
-            inode_id,
-            mask,
+            inode,
+            mask: AtomicU32::new(mask),
             path,
+            instance,
+        }
+    }
+
+    /// Returns whether this watch is on `inode`.
+    pub fn watches(&self, inode: &Arc<dyn Inode>) -> bool {
+        Arc::ptr_eq(&self.inode, inode)
+    }
+}
+
+impl InotifyMark for InotifyWatch {
+    fn deliver(&self, mask: u32, cookie: u32, name: Option<&str>, unlinked: bool) {
+        let Some(instance) = self.instance.upgrade() else {
+            return;
+        };
+        let watch_mask = InotifyMask::from_bits_retain(self.mask.load(Ordering::Relaxed));
+        if name.is_some() && unlinked && watch_mask.contains(InotifyMask::IN_EXCL_UNLINK) {
+            return;
+        }
+
+        let events = mask & watch_mask.intersection(InotifyMask::IN_ALL_EVENTS).bits();
+        if events != 0 {
+            let mask = events | mask & InotifyMask::IN_ISDIR.bits();
+            instance.push_event(InotifyEvent::new(
+                self.wd,
+                mask,
+                cookie,
+                name.map(String::from),
+            ));
+        }
+
+        // A watch is removed after its first event if it is one-shot, or when the watched
+        // inode is deleted. The removal generates an `IN_IGNORED` event.
+        let oneshot = events != 0 && watch_mask.contains(InotifyMask::IN_ONESHOT);
+        let deleted = name.is_none() && mask & InotifyMask::IN_DELETE_SELF.bits() != 0;
+        if oneshot || deleted {
+            let _ = instance.remove_watch(self.wd);
         }
     }
 }
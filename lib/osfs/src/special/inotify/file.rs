@@ -1,6 +1,7 @@
 use alloc::boxed::Box;
 use alloc::sync::Arc;
 use async_trait::async_trait;
+use config::vfs::FileInternalFlags;
 use core::future::Future;
 use core::pin::Pin;
 use core::task::{Context, Poll};
@@ -8,6 +9,7 @@ use systype::error::{SysError, SysResult};
 use vfs::{
     dentry::Dentry,
     file::{File, FileMeta},
+    inode::Inode,
 };
 
 use super::{
@@ -21,9 +23,9 @@ pub struct InotifyFile {
 
 impl InotifyFile {
     pub fn new(dentry: Arc<dyn Dentry>) -> Arc<Self> {
-        Arc::new(Self {
-            meta: FileMeta::new(dentry),
-        })
+        let meta = FileMeta::new(dentry);
+        *meta.internal_flags.lock() |= FileInternalFlags::FMODE_NONOTIFY;
+        Arc::new(Self { meta })
     }
 
     pub fn into_dyn_ref(&self) -> &dyn File {
@@ -32,7 +34,7 @@ impl InotifyFile {
 
     pub fn add_watch(
         &self,
-        inode_id: u64,
+        target: Arc<dyn Inode>,
         mask: u32,
         path: Option<alloc::string::String>,
     ) -> SysResult<i32> {
@@ -44,7 +46,7 @@ impl InotifyFile {
         // Validate mask
         let valid_mask = InotifyMask::from_bits(mask).ok_or(SysError::EINVAL)?;
 
-        inotify_inode.add_watch(inode_id, valid_mask.bits(), path)
+        inotify_inode.add_watch(target, valid_mask.bits(), path)
     }
 
     pub fn remove_watch(&self, wd: i32) -> SysResult<()> {
@@ -73,21 +75,6 @@ impl InotifyFile {
 
         Ok(inotify_inode.has_events())
     }
-
-    pub fn notify_event(
-        &self,
-        inode_id: u64,
-        mask: u32,
-        name: Option<alloc::string::String>,
-    ) -> SysResult<()> {
-        let inode = self.inode();
-        let inotify_inode = inode
-            .downcast_arc::<InotifyInode>()
-            .map_err(|_| SysError::EINVAL)?;
-
-        inotify_inode.notify_inode_event(inode_id, mask, name);
-        Ok(())
-    }
 }
 
 pub struct InotifyReadFuture<'a> {
@@ -1,4 +1,4 @@
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
 use alloc::{collections::VecDeque, vec::Vec};
 use core::{
     sync::atomic::{AtomicI32, Ordering},
@@ -9,20 +9,23 @@ use systype::error::{SysError, SysResult};
 use vfs::{
     inode::{Inode, InodeMeta},
     inoid::alloc_ino,
+    inotify::{self, InotifyMark},
     stat::Stat,
+    superblock::SuperBlock,
     sys_root_dentry,
 };
 
 use super::{
+    INOTIFY_INSTANCES,
     event::{InotifyEvent, InotifyWatch},
-    flags::InotifyFlags,
+    flags::{InotifyFlags, InotifyMask},
 };
 
 pub struct InotifyInode {
     meta: InodeMeta,
     flags: InotifyFlags,
     next_wd: AtomicI32,
-    watches: SpinNoIrqLock<alloc::collections::BTreeMap<i32, InotifyWatch>>,
+    watches: SpinNoIrqLock<alloc::collections::BTreeMap<i32, Arc<InotifyWatch>>>,
     events: SpinNoIrqLock<VecDeque<InotifyEvent>>,
     wakers: SpinNoIrqLock<Vec<Waker>>,
     max_events: usize,
@@ -30,7 +33,7 @@ pub struct InotifyInode {
 
 impl InotifyInode {
     pub fn new(flags: InotifyFlags) -> Arc<Self> {
-        Arc::new(Self {
+        let instance = Arc::new(Self {
             meta: InodeMeta::new(alloc_ino(), sys_root_dentry().superblock().unwrap()),
             flags,
             next_wd: AtomicI32::new(1),
@@ -38,29 +41,53 @@ impl InotifyInode {
             events: SpinNoIrqLock::new(VecDeque::new()),
             wakers: SpinNoIrqLock::new(Vec::new()),
             max_events: 16384, // Default max events
-        })
+        });
+        INOTIFY_INSTANCES.lock().push(Arc::downgrade(&instance));
+        instance
     }
 
+    /// Adds a watch on `inode`, or modifies the existing watch on it, and returns the
+    /// watch descriptor.
+    ///
+    /// If `mask` contains `IN_MASK_ADD`, the events are added to the existing watch
+    /// rather than replacing them.
     pub fn add_watch(
-        &self,
-        inode_id: u64,
+        self: &Arc<Self>,
+        inode: Arc<dyn Inode>,
         mask: u32,
         path: Option<alloc::string::String>,
     ) -> SysResult<i32> {
-        let wd = self.next_wd.fetch_add(1, Ordering::SeqCst);
-        let watch = InotifyWatch::new(wd, inode_id, mask, path);
+        let mut watches = self.watches.lock();
+        if let Some(watch) = watches.values().find(|w| w.watches(&inode)) {
+            if mask & InotifyMask::IN_MASK_ADD.bits() != 0 {
+                watch.mask.fetch_or(mask, Ordering::Relaxed);
+            } else {
+                watch.mask.store(mask, Ordering::Relaxed);
+            }
+            return Ok(watch.wd);
+        }
 
-        self.watches.lock().insert(wd, watch);
+        let wd = self.next_wd.fetch_add(1, Ordering::SeqCst);
+        let watch = Arc::new(InotifyWatch::new(
+            wd,
+            Arc::clone(&inode),
+            mask,
+            path,
+            Arc::downgrade(self),
+        ));
+        inotify::add_mark(&inode, Arc::downgrade(&watch) as Weak<dyn InotifyMark>);
+
+        watches.insert(wd, watch);
         Ok(wd)
     }
 
     pub fn remove_watch(&self, wd: i32) -> SysResult<()> {
-        let mut watches = self.watches.lock();
-        if watches.remove(&wd).is_some() {
+        let removed = self.watches.lock().remove(&wd);
+        if removed.is_some() {
             // Generate IN_IGNORED event
             self.push_event_internal(InotifyEvent::new(
                 wd,
-                super::flags::InotifyMask::IN_IGNORED.bits(),
+                InotifyMask::IN_IGNORED.bits(),
                 0,
                 None,
             ));
@@ -82,11 +109,11 @@ impl InotifyInode {
             events.pop_front();
             if !events
                 .iter()
-                .any(|e| e.mask & super::flags::InotifyMask::IN_Q_OVERFLOW.bits() != 0)
+                .any(|e| e.mask & InotifyMask::IN_Q_OVERFLOW.bits() != 0)
             {
                 events.push_back(InotifyEvent::new(
                     -1,
-                    super::flags::InotifyMask::IN_Q_OVERFLOW.bits(),
+                    InotifyMask::IN_Q_OVERFLOW.bits(),
                     0,
                     None,
                 ));
@@ -155,20 +182,24 @@ impl InotifyInode {
         self.flags
     }
 
-    pub fn notify_inode_event(
-        &self,
-        inode_id: u64,
-        mask: u32,
-        name: Option<alloc::string::String>,
-    ) {
-        let watches = self.watches.lock();
-        for (wd, watch) in watches.iter() {
-            if watch.inode_id == inode_id && (watch.mask & mask) != 0 {
-                let event = InotifyEvent::new(*wd, mask, 0, name.clone());
-                drop(watches); // Release lock before pushing event
-                self.push_event_internal(event);
-                return;
-            }
+    /// Removes all watches on inodes of `superblock`, which is being unmounted, with an
+    /// `IN_UNMOUNT` event for each watch.
+    pub fn unmount(&self, superblock: &Arc<dyn SuperBlock>) {
+        let wds: Vec<i32> = self
+            .watches
+            .lock()
+            .values()
+            .filter(|w| Arc::ptr_eq(&w.inode.get_meta().superblock, superblock))
+            .map(|w| w.wd)
+            .collect();
+        for wd in wds {
+            self.push_event_internal(InotifyEvent::new(
+                wd,
+                InotifyMask::IN_UNMOUNT.bits(),
+                0,
+                None,
+            ));
+            let _ = self.remove_watch(wd);
         }
     }
 }
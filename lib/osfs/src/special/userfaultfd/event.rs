@@ -122,6 +122,36 @@ impl UserfaultfdMsg {
         }
     }
 
+    pub fn new_remap(from: u64, to: u64, len: u64) -> Self {
+        Self {
+            event: UserfaultfdEventType::Remap as u8,
+            reserved1: 0,
+            reserved2: 0,
+            reserved3: 0,
+            arg: UserfaultfdMsgArg {
+                remap: UserfaultfdMsgRemap { from, to, len },
+            },
+        }
+    }
+
+    /// Creates a remove event, or an unmap event if `unmap` is set
+    pub fn new_remove(start: u64, end: u64, unmap: bool) -> Self {
+        let event = if unmap {
+            UserfaultfdEventType::Unmap
+        } else {
+            UserfaultfdEventType::Remove
+        };
+        Self {
+            event: event as u8,
+            reserved1: 0,
+            reserved2: 0,
+            reserved3: 0,
+            arg: UserfaultfdMsgArg {
+                remove: UserfaultfdMsgRemove { start, end },
+            },
+        }
+    }
+
     /// Serialize message to buffer
     pub fn serialize_into(&self, buf: &mut [u8]) -> Result<usize, ()> {
         let size = mem::size_of::<UserfaultfdMsg>();
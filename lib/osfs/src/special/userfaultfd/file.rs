@@ -1,16 +1,27 @@
 use alloc::boxed::Box;
 use alloc::sync::Arc;
 use async_trait::async_trait;
+use config::{inode::InodeMode, vfs::PollEvents};
 use core::future::Future;
 use core::pin::Pin;
 use core::task::{Context, Poll};
-use systype::error::{SysError, SysResult};
+use systype::error::{SysError, SysResult, SyscallResult};
 use vfs::{
     dentry::Dentry,
     file::{File, FileMeta},
+    inode::Inode,
+    sys_root_dentry,
 };
 
-use super::{flags::UserfaultfdFlags, inode::UserfaultfdInode};
+use super::{
+    dentry::UserfaultfdDentry,
+    flags::UserfaultfdFlags,
+    inode::{UserfaultfdFaultFuture, UserfaultfdInode},
+    ioctl::{
+        UffdIoctlCmd, UffdioApi, UffdioContinue, UffdioCopy, UffdioRange, UffdioRegister,
+        UffdioWriteprotect, UffdioZeropage,
+    },
+};
 
 pub struct UserfaultfdFile {
     meta: FileMeta,
@@ -23,6 +34,18 @@ impl UserfaultfdFile {
         })
     }
 
+    /// Creates a file for `inode`, with an anonymous dentry.
+    pub fn with_inode(inode: Arc<UserfaultfdInode>) -> Arc<Self> {
+        inode.set_mode(InodeMode::REG);
+        let dentry = UserfaultfdDentry::new(
+            "userfaultfd",
+            Some(inode),
+            Some(Arc::downgrade(&sys_root_dentry())),
+        );
+        sys_root_dentry().add_child(dentry.clone());
+        Self::new(dentry)
+    }
+
     pub fn into_dyn_ref(&self) -> &dyn File {
         self
     }
@@ -58,33 +81,44 @@ impl UserfaultfdFile {
     }
 
     /// Handle pagefault
-    pub fn handle_pagefault(&self, address: u64, flags: u64, ptid: u32) -> SysResult<()> {
+    pub fn handle_pagefault(
+        &self,
+        address: u64,
+        flags: u64,
+        ptid: u32,
+    ) -> SysResult<UserfaultfdFaultFuture> {
         let inode = self.inode();
         let uffd_inode = inode
             .downcast_arc::<UserfaultfdInode>()
             .map_err(|_| SysError::EINVAL)?;
 
-        uffd_inode.handle_pagefault(address, flags, ptid)
+        Ok(uffd_inode.handle_pagefault(address, flags, ptid))
     }
 
-    /// Copy page to resolve fault
-    pub fn copy_page(&self, dst: u64, src: &[u8], mode: u64) -> SysResult<usize> {
+    /// Copy pages to resolve faults
+    pub fn copy_page(
+        &self,
+        dst: u64,
+        src: u64,
+        len: u64,
+        mode: u64,
+    ) -> SysResult<(u64, SysResult<()>)> {
         let inode = self.inode();
         let uffd_inode = inode
             .downcast_arc::<UserfaultfdInode>()
             .map_err(|_| SysError::EINVAL)?;
 
-        uffd_inode.copy_page(dst, src, mode)
+        Ok(uffd_inode.copy_page(dst, src, len, mode))
     }
 
-    /// Create zero page
-    pub fn zeropage(&self, dst: u64, len: u64, mode: u64) -> SysResult<usize> {
+    /// Create zero pages
+    pub fn zeropage(&self, dst: u64, len: u64, mode: u64) -> SysResult<(u64, SysResult<()>)> {
         let inode = self.inode();
         let uffd_inode = inode
             .downcast_arc::<UserfaultfdInode>()
             .map_err(|_| SysError::EINVAL)?;
 
-        uffd_inode.zeropage(dst, len, mode)
+        Ok(uffd_inode.zeropage(dst, len, mode))
     }
 
     /// Wake threads waiting on range
@@ -94,7 +128,7 @@ impl UserfaultfdFile {
             .downcast_arc::<UserfaultfdInode>()
             .map_err(|_| SysError::EINVAL)?;
 
-        uffd_inode.wake_range(start, len)
+        Ok(uffd_inode.wake_range(start, len))
     }
 
     /// Get API info
@@ -185,4 +219,99 @@ impl File for UserfaultfdFile {
     async fn base_write(&self, _buf: &[u8], _offset: usize) -> SysResult<usize> {
         Err(SysError::EINVAL)
     }
+
+    async fn base_poll(&self, events: PollEvents) -> PollEvents {
+        let mut res = PollEvents::empty();
+        if events.contains(PollEvents::IN) && self.has_events().unwrap_or(false) {
+            res |= PollEvents::IN;
+        }
+        res
+    }
+
+    fn ioctl(&self, cmd: usize, arg: usize) -> SyscallResult {
+        let Some(cmd) = UffdIoctlCmd::from_repr(cmd) else {
+            return Err(SysError::EINVAL);
+        };
+        let inode = self.inode();
+        let uffd_inode = inode
+            .downcast_arc::<UserfaultfdInode>()
+            .map_err(|_| SysError::EINVAL)?;
+
+        // The result of an ioctl resolving pages, which is the number of bytes resolved,
+        // or the error number if none is. A partial result fails with `EAGAIN`.
+        let resolved = |(bytes, result): (u64, SysResult<()>), out: &mut i64| match result {
+            Err(e) if bytes == 0 => {
+                *out = -(e as i64);
+                Err(e)
+            }
+            Err(_) => {
+                *out = bytes as i64;
+                Err(SysError::EAGAIN)
+            }
+            Ok(()) => {
+                *out = bytes as i64;
+                Ok(0)
+            }
+        };
+
+        // The argument has been checked to be a valid pointer by `sys_ioctl`.
+        unsafe {
+            match cmd {
+                UffdIoctlCmd::UFFDIO_API => {
+                    let api = &mut *(arg as *mut UffdioApi);
+                    let (features, ioctls) = uffd_inode.initialize_api(api.api, api.features)?;
+                    api.features = features;
+                    api.ioctls = ioctls;
+                    Ok(0)
+                }
+                UffdIoctlCmd::UFFDIO_REGISTER => {
+                    let register = &mut *(arg as *mut UffdioRegister);
+                    let range = register.range;
+                    register.ioctls =
+                        uffd_inode.register_range(range.start, range.len, register.mode)?;
+                    Ok(0)
+                }
+                UffdIoctlCmd::UFFDIO_UNREGISTER => {
+                    let range = *(arg as *const UffdioRange);
+                    uffd_inode.unregister_range(range.start, range.len)?;
+                    Ok(0)
+                }
+                UffdIoctlCmd::UFFDIO_WAKE => {
+                    let range = *(arg as *const UffdioRange);
+                    uffd_inode.wake_range(range.start, range.len);
+                    Ok(0)
+                }
+                UffdIoctlCmd::UFFDIO_COPY => {
+                    let copy = &mut *(arg as *mut UffdioCopy);
+                    let result = uffd_inode.copy_page(copy.dst, copy.src, copy.len, copy.mode);
+                    resolved(result, &mut copy.copy)
+                }
+                UffdIoctlCmd::UFFDIO_ZEROPAGE => {
+                    let zeropage = &mut *(arg as *mut UffdioZeropage);
+                    let range = zeropage.range;
+                    let result = uffd_inode.zeropage(range.start, range.len, zeropage.mode);
+                    resolved(result, &mut zeropage.zeropage)
+                }
+                UffdIoctlCmd::UFFDIO_WRITEPROTECT => {
+                    let wp = *(arg as *const UffdioWriteprotect);
+                    uffd_inode.write_protect(wp.range.start, wp.range.len, wp.mode)?;
+                    Ok(0)
+                }
+                UffdIoctlCmd::UFFDIO_CONTINUE => {
+                    let cont = &mut *(arg as *mut UffdioContinue);
+                    let range = cont.range;
+                    let result = uffd_inode.continue_range(range.start, range.len, cont.mode);
+                    resolved(result, &mut cont.mapped)
+                }
+            }
+        }
+    }
+}
+
+impl Drop for UserfaultfdFile {
+    fn drop(&mut self) {
+        if let Ok(uffd_inode) = self.inode().downcast_arc::<UserfaultfdInode>() {
+            uffd_inode.release();
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use bitflags::bitflags;
 use common::atomic_bitflags;
+use config::vfs::OpenFlags;
 
 bitflags! {
     #[derive(Debug, Clone, Copy)]
@@ -13,6 +14,20 @@ bitflags! {
     }
 }
 
+impl UserfaultfdFlags {
+    /// Returns the open flags of a file for a userfaultfd with these flags.
+    pub fn open_flags(self) -> OpenFlags {
+        let mut file_flags = OpenFlags::O_RDONLY;
+        if self.contains(Self::UFFD_CLOEXEC) {
+            file_flags |= OpenFlags::O_CLOEXEC;
+        }
+        if self.contains(Self::UFFD_NONBLOCK) {
+            file_flags |= OpenFlags::O_NONBLOCK;
+        }
+        file_flags
+    }
+}
+
 bitflags! {
     #[derive(Debug, Clone, Copy)]
     pub struct UserfaultfdFeatures: u64 {
@@ -22,19 +37,19 @@ bitflags! {
         const UFFD_FEATURE_EVENT_FORK = 1 << 1;
         const UFFD_FEATURE_EVENT_REMAP = 1 << 2;
         const UFFD_FEATURE_EVENT_REMOVE = 1 << 3;
-        const UFFD_FEATURE_EVENT_UNMAP = 1 << 4;
         /// Support for missing mode on hugetlbfs
-        const UFFD_FEATURE_MISSING_HUGETLBFS = 1 << 5;
+        const UFFD_FEATURE_MISSING_HUGETLBFS = 1 << 4;
         /// Support for missing mode on shmem
-        const UFFD_FEATURE_MISSING_SHMEM = 1 << 6;
-        /// Support for minor mode on hugetlbfs
-        const UFFD_FEATURE_MINOR_HUGETLBFS = 1 << 7;
-        /// Support for minor mode on shmem
-        const UFFD_FEATURE_MINOR_SHMEM = 1 << 8;
+        const UFFD_FEATURE_MISSING_SHMEM = 1 << 5;
+        const UFFD_FEATURE_EVENT_UNMAP = 1 << 6;
         /// Support for SIGBUS feature
-        const UFFD_FEATURE_SIGBUS = 1 << 9;
+        const UFFD_FEATURE_SIGBUS = 1 << 7;
         /// Support for thread ID in events
-        const UFFD_FEATURE_THREAD_ID = 1 << 10;
+        const UFFD_FEATURE_THREAD_ID = 1 << 8;
+        /// Support for minor mode on hugetlbfs
+        const UFFD_FEATURE_MINOR_HUGETLBFS = 1 << 9;
+        /// Support for minor mode on shmem
+        const UFFD_FEATURE_MINOR_SHMEM = 1 << 10;
     }
 }
 
@@ -51,26 +66,41 @@ bitflags! {
 }
 
 bitflags! {
+    /// Ioctls reported by `UFFDIO_API` and `UFFDIO_REGISTER`, each of which is the bit
+    /// of its ioctl number.
     #[derive(Debug, Clone, Copy)]
     pub struct UserfaultfdIoctls: u64 {
-        /// API handshake ioctl
-        const UFFDIO_API = 1 << 0;
         /// Register memory range
-        const UFFDIO_REGISTER = 1 << 1;
+        const UFFDIO_REGISTER = 1 << 0x00;
         /// Unregister memory range
-        const UFFDIO_UNREGISTER = 1 << 2;
+        const UFFDIO_UNREGISTER = 1 << 0x01;
         /// Wake up blocked threads
-        const UFFDIO_WAKE = 1 << 3;
+        const UFFDIO_WAKE = 1 << 0x02;
         /// Copy pages to resolve faults
-        const UFFDIO_COPY = 1 << 4;
+        const UFFDIO_COPY = 1 << 0x03;
         /// Zero pages to resolve faults
-        const UFFDIO_ZEROPAGE = 1 << 5;
+        const UFFDIO_ZEROPAGE = 1 << 0x04;
         /// Write protect pages
-        const UFFDIO_WRITEPROTECT = 1 << 6;
+        const UFFDIO_WRITEPROTECT = 1 << 0x06;
         /// Continue execution after minor fault
-        const UFFDIO_CONTINUE = 1 << 7;
+        const UFFDIO_CONTINUE = 1 << 0x07;
         /// Poison pages
-        const UFFDIO_POISON = 1 << 8;
+        const UFFDIO_POISON = 1 << 0x08;
+        /// API handshake ioctl
+        const UFFDIO_API = 1 << 0x3f;
+    }
+}
+
+bitflags! {
+    /// Flags of a pagefault event, telling the kind of the fault.
+    #[derive(Debug, Clone, Copy)]
+    pub struct UserfaultfdPagefaultFlags: u64 {
+        /// The fault is a write access
+        const UFFD_PAGEFAULT_FLAG_WRITE = 1 << 0;
+        /// The fault is a write to a write-protected page
+        const UFFD_PAGEFAULT_FLAG_WP = 1 << 1;
+        /// The fault is on a page whose content exists but is not mapped
+        const UFFD_PAGEFAULT_FLAG_MINOR = 1 << 2;
     }
 }
 
@@ -1,11 +1,17 @@
-use alloc::collections::{BTreeMap, VecDeque};
-use alloc::sync::Arc;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
+use config::mm::PAGE_SIZE;
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
 use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use core::task::Waker;
+use core::task::{Context, Poll, Waker};
+use crate_interface::call_interface;
 use mutex::SpinNoIrqLock;
 use systype::error::{SysError, SysResult};
 use vfs::{
+    fanotify::kinterface::KernelFdTableOperations,
     inode::{Inode, InodeMeta},
     inoid::alloc_ino,
     stat::Stat,
@@ -14,9 +20,25 @@ use vfs::{
 
 use super::{
     event::{UserfaultfdMsg, UserfaultfdRange},
-    flags::{UserfaultfdFeatures, UserfaultfdFlags, UserfaultfdIoctls},
+    file::UserfaultfdFile,
+    flags::{UserfaultfdFeatures, UserfaultfdFlags, UserfaultfdIoctls, UserfaultfdRegisterMode},
+    mm::UserfaultMm,
 };
 
+/// An event queued on a userfaultfd
+enum QueuedEvent {
+    Msg(UserfaultfdMsg),
+    /// A fork event with the userfaultfd of the child, which is opened in the file
+    /// descriptor table of the task reading the event
+    Fork(Arc<UserfaultfdInode>),
+}
+
+/// A task waiting for the page fault at `address` to be resolved
+struct FaultWaiter {
+    address: u64,
+    waker: Waker,
+}
+
 pub struct UserfaultfdInode {
     meta: InodeMeta,
     flags: UserfaultfdFlags,
@@ -28,20 +50,76 @@ pub struct UserfaultfdInode {
     ioctls: AtomicU64,
     /// Whether API handshake completed
     api_initialized: AtomicBool,
+    /// Whether the file is closed, after which faults are no longer handled
+    released: AtomicBool,
     /// Registered memory ranges
     ranges: SpinNoIrqLock<BTreeMap<u64, UserfaultfdRange>>,
+    /// Addresses of the pages write-protected by `UFFDIO_WRITEPROTECT`
+    protected: SpinNoIrqLock<BTreeSet<u64>>,
     /// Event queue
-    events: SpinNoIrqLock<VecDeque<UserfaultfdMsg>>,
+    events: SpinNoIrqLock<VecDeque<QueuedEvent>>,
     /// Waker queue for blocked readers
     wakers: SpinNoIrqLock<Vec<Waker>>,
+    /// Tasks waiting for their page faults to be resolved, by waiter id
+    waiters: SpinNoIrqLock<BTreeMap<u64, FaultWaiter>>,
+    /// Id of the next waiter
+    next_waiter: AtomicU64,
     /// Maximum events in queue
     max_events: usize,
-    /// Associated process memory management
-    mm_id: u64,
+    /// Memory whose faults are handled
+    mm: Weak<dyn UserfaultMm>,
+}
+
+/// Removes `[start, end)` from `ranges`, splitting the ranges across its bounds, and
+/// returns the parts removed.
+fn remove_ranges(
+    ranges: &mut BTreeMap<u64, UserfaultfdRange>,
+    start: u64,
+    end: u64,
+) -> Vec<UserfaultfdRange> {
+    let overlapping: Vec<u64> = ranges
+        .values()
+        .filter(|range| range.start < end && range.end > start)
+        .map(|range| range.start)
+        .collect();
+    let mut removed = Vec::new();
+    for key in overlapping {
+        let range = ranges.remove(&key).unwrap();
+        if range.start < start {
+            let low = UserfaultfdRange {
+                end: start,
+                ..range.clone()
+            };
+            ranges.insert(low.start, low);
+        }
+        if range.end > end {
+            let high = UserfaultfdRange {
+                start: end,
+                ..range.clone()
+            };
+            ranges.insert(high.start, high);
+        }
+        removed.push(UserfaultfdRange {
+            start: range.start.max(start),
+            end: range.end.min(end),
+            ..range
+        });
+    }
+    removed
+}
+
+/// Checks that `[start, start + len)` is a nonempty page-aligned range, and returns its
+/// end.
+fn check_range(start: u64, len: u64) -> SysResult<u64> {
+    let end = start.checked_add(len).ok_or(SysError::EINVAL)?;
+    if start % PAGE_SIZE as u64 != 0 || len % PAGE_SIZE as u64 != 0 || len == 0 {
+        return Err(SysError::EINVAL);
+    }
+    Ok(end)
 }
 
 impl UserfaultfdInode {
-    pub fn new(flags: UserfaultfdFlags, mm_id: u64) -> Arc<Self> {
+    pub fn new(flags: UserfaultfdFlags, mm: Weak<dyn UserfaultMm>) -> Arc<Self> {
         Arc::new(Self {
             meta: InodeMeta::new(alloc_ino(), sys_root_dentry().superblock().unwrap()),
             flags,
@@ -49,14 +127,32 @@ impl UserfaultfdInode {
             features: AtomicU64::new(0),
             ioctls: AtomicU64::new(0),
             api_initialized: AtomicBool::new(false),
+            released: AtomicBool::new(false),
             ranges: SpinNoIrqLock::new(BTreeMap::new()),
+            protected: SpinNoIrqLock::new(BTreeSet::new()),
             events: SpinNoIrqLock::new(VecDeque::new()),
             wakers: SpinNoIrqLock::new(Vec::new()),
+            waiters: SpinNoIrqLock::new(BTreeMap::new()),
+            next_waiter: AtomicU64::new(0),
             max_events: 64, // Reasonable default
-            mm_id,
+            mm,
         })
     }
 
+    /// Creates a userfaultfd for the memory `mm` of a child forked from the memory of
+    /// this userfaultfd, with the same features and registered ranges
+    pub fn fork(&self, mm: Weak<dyn UserfaultMm>) -> Arc<Self> {
+        let child = Self::new(self.flags, mm);
+        let (api_version, features, ioctls) = self.get_api_info();
+        child.api_version.store(api_version, Ordering::Relaxed);
+        child.features.store(features, Ordering::Relaxed);
+        child.ioctls.store(ioctls, Ordering::Relaxed);
+        child.api_initialized.store(true, Ordering::Relaxed);
+        *child.ranges.lock() = self.ranges.lock().clone();
+        *child.protected.lock() = self.protected.lock().clone();
+        child
+    }
+
     /// Initialize API version and features
     pub fn initialize_api(
         &self,
@@ -69,22 +165,23 @@ impl UserfaultfdInode {
 
         // Determine supported features
         let mut supported_features = UserfaultfdFeatures::empty();
+        supported_features |= UserfaultfdFeatures::UFFD_FEATURE_PAGEFAULT_FLAG_WP;
         supported_features |= UserfaultfdFeatures::UFFD_FEATURE_EVENT_FORK;
         supported_features |= UserfaultfdFeatures::UFFD_FEATURE_EVENT_REMAP;
         supported_features |= UserfaultfdFeatures::UFFD_FEATURE_EVENT_REMOVE;
         supported_features |= UserfaultfdFeatures::UFFD_FEATURE_EVENT_UNMAP;
         supported_features |= UserfaultfdFeatures::UFFD_FEATURE_MISSING_SHMEM;
+        supported_features |= UserfaultfdFeatures::UFFD_FEATURE_MINOR_SHMEM;
         supported_features |= UserfaultfdFeatures::UFFD_FEATURE_SIGBUS;
+        supported_features |= UserfaultfdFeatures::UFFD_FEATURE_THREAD_ID;
 
         let enabled_features = supported_features.bits() & requested_features;
 
         // Set available ioctls
         let mut available_ioctls = UserfaultfdIoctls::empty();
+        available_ioctls |= UserfaultfdIoctls::UFFDIO_API;
         available_ioctls |= UserfaultfdIoctls::UFFDIO_REGISTER;
         available_ioctls |= UserfaultfdIoctls::UFFDIO_UNREGISTER;
-        available_ioctls |= UserfaultfdIoctls::UFFDIO_WAKE;
-        available_ioctls |= UserfaultfdIoctls::UFFDIO_COPY;
-        available_ioctls |= UserfaultfdIoctls::UFFDIO_ZEROPAGE;
 
         self.api_version.store(api_version, Ordering::Relaxed);
         self.features.store(enabled_features, Ordering::Relaxed);
@@ -95,77 +192,142 @@ impl UserfaultfdInode {
         Ok((enabled_features, available_ioctls.bits()))
     }
 
-    /// Register a memory range for userfault handling
-    pub fn register_range(&self, start: u64, len: u64, mode: u64) -> SysResult<u64> {
-        if !self.api_initialized.load(Ordering::Relaxed) {
-            return Err(SysError::EINVAL);
-        }
+    /// Returns whether `feature` is enabled
+    pub fn has_feature(&self, feature: UserfaultfdFeatures) -> bool {
+        UserfaultfdFeatures::from_bits_truncate(self.features.load(Ordering::Relaxed))
+            .contains(feature)
+    }
 
-        // Validate alignment
-        if start % 4096 != 0 || len % 4096 != 0 {
+    /// Returns the memory whose faults are handled.
+    fn mm(&self) -> SysResult<Arc<dyn UserfaultMm>> {
+        if !self.api_initialized.load(Ordering::Relaxed) {
             return Err(SysError::EINVAL);
         }
+        self.mm.upgrade().ok_or(SysError::ESRCH)
+    }
 
-        if len == 0 {
+    /// Register a memory range for userfault handling, and returns the ioctls available
+    /// for the range
+    pub fn register_range(&self, start: u64, len: u64, mode: u64) -> SysResult<u64> {
+        let mm = self.mm()?;
+        let end = check_range(start, len)?;
+        let mode = UserfaultfdRegisterMode::from_bits(mode).ok_or(SysError::EINVAL)?;
+        if mode.is_empty() {
             return Err(SysError::EINVAL);
         }
 
-        let range = UserfaultfdRange::new(start, len, mode);
         let mut ranges = self.ranges.lock();
-
         // Check for overlaps
-        for existing in ranges.values() {
-            if !(range.end <= existing.start || range.start >= existing.end) {
-                return Err(SysError::EEXIST);
-            }
+        if ranges
+            .values()
+            .any(|existing| existing.start < end && existing.end > start)
+        {
+            return Err(SysError::EEXIST);
         }
-
+        if mm.is_registered(start as usize, end as usize) {
+            return Err(SysError::EBUSY);
+        }
+        let minor = mode.contains(UserfaultfdRegisterMode::UFFDIO_REGISTER_MODE_MINOR);
+        mm.check_register(start as usize, end as usize, minor)?;
+
+        let mut ioctls = UserfaultfdIoctls::UFFDIO_WAKE
+            | UserfaultfdIoctls::UFFDIO_COPY
+            | UserfaultfdIoctls::UFFDIO_ZEROPAGE;
+        if mode.contains(UserfaultfdRegisterMode::UFFDIO_REGISTER_MODE_WP) {
+            ioctls |= UserfaultfdIoctls::UFFDIO_WRITEPROTECT;
+        }
+        if minor {
+            ioctls |= UserfaultfdIoctls::UFFDIO_CONTINUE;
+        }
+        let range = UserfaultfdRange {
+            ioctls: ioctls.bits(),
+            ..UserfaultfdRange::new(start, len, mode.bits())
+        };
         ranges.insert(start, range);
-        Ok(UserfaultfdIoctls::all().bits()) // Return available ioctls for this range
+        Ok(ioctls.bits())
     }
 
-    /// Unregister a memory range
+    /// Unregister a memory range, waking up the tasks waiting for faults in it
     pub fn unregister_range(&self, start: u64, len: u64) -> SysResult<()> {
-        if !self.api_initialized.load(Ordering::Relaxed) {
-            return Err(SysError::EINVAL);
-        }
-
-        let mut ranges = self.ranges.lock();
-        let end = start + len;
-
-        // Remove ranges that overlap with the specified region
-        ranges.retain(|_, range| !(range.start < end && range.end > start));
-
+        self.mm()?;
+        let end = check_range(start, len)?;
+        remove_ranges(&mut self.ranges.lock(), start, end);
+        self.protected
+            .lock()
+            .retain(|&page| page < start || page >= end);
+        self.wake_range(start, len);
         Ok(())
     }
 
-    /// Handle a page fault
-    pub fn handle_pagefault(&self, address: u64, flags: u64, ptid: u32) -> SysResult<()> {
+    /// Returns the registration mode of the range containing `address`, if any
+    pub fn mode_at(&self, address: u64) -> Option<UserfaultfdRegisterMode> {
         let ranges = self.ranges.lock();
+        ranges
+            .range(..=address)
+            .next_back()
+            .filter(|(_, range)| range.contains(address))
+            .map(|(_, range)| UserfaultfdRegisterMode::from_bits_truncate(range.mode))
+    }
 
-        // Check if address is in a registered range
-        let mut in_range = false;
-        for range in ranges.values() {
-            if range.contains(address) {
-                in_range = true;
-                break;
-            }
-        }
-        drop(ranges);
+    /// Returns whether any registered range overlaps with `[start, end)`
+    pub fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.ranges
+            .lock()
+            .values()
+            .any(|range| range.start < end && range.end > start)
+    }
 
-        if !in_range {
-            return Err(SysError::EFAULT);
+    /// Returns whether `[start, end)` is entirely within registered ranges
+    fn covers(&self, start: u64, end: u64) -> bool {
+        let ranges = self.ranges.lock();
+        let mut covered = start;
+        for range in ranges.range(..end).map(|(_, range)| range) {
+            if range.start <= covered && range.end > covered {
+                covered = range.end;
+            }
         }
+        covered >= end
+    }
 
-        // Create and queue pagefault event
-        let msg = UserfaultfdMsg::new_pagefault(address, flags, ptid);
-        self.push_event(msg);
+    /// Returns whether the page at `address` is write-protected
+    pub fn is_write_protected(&self, address: u64) -> bool {
+        let page = address & !(PAGE_SIZE as u64 - 1);
+        self.protected.lock().contains(&page)
+    }
 
-        Ok(())
+    /// Handle a page fault, by queueing a pagefault event and returning a future which
+    /// waits until the fault is resolved
+    pub fn handle_pagefault(
+        self: &Arc<Self>,
+        address: u64,
+        flags: u64,
+        ptid: u32,
+    ) -> UserfaultfdFaultFuture {
+        let ptid = if self.has_feature(UserfaultfdFeatures::UFFD_FEATURE_THREAD_ID) {
+            ptid
+        } else {
+            0
+        };
+        UserfaultfdFaultFuture {
+            inode: Arc::clone(self),
+            address,
+            msg: UserfaultfdMsg::new_pagefault(address, flags, ptid),
+            id: None,
+        }
     }
 
     /// Push an event to the queue
     pub fn push_event(&self, event: UserfaultfdMsg) {
+        self.push_queued_event(QueuedEvent::Msg(event));
+    }
+
+    /// Push a fork event for `child`, the userfaultfd of the forked memory, to the
+    /// queue. The reader of the event gets a new file descriptor of `child`.
+    pub fn push_fork_event(&self, child: Arc<UserfaultfdInode>) {
+        self.push_queued_event(QueuedEvent::Fork(child));
+    }
+
+    fn push_queued_event(&self, event: QueuedEvent) {
         let mut events = self.events.lock();
 
         if events.len() >= self.max_events {
@@ -219,7 +381,23 @@ impl UserfaultfdInode {
                 break;
             }
 
-            let event = events.pop_front().unwrap();
+            let event = match events.pop_front().unwrap() {
+                QueuedEvent::Msg(msg) => msg,
+                QueuedEvent::Fork(child) => {
+                    let file = UserfaultfdFile::with_inode(child.clone());
+                    let flags = child.get_flags().open_flags();
+                    match call_interface!(KernelFdTableOperations::add_file(file, flags)) {
+                        Ok(fd) => UserfaultfdMsg::new_fork(fd as u32),
+                        Err(e) => {
+                            events.push_front(QueuedEvent::Fork(child));
+                            if total_bytes == 0 {
+                                return Err(e);
+                            }
+                            break;
+                        }
+                    }
+                }
+            };
             match event.serialize_into(&mut buf[buf_offset..]) {
                 Ok(bytes) => {
                     buf_offset += bytes;
@@ -259,36 +437,248 @@ impl UserfaultfdInode {
         )
     }
 
-    /// Copy data to resolve a pagefault
-    pub fn copy_page(&self, dst: u64, src: &[u8], mode: u64) -> SysResult<usize> {
-        if src.len() != 4096 {
-            return Err(SysError::EINVAL);
+    /// Resolves the pages in the registered range `[start, start + len)` one by one
+    /// with `resolve_page`, then wakes up the tasks waiting for them unless `dontwake`
+    /// is set.
+    ///
+    /// Returns the number of bytes resolved, with the error which stopped resolving the
+    /// rest of the pages, if any.
+    fn resolve(
+        &self,
+        start: u64,
+        len: u64,
+        dontwake: bool,
+        mut resolve_page: impl FnMut(&dyn UserfaultMm, u64) -> SysResult<()>,
+    ) -> (u64, SysResult<()>) {
+        let check = || {
+            let mm = self.mm()?;
+            let end = check_range(start, len)?;
+            if !self.covers(start, end) {
+                return Err(SysError::ENOENT);
+            }
+            Ok(mm)
+        };
+        let mm = match check() {
+            Ok(mm) => mm,
+            Err(e) => return (0, Err(e)),
+        };
+        let mut resolved = 0;
+        let mut result = Ok(());
+        while resolved < len {
+            if let Err(e) = resolve_page(mm.as_ref(), start + resolved) {
+                result = Err(e);
+                break;
+            }
+            resolved += PAGE_SIZE as u64;
+        }
+        if resolved > 0 && !dontwake {
+            self.wake_range(start, resolved);
         }
+        (resolved, result)
+    }
+
+    /// Marks the pages in `[start, start + len)` write-protected if `wp` is set, or else
+    /// unprotected.
+    fn set_protected(&self, start: u64, len: u64, wp: bool) {
+        let mut protected = self.protected.lock();
+        for page in (start..start + len).step_by(PAGE_SIZE) {
+            if wp {
+                protected.insert(page);
+            } else {
+                protected.remove(&page);
+            }
+        }
+    }
+
+    /// Copy the pages at the user address `src` to the missing pages at `dst`, to
+    /// resolve pagefaults
+    pub fn copy_page(&self, dst: u64, src: u64, len: u64, mode: u64) -> (u64, SysResult<()>) {
+        use super::ioctl::{UFFDIO_COPY_MODE_DONTWAKE, UFFDIO_COPY_MODE_WP};
+
+        let wp = mode & UFFDIO_COPY_MODE_WP != 0;
+        let dontwake = mode & UFFDIO_COPY_MODE_DONTWAKE != 0;
+        let (copied, result) = self.resolve(dst, len, dontwake, |mm, page| {
+            let src = (src + page - dst) as usize;
+            mm.fill_page(page as usize, Some(src), wp)
+        });
+        self.set_protected(dst, copied, wp);
+        (copied, result)
+    }
+
+    /// Create zero pages to resolve pagefaults
+    pub fn zeropage(&self, dst: u64, len: u64, mode: u64) -> (u64, SysResult<()>) {
+        use super::ioctl::UFFDIO_ZEROPAGE_MODE_DONTWAKE;
 
-        // In a real implementation, this would:
-        // 1. Validate the destination address is in a registered range
-        // 2. Copy the data to the process's memory space
-        // 3. Update page tables
-        // 4. Wake up any threads waiting on this address
+        let dontwake = mode & UFFDIO_ZEROPAGE_MODE_DONTWAKE != 0;
+        let (zeroed, result) = self.resolve(dst, len, dontwake, |mm, page| {
+            mm.fill_page(page as usize, None, false)
+        });
+        self.set_protected(dst, zeroed, false);
+        (zeroed, result)
+    }
 
-        todo!()
+    /// Map the existing pages of shared memory to resolve minor faults
+    pub fn continue_range(&self, start: u64, len: u64, mode: u64) -> (u64, SysResult<()>) {
+        use super::ioctl::{UFFDIO_CONTINUE_MODE_DONTWAKE, UFFDIO_CONTINUE_MODE_WP};
+
+        let wp = mode & UFFDIO_CONTINUE_MODE_WP != 0;
+        let dontwake = mode & UFFDIO_CONTINUE_MODE_DONTWAKE != 0;
+        let (mapped, result) = self.resolve(start, len, dontwake, |mm, page| {
+            mm.continue_page(page as usize, wp)
+        });
+        self.set_protected(start, mapped, wp);
+        (mapped, result)
     }
 
-    /// Create zero page to resolve a pagefault
-    pub fn zeropage(&self, dst: u64, len: u64, mode: u64) -> SysResult<usize> {
-        if len % 4096 != 0 || dst % 4096 != 0 {
+    /// Write-protect or unprotect a range, waking up the tasks waiting for writes to
+    /// the unprotected pages
+    pub fn write_protect(&self, start: u64, len: u64, mode: u64) -> SysResult<()> {
+        use super::ioctl::{UFFDIO_WRITEPROTECT_MODE_DONTWAKE, UFFDIO_WRITEPROTECT_MODE_WP};
+
+        let mm = self.mm()?;
+        let end = check_range(start, len)?;
+        let wp = mode & UFFDIO_WRITEPROTECT_MODE_WP != 0;
+        if wp && mode & UFFDIO_WRITEPROTECT_MODE_DONTWAKE != 0 {
             return Err(SysError::EINVAL);
         }
+        if !self.covers(start, end) {
+            return Err(SysError::ENOENT);
+        }
+        mm.write_protect(start as usize, end as usize, wp)?;
+        self.set_protected(start, len, wp);
+        if !wp && mode & UFFDIO_WRITEPROTECT_MODE_DONTWAKE == 0 {
+            self.wake_range(start, len);
+        }
+        Ok(())
+    }
 
-        // In real implementation, would zero-fill pages and update mappings
-        todo!()
+    /// Wake up threads waiting on a range, and returns the number of them
+    pub fn wake_range(&self, start: u64, len: u64) -> usize {
+        let end = start.saturating_add(len);
+        let mut waiters = self.waiters.lock();
+        let woken: Vec<u64> = waiters
+            .iter()
+            .filter(|(_, waiter)| waiter.address >= start && waiter.address < end)
+            .map(|(&id, _)| id)
+            .collect();
+        for id in woken.iter() {
+            waiters.remove(id).unwrap().waker.wake();
+        }
+        woken.len()
     }
 
-    /// Wake up threads waiting on a range
-    pub fn wake_range(&self, start: u64, len: u64) -> SysResult<usize> {
-        // In real implementation, would wake threads blocked on addresses in range
-        // Return number of woken threads
-        todo!()
+    /// Moves the registration of `[from, from + len)` to `[to, to + len)` after the
+    /// memory is moved by `mremap()`, sending a remap event. Without the remap event
+    /// enabled, the moved memory is no longer registered.
+    pub fn remap(&self, from: u64, to: u64, len: u64) {
+        let moved = remove_ranges(&mut self.ranges.lock(), from, from + len);
+        if moved.is_empty() {
+            return;
+        }
+        let mut protected = self.protected.lock();
+        let moved_pages: Vec<u64> = protected.range(from..from + len).copied().collect();
+        for page in moved_pages.iter() {
+            protected.remove(page);
+        }
+        if !self.has_feature(UserfaultfdFeatures::UFFD_FEATURE_EVENT_REMAP) {
+            return;
+        }
+        protected.extend(moved_pages.iter().map(|page| page - from + to));
+        drop(protected);
+
+        let mut ranges = self.ranges.lock();
+        remove_ranges(&mut ranges, to, to + len);
+        for range in moved {
+            let range = UserfaultfdRange {
+                start: range.start - from + to,
+                end: range.end - from + to,
+                ..range
+            };
+            ranges.insert(range.start, range);
+        }
+        drop(ranges);
+        self.push_event(UserfaultfdMsg::new_remap(from, to, len));
+    }
+
+    /// Sends a remove event for `[start, end)` whose pages are dropped by `madvise()`,
+    /// or an unmap event if it is unmapped, in which case it is no longer registered
+    pub fn remove(&self, start: u64, end: u64, unmap: bool) {
+        if !self.overlaps(start, end) {
+            return;
+        }
+        let feature = if unmap {
+            remove_ranges(&mut self.ranges.lock(), start, end);
+            self.protected
+                .lock()
+                .retain(|&page| page < start || page >= end);
+            self.wake_range(start, end - start);
+            UserfaultfdFeatures::UFFD_FEATURE_EVENT_UNMAP
+        } else {
+            UserfaultfdFeatures::UFFD_FEATURE_EVENT_REMOVE
+        };
+        if self.has_feature(feature) {
+            self.push_event(UserfaultfdMsg::new_remove(start, end, unmap));
+        }
+    }
+
+    /// Stops handling faults after the file is closed, waking up all the waiting tasks
+    /// so that their faults are handled as if the memory is not registered
+    pub fn release(&self) {
+        self.released.store(true, Ordering::Relaxed);
+        self.ranges.lock().clear();
+        for (_, waiter) in mem::take(&mut *self.waiters.lock()) {
+            waiter.waker.wake();
+        }
+    }
+}
+
+/// Future of a task waiting for its page fault to be resolved, which queues the
+/// pagefault event on the first poll
+pub struct UserfaultfdFaultFuture {
+    inode: Arc<UserfaultfdInode>,
+    address: u64,
+    msg: UserfaultfdMsg,
+    id: Option<u64>,
+}
+
+impl Future for UserfaultfdFaultFuture {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inode = Arc::clone(&self.inode);
+        let mut waiters = inode.waiters.lock();
+        if inode.released.load(Ordering::Relaxed) {
+            return Poll::Ready(());
+        }
+        match self.id {
+            None => {
+                let id = inode.next_waiter.fetch_add(1, Ordering::Relaxed);
+                let waiter = FaultWaiter {
+                    address: self.address,
+                    waker: cx.waker().clone(),
+                };
+                waiters.insert(id, waiter);
+                drop(waiters);
+                self.id = Some(id);
+                inode.push_event(self.msg.clone());
+                Poll::Pending
+            }
+            Some(id) => match waiters.get_mut(&id) {
+                Some(waiter) => {
+                    waiter.waker = cx.waker().clone();
+                    Poll::Pending
+                }
+                None => Poll::Ready(()),
+            },
+        }
+    }
+}
+
+impl Drop for UserfaultfdFaultFuture {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.inode.waiters.lock().remove(&id);
+        }
     }
 }
 
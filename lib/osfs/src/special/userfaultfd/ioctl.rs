@@ -0,0 +1,120 @@
+use strum::FromRepr;
+
+/// Ioctls on a userfaultfd, defined in <linux/userfaultfd.h>
+#[allow(non_camel_case_types)]
+#[derive(FromRepr, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(usize)]
+pub enum UffdIoctlCmd {
+    /// Handshakes the API version and features, with a `UffdioApi`
+    UFFDIO_API = 0xc018aa3f,
+    /// Registers a memory range, with a `UffdioRegister`
+    UFFDIO_REGISTER = 0xc020aa00,
+    /// Unregisters a memory range, with a `UffdioRange`
+    UFFDIO_UNREGISTER = 0x8010aa01,
+    /// Wakes up the tasks waiting for faults in a range, with a `UffdioRange`
+    UFFDIO_WAKE = 0x8010aa02,
+    /// Resolves missing faults with copied pages, with a `UffdioCopy`
+    UFFDIO_COPY = 0xc028aa03,
+    /// Resolves missing faults with zeroed pages, with a `UffdioZeropage`
+    UFFDIO_ZEROPAGE = 0xc020aa04,
+    /// Write-protects or unprotects a range, with a `UffdioWriteprotect`
+    UFFDIO_WRITEPROTECT = 0xc018aa06,
+    /// Resolves minor faults with the existing pages, with a `UffdioContinue`
+    UFFDIO_CONTINUE = 0xc020aa07,
+}
+
+impl UffdIoctlCmd {
+    /// Returns the size of the argument of the ioctl.
+    pub fn arg_size(self) -> usize {
+        match self {
+            Self::UFFDIO_API => size_of::<UffdioApi>(),
+            Self::UFFDIO_REGISTER => size_of::<UffdioRegister>(),
+            Self::UFFDIO_UNREGISTER | Self::UFFDIO_WAKE => size_of::<UffdioRange>(),
+            Self::UFFDIO_COPY => size_of::<UffdioCopy>(),
+            Self::UFFDIO_ZEROPAGE => size_of::<UffdioZeropage>(),
+            Self::UFFDIO_WRITEPROTECT => size_of::<UffdioWriteprotect>(),
+            Self::UFFDIO_CONTINUE => size_of::<UffdioContinue>(),
+        }
+    }
+}
+
+/// Don't wake up the tasks waiting for the resolved pages
+pub const UFFDIO_COPY_MODE_DONTWAKE: u64 = 1 << 0;
+/// Map the copied pages write-protected
+pub const UFFDIO_COPY_MODE_WP: u64 = 1 << 1;
+/// Don't wake up the tasks waiting for the resolved pages
+pub const UFFDIO_ZEROPAGE_MODE_DONTWAKE: u64 = 1 << 0;
+/// Write-protect the range, or unprotect it if unset
+pub const UFFDIO_WRITEPROTECT_MODE_WP: u64 = 1 << 0;
+/// Don't wake up the tasks waiting for the unprotected pages
+pub const UFFDIO_WRITEPROTECT_MODE_DONTWAKE: u64 = 1 << 1;
+/// Don't wake up the tasks waiting for the resolved pages
+pub const UFFDIO_CONTINUE_MODE_DONTWAKE: u64 = 1 << 0;
+/// Map the resolved pages write-protected
+pub const UFFDIO_CONTINUE_MODE_WP: u64 = 1 << 1;
+
+/// `struct uffdio_api`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UffdioApi {
+    pub api: u64,
+    pub features: u64,
+    pub ioctls: u64,
+}
+
+/// `struct uffdio_range`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UffdioRange {
+    pub start: u64,
+    pub len: u64,
+}
+
+/// `struct uffdio_register`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UffdioRegister {
+    pub range: UffdioRange,
+    pub mode: u64,
+    pub ioctls: u64,
+}
+
+/// `struct uffdio_copy`, whose `copy` is set to the number of bytes copied, or a
+/// negated error number
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UffdioCopy {
+    pub dst: u64,
+    pub src: u64,
+    pub len: u64,
+    pub mode: u64,
+    pub copy: i64,
+}
+
+/// `struct uffdio_zeropage`, whose `zeropage` is set to the number of bytes zeroed, or
+/// a negated error number
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UffdioZeropage {
+    pub range: UffdioRange,
+    pub mode: u64,
+    pub zeropage: i64,
+}
+
+/// `struct uffdio_writeprotect`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UffdioWriteprotect {
+    pub range: UffdioRange,
+    pub mode: u64,
+}
+
+/// `struct uffdio_continue`, whose `mapped` is set to the number of bytes mapped, or a
+/// negated error number
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct UffdioContinue {
+    pub range: UffdioRange,
+    pub mode: u64,
+    pub mapped: i64,
+}
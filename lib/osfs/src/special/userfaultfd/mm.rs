@@ -0,0 +1,29 @@
+use systype::error::SysResult;
+
+/// The memory of a process whose faults are handled by a userfaultfd, which the kernel
+/// implements for an address space. Addresses are page-aligned.
+pub trait UserfaultMm: Send + Sync {
+    /// Checks that `[start, end)` is covered by memory which can be registered, which
+    /// must be shared memory if `minor` is set.
+    fn check_register(&self, start: usize, end: usize, minor: bool) -> SysResult<()>;
+
+    /// Returns whether `[start, end)` overlaps with a range registered by any userfaultfd.
+    fn is_registered(&self, start: usize, end: usize) -> bool;
+
+    /// Maps a page at the missing page `dst`, with the content of the page at the user
+    /// address `src` of the current task, or with zeros if `src` is `None`. The page is
+    /// write-protected if `wp` is set.
+    ///
+    /// Returns `EEXIST` if the page exists.
+    fn fill_page(&self, dst: usize, src: Option<usize>, wp: bool) -> SysResult<()>;
+
+    /// Maps the existing page of shared memory at `dst`, which is write-protected if
+    /// `wp` is set.
+    ///
+    /// Returns `EEXIST` if the page is mapped, or `EFAULT` if it does not exist.
+    fn continue_page(&self, dst: usize, wp: bool) -> SysResult<()>;
+
+    /// Write-protects the resident pages in `[start, end)`, or unprotects them if `wp`
+    /// is not set.
+    fn write_protect(&self, start: usize, end: usize, wp: bool) -> SysResult<()>;
+}
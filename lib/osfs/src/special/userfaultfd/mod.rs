@@ -3,9 +3,16 @@ pub mod event;
 pub mod file;
 pub mod flags;
 pub mod inode;
+pub mod ioctl;
+pub mod mm;
 
 pub use dentry::UserfaultfdDentry;
 pub use event::{UserfaultfdMsg, UserfaultfdRange};
 pub use file::UserfaultfdFile;
-pub use flags::{UFFD_API, UserfaultfdFeatures, UserfaultfdFlags, UserfaultfdIoctls};
-pub use inode::UserfaultfdInode;
+pub use flags::{
+    UFFD_API, UserfaultfdFeatures, UserfaultfdFlags, UserfaultfdIoctls, UserfaultfdPagefaultFlags,
+    UserfaultfdRegisterMode,
+};
+pub use inode::{UserfaultfdFaultFuture, UserfaultfdInode};
+pub use ioctl::UffdIoctlCmd;
+pub use mm::UserfaultMm;
@@ -5,12 +5,16 @@ use vfs::{dentry::Dentry, inode::Inode};
 
 use crate::{
     simple::{dentry::SimpleDentry, inode::SimpleInode},
-    sys::meminfo::{dentry::MemInfoDentry, inode::MemInfoInode},
+    sys::{
+        meminfo::{dentry::MemInfoDentry, inode::MemInfoInode},
+        thp::{dentry::ThpDentry, inode::ThpInode},
+    },
 };
 
 pub mod fs;
 pub mod meminfo;
 pub mod superblock;
+pub mod thp;
 
 #[allow(unused)]
 pub fn init_sysfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
@@ -70,6 +74,47 @@ pub fn init_sysfs(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
     // let node1_dentry: Arc<dyn Dentry> = create_node(node_dentry.clone(), 1);
     // init_node(node1_dentry, 1);
 
+    init_kernel_mm(root_dentry)
+}
+
+/// Creates `/sys/kernel/mm/transparent_hugepage`.
+fn init_kernel_mm(root_dentry: Arc<dyn Dentry>) -> SysResult<()> {
+    // /sys/kernel
+    let kernel_inode = SimpleInode::new(root_dentry.superblock().unwrap());
+    kernel_inode.set_inotype(InodeType::Dir);
+    let kernel_dentry: Arc<dyn Dentry> = SimpleDentry::new(
+        "kernel",
+        Some(kernel_inode),
+        Some(Arc::downgrade(&root_dentry)),
+    );
+    root_dentry.add_child(kernel_dentry.clone());
+
+    // /sys/kernel/mm
+    let mm_inode = SimpleInode::new(root_dentry.superblock().unwrap());
+    mm_inode.set_inotype(InodeType::Dir);
+    let mm_dentry: Arc<dyn Dentry> =
+        SimpleDentry::new("mm", Some(mm_inode), Some(Arc::downgrade(&kernel_dentry)));
+    kernel_dentry.add_child(mm_dentry.clone());
+
+    // /sys/kernel/mm/transparent_hugepage
+    let thp_inode = SimpleInode::new(root_dentry.superblock().unwrap());
+    thp_inode.set_inotype(InodeType::Dir);
+    let thp_dentry: Arc<dyn Dentry> = SimpleDentry::new(
+        "transparent_hugepage",
+        Some(thp_inode),
+        Some(Arc::downgrade(&mm_dentry)),
+    );
+    mm_dentry.add_child(thp_dentry.clone());
+
+    // /sys/kernel/mm/transparent_hugepage/enabled
+    let enabled_inode = ThpInode::new(root_dentry.superblock().unwrap());
+    let enabled_dentry: Arc<dyn Dentry> = ThpDentry::new(
+        "enabled",
+        Some(enabled_inode),
+        Some(Arc::downgrade(&thp_dentry)),
+    );
+    thp_dentry.add_child(enabled_dentry);
+
     Ok(())
 }
 
@@ -0,0 +1,50 @@
+use alloc::boxed::Box;
+use core::{cmp, sync::atomic::Ordering};
+
+use async_trait::async_trait;
+
+use systype::error::{SysError, SysResult, SyscallResult};
+use vfs::{
+    direntry::DirEntry,
+    file::{File, FileMeta},
+};
+
+use super::{THP_ENABLED, parse_policy, serialize_policy};
+
+pub struct ThpFile {
+    pub(crate) meta: FileMeta,
+}
+
+#[async_trait]
+impl File for ThpFile {
+    fn meta(&self) -> &FileMeta {
+        &self.meta
+    }
+
+    async fn base_read(&self, buf: &mut [u8], offset: usize) -> SyscallResult {
+        let content = serialize_policy();
+        let len = cmp::min(content.len().saturating_sub(offset), buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        buf[..len].copy_from_slice(&content.as_bytes()[offset..offset + len]);
+        Ok(len)
+    }
+
+    async fn base_write(&self, buf: &[u8], _offset: usize) -> SyscallResult {
+        let policy = core::str::from_utf8(buf)
+            .ok()
+            .and_then(parse_policy)
+            .ok_or(SysError::EINVAL)?;
+        THP_ENABLED.store(policy, Ordering::Relaxed);
+        Ok(buf.len())
+    }
+
+    fn base_read_dir(&self) -> SysResult<Option<DirEntry>> {
+        Err(SysError::ENOTDIR)
+    }
+
+    fn base_readlink(&self, _buf: &mut [u8]) -> SysResult<usize> {
+        panic!("ThpFile does not support readlink");
+    }
+}
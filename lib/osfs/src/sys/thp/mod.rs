@@ -0,0 +1,51 @@
+//! `/sys/kernel/mm/transparent_hugepage/enabled`, the transparent huge page policy.
+//!
+//! The policy is backed by a static atomic variable, which the kernel reads directly
+//! when it decides whether to back a page fault in an anonymous area with a huge page.
+
+use alloc::string::String;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub mod dentry;
+pub mod file;
+pub mod inode;
+
+/// Use huge pages in all anonymous areas, except those advised `MADV_NOHUGEPAGE`.
+pub const THP_ALWAYS: usize = 0;
+/// Use huge pages only in anonymous areas advised `MADV_HUGEPAGE`.
+pub const THP_MADVISE: usize = 1;
+/// Never use transparent huge pages.
+pub const THP_NEVER: usize = 2;
+
+/// Names of the policies, indexed by their values.
+const THP_POLICIES: [&str; 3] = ["always", "madvise", "never"];
+
+/// The current transparent huge page policy.
+pub static THP_ENABLED: AtomicUsize = AtomicUsize::new(THP_MADVISE);
+
+/// Returns the content of the policy file, which lists all policies with the current
+/// one in brackets, e.g., `always [madvise] never`.
+pub fn serialize_policy() -> String {
+    let current = THP_ENABLED.load(Ordering::Relaxed);
+    let mut content = String::new();
+    for (i, name) in THP_POLICIES.iter().enumerate() {
+        if i != 0 {
+            content.push(' ');
+        }
+        if i == current {
+            content.push('[');
+            content.push_str(name);
+            content.push(']');
+        } else {
+            content.push_str(name);
+        }
+    }
+    content.push('\n');
+    content
+}
+
+/// Parses a policy name written to the policy file.
+pub fn parse_policy(name: &str) -> Option<usize> {
+    let name = name.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+    THP_POLICIES.iter().position(|&policy| policy == name)
+}
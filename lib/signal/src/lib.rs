@@ -118,6 +118,35 @@ impl SigInfo {
     /// stopped child has continued
     pub const CLD_CONTINUED: i32 = 6;
     pub const NSIGCHLD: i32 = 6;
+
+    // SIGILL si_codes
+    /// illegal opcode
+    pub const ILL_ILLOPC: i32 = 1;
+
+    // SIGBUS si_codes
+    /// non-existent physical address
+    pub const BUS_ADRERR: i32 = 2;
+
+    /// Builds a signal sent by the kernel for a fault at `addr`, such as `SIGILL` or
+    /// `SIGBUS`, whose `si_addr` is `addr`.
+    pub fn fault(sig: Sig, code: i32, addr: usize) -> Self {
+        // `si_addr` overlays `si_pid` and `si_uid` of the Linux `siginfo_t` union.
+        let info = LinuxSigInfo {
+            si_signo: sig.0,
+            si_code: code,
+            si_pid: addr as i32,
+            si_uid: (addr >> 32) as u32,
+            ..Default::default()
+        };
+        Self {
+            sig,
+            code,
+            details: SigDetails::Kill {
+                pid: 0,
+                siginfo: Some(info),
+            },
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -107,6 +107,8 @@ pub enum SysError {
     EOVERFLOW = 75,
     /// Socket operation on non-socket
     ENOTSOCK = 88,
+    /// Message too long
+    EMSGSIZE = 90,
     /// Protocol not available
     ENOPROTOOPT = 92,
     /// Protocol not supported
@@ -119,12 +121,20 @@ pub enum SysError {
     EADDRINUSE = 98,
     /// Address not available
     EADDRNOTAVAIL = 99,
+    /// Network is down
+    ENETDOWN = 100,
+    /// Network is unreachable
+    ENETUNREACH = 101,
     /// Connection reset
     ECONNRESET = 104,
+    /// No buffer space available
+    ENOBUFS = 105,
     /// Transport endpoint is already connected
     EISCONN = 106,
     /// Transport endpoint is not connected
     ENOTCONN = 107,
+    /// Connection timed out
+    ETIMEDOUT = 110,
     /// Connection refused
     ECONNREFUSED = 111,
     /// The socket is nonblocking and the connection cannot be completed
@@ -187,16 +197,21 @@ impl SysError {
             ELOOP => "Trap in Infinite loop",
             EOVERFLOW => "too much data",
             ENOTSOCK => "Socket operation on non-socket",
+            EMSGSIZE => "Message too long",
             ENOPROTOOPT => "Protocol not available",
             EPROTONOSUPPORT => "Protocol not supported",
             EOPNOTSUPP => "Unsupported",
             EAFNOSUPPORT => "Address family not supported (POSIX.1-2001).",
             EADDRINUSE => "Address already in use",
             EADDRNOTAVAIL => "Address not available",
+            ENETDOWN => "Network is down",
+            ENETUNREACH => "Network is unreachable",
             EISCONN => "Transport endpoint is already connected",
             ENOTCONN => "Transport endpoint is not connected",
+            ETIMEDOUT => "Connection timed out",
             ECONNREFUSED => "Connection refused",
             ECONNRESET => "Connection reset",
+            ENOBUFS => "No buffer space available",
             EINPROGRESS => "Operation now in progress",
             ECANCELED => "operation is cancelled",
             EUTFFAIL => "UTF-8 Convert Failed",
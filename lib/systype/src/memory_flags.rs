@@ -91,6 +91,29 @@ bitflags! {
         const MAP_ANONYMOUS = 0x20;
         /// Don't check for reservations.
         const MAP_NORESERVE = 0x04000;
+        /// Allocate the mapping using huge pages.
+        const MAP_HUGETLB = 0x40000;
+    }
+}
+
+impl MmapFlags {
+    /// Bit offset of the huge page size in `mmap` flags, encoded as its base-2 logarithm
+    /// along with `MAP_HUGETLB`. The size is the default one if it is zero.
+    pub const MAP_HUGE_SHIFT: u32 = 26;
+    /// Mask of the huge page size in `mmap` flags, after shifting by `MAP_HUGE_SHIFT`.
+    pub const MAP_HUGE_MASK: i32 = 0x3f;
+}
+
+bitflags! {
+    /// Flags for `mremap` system call that specify how a mapping may be moved.
+    #[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct MremapFlags: i32 {
+        /// The mapping may be moved to a new address.
+        const MREMAP_MAYMOVE = 0x1;
+        /// Move the mapping to exactly the new address.
+        const MREMAP_FIXED = 0x2;
+        /// Keep the old mapping after moving its pages, so that it is refilled on access.
+        const MREMAP_DONTUNMAP = 0x4;
     }
 }
 
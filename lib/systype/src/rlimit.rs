@@ -1,5 +1,8 @@
 pub const RLIM_INFINITY: usize = usize::MAX;
 
+/// Number of resources which can be limited by `setrlimit`.
+pub const RLIM_NLIMITS: usize = 16;
+
 /// Resource Limit
 ///
 /// `rlim_cur` is the soft limit to current resource. User can
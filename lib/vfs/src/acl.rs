@@ -0,0 +1,373 @@
+//! Module for POSIX access control lists.
+//!
+//! The access ACL of an inode is stored in its `system.posix_acl_access` extended
+//! attribute and refines the permission checks on the inode. The default ACL of a
+//! directory is stored in its `system.posix_acl_default` extended attribute and is
+//! inherited by the files created in the directory.
+//!
+//! ACLs are passed to and from [`Inode::get_xattr`] and [`Inode::set_xattr`] in the
+//! format used by the `getxattr` and `setxattr` system calls. A filesystem which stores
+//! ACLs in another format on disk converts them itself.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use config::{inode::InodeMode, vfs::AccessFlags};
+use systype::error::{SysError, SysResult};
+
+use crate::inode::Inode;
+
+/// Name of the extended attribute holding the access ACL.
+pub const XATTR_NAME_POSIX_ACL_ACCESS: &str = "system.posix_acl_access";
+/// Name of the extended attribute holding the default ACL.
+pub const XATTR_NAME_POSIX_ACL_DEFAULT: &str = "system.posix_acl_default";
+
+/// Version of the extended attribute format of ACLs.
+pub const POSIX_ACL_XATTR_VERSION: u32 = 2;
+/// Qualifier of an ACL entry whose tag has no qualifier.
+pub const ACL_UNDEFINED_ID: u32 = u32::MAX;
+
+// Tags of ACL entries.
+pub const ACL_USER_OBJ: u16 = 0x01;
+pub const ACL_USER: u16 = 0x02;
+pub const ACL_GROUP_OBJ: u16 = 0x04;
+pub const ACL_GROUP: u16 = 0x08;
+pub const ACL_MASK: u16 = 0x10;
+pub const ACL_OTHER: u16 = 0x20;
+
+/// Mask of the permission bits of an ACL entry, which are the same as those of
+/// [`AccessFlags`].
+const ACL_PERM_MASK: u16 = 0o7;
+
+/// An entry of a POSIX ACL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PosixAclEntry {
+    /// Tag of the entry, which is one of `ACL_USER_OBJ`, `ACL_USER`, `ACL_GROUP_OBJ`,
+    /// `ACL_GROUP`, `ACL_MASK`, and `ACL_OTHER`.
+    pub tag: u16,
+    /// Permission bits of the entry.
+    pub perm: u16,
+    /// User ID or group ID for `ACL_USER` and `ACL_GROUP` entries, or
+    /// `ACL_UNDEFINED_ID` for other entries.
+    pub id: u32,
+}
+
+/// A POSIX ACL, whose entries are sorted by tag and then by qualifier.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PosixAcl {
+    pub entries: Vec<PosixAclEntry>,
+}
+
+impl PosixAcl {
+    /// Parses an ACL in the extended attribute format.
+    ///
+    /// # Errors
+    /// Returns `EOPNOTSUPP` if the version of the format is not supported, or `EINVAL`
+    /// if the value is malformed or the ACL is not valid.
+    pub fn from_xattr(value: &[u8]) -> SysResult<Self> {
+        if value.len() < 4 || (value.len() - 4) % 8 != 0 {
+            return Err(SysError::EINVAL);
+        }
+        let version = u32::from_le_bytes(value[0..4].try_into().unwrap());
+        if version != POSIX_ACL_XATTR_VERSION {
+            return Err(SysError::EOPNOTSUPP);
+        }
+
+        let entries = value[4..]
+            .chunks_exact(8)
+            .map(|e| PosixAclEntry {
+                tag: u16::from_le_bytes([e[0], e[1]]),
+                perm: u16::from_le_bytes([e[2], e[3]]),
+                id: u32::from_le_bytes([e[4], e[5], e[6], e[7]]),
+            })
+            .collect();
+        let acl = Self { entries };
+        acl.validate()?;
+        Ok(acl)
+    }
+
+    /// Converts the ACL to the extended attribute format.
+    pub fn to_xattr(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(4 + self.entries.len() * 8);
+        value.extend_from_slice(&POSIX_ACL_XATTR_VERSION.to_le_bytes());
+        for entry in &self.entries {
+            let id = match entry.tag {
+                ACL_USER | ACL_GROUP => entry.id,
+                _ => ACL_UNDEFINED_ID,
+            };
+            value.extend_from_slice(&entry.tag.to_le_bytes());
+            value.extend_from_slice(&entry.perm.to_le_bytes());
+            value.extend_from_slice(&id.to_le_bytes());
+        }
+        value
+    }
+
+    /// Checks that the ACL has exactly one `ACL_USER_OBJ`, `ACL_GROUP_OBJ` and
+    /// `ACL_OTHER` entry, has an `ACL_MASK` entry if it has any `ACL_USER` or
+    /// `ACL_GROUP` entry, and that its entries are sorted without duplicates.
+    ///
+    /// # Errors
+    /// Returns `EINVAL` if the ACL is not valid.
+    pub fn validate(&self) -> SysResult<()> {
+        // The tag expected next, where `ACL_OTHER` after `ACL_MASK` is represented by
+        // `ACL_MASK`, and 0 means that all entries have been seen.
+        let mut state = ACL_USER_OBJ;
+        let mut needs_mask = false;
+        let mut last_id = None;
+
+        for entry in &self.entries {
+            if entry.perm & !ACL_PERM_MASK != 0 {
+                return Err(SysError::EINVAL);
+            }
+            match entry.tag {
+                ACL_USER_OBJ if state == ACL_USER_OBJ => state = ACL_USER,
+                ACL_GROUP_OBJ if state == ACL_USER => {
+                    state = ACL_GROUP;
+                    last_id = None;
+                }
+                ACL_USER | ACL_GROUP if state == entry.tag => {
+                    if last_id.is_some_and(|id| id >= entry.id) {
+                        return Err(SysError::EINVAL);
+                    }
+                    last_id = Some(entry.id);
+                    needs_mask = true;
+                }
+                ACL_MASK if state == ACL_GROUP => state = ACL_MASK,
+                ACL_OTHER if state == ACL_MASK || (state == ACL_GROUP && !needs_mask) => {
+                    state = 0;
+                }
+                _ => return Err(SysError::EINVAL),
+            }
+        }
+
+        if state == 0 {
+            Ok(())
+        } else {
+            Err(SysError::EINVAL)
+        }
+    }
+
+    /// Returns whether the ACL grants `access` to a task with effective user ID `euid`,
+    /// effective group ID `egid` and supplementary groups `groups`, on an inode owned by
+    /// user `uid` and group `gid`.
+    pub fn permits(
+        &self,
+        euid: u32,
+        egid: u32,
+        groups: &[u32],
+        uid: u32,
+        gid: u32,
+        access: AccessFlags,
+    ) -> bool {
+        let want = access.bits() as u16 & ACL_PERM_MASK;
+        let in_group = |id: u32| egid == id || groups.contains(&id);
+        let mut found_group = false;
+
+        for (i, entry) in self.entries.iter().enumerate() {
+            let matched = match entry.tag {
+                ACL_USER_OBJ if euid == uid => return entry.perm & want == want,
+                ACL_USER => entry.id == euid,
+                ACL_GROUP_OBJ | ACL_GROUP => {
+                    let id = if entry.tag == ACL_GROUP_OBJ {
+                        gid
+                    } else {
+                        entry.id
+                    };
+                    if in_group(id) {
+                        found_group = true;
+                        entry.perm & want == want
+                    } else {
+                        false
+                    }
+                }
+                ACL_OTHER => return !found_group && entry.perm & want == want,
+                _ => false,
+            };
+            if matched {
+                // The permissions of a named user or any group entry are limited by
+                // the mask entry, which follows them.
+                let mask = self.entries[i + 1..]
+                    .iter()
+                    .find(|e| e.tag == ACL_MASK)
+                    .map_or(ACL_PERM_MASK, |e| e.perm);
+                return entry.perm & mask & want == want;
+            }
+        }
+
+        false
+    }
+
+    /// Returns the permission bits of a mode that are equivalent to the ACL, and whether
+    /// the ACL has entries that cannot be represented by the mode.
+    pub fn equiv_mode(&self) -> (u32, bool) {
+        let mut mode = 0;
+        let mut not_equiv = false;
+        for entry in &self.entries {
+            let perm = (entry.perm & ACL_PERM_MASK) as u32;
+            match entry.tag {
+                ACL_USER_OBJ => mode |= perm << 6,
+                ACL_GROUP_OBJ => mode |= perm << 3,
+                ACL_OTHER => mode |= perm,
+                ACL_MASK => {
+                    mode = (mode & !0o070) | (perm << 3);
+                    not_equiv = true;
+                }
+                _ => not_equiv = true,
+            }
+        }
+        (mode, not_equiv)
+    }
+
+    /// Updates the entries of the ACL which correspond to the owner, group and other
+    /// permission bits of a mode to those in `mode`, as done when the mode is changed.
+    ///
+    /// The group permission bits of the mode correspond to the `ACL_MASK` entry if there
+    /// is one, or to the `ACL_GROUP_OBJ` entry otherwise.
+    pub fn chmod(&mut self, mode: u32) {
+        let has_mask = self.entries.iter().any(|e| e.tag == ACL_MASK);
+        for entry in &mut self.entries {
+            match entry.tag {
+                ACL_USER_OBJ => entry.perm = (mode >> 6) as u16 & ACL_PERM_MASK,
+                ACL_GROUP_OBJ if !has_mask => entry.perm = (mode >> 3) as u16 & ACL_PERM_MASK,
+                ACL_MASK => entry.perm = (mode >> 3) as u16 & ACL_PERM_MASK,
+                ACL_OTHER => entry.perm = mode as u16 & ACL_PERM_MASK,
+                _ => {}
+            }
+        }
+    }
+
+    /// Limits an ACL inherited by a new file to the permission bits of `mode` which the
+    /// file is created with, and limits `mode` to the ACL in turn.
+    ///
+    /// Returns whether the ACL has entries that cannot be represented by the mode, in
+    /// which case it should be kept as the access ACL of the new file.
+    pub fn create_masq(&mut self, mode: &mut u32) -> bool {
+        let mut not_equiv = false;
+        let mut group_obj = None;
+        let mut mask = None;
+
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            match entry.tag {
+                ACL_USER_OBJ => {
+                    entry.perm &= (*mode >> 6) as u16 & ACL_PERM_MASK;
+                    *mode &= ((entry.perm as u32) << 6) | !0o700;
+                }
+                ACL_USER | ACL_GROUP => not_equiv = true,
+                ACL_GROUP_OBJ => group_obj = Some(i),
+                ACL_OTHER => {
+                    entry.perm &= *mode as u16 & ACL_PERM_MASK;
+                    *mode &= entry.perm as u32 | !0o007;
+                }
+                ACL_MASK => {
+                    mask = Some(i);
+                    not_equiv = true;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(i) = mask.or(group_obj) {
+            let entry = &mut self.entries[i];
+            entry.perm &= (*mode >> 3) as u16 & ACL_PERM_MASK;
+            *mode &= ((entry.perm as u32) << 3) | !0o070;
+        }
+
+        not_equiv
+    }
+}
+
+/// Returns the ACL of `inode` stored in the extended attribute `name`, or `None` if
+/// there is no such ACL.
+pub fn get_acl(inode: &Arc<dyn Inode>, name: &str) -> SysResult<Option<PosixAcl>> {
+    match inode.get_xattr(name) {
+        Ok(value) => PosixAcl::from_xattr(&value).map(Some),
+        Err(SysError::ENODATA | SysError::EOPNOTSUPP) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Removes the extended attribute `name` from `inode` if it exists.
+fn remove_acl(inode: &Arc<dyn Inode>, name: &str) -> SysResult<()> {
+    match inode.remove_xattr(name) {
+        Ok(()) | Err(SysError::ENODATA) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Sets an extended attribute of `inode`, taking care of the ACL attributes.
+///
+/// Setting the access ACL also updates the permission bits of the mode of `inode`,
+/// and the ACL is not stored if it is equivalent to the mode. The default ACL can only
+/// be set on a directory. An empty value removes an ACL. Other attributes are set with
+/// [`Inode::set_xattr`] directly.
+///
+/// # Errors
+/// Returns `EINVAL` if the value of an ACL attribute is not a valid ACL, or `EACCES`
+/// if a default ACL is set on a file which is not a directory.
+pub fn set_xattr(inode: &Arc<dyn Inode>, name: &str, value: &[u8], flags: i32) -> SysResult<()> {
+    match name {
+        XATTR_NAME_POSIX_ACL_ACCESS => {
+            if value.is_empty() {
+                return remove_acl(inode, name);
+            }
+            let acl = PosixAcl::from_xattr(value)?;
+            let (perm, not_equiv) = acl.equiv_mode();
+            if not_equiv {
+                inode.set_xattr(name, &acl.to_xattr(), flags)?;
+            } else {
+                remove_acl(inode, name)?;
+            }
+            let mode = inode.get_meta().inner.lock().mode.bits();
+            inode.set_mode(InodeMode::from_bits_retain((mode & !0o777) | perm));
+            Ok(())
+        }
+        XATTR_NAME_POSIX_ACL_DEFAULT => {
+            if !inode.inotype().is_dir() {
+                return if value.is_empty() {
+                    Ok(())
+                } else {
+                    Err(SysError::EACCES)
+                };
+            }
+            if value.is_empty() {
+                return remove_acl(inode, name);
+            }
+            let acl = PosixAcl::from_xattr(value)?;
+            inode.set_xattr(name, &acl.to_xattr(), flags)
+        }
+        _ => inode.set_xattr(name, value, flags),
+    }
+}
+
+/// Lets `inode`, which has just been created in directory `parent`, inherit the default
+/// ACL of `parent`.
+///
+/// The inherited ACL is limited to the mode of `inode` and becomes its access ACL,
+/// unless it is equivalent to the mode. A new directory also gets the default ACL as
+/// its own default ACL.
+pub fn inherit(parent: &Arc<dyn Inode>, inode: &Arc<dyn Inode>) -> SysResult<()> {
+    let Some(mut acl) = get_acl(parent, XATTR_NAME_POSIX_ACL_DEFAULT)? else {
+        return Ok(());
+    };
+
+    if inode.inotype().is_dir() {
+        inode.set_xattr(XATTR_NAME_POSIX_ACL_DEFAULT, &acl.to_xattr(), 0)?;
+    }
+
+    let mode = inode.get_meta().inner.lock().mode.bits();
+    let mut perm = mode & 0o777;
+    if acl.create_masq(&mut perm) {
+        inode.set_xattr(XATTR_NAME_POSIX_ACL_ACCESS, &acl.to_xattr(), 0)?;
+    }
+    inode.set_mode(InodeMode::from_bits_retain((mode & !0o777) | perm));
+    Ok(())
+}
+
+/// Updates the access ACL of `inode` to its mode after the mode is changed.
+pub fn chmod(inode: &Arc<dyn Inode>) -> SysResult<()> {
+    let Some(mut acl) = get_acl(inode, XATTR_NAME_POSIX_ACL_ACCESS)? else {
+        return Ok(());
+    };
+    let mode = inode.get_meta().inner.lock().mode;
+    acl.chmod(mode.bits());
+    inode.set_xattr(XATTR_NAME_POSIX_ACL_ACCESS, &acl.to_xattr(), 0)
+}
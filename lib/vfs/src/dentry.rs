@@ -1,15 +1,18 @@
 use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
 use config::inode::InodeMode;
 use mutex::SpinNoIrqLock;
 use systype::error::{SysError, SysResult};
 
+use crate::acl;
 use crate::fanotify::types::FanEventMask;
 use crate::fanotify::{FanotifyEntrySet, FanotifyGroupKey};
 use crate::file::File;
 use crate::inode::Inode;
+use crate::inotify::{self, INOTIFY_CHILD_EVENTS};
 use crate::superblock::SuperBlock;
 
 /// Data that is common to all dentries.
@@ -255,6 +258,7 @@ impl dyn Dentry {
 
         let inode_number = dentry.inode().unwrap().ino() as u32;
         let inode = Arc::clone(&dentry.inode().unwrap());
+        acl::inherit(&self.inode().unwrap(), &inode)?;
         self.superblock()
             .unwrap()
             .meta()
@@ -264,6 +268,7 @@ impl dyn Dentry {
 
         let file_name = dentry.name();
         self.fanotify_publish(Some(dentry), FanEventMask::CREATE, file_name, file_name);
+        self.inotify_publish_child(FanEventMask::CREATE, 0, file_name);
 
         Ok(())
     }
@@ -283,6 +288,7 @@ impl dyn Dentry {
 
         let inode_number = dentry.inode().unwrap().ino() as u32;
         let inode = Arc::clone(&dentry.inode().unwrap());
+        acl::inherit(&self.inode().unwrap(), &inode)?;
         self.superblock()
             .unwrap()
             .meta()
@@ -297,6 +303,7 @@ impl dyn Dentry {
             file_name,
             file_name,
         );
+        self.inotify_publish_child(FanEventMask::CREATE | FanEventMask::ONDIR, 0, file_name);
 
         Ok(())
     }
@@ -324,6 +331,7 @@ impl dyn Dentry {
 
         let file_name = dentry.name();
         self.fanotify_publish(Some(dentry), FanEventMask::CREATE, file_name, file_name);
+        self.inotify_publish_child(FanEventMask::CREATE, 0, file_name);
 
         Ok(())
     }
@@ -403,6 +411,8 @@ impl dyn Dentry {
 
         let file_name = new_dentry.name();
         self.fanotify_publish(Some(new_dentry), FanEventMask::CREATE, file_name, file_name);
+        self.inotify_publish_child(FanEventMask::CREATE, 0, file_name);
+        old_dentry.inotify_publish(FanEventMask::ATTRIB);
 
         Ok(())
     }
@@ -419,9 +429,27 @@ impl dyn Dentry {
 
         let file_name = dentry.name();
         self.fanotify_publish(Some(dentry), FanEventMask::DELETE, file_name, file_name);
-        dentry.fanotify_publish(None, FanEventMask::DELETE_SELF, file_name, file_name);
 
-        self.base_unlink(dentry.as_ref())
+        let inode = dentry.inode().unwrap();
+        let last_link = inode.get_meta().inner.lock().nlink <= 1;
+        self.base_unlink(dentry.as_ref())?;
+
+        self.inotify_publish_child(FanEventMask::DELETE, 0, file_name);
+        // `DELETE_SELF` is published once the inode has no links and no open files,
+        // which is when the last open file is closed if it is still open.
+        let delete_self = last_link && {
+            let mut inner = inode.get_meta().inner.lock();
+            inner.delete_self_pending = inner.open_files > 0;
+            !inner.delete_self_pending
+        };
+        if delete_self {
+            dentry.fanotify_publish(None, FanEventMask::DELETE_SELF, file_name, file_name);
+            dentry.inotify_publish(FanEventMask::DELETE_SELF);
+        } else {
+            dentry.inotify_publish(FanEventMask::ATTRIB);
+        }
+
+        Ok(())
     }
 
     /// Removes the child directory `dentry` from directory `self` if it is empty.
@@ -450,7 +478,12 @@ impl dyn Dentry {
             file_name,
         );
 
-        self.base_rmdir(dentry.as_ref())
+        self.base_rmdir(dentry.as_ref())?;
+
+        self.inotify_publish_child(FanEventMask::DELETE | FanEventMask::ONDIR, 0, file_name);
+        dentry.inotify_publish(FanEventMask::DELETE_SELF);
+
+        Ok(())
     }
 
     /// Removes the child directory `dentry` recursively from directory `self`.
@@ -550,8 +583,22 @@ impl dyn Dentry {
             old_name,
         );
 
+        // The file replaced by the rename, if any, is deleted unless it has other links.
+        let replaced = new_dentry
+            .inode()
+            .filter(|inode| inode.inotype().is_dir() || inode.get_meta().inner.lock().nlink <= 1);
+
         self.base_rename(dentry.as_ref(), new_dir.as_ref(), new_dentry.as_ref())?;
 
+        let cookie = inotify::next_cookie();
+        self.inotify_publish_child(FanEventMask::MOVED_FROM | ondir_mask, cookie, old_name);
+        new_dir.inotify_publish_child(FanEventMask::MOVED_TO | ondir_mask, cookie, new_name);
+        new_dentry.inotify_publish(FanEventMask::MOVE_SELF);
+        if let Some(inode) = replaced {
+            let mask = FanEventMask::DELETE_SELF | ondir_mask;
+            inotify::publish(&inode, mask.bits(), 0, None, false);
+        }
+
         new_dir.fanotify_publish(
             Some(new_dentry),
             FanEventMask::MOVED_TO | ondir_mask,
@@ -625,6 +672,56 @@ impl dyn Dentry {
     ) {
         assert!(!self.is_negative());
 
+        // Publish the event to all fanotify groups.
+        #[allow(clippy::mutable_key_type)]
+        for (group, entry_set) in self.fanotify_entries(event) {
+            group
+                .0
+                .publish(self, subobject, entry_set, event, old_name, new_name);
+        }
+    }
+
+    /// Publishes an fanotify permission event on this dentry to all associated fanotify
+    /// groups, and waits for the responses of the groups that are interested in it.
+    ///
+    /// `event` must be one of `FanEventMask::ACCESS_PERM`, `FanEventMask::OPEN_PERM`,
+    /// and `FanEventMask::OPEN_EXEC_PERM`. `FanEventMask::ONDIR` is added to the event
+    /// if this dentry is a directory.
+    ///
+    /// # Errors
+    /// Returns `EPERM` if any group denies the permission.
+    pub async fn fanotify_permission(self: &Arc<Self>, event: FanEventMask) -> SysResult<()> {
+        let Some(inode) = self.inode() else {
+            return Ok(());
+        };
+        let mut event = event;
+        if inode.inotype().is_dir() {
+            event |= FanEventMask::ONDIR;
+        }
+
+        #[allow(clippy::mutable_key_type)]
+        let permissions: Vec<_> = self
+            .fanotify_entries(event)
+            .into_iter()
+            .filter_map(|(group, entry_set)| group.0.publish_permission(self, entry_set, event))
+            .collect();
+
+        for permission in permissions {
+            permission.wait().await?;
+        }
+        Ok(())
+    }
+
+    /// Collects the fanotify entries associated with this dentry by group: the entries
+    /// on the inode of this dentry, on its parent directory, and on its filesystem.
+    ///
+    /// This function also tries to clear the ignore mask of the entries on the inode if
+    /// `event` contains `FanEventMask::MODIFY`.
+    #[allow(clippy::mutable_key_type)]
+    fn fanotify_entries(
+        self: &Arc<Self>,
+        event: FanEventMask,
+    ) -> BTreeMap<FanotifyGroupKey, FanotifyEntrySet> {
         // A map from fanotify group to fanotify entry set.
         #[allow(clippy::mutable_key_type)]
         let mut entries_by_group: BTreeMap<FanotifyGroupKey, FanotifyEntrySet> = BTreeMap::new();
@@ -677,11 +774,46 @@ impl dyn Dentry {
             mount
         );
 
-        // Publish the event to all fanotify groups.
-        for (group, entry_set) in entries_by_group {
-            group
-                .0
-                .publish(self, subobject, entry_set, event, old_name, new_name);
+        entries_by_group
+    }
+
+    /// Publishes an inotify event on this dentry.
+    ///
+    /// The event is delivered to the watches on the inode of this dentry, and also to
+    /// the watches on its parent directory if it is one of [`INOTIFY_CHILD_EVENTS`].
+    /// `FanEventMask::ONDIR` is added to the event if this dentry is a directory.
+    pub fn inotify_publish(self: &Arc<Self>, event: FanEventMask) {
+        let Some(inode) = self.inode() else {
+            return;
+        };
+        let mut mask = event;
+        if inode.inotype().is_dir() {
+            mask |= FanEventMask::ONDIR;
+        }
+
+        inotify::publish(&inode, mask.bits(), 0, None, false);
+
+        if event.intersects(INOTIFY_CHILD_EVENTS) {
+            if let Some(parent_inode) = self.parent().and_then(|p| p.inode()) {
+                let unlinked = inode.get_meta().inner.lock().nlink == 0;
+                inotify::publish(&parent_inode, mask.bits(), 0, Some(self.name()), unlinked);
+            }
+        }
+    }
+
+    /// Publishes an inotify event on directory `self` about its child named `name`,
+    /// such as `IN_CREATE` or `IN_MOVED_FROM`.
+    ///
+    /// `cookie` associates the `IN_MOVED_FROM` and `IN_MOVED_TO` events of a rename,
+    /// and is 0 for other events.
+    pub(crate) fn inotify_publish_child(
+        self: &Arc<Self>,
+        event: FanEventMask,
+        cookie: u32,
+        name: &str,
+    ) {
+        if let Some(inode) = self.inode() {
+            inotify::publish(&inode, event.bits(), cookie, Some(name), false);
         }
     }
 }
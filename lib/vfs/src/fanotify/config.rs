@@ -9,3 +9,7 @@ pub static MAX_USER_GROUPS: AtomicU32 = AtomicU32::new(128);
 
 /// Maximum number of fanotify marks that can be created per real user ID.
 pub static MAX_USER_MARKS: AtomicU32 = AtomicU32::new(8192);
+
+/// Time in milliseconds to wait for the listener's response to a permission event,
+/// after which the event is denied.
+pub static PERMISSION_TIMEOUT_MS: AtomicU32 = AtomicU32::new(30000);
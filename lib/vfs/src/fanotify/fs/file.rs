@@ -8,7 +8,11 @@ use systype::error::{SysError, SysResult, SyscallResult};
 
 use crate::file::{File, FileMeta};
 
-use super::super::{FanotifyGroup, constants::FAN_NOFD, types::FanInitFlags};
+use super::super::{
+    FanotifyGroup,
+    constants::{FAN_AUDIT, FAN_INFO, FAN_NOFD, FAN_RESPONSE_INFO_AUDIT_RULE},
+    types::{FanInitFlags, FanotifyResponse},
+};
 use super::inode::FanotifyGroupInode;
 
 /// File implementation for fanotify group file descriptor.
@@ -38,6 +42,7 @@ impl FanotifyGroupFile {
 
         while let Some(mut event) = event_queue.pop_front() {
             let event_object = event.object().cloned();
+            let permission = event.permission().cloned();
             let metadata = event.metadata_mut();
 
             let event_len = metadata.event_len as usize;
@@ -81,6 +86,10 @@ impl FanotifyGroupFile {
                 };
             }
 
+            if let Some(permission) = permission {
+                group.add_pending_permission(metadata.fd, permission);
+            }
+
             log::info!(
                 "Event metadata read: object={}, fd={}, pid={}, mask={:?}",
                 event_object.unwrap().path(),
@@ -103,19 +112,47 @@ impl FanotifyGroupFile {
         Ok(total_read)
     }
 
-    // TODO: Implement write methods after fanotify event file system is ready
-    //
-    // The write implementation should:
-    // 1. Parse FanotifyResponse from the input buffer
-    // 2. Find the permission event by matching the event_fd
-    // 3. Close the fanotify event file descriptor (remove from process fd table)
-    // 4. Apply the permission decision (allow/deny) to the original operation
-    // 5. Remove the permission event from the queue
-    //
-    // This requires integration with:
-    // - Process file descriptor table management
-    // - Filesystem operation callback mechanism
-    // - Permission decision application
+    /// Parses a response to a permission event written by the listener and passes it
+    /// to the waiting operation.
+    ///
+    /// Returns the number of bytes consumed from `buf`, which includes the information
+    /// record following the response if `FAN_INFO` is set.
+    fn write_response(&self, buf: &[u8]) -> SysResult<usize> {
+        let group = self.group();
+        let response = FanotifyResponse::from_bytes(buf).ok_or(SysError::EINVAL)?;
+        if response.fd < 0 {
+            return Err(SysError::EINVAL);
+        }
+
+        let mut len = size_of::<u32>() * 2;
+        if response.flags & FAN_AUDIT != 0 && !group.flags.contains(FanInitFlags::ENABLE_AUDIT) {
+            return Err(SysError::EINVAL);
+        }
+        if response.flags & FAN_INFO != 0 {
+            // struct fanotify_response_info_header { __u8 type; __u8 pad; __u16 len; }
+            let header = buf.get(len..len + 4).ok_or(SysError::EINVAL)?;
+            let info_type = header[0];
+            let info_len = u16::from_ne_bytes([header[2], header[3]]) as usize;
+            if info_type != FAN_RESPONSE_INFO_AUDIT_RULE
+                || info_len < 4
+                || buf.len() < len + info_len
+            {
+                return Err(SysError::EINVAL);
+            }
+            len += info_len;
+        }
+
+        if response.flags & FAN_AUDIT != 0 {
+            log::info!(
+                "[fanotify] audit: fd={}, response={:?}",
+                response.fd,
+                response.response
+            );
+        }
+
+        group.respond(response.fd, response.response as u32)?;
+        Ok(len)
+    }
 }
 
 #[async_trait]
@@ -138,14 +175,8 @@ impl File for FanotifyGroupFile {
         }
     }
 
-    async fn base_write(&self, _buf: &[u8], _offset: usize) -> SyscallResult {
-        // TODO: Implement fanotify permission response handling
-        // This should:
-        // 1. Parse FanotifyResponse from buf
-        // 2. Find the corresponding fanotify event file by fd
-        // 3. Close the fanotify event file descriptor
-        // 4. Apply the permission decision (allow/deny)
-        unimplemented!()
+    async fn base_write(&self, buf: &[u8], _offset: usize) -> SyscallResult {
+        self.write_response(buf)
     }
 
     fn base_read_dir(&self) -> SysResult<Option<crate::direntry::DirEntry>> {
@@ -70,3 +70,11 @@ impl Inode for FanotifyGroupInode {
         })
     }
 }
+
+impl Drop for FanotifyGroupInode {
+    /// Allows all permission events of the group which have not been responded to when
+    /// the last file description of the group is closed.
+    fn drop(&mut self) {
+        self.group.release();
+    }
+}
@@ -5,20 +5,31 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
-use core::{cmp, mem, ptr, sync::atomic, task::Waker};
+use core::{
+    cmp,
+    future::Future,
+    mem,
+    pin::Pin,
+    ptr,
+    sync::atomic,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
 
 use crate_interface::call_interface;
 
 use mutex::SpinNoIrqLock;
 use osfuture::{suspend_now, take_waker};
+use systype::error::{SysError, SysResult};
+use timer::{TimedTaskResult, run_with_timeout};
 
 use crate::{
     dentry::Dentry,
     fanotify::{
-        config::MAX_QUEUED_EVENTS,
+        config::{MAX_QUEUED_EVENTS, PERMISSION_TIMEOUT_MS},
+        constants::{FAN_ALLOW, FAN_DENY},
         types::{FanMarkFlags, FanotifyEventInfoError, FanotifyEventInfoPid},
     },
-    file::File,
     inode::Inode,
     superblock::SuperBlock,
 };
@@ -71,6 +82,10 @@ pub struct FanotifyGroup {
 
     /// Maximum number of events that can be queued in the event queue.
     max_queued_events: u32,
+
+    /// Permission events that have been read by the listener and are waiting for its
+    /// response, keyed by the file descriptor reported in the event.
+    pending_permissions: SpinNoIrqLock<BTreeMap<i32, Arc<FanotifyPermissionEvent>>>,
 }
 
 impl FanotifyGroup {
@@ -86,6 +101,7 @@ impl FanotifyGroup {
             } else {
                 MAX_QUEUED_EVENTS.load(atomic::Ordering::Relaxed)
             },
+            pending_permissions: SpinNoIrqLock::new(BTreeMap::new()),
         }
     }
 
@@ -192,6 +208,74 @@ impl FanotifyGroup {
         entries.retain(|_, entry| !matches!(entry.object, FsObject::Mount(_)));
     }
 
+    /// Returns whether `event` on `object` is marked and not ignored by the entries of
+    /// this group in `entries`.
+    ///
+    /// The parameters are the same as those of [`FanotifyGroup::publish`].
+    fn accepts(
+        &self,
+        object: &Arc<dyn Dentry>,
+        entries: &FanotifyEntrySet,
+        event: FanEventMask,
+    ) -> bool {
+        // Check whether the event is marked and not ignored.
+        let mark_of = |entry: &Option<Arc<FanotifyEntry>>| {
+            entry.as_ref().map_or(FanEventMask::empty(), |e| e.mark())
+        };
+        let object_mark = mark_of(&entries.object);
+        let parent_mark = mark_of(&entries.parent);
+        let mount_mark = mark_of(&entries.mount);
+        let fs_mark = mark_of(&entries.fs);
+
+        if !(object_mark.contains(event)
+            || ((FanEventMask::FILE_EVENT_MASK | FanEventMask::ONDIR).contains(event)
+                && parent_mark.contains(event | FanEventMask::EVENT_ON_CHILD))
+            || mount_mark.contains(event)
+            || fs_mark.contains(event))
+        {
+            log::debug!(
+                "[FanotifyGroup::accepts] Event not marked: event={:?}, object={:?}, \
+                object_mark={:?}, parent_mark={:?}, mount_mark={:?}, fs_mark={:?}",
+                event,
+                object.path(),
+                object_mark,
+                parent_mark,
+                mount_mark,
+                fs_mark
+            );
+            return false;
+        }
+
+        let ignore_of = |entry: &Option<Arc<FanotifyEntry>>| {
+            entry.as_ref().map_or(FanEventMask::empty(), |e| e.ignore())
+        };
+        let object_ignore = ignore_of(&entries.object);
+        let parent_ignore = ignore_of(&entries.parent);
+        let mount_ignore = ignore_of(&entries.mount);
+        let fs_ignore = ignore_of(&entries.fs);
+
+        if object_ignore.contains(event)
+            || (FanEventMask::FILE_EVENT_MASK | FanEventMask::ONDIR).contains(event)
+                && parent_ignore.contains(event | FanEventMask::EVENT_ON_CHILD)
+            || mount_ignore.contains(event)
+            || fs_ignore.contains(event)
+        {
+            log::debug!(
+                "[FanotifyGroup::accepts] Event ignored: event={:?}, object={:?}, \
+                object_ignore={:?}, parent_ignore={:?}, mount_ignore={:?}, fs_ignore={:?}",
+                event,
+                object.path(),
+                object_ignore,
+                parent_ignore,
+                mount_ignore,
+                fs_ignore
+            );
+            return false;
+        }
+
+        true
+    }
+
     /// Publishes an event to the fanotify group's event queue.
     ///
     /// This method is called when the current task performs a filesystem action on a
@@ -244,63 +328,7 @@ impl FanotifyGroup {
         old_name: &str,
         new_name: &str,
     ) {
-        // Check whether the event is marked and not ignored.
-        let object_entry = entries.object;
-        let parent_entry = entries.parent;
-        let mount_entry = entries.mount;
-        let fs_entry = entries.fs;
-
-        let object_mark = object_entry
-            .clone()
-            .map_or(FanEventMask::empty(), |e| e.mark());
-        let parent_mark = parent_entry
-            .clone()
-            .map_or(FanEventMask::empty(), |e| e.mark());
-        let mount_mark = mount_entry
-            .clone()
-            .map_or(FanEventMask::empty(), |e| e.mark());
-        let fs_mark = fs_entry.clone().map_or(FanEventMask::empty(), |e| e.mark());
-
-        if !(object_mark.contains(event)
-            || ((FanEventMask::FILE_EVENT_MASK | FanEventMask::ONDIR).contains(event)
-                && parent_mark.contains(event | FanEventMask::EVENT_ON_CHILD))
-            || mount_mark.contains(event)
-            || fs_mark.contains(event))
-        {
-            log::debug!(
-                "[FanotifyGroup::publish] Event not marked: event={:?}, object={:?}, \
-                object_mark={:?}, parent_mark={:?}, mount_mark={:?}, fs_mark={:?}",
-                event,
-                object.path(),
-                object_mark,
-                parent_mark,
-                mount_mark,
-                fs_mark
-            );
-            return;
-        }
-
-        let object_ignore = object_entry.map_or(FanEventMask::empty(), |e| e.ignore());
-        let parent_ignore = parent_entry.map_or(FanEventMask::empty(), |e| e.ignore());
-        let mount_ignore = mount_entry.map_or(FanEventMask::empty(), |e| e.ignore());
-        let fs_ignore = fs_entry.map_or(FanEventMask::empty(), |e| e.ignore());
-
-        if object_ignore.contains(event)
-            || (FanEventMask::FILE_EVENT_MASK | FanEventMask::ONDIR).contains(event)
-                && parent_ignore.contains(event | FanEventMask::EVENT_ON_CHILD)
-            || mount_ignore.contains(event)
-            || fs_ignore.contains(event)
-        {
-            log::debug!(
-                "[FanotifyGroup::publish] Event ignored: event={:?}, object={:?}, \
-                object_ignore={:?}, parent_ignore={:?}, mount_ignore={:?}, fs_ignore={:?}",
-                event,
-                object.path(),
-                object_ignore,
-                parent_ignore,
-                mount_ignore,
-                fs_ignore
-            );
+        if !self.accepts(object, &entries, event) {
             return;
         }
 
@@ -416,7 +444,8 @@ impl FanotifyGroup {
                 let existing_object = existing_event.object().unwrap();
                 let existing_metadata = existing_event.metadata();
 
-                if Arc::ptr_eq(existing_object, object)
+                if existing_event.permission.is_none()
+                    && Arc::ptr_eq(existing_object, object)
                     && existing_metadata.pid == metadata.pid
                     && !existing_metadata.mask.contains(metadata.mask)
                     && ((existing_metadata.mask ^ metadata.mask) & FanEventMask::ONDIR).is_empty()
@@ -438,6 +467,121 @@ impl FanotifyGroup {
         self.wake();
     }
 
+    /// Publishes a permission event to the fanotify group's event queue.
+    ///
+    /// The parameters are the same as those of [`FanotifyGroup::publish`]. `event` must
+    /// be a permission event, optionally with `FAN_ONDIR`.
+    ///
+    /// Returns the queued permission event, whose response the caller should wait for,
+    /// or `None` if the group is not interested in the event. The event is also not
+    /// queued, and thus allowed, if the event queue is full.
+    pub(crate) fn publish_permission(
+        self: Arc<Self>,
+        object: &Arc<dyn Dentry>,
+        entries: FanotifyEntrySet,
+        event: FanEventMask,
+    ) -> Option<Arc<FanotifyPermissionEvent>> {
+        if !self
+            .flags
+            .intersects(FanInitFlags::CLASS_CONTENT | FanInitFlags::CLASS_PRE_CONTENT)
+            || !self.accepts(object, &entries, event)
+        {
+            return None;
+        }
+
+        let mut event_queue = self.event_queue.lock();
+        if event_queue.len() >= self.max_queued_events as usize {
+            log::warn!(
+                "[FanotifyGroup::publish_permission] Event queue is full, allowing event: \
+                event={:?}, object={}",
+                event,
+                object.path()
+            );
+            return None;
+        }
+
+        let mut metadata = self.create_metadata(event.difference(FanEventMask::ONDIR));
+        metadata.event_len = mem::size_of::<FanotifyEventMetadata>() as u32;
+
+        let permission = Arc::new(FanotifyPermissionEvent::new(event, Arc::downgrade(&self)));
+        let mut event_data = FanotifyEventData::new(Some(Arc::clone(object)));
+        event_data.add_datum(FanotifyEventDatum::Metadata(metadata));
+        event_data.permission = Some(Arc::clone(&permission));
+        event_queue.push_back(event_data);
+        drop(event_queue);
+
+        log::info!(
+            "[FanotifyGroup::publish_permission] Publishing event: event={:?}, object={}",
+            event,
+            object.path()
+        );
+
+        self.wake();
+        Some(permission)
+    }
+
+    /// Records that the permission event `permission` has been read by the listener and
+    /// reported with file descriptor `fd`, so that the listener can respond to it.
+    ///
+    /// An event which has already timed out is not recorded, so that a late response to
+    /// it fails.
+    pub(crate) fn add_pending_permission(&self, fd: i32, permission: Arc<FanotifyPermissionEvent>) {
+        // Check the response with the lock held, so that an event timing out in between
+        // is removed by `withdraw_permission` after it is inserted.
+        let mut pending_permissions = self.pending_permissions.lock();
+        if permission.response.lock().is_none() {
+            pending_permissions.insert(fd, permission);
+        }
+    }
+
+    /// Removes the permission event `permission` from the group, whether it has been
+    /// read by the listener or not, after it has timed out.
+    fn withdraw_permission(&self, permission: &Arc<FanotifyPermissionEvent>) {
+        self.pending_permissions
+            .lock()
+            .retain(|_, pending| !Arc::ptr_eq(pending, permission));
+        self.event_queue.lock().retain(|event| {
+            event
+                .permission
+                .as_ref()
+                .is_none_or(|queued| !Arc::ptr_eq(queued, permission))
+        });
+    }
+
+    /// Responds to the pending permission event reported with file descriptor `fd`.
+    ///
+    /// `response` must be `FAN_ALLOW` or `FAN_DENY`.
+    ///
+    /// # Errors
+    /// Returns `ENOENT` if there is no pending permission event with `fd`.
+    pub(crate) fn respond(&self, fd: i32, response: u32) -> SysResult<()> {
+        let permission = self
+            .pending_permissions
+            .lock()
+            .remove(&fd)
+            .ok_or(SysError::ENOENT)?;
+        permission.respond(response);
+        Ok(())
+    }
+
+    /// Allows all permission events of the group, whether they have been read or not.
+    ///
+    /// This method is called when the group is released, so that no task waits for a
+    /// response which never comes.
+    pub(crate) fn release(self: &Arc<Self>) {
+        let queued = mem::take(&mut *self.event_queue.lock());
+        let pending = mem::take(&mut *self.pending_permissions.lock());
+        queued
+            .into_iter()
+            .filter_map(|event| event.permission)
+            .chain(pending.into_values())
+            .for_each(|permission| permission.respond(FAN_ALLOW));
+
+        FANOTIFY_WAKERS
+            .lock()
+            .remove(&FanotifyGroupKey(Arc::clone(self)));
+    }
+
     pub(crate) async fn wait(self: Arc<Self>) {
         let group_key = FanotifyGroupKey(self);
         let waker = take_waker().await;
@@ -650,24 +794,91 @@ pub(crate) struct FanotifyEntrySet {
     pub fs: Option<Arc<FanotifyEntry>>,
 }
 
-/// Data structure for pending permission events.
-///
-/// This represents a permission event that is waiting for a response from userspace.
-#[derive(Debug, Clone)]
+/// A permission event waiting for a response from the listener of a fanotify group.
 pub struct FanotifyPermissionEvent {
-    /// File descriptor of the fanotify event file created for this permission event.
-    pub event_fd: i32,
+    /// The event mask of the permission event.
+    mask: FanEventMask,
+
+    /// The response of the listener, which is `FAN_ALLOW` or `FAN_DENY`, or `None` if
+    /// the listener has not responded yet.
+    response: SpinNoIrqLock<Option<u32>>,
+
+    /// The waker of the task waiting for the response.
+    waker: SpinNoIrqLock<Option<Waker>>,
+
+    /// The fanotify group the event is published to.
+    group: Weak<FanotifyGroup>,
+}
+
+impl FanotifyPermissionEvent {
+    fn new(mask: FanEventMask, group: Weak<FanotifyGroup>) -> Self {
+        Self {
+            mask,
+            response: SpinNoIrqLock::new(None),
+            waker: SpinNoIrqLock::new(None),
+            group,
+        }
+    }
+
+    /// Sets the response to the event and wakes up the waiting task.
+    fn respond(&self, response: u32) {
+        *self.response.lock() = Some(response);
+        if let Some(waker) = self.waker.lock().take() {
+            waker.wake();
+        }
+    }
+
+    /// Waits for the response to the event.
+    ///
+    /// If the listener does not respond within [`PERMISSION_TIMEOUT_MS`] milliseconds,
+    /// the event is denied and removed from the group, so that a stuck listener cannot
+    /// block the task forever, and a late response fails with `ENOENT`.
+    ///
+    /// # Errors
+    /// Returns `EPERM` if the event is denied.
+    pub(crate) async fn wait(self: Arc<Self>) -> SysResult<()> {
+        let timeout = PERMISSION_TIMEOUT_MS.load(atomic::Ordering::Relaxed) as u64;
+        let future = FanotifyResponseFuture(Arc::clone(&self));
+        let response = match run_with_timeout(Duration::from_millis(timeout), future).await {
+            TimedTaskResult::Completed(response) => response,
+            TimedTaskResult::Timeout => {
+                log::warn!(
+                    "[FanotifyPermissionEvent::wait] No response in {timeout} ms, denying \
+                    event {:?}",
+                    self.mask
+                );
+                self.respond(FAN_DENY);
+                if let Some(group) = self.group.upgrade() {
+                    group.withdraw_permission(&self);
+                }
+                FAN_DENY
+            }
+        };
+
+        if response == FAN_ALLOW {
+            Ok(())
+        } else {
+            Err(SysError::EPERM)
+        }
+    }
+}
 
-    /// Process ID that triggered the event.
-    pub pid: i32,
+/// Future that resolves to the response to a permission event.
+struct FanotifyResponseFuture(Arc<FanotifyPermissionEvent>);
 
-    /// The event mask that triggered this permission check.
-    pub mask: FanEventMask,
+impl Future for FanotifyResponseFuture {
+    type Output = u32;
 
-    /// Handle to the fanotify event file (weak reference to avoid cycles).
-    pub event_file: Weak<dyn File>,
-    // TODO: This will need to be defined based on how filesystem operations are handled
-    // pub permission_callback: Box<dyn FnOnce(bool) + Send + Sync>,
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Hold the waker lock while checking the response, so that a response set in
+        // between cannot miss the waker.
+        let mut waker = self.0.waker.lock();
+        if let Some(response) = *self.0.response.lock() {
+            return Poll::Ready(response);
+        }
+        *waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
 }
 
 /// A filesystem object that can be monitored by fanotify.
@@ -697,6 +908,8 @@ pub struct FanotifyEventData {
     dentry: Option<Arc<dyn Dentry>>,
     /// The event data, which contains a metadata and optional information records.
     data: Vec<FanotifyEventDatum>,
+    /// The permission event waiting for a response, if this is a permission event.
+    permission: Option<Arc<FanotifyPermissionEvent>>,
 }
 
 impl FanotifyEventData {
@@ -705,6 +918,7 @@ impl FanotifyEventData {
         Self {
             dentry,
             data: Vec::new(),
+            permission: None,
         }
     }
 
@@ -723,6 +937,11 @@ impl FanotifyEventData {
         &self.data
     }
 
+    /// Returns the permission event of the event data, if it is a permission event.
+    fn permission(&self) -> Option<&Arc<FanotifyPermissionEvent>> {
+        self.permission.as_ref()
+    }
+
     /// Returns an immutable reference to the metadata structure of the event data.
     ///
     /// # Panic
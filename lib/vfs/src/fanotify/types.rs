@@ -174,10 +174,11 @@ pub enum FanotifyEventInfoType {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(C)]
 pub struct FanotifyResponse {
     pub fd: i32,
     pub response: FanotifyResponseOption,
+    /// `FAN_AUDIT` and `FAN_INFO` bits set along with the response.
+    pub flags: u32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -449,23 +450,29 @@ bitflags! {
 impl FanotifyResponse {
     /// Creates a FanotifyResponse from a byte buffer.
     ///
-    /// The buffer should contain exactly 8 bytes. If the buffer is not of the correct
-    /// size or contains an invalid response value, this function returns `None`.
+    /// The first 8 bytes of the buffer are parsed as a `struct fanotify_response`;
+    /// any following bytes are ignored. If the buffer is too short or contains an
+    /// invalid response value, this function returns `None`.
     pub fn from_bytes(buf: &[u8]) -> Option<Self> {
-        if buf.len() != 8 {
+        if buf.len() < 8 {
             return None;
         }
 
         let fd = i32::from_ne_bytes(buf[0..4].try_into().unwrap());
         let response_val = u32::from_ne_bytes(buf[4..8].try_into().unwrap());
+        let flags = response_val & (FAN_AUDIT | FAN_INFO);
 
-        let response = match response_val {
+        let response = match response_val & !flags {
             FAN_ALLOW => FanotifyResponseOption::Allow,
             FAN_DENY => FanotifyResponseOption::Deny,
             _ => return None,
         };
 
-        Some(FanotifyResponse { fd, response })
+        Some(FanotifyResponse {
+            fd,
+            response,
+            flags,
+        })
     }
 
     /// Returns true if this response allows the operation.
@@ -38,6 +38,9 @@ impl FileMeta {
     /// Creates a new `FileMeta` with the given dentry. Position is set to 0.
     /// The flags are set to empty.
     pub fn new(dentry: Arc<dyn Dentry>) -> Self {
+        if let Some(inode) = dentry.inode() {
+            inode.get_meta().inner.lock().open_files += 1;
+        }
         Self {
             dentry,
             pos: AtomicUsize::new(0),
@@ -47,6 +50,31 @@ impl FileMeta {
     }
 }
 
+impl Drop for FileMeta {
+    fn drop(&mut self) {
+        let Some(inode) = self.dentry.inode() else {
+            return;
+        };
+        let delete_self = {
+            let mut inner = inode.get_meta().inner.lock();
+            inner.open_files = inner.open_files.saturating_sub(1);
+            let delete_self = inner.open_files == 0 && inner.delete_self_pending;
+            if delete_self {
+                inner.delete_self_pending = false;
+            }
+            delete_self
+        };
+        // The inode is gone now that its last link is removed and its last open file
+        // is closed.
+        if delete_self {
+            let file_name = self.dentry.name();
+            self.dentry
+                .fanotify_publish(None, FanEventMask::DELETE_SELF, file_name, file_name);
+            self.dentry.inotify_publish(FanEventMask::DELETE_SELF);
+        }
+    }
+}
+
 #[async_trait]
 pub trait File: Send + Sync + DowncastSync {
     /// Returns the metadata of this file.
@@ -258,6 +286,8 @@ impl dyn File {
     ///
     /// Returns the number of bytes read.
     pub async fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        self.fanotify_permission(FanEventMask::ACCESS_PERM).await?;
+
         let inode = self.inode();
         let position = self.pos();
 
@@ -273,7 +303,7 @@ impl dyn File {
 
         self.set_pos(position + bytes_read);
 
-        self.fanotify_publish(FanEventMask::ACCESS);
+        self.fsnotify_publish(FanEventMask::ACCESS);
 
         Ok(bytes_read)
     }
@@ -406,7 +436,7 @@ impl dyn File {
         }
         inode.set_state(InodeState::DirtyAll);
 
-        self.fanotify_publish(FanEventMask::MODIFY);
+        self.fsnotify_publish(FanEventMask::MODIFY);
 
         // log::debug!("write bytes: {}", bytes_written);
 
@@ -427,7 +457,7 @@ impl dyn File {
 
         self.inode().set_state(InodeState::DirtyAll);
 
-        self.fanotify_publish(FanEventMask::MODIFY);
+        self.fsnotify_publish(FanEventMask::MODIFY);
 
         Ok(())
     }
@@ -540,7 +570,7 @@ impl dyn File {
         }
         log::debug!("[read_dir] read {writen_len} bytes");
 
-        self.fanotify_publish(FanEventMask::ACCESS | FanEventMask::ONDIR);
+        self.fsnotify_publish(FanEventMask::ACCESS | FanEventMask::ONDIR);
 
         Ok(writen_len)
     }
@@ -667,12 +697,12 @@ impl dyn File {
         Ok(())
     }
 
-    /// Publishes an fanotify file event on this file or directory to all related fanotify
-    /// groups, if the file is not marked with `FMODE_NONOTIFY`. It also tries to clear
-    /// the ignore flag for each fanotify entry.
+    /// Publishes a file event on this file or directory to all related fanotify groups
+    /// and inotify watches, if the file is not marked with `FMODE_NONOTIFY`. It also
+    /// tries to clear the ignore flag for each fanotify entry.
     ///
     /// `flags` is the event mask that indicates the type of event to publish.
-    pub fn fanotify_publish(&self, event: FanEventMask) {
+    pub fn fsnotify_publish(&self, event: FanEventMask) {
         if self
             .meta()
             .internal_flags
@@ -687,6 +717,26 @@ impl dyn File {
         let new_name = old_name;
 
         object.fanotify_publish(None, event, old_name, new_name);
+        object.inotify_publish(event);
+    }
+
+    /// Publishes an fanotify permission event on this file or directory, and waits
+    /// until all interested fanotify groups allow it, if the file is not marked with
+    /// `FMODE_NONOTIFY`.
+    ///
+    /// # Errors
+    /// Returns `EPERM` if any fanotify group denies the permission.
+    pub async fn fanotify_permission(&self, event: FanEventMask) -> SysResult<()> {
+        if self
+            .meta()
+            .internal_flags
+            .lock()
+            .contains(FileInternalFlags::FMODE_NONOTIFY)
+        {
+            return Ok(());
+        }
+
+        self.dentry().fanotify_permission(event).await
     }
 }
 
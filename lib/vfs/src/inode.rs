@@ -18,7 +18,14 @@ use systype::{
     time::TimeSpec,
 };
 
-use crate::{fanotify::FanotifyEntry, handle::FileHandle, stat::Stat, superblock::SuperBlock};
+use crate::{
+    acl::{PosixAcl, XATTR_NAME_POSIX_ACL_ACCESS},
+    fanotify::FanotifyEntry,
+    handle::FileHandle,
+    inotify::InotifyMark,
+    stat::Stat,
+    superblock::SuperBlock,
+};
 
 /// Data that is common to all inodes.
 pub struct InodeMeta {
@@ -63,6 +70,19 @@ pub struct InodeMetaInner {
     pub symlink: Option<String>,
     /// Registered fanotify entries on this inode.
     pub fanotify_entries: Vec<Weak<FanotifyEntry>>,
+    /// Attached inotify marks on this inode.
+    pub inotify_marks: Vec<Weak<dyn InotifyMark>>,
+    /// Parsed access ACL, which is `None` if it has not been read since it last
+    /// changed, or `Some(None)` if the inode has no access ACL.
+    pub access_acl: Option<Option<Arc<PosixAcl>>>,
+    /// Number of changes of the access ACL, so that an ACL read while it changes is
+    /// not cached.
+    pub access_acl_seq: usize,
+    /// Number of open files on this inode.
+    pub open_files: usize,
+    /// Whether the last link to this inode has been removed while it is open, so that
+    /// `DELETE_SELF` is published when its last open file is closed.
+    pub delete_self_pending: bool,
 }
 
 impl InodeMeta {
@@ -85,6 +105,11 @@ impl InodeMeta {
                 xattrs: BTreeMap::new(),
                 symlink: None,
                 fanotify_entries: Vec::new(),
+                inotify_marks: Vec::new(),
+                access_acl: None,
+                access_acl_seq: 0,
+                open_files: 0,
+                delete_self_pending: false,
             }),
         }
     }
@@ -112,6 +137,7 @@ impl InodeMetaInner {
             _ => {}
         }
         self.xattrs.insert(name.to_string(), value.to_vec());
+        self.invalidate_acl(name);
         Ok(())
     }
 
@@ -124,11 +150,23 @@ impl InodeMetaInner {
 
     pub fn remove_xattr(&mut self, name: &str) -> SysResult<()> {
         if self.xattrs.remove(name).is_some() {
+            self.invalidate_acl(name);
             Ok(())
         } else {
             Err(SysError::ENODATA)
         }
     }
+
+    /// Drops the cached access ACL if `name` is the extended attribute holding it.
+    ///
+    /// A filesystem which stores extended attributes itself must call this method
+    /// after changing one.
+    pub fn invalidate_acl(&mut self, name: &str) {
+        if name == XATTR_NAME_POSIX_ACL_ACCESS {
+            self.access_acl = None;
+            self.access_acl_seq += 1;
+        }
+    }
 }
 
 pub trait Inode: Send + Sync + DowncastSync {
@@ -237,9 +275,34 @@ pub trait Inode: Send + Sync + DowncastSync {
         self.get_meta().inner.lock().remove_xattr(name)
     }
 
+    /// Returns the access ACL of the inode, or `None` if it has none or it is
+    /// malformed. The parsed ACL is cached until the extended attribute changes.
+    fn access_acl(&self) -> Option<Arc<PosixAcl>> {
+        let seq = {
+            let meta = self.get_meta().inner.lock();
+            if let Some(acl) = &meta.access_acl {
+                return acl.clone();
+            }
+            meta.access_acl_seq
+        };
+
+        let acl = self
+            .get_xattr(XATTR_NAME_POSIX_ACL_ACCESS)
+            .and_then(|value| PosixAcl::from_xattr(&value))
+            .ok()
+            .map(Arc::new);
+        let mut meta = self.get_meta().inner.lock();
+        if meta.access_acl_seq == seq {
+            meta.access_acl = Some(acl.clone());
+        }
+        acl
+    }
+
     fn check_permission(&self, euid: u32, egid: u32, groups: &[u32], access: AccessFlags) -> bool {
-        let meta = self.get_meta().inner.lock();
-        let mode = meta.mode.bits();
+        let (mode, uid, gid) = {
+            let meta = self.get_meta().inner.lock();
+            (meta.mode.bits(), meta.uid, meta.gid)
+        };
 
         if euid == 0 {
             if access.contains(AccessFlags::X_OK) && mode & 0o111 == 0 {
@@ -249,8 +312,16 @@ pub trait Inode: Send + Sync + DowncastSync {
             }
         }
 
-        let is_owner = euid == meta.uid;
-        let is_group = egid == meta.gid || groups.contains(&meta.gid);
+        // The access ACL, if any, decides for users other than the owner, whose
+        // permissions are always those in the mode.
+        if euid != uid {
+            if let Some(acl) = self.access_acl() {
+                return acl.permits(euid, egid, groups, uid, gid, access);
+            }
+        }
+
+        let is_owner = euid == uid;
+        let is_group = egid == gid || groups.contains(&gid);
 
         let (r, w, x) = if is_owner {
             (0o400, 0o200, 0o100)
@@ -0,0 +1,70 @@
+//! Module for delivering inotify events from the VFS.
+//!
+//! An inotify instance is implemented outside of the VFS. It attaches an
+//! [`InotifyMark`] to each inode it watches, and the VFS delivers events to the marks
+//! on the inodes involved in an operation. An event on a file is delivered to the
+//! marks on the file itself, and, if it is one of [`INOTIFY_CHILD_EVENTS`], to the
+//! marks on its parent directory together with the name of the file.
+//!
+//! Event masks are passed in the inotify format. The bits of [`FanEventMask`] are the
+//! same as the inotify ones, with `FanEventMask::ONDIR` being `IN_ISDIR`.
+
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{fanotify::types::FanEventMask, inode::Inode};
+
+/// Events on a file which are also reported to the watches on its parent directory.
+pub const INOTIFY_CHILD_EVENTS: FanEventMask = FanEventMask::ACCESS
+    .union(FanEventMask::MODIFY)
+    .union(FanEventMask::ATTRIB)
+    .union(FanEventMask::CLOSE)
+    .union(FanEventMask::OPEN);
+
+/// A watch of an inotify instance on an inode.
+pub trait InotifyMark: Send + Sync {
+    /// Delivers an event to this watch.
+    ///
+    /// `mask` is the event mask in the inotify format. `cookie` is nonzero for the
+    /// `IN_MOVED_FROM` and `IN_MOVED_TO` events of the same rename. `name` is the name
+    /// of the child the event happened on if the watch is on its parent directory,
+    /// and `unlinked` tells whether that child has been unlinked.
+    fn deliver(&self, mask: u32, cookie: u32, name: Option<&str>, unlinked: bool);
+}
+
+/// Attaches an inotify mark to `inode`. The mark is detached when it is dropped.
+pub fn add_mark(inode: &Arc<dyn Inode>, mark: Weak<dyn InotifyMark>) {
+    inode.get_meta().inner.lock().inotify_marks.push(mark);
+}
+
+/// Returns a new cookie to associate the `IN_MOVED_FROM` and `IN_MOVED_TO` events of a
+/// rename.
+pub fn next_cookie() -> u32 {
+    static NEXT_COOKIE: AtomicU32 = AtomicU32::new(1);
+    loop {
+        let cookie = NEXT_COOKIE.fetch_add(1, Ordering::Relaxed);
+        if cookie != 0 {
+            return cookie;
+        }
+    }
+}
+
+/// Delivers an event to all inotify marks on `inode`.
+///
+/// The parameters are the same as those of [`InotifyMark::deliver`].
+pub fn publish(inode: &Arc<dyn Inode>, mask: u32, cookie: u32, name: Option<&str>, unlinked: bool) {
+    let marks: alloc::vec::Vec<_> = {
+        let mut inner = inode.get_meta().inner.lock();
+        inner.inotify_marks.retain(|m| m.strong_count() > 0);
+        inner
+            .inotify_marks
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    };
+
+    // The lock of the inode is released here, as a mark may remove itself on delivery.
+    for mark in marks {
+        mark.deliver(mask, cookie, name, unlinked);
+    }
+}
@@ -8,6 +8,7 @@ use alloc::sync::Arc;
 use dentry::Dentry;
 use spin::Once;
 
+pub mod acl;
 pub mod dcache;
 pub mod dentry;
 pub mod direntry;
@@ -17,6 +18,7 @@ pub mod fstype;
 pub mod handle;
 pub mod inode;
 pub mod inoid;
+pub mod inotify;
 pub mod kstat;
 pub mod path;
 pub mod stat;